@@ -8,17 +8,32 @@ use log::{debug, error, info};
 use rand::distr::Alphanumeric;
 use rand::Rng;
 use secluso_client_lib::config::{
-    CameraVersionInfo, Heartbeat, HeartbeatRequest, HeartbeatResult, OPCODE_HEARTBEAT_REQUEST, OPCODE_HEARTBEAT_RESPONSE,
+    CameraEvent, CameraVersionInfo, Heartbeat, HeartbeatRequest, HeartbeatResult, OPCODE_HEARTBEAT_REQUEST, OPCODE_HEARTBEAT_RESPONSE,
     AddAppRequest, AddAppResponseCommon, AddAppResponseDedicated, OPCODE_ADD_APP_REQUEST, OPCODE_ADD_APP_RESPONSE,
+    SnapshotResponse, OPCODE_SNAPSHOT_REQUEST, ListSegmentsResponse, RetrieveSegmentsRequest,
+    RetrieveSegmentsResponse, OPCODE_LIST_SEGMENTS_REQUEST, OPCODE_LIST_SEGMENTS_RESPONSE,
+    OPCODE_RETRIEVE_SEGMENTS_REQUEST, OPCODE_RETRIEVE_SEGMENTS_RESPONSE, SetScheduleResponse,
+    OPCODE_SET_SCHEDULE_REQUEST, OPCODE_SET_SCHEDULE_RESPONSE,
 };
+use secluso_client_lib::fcm_message::{self, FcmMessage};
+use secluso_client_lib::heartbeat_tracker::HeartbeatTracker;
+use secluso_client_lib::http_client::HttpClient;
+use secluso_client_lib::livestream_buffer::LivestreamBuffer;
+use secluso_client_lib::livestream_chunk;
 use secluso_client_lib::mls_client::{Contact, MlsClient, ClientType};
 use secluso_client_lib::mls_clients::MlsClients;
 use secluso_client_lib::mls_clients::{
-    CONFIG, FCM, LIVESTREAM, MLS_CLIENT_TAGS, MOTION, NUM_MLS_CLIENTS, THUMBNAIL,
+    CONFIG, FCM, LIVESTREAM, MAX_CIPHERTEXT_SIZES, MLS_CLIENT_TAGS, MOTION, NUM_MLS_CLIENTS, THUMBNAIL,
     NUM_COMMON_MLS_CLIENTS, NUM_DEDICATED_MLS_CLIENTS,
 };
+use secluso_client_lib::notification_schedule::NotificationSchedule;
 use secluso_client_lib::pairing::{self, MAX_ALLOWED_MSG_LEN, generate_add_app_secret};
-use secluso_client_lib::video::{encrypt_video_file, decrypt_video_file, decrypt_thumbnail_file};
+use secluso_client_lib::talkback::encrypt_talkback_chunk;
+use secluso_client_lib::thumbnail_meta_info::ThumbnailMetaFile;
+use secluso_client_lib::video::{
+    encrypt_video_file, decrypt_video_file_and_retire_source, decrypt_thumbnail_file,
+};
+use secluso_client_server_lib::auth::parse_user_credentials;
 use openmls::prelude::KeyPackage;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -28,6 +43,7 @@ use std::io;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::net::SocketAddr;
 use std::net::TcpStream;
+use std::path::Path;
 use std::str;
 use std::str::FromStr;
 use std::thread;
@@ -43,45 +59,56 @@ const CAMERA_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
 const CAMERA_IO_TIMEOUT: Duration = Duration::from_secs(12);
 const CAMERA_CONNECT_RETRIES: usize = 3;
 const CAMERA_CONNECT_RETRY_DELAY: Duration = Duration::from_millis(350);
+// Pairing port of a standalone camera, and of the first camera of a hub.
+const PAIRING_PORT: u16 = 12348;
+
+const HEALTHY_HEARTBEAT: &str = "healthy";
 
 #[derive(Serialize)]
 struct HeartbeatStatus {
     status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     version_info: Option<CameraVersionInfo>,
+    // Recent camera-side problems, oldest first (empty for old cameras).
+    events: Vec<CameraEvent>,
 }
 
 #[flutter_rust_bridge::frb]
 pub struct Clients {
     mls_clients: MlsClients,
+    // Number of encrypted videos to keep around after they're decrypted (for debugging).
+    keep_last_encrypted_videos: usize,
 }
 
 #[flutter_rust_bridge::frb]
 impl Clients {
     pub fn new(first_time: bool, file_dir: String) -> io::Result<Self> {
-        let mls_clients: MlsClients = array::from_fn(|i| {
+        let mut mls_clients: MlsClients = array::from_fn(|i| {
             let app_name = get_app_name(
                 first_time,
                 file_dir.clone(),
                 format!("app_{}_name", MLS_CLIENT_TAGS[i]),
             );
 
-            let mut mls_client = MlsClient::new(
+            MlsClient::new(
                 app_name,
                 first_time,
                 file_dir.clone(),
                 MLS_CLIENT_TAGS[i].to_string(),
                 ClientType::App,
             )
-                .expect("MlsClient::new() for returned error.");
-
-            // Make sure the groups_state files are created in case we initialize again soon.
-            mls_client.save_group_state().unwrap();
-
-            mls_client
+                .expect("MlsClient::new() for returned error.")
         });
 
-        Ok(Self { mls_clients })
+        // Make sure the groups_state files are created in case we initialize again soon.
+        for mls_client in mls_clients.iter_mut() {
+            mls_client.save_group_state()?;
+        }
+
+        Ok(Self {
+            mls_clients,
+            keep_last_encrypted_videos: 0,
+        })
     }
 }
 
@@ -180,7 +207,7 @@ fn send_wifi_and_pairing_info(
     write_varying_len(stream, &wifi_info_msg)?;
     info!("After Wifi Msg Sent");
 
-    mls_client.save_group_state().unwrap();
+    mls_client.save_group_state()?;
 
     Ok(())
 }
@@ -201,7 +228,7 @@ fn send_credentials_full(
 
     write_varying_len(stream, &encrypted_msg)?;
 
-    mls_client.save_group_state().unwrap();
+    mls_client.save_group_state()?;
 
     Ok(())
 }
@@ -295,7 +322,7 @@ fn process_welcome_message(
     group_name: String,
 ) -> io::Result<()> {
     mls_client.process_welcome_with_secret(contact, welcome_msg, secret, &group_name)?;
-    mls_client.save_group_state().unwrap();
+    mls_client.save_group_state()?;
 
     Ok(())
 }
@@ -315,7 +342,7 @@ pub fn encrypt_settings_message(
     let settings_msg = config_mls_client
         .encrypt(&message)
         .context("Failed to encrypt SSID")?;
-    config_mls_client.save_group_state().unwrap();
+    config_mls_client.save_group_state()?;
 
     Ok(settings_msg)
 }
@@ -349,8 +376,13 @@ pub fn add_camera(
     }
 
     // Connect to the camera
-    //FIXME: port number hardcoded.
-    let addr = match SocketAddr::from_str(&(camera_ip + ":12348")) {
+    // A hub with several cameras pairs each on its own port, which is given as IP:port.
+    let camera_addr = if SocketAddr::from_str(&camera_ip).is_ok() {
+        camera_ip
+    } else {
+        format!("{camera_ip}:{PAIRING_PORT}")
+    };
+    let addr = match SocketAddr::from_str(&camera_addr) {
         Ok(a) => a,
         Err(e) => {
             info!("Error: invalid IP address: {e}");
@@ -455,6 +487,7 @@ pub fn initialize(
     Ok(true)
 }
 
+/// On failure, DecryptError::of() tells whether rolling back the group state and retrying can help.
 pub fn decrypt_video(
     clients: &mut Option<Box<Clients>>,
     encrypted_filename: String,
@@ -470,12 +503,29 @@ pub fn decrypt_video(
     let enc_pathname: String = format!("{}/encrypted/{}", file_dir, encrypted_filename);
     info!("Encrypted pathname: {}", enc_pathname);
 
-    decrypt_video_file(
+    decrypt_video_file_and_retire_source(
         &mut clients.mls_clients[MOTION],
         &enc_pathname,
+        clients.keep_last_encrypted_videos,
     )
 }
 
+/// Sets how many encrypted videos are kept after they're decrypted successfully.
+/// 0 (the default) deletes them right away.
+pub fn set_encrypted_video_retention(
+    clients: &mut Option<Box<Clients>>,
+    keep_last: u32,
+) -> io::Result<()> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    clients.as_mut().unwrap().keep_last_encrypted_videos = keep_last as usize;
+    Ok(())
+}
+
 // This function is used to aid in performance testing; this is not used in the production app
 pub fn encrypt_video(
     clients: &mut Option<Box<Clients>>,
@@ -498,6 +548,7 @@ pub fn encrypt_video(
     )
 }
 
+/// On failure, DecryptError::of() tells whether rolling back the group state and retrying can help.
 pub fn decrypt_thumbnail(
     clients: &mut Option<Box<Clients>>,
     encrypted_filename: String,
@@ -521,6 +572,20 @@ pub fn decrypt_thumbnail(
     )
 }
 
+/// Returns the content of a thumbnail meta file (meta_{timestamp}.txt) in the current format,
+/// converting the files written by older versions.
+pub fn migrate_thumbnail_meta(contents: String) -> io::Result<String> {
+    let meta_file = ThumbnailMetaFile::parse(&contents)?;
+    serde_json::to_string(&meta_file).map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Reads a thumbnail meta file for the pending processor, in the current format.
+/// Files written by older versions are converted (see migrate_thumbnail_meta) and rewritten.
+pub fn load_thumbnail_meta(meta_file_path: String) -> io::Result<String> {
+    let meta_file = ThumbnailMetaFile::load(Path::new(&meta_file_path))?;
+    serde_json::to_string(&meta_file).map_err(|e| io::Error::other(e.to_string()))
+}
+
 pub fn decrypt_message(
     clients: &mut Option<Box<Clients>>,
     client_tag: &str,
@@ -539,29 +604,13 @@ pub fn decrypt_message(
 
     let dec_msg_bytes =
         clients.as_mut().unwrap().mls_clients[mls_client_index.unwrap()].decrypt(message, true)?;
-    clients.as_mut().unwrap().mls_clients[mls_client_index.unwrap()].save_group_state().unwrap();
+    clients.as_mut().unwrap().mls_clients[mls_client_index.unwrap()].save_group_state()?;
 
-    // New JSON structure. Ensure valid JSON string
-    if let Ok(message) = str::from_utf8(&dec_msg_bytes) {
-        if serde_json::from_str::<serde_json::Value>(message).is_ok() {
-            return Ok(message.to_string());
-        }
-    }
-
-    // For messages not in JSON. For now, this is only for decoding FCM messages. TODO: Port all FCM over to JSON
-    let response = if dec_msg_bytes.len() == 8 {
-        let timestamp: u64 = bincode::deserialize(&dec_msg_bytes)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-        if timestamp != 0 {
-            timestamp.to_string()
-        } else {
-            "Download".to_string()
-        }
-    } else {
-        return Err(io::Error::other(format!(
-            "Error: invalid len in decrypted msg ({})",
-            dec_msg_bytes.len()
-        )));
+    // TODO: Port all FCM over to JSON
+    let response = match fcm_message::decode(&dec_msg_bytes)? {
+        FcmMessage::Json(message) => message,
+        FcmMessage::Timestamp(0) => "Download".to_string(),
+        FcmMessage::Timestamp(timestamp) => timestamp.to_string(),
     };
 
     Ok(response)
@@ -582,6 +631,26 @@ pub fn get_group_name(clients: &mut Option<Box<Clients>>, client_tag: &str) -> i
     clients.as_mut().unwrap().mls_clients[mls_client_index.unwrap()].get_group_name()
 }
 
+/// DER-encoded Ed25519 public key of the camera in the client's group, for the user to compare
+/// (e.g., as a fingerprint or QR code) with the one that the camera shows.
+/// Tells whether the camera was replaced since pairing.
+pub fn get_contact_public_key(
+    clients: &Option<Box<Clients>>,
+    client_tag: &str,
+) -> io::Result<Vec<u8>> {
+    let Some(clients) = clients.as_ref() else {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    };
+
+    let Some(mls_client_index) = client_tag_to_index(client_tag) else {
+        return Err(io::Error::other("Error: No matching client!".to_string()));
+    };
+
+    clients.mls_clients[mls_client_index].get_contact_public_key()
+}
+
 pub fn get_client_epoch(
     clients: &mut Option<Box<Clients>>,
     client_tag: &str,
@@ -616,6 +685,70 @@ pub fn livestream_decrypt(
     enc_data: Vec<u8>,
     expected_chunk_number: u64,
 ) -> io::Result<Vec<u8>> {
+    let (data, _quality_level) =
+        livestream_decrypt_with_quality(clients, enc_data, expected_chunk_number)?;
+
+    Ok(data)
+}
+
+/// Same as livestream_decrypt, but also returns the quality level of the chunk
+/// (0 is full quality, higher levels mean the camera reduced it because of a slow uplink).
+pub fn livestream_decrypt_with_quality(
+    clients: &mut Option<Box<Clients>>,
+    enc_data: Vec<u8>,
+    expected_chunk_number: u64,
+) -> io::Result<(Vec<u8>, u8)> {
+    let (chunk_number, quality_level, data) = decrypt_livestream_chunk(clients, enc_data)?;
+    if chunk_number != expected_chunk_number {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Error: invalid chunk number!".to_string(),
+        ));
+    }
+
+    Ok((data, quality_level))
+}
+
+/// Same as livestream_decrypt, but tolerates chunks delivered out of order.
+/// A chunk ahead of the expected one is held in buffer (if it's not too far ahead), and the call
+/// fails with ErrorKind::WouldBlock until the expected chunk is available.
+/// After playing a chunk, the following ones may already be in buffer: see livestream_take_buffered.
+pub fn livestream_decrypt_buffered(
+    clients: &mut Option<Box<Clients>>,
+    enc_data: Vec<u8>,
+    expected_chunk_number: u64,
+    buffer: &mut LivestreamBuffer,
+) -> io::Result<Vec<u8>> {
+    let (chunk_number, _quality_level, data) = decrypt_livestream_chunk(clients, enc_data)?;
+
+    buffer
+        .push(expected_chunk_number, chunk_number, data)?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("Chunk {expected_chunk_number} not received yet"),
+            )
+        })
+}
+
+/// Buffer for livestream_decrypt_buffered, for one livestream session.
+pub fn new_livestream_buffer(capacity: usize) -> LivestreamBuffer {
+    LivestreamBuffer::new(capacity)
+}
+
+/// The expected chunk, if livestream_decrypt_buffered received it ahead of time.
+pub fn livestream_take_buffered(
+    buffer: &mut LivestreamBuffer,
+    expected_chunk_number: u64,
+) -> Option<Vec<u8>> {
+    buffer.take(expected_chunk_number)
+}
+
+// Returns the chunk number, the quality level, and the data of a livestream chunk.
+fn decrypt_livestream_chunk(
+    clients: &mut Option<Box<Clients>>,
+    enc_data: Vec<u8>,
+) -> io::Result<(u64, u8, Vec<u8>)> {
     if clients.is_none() {
         return Err(io::Error::other(
             "Error: clients not initialized!".to_string(),
@@ -623,25 +756,36 @@ pub fn livestream_decrypt(
     }
 
     let dec_data = clients.as_mut().unwrap().mls_clients[LIVESTREAM].decrypt(enc_data, true)?;
-    clients.as_mut().unwrap().mls_clients[LIVESTREAM].save_group_state().unwrap();
+    clients.as_mut().unwrap().mls_clients[LIVESTREAM].save_group_state()?;
 
-    // check the chunk number
-    if dec_data.len() < 8 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Error: too few bytes!".to_string(),
-        ));
-    }
+    // Chunks from older cameras (or for older apps) have no quality level, see livestream_chunk.
+    let (chunk_number, quality_level, data) = livestream_chunk::decode_chunk(&dec_data)?;
 
-    let chunk_number = u64::from_be_bytes(dec_data[..8].try_into().unwrap());
-    if chunk_number != expected_chunk_number {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Error: invalid chunk number!".to_string(),
+    Ok((chunk_number, quality_level, data.to_vec()))
+}
+
+/// Encrypts a talkback (app-to-camera audio) chunk for upload during a livestream.
+/// Chunk numbers start at 1 in every livestream session.
+/// Must be called after livestream_update() so that the chunk is encrypted in the camera's current epoch.
+pub fn livestream_encrypt_audio(
+    clients: &mut Option<Box<Clients>>,
+    audio_chunk: Vec<u8>,
+    chunk_number: u64,
+) -> io::Result<Vec<u8>> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
         ));
     }
 
-    Ok(dec_data[8..].to_vec())
+    let enc_data = encrypt_talkback_chunk(
+        &mut clients.as_mut().unwrap().mls_clients[LIVESTREAM],
+        &audio_chunk,
+        chunk_number,
+    )?;
+    clients.as_mut().unwrap().mls_clients[LIVESTREAM].save_group_state()?;
+
+    Ok(enc_data)
 }
 
 pub fn livestream_update(
@@ -664,11 +808,29 @@ pub fn livestream_update(
         let _ = clients.as_mut().unwrap().mls_clients[LIVESTREAM].decrypt(commit_msg, false)?;
     }
 
-    clients.as_mut().unwrap().mls_clients[LIVESTREAM].save_group_state().unwrap();
+    clients.as_mut().unwrap().mls_clients[LIVESTREAM].save_group_state()?;
 
     Ok(())
 }
 
+/// Compacts the storage of all MLS clients and returns the total number of bytes reclaimed.
+/// Should be called after a batch of videos/thumbnails has been successfully downloaded and decrypted.
+pub fn compact_storage(clients: &mut Option<Box<Clients>>) -> io::Result<u64> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    let mut reclaimed: u64 = 0;
+    for mls_client in clients.as_mut().unwrap().mls_clients.iter_mut() {
+        reclaimed += mls_client.compact_storage()? as u64;
+        mls_client.save_group_state()?;
+    }
+
+    Ok(reclaimed)
+}
+
 pub fn deregister(clients: &mut Option<Box<Clients>>) {
     if clients.is_none() {
         info!("Error: clients not initialized!");
@@ -690,6 +852,176 @@ pub fn deregister(clients: &mut Option<Box<Clients>>) {
     *clients = None;
 }
 
+/// Outcome of remove_camera().
+#[derive(Debug, Serialize)]
+pub struct RemoveCameraResult {
+    /// The camera's directories are gone from the server.
+    pub server_removed: bool,
+    /// The camera's MLS clients are cleaned. The app must call initialize() again to use the others.
+    pub local_removed: bool,
+    pub error: Option<String>,
+}
+
+/// Removes a camera from both the server and the app: deletes its directories on the server,
+/// then cleans the MLS clients of the camera's groups (and only them, unlike deregister()).
+/// The local state is kept if the server fails, so that the camera can still be used or the
+/// removal retried. Retrying after the local cleaning failed is fine too: the directories that
+/// are already gone from the server are skipped.
+pub fn remove_camera(
+    clients: &mut Option<Box<Clients>>,
+    camera_tag: String,
+    server_addr: String,
+    credentials: Vec<u8>,
+) -> io::Result<RemoveCameraResult> {
+    let Some(clients_inner) = clients.as_mut() else {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    };
+
+    let mls_clients = &mut clients_inner.mls_clients;
+    let camera_clients: Vec<usize> = (0..NUM_MLS_CLIENTS)
+        .filter(|&i| {
+            mls_clients[i]
+                .get_contact_name()
+                .is_ok_and(|name| name == camera_tag)
+        })
+        .collect();
+    if camera_clients.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("Error: No camera named {camera_tag}!"),
+        ));
+    }
+
+    let (server_username, server_password) = parse_user_credentials(credentials)?;
+    let http_client = HttpClient::new(server_addr, server_username, server_password);
+
+    for &i in &camera_clients {
+        let group_name = mls_clients[i].get_group_name()?;
+        match http_client.deregister(&group_name) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                info!("Error: Deleting {group_name} from the server failed: {e}");
+                return Ok(RemoveCameraResult {
+                    server_removed: false,
+                    local_removed: false,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    let mut error = None;
+    for &i in &camera_clients {
+        let file_dir = mls_clients[i].get_file_dir();
+
+        if let Err(e) = mls_clients[i].clean() {
+            info!("Error: Cleaning client_{} failed: {e}", MLS_CLIENT_TAGS[i]);
+            error.get_or_insert(e.to_string());
+            continue;
+        }
+
+        let _ = fs::remove_file(format!("{}/app_{}_name", file_dir, MLS_CLIENT_TAGS[i]));
+    }
+
+    // The cleaned clients can't be used anymore.
+    *clients = None;
+
+    Ok(RemoveCameraResult {
+        server_removed: true,
+        local_removed: error.is_none(),
+        error,
+    })
+}
+
+/// A file uploaded to one of the cameras, see wait_for_upload_event().
+pub struct UploadEventInfo {
+    /// Group name of the camera's client that the file is for (see get_group_name()).
+    pub camera: String,
+    pub filename: String,
+    /// To pass to the next wait_for_upload_event().
+    pub event_id: String,
+}
+
+/// Blocks until a file is uploaded to any of the cameras, for apps that don't use push
+/// notifications (instead of polling bulkCheck). Returns None after timeout_secs without uploads.
+/// last_event_id is the event_id of the last event returned, so that the uploads in between two
+/// calls are returned too. It's None for the first call, which must come after checking for new
+/// files with bulkCheck (and after every failure).
+/// Fails if the server missed some uploads: the app should then check for new files with bulkCheck.
+pub fn wait_for_upload_event(
+    server_addr: String,
+    credentials: Vec<u8>,
+    last_event_id: Option<String>,
+    timeout_secs: u64,
+) -> io::Result<Option<UploadEventInfo>> {
+    let (server_username, server_password) = parse_user_credentials(credentials)?;
+    let http_client = HttpClient::new(server_addr, server_username, server_password);
+
+    let event = http_client
+        .wait_for_upload_event(last_event_id.as_deref(), Duration::from_secs(timeout_secs))?;
+    Ok(event.map(|event| UploadEventInfo {
+        camera: event.camera,
+        filename: event.filename,
+        event_id: event.id,
+    }))
+}
+
+/// Exports the state of all MLS clients, encrypted under the passphrase, to move the app to a new device.
+/// The app on this device must be deregistered (without notifying the camera) once the state is imported.
+pub fn export_state(clients: &mut Option<Box<Clients>>, passphrase: String) -> io::Result<Vec<u8>> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    let mut states: Vec<Vec<u8>> = Vec::with_capacity(NUM_MLS_CLIENTS);
+    for mls_client in clients.as_mut().unwrap().mls_clients.iter_mut() {
+        states.push(mls_client.export_state(&passphrase)?);
+    }
+
+    bincode::serialize(&states)
+        .map_err(|e| io::Error::other(format!("Failed to serialize exported state - {e}")))
+}
+
+/// Imports the state exported by export_state() on the old device and initializes the clients with it.
+/// Must be called on a fresh install, instead of initialize(..., true).
+pub fn import_state(
+    clients: &mut Option<Box<Clients>>,
+    file_dir: String,
+    state: Vec<u8>,
+    passphrase: String,
+) -> io::Result<bool> {
+    let states: Vec<Vec<u8>> = bincode::deserialize(&state)
+        .map_err(|e| io::Error::other(format!("Failed to deserialize exported state - {e}")))?;
+    if states.len() != NUM_MLS_CLIENTS {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Expected state for {NUM_MLS_CLIENTS} clients, found {}", states.len()),
+        ));
+    }
+
+    for (i, client_state) in states.iter().enumerate() {
+        let app_name = MlsClient::import_state(
+            client_state,
+            &passphrase,
+            file_dir.clone(),
+            MLS_CLIENT_TAGS[i].to_string(),
+        )?;
+
+        let mut file = fs::File::create(format!("{}/app_{}_name", file_dir, MLS_CLIENT_TAGS[i]))?;
+        file.write_all(app_name.as_bytes())?;
+        file.flush()?;
+        file.sync_all()?;
+    }
+
+    info!("Imported state of {NUM_MLS_CLIENTS} clients");
+    initialize(clients, file_dir, false)
+}
+
 pub fn generate_heartbeat_request_config_command(
     clients: &mut Option<Box<Clients>>,
     timestamp: u64,
@@ -706,9 +1038,10 @@ pub fn generate_heartbeat_request_config_command(
     let mut config_msg = vec![OPCODE_HEARTBEAT_REQUEST];
     config_msg.extend(bincode::serialize(&heartbeat_request).unwrap());
 
-    let config_msg_enc = clients.as_mut().unwrap().mls_clients[CONFIG].encrypt(&config_msg)?;
+    let config_msg_enc = clients.as_mut().unwrap().mls_clients[CONFIG]
+        .encrypt_bounded(&config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
 
-    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state().unwrap();
+    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
 
     Ok(config_msg_enc)
 }
@@ -718,6 +1051,58 @@ pub fn process_heartbeat_config_response(
     config_response: Vec<u8>,
     expected_timestamp: u64,
 ) -> io::Result<String> {
+    let status = heartbeat_status(clients, config_response, expected_timestamp)?;
+    serde_json::to_string(&status).map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Same as process_heartbeat_config_response, and records a healthy response in tracker.
+pub fn process_heartbeat_config_response_tracked(
+    clients: &mut Option<Box<Clients>>,
+    config_response: Vec<u8>,
+    expected_timestamp: u64,
+    tracker: &mut HeartbeatTracker,
+    camera: String,
+) -> io::Result<String> {
+    let status = heartbeat_status(clients, config_response, expected_timestamp)?;
+    if status.status == HEALTHY_HEARTBEAT {
+        tracker.record_healthy_response(&camera, expected_timestamp);
+    }
+    serde_json::to_string(&status).map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Tracks the heartbeat requests sent to the cameras (see is_heartbeat_overdue).
+pub fn new_heartbeat_tracker() -> HeartbeatTracker {
+    HeartbeatTracker::new()
+}
+
+/// To be called with the timestamp passed to generate_heartbeat_request_config_command
+/// once the request is sent.
+pub fn record_heartbeat_request(tracker: &mut HeartbeatTracker, camera: String, timestamp: u64) {
+    tracker.record_request(&camera, timestamp);
+}
+
+/// Whether no healthy heartbeat came back within threshold of a request,
+/// i.e., the camera should be shown as unreachable.
+/// now and threshold must use the same unit as the request timestamps.
+pub fn is_heartbeat_overdue(
+    tracker: &HeartbeatTracker,
+    camera: String,
+    now: u64,
+    threshold: u64,
+) -> bool {
+    tracker.is_heartbeat_overdue(&camera, now, threshold)
+}
+
+/// Timestamp of the last heartbeat request sent to the camera, if any.
+pub fn last_heartbeat_request(tracker: &HeartbeatTracker, camera: String) -> Option<u64> {
+    tracker.last_request(&camera)
+}
+
+fn heartbeat_status(
+    clients: &mut Option<Box<Clients>>,
+    config_response: Vec<u8>,
+    expected_timestamp: u64,
+) -> io::Result<HeartbeatStatus> {
     if clients.is_none() {
         return Err(io::Error::other(
             "Error: clients not initialized!".to_string(),
@@ -726,13 +1111,10 @@ pub fn process_heartbeat_config_response(
 
     match clients.as_mut().unwrap().mls_clients[CONFIG].decrypt(config_response, true) {
         Ok(command) => {
-            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state().unwrap();
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
             match command[0] {
                 OPCODE_HEARTBEAT_RESPONSE => {
-                    let heartbeat: Heartbeat =
-                        bincode::deserialize(&command[1..]).map_err(|e| {
-                            io::Error::other(format!("Failed to deserialize heartbeat msg - {e}"))
-                        })?;
+                    let heartbeat = Heartbeat::from_bytes(&command[1..])?;
 
                     let heartbeat_result = heartbeat.process(
                         &mut clients.as_mut().unwrap().mls_clients,
@@ -740,29 +1122,29 @@ pub fn process_heartbeat_config_response(
                     )?;
 
                     match heartbeat_result {
-                        HeartbeatResult::HealthyHeartbeat(_timestamp) => {
-                            let status = HeartbeatStatus {
-                                status: "healthy".to_string(),
-                                version_info: Some(CameraVersionInfo {
-                                    firmware_version: heartbeat.firmware_version,
-                                    os_version: heartbeat.os_version,
-                                }),
-                            };
-                            serde_json::to_string(&status)
-                                .map_err(|e| io::Error::other(e.to_string()))
-                        }
-                        HeartbeatResult::InvalidTimestamp => Ok(serde_json::to_string(&HeartbeatStatus {
+                        HeartbeatResult::HealthyHeartbeat(_timestamp) => Ok(HeartbeatStatus {
+                            status: HEALTHY_HEARTBEAT.to_string(),
+                            version_info: Some(CameraVersionInfo {
+                                firmware_version: heartbeat.firmware_version,
+                                os_version: heartbeat.os_version,
+                            }),
+                            events: heartbeat.events,
+                        }),
+                        HeartbeatResult::InvalidTimestamp => Ok(HeartbeatStatus {
                             status: "invalid timestamp".to_string(),
                             version_info: None,
-                        }).unwrap()),
-                        HeartbeatResult::InvalidCiphertext => Ok(serde_json::to_string(&HeartbeatStatus {
+                            events: vec![],
+                        }),
+                        HeartbeatResult::InvalidCiphertext => Ok(HeartbeatStatus {
                             status: "invalid ciphertext".to_string(),
                             version_info: None,
-                        }).unwrap()),
-                        HeartbeatResult::InvalidEpoch => Ok(serde_json::to_string(&HeartbeatStatus {
+                            events: vec![],
+                        }),
+                        HeartbeatResult::InvalidEpoch => Ok(HeartbeatStatus {
                             status: "invalid epoch".to_string(),
                             version_info: None,
-                        }).unwrap()),
+                            events: vec![],
+                        }),
                     }
                 }
                 _ => {
@@ -775,7 +1157,7 @@ pub fn process_heartbeat_config_response(
         }
         Err(e) => {
             error!("Failed to decrypt command message: {e}");
-            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state().unwrap();
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
             Err(io::Error::other(format!(
                 "Failed to decrypt command message: {e}"
             )))
@@ -783,6 +1165,189 @@ pub fn process_heartbeat_config_response(
     }
 }
 
+pub fn generate_snapshot_request_config_command(
+    clients: &mut Option<Box<Clients>>,
+) -> io::Result<Vec<u8>> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    let config_msg = vec![OPCODE_SNAPSHOT_REQUEST];
+
+    let config_msg_enc = clients.as_mut().unwrap().mls_clients[CONFIG]
+        .encrypt_bounded(&config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
+
+    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+
+    Ok(config_msg_enc)
+}
+
+/// Returns the snapshot response as JSON. When the snapshot was taken, it's delivered
+/// as a regular thumbnail (see decrypt_thumbnail()).
+pub fn process_snapshot_config_response(
+    clients: &mut Option<Box<Clients>>,
+    config_response: Vec<u8>,
+) -> io::Result<String> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    match clients.as_mut().unwrap().mls_clients[CONFIG].decrypt(config_response, true) {
+        Ok(command) => {
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+            let response = SnapshotResponse::from_config_msg(&command)?;
+            serde_json::to_string(&response).map_err(|e| io::Error::other(e.to_string()))
+        }
+        Err(e) => {
+            error!("Failed to decrypt command message: {e}");
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+            Err(io::Error::other(format!(
+                "Failed to decrypt command message: {e}"
+            )))
+        }
+    }
+}
+
+pub fn generate_list_segments_request_config_command(
+    clients: &mut Option<Box<Clients>>,
+) -> io::Result<Vec<u8>> {
+    encrypt_config_command(clients, vec![OPCODE_LIST_SEGMENTS_REQUEST])
+}
+
+/// Asks the camera to send its recorded segments that overlap [from, to] as motion videos.
+pub fn generate_retrieve_segments_request_config_command(
+    clients: &mut Option<Box<Clients>>,
+    from: u64,
+    to: u64,
+) -> io::Result<Vec<u8>> {
+    let mut config_msg = vec![OPCODE_RETRIEVE_SEGMENTS_REQUEST];
+    config_msg.extend(bincode::serialize(&RetrieveSegmentsRequest { from, to }).unwrap());
+
+    encrypt_config_command(clients, config_msg)
+}
+
+/// schedule_json is a NotificationSchedule in JSON (see client_lib/src/notification_schedule.rs).
+pub fn generate_set_schedule_config_command(
+    clients: &mut Option<Box<Clients>>,
+    schedule_json: String,
+) -> io::Result<Vec<u8>> {
+    let schedule: NotificationSchedule = serde_json::from_str(&schedule_json)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    schedule.validate()?;
+
+    let mut config_msg = vec![OPCODE_SET_SCHEDULE_REQUEST];
+    config_msg.extend(bincode::serialize(&schedule).unwrap());
+
+    encrypt_config_command(clients, config_msg)
+}
+
+/// Returns the set schedule response as JSON.
+pub fn process_set_schedule_config_response(
+    clients: &mut Option<Box<Clients>>,
+    config_response: Vec<u8>,
+) -> io::Result<String> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    let command = match clients.as_mut().unwrap().mls_clients[CONFIG].decrypt(config_response, true) {
+        Ok(command) => command,
+        Err(e) => {
+            error!("Failed to decrypt command message: {e}");
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+            return Err(io::Error::other(format!(
+                "Failed to decrypt command message: {e}"
+            )));
+        }
+    };
+    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+
+    match command.split_first() {
+        Some((&OPCODE_SET_SCHEDULE_RESPONSE, response_bytes)) => {
+            let response: SetScheduleResponse = bincode::deserialize(response_bytes)
+                .map_err(|e| io::Error::other(format!("Failed to deserialize schedule msg - {e}")))?;
+            serde_json::to_string(&response).map_err(|e| io::Error::other(e.to_string()))
+        }
+        _ => {
+            error!("Error: Unexpected config command response opcode! - {:?}", command.first());
+            Err(io::Error::other(
+                "Error: Unexpected config response opcode!".to_string(),
+            ))
+        }
+    }
+}
+
+fn encrypt_config_command(
+    clients: &mut Option<Box<Clients>>,
+    config_msg: Vec<u8>,
+) -> io::Result<Vec<u8>> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    let config_msg_enc = clients.as_mut().unwrap().mls_clients[CONFIG]
+        .encrypt_bounded(&config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
+
+    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+
+    Ok(config_msg_enc)
+}
+
+/// Returns the list segments or retrieve segments response as JSON.
+pub fn process_segments_config_response(
+    clients: &mut Option<Box<Clients>>,
+    config_response: Vec<u8>,
+) -> io::Result<String> {
+    if clients.is_none() {
+        return Err(io::Error::other(
+            "Error: clients not initialized!".to_string(),
+        ));
+    }
+
+    let command = match clients.as_mut().unwrap().mls_clients[CONFIG].decrypt(config_response, true) {
+        Ok(command) => command,
+        Err(e) => {
+            error!("Failed to decrypt command message: {e}");
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+            return Err(io::Error::other(format!(
+                "Failed to decrypt command message: {e}"
+            )));
+        }
+    };
+    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
+
+    let deserialize_error =
+        |e| io::Error::other(format!("Failed to deserialize segments msg - {e}"));
+    let json = match command.first() {
+        Some(&OPCODE_LIST_SEGMENTS_RESPONSE) => {
+            let response: ListSegmentsResponse =
+                bincode::deserialize(&command[1..]).map_err(deserialize_error)?;
+            serde_json::to_string(&response)
+        }
+        Some(&OPCODE_RETRIEVE_SEGMENTS_RESPONSE) => {
+            let response: RetrieveSegmentsResponse =
+                bincode::deserialize(&command[1..]).map_err(deserialize_error)?;
+            serde_json::to_string(&response)
+        }
+        _ => {
+            error!("Error: Unexpected config command response opcode! - {:?}", command.first());
+            return Err(io::Error::other(
+                "Error: Unexpected config response opcode!".to_string(),
+            ));
+        }
+    };
+
+    json.map_err(|e| io::Error::other(e.to_string()))
+}
+
 pub fn get_add_app_secret() -> io::Result<String> {
     generate_add_app_secret()
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
@@ -826,9 +1391,10 @@ pub fn generate_add_app_request_config_command(
     let mut config_msg = vec![OPCODE_ADD_APP_REQUEST];
     config_msg.extend(bincode::serialize(&add_app_requests).unwrap());
 
-    let config_msg_enc = clients.as_mut().unwrap().mls_clients[CONFIG].encrypt(&config_msg)?;
+    let config_msg_enc = clients.as_mut().unwrap().mls_clients[CONFIG]
+        .encrypt_bounded(&config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
 
-    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state().unwrap();
+    clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
 
     Ok(config_msg_enc)
 }
@@ -853,7 +1419,7 @@ pub fn process_add_app_config_response(
 
     match clients.as_mut().unwrap().mls_clients[CONFIG].decrypt(config_response, true) {
         Ok(command) => {
-            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state().unwrap();
+            clients.as_mut().unwrap().mls_clients[CONFIG].save_group_state()?;
             match command[0] {
                 OPCODE_ADD_APP_RESPONSE => {
                     let (add_app_resps_com, add_app_resps_ded):
@@ -867,7 +1433,6 @@ pub fn process_add_app_config_response(
                             // Merge the psk_proposal and commit for the add operation
                             clients.as_mut().unwrap().mls_clients[i].decrypt(add_app_resps_com[i].psk_proposal_vec.clone(), false).unwrap();
                             clients.as_mut().unwrap().mls_clients[i].decrypt_with_secret(add_app_resps_com[i].commit_msg_vec.clone(), false, secret.clone()).unwrap();
-                            clients.as_mut().unwrap().mls_clients[i].save_group_state().unwrap();
 
                             // Prepare data for the new app
                             NewAppData {
@@ -884,6 +1449,10 @@ pub fn process_add_app_config_response(
                         }
                     });
 
+                    for mls_client in clients.as_mut().unwrap().mls_clients[..NUM_COMMON_MLS_CLIENTS].iter_mut() {
+                        mls_client.save_group_state()?;
+                    }
+
                     let new_app_data_vec = bincode::serialize(&new_app_data).unwrap();
 
                     return Ok(new_app_data_vec)
@@ -918,11 +1487,14 @@ pub fn join_camera_groups(
             MlsClient::create_contact("camera", new_app_data[i].camera_key_package.clone()).unwrap();
 
         clients.as_mut().unwrap().mls_clients[i].process_welcome_with_secret(app_contact, new_app_data[i].welcome_msg_vec.clone(), secret.clone(), &new_app_data[i].group_name).unwrap();
-        clients.as_mut().unwrap().mls_clients[i].save_group_state().unwrap();
 
         clients.as_mut().unwrap().mls_clients[i].get_epoch().unwrap()
     });
 
+    for mls_client in clients.as_mut().unwrap().mls_clients.iter_mut() {
+        mls_client.save_group_state()?;
+    }
+
     // FIXME: return the firmware version too.
     // FIXME: return a String, similar to add_camera
     Ok(epochs)
@@ -0,0 +1,72 @@
+//! Recent camera-side problems, sent to the app with the heartbeat response.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use secluso_client_lib::config::CameraEvent;
+use std::collections::VecDeque;
+#[cfg(feature = "ip")]
+use std::io;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Oldest events are dropped beyond this.
+const MAX_CAMERA_EVENTS: usize = 32;
+/// How many of the most recent events go into a heartbeat response.
+/// Keeps the response well under the config channel's ciphertext limit.
+const MAX_HEARTBEAT_EVENTS: usize = 10;
+const MAX_EVENT_MESSAGE_LEN: usize = 256;
+
+pub const EVENT_MOTION_DETECTION_ERROR: &str = "motion_detection_error";
+#[cfg(feature = "ip")]
+pub const EVENT_STREAM_FAILED: &str = "stream_failed";
+#[cfg(feature = "ip")]
+pub const EVENT_RECORDING_FAILED: &str = "recording_failed";
+#[cfg(feature = "ip")]
+pub const EVENT_STORAGE_FULL: &str = "storage_full";
+
+static CAMERA_EVENTS: Mutex<VecDeque<CameraEvent>> = Mutex::new(VecDeque::new());
+
+/// Records a camera-side problem for the app.
+/// A repeat of the most recent event only refreshes its timestamp,
+/// so that an error hit in a loop doesn't push out everything else.
+pub fn report_camera_event(code: &str, message: &str) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let message: String = message.chars().take(MAX_EVENT_MESSAGE_LEN).collect();
+
+    let mut events = CAMERA_EVENTS.lock().unwrap();
+    if let Some(last) = events.back_mut() {
+        if last.code == code && last.message == message {
+            last.timestamp = timestamp;
+            return;
+        }
+    }
+
+    if events.len() == MAX_CAMERA_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(CameraEvent {
+        code: code.to_string(),
+        message,
+        timestamp,
+    });
+}
+
+/// The most recent events, oldest first.
+pub fn recent_camera_events() -> Vec<CameraEvent> {
+    let events = CAMERA_EVENTS.lock().unwrap();
+    let skip = events.len().saturating_sub(MAX_HEARTBEAT_EVENTS);
+    events.iter().skip(skip).cloned().collect()
+}
+
+/// Event code for a failure to write a recording to disk.
+#[cfg(feature = "ip")]
+pub fn recording_event_code(e: &io::Error) -> &'static str {
+    if e.kind() == io::ErrorKind::StorageFull {
+        EVENT_STORAGE_FULL
+    } else {
+        EVENT_RECORDING_FAILED
+    }
+}
@@ -0,0 +1,36 @@
+//! Decides when to tell the app that the camera went offline or came back,
+//! based on the failed attempts in a row to (re)connect to the camera's stream.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use secluso_client_lib::camera_status::CameraStatusNotification;
+
+// After this many failed attempts in a row to reconnect to the camera's stream, we tell the app the camera is offline.
+pub const CAMERA_OFFLINE_FAILURES: u32 = 3;
+
+#[derive(Default)]
+pub struct CameraStatusTracker {
+    offline_notified: bool,
+}
+
+impl CameraStatusTracker {
+    /// The notification to send, if the camera's status changed since the last one that was sent.
+    pub fn status_change(&self, stream_failures: u32, now: u64) -> Option<CameraStatusNotification> {
+        if stream_failures >= CAMERA_OFFLINE_FAILURES && !self.offline_notified {
+            Some(CameraStatusNotification::CameraOffline {
+                consecutive_failures: stream_failures,
+                timestamp: now,
+            })
+        } else if stream_failures == 0 && self.offline_notified {
+            Some(CameraStatusNotification::CameraOnline { timestamp: now })
+        } else {
+            None
+        }
+    }
+
+    /// Called once the notification returned by status_change() is sent.
+    /// If sending it failed, status_change() returns it again next time.
+    pub fn notification_sent(&mut self) {
+        self.offline_notified = !self.offline_notified;
+    }
+}
@@ -2,30 +2,46 @@
 //!
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::camera_events::recent_camera_events;
+use crate::delivery_monitor::VideoInfo;
+use crate::motion::{
+    prepare_motion_thumbnail, prepare_motion_video, upload_pending_enc_thumbnails,
+    upload_pending_enc_videos,
+};
+use crate::notification_schedule::persist_notification_schedule;
 use crate::pairing::io::get_names;
+use crate::traits::Camera;
 use crate::version::camera_version_info;
 use crate::DeliveryMonitor;
+use image::ImageFormat;
 use secluso_client_lib::config::{
     AddAppRequest, AddAppResponseCommon, AddAppResponseDedicated, Heartbeat, HeartbeatRequest,
-    OPCODE_ADD_APP_REQUEST, OPCODE_ADD_APP_RESPONSE, OPCODE_HEARTBEAT_REQUEST,
-    OPCODE_HEARTBEAT_RESPONSE,
+    ListSegmentsResponse, RetrieveSegmentsRequest, RetrieveSegmentsResponse, SetScheduleResponse,
+    SnapshotResponse, OPCODE_ADD_APP_REQUEST, OPCODE_ADD_APP_RESPONSE, OPCODE_HEARTBEAT_REQUEST,
+    OPCODE_HEARTBEAT_RESPONSE, OPCODE_LIST_SEGMENTS_REQUEST, OPCODE_LIST_SEGMENTS_RESPONSE,
+    OPCODE_RETRIEVE_SEGMENTS_REQUEST, OPCODE_RETRIEVE_SEGMENTS_RESPONSE,
+    OPCODE_SET_SCHEDULE_REQUEST, OPCODE_SET_SCHEDULE_RESPONSE, OPCODE_SNAPSHOT_REQUEST,
 };
 use secluso_client_lib::http_client::HttpClient;
-use secluso_client_lib::mls_client::{ClientType, MlsClient};
+use secluso_client_lib::mls_client::{ClientType, MlsClient, MAX_APPS_PER_GROUP};
 use secluso_client_lib::mls_clients::{
-    MlsClientsCommon, MlsClientsDedicated, CONFIG_DED, NUM_COMMON_MLS_CLIENTS,
-    NUM_DEDICATED_MLS_CLIENTS, NUM_MLS_CLIENTS,
+    MlsClientsCommon, MlsClientsDedicated, CONFIG, CONFIG_DED, MAX_CIPHERTEXT_SIZES,
+    NUM_COMMON_MLS_CLIENTS, NUM_DEDICATED_MLS_CLIENTS, NUM_MLS_CLIENTS, MOTION, THUMBNAIL,
 };
+use secluso_client_lib::notification_schedule::NotificationSchedule;
+use secluso_client_lib::thumbnail_meta_info::ThumbnailMetaInfo;
 use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn process_config_command(
     clients_com: &mut MlsClientsCommon,
     clients_ded: &mut MlsClientsDedicated,
     enc_config_command: &[u8],
     http_client: &HttpClient,
-    delivery_monitor_opt: Option<&mut DeliveryMonitor>,
+    camera: &dyn Camera,
+    delivery_monitor: &mut DeliveryMonitor,
     primary_app: bool,
-    second_app_already_paired: bool,
+    num_apps: u32, // Number of apps paired so far, including the primary one
 ) -> anyhow::Result<Option<MlsClientsDedicated>> {
     debug!("Processing config command");
     match clients_ded[CONFIG_DED].decrypt(enc_config_command.to_vec(), true) {
@@ -39,22 +55,81 @@ pub fn process_config_command(
                         clients_ded,
                         &command[1..],
                         http_client,
-                        delivery_monitor_opt,
+                        // TODO: We only keep track of video delivery to the primary app for now.
+                        primary_app.then_some(delivery_monitor),
+                    )?;
+                    Ok(None)
+                }
+                OPCODE_SNAPSHOT_REQUEST => {
+                    debug!("Handling snapshot request");
+                    handle_snapshot_request(
+                        clients_com,
+                        clients_ded,
+                        http_client,
+                        camera,
+                        delivery_monitor,
+                        num_apps,
+                    )?;
+                    Ok(None)
+                }
+                OPCODE_LIST_SEGMENTS_REQUEST => {
+                    debug!("Handling list segments request");
+                    let response = match camera.recorded_segments() {
+                        Some(store) => match store.catalog() {
+                            Ok(segments) => ListSegmentsResponse::Segments(segments),
+                            Err(e) => ListSegmentsResponse::Failed(e.to_string()),
+                        },
+                        None => ListSegmentsResponse::Failed(
+                            "Continuous recording is not enabled".to_string(),
+                        ),
+                    };
+
+                    let mut config_msg = vec![OPCODE_LIST_SEGMENTS_RESPONSE];
+                    config_msg.extend(bincode::serialize(&response)?);
+                    send_config_response(clients_ded, &config_msg, http_client)?;
+                    Ok(None)
+                }
+                OPCODE_RETRIEVE_SEGMENTS_REQUEST => {
+                    debug!("Handling retrieve segments request");
+                    handle_retrieve_segments_request(
+                        clients_com,
+                        clients_ded,
+                        &command[1..],
+                        http_client,
+                        camera,
+                        delivery_monitor,
+                        num_apps,
                     )?;
                     Ok(None)
                 }
+                OPCODE_SET_SCHEDULE_REQUEST => {
+                    debug!("Handling set schedule request");
+                    let response = match set_notification_schedule(camera, &command[1..]) {
+                        Ok(()) => SetScheduleResponse::Saved,
+                        Err(e) => {
+                            error!("Failed to set the notification schedule: {e}");
+                            SetScheduleResponse::Failed(e.to_string())
+                        }
+                    };
+
+                    let mut config_msg = vec![OPCODE_SET_SCHEDULE_RESPONSE];
+                    config_msg.extend(bincode::serialize(&response)?);
+                    send_config_response(clients_ded, &config_msg, http_client)?;
+                    Ok(None)
+                }
                 OPCODE_ADD_APP_REQUEST => {
                     if primary_app {
-                        if !second_app_already_paired {
+                        if (num_apps as usize) < MAX_APPS_PER_GROUP {
                             debug!("Handling add_app request");
                             handle_add_app_request(
                                 clients_com,
                                 clients_ded,
                                 &command[1..],
                                 http_client,
+                                num_apps + 1,
                             )
                         } else {
-                            error!("Error: Secondary app is already paired!");
+                            error!("Error: Already paired with {MAX_APPS_PER_GROUP} apps!");
                             Ok(None)
                         }
                     } else {
@@ -118,12 +193,113 @@ fn send_heartbeat_response(
     http_client: &HttpClient,
 ) -> io::Result<()> {
     let heartbeat =
-        Heartbeat::generate(clients_com, clients_ded, timestamp, camera_version_info()?)?;
+        Heartbeat::generate(
+            clients_com,
+            clients_ded,
+            timestamp,
+            camera_version_info()?,
+            recent_camera_events(),
+        )?;
 
     let mut config_msg = vec![OPCODE_HEARTBEAT_RESPONSE];
     config_msg.extend(bincode::serialize(&heartbeat).unwrap());
 
-    let config_msg_enc = clients_ded[CONFIG_DED].encrypt(&config_msg)?;
+    let config_msg_enc =
+        clients_ded[CONFIG_DED].encrypt_bounded(&config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
+    clients_ded[CONFIG_DED].save_group_state()?;
+
+    http_client.config_response(
+        &clients_ded[CONFIG_DED].get_group_name().unwrap(),
+        config_msg_enc,
+    )?;
+
+    Ok(())
+}
+
+fn handle_snapshot_request(
+    clients_com: &mut MlsClientsCommon,
+    clients_ded: &mut MlsClientsDedicated,
+    http_client: &HttpClient,
+    camera: &dyn Camera,
+    delivery_monitor: &mut DeliveryMonitor,
+    num_apps: u32,
+) -> io::Result<()> {
+    let response = match take_snapshot(clients_com, http_client, camera, delivery_monitor, num_apps)
+    {
+        Ok(thumbnail_info) => SnapshotResponse::Taken {
+            timestamp: thumbnail_info.timestamp,
+            filename: ThumbnailMetaInfo::get_filename_from_timestamp(thumbnail_info.timestamp),
+        },
+        Err(e) => {
+            error!("Failed to take snapshot: {e}");
+            SnapshotResponse::Failed(e.to_string())
+        }
+    };
+
+    send_config_response(clients_ded, &response.to_config_msg(), http_client)
+}
+
+/// Sends a still from the camera as a regular thumbnail (without detections),
+/// so that the app downloads and decrypts it like any other thumbnail.
+fn take_snapshot(
+    clients_com: &mut MlsClientsCommon,
+    http_client: &HttpClient,
+    camera: &dyn Camera,
+    delivery_monitor: &mut DeliveryMonitor,
+    num_apps: u32,
+) -> io::Result<ThumbnailMetaInfo> {
+    let jpeg = camera.capture_still()?;
+    // Thumbnails are stored as PNG files.
+    let image = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let thumbnail_info = ThumbnailMetaInfo::new(timestamp, 0, vec![]); //0 epoch = unset
+    let thumbnail_file = camera.get_thumbnail_dir()
+        + "/"
+        + &ThumbnailMetaInfo::get_filename_from_timestamp(timestamp);
+    image
+        .to_rgb8()
+        .save(thumbnail_file)
+        .map_err(io::Error::other)?;
+
+    prepare_motion_thumbnail(
+        &mut clients_com[THUMBNAIL],
+        thumbnail_info.clone(),
+        delivery_monitor,
+    )?;
+
+    // If this fails, the core loop retries with the other pending thumbnails.
+    let _ = upload_pending_enc_thumbnails(
+        &clients_com[THUMBNAIL].get_group_name().unwrap(),
+        delivery_monitor,
+        http_client,
+        num_apps,
+    );
+
+    Ok(thumbnail_info)
+}
+
+fn set_notification_schedule(camera: &dyn Camera, command_bytes: &[u8]) -> io::Result<()> {
+    let schedule: NotificationSchedule = bincode::deserialize(command_bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    schedule.validate()?;
+    persist_notification_schedule(&camera.get_state_dir(), &schedule)?;
+    info!("Notification schedule updated: {:?}", schedule);
+
+    Ok(())
+}
+
+fn send_config_response(
+    clients_ded: &mut MlsClientsDedicated,
+    config_msg: &[u8],
+    http_client: &HttpClient,
+) -> io::Result<()> {
+    let config_msg_enc =
+        clients_ded[CONFIG_DED].encrypt_bounded(config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
     clients_ded[CONFIG_DED].save_group_state()?;
 
     http_client.config_response(
@@ -134,11 +310,88 @@ fn send_heartbeat_response(
     Ok(())
 }
 
+fn handle_retrieve_segments_request(
+    clients_com: &mut MlsClientsCommon,
+    clients_ded: &mut MlsClientsDedicated,
+    command_bytes: &[u8],
+    http_client: &HttpClient,
+    camera: &dyn Camera,
+    delivery_monitor: &mut DeliveryMonitor,
+    num_apps: u32,
+) -> io::Result<()> {
+    let request: RetrieveSegmentsRequest = bincode::deserialize(command_bytes).map_err(|e| {
+        io::Error::other(format!("Failed to deserialize retrieve segments msg - {e}"))
+    })?;
+
+    let response = match queue_recorded_segments(
+        clients_com,
+        &request,
+        http_client,
+        camera,
+        delivery_monitor,
+        num_apps,
+    ) {
+        Ok(timestamps) => RetrieveSegmentsResponse::Queued(timestamps),
+        Err(e) => {
+            error!("Failed to retrieve recorded segments: {e}");
+            RetrieveSegmentsResponse::Failed(e.to_string())
+        }
+    };
+
+    let mut config_msg = vec![OPCODE_RETRIEVE_SEGMENTS_RESPONSE];
+    config_msg.extend(bincode::serialize(&response).map_err(io::Error::other)?);
+    send_config_response(clients_ded, &config_msg, http_client)
+}
+
+/// Sends the recorded segments in the requested range as motion videos (one per segment).
+/// Returns their video timestamps.
+fn queue_recorded_segments(
+    clients_com: &mut MlsClientsCommon,
+    request: &RetrieveSegmentsRequest,
+    http_client: &HttpClient,
+    camera: &dyn Camera,
+    delivery_monitor: &mut DeliveryMonitor,
+    num_apps: u32,
+) -> io::Result<Vec<u64>> {
+    let store = camera.recorded_segments().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Continuous recording is not enabled",
+        )
+    })?;
+
+    let mut timestamps = Vec::new();
+    for segment in store.segments_in_range(request.from, request.to)? {
+        let video_info = VideoInfo::from(segment.start);
+        let video_file_path = delivery_monitor.get_video_file_path(&video_info);
+        // Already being sent (e.g., requested twice).
+        if video_file_path.exists() {
+            timestamps.push(video_info.timestamp);
+            continue;
+        }
+
+        store.decrypt_segment(&segment, &video_file_path)?;
+        prepare_motion_video(&mut clients_com[MOTION], video_info.clone(), delivery_monitor)?;
+        timestamps.push(video_info.timestamp);
+    }
+
+    // The rest are uploaded by the core loop.
+    let _ = upload_pending_enc_videos(
+        &clients_com[MOTION].get_group_name().unwrap(),
+        delivery_monitor,
+        http_client,
+        num_apps,
+    );
+
+    Ok(timestamps)
+}
+
 fn handle_add_app_request(
     clients_com: &mut MlsClientsCommon,
     clients_ded: &mut MlsClientsDedicated,
     command_bytes: &[u8],
     http_client: &HttpClient,
+    app_number: u32, // 2 for the first app added after the primary one
 ) -> anyhow::Result<Option<MlsClientsDedicated>> {
     let add_app_requests: [AddAppRequest; NUM_MLS_CLIENTS] = bincode::deserialize(command_bytes)
         .map_err(|e| io::Error::other(format!("Failed to deserialize add_app msg - {e}")))?;
@@ -148,11 +401,12 @@ fn handle_add_app_request(
             println!("handle_add_app_request [1]");
             let camera_key_package = clients_com[i].key_package();
 
-            // FIXME: "app2" is hardcoded.
             println!("handle_add_app_request [2]");
-            let camera_contact =
-                MlsClient::create_contact("app2", add_app_requests[i].new_app_key_package.clone())
-                    .unwrap();
+            let camera_contact = MlsClient::create_contact(
+                &format!("app{app_number}"),
+                add_app_requests[i].new_app_key_package.clone(),
+            )
+            .unwrap();
 
             println!("handle_add_app_request [3]");
             // FIXME: Use a different secret per channel
@@ -161,9 +415,6 @@ fn handle_add_app_request(
                 .unwrap();
 
             println!("handle_add_app_request [4]");
-            clients_com[i].save_group_state().unwrap();
-
-            println!("handle_add_app_request [5]");
             AddAppResponseCommon {
                 camera_key_package,
                 welcome_msg_vec,
@@ -172,10 +423,14 @@ fn handle_add_app_request(
             }
         });
 
+    for client in clients_com.iter_mut() {
+        client.save_group_state()?;
+    }
+
     let [(client_l, resp_l), (client_c, resp_c)]: [(MlsClient, AddAppResponseDedicated);
         NUM_DEDICATED_MLS_CLIENTS] = [
-        create_client(0, clients_ded, &add_app_requests)?,
-        create_client(1, clients_ded, &add_app_requests)?,
+        create_client(0, clients_ded, &add_app_requests, app_number)?,
+        create_client(1, clients_ded, &add_app_requests, app_number)?,
     ];
 
     let new_clients_ded: MlsClientsDedicated = [client_l, client_c];
@@ -187,7 +442,8 @@ fn handle_add_app_request(
     let mut config_msg = vec![OPCODE_ADD_APP_RESPONSE];
     config_msg.extend(bincode::serialize(&add_app_resp_combined)?);
 
-    let config_msg_enc = clients_ded[CONFIG_DED].encrypt(&config_msg)?;
+    let config_msg_enc =
+        clients_ded[CONFIG_DED].encrypt_bounded(&config_msg, MAX_CIPHERTEXT_SIZES[CONFIG])?;
     println!("[1]: config_msg_enc len = {:?}", config_msg_enc.len());
     clients_ded[CONFIG_DED].save_group_state()?;
 
@@ -203,12 +459,17 @@ fn create_client(
     i: usize,
     clients_ded: &mut MlsClientsDedicated,
     add_app_requests: &[AddAppRequest; NUM_MLS_CLIENTS],
+    app_number: u32,
 ) -> anyhow::Result<(MlsClient, AddAppResponseDedicated)> {
     // This part of code has a lot in common with initialize_mls_clients() in main.rs
 
     // Initialize mls_client
-    // FIXME
-    let tag = if i == 0 { "livestream2" } else { "config2" };
+    // The second app's clients are livestream2 and config2, as when only two apps were supported.
+    let tag = if i == 0 {
+        format!("livestream{app_number}")
+    } else {
+        format!("config{app_number}")
+    };
 
     let (camera_name, group_name) = get_names(
         &clients_ded[CONFIG_DED].get_file_dir(), // Could use either of the clients
@@ -220,7 +481,7 @@ fn create_client(
         camera_name,
         true,
         clients_ded[CONFIG_DED].get_file_dir(), // Could use either of the clients
-        tag.to_string(),
+        tag,
         ClientType::Camera,
     )
     .expect("MlsClient::new() for returned error.");
@@ -260,3 +521,228 @@ fn create_client(
 
     Ok((client, resp))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::livestream::LivestreamWriter;
+    use crate::motion::MotionResult;
+    use image::{Rgb, RgbImage};
+    use secluso_client_lib::mls_clients::MLS_CLIENT_TAGS;
+    use secluso_client_lib::pairing::NUM_SECRET_BYTES;
+    use secluso_client_lib::video::decrypt_thumbnail_file;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // A camera that only takes stills (or can't, if it has none).
+    struct FakeCamera {
+        thumbnail_dir: String,
+        still: Option<Vec<u8>>,
+    }
+
+    impl Camera for FakeCamera {
+        fn is_there_motion(&mut self) -> Result<MotionResult, anyhow::Error> {
+            Ok(MotionResult {
+                motion: false,
+                detections: vec![],
+                thumbnail: None,
+            })
+        }
+
+        fn record_motion_video(
+            &self,
+            _info: &VideoInfo,
+            _duration: u64,
+            _preroll_secs: u64,
+        ) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn launch_livestream(&self, _livestream_writer: LivestreamWriter) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn capture_still(&self) -> io::Result<Vec<u8>> {
+            self.still.clone().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::Unsupported, "Camera does not support snapshots")
+            })
+        }
+
+        fn get_name(&self) -> String {
+            "Fake".to_string()
+        }
+
+        fn get_state_dir(&self) -> String {
+            String::new()
+        }
+
+        fn get_video_dir(&self) -> String {
+            String::new()
+        }
+
+        fn get_thumbnail_dir(&self) -> String {
+            self.thumbnail_dir.clone()
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("secluso-config-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for subdir in [
+            "camera",
+            "app/videos",
+            "videos",
+            "thumbnails",
+            "state",
+            "pending_meta",
+        ] {
+            fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        dir
+    }
+
+    fn dir_string(dir: &Path, subdir: &str) -> String {
+        dir.join(subdir).to_str().unwrap().to_string()
+    }
+
+    // The common clients of the camera, with the app in the thumbnail group.
+    fn pair_common(dir: &Path) -> (MlsClientsCommon, MlsClient) {
+        let secret = vec![0u8; NUM_SECRET_BYTES];
+        let mut clients_com: MlsClientsCommon = std::array::from_fn(|i| {
+            let mut client = MlsClient::new(
+                format!("camera-{}", MLS_CLIENT_TAGS[i]),
+                true,
+                dir_string(dir, "camera"),
+                MLS_CLIENT_TAGS[i].to_string(),
+                ClientType::Camera,
+            )
+            .unwrap();
+            client
+                .create_group(&format!("{}_group", MLS_CLIENT_TAGS[i]))
+                .unwrap();
+            client
+        });
+
+        let mut app = MlsClient::new(
+            "app".to_string(),
+            true,
+            dir_string(dir, "app"),
+            "thumbnail".to_string(),
+            ClientType::App,
+        )
+        .unwrap();
+        let camera_contact = MlsClient::create_contact("app", app.key_package()).unwrap();
+        let app_contact =
+            MlsClient::create_contact("camera", clients_com[THUMBNAIL].key_package()).unwrap();
+        let (welcome_msg_vec, _, _) = clients_com[THUMBNAIL]
+            .invite_with_secret(&camera_contact, secret.clone())
+            .unwrap();
+        clients_com[THUMBNAIL].save_group_state().unwrap();
+        app.process_welcome_with_secret(app_contact, welcome_msg_vec, secret, "thumbnail_group")
+            .unwrap();
+        app.save_group_state().unwrap();
+
+        (clients_com, app)
+    }
+
+    fn delivery_monitor(dir: &Path) -> DeliveryMonitor {
+        DeliveryMonitor::from_file_or_new(
+            dir_string(dir, "videos"),
+            dir_string(dir, "thumbnails"),
+            dir_string(dir, "state"),
+        )
+    }
+
+    // Nothing listens there, so the upload fails right away and the thumbnail stays queued.
+    fn unreachable_server() -> HttpClient {
+        HttpClient::new(
+            "http://127.0.0.1:1".to_string(),
+            "user".to_string(),
+            "password".to_string(),
+        )
+    }
+
+    #[test]
+    fn snapshot_is_sent_as_a_thumbnail() {
+        let dir = test_dir("snapshot");
+        let (mut clients_com, mut app) = pair_common(&dir);
+        let mut delivery_monitor = delivery_monitor(&dir);
+
+        let frame = RgbImage::from_fn(320, 240, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, 64])
+        });
+        let mut jpeg = io::Cursor::new(vec![]);
+        frame.write_to(&mut jpeg, ImageFormat::Jpeg).unwrap();
+        let camera = FakeCamera {
+            thumbnail_dir: dir_string(&dir, "thumbnails"),
+            still: Some(jpeg.get_ref().clone()),
+        };
+
+        let epoch = clients_com[THUMBNAIL].get_epoch().unwrap() + 1;
+        let thumbnail_info = take_snapshot(
+            &mut clients_com,
+            &unreachable_server(),
+            &camera,
+            &mut delivery_monitor,
+            1,
+        )
+        .unwrap();
+        assert_eq!(thumbnail_info.epoch, 0);
+        assert!(thumbnail_info.detections.is_empty());
+
+        // Still queued, and the app can decrypt it like any other thumbnail.
+        let queued = delivery_monitor.thumbnails_to_send();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].epoch, epoch);
+        assert_eq!(queued[0].timestamp, thumbnail_info.timestamp);
+
+        let enc_thumbnail_file_path = dir.join("thumbnails").join(epoch.to_string());
+        let dec_filename = decrypt_thumbnail_file(
+            &mut app,
+            enc_thumbnail_file_path.to_str().unwrap(),
+            &dir_string(&dir, "pending_meta"),
+        )
+        .unwrap();
+        assert_eq!(
+            dec_filename,
+            ThumbnailMetaInfo::get_filename_from_timestamp(thumbnail_info.timestamp)
+        );
+        let decrypted = image::open(dir.join("app/videos").join(dec_filename))
+            .unwrap()
+            .to_rgb8();
+        let expected = image::load_from_memory_with_format(jpeg.get_ref(), ImageFormat::Jpeg)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(decrypted, expected);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_snapshot_uses_no_epoch() {
+        let dir = test_dir("no-snapshot");
+        let (mut clients_com, _app) = pair_common(&dir);
+        let mut delivery_monitor = delivery_monitor(&dir);
+        let camera = FakeCamera {
+            thumbnail_dir: dir_string(&dir, "thumbnails"),
+            still: None,
+        };
+
+        let epoch = clients_com[THUMBNAIL].get_epoch().unwrap();
+        let err = take_snapshot(
+            &mut clients_com,
+            &unreachable_server(),
+            &camera,
+            &mut delivery_monitor,
+            1,
+        )
+        .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(clients_com[THUMBNAIL].get_epoch().unwrap(), epoch);
+        assert!(delivery_monitor.thumbnails_to_send().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
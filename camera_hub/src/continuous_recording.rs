@@ -0,0 +1,468 @@
+//! Continuous (24/7) recording to local storage on the hub.
+//! The camera stream is cut into fixed-length segments, each segment is encrypted with a
+//! key that never leaves the hub, and the oldest segments are deleted to stay within the
+//! configured disk budget. The app can list the segments and request a time range,
+//! which is then sent like a motion video.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use secluso_client_lib::config::RecordedSegment;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SEGMENT_MINUTES: u64 = 5;
+const KEY_FILENAME: &str = "continuous_recording_key";
+const KEY_SIZE: usize = 32;
+const NONCE_PREFIX_SIZE: usize = 4;
+// Segments are encrypted in chunks so that we never hold a whole segment in memory.
+const CHUNK_SIZE: usize = 1024 * 1024;
+const SEGMENT_PREFIX: &str = "segment_";
+const ENCRYPTED_SUFFIX: &str = ".enc";
+const PARTIAL_SUFFIX: &str = ".mp4.part";
+const GIB: f64 = (1u64 << 30) as f64;
+
+/// The continuous_recording section of a camera in cameras.yaml.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "ip", derive(schemars::JsonSchema))]
+pub struct ContinuousRecordingConfig {
+    /// Where the encrypted segments are stored (e.g., a mounted external disk).
+    pub dir: String,
+    /// Once the segments take more space than this, the oldest ones are deleted.
+    pub max_total_gib: f64,
+    #[serde(default)]
+    pub segment_minutes: Option<u64>,
+}
+
+impl ContinuousRecordingConfig {
+    pub fn segment_secs(&self) -> u64 {
+        self.segment_minutes.unwrap_or(DEFAULT_SEGMENT_MINUTES) * 60
+    }
+}
+
+/// The encrypted segments of one camera on disk.
+#[derive(Clone)]
+pub struct SegmentStore {
+    dir: PathBuf,
+    key_path: PathBuf,
+    max_total_bytes: u64,
+}
+
+impl SegmentStore {
+    /// The key is kept in the camera's state dir, separately from the segments.
+    pub fn new(config: &ContinuousRecordingConfig, state_dir: &str) -> io::Result<Self> {
+        if config.max_total_gib <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "continuous_recording.max_total_gib must be positive",
+            ));
+        }
+        if config.segment_minutes == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "continuous_recording.segment_minutes must be positive",
+            ));
+        }
+
+        let store = Self {
+            dir: PathBuf::from(&config.dir),
+            key_path: Path::new(state_dir).join(KEY_FILENAME),
+            max_total_bytes: (config.max_total_gib * GIB) as u64,
+        };
+        fs::create_dir_all(&store.dir)?;
+        store.remove_partial_segments()?;
+        store.load_or_create_key()?;
+
+        Ok(store)
+    }
+
+    /// Where the segment that starts at `start` is recorded before it's encrypted.
+    pub fn partial_segment_path(&self, start: u64) -> PathBuf {
+        self.dir.join(format!("{SEGMENT_PREFIX}{start}{PARTIAL_SUFFIX}"))
+    }
+
+    fn segment_path(&self, segment: &RecordedSegment) -> PathBuf {
+        self.dir.join(format!(
+            "{SEGMENT_PREFIX}{}_{}{ENCRYPTED_SUFFIX}",
+            segment.start, segment.end
+        ))
+    }
+
+    /// Encrypts a finished segment, removes the plaintext, and enforces the size limit.
+    pub fn finish_segment(&self, start: u64, end: u64) -> io::Result<RecordedSegment> {
+        let partial = self.partial_segment_path(start);
+        let mut segment = RecordedSegment {
+            start,
+            end,
+            size: 0,
+        };
+        let path = self.segment_path(&segment);
+
+        let result = encrypt_file(&self.load_or_create_key()?, &partial, &path);
+        let _ = fs::remove_file(&partial);
+        result?;
+
+        segment.size = fs::metadata(&path)?.len();
+        let evicted = self.evict()?;
+        if !evicted.is_empty() {
+            info!("Deleted {} old recorded segment(s)", evicted.len());
+        }
+
+        Ok(segment)
+    }
+
+    /// All the encrypted segments, oldest first.
+    pub fn catalog(&self) -> io::Result<Vec<RecordedSegment>> {
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some((start, end)) = parse_segment_filename(&name) {
+                segments.push(RecordedSegment {
+                    start,
+                    end,
+                    size: entry.metadata()?.len(),
+                });
+            }
+        }
+
+        segments.sort_by_key(|s| s.start);
+        Ok(segments)
+    }
+
+    /// The segments that overlap [from, to].
+    pub fn segments_in_range(&self, from: u64, to: u64) -> io::Result<Vec<RecordedSegment>> {
+        Ok(self
+            .catalog()?
+            .into_iter()
+            .filter(|s| s.start <= to && s.end >= from)
+            .collect())
+    }
+
+    /// Deletes the oldest segments until the total size is within the limit.
+    /// Returns the deleted segments.
+    pub fn evict(&self) -> io::Result<Vec<RecordedSegment>> {
+        let segments = self.catalog()?;
+        let mut total: u64 = segments.iter().map(|s| s.size).sum();
+        let mut evicted = Vec::new();
+
+        for segment in segments {
+            if total <= self.max_total_bytes {
+                break;
+            }
+            fs::remove_file(self.segment_path(&segment))?;
+            total -= segment.size;
+            evicted.push(segment);
+        }
+
+        Ok(evicted)
+    }
+
+    /// Decrypts a segment into a plain mp4 file.
+    pub fn decrypt_segment(&self, segment: &RecordedSegment, output: &Path) -> io::Result<()> {
+        decrypt_file(&self.load_or_create_key()?, &self.segment_path(segment), output)
+    }
+
+    // Segments that were being recorded when the hub stopped can't be finished anymore.
+    fn remove_partial_segments(&self) -> io::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().ends_with(PARTIAL_SUFFIX) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_or_create_key(&self) -> io::Result<Key> {
+        if self.key_path.exists() {
+            let key = fs::read(&self.key_path)?;
+            if key.len() != KEY_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is corrupted", self.key_path.display()),
+                ));
+            }
+            return Ok(*Key::from_slice(&key));
+        }
+
+        let mut key = [0u8; KEY_SIZE];
+        rand::rng().fill_bytes(&mut key);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&self.key_path)?;
+        file.write_all(&key)?;
+        file.sync_all()?;
+
+        Ok(*Key::from_slice(&key))
+    }
+}
+
+fn parse_segment_filename(name: &str) -> Option<(u64, u64)> {
+    let (start, end) = name
+        .strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(ENCRYPTED_SUFFIX)?
+        .split_once('_')?;
+
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+// Each chunk's nonce is the file's random prefix followed by the chunk number.
+// The associated data marks the last chunk so that a truncated file doesn't decrypt.
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_SIZE], chunk_number: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_SIZE..].copy_from_slice(&chunk_number.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+// Reads up to buf.len() bytes, only returning less at the end of the file.
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+
+    Ok(filled)
+}
+
+fn encrypt_file(key: &Key, input: &Path, output: &Path) -> io::Result<()> {
+    let cipher = ChaCha20Poly1305::new(key);
+    let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+    rand::rng().fill_bytes(&mut prefix);
+
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(&prefix)?;
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut next = vec![0u8; CHUNK_SIZE];
+    let mut len = read_chunk(&mut reader, &mut buf)?;
+    let mut chunk_number = 0;
+    loop {
+        // Read ahead to know whether this is the last chunk.
+        let next_len = read_chunk(&mut reader, &mut next)?;
+        let is_last = next_len == 0;
+
+        let ciphertext = cipher
+            .encrypt(
+                &chunk_nonce(&prefix, chunk_number),
+                Payload {
+                    msg: &buf[..len],
+                    aad: &[is_last as u8],
+                },
+            )
+            .map_err(|_| io::Error::other("Failed to encrypt segment"))?;
+        writer.write_all(&(ciphertext.len() as u32).to_be_bytes())?;
+        writer.write_all(&ciphertext)?;
+
+        if is_last {
+            break;
+        }
+        std::mem::swap(&mut buf, &mut next);
+        len = next_len;
+        chunk_number += 1;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+fn decrypt_file(key: &Key, input: &Path, output: &Path) -> io::Result<()> {
+    let cipher = ChaCha20Poly1305::new(key);
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+
+    let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+    reader.read_exact(&mut prefix)?;
+
+    let mut len_bytes = [0u8; 4];
+    let mut ciphertext = Vec::new();
+    let mut chunk_number = 0;
+    let mut pending: Option<Vec<u8>> = None;
+    loop {
+        let is_last = match read_chunk(&mut reader, &mut len_bytes)? {
+            0 => true,
+            4 => false,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Truncated segment file",
+                ))
+            }
+        };
+        // The chunk we read before is the last one iff there's nothing after it.
+        if let Some(previous) = pending.take() {
+            let plaintext = cipher
+                .decrypt(
+                    &chunk_nonce(&prefix, chunk_number),
+                    Payload {
+                        msg: &previous,
+                        aad: &[is_last as u8],
+                    },
+                )
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt segment")
+                })?;
+            writer.write_all(&plaintext)?;
+            chunk_number += 1;
+        }
+
+        if is_last {
+            break;
+        }
+
+        let len = u32::from_be_bytes(len_bytes) as usize;
+        if len > CHUNK_SIZE + 16 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid segment chunk size",
+            ));
+        }
+        ciphertext.resize(len, 0);
+        reader.read_exact(&mut ciphertext)?;
+        pending = Some(ciphertext.clone());
+    }
+
+    if chunk_number == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Empty segment file",
+        ));
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Encrypted size of a 1000-byte segment: nonce prefix, then one chunk (length, data, tag).
+    const SEGMENT_SIZE: u64 = (NONCE_PREFIX_SIZE + 4 + 1000 + 16) as u64;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "secluso-segments-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("state")).unwrap();
+        dir
+    }
+
+    fn store(dir: &Path, max_total_bytes: u64) -> SegmentStore {
+        let config = ContinuousRecordingConfig {
+            dir: dir.join("segments").to_str().unwrap().to_string(),
+            max_total_gib: max_total_bytes as f64 / GIB,
+            segment_minutes: None,
+        };
+        SegmentStore::new(&config, dir.join("state").to_str().unwrap()).unwrap()
+    }
+
+    // Same as the recorder: write the partial segment, then finish it.
+    fn record(store: &SegmentStore, start: u64, end: u64, data: &[u8]) -> RecordedSegment {
+        fs::write(store.partial_segment_path(start), data).unwrap();
+        store.finish_segment(start, end).unwrap()
+    }
+
+    #[test]
+    fn finished_segments_are_encrypted_and_listed_in_order() {
+        let dir = test_dir("catalog");
+        let store = store(&dir, 1 << 20);
+
+        let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        for start in [600, 0, 300] {
+            let segment = record(&store, start, start + 300, &data);
+            assert_eq!(segment.size, SEGMENT_SIZE);
+            assert!(!store.partial_segment_path(start).exists());
+        }
+
+        let starts: Vec<u64> = store.catalog().unwrap().iter().map(|s| s.start).collect();
+        assert_eq!(starts, [0, 300, 600]);
+        let in_range: Vec<u64> = store
+            .segments_in_range(350, 610)
+            .unwrap()
+            .iter()
+            .map(|s| s.start)
+            .collect();
+        assert_eq!(in_range, [300, 600]);
+
+        // The plaintext isn't on disk, but decrypts back.
+        let segment = &store.catalog().unwrap()[1];
+        assert_ne!(fs::read(store.segment_path(segment)).unwrap()[..], data[..]);
+        let output = dir.join("segment.mp4");
+        store.decrypt_segment(segment, &output).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), data);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn oldest_segments_are_evicted_over_the_limit() {
+        let dir = test_dir("evict");
+        let store = store(&dir, 2 * SEGMENT_SIZE + SEGMENT_SIZE / 2);
+
+        let data = vec![7u8; 1000];
+        for start in [0, 300] {
+            record(&store, start, start + 300, &data);
+        }
+        assert_eq!(store.catalog().unwrap().len(), 2);
+
+        // The third segment goes over the limit, so the oldest one is deleted.
+        record(&store, 600, 900, &data);
+        let starts: Vec<u64> = store.catalog().unwrap().iter().map(|s| s.start).collect();
+        assert_eq!(starts, [300, 600]);
+        let total: u64 = store.catalog().unwrap().iter().map(|s| s.size).sum();
+        assert!(total <= 2 * SEGMENT_SIZE + SEGMENT_SIZE / 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn restart_removes_partial_segments_and_keeps_the_key() {
+        let dir = test_dir("restart");
+        let first = store(&dir, 1 << 20);
+        let data = vec![1u8; 1000];
+        let segment = record(&first, 0, 300, &data);
+        // The hub stopped while recording the next segment.
+        fs::write(first.partial_segment_path(300), b"cut short").unwrap();
+
+        let second = store(&dir, 1 << 20);
+        assert!(!second.partial_segment_path(300).exists());
+        let output = dir.join("segment.mp4");
+        second.decrypt_segment(&segment, &output).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), data);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn truncated_segment_does_not_decrypt() {
+        let dir = test_dir("truncated");
+        let store = store(&dir, 4 << 20);
+        let data = vec![3u8; 3 * CHUNK_SIZE / 2];
+        let segment = record(&store, 0, 300, &data);
+
+        // Drop the last chunk: what's left is a valid chunk, but not marked as the last one.
+        let path = store.segment_path(&segment);
+        let encrypted = fs::read(&path).unwrap();
+        fs::write(&path, &encrypted[..NONCE_PREFIX_SIZE + 4 + CHUNK_SIZE + 16]).unwrap();
+
+        let err = store
+            .decrypt_segment(&segment, &dir.join("segment.mp4"))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -6,8 +6,9 @@
 use secluso_client_lib::thumbnail_meta_info::ThumbnailMetaInfo;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt::Write as _;
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -42,6 +43,36 @@ impl VideoInfo {
     }
 }
 
+/// Snapshot of the delivery state, for status reporting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryStatistics {
+    /// Videos the monitor tracks, i.e., not yet uploaded or not yet acknowledged.
+    pub tracked: usize,
+    /// Videos not yet acknowledged by the app.
+    pub pending: usize,
+    /// Videos whose upload failed or hasn't happened yet (they're resent on the next check).
+    pub awaiting_resend: usize,
+    /// Creation time of the oldest unacknowledged video.
+    pub oldest_pending_timestamp: Option<u64>,
+    /// Number of times a video upload failed and had to be retried.
+    pub upload_retries: usize,
+    /// Age of the oldest unacknowledged video.
+    pub max_pending_age_secs: u64,
+    /// Videos uploaded to the server.
+    pub total_sent: u64,
+    /// Videos acknowledged by the app.
+    pub total_acked: u64,
+}
+
+// Counters are only kept in memory, i.e., they count since the hub started.
+// (They're not serialized so that the persisted state format doesn't change.)
+#[derive(Default)]
+struct DeliveryCounters {
+    upload_retries: usize,
+    sent: u64,
+    acked: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct DeliveryMonitor {
     // We use the watch_list to keep track of video files that are yet to be
@@ -59,21 +90,14 @@ pub struct DeliveryMonitor {
     thumbnail_dir: String,
     state_dir: String,
     pending_livestream_updates: Vec<Vec<u8>>,
+    #[serde(skip)]
+    counters: DeliveryCounters,
 }
 
 impl DeliveryMonitor {
     pub fn from_file_or_new(video_dir: String, thumbnail_dir: String, state_dir: String) -> Self {
-        let d_files = Self::get_state_files_sorted(&state_dir, "delivery_monitor_").unwrap();
-        for f in &d_files {
-            let pathname = state_dir.clone() + "/" + f;
-            let file = fs::File::open(pathname).expect("Could not open file");
-            let mut reader =
-                BufReader::with_capacity(file.metadata().unwrap().len().try_into().unwrap(), file);
-            let data = reader.fill_buf().unwrap();
-            let deserialize_result = bincode::deserialize(data);
-            if let Ok(deserialized_data) = deserialize_result {
-                return deserialized_data;
-            }
+        if let Some(deserialized_data) = Self::from_file(&state_dir).unwrap() {
+            return deserialized_data;
         }
 
         Self {
@@ -86,9 +110,69 @@ impl DeliveryMonitor {
             thumbnail_dir,
             state_dir,
             pending_livestream_updates: vec![],
+            counters: DeliveryCounters::default(),
         }
     }
 
+    /// Loads the latest persisted state in state_dir, if any. Doesn't modify anything on disk.
+    pub fn from_file(state_dir: &str) -> io::Result<Option<Self>> {
+        let d_files = Self::get_state_files_sorted(state_dir, "delivery_monitor_")?;
+        for f in &d_files {
+            let pathname = state_dir.to_owned() + "/" + f;
+            let file = fs::File::open(pathname)?;
+            let mut reader =
+                BufReader::with_capacity(file.metadata()?.len().try_into().unwrap(), file);
+            let data = reader.fill_buf()?;
+            if let Ok(deserialized_data) = bincode::deserialize(data) {
+                return Ok(Some(deserialized_data));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Human-readable summary of the delivery state (used by --status).
+    pub fn status_summary(&self) -> String {
+        let mut pending: Vec<&VideoInfo> = self.video_pending_list.values().collect();
+        pending.sort_by_key(|info| info.timestamp);
+
+        let mut summary = String::new();
+        let _ = writeln!(summary, "Videos waiting to be uploaded: {}", self.video_watch_list.len());
+        let _ = writeln!(summary, "Videos not yet acknowledged by the app: {}", pending.len());
+        match pending.first() {
+            Some(oldest) => {
+                let _ = writeln!(summary, "Oldest unacknowledged video: {}", oldest.timestamp);
+            }
+            None => {
+                let _ = writeln!(summary, "Oldest unacknowledged video: none");
+            }
+        }
+        for info in pending {
+            let _ = writeln!(
+                summary,
+                "  epoch {}: {} (timestamp {})",
+                info.epoch, info.filename, info.timestamp
+            );
+        }
+        let _ = writeln!(
+            summary,
+            "Thumbnails waiting to be uploaded: {}",
+            self.thumbnail_watch_list.len()
+        );
+        let _ = writeln!(
+            summary,
+            "Thumbnails not yet acknowledged by the app: {}",
+            self.thumbnail_pending_list.len()
+        );
+        let _ = writeln!(
+            summary,
+            "Pending livestream updates: {}",
+            self.pending_livestream_updates.len()
+        );
+
+        summary
+    }
+
     fn get_state_files_sorted(dir_path: &str, pattern: &str) -> std::io::Result<Vec<String>> {
         let mut matching_files: Vec<(String, u128)> = Vec::new();
 
@@ -167,10 +251,48 @@ impl DeliveryMonitor {
 
         let _ = self.video_watch_list.remove(&video_info.timestamp);
         let _ = fs::remove_file(self.get_enc_video_file_path(video_info));
+        self.counters.sent += 1;
 
         self.save_state();
     }
 
+    /// Called when uploading a video failed (it'll be retried later).
+    pub fn record_upload_retry(&mut self) {
+        self.counters.upload_retries += 1;
+    }
+
+    /// Read-only: doesn't change what's resent or when.
+    pub fn statistics(&self) -> DeliveryStatistics {
+        // VideoInfo.timestamp is the time the video was created (in seconds).
+        let now = Self::now();
+        let oldest_pending_timestamp = self
+            .video_pending_list
+            .values()
+            .map(|info| info.timestamp)
+            .min();
+        let max_pending_age_secs = oldest_pending_timestamp
+            .map(|timestamp| now.saturating_sub(timestamp))
+            .unwrap_or(0);
+        // A video is in the watch list until it's uploaded and in the pending list until it's acked.
+        let tracked = self
+            .video_watch_list
+            .values()
+            .filter(|info| !self.video_pending_list.contains_key(&info.epoch))
+            .count()
+            + self.video_pending_list.len();
+
+        DeliveryStatistics {
+            tracked,
+            pending: self.video_pending_list.len(),
+            awaiting_resend: self.video_watch_list.len(),
+            oldest_pending_timestamp,
+            upload_retries: self.counters.upload_retries,
+            max_pending_age_secs,
+            total_sent: self.counters.sent,
+            total_acked: self.counters.acked,
+        }
+    }
+
     pub fn process_heartbeat(&mut self, motion_epoch: u64, thumbnail_epoch: u64) {
         let mut removed_list = vec![];
 
@@ -187,6 +309,7 @@ impl DeliveryMonitor {
             }
         });
 
+        self.counters.acked += removed_list.len() as u64;
         for video_info in removed_list {
             let _ = fs::remove_file(self.get_video_file_path(&video_info));
         }
@@ -334,3 +457,71 @@ impl DeliveryMonitor {
             .ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DeliveryMonitor, DeliveryStatistics, VideoInfo};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "secluso-delivery-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for subdir in ["videos", "thumbnails", "state"] {
+            fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn statistics_count_sent_and_acked_videos() {
+        let dir = test_dir("statistics");
+        let dir_string = |subdir: &str| dir.join(subdir).to_str().unwrap().to_string();
+        let mut monitor = DeliveryMonitor::from_file_or_new(
+            dir_string("videos"),
+            dir_string("thumbnails"),
+            dir_string("state"),
+        );
+
+        let now = DeliveryMonitor::now();
+        let videos: Vec<VideoInfo> = (1..=5)
+            .map(|epoch| {
+                let mut info = VideoInfo::from(now - 100 + epoch);
+                info.epoch = epoch;
+                info
+            })
+            .collect();
+        for info in &videos {
+            monitor.enqueue_video(info.clone());
+        }
+
+        // The first upload fails once, then all five go through.
+        monitor.record_upload_retry();
+        for info in &videos {
+            monitor.dequeue_video(info);
+        }
+        // The app acks up to epoch 3.
+        monitor.process_heartbeat(3, 0);
+
+        let stats = monitor.statistics();
+        assert_eq!(
+            stats,
+            DeliveryStatistics {
+                tracked: 2,
+                pending: 2,
+                awaiting_resend: 0,
+                oldest_pending_timestamp: Some(videos[3].timestamp),
+                upload_retries: 1,
+                max_pending_age_secs: stats.max_pending_age_secs,
+                total_sent: 5,
+                total_acked: 3,
+            }
+        );
+        assert!(stats.max_pending_age_secs >= 96);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
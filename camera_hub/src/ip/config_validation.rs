@@ -0,0 +1,219 @@
+//! Validation of cameras.yaml against a schema derived from the camera config types,
+//! so that configuration mistakes are reported clearly instead of as an opaque parse error.
+//! Values that the schema can't check (e.g., ranges and duplicate names) are checked after it.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::ip::ip_camera::Config;
+use jsonschema::error::{TypeKind, ValidationErrorKind};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct ConfigError {
+    /// Where in the file the problem is, e.g., /cameras/0/ip ("/" is the whole file).
+    pub path: String,
+    /// The expected type, for type mismatches and missing fields.
+    pub expected: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, " (expected {expected})")?;
+        }
+        Ok(())
+    }
+}
+
+impl ConfigError {
+    pub(crate) fn new(path: &str, message: String) -> Self {
+        Self {
+            path: path.to_string(),
+            expected: None,
+            message,
+        }
+    }
+}
+
+/// Checks the given cameras.yaml file against the schema of the camera config types.
+/// Returns every problem found.
+pub fn validate_cameras_config(path: &Path) -> Result<(), Vec<ConfigError>> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        vec![ConfigError::new(
+            "/",
+            format!("Failed to read {}: {e}", path.display()),
+        )]
+    })?;
+
+    validate_cameras_config_str(&content)
+}
+
+fn validate_cameras_config_str(content: &str) -> Result<(), Vec<ConfigError>> {
+    let instance: serde_json::Value = serde_yaml2::from_str(content)
+        .map_err(|e| vec![ConfigError::new("/", format!("Invalid YAML: {e}"))])?;
+
+    let schema = serde_json::to_value(schemars::schema_for!(Config)).map_err(|e| {
+        vec![ConfigError::new(
+            "/",
+            format!("Failed to generate the config schema: {e}"),
+        )]
+    })?;
+    let validator = jsonschema::validator_for(&schema).map_err(|e| {
+        vec![ConfigError::new(
+            "/",
+            format!("Failed to compile the config schema: {e}"),
+        )]
+    })?;
+
+    let errors: Vec<ConfigError> = validator
+        .iter_errors(&instance)
+        .map(|e| {
+            let mut path = e.instance_path.to_string();
+            let mut expected = None;
+            match &e.kind {
+                ValidationErrorKind::Type { kind } => {
+                    expected = Some(match kind {
+                        TypeKind::Single(t) => t.to_string(),
+                        TypeKind::Multiple(types) => (*types)
+                            .into_iter()
+                            .map(|t| t.to_string())
+                            .collect::<Vec<_>>()
+                            .join(" or "),
+                    });
+                }
+                ValidationErrorKind::Required { property } => {
+                    // Point at the missing field rather than at the object that lacks it.
+                    let field = property
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| property.to_string());
+                    path = format!("{}/{}", path.trim_end_matches('/'), field);
+                    expected = field_type(&schema, &field);
+                }
+                _ => {}
+            }
+
+            ConfigError {
+                path: if path.is_empty() { "/".to_string() } else { path },
+                expected,
+                message: e.to_string(),
+            }
+        })
+        .collect();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // The values that have the right types can still be wrong, or conflict between cameras.
+    let cfg: Config = serde_yaml2::from_str(content)
+        .map_err(|e| vec![ConfigError::new("/", format!("Invalid config: {e}"))])?;
+    let errors = cfg.semantic_errors();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// The type of a required field of the config, for reporting missing fields.
+fn field_type(schema: &serde_json::Value, field: &str) -> Option<String> {
+    let defs = schema.get("$defs").and_then(|d| d.as_object());
+    std::iter::once(schema)
+        .chain(defs.into_iter().flat_map(|d| d.values()))
+        .find_map(|def| def.get("properties")?.get(field)?.get("type"))
+        .map(|t| t.to_string().trim_matches('"').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_cameras_config_str, ConfigError};
+
+    fn errors(content: &str) -> Vec<ConfigError> {
+        validate_cameras_config_str(content).unwrap_err()
+    }
+
+    const VALID: &str = "cameras:
+  - name: Front Door
+    ip: 192.168.1.2
+    rtsp_port: 554
+    motion_fps: 5
+";
+
+    #[test]
+    fn valid_config_passes() {
+        validate_cameras_config_str(VALID).unwrap();
+    }
+
+    #[test]
+    fn missing_motion_fps_points_at_the_field() {
+        let errors = errors(&VALID.replace("    motion_fps: 5\n", ""));
+
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, "/cameras/0/motion_fps");
+        assert_eq!(errors[0].expected.as_deref(), Some("integer"));
+    }
+
+    #[test]
+    fn missing_ip_points_at_the_field() {
+        let errors = errors(&VALID.replace("    ip: 192.168.1.2\n", ""));
+
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, "/cameras/0/ip");
+        assert_eq!(errors[0].expected.as_deref(), Some("string"));
+    }
+
+    #[test]
+    fn wrong_type_reports_the_expected_type() {
+        let errors = errors(&VALID.replace("rtsp_port: 554", "rtsp_port: \"default\""));
+
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, "/cameras/0/rtsp_port");
+        assert_eq!(errors[0].expected.as_deref(), Some("integer"));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let content = VALID
+            .replace("    ip: 192.168.1.2\n", "")
+            .replace("motion_fps: 5", "motion_fps: fast");
+        let mut paths: Vec<String> = errors(&content).into_iter().map(|e| e.path).collect();
+        paths.sort();
+
+        assert_eq!(paths, ["/cameras/0/ip", "/cameras/0/motion_fps"]);
+    }
+
+    #[test]
+    fn duplicate_names_are_reported_after_the_schema() {
+        let content = format!(
+            "{VALID}  - name: front door\n    ip: 192.168.1.3\n    rtsp_port: 554\n    motion_fps: 5\n"
+        );
+        let errors = errors(&content);
+
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, "/cameras/1/name");
+    }
+
+    #[test]
+    fn cameras_need_their_own_pairing_port() {
+        // The second camera gets 12349 by default.
+        let content = format!(
+            "{VALID}    pairing_port: 12349\n  - name: Back Door\n    ip: 192.168.1.3\n    rtsp_port: 554\n    motion_fps: 5\n"
+        );
+        let clashing = errors(&content);
+
+        assert_eq!(clashing.len(), 1, "{clashing:?}");
+        assert_eq!(clashing[0].path, "/cameras/1/pairing_port");
+
+        validate_cameras_config_str(&format!("{content}    pairing_port: 12400\n")).unwrap();
+
+        let zero = errors(&format!("{VALID}    pairing_port: 0\n"));
+        assert_eq!(zero.len(), 1, "{zero:?}");
+        assert_eq!(zero[0].path, "/cameras/0/pairing_port");
+    }
+}
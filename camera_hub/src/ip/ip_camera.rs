@@ -25,11 +25,16 @@
 //! https://github.com/scottlamb/moonfire-nvr/wiki/Standards-and-specifications
 //! https://standards.iso.org/ittf/PubliclyAvailableStandards/c068960_ISO_IEC_14496-12_2015.zip
 
+use crate::camera_events::{
+    recording_event_code, report_camera_event, EVENT_RECORDING_FAILED, EVENT_STREAM_FAILED,
+};
+use crate::continuous_recording::{ContinuousRecordingConfig, SegmentStore};
 use crate::delivery_monitor::VideoInfo;
 use crate::fmp4::Fmp4Writer;
-use crate::livestream::LivestreamWriter;
+use crate::livestream::{LivestreamWriter, SharedStreamQuality, StreamQuality};
 use crate::motion::MotionResult;
 use crate::mp4::Mp4Writer;
+use crate::pairing::flow::default_pairing_port;
 use crate::traits::{Camera, CodecParameters, Mp4};
 use std::fs;
 use std::io;
@@ -48,18 +53,64 @@ use url::Url;
 
 use std::convert::TryFrom;
 use std::num::NonZeroU32;
+use std::path::Path;
 use std::sync::Arc;
 
+use crate::ip::config_validation::{validate_cameras_config, ConfigError};
 use crate::ip::ip_motion_detection::MotionDetection;
+use crate::ip::onvif::{HttpTransport, OnvifClient, OnvifMotion, DEFAULT_ONVIF_PORT};
+use crate::motion_settings::MotionSettings;
+use crate::preroll::{self, BufferedFrame};
 use crate::{STATE_DIR_GENERAL, THUMBNAIL_DIR_GENERAL, VIDEO_DIR_GENERAL};
 use rpassword::read_password;
+use schemars::JsonSchema;
 use std::collections::VecDeque;
 use std::process::exit;
 use std::sync::{
+    atomic::{AtomicU32, Ordering},
     mpsc::{self, Sender},
     Mutex,
 };
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::retry::RetryPolicy;
+
+// How far the continuous recorder may fall behind the stream before we drop its frames.
+const CONTINUOUS_RECORDING_WINDOW: Duration = Duration::from_secs(30);
+
+// If no frame arrives for this long, the RTSP session is torn down and rebuilt.
+// This catches cameras that reboot without closing the connection.
+const STREAM_STALL_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Backoff between attempts to rebuild the RTSP session.
+fn stream_reconnect_policy() -> RetryPolicy {
+    RetryPolicy {
+        initial_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(60),
+        multiplier: 2.0,
+        jitter_fraction: 0.1,
+    }
+}
+
+/// Health of the RTSP session, shared between the stream thread and the camera.
+#[derive(Default)]
+struct StreamHealth {
+    // Attempts to (re)build the session that failed since the last frame was received.
+    consecutive_failures: AtomicU32,
+}
+
+impl StreamHealth {
+    fn record_frame(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) -> u32 {
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+}
 
 pub struct IpCamera {
     name: String,
@@ -68,10 +119,19 @@ pub struct IpCamera {
     thumbnail_dir: String,
     frame_queue: Arc<Mutex<VecDeque<Frame>>>,
     video_params: VideoParameters,
-    audio_params: AudioParameters,
+    // None unless record_audio is set and the camera has an audio stream we can put in an .mp4.
+    audio_params: Option<AudioParameters>,
     motion_detection: MotionDetection,
+    onvif_motion: Option<OnvifMotion<HttpTransport>>,
+    motion_settings: MotionSettings,
+    stream_quality: SharedStreamQuality,
+    segment_store: Option<SegmentStore>,
+    stream_health: Arc<StreamHealth>,
+    hub_thumbnails: bool,
+    pairing_port: Option<u16>,
 }
 
+#[derive(Clone)]
 struct Frame {
     frame: Vec<u8>,
     frame_timestamp: u64,  // timestamp sent by the camera
@@ -80,12 +140,34 @@ struct Frame {
     is_random_access_point: bool,
 }
 
-#[derive(Debug, Deserialize)]
-struct Config {
+impl BufferedFrame for Frame {
+    fn captured_at(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    fn is_keyframe(&self) -> bool {
+        self.is_video && self.is_random_access_point
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub(crate) struct Config {
     cameras: Vec<CameraConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Where motion events for an IP camera come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum MotionSource {
+    /// Motion detection on the hub, comparing frames of the MJPEG stream.
+    #[default]
+    FrameDiff,
+    /// Motion events from the camera's own detector through an ONVIF PullPoint subscription.
+    /// Falls back to frame_diff when the subscription fails.
+    Onvif,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 struct CameraConfig {
     name: String,
     motion_fps: u64,
@@ -95,6 +177,95 @@ struct CameraConfig {
     username: Option<String>,
     #[serde(default)]
     password: Option<String>,
+    #[serde(default)]
+    motion_cooldown_secs: Option<u64>,
+    #[serde(default)]
+    record_secs: Option<u64>,
+    #[serde(default)]
+    preroll_secs: Option<u64>,
+    #[serde(default)]
+    continuous_recording: Option<ContinuousRecordingConfig>,
+    #[serde(default)]
+    motion_source: Option<MotionSource>,
+    #[serde(default)]
+    onvif_port: Option<u16>,
+    #[serde(default)]
+    hub_thumbnails: bool,
+    #[serde(default)]
+    record_audio: bool,
+    #[serde(default)]
+    pairing_port: Option<u16>,
+}
+
+impl Config {
+    /// Problems in the values of the cameras that the schema doesn't catch, for all cameras.
+    pub(crate) fn semantic_errors(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        // Cameras whose names map to the same directories would share their state.
+        let mut dir_names: Vec<String> = Vec::new();
+        // Only one camera at a time can wait for the app on a port.
+        let mut pairing_ports: Vec<u16> = Vec::new();
+
+        for (index, c) in self.cameras.iter().enumerate() {
+            let path = format!("/cameras/{index}");
+
+            if c.name.trim().is_empty() {
+                errors.push(ConfigError::new(
+                    &format!("{path}/name"),
+                    "Camera name is empty".to_string(),
+                ));
+            }
+
+            let dir_name = c.name.replace(" ", "_").to_lowercase();
+            if let Some(other) = dir_names.iter().position(|d| *d == dir_name) {
+                errors.push(ConfigError::new(
+                    &format!("{path}/name"),
+                    format!("Camera name {:?} is already used by camera {other}", c.name),
+                ));
+            }
+            dir_names.push(dir_name);
+
+            if c.motion_fps == 0 {
+                errors.push(ConfigError::new(
+                    &format!("{path}/motion_fps"),
+                    "motion_fps must be at least 1".to_string(),
+                ));
+            }
+
+            let pairing_port = c.pairing_port.unwrap_or(default_pairing_port(index));
+            if let Some(other) = pairing_ports.iter().position(|p| *p == pairing_port) {
+                errors.push(ConfigError::new(
+                    &format!("{path}/pairing_port"),
+                    format!("Pairing port {pairing_port} is already used by camera {other}"),
+                ));
+            }
+            pairing_ports.push(pairing_port);
+
+            for (field, port) in [
+                ("rtsp_port", Some(c.rtsp_port)),
+                ("onvif_port", c.onvif_port),
+                ("pairing_port", c.pairing_port),
+            ] {
+                if port == Some(0) {
+                    errors.push(ConfigError::new(
+                        &format!("{path}/{field}"),
+                        format!("{field} can't be 0"),
+                    ));
+                }
+            }
+
+            if let Err(e) = MotionSettings::new(
+                &c.name,
+                c.motion_cooldown_secs,
+                c.record_secs,
+                c.preroll_secs,
+            ) {
+                errors.push(ConfigError::new(&path, e.to_string()));
+            }
+        }
+
+        errors
+    }
 }
 
 impl IpCamera {
@@ -109,11 +280,27 @@ impl IpCamera {
         video_dir: String,
         thumbnail_dir: String,
         motion_fps: u64,
+        motion_settings: MotionSettings,
+        continuous_recording: Option<ContinuousRecordingConfig>,
+        motion_source: MotionSource,
+        onvif_port: u16,
+        hub_thumbnails: bool,
+        record_audio: bool,
+        pairing_port: Option<u16>,
     ) -> io::Result<Self> {
         let frame_queue: Arc<Mutex<VecDeque<Frame>>> = Arc::new(Mutex::new(VecDeque::new()));
         let frame_queue_clone = Arc::clone(&frame_queue);
+        // The continuous recorder gets its own copy of the stream.
+        let continuous_queue: Option<Arc<Mutex<VecDeque<Frame>>>> = continuous_recording
+            .as_ref()
+            .map(|_| Arc::new(Mutex::new(VecDeque::new())));
+        let continuous_queue_clone = continuous_queue.clone();
         let (video_params_tx, video_params_rx) = mpsc::channel::<VideoParameters>();
-        let (audio_params_tx, audio_params_rx) = mpsc::channel::<AudioParameters>();
+        let (audio_params_tx, audio_params_rx) = mpsc::channel::<Option<AudioParameters>>();
+        let buffer_window = preroll::buffer_window(motion_settings.preroll_secs);
+
+        let stream_health = Arc::new(StreamHealth::default());
+        let stream_health_clone = Arc::clone(&stream_health);
 
         let ip_clone = ip.clone();
         let username_clone = username.clone();
@@ -125,7 +312,11 @@ impl IpCamera {
                 username_clone,
                 password_clone,
                 format!("rtsp://{}:{}", ip_clone, rtsp_port),
+                record_audio,
                 frame_queue_clone,
+                buffer_window,
+                continuous_queue_clone,
+                stream_health_clone,
                 video_params_tx,
                 audio_params_tx,
             );
@@ -133,28 +324,47 @@ impl IpCamera {
             rt.block_on(future).unwrap();
         });
 
-        let video_params_prior = video_params_rx.recv();
-        match video_params_prior {
-            Ok(_) => {}
-            Err(e) => {
-                println!(
-                    "[{}] You most likely entered invalid credentials",
-                    name.clone()
-                );
-                debug!("{}", e);
-                exit(1);
-            }
-        }
-
-        let video_params = video_params_prior.unwrap();
+        // The stream thread drops the sender without sending if it can't connect.
+        let video_params = video_params_rx.recv().map_err(|e| {
+            debug!("{}", e);
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("[{}] You most likely entered invalid credentials", name),
+            )
+        })?;
         let audio_params = audio_params_rx.recv().unwrap();
 
         fs::create_dir_all(state_dir.clone()).unwrap();
         fs::create_dir_all(video_dir.clone()).unwrap();
         fs::create_dir_all(thumbnail_dir.clone()).unwrap();
 
+        let onvif_motion = match motion_source {
+            MotionSource::FrameDiff => None,
+            MotionSource::Onvif => Some(OnvifMotion::new(OnvifClient::new(
+                HttpTransport::new()?,
+                &ip,
+                onvif_port,
+                username.clone(),
+                password.clone(),
+            ))),
+        };
         let motion_detection = MotionDetection::new(ip, username, password, motion_fps).unwrap();
 
+        let segment_store = match (continuous_recording, continuous_queue) {
+            (Some(config), Some(queue)) => {
+                let store = SegmentStore::new(&config, &state_dir)?;
+                Self::spawn_continuous_recorder(
+                    store.clone(),
+                    config.segment_secs(),
+                    queue,
+                    video_params.clone(),
+                    audio_params.clone(),
+                );
+                Some(store)
+            }
+            _ => None,
+        };
+
         Ok(Self {
             name,
             state_dir,
@@ -164,9 +374,68 @@ impl IpCamera {
             video_params,
             audio_params,
             motion_detection,
+            onvif_motion,
+            motion_settings,
+            stream_quality: SharedStreamQuality::default(),
+            segment_store,
+            stream_health,
+            hub_thumbnails,
+            pairing_port,
         })
     }
 
+    /// Records the stream into back-to-back segments of segment_secs seconds.
+    fn spawn_continuous_recorder(
+        store: SegmentStore,
+        segment_secs: u64,
+        queue: Arc<Mutex<VecDeque<Frame>>>,
+        video_params: VideoParameters,
+        audio_params: Option<AudioParameters>,
+    ) {
+        thread::spawn(move || {
+            let rt = Runtime::new().unwrap();
+
+            loop {
+                let start = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let partial = store.partial_segment_path(start);
+
+                let result = rt.block_on(Self::write_mp4(
+                    partial.to_string_lossy().into_owned(),
+                    segment_secs,
+                    Arc::clone(&queue),
+                    video_params.clone(),
+                    audio_params.clone(),
+                ));
+                if let Err(e) = result {
+                    error!("Failed to record segment {start}: {e}");
+                    let code = match e.downcast_ref::<io::Error>() {
+                        Some(io_error) => recording_event_code(io_error),
+                        None => EVENT_RECORDING_FAILED,
+                    };
+                    report_camera_event(code, &format!("Failed to record segment: {e}"));
+                    let _ = fs::remove_file(&partial);
+                    thread::sleep(Duration::from_secs(5));
+                    continue;
+                }
+
+                let end = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                if let Err(e) = store.finish_segment(start, end) {
+                    error!("Failed to store segment {start}: {e}");
+                    report_camera_event(
+                        recording_event_code(&e),
+                        &format!("Failed to store segment: {e}"),
+                    );
+                }
+            }
+        });
+    }
+
     /// Parses cameras.yaml file and returns a list of all cameras.
     pub fn get_all_cameras_info() -> io::Result<Vec<Box<dyn Camera + Send>>> {
         // Retrieve the cameras.yaml file. If it doesn't exist, print an error message for the user.
@@ -179,13 +448,36 @@ impl IpCamera {
             }
         };
 
+        // Report every configuration mistake at once instead of the first parse error.
+        if let Err(errors) = validate_cameras_config(Path::new("cameras.yaml")) {
+            println!("Invalid cameras.yaml:");
+            for error in errors {
+                println!("  {error}");
+            }
+            exit(1);
+        }
+
         // Load the yml file in for analysis
         let cfg: Config = serde_yaml2::from_str(&content).map_err(io::Error::other)?;
 
+        // Validate the motion settings of all cameras before we start connecting to any of them.
+        let all_motion_settings = cfg
+            .cameras
+            .iter()
+            .map(|c| {
+                MotionSettings::new(
+                    &c.name,
+                    c.motion_cooldown_secs,
+                    c.record_secs,
+                    c.preroll_secs,
+                )
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
         let mut camera_list: Vec<Box<dyn Camera + Send>> = Vec::new();
 
         // Iterate through every camera in the cameras.yaml file, accumulating structs representing their data
-        for c in cfg.cameras {
+        for (c, motion_settings) in cfg.cameras.into_iter().zip(all_motion_settings) {
             let mut camera_username = c.username.unwrap_or_default();
             let mut camera_password = c.password.unwrap_or_default();
 
@@ -227,6 +519,13 @@ impl IpCamera {
                     c.name.replace(" ", "_").to_lowercase()
                 ),
                 c.motion_fps,
+                motion_settings,
+                c.continuous_recording,
+                c.motion_source.unwrap_or_default(),
+                c.onvif_port.unwrap_or(DEFAULT_ONVIF_PORT),
+                c.hub_thumbnails,
+                c.record_audio,
+                c.pairing_port,
             );
 
             match ip_camera_result {
@@ -234,7 +533,10 @@ impl IpCamera {
                     camera_list.push(Box::new(camera));
                 }
                 Err(err) => {
-                    panic!("Failed to initialize the IP camera object. Consider resetting the camera. (Error: {err})");
+                    return Err(io::Error::new(
+                        err.kind(),
+                        format!("Failed to initialize the IP camera object. Consider resetting the camera. (Error: {err})"),
+                    ));
                 }
             }
         }
@@ -242,6 +544,25 @@ impl IpCamera {
         Ok(camera_list)
     }
 
+    /// Names and state directories of the cameras in cameras.yaml, without connecting to them.
+    pub fn get_all_cameras_state_dirs() -> io::Result<Vec<(String, String)>> {
+        let content = fs::read_to_string("cameras.yaml")?;
+        let cfg: Config = serde_yaml2::from_str(&content).map_err(io::Error::other)?;
+
+        Ok(cfg
+            .cameras
+            .into_iter()
+            .map(|c| {
+                let state_dir = format!(
+                    "{}/{}",
+                    STATE_DIR_GENERAL,
+                    c.name.replace(" ", "_").to_lowercase()
+                );
+                (c.name, state_dir)
+            })
+            .collect())
+    }
+
     fn ask_user(prompt: String) -> io::Result<String> {
         print!("{prompt}");
         // Make sure the prompt is displayed before reading input
@@ -263,31 +584,32 @@ impl IpCamera {
         Ok(password.trim().to_string())
     }
 
-    fn add_frame_and_drop_old(frame_queue: Arc<Mutex<VecDeque<Frame>>>, frame: Frame) {
-        // We want to record 5 seconds of frames at any given time.
-        let time_window = Duration::new(5, 0);
-        let mut queue = frame_queue.lock().unwrap();
-        queue.push_back(frame);
-
-        // Remove old entries
-        let now = SystemTime::now();
-        while let Some(front) = queue.front() {
-            if now.duration_since(front.timestamp).unwrap_or_default() > time_window {
-                queue.pop_front();
-            } else {
-                break;
-            }
-        }
-    }
-
     /// Copies packets from the IP camera session to the frame queue
     async fn stream_loop(
         session: &mut retina::client::Demuxed,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+        buffer_window: Duration,
+        continuous_queue: Option<Arc<Mutex<VecDeque<Frame>>>>,
+        stream_health: &StreamHealth,
     ) -> Result<(), Error> {
+        let add_frame = |frame: Frame| {
+            stream_health.record_frame();
+            if let Some(queue) = &continuous_queue {
+                preroll::add_frame_and_drop_old(
+                    &mut queue.lock().unwrap(),
+                    frame.clone(),
+                    CONTINUOUS_RECORDING_WINDOW,
+                );
+            }
+            preroll::add_frame_and_drop_old(&mut frame_queue.lock().unwrap(), frame, buffer_window);
+        };
+
         loop {
             tokio::select! {
-                pkt = session.next() => {
+                pkt = tokio::time::timeout(STREAM_STALL_TIMEOUT, session.next()) => {
+                    let pkt = pkt.map_err(|_| {
+                        anyhow!("no data from the camera for {}s", STREAM_STALL_TIMEOUT.as_secs())
+                    })?;
                     match pkt.ok_or_else(|| anyhow!("EOF"))?? {
                         CodecItem::VideoFrame(f) => {
                             let frame = Frame {
@@ -298,8 +620,7 @@ impl IpCamera {
                                 is_random_access_point: f.is_random_access_point(),
                             };
 
-                            let frame_queue_clone = Arc::clone(&frame_queue);
-                            Self::add_frame_and_drop_old(frame_queue_clone, frame);
+                            add_frame(frame);
                         },
                         CodecItem::AudioFrame(f) => {
                             let frame = Frame {
@@ -310,8 +631,7 @@ impl IpCamera {
                                 is_random_access_point: false,
                             };
 
-                            let frame_queue_clone = Arc::clone(&frame_queue);
-                            Self::add_frame_and_drop_old(frame_queue_clone, frame);
+                            add_frame(frame);
                         },
                         CodecItem::Rtcp(rtcp) => {
                             if let (Some(_t), Some(Ok(Some(_sr)))) = (rtcp.rtp_timestamp(), rtcp.pkts().next().map(retina::rtcp::PacketRef::as_sender_report)) {
@@ -325,16 +645,21 @@ impl IpCamera {
     }
 
     /// Streams frames from the IP camera.
+    #[allow(clippy::too_many_arguments)]
     async fn start_camera_stream_attempt(
         username: String,
         password: String,
         url: String,
+        record_audio: bool,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+        buffer_window: Duration,
+        continuous_queue: Option<Arc<Mutex<VecDeque<Frame>>>>,
+        stream_health: &StreamHealth,
         video_params_tx: Option<Sender<VideoParameters>>,
-        audio_params_tx: Option<Sender<AudioParameters>>,
+        audio_params_tx: Option<Sender<Option<AudioParameters>>>,
     ) -> Result<(), Error> {
         let (session, video_params, audio_params) =
-            Self::get_stream(username, password, url).await?;
+            Self::get_stream(username, password, url, record_audio).await?;
 
         let mut session = session
             .play(
@@ -353,45 +678,78 @@ impl IpCamera {
             let _ = atx.send(audio_params);
         }
 
-        Self::stream_loop(&mut session, frame_queue).await?;
+        Self::stream_loop(
+            &mut session,
+            frame_queue,
+            buffer_window,
+            continuous_queue,
+            stream_health,
+        )
+        .await?;
 
         // FIXME: do we need to wait for teardown here?
 
         Ok(())
     }
 
-    /// Start the camera stream in a loop
+    /// Start the camera stream, and keep rebuilding the session whenever it fails or stalls.
+    /// Only the first attempt is fatal (e.g., invalid credentials).
+    #[allow(clippy::too_many_arguments)]
     async fn start_camera_stream(
         username: String,
         password: String,
         url: String,
+        record_audio: bool,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+        buffer_window: Duration,
+        continuous_queue: Option<Arc<Mutex<VecDeque<Frame>>>>,
+        stream_health: Arc<StreamHealth>,
         video_params_tx: Sender<VideoParameters>,
-        audio_params_tx: Sender<AudioParameters>,
+        audio_params_tx: Sender<Option<AudioParameters>>,
     ) -> Result<(), Error> {
-        Self::start_camera_stream_attempt(
+        let mut result = Self::start_camera_stream_attempt(
             username.clone(),
             password.clone(),
             url.clone(),
+            record_audio,
             Arc::clone(&frame_queue),
+            buffer_window,
+            continuous_queue.clone(),
+            &stream_health,
             Some(video_params_tx),
             Some(audio_params_tx),
         )
-        .await?;
+        .await;
 
+        let policy = stream_reconnect_policy();
         loop {
-            println!("IP camera stream stopped or didn't start. Will try to restart soon.");
-            thread::sleep(Duration::from_secs(5));
+            let failures = stream_health.record_failure();
+            let delay = policy.delay(failures - 1);
+            match result {
+                Ok(()) => warn!("IP camera stream stopped, reconnecting in {:?}", delay),
+                Err(e) => {
+                    warn!(
+                        "IP camera stream failed ({e}), reconnecting in {:?} (consecutive failures: {failures})",
+                        delay
+                    );
+                    report_camera_event(EVENT_STREAM_FAILED, &format!("{e:#}"));
+                }
+            }
+            tokio::time::sleep(delay).await;
 
-            Self::start_camera_stream_attempt(
+            result = Self::start_camera_stream_attempt(
                 username.clone(),
                 password.clone(),
                 url.clone(),
+                record_audio,
                 Arc::clone(&frame_queue),
+                buffer_window,
+                continuous_queue.clone(),
+                &stream_health,
                 None,
                 None,
             )
-            .await?;
+            .await;
         }
     }
 
@@ -401,16 +759,17 @@ impl IpCamera {
         duration: u64,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
         video_params: VideoParameters,
-        audio_params: AudioParameters,
+        audio_params: Option<AudioParameters>,
     ) -> Result<(), Error> {
         let out = tokio::fs::File::create(&filename).await?;
+        let with_audio = audio_params.is_some();
         let mut mp4 = Mp4Writer::new(
             IpCameraVideoParameters::new(video_params),
             IpCameraAudioParameters::new(audio_params),
             out,
         )
         .await?;
-        Self::copy(&mut mp4, Some(duration), frame_queue).await?;
+        Self::copy(&mut mp4, Some(duration), frame_queue, None, with_audio).await?;
         mp4.finish().await?;
 
         // FIXME: do we need to wait for teardown here?
@@ -428,7 +787,8 @@ impl IpCamera {
         livestream_writer: LivestreamWriter,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
         video_params: VideoParameters,
-        audio_params: AudioParameters,
+        audio_params: Option<AudioParameters>,
+        stream_quality: SharedStreamQuality,
     ) -> Result<(), Error> {
         let mut fmp4 = Fmp4Writer::new(
             IpCameraVideoParameters::new(video_params),
@@ -437,7 +797,8 @@ impl IpCamera {
         )
         .await?;
         fmp4.finish_header(None).await?;
-        Self::copy(&mut fmp4, None, frame_queue).await?;
+        // The fmp4 header has no audio track, so the livestream stays video-only.
+        Self::copy(&mut fmp4, None, frame_queue, Some(stream_quality), false).await?;
 
         // FIXME: do we need to wait for teardown here?
 
@@ -445,10 +806,14 @@ impl IpCamera {
     }
 
     /// Copies packets from `session` to `mp4` without handling any cleanup on error.
+    /// With a stream quality (livestream), frames are dropped to match it.
+    /// Audio frames are only copied with_audio.
     async fn copy<M: Mp4>(
         mp4: &mut M,
         duration: Option<u64>,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+        stream_quality: Option<SharedStreamQuality>,
+        with_audio: bool,
     ) -> Result<(), Error> {
         let recording_window = duration.map(|secs| Duration::new(secs, 0));
         let recording_start_time = SystemTime::now();
@@ -468,6 +833,15 @@ impl IpCamera {
                 }
             };
 
+            if let Some(quality) = &stream_quality {
+                if !quality
+                    .get()
+                    .keeps_frame(frame.is_video, frame.is_random_access_point)
+                {
+                    continue;
+                }
+            }
+
             if frame.is_video {
                 if frame.is_random_access_point {
                     first_frame_found = true;
@@ -490,7 +864,7 @@ impl IpCamera {
                 }
             } else {
                 // audio
-                if first_frame_found {
+                if first_frame_found && with_audio {
                     mp4.audio(&frame.frame, frame.frame_timestamp)
                         .await
                         .with_context(|| "Error processing audio frame")?;
@@ -518,15 +892,17 @@ impl IpCamera {
     /// url: RTSP url of the IP camera
     /// filename: the name of the mp4 file to be used
     /// duration: the duration of the video, in seconds.
+    /// record_audio: whether to also set up the audio stream, if the camera has one.
     async fn get_stream(
         username: String,
         password: String,
         url: String,
+        record_audio: bool,
     ) -> Result<
         (
             retina::client::Session<retina::client::Described>,
             VideoParameters,
-            AudioParameters,
+            Option<AudioParameters>,
         ),
         Error,
     > {
@@ -568,7 +944,9 @@ impl IpCamera {
                 )
                 .await?;
         }
-        let audio_stream = {
+        let audio_stream = if !record_audio {
+            None
+        } else {
             let s = session
                 .streams()
                 .iter()
@@ -599,8 +977,8 @@ impl IpCamera {
                 )
                 .await?;
         }
-        if video_stream_i.is_none() && audio_stream.is_none() {
-            bail!("Exiting because no video or audio stream was selected; see info log messages above");
+        if video_stream_i.is_none() {
+            bail!("Exiting because no video stream was selected; see info log messages above");
         }
 
         //FIXME: what if there are multiple streams?
@@ -613,16 +991,28 @@ impl IpCamera {
             }
         };
 
-        let audio_params = audio_stream.map(|(_i, p)| p).unwrap();
+        let audio_params = audio_stream.map(|(_i, p)| *p);
 
-        Ok((session, video_params, *audio_params))
+        Ok((session, video_params, audio_params))
     }
 }
 
 impl Camera for IpCamera {
-    fn record_motion_video(&self, info: &VideoInfo, duration: u64) -> io::Result<()> {
+    fn record_motion_video(
+        &self,
+        info: &VideoInfo,
+        duration: u64,
+        preroll_secs: u64,
+    ) -> io::Result<()> {
         let rt = Runtime::new()?;
 
+        // Start the video with the buffered frames from right before the motion.
+        preroll::trim_to_preroll(
+            &mut self.frame_queue.lock().unwrap(),
+            Duration::from_secs(preroll_secs),
+            SystemTime::now(),
+        );
+
         // FIXME: use a temp name for recording and then rename at the end?
         // If not, we might end up with half-recorded videos on crash, factory reset, etc.
         // This might be okay though.
@@ -644,14 +1034,23 @@ impl Camera for IpCamera {
         queue.clear();
         drop(queue);
 
+        // Every livestream starts at full quality.
+        self.stream_quality.set(StreamQuality::Full);
+
         let frame_queue = Arc::clone(&self.frame_queue);
         let video_params = self.video_params.clone();
         let audio_params = self.audio_params.clone();
+        let stream_quality = self.stream_quality.clone();
         thread::spawn(move || {
             let rt = Runtime::new().unwrap();
 
-            let future =
-                Self::write_fmp4(livestream_writer, frame_queue, video_params, audio_params);
+            let future = Self::write_fmp4(
+                livestream_writer,
+                frame_queue,
+                video_params,
+                audio_params,
+                stream_quality,
+            );
 
             rt.block_on(future).unwrap();
         });
@@ -659,10 +1058,48 @@ impl Camera for IpCamera {
         Ok(())
     }
 
+    fn set_stream_quality(&self, quality: StreamQuality) -> StreamQuality {
+        self.stream_quality.set(quality);
+        quality
+    }
+
     fn is_there_motion(&mut self) -> Result<MotionResult, Error> {
+        if let Some(motion) = self.onvif_motion.as_mut().and_then(|onvif| onvif.poll()) {
+            return Ok(MotionResult {
+                motion,
+                detections: vec![],
+                thumbnail: if motion {
+                    self.motion_detection.latest_thumbnail()
+                } else {
+                    None
+                },
+            });
+        }
+
         self.motion_detection.handle_motion_event()
     }
 
+    fn recorded_segments(&self) -> Option<SegmentStore> {
+        self.segment_store.clone()
+    }
+
+    fn stream_failures(&self) -> u32 {
+        self.stream_health.consecutive_failures()
+    }
+
+    fn hub_thumbnails(&self) -> bool {
+        self.hub_thumbnails
+    }
+
+    fn capture_still(&self) -> io::Result<Vec<u8>> {
+        self.motion_detection.latest_jpeg().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No frame received from the camera yet",
+            )
+        })
+    }
+
     fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -677,6 +1114,14 @@ impl Camera for IpCamera {
     fn get_thumbnail_dir(&self) -> String {
         self.thumbnail_dir.clone()
     }
+
+    fn get_motion_settings(&self) -> MotionSettings {
+        self.motion_settings
+    }
+
+    fn get_pairing_port(&self) -> Option<u16> {
+        self.pairing_port
+    }
 }
 
 struct IpCameraVideoParameters {
@@ -722,21 +1167,25 @@ impl CodecParameters for IpCameraVideoParameters {
     }
 }
 
+// Without parameters (no audio recorded), the writers never get audio samples,
+// so they don't write an audio track.
 struct IpCameraAudioParameters {
-    parameters: AudioParameters,
+    parameters: Option<AudioParameters>,
 }
 
 impl IpCameraAudioParameters {
-    pub fn new(parameters: AudioParameters) -> Self {
+    pub fn new(parameters: Option<AudioParameters>) -> Self {
         Self { parameters }
     }
 }
 
 impl CodecParameters for IpCameraAudioParameters {
     fn write_codec_box(&self, buf: &mut BytesMut) -> Result<(), Error> {
+        let Some(parameters) = &self.parameters else {
+            bail!("No audio stream");
+        };
         buf.extend_from_slice(
-            &self
-                .parameters
+            &parameters
                 .mp4_sample_entry()
                 .build()
                 .expect("all added streams have sample entries"),
@@ -746,7 +1195,10 @@ impl CodecParameters for IpCameraAudioParameters {
     }
 
     fn get_clock_rate(&self) -> u32 {
-        self.parameters.clock_rate()
+        self.parameters
+            .as_ref()
+            .map(AudioParameters::clock_rate)
+            .unwrap_or(0)
     }
 
     // Not applicable to audio
@@ -754,3 +1206,125 @@ impl CodecParameters for IpCameraAudioParameters {
         (0, 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_status::{CameraStatusTracker, CAMERA_OFFLINE_FAILURES};
+    use secluso_client_lib::camera_status::CameraStatusNotification;
+
+    const FPS: u64 = 10;
+    // RTP clock rate of H.264 video.
+    const CLOCK_RATE: u64 = 90_000;
+
+    // Keeps what the muxer was given instead of writing an .mp4.
+    #[derive(Default)]
+    struct RecordingMp4 {
+        video: Vec<(u64, bool)>,
+        fragments: usize,
+    }
+
+    impl Mp4 for RecordingMp4 {
+        async fn video(
+            &mut self,
+            _frame: &[u8],
+            frame_timestamp: u64,
+            is_random_access_point: bool,
+        ) -> Result<(), Error> {
+            self.video.push((frame_timestamp, is_random_access_point));
+            Ok(())
+        }
+
+        async fn audio(&mut self, _frame: &[u8], _frame_timestamp: u64) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn finish_fragment(&mut self) -> Result<(), Error> {
+            self.fragments += 1;
+            Ok(())
+        }
+    }
+
+    // Frame number i of a stream with a 1-second GOP, captured at `at`.
+    fn frame(i: u64, at: SystemTime) -> Frame {
+        Frame {
+            frame: vec![0; 16],
+            frame_timestamp: i * CLOCK_RATE / FPS,
+            timestamp: at,
+            is_video: true,
+            is_random_access_point: i % FPS == 0,
+        }
+    }
+
+    #[test]
+    fn preroll_is_stitched_to_the_live_frames() {
+        let trigger = SystemTime::now();
+        let frame_interval = Duration::from_millis(1000 / FPS);
+
+        // 5 seconds buffered before the trigger, then the frames that arrive while recording.
+        let mut queue: VecDeque<Frame> = (0..5 * FPS)
+            .map(|i| frame(i, trigger - Duration::from_secs(5) + frame_interval * i as u32))
+            .collect();
+        preroll::trim_to_preroll(&mut queue, Duration::from_secs(3), trigger);
+        assert!(queue.front().unwrap().is_keyframe());
+
+        queue.extend((5 * FPS..10 * FPS).map(|i| {
+            frame(i, trigger + frame_interval * (i - 5 * FPS) as u32)
+        }));
+        let frame_queue = Arc::new(Mutex::new(queue));
+
+        let mut mp4 = RecordingMp4::default();
+        Runtime::new()
+            .unwrap()
+            .block_on(IpCamera::copy(&mut mp4, Some(2), frame_queue, None, false))
+            .unwrap();
+
+        // Starts with the keyframe 3 seconds before the trigger.
+        let (first_timestamp, first_is_keyframe) = mp4.video[0];
+        assert!(first_is_keyframe);
+        assert_eq!(first_timestamp, 2 * CLOCK_RATE);
+        // No gap or reordering where the pre-roll meets the live frames.
+        assert!(mp4.video.windows(2).all(|w| w[1].0 == w[0].0 + CLOCK_RATE / FPS));
+        assert!(mp4.video.last().unwrap().0 > 5 * CLOCK_RATE + 2 * CLOCK_RATE - CLOCK_RATE / FPS);
+        assert_eq!(
+            mp4.fragments,
+            mp4.video.iter().filter(|(_, keyframe)| *keyframe).count()
+        );
+    }
+
+    #[test]
+    fn stalled_stream_is_reported_offline_until_it_reconnects() {
+        let health = StreamHealth::default();
+        let mut tracker = CameraStatusTracker::default();
+        health.record_frame();
+        assert_eq!(tracker.status_change(health.consecutive_failures(), 1), None);
+
+        // The stream stalls, and reconnecting keeps failing.
+        for _ in 1..CAMERA_OFFLINE_FAILURES {
+            health.record_failure();
+            assert_eq!(tracker.status_change(health.consecutive_failures(), 2), None);
+        }
+        assert_eq!(health.record_failure(), CAMERA_OFFLINE_FAILURES);
+        let offline = Some(CameraStatusNotification::CameraOffline {
+            consecutive_failures: CAMERA_OFFLINE_FAILURES,
+            timestamp: 3,
+        });
+        assert_eq!(tracker.status_change(health.consecutive_failures(), 3), offline);
+        // Not sent (e.g., the server was unreachable): it's tried again.
+        assert_eq!(tracker.status_change(health.consecutive_failures(), 3), offline);
+        tracker.notification_sent();
+
+        // Only one notification while the camera stays offline.
+        health.record_failure();
+        assert_eq!(tracker.status_change(health.consecutive_failures(), 4), None);
+
+        // A reconnect gets frames again.
+        health.record_frame();
+        assert_eq!(
+            tracker.status_change(health.consecutive_failures(), 5),
+            Some(CameraStatusNotification::CameraOnline { timestamp: 5 })
+        );
+        tracker.notification_sent();
+        assert_eq!(tracker.status_change(health.consecutive_failures(), 6), None);
+    }
+}
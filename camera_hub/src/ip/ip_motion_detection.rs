@@ -4,7 +4,7 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::motion::MotionResult;
-use image::{imageops, GenericImageView, GrayImage, ImageReader};
+use image::{imageops, GenericImageView, GrayImage, ImageReader, RgbImage};
 use linfa::dataset::Labels;
 use linfa::prelude::Transformer;
 use linfa::Dataset;
@@ -106,6 +106,26 @@ impl MotionDetection {
         })
     }
 
+    /// The most recent JPEG frame from the MJPEG stream, if any has arrived yet.
+    pub fn latest_jpeg(&self) -> Option<Vec<u8>> {
+        self.latest_frame
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|latest_frame| latest_frame.frame.clone())
+    }
+
+    /// The most recent frame, decoded, for use as a motion thumbnail.
+    pub fn latest_thumbnail(&self) -> Option<RgbImage> {
+        let jpeg = self.latest_jpeg()?;
+        ImageReader::new(io::Cursor::new(jpeg))
+            .with_guessed_format()
+            .ok()?
+            .decode()
+            .ok()
+            .map(|decoded| decoded.to_rgb8())
+    }
+
     /// Reads the multipart/x-mixed-replace stream, printing debug info for each line,
     /// and attempts to parse `Content-Length` to read JPEG frames.
     fn process_mjpeg_stream(
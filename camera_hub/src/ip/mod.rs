@@ -1,4 +1,6 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
+pub(crate) mod config_validation;
 pub(crate) mod ip_camera;
-pub(crate) mod ip_motion_detection;
\ No newline at end of file
+pub(crate) mod ip_motion_detection;
+pub(crate) mod onvif;
\ No newline at end of file
@@ -0,0 +1,448 @@
+//! Minimal ONVIF events client, used to get motion events from IP cameras that support
+//! PullPoint subscriptions instead of running motion detection on the hub.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use base64::engine::general_purpose::STANDARD as base64_engine;
+use base64::Engine;
+use rand::RngCore;
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use sha1::{Digest, Sha1};
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_ONVIF_PORT: u16 = 80;
+
+// How long the subscription lives without being renewed. We renew well before it expires.
+const SUBSCRIPTION_SECS: u64 = 60;
+const RENEW_MARGIN: Duration = Duration::from_secs(15);
+// How long the camera may hold a PullMessages request when there are no events.
+const PULL_TIMEOUT: &str = "PT1S";
+const PULL_MESSAGE_LIMIT: u32 = 10;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+// After a failure, we use frame-diff motion detection for this long before trying ONVIF again.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+const NS_DEVICE: &str = "http://www.onvif.org/ver10/device/wsdl";
+const NS_EVENTS: &str = "http://www.onvif.org/ver10/events/wsdl";
+const NS_WSN: &str = "http://docs.oasis-open.org/wsn/b-2";
+
+/// Sends a SOAP request and returns the response body. Separate from the client so
+/// that the ONVIF logic can run against canned responses.
+pub trait OnvifTransport {
+    fn post(&self, url: &str, body: String) -> io::Result<String>;
+}
+
+pub struct HttpTransport {
+    client: Client,
+}
+
+impl HttpTransport {
+    pub fn new() -> io::Result<Self> {
+        let client = Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(io::Error::other)?;
+
+        Ok(Self { client })
+    }
+}
+
+impl OnvifTransport for HttpTransport {
+    fn post(&self, url: &str, body: String) -> io::Result<String> {
+        let response = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/soap+xml; charset=utf-8")
+            .body(body)
+            .send()
+            .map_err(io::Error::other)?;
+
+        let status = response.status();
+        let text = response.text().map_err(io::Error::other)?;
+        if !status.is_success() {
+            return Err(io::Error::other(format!(
+                "ONVIF request to {url} failed: HTTP {status}"
+            )));
+        }
+
+        Ok(text)
+    }
+}
+
+/// A motion state change reported by the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionEvent {
+    pub active: bool,
+}
+
+struct Subscription {
+    address: String,
+    expires_at: Instant,
+}
+
+pub struct OnvifClient<T: OnvifTransport> {
+    transport: T,
+    device_url: String,
+    username: String,
+    password: String,
+    subscription: Option<Subscription>,
+}
+
+impl<T: OnvifTransport> OnvifClient<T> {
+    pub fn new(transport: T, ip: &str, port: u16, username: String, password: String) -> Self {
+        Self {
+            transport,
+            device_url: format!("http://{ip}:{port}/onvif/device_service"),
+            username,
+            password,
+            subscription: None,
+        }
+    }
+
+    /// Returns the motion events since the last call, (re)subscribing as needed.
+    pub fn pull_motion_events(&mut self) -> io::Result<Vec<MotionEvent>> {
+        let now = Instant::now();
+        match &self.subscription {
+            None => self.subscribe(now)?,
+            Some(subscription) if subscription.expires_at <= now + RENEW_MARGIN => {
+                if let Err(e) = self.renew(now) {
+                    debug!("Failed to renew the ONVIF subscription ({e}), subscribing again");
+                    self.subscribe(now)?;
+                }
+            }
+            Some(_) => {}
+        }
+
+        let address = self.subscription.as_ref().unwrap().address.clone();
+        let body = format!(
+            r#"<tev:PullMessages xmlns:tev="{NS_EVENTS}"><tev:Timeout>{PULL_TIMEOUT}</tev:Timeout><tev:MessageLimit>{PULL_MESSAGE_LIMIT}</tev:MessageLimit></tev:PullMessages>"#
+        );
+        let response = self
+            .request(&address, &body)
+            .inspect_err(|_| self.subscription = None)?;
+
+        parse_pull_messages(&response)
+    }
+
+    fn subscribe(&mut self, now: Instant) -> io::Result<()> {
+        let body = format!(
+            r#"<tds:GetCapabilities xmlns:tds="{NS_DEVICE}"><tds:Category>Events</tds:Category></tds:GetCapabilities>"#
+        );
+        let response = self.request(&self.device_url.clone(), &body)?;
+        let events_url = parse_events_address(&response)?;
+
+        let body = format!(
+            r#"<tev:CreatePullPointSubscription xmlns:tev="{NS_EVENTS}"><tev:InitialTerminationTime>PT{SUBSCRIPTION_SECS}S</tev:InitialTerminationTime></tev:CreatePullPointSubscription>"#
+        );
+        let response = self.request(&events_url, &body)?;
+        let address = parse_subscription_address(&response)?;
+        debug!("Created ONVIF PullPoint subscription at {address}");
+
+        self.subscription = Some(Subscription {
+            address,
+            expires_at: now + Duration::from_secs(SUBSCRIPTION_SECS),
+        });
+
+        Ok(())
+    }
+
+    fn renew(&mut self, now: Instant) -> io::Result<()> {
+        let address = self.subscription.as_ref().unwrap().address.clone();
+        let body = format!(
+            r#"<wsnt:Renew xmlns:wsnt="{NS_WSN}"><wsnt:TerminationTime>PT{SUBSCRIPTION_SECS}S</wsnt:TerminationTime></wsnt:Renew>"#
+        );
+        self.request(&address, &body)?;
+
+        self.subscription.as_mut().unwrap().expires_at =
+            now + Duration::from_secs(SUBSCRIPTION_SECS);
+
+        Ok(())
+    }
+
+    fn request(&self, url: &str, body: &str) -> io::Result<String> {
+        self.transport
+            .post(url, soap_envelope(&self.username, &self.password, body))
+    }
+}
+
+/// ONVIF motion events with a fallback: when the camera can't be reached over ONVIF,
+/// the caller uses frame-diff motion detection until RETRY_DELAY has passed.
+pub struct OnvifMotion<T: OnvifTransport> {
+    client: OnvifClient<T>,
+    retry_at: Option<Instant>,
+}
+
+impl<T: OnvifTransport> OnvifMotion<T> {
+    pub fn new(client: OnvifClient<T>) -> Self {
+        Self {
+            client,
+            retry_at: None,
+        }
+    }
+
+    /// Whether there was motion since the last poll. None when ONVIF isn't available right now.
+    pub fn poll(&mut self) -> Option<bool> {
+        let now = Instant::now();
+        if self.retry_at.is_some_and(|retry_at| now < retry_at) {
+            return None;
+        }
+
+        match self.client.pull_motion_events() {
+            Ok(events) => {
+                self.retry_at = None;
+                Some(events.iter().any(|e| e.active))
+            }
+            Err(e) => {
+                warn!("ONVIF motion events failed ({e}), falling back to frame-diff motion detection");
+                self.retry_at = Some(now + RETRY_DELAY);
+                None
+            }
+        }
+    }
+}
+
+fn soap_envelope(username: &str, password: &str, body: &str) -> String {
+    let mut nonce = [0u8; 16];
+    rand::rng().fill_bytes(&mut nonce);
+    let created = format_utc_timestamp(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    );
+    let digest = password_digest(&nonce, &created, password);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope"><s:Header><wsse:Security xmlns:wsse="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd" xmlns:wsu="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-utility-1.0.xsd"><wsse:UsernameToken><wsse:Username>{}</wsse:Username><wsse:Password Type="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-username-token-profile-1.0#PasswordDigest">{digest}</wsse:Password><wsse:Nonce EncodingType="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-soap-message-security-1.0#Base64Binary">{}</wsse:Nonce><wsu:Created>{created}</wsu:Created></wsse:UsernameToken></wsse:Security></s:Header><s:Body>{body}</s:Body></s:Envelope>"#,
+        xml_escape(username),
+        base64_engine.encode(nonce),
+    )
+}
+
+// WS-UsernameToken: Base64(SHA1(nonce + created + password)).
+fn password_digest(nonce: &[u8], created: &str, password: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(nonce);
+    hasher.update(created.as_bytes());
+    hasher.update(password.as_bytes());
+    base64_engine.encode(hasher.finalize())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Formats seconds since the Unix epoch as, e.g., 2025-01-31T12:00:00Z.
+fn format_utc_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Civil-from-days (Howard Hinnant's algorithm).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60
+    )
+}
+
+fn parse_xml(xml: &str) -> io::Result<roxmltree::Document<'_>> {
+    roxmltree::Document::parse(xml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn missing(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("ONVIF response has no {what}"),
+    )
+}
+
+/// The events service address from a GetCapabilities response.
+pub fn parse_events_address(xml: &str) -> io::Result<String> {
+    let doc = parse_xml(xml)?;
+    doc.descendants()
+        .filter(|n| n.has_tag_name("Events"))
+        .flat_map(|n| n.descendants())
+        .find(|n| n.has_tag_name("XAddr"))
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .ok_or_else(|| missing("events address"))
+}
+
+/// The subscription address from a CreatePullPointSubscription response.
+pub fn parse_subscription_address(xml: &str) -> io::Result<String> {
+    let doc = parse_xml(xml)?;
+    doc.descendants()
+        .filter(|n| n.has_tag_name("SubscriptionReference"))
+        .flat_map(|n| n.descendants())
+        .find(|n| n.has_tag_name("Address"))
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .ok_or_else(|| missing("subscription address"))
+}
+
+/// The motion events in a PullMessages response. Other events are ignored.
+pub fn parse_pull_messages(xml: &str) -> io::Result<Vec<MotionEvent>> {
+    let doc = parse_xml(xml)?;
+    if doc.descendants().any(|n| n.has_tag_name("Fault")) {
+        return Err(io::Error::other("ONVIF PullMessages returned a fault"));
+    }
+
+    let mut events = Vec::new();
+    for message in doc
+        .descendants()
+        .filter(|n| n.has_tag_name("NotificationMessage"))
+    {
+        let topic = message
+            .descendants()
+            .find(|n| n.has_tag_name("Topic"))
+            .and_then(|n| n.text())
+            .unwrap_or_default();
+        if !is_motion_topic(topic) {
+            continue;
+        }
+
+        let value = message
+            .descendants()
+            .filter(|n| n.has_tag_name("Data"))
+            .flat_map(|n| n.descendants())
+            .filter(|n| n.has_tag_name("SimpleItem"))
+            .find(|n| matches!(n.attribute("Name"), Some("IsMotion") | Some("State")))
+            .and_then(|n| n.attribute("Value"));
+
+        if let Some(value) = value {
+            events.push(MotionEvent {
+                active: value.eq_ignore_ascii_case("true"),
+            });
+        }
+    }
+
+    Ok(events)
+}
+
+// e.g., tns1:RuleEngine/CellMotionDetector/Motion and tns1:VideoSource/MotionAlarm
+fn is_motion_topic(topic: &str) -> bool {
+    topic.contains("RuleEngine/CellMotionDetector") || topic.contains("VideoSource/MotionAlarm")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_pull_messages, MotionEvent};
+    use std::io;
+
+    // A PullMessagesResponse with one NotificationMessage per (topic, item name, value).
+    fn pull_messages_response(messages: &[(&str, &str, &str)]) -> String {
+        let messages: String = messages
+            .iter()
+            .map(|(topic, name, value)| {
+                format!(
+                    r#"<wsnt:NotificationMessage>
+  <wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">{topic}</wsnt:Topic>
+  <wsnt:Message>
+    <tt:Message UtcTime="2026-10-15T09:00:00Z" PropertyOperation="Changed">
+      <tt:Source><tt:SimpleItem Name="VideoSourceConfigurationToken" Value="VideoSource_1"/></tt:Source>
+      <tt:Data><tt:SimpleItem Name="{name}" Value="{value}"/></tt:Data>
+    </tt:Message>
+  </wsnt:Message>
+</wsnt:NotificationMessage>"#
+                )
+            })
+            .collect();
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://www.w3.org/2003/05/soap-envelope"
+    xmlns:tt="http://www.onvif.org/ver10/schema"
+    xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2"
+    xmlns:tev="http://www.onvif.org/ver10/events/wsdl"
+    xmlns:tns1="http://www.onvif.org/ver10/topics">
+<SOAP-ENV:Body>
+<tev:PullMessagesResponse>
+  <tev:CurrentTime>2026-10-15T09:00:01Z</tev:CurrentTime>
+  <tev:TerminationTime>2026-10-15T09:01:01Z</tev:TerminationTime>
+  {messages}
+</tev:PullMessagesResponse>
+</SOAP-ENV:Body>
+</SOAP-ENV:Envelope>"#
+        )
+    }
+
+    #[test]
+    fn motion_start_and_end_are_parsed() {
+        let xml = pull_messages_response(&[
+            ("tns1:RuleEngine/CellMotionDetector/Motion", "IsMotion", "true"),
+            ("tns1:VideoSource/MotionAlarm", "State", "false"),
+        ]);
+
+        assert_eq!(
+            parse_pull_messages(&xml).unwrap(),
+            [MotionEvent { active: true }, MotionEvent { active: false }]
+        );
+    }
+
+    #[test]
+    fn unknown_topics_are_ignored() {
+        let xml = pull_messages_response(&[
+            ("tns1:Device/Trigger/DigitalInput", "LogicalState", "true"),
+            ("tns1:RuleEngine/TamperDetector/Tamper", "IsTamper", "true"),
+            ("tns1:RuleEngine/CellMotionDetector/Motion", "IsMotion", "TRUE"),
+        ]);
+
+        assert_eq!(
+            parse_pull_messages(&xml).unwrap(),
+            [MotionEvent { active: true }]
+        );
+    }
+
+    #[test]
+    fn empty_response_has_no_events() {
+        assert!(parse_pull_messages(&pull_messages_response(&[]))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        let xml = pull_messages_response(&[(
+            "tns1:RuleEngine/CellMotionDetector/Motion",
+            "IsMotion",
+            "true",
+        )]);
+        let truncated = &xml[..xml.len() / 2];
+
+        let err = parse_pull_messages(truncated).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(parse_pull_messages("not xml").is_err());
+    }
+
+    #[test]
+    fn fault_is_an_error() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://www.w3.org/2003/05/soap-envelope">
+<SOAP-ENV:Body>
+  <SOAP-ENV:Fault>
+    <SOAP-ENV:Code><SOAP-ENV:Value>SOAP-ENV:Receiver</SOAP-ENV:Value></SOAP-ENV:Code>
+    <SOAP-ENV:Reason><SOAP-ENV:Text xml:lang="en">Subscription expired</SOAP-ENV:Text></SOAP-ENV:Reason>
+  </SOAP-ENV:Fault>
+</SOAP-ENV:Body>
+</SOAP-ENV:Envelope>"#;
+
+        assert!(parse_pull_messages(xml).is_err());
+    }
+}
@@ -3,20 +3,247 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::delivery_monitor::DeliveryMonitor;
+use crate::talkback::play_pending_talkback;
 use crate::Camera;
 use secluso_client_lib::http_client::HttpClient;
+use secluso_client_lib::livestream_chunk::{encode_chunk, LIVESTREAM_CHUNK_FORMAT};
 use secluso_client_lib::mls_client::MlsClient;
 use secluso_client_lib::mls_clients::MAX_OFFLINE_WINDOW;
 use std::io;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::io::AsyncWrite;
 
+#[cfg(feature = "ip")]
+use std::fs;
+
 /// Used to determine when to end livestream
 const MAX_NUM_PENDING_LIVESTREAM_CHUNKS: usize = 5;
+// How long we wait for the next fragment before checking for a shutdown request again.
+const FRAGMENT_SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Quality controller thresholds for the number of pending chunks reported by the server.
+// Right after an upload, a viewer that keeps up has 1 pending chunk (the one we just uploaded).
+const QUALITY_DOWN_PENDING_CHUNKS: usize = 3;
+const QUALITY_UP_PENDING_CHUNKS: usize = 1;
+// Number of consecutive uploads past a threshold before we change the quality.
+// Stepping up is slower than stepping down so that we don't oscillate on a borderline uplink.
+const QUALITY_DOWN_UPLOADS: u32 = 2;
+const QUALITY_UP_UPLOADS: u32 = 5;
+
+/// Livestream quality ladder, from full quality down.
+/// The active level is sent in the header of every chunk to the apps that support it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StreamQuality {
+    /// All frames.
+    #[default]
+    Full = 0,
+    /// Video keyframes and audio.
+    Reduced = 1,
+    /// Video keyframes only.
+    Minimal = 2,
+}
+
+impl StreamQuality {
+    fn from_level(level: u8) -> Self {
+        match level {
+            0 => Self::Full,
+            1 => Self::Reduced,
+            _ => Self::Minimal,
+        }
+    }
+
+    fn lower(self) -> Self {
+        Self::from_level(self as u8 + 1)
+    }
+
+    fn higher(self) -> Self {
+        Self::from_level((self as u8).saturating_sub(1))
+    }
+
+    /// Whether a camera that lowers the bitrate by dropping frames keeps this frame.
+    pub fn keeps_frame(self, is_video: bool, is_keyframe: bool) -> bool {
+        match self {
+            Self::Full => true,
+            Self::Reduced => !is_video || is_keyframe,
+            Self::Minimal => is_video && is_keyframe,
+        }
+    }
+}
+
+/// Stream quality shared between a camera and its livestream thread.
+#[derive(Clone, Default)]
+pub struct SharedStreamQuality(Arc<AtomicU8>);
+
+impl SharedStreamQuality {
+    pub fn get(&self) -> StreamQuality {
+        StreamQuality::from_level(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, quality: StreamQuality) {
+        self.0.store(quality as u8, Ordering::Relaxed);
+    }
+}
+
+/// Picks the livestream quality based on the number of pending chunks on the server,
+/// which grows when the uplink can't keep up.
+#[derive(Default)]
+pub struct QualityController {
+    quality: StreamQuality,
+    uploads_above: u32,
+    uploads_below: u32,
+}
+
+impl QualityController {
+    /// Called after every upload with the number of pending chunks.
+    /// Returns the new quality if it changed.
+    pub fn observe(&mut self, num_pending: usize) -> Option<StreamQuality> {
+        let new_quality = if num_pending >= QUALITY_DOWN_PENDING_CHUNKS {
+            self.uploads_below = 0;
+            self.uploads_above += 1;
+            if self.uploads_above < QUALITY_DOWN_UPLOADS {
+                return None;
+            }
+            self.quality.lower()
+        } else if num_pending <= QUALITY_UP_PENDING_CHUNKS {
+            self.uploads_above = 0;
+            self.uploads_below += 1;
+            if self.uploads_below < QUALITY_UP_UPLOADS {
+                return None;
+            }
+            self.quality.higher()
+        } else {
+            self.uploads_above = 0;
+            self.uploads_below = 0;
+            return None;
+        };
+
+        // Each step needs its own run of uploads past the threshold.
+        self.uploads_above = 0;
+        self.uploads_below = 0;
+
+        if new_quality == self.quality {
+            return None;
+        }
+        self.quality = new_quality;
+        Some(new_quality)
+    }
+}
+
+/// Limits that end a livestream session when the app is gone without ending it
+/// (e.g., the app process was killed), so that we go back to handling motion.
+#[derive(Debug, Clone)]
+pub struct LivestreamLimits {
+    /// End the session if the app hasn't retrieved any chunk for this long.
+    pub idle_timeout: Duration,
+    /// End the session after this long, no matter what.
+    pub max_session: Duration,
+}
+
+impl Default for LivestreamLimits {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(30),
+            max_session: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// The optional livestream section of cameras.yaml. Durations are in seconds.
+#[cfg(feature = "ip")]
+#[derive(Debug, Default, Deserialize)]
+struct LivestreamLimitsConfig {
+    idle_timeout_secs: Option<u64>,
+    max_session_secs: Option<u64>,
+}
+
+#[cfg(feature = "ip")]
+#[derive(Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    livestream: LivestreamLimitsConfig,
+}
+
+impl LivestreamLimits {
+    /// Loads the limits from cameras.yaml (IP cameras only).
+    /// Missing fields (or a missing file) fall back to the defaults.
+    pub fn load() -> Self {
+        #[cfg(feature = "ip")]
+        if let Ok(content) = fs::read_to_string("cameras.yaml") {
+            match serde_yaml2::from_str::<Config>(&content) {
+                Ok(cfg) => {
+                    let default = Self::default();
+                    return Self {
+                        idle_timeout: cfg
+                            .livestream
+                            .idle_timeout_secs
+                            .map(Duration::from_secs)
+                            .unwrap_or(default.idle_timeout),
+                        max_session: cfg
+                            .livestream
+                            .max_session_secs
+                            .map(Duration::from_secs)
+                            .unwrap_or(default.max_session),
+                    };
+                }
+                Err(e) => {
+                    error!("Failed to parse livestream in cameras.yaml, using defaults ({e})");
+                }
+            }
+        }
+
+        Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    Idle,
+    MaxDuration,
+}
+
+/// Detects an app that stopped retrieving chunks, based on the number of pending chunks
+/// the server reports after each upload.
+pub struct IdleMonitor {
+    limits: LivestreamLimits,
+    started: Instant,
+    last_progress: Instant,
+    last_pending: Option<usize>,
+}
+
+impl IdleMonitor {
+    pub fn new(limits: LivestreamLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            started: now,
+            last_progress: now,
+            last_pending: None,
+        }
+    }
+
+    /// Called after every upload with the number of chunks the app hasn't retrieved yet.
+    /// Each upload adds one chunk, so if the count didn't go up, the app retrieved at least one.
+    pub fn observe(&mut self, num_pending: usize, now: Instant) -> Option<SessionEnd> {
+        match self.last_pending {
+            Some(last) if num_pending > last => {}
+            _ => self.last_progress = now,
+        }
+        self.last_pending = Some(num_pending);
+
+        if now.duration_since(self.started) >= self.limits.max_session {
+            Some(SessionEnd::MaxDuration)
+        } else if now.duration_since(self.last_progress) >= self.limits.idle_timeout {
+            Some(SessionEnd::Idle)
+        } else {
+            None
+        }
+    }
+}
 
 pub struct LivestreamWriter {
     sender: Sender<Vec<u8>>,
@@ -65,6 +292,8 @@ pub fn livestream(
     camera: &dyn Camera,
     delivery_monitor: &mut DeliveryMonitor,
     http_client: &HttpClient,
+    limits: &LivestreamLimits,
+    chunk_format: u8,
 ) -> io::Result<()> {
     if mls_client.offline_period() > MAX_OFFLINE_WINDOW {
         info!("App has been offline for too long. Won't send any more videos until there is a heartbeat.");
@@ -75,7 +304,7 @@ pub fn livestream(
 
     // Update MLS epoch
     let (commit_msg, _epoch) = mls_client.update()?;
-    mls_client.save_group_state().unwrap();
+    mls_client.save_group_state()?;
     let group_name = mls_client.get_group_name().unwrap();
 
     // Why bother with enqueueing the updates in the delivery monitor?
@@ -98,17 +327,32 @@ pub fn livestream(
     let livestream_writer = LivestreamWriter::new(tx);
     camera.launch_livestream(livestream_writer)?;
 
+    let chunk_format = chunk_format.min(LIVESTREAM_CHUNK_FORMAT);
     let mut chunk_number: u64 = 1;
+    let mut idle_monitor = IdleMonitor::new(limits.clone(), Instant::now());
+    let mut quality_controller = QualityController::default();
+    let mut quality = StreamQuality::Full;
+    let mut talkback_sink = camera.talkback_sink();
+    let mut talkback_chunk_number: u64 = 1;
 
     loop {
+        if crate::shutdown_requested() {
+            info!("Ending livestream because of shutdown.");
+            break;
+        }
+
         // We include the chunk number in the chunk itself (and check it in the app)
         // to prevent a malicious server from reordering the chunks.
-        let Ok(fragment) = rx.recv() else {
-            info!("Ending livestream because the camera backend stopped producing fragments.");
-            break;
+        // Apps that asked for it also get the quality level, so that they can show when it's reduced.
+        let fragment = match rx.recv_timeout(FRAGMENT_SHUTDOWN_POLL_INTERVAL) {
+            Ok(fragment) => fragment,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                info!("Ending livestream because the camera backend stopped producing fragments.");
+                break;
+            }
         };
-        let mut data: Vec<u8> = chunk_number.to_be_bytes().to_vec();
-        data.extend(fragment);
+        let data = encode_chunk(chunk_format, chunk_number, quality as u8, &fragment);
 
         let received_epoch_ms = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -149,9 +393,187 @@ pub fn livestream(
             info!("Ending livestream.");
             break;
         }
+
+        match idle_monitor.observe(num_pending_files, Instant::now()) {
+            Some(SessionEnd::Idle) => {
+                info!("Ending livestream because the app stopped retrieving chunks.");
+                break;
+            }
+            Some(SessionEnd::MaxDuration) => {
+                info!("Ending livestream because it reached the maximum session length.");
+                break;
+            }
+            None => {}
+        }
+
+        if let Some(new_quality) = quality_controller.observe(num_pending_files) {
+            quality = camera.set_stream_quality(new_quality);
+            info!(
+                "Livestream: {} pending chunks, switched to {:?} quality.",
+                num_pending_files, quality
+            );
+        }
+
+        // The app sends talkback in the same MLS group, so it's decrypted with the same client.
+        play_pending_talkback(
+            mls_client,
+            http_client,
+            &group_name,
+            &mut talkback_chunk_number,
+            talkback_sink.as_mut(),
+        );
     }
 
-    mls_client.save_group_state().unwrap();
+    // The livestream already ended. Keep the hub running and retry with the next save.
+    if let Err(e) = mls_client.save_group_state() {
+        error!("Failed to save the livestream group state ({e})");
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        IdleMonitor, LivestreamLimits, QualityController, SessionEnd, StreamQuality,
+        QUALITY_DOWN_PENDING_CHUNKS, QUALITY_DOWN_UPLOADS, QUALITY_UP_PENDING_CHUNKS,
+        QUALITY_UP_UPLOADS,
+    };
+    use std::time::{Duration, Instant};
+
+    // Feeds the queue depths to the controller and returns the index and quality of every change.
+    fn changes(
+        controller: &mut QualityController,
+        depths: &[usize],
+    ) -> Vec<(usize, StreamQuality)> {
+        depths
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &depth)| controller.observe(depth).map(|quality| (i, quality)))
+            .collect()
+    }
+
+    #[test]
+    fn quality_steps_down_once_after_enough_slow_uploads() {
+        let mut controller = QualityController::default();
+        let depths = vec![QUALITY_DOWN_PENDING_CHUNKS; QUALITY_DOWN_UPLOADS as usize];
+
+        assert_eq!(
+            changes(&mut controller, &depths),
+            [(QUALITY_DOWN_UPLOADS as usize - 1, StreamQuality::Reduced)]
+        );
+    }
+
+    #[test]
+    fn quality_steps_up_once_after_enough_fast_uploads() {
+        let mut controller = QualityController::default();
+        let down = vec![QUALITY_DOWN_PENDING_CHUNKS; QUALITY_DOWN_UPLOADS as usize];
+        changes(&mut controller, &down);
+
+        let up = vec![QUALITY_UP_PENDING_CHUNKS; QUALITY_UP_UPLOADS as usize];
+        assert_eq!(
+            changes(&mut controller, &up),
+            [(QUALITY_UP_UPLOADS as usize - 1, StreamQuality::Full)]
+        );
+
+        // Already at full quality.
+        assert!(changes(&mut controller, &up).is_empty());
+    }
+
+    #[test]
+    fn borderline_uplink_does_not_oscillate() {
+        let mut controller = QualityController::default();
+
+        // Never enough uploads in a row past a threshold.
+        let alternating: Vec<usize> = (0..50)
+            .map(|i| {
+                if i % 2 == 0 {
+                    QUALITY_DOWN_PENDING_CHUNKS
+                } else {
+                    QUALITY_UP_PENDING_CHUNKS
+                }
+            })
+            .collect();
+        assert!(changes(&mut controller, &alternating).is_empty());
+
+        // Queue depths between the thresholds reset the runs.
+        let mut depths = vec![QUALITY_DOWN_PENDING_CHUNKS; QUALITY_DOWN_UPLOADS as usize - 1];
+        depths.push(QUALITY_DOWN_PENDING_CHUNKS - 1);
+        depths.extend(vec![
+            QUALITY_DOWN_PENDING_CHUNKS;
+            QUALITY_DOWN_UPLOADS as usize - 1
+        ]);
+        assert!(changes(&mut controller, &depths).is_empty());
+    }
+
+    #[test]
+    fn each_step_needs_its_own_run() {
+        let mut controller = QualityController::default();
+        let n = QUALITY_DOWN_UPLOADS as usize;
+        let depths = vec![QUALITY_DOWN_PENDING_CHUNKS + 5; 10 * n];
+
+        // Down to the lowest level, then it stays there.
+        assert_eq!(
+            changes(&mut controller, &depths),
+            [
+                (n - 1, StreamQuality::Reduced),
+                (2 * n - 1, StreamQuality::Minimal)
+            ]
+        );
+    }
+
+    fn limits() -> LivestreamLimits {
+        LivestreamLimits {
+            idle_timeout: Duration::from_secs(30),
+            max_session: Duration::from_secs(600),
+        }
+    }
+
+    // Feeds one pending-chunk count per second (one upload per second) to a new monitor
+    // and returns when (in seconds) and why it ended the session, if it did.
+    fn session_end(pending: &[usize]) -> Option<(u64, SessionEnd)> {
+        let start = Instant::now();
+        let mut monitor = IdleMonitor::new(limits(), start);
+        pending.iter().zip(1u64..).find_map(|(&num_pending, secs)| {
+            monitor
+                .observe(num_pending, start + Duration::from_secs(secs))
+                .map(|end| (secs, end))
+        })
+    }
+
+    #[test]
+    fn app_retrieving_chunks_keeps_the_session() {
+        // The app keeps up, or falls behind a bit but still retrieves chunks.
+        let keeping_up = vec![1; 300];
+        assert_eq!(session_end(&keeping_up), None);
+
+        let slow: Vec<usize> = (0..300).map(|i| 5 + (i % 3)).collect();
+        assert_eq!(session_end(&slow), None);
+    }
+
+    #[test]
+    fn app_gone_ends_the_session_after_the_idle_timeout() {
+        // The app retrieves chunks for 10 seconds, then stops: the count grows with every upload.
+        let mut pending = vec![1; 10];
+        pending.extend(2..100);
+
+        assert_eq!(session_end(&pending), Some((40, SessionEnd::Idle)));
+    }
+
+    #[test]
+    fn app_coming_back_resets_the_idle_timeout() {
+        // Stops for 20 seconds (less than the timeout), catches up, then stops for good.
+        let mut pending = vec![1; 10];
+        pending.extend(2..22);
+        pending.extend(vec![0; 5]);
+        pending.extend(1..100);
+
+        assert_eq!(session_end(&pending), Some((65, SessionEnd::Idle)));
+    }
+
+    #[test]
+    fn session_ends_after_the_max_duration() {
+        let pending = vec![1; 1000];
+        assert_eq!(session_end(&pending), Some((600, SessionEnd::MaxDuration)));
+    }
+}
@@ -10,6 +10,8 @@ extern crate serde_derive;
 
 use cfg_if::cfg_if;
 use docopt::Docopt;
+use image::RgbImage;
+use secluso_client_lib::fcm_message;
 use secluso_client_lib::http_client::HttpClient;
 use secluso_client_lib::mls_client::{ClientType, MlsClient};
 use secluso_client_lib::mls_clients::{
@@ -17,17 +19,18 @@ use secluso_client_lib::mls_clients::{
     THUMBNAIL, LIVESTREAM_DED, CONFIG_DED,
     MlsClientsCommon, MlsClientsDedicated,
 };
-use secluso_client_lib::thumbnail_meta_info::ThumbnailMetaInfo;
+use secluso_client_lib::thumbnail_meta_info::{Detection, ThumbnailMetaInfo};
+use secluso_client_lib::camera_status::CameraStatusNotification;
+use secluso_client_lib::livestream_chunk::LIVESTREAM_CHUNK_FORMAT_LEGACY;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::ops::Add;
 use std::panic;
 use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread::sleep;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{thread, time::Duration};
 use anyhow::anyhow;
 
@@ -38,13 +41,13 @@ use crate::delivery_monitor::{DeliveryMonitor, VideoInfo};
 mod motion;
 
 use crate::motion::{
-    prepare_motion_thumbnail, prepare_motion_video,
-    upload_pending_enc_thumbnails, upload_pending_enc_videos,
+    prepare_motion_thumbnail, prepare_motion_video, scaled_thumbnail,
+    upload_pending_enc_thumbnails, upload_pending_enc_videos, video_thumbnail,
 };
 
 mod livestream;
 
-use crate::livestream::livestream;
+use crate::livestream::{livestream, LivestreamLimits};
 
 mod traits;
 
@@ -58,16 +61,47 @@ use crate::config::process_config_command;
 
 mod version;
 
+mod retry;
+
+mod wakeup;
+
+mod talkback;
+
+mod motion_settings;
+
+use crate::motion_settings::MotionSettings;
+
+use crate::wakeup::Wakeup;
+
+use crate::retry::{retry_with_policy, RetryPolicy};
+
 mod notification_target;
 
+mod notification_schedule;
+
+use crate::notification_schedule::motion_notifications_allowed;
+
 use crate::notification_target::send_notification;
-use crate::pairing::flow::pair_all;
+use crate::pairing::flow::{default_pairing_port, pair_all};
 use crate::pairing::io::{get_input_camera_secret, get_names, read_parse_full_credentials};
 
 #[cfg(any(feature = "raspberry", feature = "ip"))]
 mod fmp4;
 #[cfg(any(feature = "raspberry", feature = "ip"))]
 mod mp4;
+#[cfg(any(feature = "raspberry", feature = "ip"))]
+mod preroll;
+
+mod continuous_recording;
+
+mod camera_events;
+
+mod camera_status;
+use crate::camera_status::CameraStatusTracker;
+
+mod metrics;
+
+use crate::camera_events::{report_camera_event, EVENT_MOTION_DETECTION_ERROR};
 
 cfg_if! {
     if #[cfg(feature = "manual")] {
@@ -92,6 +126,9 @@ const STATE_DIR_GENERAL: &str = "state";
 const VIDEO_DIR_GENERAL: &str = "pending_videos";
 const THUMBNAIL_DIR_GENERAL: &str = "pending_thumbnails";
 
+// Upper bound on how long the core loop blocks, so that shutdown requests are noticed promptly.
+const MAX_IDLE_WAIT: Duration = Duration::from_secs(1);
+
 #[cfg(feature = "test")]
 const VERSION_DIR: &str = "current_version";
 #[cfg(feature = "test")]
@@ -102,8 +139,26 @@ const VERSION_DIR: &str = "/var/lib/secluso/current_version";
 #[cfg(not(feature = "test"))]
 const VERSION_FILE: &str = "/var/lib/secluso/current_version/raspberry_camera_hub";
 
-// A counter representing the amount of active camera threads
-static GLOBAL_THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
+// Set when the process receives SIGINT/SIGTERM. Camera threads check it,
+// persist their state, and exit.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// On SIGINT/SIGTERM (e.g., systemd stop), asks the camera threads to save their state
+/// and exit instead of getting killed mid-save. A second signal exits right away.
+pub fn install_shutdown_handler() -> io::Result<()> {
+    ctrlc::set_handler(|| {
+        if SHUTDOWN.swap(true, Ordering::SeqCst) {
+            println!("Shutdown requested again, exiting now.");
+            std::process::exit(1);
+        }
+        println!("Shutdown requested.");
+    })
+    .map_err(io::Error::other)
+}
 
 const USAGE: &str = "
 Secluso camera hub: connects to an IP camera and send videos to the secluso app end-to-end encrypted (through an untrusted server).
@@ -112,12 +167,16 @@ Usage:
   secluso-camera-hub [--save-all]
   secluso-camera-hub [--save-all] --reset
   secluso-camera-hub [--save-all] --reset-full
+  secluso-camera-hub --status
+  secluso-camera-hub --validate-config
   secluso-camera-hub (--version | -v)
   secluso-camera-hub (--help | -h)
 
 Options:
     --reset             Wipe all the state, but not pending videos
     --reset-full        Wipe all the state and pending videos
+    --status            Print the pairing and delivery state of the cameras and exit
+    --validate-config   Check cameras.yaml (IP cameras) and exit with 0 if it's valid, 1 if not
     --save-all          Save all telemetry events, not just human detections
     --version, -v       Show version
     --help, -h          Show help
@@ -127,6 +186,8 @@ Options:
 struct Args {
     flag_reset: bool,
     flag_reset_full: bool,
+    flag_status: bool,
+    flag_validate_config: bool,
     #[cfg(feature = "raspberry")]
     flag_save_all: bool,
 }
@@ -141,6 +202,15 @@ fn main() -> io::Result<()> {
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
 
+    // Only reads the persisted state, so it's safe to run next to a running hub.
+    if args.flag_status {
+        return print_status().map_err(io::Error::other);
+    }
+
+    if args.flag_validate_config {
+        std::process::exit(validate_config());
+    }
+
     // Create the general outer directories (where we'll have inner directories representing each camera)
     fs::create_dir_all(STATE_DIR_GENERAL)?;
     fs::create_dir_all(VIDEO_DIR_GENERAL)?;
@@ -163,13 +233,19 @@ fn main() -> io::Result<()> {
             // Manual mode is meant to stand in for the Raspberry Pi camera during local testing
             let input_camera_secret = Some(get_input_camera_secret());
         } else if #[cfg(feature = "raspberry")] {
+            let camera_name = "RPi".to_string();
+            let blur_kernel_size = raspberry_pi::privacy_mask::load_blur_kernel_size(&camera_name)?;
+            if blur_kernel_size.is_some() {
+                info!("Privacy mask enabled for the thumbnails of {camera_name} (not for its videos or livestreams).");
+            }
             let camera = RaspberryPiCamera::new(
-                "RPi".to_string(),
+                camera_name,
                 STATE_DIR_GENERAL.to_string(),
                 VIDEO_DIR_GENERAL.to_string(),
                 THUMBNAIL_DIR_GENERAL.to_string(),
                 1,
                 args.flag_save_all,
+                blur_kernel_size,
             );
 
             let camera_list: Vec<Box<dyn Camera + Send>> = vec![Box::new(camera)];
@@ -213,15 +289,24 @@ fn main() -> io::Result<()> {
         std::process::abort();
     }));
 
+    install_shutdown_handler()?;
+
+    if !args.flag_reset && !args.flag_reset_full {
+        metrics::start_from_config();
+    }
+
     // Iterate through each camera struct and spawn in a thread to manage each individual one
-    for mut camera in camera_list.into_iter() {
+    let mut handles = Vec::with_capacity(camera_list.len());
+    for (camera_index, mut camera) in camera_list.into_iter().enumerate() {
         println!("Starting to instantiate camera: {:?}", camera.get_name());
+        let pairing_port = camera
+            .get_pairing_port()
+            .unwrap_or(default_pairing_port(camera_index));
 
         let args = args.clone();
         let input_camera_secret = input_camera_secret.clone();
 
-        GLOBAL_THREAD_COUNT.fetch_add(1, Ordering::SeqCst);
-        thread::spawn(move || {
+        handles.push(thread::spawn(move || {
             if args.flag_reset || args.flag_reset_full {
                 match reset(camera.as_ref(), args.flag_reset_full) {
                     Ok(_) => {}
@@ -229,26 +314,93 @@ fn main() -> io::Result<()> {
                         panic!("reset() returned with: {e}");
                     }
                 };
-
-                // Deduct one from our thread count for main thread to know when to exit (when all are finished)
-                GLOBAL_THREAD_COUNT.fetch_sub(1, Ordering::SeqCst);
             } else {
-                match core(
-                    camera.as_mut(),
-                    input_camera_secret.clone(),
-                ) {
+                match core(camera.as_mut(), input_camera_secret.clone(), pairing_port) {
                     Ok(_) => {}
                     Err(e) => {
                         panic!("core() returned with: {e}");
                     }
                 }
             }
-        });
+        }));
     }
 
     // Terminate when no cameras are left running
-    while GLOBAL_THREAD_COUNT.load(Ordering::SeqCst) != 0 {
-        sleep(Duration::from_millis(1));
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
+
+/// Checks cameras.yaml and returns the exit code: 0 if it's valid, 1 if not.
+fn validate_config() -> i32 {
+    cfg_if! {
+        if #[cfg(feature = "ip")] {
+            match ip::config_validation::validate_cameras_config(Path::new("cameras.yaml")) {
+                Ok(()) => {
+                    println!("cameras.yaml is valid.");
+                    0
+                }
+                Err(errors) => {
+                    for error in errors {
+                        println!("{error}");
+                    }
+                    1
+                }
+            }
+        } else {
+            println!("cameras.yaml is only used for IP cameras.");
+            1
+        }
+    }
+}
+
+/// Prints the pairing and delivery state of every camera without starting the cameras.
+fn print_status() -> anyhow::Result<()> {
+    cfg_if! {
+        if #[cfg(feature = "manual")] {
+            let cameras = vec![("RPi".to_string(), format!("{}/manual", STATE_DIR_GENERAL))];
+        } else if #[cfg(feature = "raspberry")] {
+            let cameras = vec![("RPi".to_string(), STATE_DIR_GENERAL.to_string())];
+        } else if #[cfg(feature = "ip")] {
+            let cameras = IpCamera::get_all_cameras_state_dirs()?;
+        } else if #[cfg(feature = "test")] {
+            let cameras = vec![("TestCamera".to_string(), STATE_DIR_GENERAL.to_string())];
+        } else {
+            compile_error!("One of the features 'manual', 'raspberry', 'ip', or 'test' must be enabled.");
+        }
+    }
+
+    for (name, state_dir) in cameras {
+        println!("[{}]", name);
+
+        if !Path::new(&(state_dir.clone() + "/first_time_done")).exists() {
+            println!("Not paired yet.");
+            continue;
+        }
+
+        for tag in MLS_CLIENT_TAGS.iter().take(NUM_MLS_CLIENTS) {
+            let (camera_name, group_name) = get_names(
+                &state_dir,
+                false,
+                format!("camera_{}_name", tag),
+                format!("group_{}_name", tag),
+            )?;
+            let client = MlsClient::new(
+                camera_name,
+                false,
+                state_dir.clone(),
+                tag.to_string(),
+                ClientType::Camera,
+            )?;
+            println!("Group {} ({}): epoch {}", tag, group_name, client.get_epoch()?);
+        }
+
+        match DeliveryMonitor::from_file(&state_dir)? {
+            Some(delivery_monitor) => print!("{}", delivery_monitor.status_summary()),
+            None => println!("No delivery state found."),
+        }
     }
 
     Ok(())
@@ -372,9 +524,144 @@ fn split_clients(clients: MlsClients) -> (MlsClientsCommon, MlsClientsDedicated)
     ([c0, c1, c2], [d0, d1])
 }
 
+/// Tells the app that the camera went offline or came back (over the FCM channel).
+fn send_camera_status(
+    clients_com: &mut MlsClientsCommon,
+    state_dir: &str,
+    http_client: &HttpClient,
+    status: CameraStatusNotification,
+) -> anyhow::Result<()> {
+    let notification_msg =
+        clients_com[FCM].encrypt(&fcm_message::encode_json(&status.to_json_bytes()))?;
+    clients_com[FCM].save_group_state()?;
+    send_notification(state_dir, http_client, notification_msg)?;
+
+    Ok(())
+}
+
+/// Starts the threads that wait for the livestream requests and config commands of an app.
+/// app is 0 for the primary app and i for clients_ded_secondary[i - 1].
+/// retry_with_policy() backs off (with jitter) when the server is unreachable
+/// so that all cameras don't reconnect at the same time after a server restart.
+/// It only returns an error when we're shutting down, which stops the threads.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn spawn_app_pollers(
+    app: usize,
+    clients_ded: &MlsClientsDedicated,
+    camera_name: &str,
+    http_client: &HttpClient,
+    retry_policy: &RetryPolicy,
+    livestream_request: &Arc<Mutex<(bool, usize, u8)>>,
+    config_enc_commands: &Arc<Mutex<Vec<(Vec<u8>, usize)>>>,
+    wakeup: &Arc<Wakeup>,
+) -> io::Result<()> {
+    let group_livestream_name = clients_ded[LIVESTREAM_DED].get_group_name()?;
+    let group_config_name = clients_ded[CONFIG_DED].get_group_name()?;
+
+    let camera_name_clone = camera_name.to_string();
+    let http_client_clone = http_client.clone();
+    let retry_policy_clone = retry_policy.clone();
+    let livestream_request_clone = Arc::clone(livestream_request);
+    let wakeup_clone = Arc::clone(wakeup);
+    thread::spawn(move || loop {
+        if let Ok(chunk_format) = retry_with_policy(&retry_policy_clone, || {
+            http_client_clone.livestream_check(&group_livestream_name)
+        }) {
+            metrics::record_server_contact(&camera_name_clone);
+            println!("Livestream detected (app {})", app + 1);
+            let mut check = livestream_request_clone.lock().unwrap();
+            *check = (true, app, chunk_format);
+            wakeup_clone.notify_request();
+        } else {
+            break;
+        }
+    });
+
+    let camera_name_clone = camera_name.to_string();
+    let http_client_clone = http_client.clone();
+    let retry_policy_clone = retry_policy.clone();
+    let config_enc_commands_clone = Arc::clone(config_enc_commands);
+    let wakeup_clone = Arc::clone(wakeup);
+    thread::spawn(move || {
+        // Acknowledged with the next check, once the command is queued here.
+        let mut last_command_id: Option<String> = None;
+        loop {
+            if let Ok((enc_command, command_id)) = retry_with_policy(&retry_policy_clone, || {
+                http_client_clone.config_check(&group_config_name, last_command_id.as_deref())
+            }) {
+                metrics::record_server_contact(&camera_name_clone);
+                let mut config_enc_commands = config_enc_commands_clone.lock().unwrap();
+                config_enc_commands.push((enc_command, app));
+                wakeup_clone.notify_request();
+                last_command_id = Some(command_id);
+            } else {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Persists the MLS group states and the delivery monitor before exiting.
+fn save_state_on_shutdown(
+    camera_name: &str,
+    clients_com: &mut MlsClientsCommon,
+    clients_ded_primary: &mut MlsClientsDedicated,
+    clients_ded_secondary: &Mutex<Vec<MlsClientsDedicated>>,
+    delivery_monitor: &DeliveryMonitor,
+) -> anyhow::Result<()> {
+    for client in clients_com.iter_mut().chain(clients_ded_primary.iter_mut()) {
+        client.save_group_state()?;
+    }
+
+    for clients_ded_sec in clients_ded_secondary.lock().unwrap().iter_mut() {
+        for client in clients_ded_sec.iter_mut() {
+            client.save_group_state()?;
+        }
+    }
+
+    delivery_monitor.save_state();
+
+    println!("[{}] State saved. Exiting.", camera_name);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_motion_thumbnail(
+    clients_com: &mut MlsClientsCommon,
+    camera: &dyn Camera,
+    delivery_monitor: &mut DeliveryMonitor,
+    http_client: &HttpClient,
+    num_apps: u32,
+    thumbnail_info: ThumbnailMetaInfo,
+    thumbnail_image: RgbImage,
+) -> anyhow::Result<()> {
+    info!("Starting to save and send video thumbnail");
+    let thumbnail_file = camera.get_thumbnail_dir()
+        + "/"
+        + &ThumbnailMetaInfo::get_filename_from_timestamp(thumbnail_info.timestamp);
+    thumbnail_image.save(&thumbnail_file).map_err(|e| {
+        io::Error::other(format!("Failed to save thumbnail {thumbnail_file} ({e})"))
+    })?;
+
+    prepare_motion_thumbnail(&mut clients_com[THUMBNAIL], thumbnail_info, delivery_monitor)?;
+
+    info!("Uploading the encrypted thumbnail.");
+    let _ = upload_pending_enc_thumbnails(
+        &clients_com[THUMBNAIL].get_group_name().unwrap(),
+        delivery_monitor,
+        http_client,
+        num_apps,
+    );
+
+    Ok(())
+}
+
 fn core(
     camera: &mut dyn Camera,
     input_camera_secret: Option<Vec<u8>>,
+    pairing_port: u16,
 ) -> anyhow::Result<()> {
     let state_dir = camera.get_state_dir();
     let first_time: bool = !Path::new(&(state_dir.clone() + "/first_time_done")).exists();
@@ -394,7 +681,10 @@ fn core(
             "[{}] Waiting to be paired with the mobile app.",
             camera_name
         );
-        pair_all(camera, &mut clients, input_camera_secret)?;
+        if !pair_all(camera, &mut clients, input_camera_secret, pairing_port)? {
+            println!("[{}] Pairing stopped by shutdown.", camera_name);
+            return Ok(());
+        }
 
         File::create(camera.get_state_dir() + "/first_time_done").expect("Could not create file");
 
@@ -409,118 +699,151 @@ fn core(
     let http_client = HttpClient::new(server_addr, server_username, server_password);
 
     let mut locked_motion_check_time: Option<Instant> = None;
+    let motion_settings = camera.get_motion_settings();
     let mut locked_delivery_check_time: Option<Instant> = None;
-    let mut locked_livestream_check_time: Option<Instant> = None;
-    let mut locked_config_check_time: Option<Instant> = None;
+    let mut locked_compaction_check_time: Option<Instant> = None;
+    let mut camera_status = CameraStatusTracker::default();
     let video_dir = camera.get_video_dir();
     let thumbnail_dir = camera.get_thumbnail_dir();
     let mut delivery_monitor =
         DeliveryMonitor::from_file_or_new(video_dir, thumbnail_dir, state_dir.clone());
-    let livestream_request = Arc::new(Mutex::new((false, true)));
-    let livestream_request_clone = Arc::clone(&livestream_request);
-    let group_livestream_name_clone = clients_ded_primary[LIVESTREAM_DED].get_group_name().unwrap();
-    let http_client_clone = http_client.clone();
-    let group_config_name_clone = clients_ded_primary[CONFIG_DED].get_group_name().unwrap();
-    let http_client_clone_2 = http_client.clone();
-    let config_enc_commands: Arc<Mutex<Vec<(Vec<u8>, bool)>>> = Arc::new(Mutex::new(vec![]));
-    let config_enc_commands_clone = Arc::clone(&config_enc_commands);
-    let clients_ded_secondary: Arc<Mutex<Option<MlsClientsDedicated>>> = Arc::new(Mutex::new(None));
-
-    thread::spawn(move || loop {
-        if http_client_clone
-            .livestream_check(&group_livestream_name_clone)
-            .is_ok()
-        {
-            println!("Livestream1 detected");
-            let mut check = livestream_request_clone.lock().unwrap();
-            *check = (true, true);  // second true -> livestream command from the primary app
-        } else {
-            sleep(Duration::from_secs(1));
-        }
-    });
-
-    thread::spawn(move || loop {
-        if let Ok(enc_command) = http_client_clone_2.config_check(&group_config_name_clone) {
-            let mut config_enc_commands = config_enc_commands_clone.lock().unwrap();
-            config_enc_commands.push((enc_command, true)); // true -> config command from the primary app
-        } else {
-            error!("Error in receiving config command");
-            sleep(Duration::from_secs(1));
-        }
-    });
+    // (requested, index of the app that asked for it, chunk format asked for by the app)
+    let livestream_request = Arc::new(Mutex::new((false, 0, LIVESTREAM_CHUNK_FORMAT_LEGACY)));
+    let config_enc_commands: Arc<Mutex<Vec<(Vec<u8>, usize)>>> = Arc::new(Mutex::new(vec![]));
+    // Dedicated clients of the apps added after the primary one. App i (i > 0) uses clients_ded_secondary[i - 1].
+    let clients_ded_secondary: Arc<Mutex<Vec<MlsClientsDedicated>>> = Arc::new(Mutex::new(vec![]));
+    let retry_policy = RetryPolicy::load();
+    let livestream_limits = LivestreamLimits::load();
+    let wakeup = Arc::new(Wakeup::new());
+    camera.set_wakeup(Arc::clone(&wakeup));
+
+    spawn_app_pollers(
+        0,
+        &clients_ded_primary,
+        &camera_name,
+        &http_client,
+        &retry_policy,
+        &livestream_request,
+        &config_enc_commands,
+        &wakeup,
+    )?;
 
     // Used for anti-dither for motion detection
     loop {
-        // Check motion events from the camera every second
-        let motion_event = match camera.is_there_motion() {
+        if shutdown_requested() {
+            return save_state_on_shutdown(
+                &camera_name,
+                &mut clients_com,
+                &mut clients_ded_primary,
+                &clients_ded_secondary,
+                &delivery_monitor,
+            );
+        }
+
+        // Block until there's motion, a request from the app, or the next periodic check is due.
+        let idle_timeout = [locked_delivery_check_time, locked_compaction_check_time]
+            .iter()
+            .flatten()
+            .map(|t| t.saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(Duration::ZERO)
+            .min(MAX_IDLE_WAIT);
+        let motion_event = match camera.wait_for_motion(&wakeup, idle_timeout) {
             Ok(event) => event,
             Err(e) => {
                 println!("Motion detection error {}", e);
+                report_camera_event(EVENT_MOTION_DETECTION_ERROR, &e.to_string());
                 continue;
             }
         };
 
         // Send motion events only if we haven't sent one in the past minute
         if (motion_event.motion)
-            && (locked_motion_check_time.is_none()
-                || locked_motion_check_time.unwrap().le(&Instant::now()))
+            && MotionSettings::motion_allowed(locked_motion_check_time, Instant::now())
         {
             let video_info = VideoInfo::new();
             let motion_timestamp = video_info.timestamp;
             println!("Detected motion.");
+            metrics::record_motion_event(&camera_name);
 
-            let clients_ded_sec_opt = clients_ded_secondary.lock().unwrap();
-            let num_apps = if clients_ded_sec_opt.is_some() {
-                2
-            } else {
-                1
-            };
+            let num_apps = 1 + clients_ded_secondary.lock().unwrap().len() as u32;
 
             // We send the thumbnail BEFORE the FCM notification, to ensure that when the mobile app receives it, it can download it.
+            let had_thumbnail = motion_event.thumbnail.is_some();
             if let Some(thumbnail_image) = motion_event.thumbnail {
-                info!("Starting to save and send video thumbnail");
-                let thumbnail_info =
-                    ThumbnailMetaInfo::new(video_info.timestamp, 0, motion_event.detections); //0 epoch = unset
-                let thumbnail_file = camera.get_thumbnail_dir()
-                    + "/"
-                    + &ThumbnailMetaInfo::get_filename_from_timestamp(thumbnail_info.timestamp);
-                thumbnail_image
-                    .save(thumbnail_file)
-                    .expect("Failed to save thumbnail PNG file");
-
-                prepare_motion_thumbnail(
-                    &mut clients_com[THUMBNAIL],
-                    thumbnail_info,
-                    &mut delivery_monitor,
-                )?;
-
-                info!("Uploading the encrypted thumbnail.");
-                let _ = upload_pending_enc_thumbnails(
-                    &clients_com[THUMBNAIL].get_group_name().unwrap(),
+                send_motion_thumbnail(
+                    &mut clients_com,
+                    camera,
                     &mut delivery_monitor,
                     &http_client,
                     num_apps,
-                );
+                    ThumbnailMetaInfo::new(
+                        video_info.timestamp,
+                        0, //0 epoch = unset
+                        // TODO: motion_ai doesn't give us the boxes and confidences of the detections yet,
+                        // so they're sent without.
+                        motion_event.detections.iter().map(Detection::from).collect(),
+                    ),
+                    thumbnail_image,
+                )?;
             }
 
             let state_dir_ref = state_dir.as_str();
-            info!("Sending the motion notification with timestamp.");
-            let notification_msg =
-                clients_com[FCM].encrypt(&bincode::serialize(&motion_timestamp).unwrap())?;
-            clients_com[FCM].save_group_state().unwrap();
-            match send_notification(state_dir_ref, &http_client, notification_msg) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("Failed to send motion notification ({})", e);
+            // The schedule only disarms the notifications. We still record and upload the video.
+            let notify = motion_notifications_allowed(state_dir_ref, motion_timestamp);
+            if notify {
+                info!("Sending the motion notification with timestamp.");
+                let notification_msg =
+                    clients_com[FCM].encrypt(&fcm_message::encode_timestamp(motion_timestamp))?;
+                // The group state is saved again after the next message. Don't stop recording over it.
+                if let Err(e) = clients_com[FCM].save_group_state() {
+                    error!("Failed to save the FCM group state ({e})");
+                }
+                match send_notification(state_dir_ref, &http_client, notification_msg) {
+                    Ok(_) => metrics::record_server_contact(&camera_name),
+                    Err(e) => {
+                        error!("Failed to send motion notification ({})", e);
+                    }
                 }
+            } else {
+                info!("Motion notifications are disarmed by the schedule, not sending any.");
             }
 
             info!("Starting to record, prepare, and encrypt video.");
-            let duration = 20;
+            camera.record_motion_video(
+                &video_info,
+                motion_settings.record_secs,
+                motion_settings.preroll_secs,
+            )?;
+            let video_file_path = delivery_monitor.get_video_file_path(&video_info);
+
+            // Motion detection didn't give us a frame for the thumbnail (e.g., the camera reported
+            // the motion over ONVIF). Generate one on the hub so the app's gallery isn't blank.
+            // It's the frame at the motion (after the pre-roll) of the recorded video. We do this
+            // before preparing the video, which moves it away if it's corrupted.
+            let hub_thumbnail = (!had_thumbnail && camera.hub_thumbnails()).then(|| {
+                video_thumbnail(&video_file_path, motion_settings.preroll_secs).or_else(|e| {
+                    error!("Failed to extract the thumbnail from the motion video ({e}), using the latest frame instead");
+                    camera.capture_still().and_then(|jpeg| scaled_thumbnail(&jpeg))
+                })
+            });
 
-            camera.record_motion_video(&video_info, duration)?;
             prepare_motion_video(&mut clients_com[MOTION], video_info, &mut delivery_monitor)?;
 
+            match hub_thumbnail {
+                Some(Ok(thumbnail_image)) => send_motion_thumbnail(
+                    &mut clients_com,
+                    camera,
+                    &mut delivery_monitor,
+                    &http_client,
+                    num_apps,
+                    ThumbnailMetaInfo::new(motion_timestamp, 0, vec![]), //0 epoch = unset
+                    thumbnail_image,
+                )?,
+                Some(Err(e)) => error!("Failed to generate a thumbnail for the motion video: {e}"),
+                None => {}
+            }
+
             info!("Uploading the encrypted video.");
             let _ = upload_pending_enc_videos(
                 &clients_com[MOTION].get_group_name().unwrap(),
@@ -529,76 +852,92 @@ fn core(
                 num_apps,
             );
 
-            let state_dir_ref = state_dir.as_str();
-            let target =
-                notification_target::refresh_notification_target(state_dir_ref, &http_client);
-            let platform_label = target
-                .as_ref()
-                .map(|target| target.platform.as_str())
-                .unwrap_or("fcm");
-            info!(
-                "Sending the post-upload notification to start downloading over {}.",
-                platform_label
-            );
-            let notification_timestamp: u64 = 0;
-            let notification_msg = clients_com[FCM]
-                .encrypt(&bincode::serialize(&notification_timestamp).unwrap())?;
-            clients_com[FCM].save_group_state().unwrap();
-            match send_notification(state_dir_ref, &http_client, notification_msg) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("Failed to send motion notification ({})", e);
+            // Disarmed: the app picks up the video the next time it checks for new ones.
+            if notify {
+                let state_dir_ref = state_dir.as_str();
+                let target =
+                    notification_target::refresh_notification_target(state_dir_ref, &http_client);
+                let platform_label = target
+                    .as_ref()
+                    .map(|target| target.platform.as_str())
+                    .unwrap_or("fcm");
+                info!(
+                    "Sending the post-upload notification to start downloading over {}.",
+                    platform_label
+                );
+                let notification_timestamp: u64 = 0;
+                let notification_msg = clients_com[FCM]
+                    .encrypt(&fcm_message::encode_timestamp(notification_timestamp))?;
+                // The group state is saved again after the next message. Don't stop recording over it.
+                if let Err(e) = clients_com[FCM].save_group_state() {
+                    error!("Failed to save the FCM group state ({e})");
+                }
+                match send_notification(state_dir_ref, &http_client, notification_msg) {
+                    Ok(_) => metrics::record_server_contact(&camera_name),
+                    Err(e) => {
+                        error!("Failed to send motion notification ({})", e);
+                    }
                 }
             }
 
-            locked_motion_check_time = Some(Instant::now().add(Duration::from_secs(60)));
+            locked_motion_check_time = Some(motion_settings.lock_until(Instant::now()));
         }
 
-        // Check for livestream requests frequently so the app doesn't sit on
-        // "starting livestream" for an extra second just waiting for the next poll.
-        if locked_livestream_check_time.is_none()
-            || locked_livestream_check_time.unwrap().le(&Instant::now())
+        // Warn the user when the camera's stream stays down (e.g., the camera lost power),
+        // and tell them once it's back.
+        let stream_failures = camera.stream_failures();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if let Some(status) = camera_status.status_change(stream_failures, now) {
+            warn!("[{}] Camera status changed: {:?}", camera_name, status);
+            match send_camera_status(&mut clients_com, &state_dir, &http_client, status) {
+                Ok(()) => camera_status.notification_sent(),
+                Err(e) => error!("Failed to send camera status notification ({})", e),
+            }
+        }
+
+        // Livestream requests and config commands wake us up (see the poller threads above),
+        // so we check for them on every iteration.
         {
             // Livestream request? Start it.
             let mut check = livestream_request.lock().unwrap();
-            let primary_app = check.1;
-            if check.0 {
+            let (requested, app, chunk_format) = *check;
+            if requested {
                 info!("Livestream start detected");
-                *check = (false, false);
-                if primary_app {
+                *check = (false, 0, LIVESTREAM_CHUNK_FORMAT_LEGACY);
+                if app == 0 {
                     livestream(
                         &mut clients_ded_primary[LIVESTREAM_DED],
                         camera,
                         &mut delivery_monitor,
                         &http_client,
+                        &livestream_limits,
+                        chunk_format,
                     )?;
                 } else {
-                    let mut clients_ded_sec_opt = clients_ded_secondary.lock().unwrap();
-                    if let Some(ref mut clients_ded_sec) = *clients_ded_sec_opt { // Should always be the case if we get here
+                    let mut clients_ded_sec = clients_ded_secondary.lock().unwrap();
+                    if let Some(clients_ded) = clients_ded_sec.get_mut(app - 1) { // Should always be the case if we get here
                         livestream(
-                            &mut clients_ded_sec[LIVESTREAM_DED],
+                            &mut clients_ded[LIVESTREAM_DED],
                             camera,
-                            // FIXME: delivery_monitor should use a separate queue for app2
+                            // FIXME: delivery_monitor should use a separate queue for each app
                             &mut delivery_monitor,
                             &http_client,
+                            &livestream_limits,
+                            chunk_format,
                         )?;
                     }
                 }
             }
-
-            locked_livestream_check_time = Some(Instant::now().add(Duration::from_millis(100)));
         }
 
         // Check with the delivery monitor every minute
         if locked_delivery_check_time.is_none()
             || locked_delivery_check_time.unwrap().le(&Instant::now())
         {
-            let clients_ded_sec_opt = clients_ded_secondary.lock().unwrap();
-            let num_apps = if clients_ded_sec_opt.is_some() {
-                2
-            } else {
-                1
-            };
+            let num_apps = 1 + clients_ded_secondary.lock().unwrap().len() as u32;
 
             if upload_pending_enc_videos(
                 &clients_com[MOTION].get_group_name().unwrap(),
@@ -630,88 +969,104 @@ fn core(
                 //let _ = send_pending_thumbnails(camera, &mut clients, &mut delivery_monitor, &http_client);
             }
 
+            let stats = delivery_monitor.statistics();
+            info!(
+                "Delivery check: {} video(s) tracked, {} awaiting resend, {} unacknowledged (oldest: {}, {}s ago), {} upload retries",
+                stats.tracked,
+                stats.awaiting_resend,
+                stats.pending,
+                stats
+                    .oldest_pending_timestamp
+                    .map_or("none".to_string(), |timestamp| timestamp.to_string()),
+                stats.max_pending_age_secs,
+                stats.upload_retries
+            );
+            metrics::record_delivery_statistics(&camera_name, stats);
+            for client in clients_com.iter().chain(clients_ded_primary.iter()) {
+                if let (Ok(group_name), Ok(epoch)) = (client.get_group_name(), client.get_epoch()) {
+                    metrics::record_epoch(&camera_name, &group_name, epoch);
+                }
+            }
+
             locked_delivery_check_time = Some(Instant::now().add(Duration::from_secs(60)));
         }
 
-        // Check for config commands every second
-        if locked_config_check_time.is_none()
-            || locked_config_check_time.unwrap().le(&Instant::now())
+        // Process config commands
         {
             let mut enc_commands = config_enc_commands.lock().unwrap();
-            for enc_command in &*enc_commands {
-                let primary_app = enc_command.1;
+            for (enc_command, app) in &*enc_commands {
+                let mut clients_ded_sec = clients_ded_secondary.lock().unwrap();
+                let num_apps = 1 + clients_ded_sec.len() as u32;
 
-                if primary_app {
+                if *app == 0 {
                     println!("About to call process_config_command for primary app");
-                    let mut clients_ded_sec_opt = clients_ded_secondary.lock().unwrap();
                     let process_ret = process_config_command(
                         &mut clients_com,
                         &mut clients_ded_primary,
-                        &enc_command.0,
+                        enc_command,
                         &http_client,
-                        // TODO: We only keep track of video delivery to the primary app for now.
-                        Some(&mut delivery_monitor),
+                        camera,
+                        &mut delivery_monitor,
                         true,
-                        clients_ded_sec_opt.is_some(),
+                        num_apps,
                     )?;
 
-                    if clients_ded_sec_opt.is_none() {
-                        *clients_ded_sec_opt = process_ret;
-
-                        if let Some(ref clients_ded_sec) = *clients_ded_sec_opt {
-                            println!("Launching threads for the second app.");
-                            let group_livestream2_name_clone = clients_ded_sec[LIVESTREAM_DED].get_group_name()?;
-                            let livestream_request_clone_2 = Arc::clone(&livestream_request);
-                            let http_client_clone_3 = http_client.clone();
-                            let group_config2_name_clone = clients_ded_sec[CONFIG_DED].get_group_name()?;
-                            let http_client_clone_4 = http_client.clone();
-                            let config_enc_commands_clone_2 = Arc::clone(&config_enc_commands);
-
-                            thread::spawn(move || loop {
-                                if http_client_clone_3
-                                    .livestream_check(&group_livestream2_name_clone)
-                                    .is_ok()
-                                {
-                                    println!("Livestream2 detected");
-                                    let mut check = livestream_request_clone_2.lock().unwrap();
-                                    *check = (true, false); // false -> livestream command from the secondary app
-                                } else {
-                                    sleep(Duration::from_secs(1));
-                                }
-                            });
-
-                            thread::spawn(move || loop {
-                                if let Ok(enc_command) = http_client_clone_4.config_check(&group_config2_name_clone) {
-                                    let mut config_enc_commands = config_enc_commands_clone_2.lock().unwrap();
-                                    config_enc_commands.push((enc_command, false)); // false -> config command from the secondary app
-                                } else {
-                                    error!("Error in receiving config command");
-                                    sleep(Duration::from_secs(1));
-                                }
-                            });
-                        }
+                    if let Some(clients_ded) = process_ret {
+                        println!("Launching threads for app {}.", num_apps + 1);
+                        spawn_app_pollers(
+                            num_apps as usize,
+                            &clients_ded,
+                            &camera_name,
+                            &http_client,
+                            &retry_policy,
+                            &livestream_request,
+                            &config_enc_commands,
+                            &wakeup,
+                        )?;
+                        clients_ded_sec.push(clients_ded);
                     }
                 } else {
-                    println!("About to call process_config_command for secondary app");
-                    let mut clients_ded_sec_opt = clients_ded_secondary.lock().unwrap();
-                    if let Some(ref mut clients_ded_sec) = *clients_ded_sec_opt {
+                    println!("About to call process_config_command for app {}", app + 1);
+                    if let Some(clients_ded) = clients_ded_sec.get_mut(app - 1) {
                         let _ = process_config_command(
                             &mut clients_com,
-                            clients_ded_sec, // Will not be None if we get here
-                            &enc_command.0,
+                            clients_ded,
+                            enc_command,
                             &http_client,
-                            None,
+                            camera,
+                            &mut delivery_monitor,
                             false,
-                            true,
+                            num_apps,
                         )?;
                     }
                 }
             }
             enc_commands.clear();
-            locked_config_check_time = Some(Instant::now().add(Duration::from_secs(1)));
         }
 
-        // Introduce a small delay since we don't need this constantly checked
-        sleep(Duration::from_millis(100));
+        // Compact the MLS storage once a week
+        if locked_compaction_check_time.is_none()
+            || locked_compaction_check_time.unwrap().le(&Instant::now())
+        {
+            let mut clients_ded_sec = clients_ded_secondary.lock().unwrap();
+            let clients = clients_com
+                .iter_mut()
+                .chain(clients_ded_primary.iter_mut())
+                .chain(clients_ded_sec.iter_mut().flat_map(|c| c.iter_mut()));
+            for client in clients {
+                match client.compact_storage() {
+                    Ok(reclaimed) => {
+                        client.save_group_state()?;
+                        debug!("Compacted MLS storage ({} bytes reclaimed)", reclaimed);
+                    }
+                    Err(e) => {
+                        error!("Failed to compact MLS storage: {e}");
+                    }
+                }
+            }
+
+            locked_compaction_check_time =
+                Some(Instant::now().add(Duration::from_secs(7 * 24 * 60 * 60)));
+        }
     }
 }
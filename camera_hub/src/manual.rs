@@ -359,7 +359,12 @@ impl Camera for ManualCamera {
         }
     }
 
-    fn record_motion_video(&self, info: &VideoInfo, _duration: u64) -> io::Result<()> {
+    fn record_motion_video(
+        &self,
+        info: &VideoInfo,
+        _duration: u64,
+        _preroll_secs: u64,
+    ) -> io::Result<()> {
         let pending_motion = self.pending_motion.lock().unwrap().take();
         let Some(pending_motion) = pending_motion else {
             return Err(io::Error::other(
@@ -0,0 +1,323 @@
+//! Optional Prometheus-style metrics endpoint, for operators running several hubs.
+//! Enabled with metrics_port in cameras.yaml (IP cameras only) and bound to localhost.
+//! Nothing secret is exposed: MLS group names are hashed.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::delivery_monitor::DeliveryStatistics;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "ip")]
+use std::fs;
+
+const METRICS_IO_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_LINE_LEN: u64 = 8192;
+
+#[derive(Default)]
+struct CameraMetrics {
+    motion_events: u64,
+    delivery: Option<DeliveryStatistics>,
+    last_server_contact: Option<u64>,
+    // Hashed group name -> epoch.
+    epochs: BTreeMap<String, u64>,
+}
+
+// Keyed by camera name. Each camera's core loop updates its own entry.
+static METRICS: Mutex<BTreeMap<String, CameraMetrics>> = Mutex::new(BTreeMap::new());
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// The optional metrics_port field of cameras.yaml.
+#[cfg(feature = "ip")]
+#[derive(Debug, Deserialize)]
+struct Config {
+    metrics_port: Option<u16>,
+}
+
+fn update(camera: &str, f: impl FnOnce(&mut CameraMetrics)) {
+    let mut metrics = METRICS.lock().unwrap();
+    f(metrics.entry(camera.to_string()).or_default());
+}
+
+pub fn record_motion_event(camera: &str) {
+    update(camera, |m| m.motion_events += 1);
+}
+
+pub fn record_delivery_statistics(camera: &str, statistics: DeliveryStatistics) {
+    update(camera, |m| m.delivery = Some(statistics));
+}
+
+pub fn record_server_contact(camera: &str) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    update(camera, |m| m.last_server_contact = Some(now));
+}
+
+pub fn record_epoch(camera: &str, group_name: &str, epoch: u64) {
+    let group = hash_group_name(group_name);
+    update(camera, |m| {
+        m.epochs.insert(group, epoch);
+    });
+}
+
+// Group names are what the server uses to address the camera's messages.
+// They're random, so a short hash is enough to tell the groups apart without revealing them.
+// FNV-1a rather than DefaultHasher, whose output may change between Rust releases and would
+// relabel the series after an upgrade.
+fn hash_group_name(group_name: &str) -> String {
+    let hash = group_name
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders all metrics in the Prometheus text exposition format.
+fn render() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut out = String::new();
+
+    let uptime = STARTED.get().map(|t| t.elapsed().as_secs()).unwrap_or(0);
+    let _ = writeln!(
+        out,
+        "# HELP secluso_hub_uptime_seconds Time since the hub started."
+    );
+    let _ = writeln!(out, "# TYPE secluso_hub_uptime_seconds gauge");
+    let _ = writeln!(out, "secluso_hub_uptime_seconds {uptime}");
+
+    let mut family =
+        |name: &str, kind: &str, help: &str, value: &dyn Fn(&CameraMetrics) -> Option<u64>| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (camera, m) in metrics.iter() {
+                if let Some(v) = value(m) {
+                    let _ = writeln!(out, "{name}{{camera=\"{}\"}} {v}", escape_label(camera));
+                }
+            }
+        };
+
+    family(
+        "secluso_hub_motion_events_total",
+        "counter",
+        "Motion events since the hub started.",
+        &|m| Some(m.motion_events),
+    );
+    family(
+        "secluso_hub_videos_pending",
+        "gauge",
+        "Videos not yet acknowledged by the app.",
+        &|m| m.delivery.as_ref().map(|d| d.pending as u64),
+    );
+    family(
+        "secluso_hub_videos_awaiting_resend",
+        "gauge",
+        "Videos whose upload failed or hasn't happened yet.",
+        &|m| m.delivery.as_ref().map(|d| d.awaiting_resend as u64),
+    );
+    family(
+        "secluso_hub_videos_sent_total",
+        "counter",
+        "Videos uploaded to the server since the hub started.",
+        &|m| m.delivery.as_ref().map(|d| d.total_sent),
+    );
+    family(
+        "secluso_hub_videos_acked_total",
+        "counter",
+        "Videos acknowledged by the app since the hub started.",
+        &|m| m.delivery.as_ref().map(|d| d.total_acked),
+    );
+    family(
+        "secluso_hub_video_upload_retries_total",
+        "counter",
+        "Video uploads that failed and had to be retried since the hub started.",
+        &|m| m.delivery.as_ref().map(|d| d.upload_retries as u64),
+    );
+    family(
+        "secluso_hub_last_server_contact_timestamp_seconds",
+        "gauge",
+        "Unix time of the last successful request to the server.",
+        &|m| m.last_server_contact,
+    );
+
+    let name = "secluso_hub_mls_epoch";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Current MLS epoch of each group (group names are hashed)."
+    );
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    for (camera, m) in metrics.iter() {
+        for (group, epoch) in &m.epochs {
+            let _ = writeln!(
+                out,
+                "{name}{{camera=\"{}\",group=\"{group}\"}} {epoch}",
+                escape_label(camera)
+            );
+        }
+    }
+
+    out
+}
+
+fn handle_connection(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(METRICS_IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(METRICS_IO_TIMEOUT))?;
+
+    // Only the request line matters. The rest of the request is ignored.
+    let mut request_line = String::new();
+    BufReader::new(stream.try_clone()?.take(MAX_REQUEST_LINE_LEN)).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        (Some("GET"), Some(_)) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method not allowed\n".to_string()),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// Starts the metrics listener on localhost in a background thread and returns the bound port
+/// (port 0 picks an ephemeral one).
+pub fn start_metrics_listener(port: u16) -> io::Result<u16> {
+    STARTED.get_or_init(Instant::now);
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let port = listener.local_addr()?.port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle_connection(stream) {
+                        debug!("Metrics request failed: {e}");
+                    }
+                }
+                Err(e) => debug!("Failed to accept metrics connection: {e}"),
+            }
+        }
+    });
+
+    Ok(port)
+}
+
+/// Reads metrics_port from cameras.yaml (IP cameras only).
+fn load_metrics_port() -> Option<u16> {
+    #[cfg(feature = "ip")]
+    if let Ok(content) = fs::read_to_string("cameras.yaml") {
+        match serde_yaml2::from_str::<Config>(&content) {
+            Ok(cfg) => return cfg.metrics_port,
+            Err(e) => error!("Failed to parse metrics_port in cameras.yaml ({e})"),
+        }
+    }
+
+    None
+}
+
+/// Starts the metrics listener if metrics_port is set in cameras.yaml. Disabled by default.
+pub fn start_from_config() {
+    if let Some(port) = load_metrics_port() {
+        match start_metrics_listener(port) {
+            Ok(port) => println!("Serving metrics on http://127.0.0.1:{port}/metrics"),
+            Err(e) => error!("Failed to start the metrics listener on port {port} ({e})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrape(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn group_names_hash_stably() {
+        assert_eq!(hash_group_name(""), "cbf29ce484222325");
+        assert_eq!(hash_group_name("group-a"), "011a377707fb5e36");
+        assert_ne!(hash_group_name("group-a"), hash_group_name("group-b"));
+    }
+
+    #[test]
+    fn exporter_serves_all_metric_families() {
+        let camera = "metrics-test-camera";
+        record_motion_event(camera);
+        record_motion_event(camera);
+        record_server_contact(camera);
+        record_epoch(camera, "group-a", 7);
+        record_delivery_statistics(
+            camera,
+            DeliveryStatistics {
+                tracked: 3,
+                pending: 3,
+                awaiting_resend: 1,
+                oldest_pending_timestamp: Some(1),
+                upload_retries: 4,
+                max_pending_age_secs: 0,
+                total_sent: 5,
+                total_acked: 2,
+            },
+        );
+
+        let port = start_metrics_listener(0).unwrap();
+        assert_ne!(port, 0);
+
+        let response = scrape(port, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        for family in [
+            "secluso_hub_uptime_seconds",
+            "secluso_hub_motion_events_total",
+            "secluso_hub_videos_pending",
+            "secluso_hub_videos_awaiting_resend",
+            "secluso_hub_videos_sent_total",
+            "secluso_hub_videos_acked_total",
+            "secluso_hub_video_upload_retries_total",
+            "secluso_hub_last_server_contact_timestamp_seconds",
+            "secluso_hub_mls_epoch",
+        ] {
+            assert!(
+                response.contains(&format!("# TYPE {family} ")),
+                "missing {family}:\n{response}"
+            );
+        }
+
+        let label = format!("{{camera=\"{camera}\"}}");
+        assert!(response.contains(&format!("secluso_hub_motion_events_total{label} 2")));
+        assert!(response.contains(&format!("secluso_hub_videos_pending{label} 3")));
+        assert!(response.contains(&format!("secluso_hub_videos_awaiting_resend{label} 1")));
+        assert!(response.contains(&format!("secluso_hub_videos_sent_total{label} 5")));
+        assert!(response.contains(&format!("secluso_hub_videos_acked_total{label} 2")));
+        assert!(response.contains(&format!("secluso_hub_video_upload_retries_total{label} 4")));
+        assert!(response.contains(&format!(
+            "secluso_hub_mls_epoch{{camera=\"{camera}\",group=\"{}\"}} 7",
+            hash_group_name("group-a")
+        )));
+        assert!(!response.contains("group-a"));
+
+        assert!(scrape(port, "/other").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
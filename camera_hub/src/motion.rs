@@ -4,12 +4,22 @@
 
 use crate::delivery_monitor::{DeliveryMonitor, VideoInfo};
 use image::RgbImage;
+use secluso_client_lib::fcm_message;
 use secluso_client_lib::http_client::HttpClient;
 use secluso_client_lib::mls_client::MlsClient;
 use secluso_client_lib::mls_clients::{MAX_OFFLINE_WINDOW};
 use secluso_client_lib::thumbnail_meta_info::{GeneralDetectionType, ThumbnailMetaInfo};
-use secluso_client_lib::video::{encrypt_thumbnail_file, encrypt_video_file};
+use secluso_client_lib::video::{encrypt_thumbnail_file, encrypt_video_file, validate_mp4_file};
+use std::fs;
 use std::io;
+use std::path::Path;
+use std::process::Command;
+
+// Subdirectory of the video dir where we move recordings that fail validation.
+const QUARANTINE_DIR: &str = "quarantine";
+
+// Width of the thumbnails generated on the hub (see scaled_thumbnail).
+const HUB_THUMBNAIL_WIDTH: u32 = 320;
 
 // Used to contain data returned from motion detection from IP + Raspberry cameras
 pub struct MotionResult {
@@ -75,6 +85,7 @@ pub fn upload_pending_enc_videos(
                     "Could not upload video {} ({}). Will try again later.",
                     video_info.timestamp, e
                 );
+                delivery_monitor.record_upload_retry();
                 return Err(e);
             }
         }
@@ -83,6 +94,38 @@ pub fn upload_pending_enc_videos(
     Ok(())
 }
 
+/// Decodes a frame from the camera (e.g., a JPEG still) and scales it down to a thumbnail.
+pub fn scaled_thumbnail(frame: &[u8]) -> io::Result<RgbImage> {
+    let image = image::load_from_memory(frame)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if image.width() <= HUB_THUMBNAIL_WIDTH {
+        return Ok(image.to_rgb8());
+    }
+
+    let height = (u64::from(image.height()) * u64::from(HUB_THUMBNAIL_WIDTH)
+        / u64::from(image.width()))
+    .max(1) as u32;
+    Ok(image.thumbnail_exact(HUB_THUMBNAIL_WIDTH, height).to_rgb8())
+}
+
+/// Extracts the frame at offset_secs of a recorded motion video (with ffmpeg) as a thumbnail.
+pub fn video_thumbnail(video_file_path: &Path, offset_secs: u64) -> io::Result<RgbImage> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-ss", &offset_secs.to_string(), "-i"])
+        .arg(video_file_path)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "mjpeg", "-"])
+        .output()?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(io::Error::other(format!(
+            "ffmpeg didn't extract a frame from {} ({})",
+            video_file_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    scaled_thumbnail(&output.stdout)
+}
+
 // TODO: for motion videos, we have VideoInfo used by the delivery monitor and
 // VideoNetInfo encrypted with the video. For Thumbnail, we only have one, ThumbnailMetaInfo.
 // Make them consistent.
@@ -140,9 +183,18 @@ pub fn prepare_motion_video(
         //return Ok(());
     }
 
+    let video_file_path = delivery_monitor.get_video_file_path(&video_info);
+
+    // Don't waste an MLS update (and server storage) on a recording the app won't be able to play.
+    if let Err(e) = validate_mp4_file(video_file_path.to_str().expect("Path is not valid UTF-8")) {
+        error!("Video {} is not a valid MP4 file ({})", video_info.timestamp, e);
+        quarantine_video_file(&video_file_path)?;
+        // We return Ok(()) since we want the core() in main.rs to continue.
+        return Ok(());
+    }
+
     // encrypt_video_file() performs an update, which increases the epoch by 1.
     video_info.epoch = mls_client.get_epoch()? + 1;
-    let video_file_path = delivery_monitor.get_video_file_path(&video_info);
     let enc_video_file_path = delivery_monitor.get_enc_video_file_path(&video_info);
 
     let epoch = encrypt_video_file(
@@ -169,6 +221,18 @@ pub fn prepare_motion_video(
     Ok(())
 }
 
+fn quarantine_video_file(video_file_path: &Path) -> io::Result<()> {
+    let video_dir = video_file_path.parent().unwrap_or(Path::new("."));
+    let quarantine_dir = video_dir.join(QUARANTINE_DIR);
+    fs::create_dir_all(&quarantine_dir)?;
+
+    let quarantine_path = quarantine_dir.join(video_file_path.file_name().unwrap_or_default());
+    fs::rename(video_file_path, &quarantine_path)?;
+    error!("Moved the video to {}", quarantine_path.display());
+
+    Ok(())
+}
+
 // TODO: Keeping these two functions here since we might need them.
 /*
 pub fn send_pending_motion_videos(
@@ -230,8 +294,8 @@ pub fn send_pending_motion_videos(
         //Timestamp of 0 tells the app it's time to start downloading.
         let dummy_timestamp: u64 = 0;
         let notification_msg =
-            clients_com[FCM].encrypt(&bincode::serialize(&dummy_timestamp).unwrap())?;
-        clients_com[FCM].save_group_state().unwrap();
+            clients_com[FCM].encrypt(&fcm_message::encode_timestamp(dummy_timestamp))?;
+        clients_com[FCM].save_group_state()?;
         send_notification(&camera.get_state_dir(), http_client, notification_msg)?;
     }
 
@@ -304,11 +368,157 @@ pub fn send_pending_thumbnails(
         //Timestamp of 0 tells the app it's time to start downloading.
         let dummy_timestamp: u64 = 0;
         let notification_msg =
-            clients_com[FCM].encrypt(&bincode::serialize(&dummy_timestamp).unwrap())?;
-        clients_com[FCM].save_group_state().unwrap();
+            clients_com[FCM].encrypt(&fcm_message::encode_timestamp(dummy_timestamp))?;
+        clients_com[FCM].save_group_state()?;
         send_notification(&camera.get_state_dir(), http_client, notification_msg)?;
     }
 
     Ok(())
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb};
+    use secluso_client_lib::mls_client::ClientType;
+    use secluso_client_lib::pairing::NUM_SECRET_BYTES;
+    use secluso_client_lib::thumbnail_meta_info::{Detection, ThumbnailMetaFile};
+    use secluso_client_lib::video::decrypt_thumbnail_file;
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("secluso-motion-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for subdir in [
+            "camera",
+            "app/videos",
+            "videos",
+            "thumbnails",
+            "state",
+            "pending_meta",
+        ] {
+            fs::create_dir_all(dir.join(subdir)).unwrap();
+        }
+        dir
+    }
+
+    fn dir_string(dir: &Path, subdir: &str) -> String {
+        dir.join(subdir).to_str().unwrap().to_string()
+    }
+
+    // Same as the pairing of the camera hub with the app, without the network.
+    fn pair(dir: &Path) -> (MlsClient, MlsClient) {
+        let secret = vec![0u8; NUM_SECRET_BYTES];
+        let mut camera = MlsClient::new(
+            "camera".to_string(),
+            true,
+            dir_string(dir, "camera"),
+            "thumbnail".to_string(),
+            ClientType::Camera,
+        )
+        .unwrap();
+        let mut app = MlsClient::new(
+            "app".to_string(),
+            true,
+            dir_string(dir, "app"),
+            "thumbnail".to_string(),
+            ClientType::App,
+        )
+        .unwrap();
+
+        let camera_contact = MlsClient::create_contact("app", app.key_package()).unwrap();
+        let app_contact = MlsClient::create_contact("camera", camera.key_package()).unwrap();
+        camera.create_group("thumbnail_group").unwrap();
+        let (welcome_msg_vec, _, _) = camera
+            .invite_with_secret(&camera_contact, secret.clone())
+            .unwrap();
+        camera.save_group_state().unwrap();
+        app.process_welcome_with_secret(app_contact, welcome_msg_vec, secret, "thumbnail_group")
+            .unwrap();
+        app.save_group_state().unwrap();
+
+        (camera, app)
+    }
+
+    #[test]
+    fn hub_thumbnail_round_trip() {
+        let dir = test_dir("thumbnail");
+        let (mut camera, mut app) = pair(&dir);
+
+        // A frame from the camera (JPEG), scaled down on the hub.
+        let frame = RgbImage::from_fn(640, 480, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        });
+        let mut jpeg = io::Cursor::new(vec![]);
+        frame.write_to(&mut jpeg, ImageFormat::Jpeg).unwrap();
+        let thumbnail = scaled_thumbnail(jpeg.get_ref()).unwrap();
+        assert_eq!(thumbnail.dimensions(), (HUB_THUMBNAIL_WIDTH, 240));
+
+        let mut delivery_monitor = DeliveryMonitor::from_file_or_new(
+            dir_string(&dir, "videos"),
+            dir_string(&dir, "thumbnails"),
+            dir_string(&dir, "state"),
+        );
+        let detections = vec![Detection::from(&GeneralDetectionType::Human)];
+        let thumbnail_info = ThumbnailMetaInfo::new(1_700_000_000, 0, detections.clone());
+        thumbnail
+            .save(delivery_monitor.get_thumbnail_file_path(&thumbnail_info))
+            .unwrap();
+
+        let epoch = camera.get_epoch().unwrap() + 1;
+        prepare_motion_thumbnail(&mut camera, thumbnail_info, &mut delivery_monitor).unwrap();
+
+        // The app gets the same thumbnail and its detections.
+        let enc_thumbnail_file_path = dir.join("thumbnails").join(epoch.to_string());
+        let dec_filename = decrypt_thumbnail_file(
+            &mut app,
+            enc_thumbnail_file_path.to_str().unwrap(),
+            &dir_string(&dir, "pending_meta"),
+        )
+        .unwrap();
+        let decrypted = image::open(dir.join("app/videos").join(dec_filename))
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(decrypted, thumbnail);
+
+        let meta_file =
+            ThumbnailMetaFile::load(&dir.join("pending_meta/meta_1700000000.txt")).unwrap();
+        assert_eq!(meta_file.detections, detections);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupt_video_is_quarantined_not_uploaded() {
+        let dir = test_dir("quarantine");
+        let (mut camera, _app) = pair(&dir);
+        let mut delivery_monitor = DeliveryMonitor::from_file_or_new(
+            dir_string(&dir, "videos"),
+            dir_string(&dir, "thumbnails"),
+            dir_string(&dir, "state"),
+        );
+
+        // E.g., the recording was cut short when ffmpeg crashed.
+        let video_info = VideoInfo::from(1_700_000_000);
+        let video_file_path = delivery_monitor.get_video_file_path(&video_info);
+        fs::write(&video_file_path, b"\0\0\0\x18ftypmp42 truncated").unwrap();
+
+        let epoch = camera.get_epoch().unwrap();
+        prepare_motion_video(&mut camera, video_info, &mut delivery_monitor).unwrap();
+
+        assert!(!video_file_path.exists());
+        assert!(dir
+            .join("videos")
+            .join(QUARANTINE_DIR)
+            .join("video_1700000000.mp4")
+            .exists());
+        // No MLS update was spent on it and nothing is queued for upload.
+        assert_eq!(camera.get_epoch().unwrap(), epoch);
+        assert!(delivery_monitor.videos_to_send().is_empty());
+        assert_eq!(delivery_monitor.statistics().pending, 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,148 @@
+//! Per-camera motion settings (anti-dither cooldown, recording duration, and pre-roll).
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::io;
+use std::ops::Add;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MOTION_COOLDOWN_SECS: u64 = 60;
+pub const DEFAULT_RECORD_SECS: u64 = 20;
+pub const DEFAULT_PREROLL_SECS: u64 = 3;
+
+// Allowed range for the cooldown and the recording duration.
+const MIN_SECS: u64 = 5;
+const MAX_SECS: u64 = 600;
+
+// Allowed range for the pre-roll. The frames are kept in memory, so we keep it short.
+const MAX_PREROLL_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MotionSettings {
+    /// We don't send a new motion event until this much time has passed since the last one.
+    pub cooldown: Duration,
+    /// Length of the video recorded for each motion event.
+    pub record_secs: u64,
+    /// Seconds of video from before the trigger included at the start of each motion video.
+    pub preroll_secs: u64,
+}
+
+impl Default for MotionSettings {
+    fn default() -> Self {
+        Self {
+            cooldown: Duration::from_secs(DEFAULT_MOTION_COOLDOWN_SECS),
+            record_secs: DEFAULT_RECORD_SECS,
+            preroll_secs: DEFAULT_PREROLL_SECS,
+        }
+    }
+}
+
+impl MotionSettings {
+    /// Builds the settings from the (optional) cameras.yaml values and validates their ranges.
+    pub fn new(
+        camera_name: &str,
+        motion_cooldown_secs: Option<u64>,
+        record_secs: Option<u64>,
+        preroll_secs: Option<u64>,
+    ) -> io::Result<Self> {
+        let motion_cooldown_secs = motion_cooldown_secs.unwrap_or(DEFAULT_MOTION_COOLDOWN_SECS);
+        let record_secs = record_secs.unwrap_or(DEFAULT_RECORD_SECS);
+        let preroll_secs = preroll_secs.unwrap_or(DEFAULT_PREROLL_SECS);
+
+        for (field, value) in [
+            ("motion_cooldown_secs", motion_cooldown_secs),
+            ("record_secs", record_secs),
+        ] {
+            if !(MIN_SECS..=MAX_SECS).contains(&value) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid {field} ({value}) for camera {camera_name:?}: must be between {MIN_SECS} and {MAX_SECS} seconds"
+                    ),
+                ));
+            }
+        }
+
+        if preroll_secs > MAX_PREROLL_SECS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid preroll_secs ({preroll_secs}) for camera {camera_name:?}: must be at most {MAX_PREROLL_SECS} seconds"
+                ),
+            ));
+        }
+
+        Ok(Self {
+            cooldown: Duration::from_secs(motion_cooldown_secs),
+            record_secs,
+            preroll_secs,
+        })
+    }
+
+    /// Whether a motion event can be sent now, given when the last cooldown ends.
+    pub fn motion_allowed(locked_until: Option<Instant>, now: Instant) -> bool {
+        match locked_until {
+            None => true,
+            Some(locked_until) => locked_until.le(&now),
+        }
+    }
+
+    /// When the cooldown started by a motion event at `now` ends.
+    pub fn lock_until(&self, now: Instant) -> Instant {
+        now.add(self.cooldown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MotionSettings, DEFAULT_MOTION_COOLDOWN_SECS, DEFAULT_PREROLL_SECS, DEFAULT_RECORD_SECS};
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn missing_values_use_the_defaults() {
+        let settings = MotionSettings::new("Front Door", None, None, None).unwrap();
+        assert_eq!(settings.cooldown, Duration::from_secs(DEFAULT_MOTION_COOLDOWN_SECS));
+        assert_eq!(settings.record_secs, DEFAULT_RECORD_SECS);
+        assert_eq!(settings.preroll_secs, DEFAULT_PREROLL_SECS);
+    }
+
+    #[test]
+    fn values_are_checked_against_their_ranges() {
+        let settings = MotionSettings::new("Front Door", Some(5), Some(600), Some(0)).unwrap();
+        assert_eq!(settings.cooldown, Duration::from_secs(5));
+        assert_eq!(settings.record_secs, 600);
+        assert_eq!(settings.preroll_secs, 0);
+
+        for (cooldown, record, preroll, field) in [
+            (Some(4), None, None, "motion_cooldown_secs"),
+            (Some(601), None, None, "motion_cooldown_secs"),
+            (None, Some(0), None, "record_secs"),
+            (None, Some(601), None, "record_secs"),
+            (None, None, Some(11), "preroll_secs"),
+        ] {
+            let err = MotionSettings::new("Front Door", cooldown, record, preroll).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains(field), "{err}");
+            assert!(err.to_string().contains("Front Door"), "{err}");
+        }
+    }
+
+    #[test]
+    fn cooldown_blocks_motion_until_it_ends() {
+        let settings = MotionSettings::new("Front Door", Some(30), None, None).unwrap();
+        let now = Instant::now();
+        assert!(MotionSettings::motion_allowed(None, now));
+
+        let locked_until = settings.lock_until(now);
+        assert!(!MotionSettings::motion_allowed(Some(locked_until), now));
+        assert!(!MotionSettings::motion_allowed(
+            Some(locked_until),
+            now + Duration::from_secs(29)
+        ));
+        assert!(MotionSettings::motion_allowed(
+            Some(locked_until),
+            now + Duration::from_secs(30)
+        ));
+    }
+}
@@ -26,7 +26,7 @@
 //! https://standards.iso.org/ittf/PubliclyAvailableStandards/c068960_ISO_IEC_14496-12_2015.zip
 
 use crate::traits::{CodecParameters, Mp4};
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
 use bytes::{BufMut, BytesMut};
 
 use std::convert::TryFrom;
@@ -438,7 +438,8 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin, V: CodecParameters, A: CodecParam
                 for _ in 0..6 {
                     buf.put_u32(0); // pre_defined
                 }
-                buf.put_u32(2); // next_track_id
+                // next_track_id (the audio track is track 2)
+                buf.put_u32(if self.audio_trak.core.samples > 0 { 3 } else { 2 });
             });
             if self.video_trak.core.samples > 0 {
                 self.core
@@ -471,6 +472,12 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin, V: CodecParameters, A: CodecParam
         frame_timestamp: u64,
         is_random_access_point: bool,
     ) -> Result<(), Error> {
+        // The first frames may come from the pre-roll buffer. Make sure that the video
+        // starts with a frame that can be decoded on its own.
+        if self.video_trak.core.samples == 0 && !is_random_access_point {
+            bail!("The first video sample must be a keyframe");
+        }
+
         let size = u32::try_from(frame.len())?;
         self.video_trak.add_sample(
             /* sample_description_index */ 1,
@@ -0,0 +1,147 @@
+//! Persistence of the notification schedule set by the app.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use secluso_client_lib::notification_schedule::NotificationSchedule;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SCHEDULE_FILENAME: &str = "notification_schedule.json";
+const CLOCK_OFFSET_FILENAME: &str = "clock_offset";
+
+pub fn persist_notification_schedule(
+    state_dir: &str,
+    schedule: &NotificationSchedule,
+) -> io::Result<()> {
+    fs::create_dir_all(state_dir)?;
+    let payload = serde_json::to_vec(schedule)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    // Write and rename, so that a crash never leaves a half-written schedule behind.
+    let path = Path::new(state_dir).join(SCHEDULE_FILENAME);
+    let tmp_path = Path::new(state_dir).join(format!("{SCHEDULE_FILENAME}.tmp"));
+    fs::write(&tmp_path, payload)?;
+    fs::rename(tmp_path, path)
+}
+
+/// The schedule set by the app, or the default (always armed) if there's none.
+pub fn load_notification_schedule(state_dir: &str) -> io::Result<NotificationSchedule> {
+    let path = Path::new(state_dir).join(SCHEDULE_FILENAME);
+    if !path.exists() {
+        return Ok(NotificationSchedule::default());
+    }
+
+    let raw = fs::read_to_string(path)?;
+    serde_json::from_str::<NotificationSchedule>(&raw)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Persists how far (in seconds) the app's clock was ahead of ours at pairing.
+pub fn persist_clock_offset(state_dir: &str, offset_secs: i64) -> io::Result<()> {
+    fs::create_dir_all(state_dir)?;
+    fs::write(
+        Path::new(state_dir).join(CLOCK_OFFSET_FILENAME),
+        offset_secs.to_string(),
+    )
+}
+
+/// The clock offset received at pairing, or 0 if there's none (e.g., an IP camera hub,
+/// whose clock isn't set by the app).
+fn load_clock_offset(state_dir: &str) -> i64 {
+    let path = Path::new(state_dir).join(CLOCK_OFFSET_FILENAME);
+    if !path.exists() {
+        return 0;
+    }
+
+    match fs::read_to_string(path).map(|raw| raw.trim().parse::<i64>()) {
+        Ok(Ok(offset_secs)) => offset_secs,
+        Ok(Err(e)) => {
+            error!("Invalid clock offset: {e}");
+            0
+        }
+        Err(e) => {
+            error!("Failed to load the clock offset: {e}");
+            0
+        }
+    }
+}
+
+/// Whether motion notifications may be sent now.
+/// The schedule is set in the app's local time, so our clock is first corrected by the offset
+/// to the app's clock and then by the schedule's UTC offset.
+/// If the schedule can't be read, we notify: a missed alert is worse than an unwanted one.
+pub fn motion_notifications_allowed(state_dir: &str, now: u64) -> bool {
+    match load_notification_schedule(state_dir) {
+        Ok(schedule) => {
+            schedule.notifications_allowed(now.saturating_add_signed(load_clock_offset(state_dir)))
+        }
+        Err(e) => {
+            error!("Failed to load the notification schedule: {e}");
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secluso_client_lib::notification_schedule::{ScheduleWindow, Weekday};
+
+    // Thursday, 1970-01-01 12:00 UTC.
+    const THURSDAY_NOON_UTC: u64 = 12 * 60 * 60;
+    const HOUR: u64 = 60 * 60;
+
+    fn test_state_dir(name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("secluso-schedule-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_string_lossy().into_owned()
+    }
+
+    // Disarmed on Thursdays from 12:00 to 13:00 local time.
+    fn thursday_lunch(utc_offset_minutes: i16) -> NotificationSchedule {
+        NotificationSchedule {
+            armed: true,
+            utc_offset_minutes,
+            disarmed_windows: vec![ScheduleWindow {
+                days: vec![Weekday::Thursday],
+                start_minute: 12 * 60,
+                end_minute: 13 * 60,
+            }],
+        }
+    }
+
+    #[test]
+    fn clock_offset_is_applied_before_matching_windows() {
+        let state_dir = test_state_dir("clock-offset");
+        persist_notification_schedule(&state_dir, &thursday_lunch(0)).unwrap();
+        assert!(!motion_notifications_allowed(&state_dir, THURSDAY_NOON_UTC));
+
+        // Our clock is two hours behind the app's, so our noon is 14:00 for the schedule.
+        persist_clock_offset(&state_dir, 2 * HOUR as i64).unwrap();
+        assert!(motion_notifications_allowed(&state_dir, THURSDAY_NOON_UTC));
+        assert!(!motion_notifications_allowed(
+            &state_dir,
+            THURSDAY_NOON_UTC - 2 * HOUR
+        ));
+
+        fs::remove_dir_all(&state_dir).unwrap();
+    }
+
+    #[test]
+    fn clock_and_utc_offsets_add_up() {
+        let state_dir = test_state_dir("both-offsets");
+        // The user is at UTC+1 and our clock is an hour ahead of the app's: they cancel out.
+        persist_notification_schedule(&state_dir, &thursday_lunch(60)).unwrap();
+        persist_clock_offset(&state_dir, -(HOUR as i64)).unwrap();
+
+        assert!(!motion_notifications_allowed(&state_dir, THURSDAY_NOON_UTC));
+        assert!(motion_notifications_allowed(
+            &state_dir,
+            THURSDAY_NOON_UTC + HOUR
+        ));
+
+        fs::remove_dir_all(&state_dir).unwrap();
+    }
+}
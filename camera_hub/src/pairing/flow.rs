@@ -17,25 +17,64 @@ use secluso_client_lib::mls_clients::MlsClients;
 use secluso_client_lib::pairing::{self, generate_ip_camera_secret};
 use std::io::ErrorKind;
 use std::net::{TcpListener, TcpStream};
-use std::sync::{Mutex, OnceLock};
+use std::thread;
 use std::time::Duration;
 use std::{fs, io};
 
-// Used to ensure there can't be attempted concurrent pairing
-static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+// Port the app connects to for pairing with the first camera. By default, the next cameras use
+// the next ports, so that they can all wait for the app at the same time.
+pub const FIRST_PAIRING_PORT: u16 = 12348;
+
+// How often a camera waiting for the app to pair checks for a shutdown request.
+const PAIRING_SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Pairing port of the camera_index-th camera of the hub, unless it has one in its config.
+pub fn default_pairing_port(camera_index: usize) -> u16 {
+    u16::try_from(FIRST_PAIRING_PORT as usize + camera_index).unwrap_or(u16::MAX)
+}
+
+/// Address the app connects to for pairing with the camera that has pairing_port.
+pub fn pairing_addr(pairing_port: u16) -> String {
+    format!("0.0.0.0:{pairing_port}")
+}
 
 #[allow(clippy::too_many_arguments)]
 pub fn pair_all(
     camera: &dyn Camera,
     mls_clients: &mut MlsClients,
     input_camera_secret: Option<Vec<u8>>,
-) -> anyhow::Result<()> {
-    // Ensure that two cameras don't attempt to pair at the same time (as this would introduce an error when opening two of the same port simultaneously)
-    let _lock = LOCK
-        .get_or_init(|| Mutex::new(()))
-        .lock()
-        .expect("Another user of the LOCK mutex panicked while holding the mutex");
+    pairing_port: u16,
+) -> anyhow::Result<bool> {
+    // Each camera has its own pairing port. It can still be in use for a little while,
+    // e.g., if the hub was just restarted, so we wait for it to be released.
+    let addr = pairing_addr(pairing_port);
+    let listener = loop {
+        match TcpListener::bind(&addr) {
+            Ok(listener) => break listener,
+            Err(e) if e.kind() == ErrorKind::AddrInUse => {
+                debug!("[Pairing] Pairing port of {addr} in use, waiting for it to be released.");
+                thread::sleep(Duration::from_secs(1));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    println!(
+        "[{}] Pairing on port {}.",
+        camera.get_name(),
+        listener.local_addr()?.port()
+    );
 
+    pair_all_with_listener(listener, camera, mls_clients, input_camera_secret)
+}
+
+/// Runs the pairing flow on an already-bound listener.
+/// Returns false if a shutdown was requested before the app paired.
+pub fn pair_all_with_listener(
+    listener: TcpListener,
+    camera: &dyn Camera,
+    mls_clients: &mut MlsClients,
+    input_camera_secret: Option<Vec<u8>>,
+) -> anyhow::Result<bool> {
     // If None, this has to be an IP camera. If the camera_secret does not exist for Raspberry Pi, it will not proceed earlier on in the flow.
     #[cfg(feature = "raspberry")]
     assert!(
@@ -57,44 +96,42 @@ pub fn pair_all(
     println!("{message}");
 
     // Loop and continuously try to pair with the app (in case of failures)
-    let listener = TcpListener::bind("0.0.0.0:12348")?;
-    for incoming in listener.incoming() {
-        match incoming {
-            Ok(mut stream) => {
-                debug!("[Pairing] Incoming connection accepted.");
-
-                if let Err(e) = stream.set_nonblocking(false) {
-                    debug!("[Pairing] Failed to set blocking mode: {e}");
-                }
+    let mut paired = false;
+    while let Some(mut stream) = wait_for_pairing_connection(&listener)? {
+        debug!("[Pairing] Incoming connection accepted.");
 
-                if let Err(e) = stream.set_read_timeout(Some(Duration::from_secs(10))) {
-                    debug!("[Pairing] Failed to set read timeout: {e}");
-                }
+        // The listener is non-blocking, and the accepted stream may inherit that.
+        if let Err(e) = stream.set_nonblocking(false) {
+            debug!("[Pairing] Failed to set blocking mode: {e}");
+        }
 
-                if let Err(e) = stream.set_write_timeout(Some(Duration::from_secs(10))) {
-                    debug!("[Pairing] Failed to set write timeout: {e}");
-                }
+        if let Err(e) = stream.set_read_timeout(Some(Duration::from_secs(10))) {
+            debug!("[Pairing] Failed to set read timeout: {e}");
+        }
 
-                if try_pairing(&mut stream, mls_clients, &secret, camera) {
-                    // Pairing was successful!
-                    break;
-                }
+        if let Err(e) = stream.set_write_timeout(Some(Duration::from_secs(10))) {
+            debug!("[Pairing] Failed to set write timeout: {e}");
+        }
 
-                // Get rid of any potential failed pairs beforehand.
-                for mls_client in mls_clients.iter_mut() {
-                    mls_client.clean()?;
-                }
+        if try_pairing(&mut stream, mls_clients, &secret, camera) {
+            // Pairing was successful!
+            paired = true;
+            break;
+        }
 
-                // We cannot use the old user objects, so create new clients.
-                *mls_clients = initialize_mls_clients(camera, true)?;
+        // Get rid of any potential failed pairs beforehand.
+        for mls_client in mls_clients.iter_mut() {
+            mls_client.clean()?;
+        }
 
-                debug!("[Pairing] Error — resetting for next connection");
-            }
+        // We cannot use the old user objects, so create new clients.
+        *mls_clients = initialize_mls_clients(camera, true)?;
 
-            Err(e) => {
-                debug!("[Pairing] Incoming connection error: {e}");
-            }
-        }
+        debug!("[Pairing] Error — resetting for next connection");
+    }
+
+    if !paired {
+        return Ok(false);
     }
 
     if input_camera_secret.is_none() {
@@ -104,7 +141,26 @@ pub fn pair_all(
         ));
     }
 
-    Ok(())
+    Ok(true)
+}
+
+/// Waits for the app to connect to the listener.
+/// Returns None once a shutdown is requested, which a blocking accept would never notice.
+fn wait_for_pairing_connection(listener: &TcpListener) -> io::Result<Option<TcpStream>> {
+    listener.set_nonblocking(true)?;
+
+    loop {
+        if crate::shutdown_requested() {
+            return Ok(None);
+        }
+
+        match listener.accept() {
+            Ok((stream, _)) => return Ok(Some(stream)),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => debug!("[Pairing] Incoming connection error: {e}"),
+        }
+        thread::sleep(PAIRING_SHUTDOWN_POLL_INTERVAL);
+    }
 }
 
 fn try_pairing(
@@ -119,7 +175,7 @@ fn try_pairing(
     // This then prevents successful pairing due to MLS checking the lifetime
     // of key packages.
     #[cfg(feature = "raspberry")]
-    if let Err(e) = receive_timestamp_set_system_time(stream) {
+    if let Err(e) = receive_timestamp_set_system_time(stream, &camera.get_state_dir()) {
         debug!("[Pairing] Failed to receive and set timestamp: {e}");
         return false;
     }
@@ -217,7 +273,10 @@ fn invite(
 }
 
 #[cfg(feature = "raspberry")]
-fn receive_timestamp_set_system_time(stream: &mut TcpStream) -> anyhow::Result<()> {
+fn receive_timestamp_set_system_time(
+    stream: &mut TcpStream,
+    state_dir: &str,
+) -> anyhow::Result<()> {
     let timestamp_vec = crate::pairing::io::read_varying_len(stream)?;
     let timestamp: u64 = bincode::deserialize(&timestamp_vec)?;
     let _ = Command::new("date")
@@ -225,6 +284,13 @@ fn receive_timestamp_set_system_time(stream: &mut TcpStream) -> anyhow::Result<(
         .arg(format!("@{timestamp}"))
         .output()?;
 
+    // Whatever setting the clock didn't fix (e.g., no permission to) is left for the
+    // notification schedule to correct, so that its windows follow the app's clock.
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    crate::notification_schedule::persist_clock_offset(state_dir, timestamp as i64 - now as i64)?;
+
     Ok(())
 }
 
@@ -240,3 +306,119 @@ fn perform_pairing_handshake(
 
     Ok(app_key_package)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::process::{self, Stdio};
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    // Set for the hub process of pairing_stops_on_a_shutdown_signal.
+    const SIGNAL_CHILD_ENV: &str = "SECLUSO_PAIRING_SIGNAL_CHILD";
+    const WAITING_FOR_APP: &str = "waiting for the app to pair";
+
+    #[test]
+    fn cameras_pair_concurrently_on_their_own_ports() {
+        // Cameras far down the list, so that we don't clash with a hub running on this machine.
+        let cameras = [default_pairing_port(40), default_pairing_port(41)];
+        assert_ne!(cameras[0], cameras[1]);
+
+        let (tx, rx) = mpsc::channel();
+        let handles: Vec<_> = cameras
+            .into_iter()
+            .map(|pairing_port| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    let listener = TcpListener::bind(pairing_addr(pairing_port)).unwrap();
+                    tx.send(listener.local_addr().unwrap().port()).unwrap();
+
+                    // Stands in for a pairing session, which lasts until the app sends its message.
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut app_msg = [0u8; 1];
+                    stream.read_exact(&mut app_msg).unwrap();
+                    app_msg[0]
+                })
+            })
+            .collect();
+
+        // Both cameras wait for the app at the same time.
+        let mut ports: Vec<u16> = (0..cameras.len())
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        ports.sort();
+        let mut streams: Vec<TcpStream> = ports
+            .iter()
+            .map(|port| TcpStream::connect(("127.0.0.1", *port)).unwrap())
+            .collect();
+
+        // The second camera finishes pairing while the first one's session is still going on.
+        let [first, second] = handles.try_into().unwrap();
+        streams[1].write_all(&[2]).unwrap();
+        assert_eq!(second.join().unwrap(), 2);
+        assert!(!first.is_finished());
+
+        streams[0].write_all(&[1]).unwrap();
+        assert_eq!(first.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn pairing_stops_on_a_shutdown_signal() {
+        // The shutdown flag is global, so the hub runs in a process of its own:
+        // this test binary, running only waits_for_the_app_until_a_shutdown_signal.
+        let mut hub = process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "pairing::flow::tests::waits_for_the_app_until_a_shutdown_signal",
+                "--nocapture",
+            ])
+            .env(SIGNAL_CHILD_ENV, "1")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut stdout = BufReader::new(hub.stdout.take().unwrap());
+        let mut line = String::new();
+        while !line.contains(WAITING_FOR_APP) {
+            line.clear();
+            assert!(
+                stdout.read_line(&mut line).unwrap() > 0,
+                "the hub exited before pairing"
+            );
+        }
+
+        // Same as systemctl stop.
+        let killed = process::Command::new("kill")
+            .args(["-TERM", &hub.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(killed.success());
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let status = loop {
+            if let Some(status) = hub.try_wait().unwrap() {
+                break status;
+            }
+            if Instant::now() > deadline {
+                let _ = hub.kill();
+                panic!("the hub kept waiting for the app after SIGTERM");
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert!(status.success());
+    }
+
+    #[test]
+    fn waits_for_the_app_until_a_shutdown_signal() {
+        // Only runs as the hub of pairing_stops_on_a_shutdown_signal.
+        if std::env::var_os(SIGNAL_CHILD_ENV).is_none() {
+            return;
+        }
+
+        crate::install_shutdown_handler().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        println!("{WAITING_FOR_APP}");
+        assert!(wait_for_pairing_connection(&listener).unwrap().is_none());
+    }
+}
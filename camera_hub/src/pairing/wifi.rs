@@ -85,9 +85,20 @@ pub(crate) fn request_wifi_info(
     ))
 }
 
+// nmcli and ip. The tests replace them with mock scripts.
+#[cfg(not(test))]
+fn tool(program: &str) -> Command {
+    Command::new(program)
+}
+
+#[cfg(test)]
+fn tool(program: &str) -> Command {
+    tests::mock_tool(program)
+}
+
 fn nmcli_output(args: &[&str]) -> io::Result<Output> {
     // Wrapper to keep all the NetworkManager calls looking the same. Nudges us to explicit argv usage.
-    Command::new("nmcli").args(args).output()
+    tool("nmcli").args(args).output()
 }
 
 fn nmcli_stdout(args: &[&str]) -> io::Result<String> {
@@ -192,7 +203,7 @@ fn wifi_has_ipv4(device: &str) -> io::Result<bool> {
 
 fn has_default_route(device: &str) -> io::Result<bool> {
     // We also need a default route on the joined Wi-Fi device itself, otherwise the relay/server request can still fail even though some other interface kept the box online.
-    let output = Command::new("ip")
+    let output = tool("ip")
         .args(["route", "show", "default", "dev", device])
         .output()?;
     Ok(!String::from_utf8_lossy(&output.stdout).trim().is_empty())
@@ -344,7 +355,7 @@ pub(crate) fn attempt_wifi_connection(
     // We still leave ourselves a path to bring the hotspot back if pairing fails.
     // Drop every active AP profile first so pairing does not "succeed" with the hotspot still running.
     for hotspot_name in active_hotspot_connection_names()? {
-        let output = Command::new("nmcli")
+        let output = tool("nmcli")
             .args(["connection", "down", "id", hotspot_name.as_str()])
             .output()?; // wait for shutdown
         ensure_command_success(
@@ -355,7 +366,7 @@ pub(crate) fn attempt_wifi_connection(
 
     // Keep one fallback for older setups that still expect the canonical Hotspot profile name.
     if let Err(e) = wait_for_hotspot_shutdown(Duration::from_secs(8)) {
-        let fallback_output = Command::new("nmcli")
+        let fallback_output = tool("nmcli")
             .args(["connection", "down", "id", HOTSPOT_CONNECTION_NAME])
             .output()?;
         if fallback_output.status.success() {
@@ -389,12 +400,12 @@ pub(crate) fn attempt_wifi_connection(
 
         // Blow away any stale profile for this SSID before retrying. Reusing a half-bad
         // saved connection can make retries behave weirdly differently from a fresh join.
-        let _ = Command::new("nmcli")
+        let _ = tool("nmcli")
             .args(["connection", "delete", "id", ssid])
             .output(); // ignore error if it doesn't exist
 
         // Use direct nmcli args instead of shell strings so we are not depending on quoting luck
-        let connect_output = Command::new("nmcli")
+        let connect_output = tool("nmcli")
             .args([
                 "device",
                 "wifi",
@@ -409,8 +420,9 @@ pub(crate) fn attempt_wifi_connection(
             // we've verified the association worked; need to verify it's ready now
             debug!("[Pairing] Association succeeded on attempt {n}; waiting for full network readiness");
 
-            // Autoconnect on reboot
-            let _ = Command::new("nmcli")
+            // Autoconnect on reboot. Not fatal since we're connected now, but without it the camera
+            // won't rejoin the network after a reboot, so don't let it fail silently.
+            let autoconnect_result = tool("nmcli")
                 .args([
                     "connection",
                     "modify",
@@ -418,7 +430,13 @@ pub(crate) fn attempt_wifi_connection(
                     "connection.autoconnect",
                     "yes",
                 ])
-                .output();
+                .output()
+                .and_then(|output| {
+                    ensure_command_success(output, "Failed to enable Wi-Fi autoconnect")
+                });
+            if let Err(e) = autoconnect_result {
+                error!("[Pairing] {e}");
+            }
 
             // Prove it's ready thru an IP, default route and a path to the relay.
             match wait_for_wifi_readiness(ssid, server_addr, Duration::from_secs(25)) {
@@ -477,7 +495,7 @@ pub(crate) fn send_pairing_token_after_wifi_ready(
 fn bring_hotspot_back_up() -> io::Result<()> {
     debug!("[Pairing] Bringing hotspot back up...");
     // If pairing fails, we want the device to recover back into the discoverable state
-    let output = Command::new("nmcli")
+    let output = tool("nmcli")
         .args(["connection", "up", "id", "Hotspot"])
         .output()?;
     ensure_command_success(output, "Failed to bring hotspot back up")?;
@@ -489,7 +507,7 @@ pub fn create_wifi_hotspot() {
 
     loop {
         // less fragile than shell parsing to use argv
-        match Command::new("nmcli")
+        match tool("nmcli")
             .args([
                 "device",
                 "wifi",
@@ -587,3 +605,81 @@ pub(crate) fn attempt_wifi_pair(
     }
     (changed_wifi, false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    thread_local! {
+        // Directory of the mock nmcli and ip scripts used by the current test thread.
+        static MOCK_BIN_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn mock_tool(program: &str) -> Command {
+        match MOCK_BIN_DIR.with(|dir| dir.borrow().clone()) {
+            Some(dir) => Command::new(dir.join(program)),
+            None => Command::new(program),
+        }
+    }
+
+    // A NetworkManager that takes 6 seconds to connect to TestNet, which is then ready right away.
+    // Connecting and enabling autoconnect are recorded in the calls file.
+    const MOCK_NMCLI: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+case "$*" in
+"-t -f NAME,TYPE,DEVICE connection show --active")
+    if [ -e "$dir/connected" ]; then echo "TestNet:802-11-wireless:wlan0"; fi ;;
+"-t -f SSID device wifi")
+    echo "TestNet" ;;
+"device wifi connect TestNet password secret")
+    echo "connect started" >> "$dir/calls"
+    sleep 6
+    touch "$dir/connected"
+    echo "connect finished" >> "$dir/calls" ;;
+"connection modify TestNet connection.autoconnect yes")
+    echo "autoconnect" >> "$dir/calls" ;;
+"-g IP4.ADDRESS device show wlan0")
+    echo "192.168.1.2/24" ;;
+esac
+"#;
+
+    const MOCK_IP: &str = "#!/bin/sh\necho \"default via 192.168.1.1 dev wlan0\"\n";
+
+    fn write_script(path: &Path, contents: &str) {
+        fs::write(path, contents).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn slow_connect_is_waited_for() {
+        let dir = std::env::temp_dir().join(format!("secluso-nmcli-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        write_script(&dir.join("nmcli"), MOCK_NMCLI);
+        write_script(&dir.join("ip"), MOCK_IP);
+        MOCK_BIN_DIR.with(|mock| *mock.borrow_mut() = Some(dir.clone()));
+
+        // Stands in for the server, which has to be reachable once the Wi-Fi is ready.
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = format!("http://127.0.0.1:{}", server.local_addr().unwrap().port());
+
+        let start = Instant::now();
+        attempt_wifi_connection("TestNet", "secret", &server_addr).unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(6));
+
+        // Autoconnect is only set up once the connection is established.
+        let calls = fs::read_to_string(dir.join("calls")).unwrap();
+        assert_eq!(
+            calls.lines().collect::<Vec<_>>(),
+            ["connect started", "connect finished", "autoconnect"]
+        );
+
+        MOCK_BIN_DIR.with(|mock| *mock.borrow_mut() = None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
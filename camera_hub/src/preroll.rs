@@ -0,0 +1,71 @@
+//! Pre-roll: the cameras keep the last few seconds of encoded frames in their
+//! frame queue so that motion videos can start before the trigger.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+// Frames kept on top of the pre-roll so that there's a keyframe at or before
+// the start of the pre-roll (both cameras use ~1-second GOPs).
+const KEYFRAME_MARGIN: Duration = Duration::from_secs(2);
+
+pub trait BufferedFrame {
+    /// When the frame was added to the queue.
+    fn captured_at(&self) -> SystemTime;
+    /// Whether the frame is a video keyframe (random access point).
+    fn is_keyframe(&self) -> bool;
+}
+
+/// How long a camera needs to keep frames in its queue for the given pre-roll.
+pub fn buffer_window(preroll_secs: u64) -> Duration {
+    Duration::from_secs(preroll_secs) + KEYFRAME_MARGIN
+}
+
+/// Appends a frame to the queue and removes frames older than the window.
+pub fn add_frame_and_drop_old<F: BufferedFrame>(
+    queue: &mut VecDeque<F>,
+    frame: F,
+    window: Duration,
+) {
+    queue.push_back(frame);
+
+    let now = SystemTime::now();
+    while let Some(front) = queue.front() {
+        if now.duration_since(front.captured_at()).unwrap_or_default() > window {
+            queue.pop_front();
+        } else {
+            break;
+        }
+    }
+}
+
+/// Drops the frames before the pre-roll so that the queue starts with the last keyframe
+/// captured at or before `now - preroll`. If there's no such keyframe (e.g., the camera
+/// just started), the queue starts with its first keyframe instead.
+/// Frames after the returned position are left untouched; the recording then continues
+/// with the live frames appended to the same queue.
+pub fn trim_to_preroll<F: BufferedFrame>(queue: &mut VecDeque<F>, preroll: Duration, now: SystemTime) {
+    let preroll_start = now.checked_sub(preroll).unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let keyframes = queue
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.is_keyframe());
+    let mut start = None;
+    for (i, f) in keyframes {
+        if start.is_none() || f.captured_at() <= preroll_start {
+            start = Some(i);
+        } else {
+            break;
+        }
+    }
+
+    match start {
+        Some(i) => {
+            queue.drain(..i);
+        }
+        // No keyframe yet. The muxer can't start before one anyway.
+        None => queue.clear(),
+    }
+}
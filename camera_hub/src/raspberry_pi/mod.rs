@@ -1,4 +1,5 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
+pub(crate) mod privacy_mask;
 pub(crate) mod rpi_camera;
 mod rpi_dual_stream;
\ No newline at end of file
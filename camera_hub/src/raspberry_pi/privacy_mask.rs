@@ -0,0 +1,82 @@
+//! Privacy masking of the people detected in the Raspberry Pi camera's thumbnails.
+//! Only the thumbnails are masked: the motion videos and livestreams are recorded from the
+//! camera's H.264 stream, which the hub doesn't decode.
+//! Configured with the privacy_mask field of the camera's entry in cameras.yaml, or with the
+//! top-level privacy_mask field for all the cameras without one (optional).
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fs;
+use std::io;
+
+pub const DEFAULT_BLUR_KERNEL_SIZE: u32 = 25;
+
+// Allowed range for the kernel size. The kernel must have a center, so it must be odd.
+const MIN_BLUR_KERNEL_SIZE: u32 = 3;
+const MAX_BLUR_KERNEL_SIZE: u32 = 255;
+
+#[derive(Debug, Deserialize)]
+pub struct PrivacyMaskConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    blur_kernel_size: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CameraEntry {
+    name: String,
+    #[serde(default)]
+    privacy_mask: Option<PrivacyMaskConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CamerasFile {
+    #[serde(default)]
+    cameras: Vec<CameraEntry>,
+    #[serde(default)]
+    privacy_mask: Option<PrivacyMaskConfig>,
+}
+
+/// Returns the blur kernel size if privacy masking is enabled for camera_name in cameras.yaml.
+/// The camera's own entry takes precedence over the top-level privacy_mask.
+pub fn load_blur_kernel_size(camera_name: &str) -> io::Result<Option<u32>> {
+    let content = match fs::read_to_string("cameras.yaml") {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let file: CamerasFile = serde_yaml2::from_str(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to parse cameras.yaml ({e})"),
+        )
+    })?;
+
+    let Some(config) = file
+        .cameras
+        .into_iter()
+        .find(|camera| camera.name == camera_name)
+        .and_then(|camera| camera.privacy_mask)
+        .or(file.privacy_mask)
+    else {
+        return Ok(None);
+    };
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    let blur_kernel_size = config.blur_kernel_size.unwrap_or(DEFAULT_BLUR_KERNEL_SIZE);
+    if !(MIN_BLUR_KERNEL_SIZE..=MAX_BLUR_KERNEL_SIZE).contains(&blur_kernel_size)
+        || blur_kernel_size % 2 == 0
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid blur_kernel_size ({blur_kernel_size}) for camera {camera_name:?}: must be an odd number between {MIN_BLUR_KERNEL_SIZE} and {MAX_BLUR_KERNEL_SIZE}"
+            ),
+        ));
+    }
+
+    Ok(Some(blur_kernel_size))
+}
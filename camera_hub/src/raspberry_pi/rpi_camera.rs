@@ -5,22 +5,26 @@
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{
     collections::VecDeque,
-    io,
-    process::Command,
+    io::{self, Write},
+    process::{Command, Stdio},
     sync::{Arc, Mutex},
     thread,
     time::Duration,
 };
 
 use crate::motion::MotionResult;
+use crate::motion_settings::MotionSettings;
+use crate::preroll::{self, BufferedFrame};
 use crate::raspberry_pi::rpi_dual_stream;
 use crate::traits::Mp4;
 use crate::{
     delivery_monitor::VideoInfo,
     fmp4::Fmp4Writer,
-    livestream::LivestreamWriter,
+    livestream::{LivestreamWriter, SharedStreamQuality, StreamQuality},
     mp4::Mp4Writer,
+    talkback::{AlsaAudioSink, AudioSink},
     traits::{Camera, CodecParameters},
+    wakeup::Wakeup,
     write_box,
 };
 use anyhow::Error;
@@ -28,12 +32,16 @@ use bytes::{BufMut, BytesMut};
 use crossbeam_channel::unbounded;
 use image::RgbImage;
 use secluso_client_lib::thumbnail_meta_info::GeneralDetectionType;
-use secluso_motion_ai::logic::pipeline::PipelineController;
+use secluso_motion_ai::logic::pipeline::{
+    DetectionHandle, HealthThresholds, PipelineBuilder, PipelineController,
+};
+use secluso_motion_ai::logic::stages::{InferenceStage, MotionStage, PrivacyMaskStage};
 use secluso_motion_ai::ml::models::DetectionType;
-use secluso_motion_ai::pipeline;
 use tokio::runtime::Runtime;
 
 const TOTAL_FRAME_RATE: usize = 10;
+// Directory with fast.onnx and/or accurate.onnx. When set, SIGHUP reloads the AI models from it.
+const MODEL_RELOAD_DIR_ENV: &str = "SECLUSO_MODEL_RELOAD_DIR";
 const I_FRAME_INTERVAL: usize = TOTAL_FRAME_RATE; // 1-second fragments
 
 //These are for our local SPS/PPS channel
@@ -63,6 +71,16 @@ impl Frame {
     }
 }
 
+impl BufferedFrame for Frame {
+    fn captured_at(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    fn is_keyframe(&self) -> bool {
+        self.kind == FrameKind::IFrame
+    }
+}
+
 #[derive(Clone)]
 pub struct CameraResolution {
     width: usize,
@@ -78,8 +96,10 @@ pub struct RaspberryPiCamera {
     frame_queue: Arc<Mutex<VecDeque<Frame>>>,
     sps_frame: Frame,
     pps_frame: Frame,
-    motion_detection: Arc<Mutex<PipelineController>>,
+    motion_detection: DetectionHandle,
+    motion_wakeup: Arc<Mutex<Option<Arc<Wakeup>>>>,
     resolution: CameraResolution,
+    stream_quality: SharedStreamQuality,
 }
 
 impl RaspberryPiCamera {
@@ -90,20 +110,32 @@ impl RaspberryPiCamera {
         thumbnail_dir: String,
         motion_fps: u64,
         save_all: bool,
+        blur_kernel_size: Option<u32>,
     ) -> Self {
         println!("Initializing Raspberry Pi Camera...");
 
         // Create a channel to receive SPS/PPS frames.
         let (ps_tx, ps_rx) = unbounded::<Frame>();
 
-        // Frame queue holds recently processed H.264 and audio frames.
+        // Frame queue holds recently processed H.264 and audio frames (enough for the pre-roll).
         let frame_queue = Arc::new(Mutex::new(VecDeque::new()));
+        let buffer_window = preroll::buffer_window(MotionSettings::default().preroll_secs);
 
         // Start motion detection using raw frames from the shared stream.
-        let pipeline = pipeline![
-            secluso_motion_ai::logic::stages::MotionStage,
-            secluso_motion_ai::logic::stages::InferenceStage,
-        ];
+        let inference = match std::env::var(MODEL_RELOAD_DIR_ENV) {
+            Ok(dir) => InferenceStage::with_hot_reload(dir.into()).unwrap_or_else(|e| {
+                error!("Failed to enable model hot reload ({e})");
+                InferenceStage
+            }),
+            Err(_) => InferenceStage,
+        };
+        let mut builder = PipelineBuilder::new().then(MotionStage).then(inference);
+        if let Some(blur_kernel_size) = blur_kernel_size {
+            // Blurs the people in the thumbnails before they're sent to the app.
+            // The recorded videos come from the H.264 stream and aren't masked.
+            builder = builder.then(PrivacyMaskStage { blur_kernel_size });
+        }
+        let pipeline = builder.build();
 
         let write_logs = cfg!(feature = "telemetry");
         println!("Telemetry Output Enabled: {write_logs}");
@@ -113,29 +145,30 @@ impl RaspberryPiCamera {
                 panic!("Failed to instantiate pipeline controller");
             }
         };
+        let health_thresholds = HealthThresholds::from_env().unwrap_or_else(|e| {
+            error!("Invalid health thresholds ({e}), using the defaults");
+            HealthThresholds::default()
+        });
+        let mut new_controller = new_controller.with_health_thresholds(health_thresholds);
 
-        new_controller.start_working();
-        let motion_detection = Arc::new(Mutex::new(new_controller));
-        let controller_clone = Arc::clone(&motion_detection);
-        motion_detection.lock().unwrap().start_working(); // TODO: Should we start processing later, maybe when we get the first frame?
+        new_controller.start_working(); // TODO: Should we start processing later, maybe when we get the first frame?
+        let frame_sender = new_controller.frame_sender();
+        let motion_detection = new_controller.detection_handle();
+        let motion_wakeup: Arc<Mutex<Option<Arc<Wakeup>>>> = Arc::new(Mutex::new(None));
+        let motion_wakeup_clone = Arc::clone(&motion_wakeup);
 
-        // Background thread: runs the pipeline's main event loop
+        // Background thread: runs the pipeline's main event loop on the frames sent by the shared stream.
         thread::spawn(move || {
-            //todo: only loop until exit
-            loop {
-                // when false (health issue), we should exit + we should also have some way for user to safely exit
-                let result = controller_clone.lock().unwrap().tick("cpu_thermal temp1"); //TODO: This string should be put somewhere as a constant
-
-                if let Err(e) = result {
-                    println!("Encountered error in tick loop: {e}");
-                    break;
-                } else if let Ok(accepted) = result {
-                    if !accepted {
-                        println!("Not accepted");
-                        break;
-                    }
+            //TODO: This string should be put somewhere as a constant
+            let result = new_controller.run("cpu_thermal temp1", |_detection| {
+                // Wake up the core loop as soon as there's a new detection instead of having it poll us.
+                if let Some(wakeup) = motion_wakeup_clone.lock().unwrap().as_ref() {
+                    wakeup.notify_motion();
                 }
-                thread::sleep(Duration::from_millis(100));
+            });
+
+            if let Err(e) = result {
+                println!("Encountered error in tick loop: {e}");
             }
 
             debug!("Exited controller tick loop");
@@ -149,14 +182,15 @@ impl RaspberryPiCamera {
             resolution.height,
             TOTAL_FRAME_RATE,
             I_FRAME_INTERVAL,
-            Arc::clone(&motion_detection),
+            frame_sender,
             Arc::clone(&frame_queue),
+            buffer_window,
             ps_tx,
             motion_fps as u8,
         )
             .expect("Failed to start shared stream");
 
-        rpi_dual_stream::start_audio(Arc::clone(&frame_queue))
+        rpi_dual_stream::start_audio(Arc::clone(&frame_queue), buffer_window)
             .expect("Failed to start audio stream");
 
         // Wait for the SPS and PPS frames before continuing.
@@ -189,7 +223,9 @@ impl RaspberryPiCamera {
             sps_frame,
             pps_frame,
             motion_detection,
+            motion_wakeup,
             resolution,
+            stream_quality: SharedStreamQuality::default(),
         }
     }
 
@@ -199,6 +235,7 @@ impl RaspberryPiCamera {
         mp4: &mut M,
         duration: Option<u64>,
         frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+        stream_quality: Option<SharedStreamQuality>,
     ) -> Result<(), Error> {
         let recording_window = duration.map(|secs| Duration::new(secs, 0));
         let recording_start_time = Instant::now();
@@ -223,6 +260,20 @@ impl RaspberryPiCamera {
                 }
             };
 
+            if let Some(quality) = &stream_quality {
+                let is_video = frame.kind != FrameKind::Audio;
+                if !quality
+                    .get()
+                    .keeps_frame(is_video, frame.kind == FrameKind::IFrame)
+                {
+                    // Video timestamps are derived from the frame count, so dropped frames still count.
+                    if is_video && started {
+                        video_frame_count += 1;
+                    }
+                    continue;
+                }
+            }
+
             // Open the very first fragment on the first IDR
             if !started && frame.kind == FrameKind::IFrame {
                 started = true;
@@ -318,7 +369,7 @@ impl RaspberryPiCamera {
             .await?;
 
         // Process the rest of the frames, writing both to the MP4 writer and to the raw file.
-        Self::copy(&mut mp4, Some(duration), frame_queue).await?;
+        Self::copy(&mut mp4, Some(duration), frame_queue, None).await?;
         mp4.finish().await?;
 
         Ok(())
@@ -391,6 +442,7 @@ impl RaspberryPiCamera {
         sps_frame: Frame,
         pps_frame: Frame,
         resolution: CameraResolution,
+        stream_quality: SharedStreamQuality,
     ) -> Result<(), Error> {
         // Detect 3/4-byte AnnexB start codes
         fn start_code_len(b: &[u8]) -> usize {
@@ -438,7 +490,7 @@ impl RaspberryPiCamera {
             .await?;
         fmp4.finish_header(None).await?;
 
-        Self::copy(&mut fmp4, None, frame_queue).await?;
+        Self::copy(&mut fmp4, None, frame_queue, Some(stream_quality)).await?;
 
         Ok(())
     }
@@ -563,7 +615,7 @@ impl RaspberryPiCamera {
 impl Camera for RaspberryPiCamera {
     /// When Ok, there's motion
     fn is_there_motion(&mut self) -> Result<MotionResult, Error> {
-        if let Some(pipeline_result) = self.motion_detection.lock().unwrap().motion_recently()? {
+        if let Some(pipeline_result) = self.motion_detection.motion_recently() {
             if pipeline_result.motion {
                 let frame = pipeline_result.thumbnail;
                 let data = frame.rgb_data.unwrap().to_vec();
@@ -596,9 +648,31 @@ impl Camera for RaspberryPiCamera {
         })
     }
 
-    fn record_motion_video(&self, info: &VideoInfo, duration: u64) -> io::Result<()> {
+    fn wait_for_motion(&mut self, wakeup: &Wakeup, timeout: Duration) -> Result<MotionResult, Error> {
+        // The tick thread notifies the wakeup on new detections (see new()).
+        wakeup.wait(timeout);
+        self.is_there_motion()
+    }
+
+    fn set_wakeup(&mut self, wakeup: Arc<Wakeup>) {
+        *self.motion_wakeup.lock().unwrap() = Some(wakeup);
+    }
+
+    fn record_motion_video(
+        &self,
+        info: &VideoInfo,
+        duration: u64,
+        preroll_secs: u64,
+    ) -> io::Result<()> {
         let rt = Runtime::new()?;
 
+        // Start the video with the buffered frames from right before the motion.
+        preroll::trim_to_preroll(
+            &mut self.frame_queue.lock().unwrap(),
+            Duration::from_secs(preroll_secs),
+            SystemTime::now(),
+        );
+
         // FIXME: use a temp name for recording and then rename at the end?
         // If not, we might end up with half-recorded videos on crash, factory reset, etc.
         // This might be okay though.
@@ -627,6 +701,10 @@ impl Camera for RaspberryPiCamera {
         let pps_frame_clone = self.pps_frame.clone();
         let resolution_clone = self.resolution.clone();
 
+        // Every livestream starts at full quality.
+        self.stream_quality.set(StreamQuality::Full);
+        let stream_quality_clone = self.stream_quality.clone();
+
         thread::spawn(move || {
             let rt = Runtime::new().unwrap();
             let future = Self::write_fmp4(
@@ -634,7 +712,8 @@ impl Camera for RaspberryPiCamera {
                 frame_queue_clone,
                 sps_frame_clone,
                 pps_frame_clone,
-                resolution_clone
+                resolution_clone,
+                stream_quality_clone,
             );
             if let Err(e) = rt.block_on(future) {
                 eprintln!("[Livestream] write_fmp4 error: {e:?}");
@@ -644,6 +723,64 @@ impl Camera for RaspberryPiCamera {
         Ok(())
     }
 
+    fn set_stream_quality(&self, quality: StreamQuality) -> StreamQuality {
+        self.stream_quality.set(quality);
+        quality
+    }
+
+    fn talkback_sink(&self) -> Box<dyn AudioSink> {
+        Box::new(AlsaAudioSink::default())
+    }
+
+    /// rpicam-vid holds the camera, so we decode the latest keyframe of the H.264 stream
+    /// with ffmpeg (which must be installed on the hub) instead of using rpicam-still.
+    fn capture_still(&self) -> io::Result<Vec<u8>> {
+        let keyframe = self
+            .frame_queue
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|f| f.kind == FrameKind::IFrame)
+            .map(|f| f.data.clone())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "No keyframe received from the camera yet",
+                )
+            })?;
+
+        // The frames are in AnnexB, so the parameter sets and the keyframe make a decodable stream.
+        let mut h264 = Vec::with_capacity(
+            self.sps_frame.data.len() + self.pps_frame.data.len() + keyframe.len(),
+        );
+        h264.extend_from_slice(&self.sps_frame.data);
+        h264.extend_from_slice(&self.pps_frame.data);
+        h264.extend_from_slice(&keyframe);
+
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-v", "error", "-f", "h264", "-i", "-"])
+            .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "mjpeg", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Written from another thread so that ffmpeg's output can't fill up while we're writing.
+        let mut stdin = ffmpeg.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&h264));
+        let output = ffmpeg.wait_with_output()?;
+        let _ = writer.join();
+
+        if !output.status.success() || output.stdout.is_empty() {
+            return Err(io::Error::other(format!(
+                "ffmpeg didn't decode the keyframe ({})",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(output.stdout)
+    }
+
     fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -16,12 +16,13 @@ use std::{
 };
 use bytes::Buf;
 
+use crate::preroll::add_frame_and_drop_old;
 use crate::raspberry_pi::rpi_camera::{Frame, FrameKind};
 use anyhow::anyhow;
 use bytes::BytesMut;
 use crossbeam_channel::Sender;
 use secluso_motion_ai::frame::RawFrame;
-use secluso_motion_ai::logic::pipeline::PipelineController;
+use secluso_motion_ai::logic::pipeline::FrameSender;
 
 /// Provides two channels: one for raw YUV420 frames from rpicam‑vid (for motion detection), one for H.264 frames converted by rpicam-vid.
 #[allow(clippy::too_many_arguments)]
@@ -30,8 +31,9 @@ pub fn start(
     height: usize,
     total_frame_rate: usize,
     i_frame_interval: usize,
-    pipeline_controller: Arc<Mutex<PipelineController>>,
+    frame_sender: FrameSender,
     frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+    buffer_window: Duration,
     ps_tx: Sender<Frame>,
     motion_fps: u8,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -89,7 +91,11 @@ pub fn start(
                                         pps_sent = true;
                                     }
 
-                                    add_frame_and_drop_old(Arc::clone(&frame_queue), frame);
+                                    add_frame_and_drop_old(
+                                        &mut frame_queue.lock().unwrap(),
+                                        frame,
+                                        buffer_window,
+                                    );
                                 }
                             }
                             Err(e) => {
@@ -128,9 +134,9 @@ pub fn start(
                 match stream.read_exact(&mut buffer) {
                     Ok(_) => {
                         let raw_frame = RawFrame::create_from_buffer(buffer, width, height);
-                        {
-                            let mut lock = pipeline_controller.lock().unwrap();
-                            lock.push_frame(raw_frame);
+                        if frame_sender.send(raw_frame).is_err() {
+                            eprintln!("Motion pipeline stopped, no longer sending it frames");
+                            return;
                         }
                     }
                     Err(e) => {
@@ -160,25 +166,6 @@ fn connect_to_socket() -> Option<UnixStream> {
     None // If all attempts fail, we return None.
 }
 
-fn add_frame_and_drop_old(frame_queue: Arc<Mutex<VecDeque<Frame>>>, frame: Frame) {
-    let time_window = Duration::new(5, 0);
-    let mut queue = frame_queue.lock().unwrap();
-    queue.push_back(frame.clone());
-
-    // Remove frames older than the time window.
-    while let Some(front) = queue.front() {
-        if SystemTime::now()
-            .duration_since(front.timestamp)
-            .unwrap_or_default()
-            > time_window
-        {
-            queue.pop_front();
-        } else {
-            break;
-        }
-    }
-}
-
 /// A modified H264 extraction frame method when I had issues working with the old ip.rs one
 fn extract_h264_frame(buffer: &mut BytesMut) -> anyhow::Result<Option<Frame>> {
     const MAX_NAL_UNIT_SIZE: usize = 2 * 1024 * 1024; // 2 MB maximum
@@ -300,6 +287,7 @@ fn strip_adts(frame: &[u8]) -> Option<&[u8]> {
 
 pub fn start_audio(
     frame_queue: Arc<Mutex<VecDeque<Frame>>>,
+    buffer_window: Duration,
 ) -> Result<(), Box<dyn std::error::Error>> {
 
     let cmd = "\
@@ -353,7 +341,11 @@ pub fn start_audio(
                                     kind: FrameKind::Audio,
                                     timestamp: SystemTime::now(),
                                 };
-                                add_frame_and_drop_old(Arc::clone(&frame_queue), frame);
+                                add_frame_and_drop_old(
+                                    &mut frame_queue.lock().unwrap(),
+                                    frame,
+                                    buffer_window,
+                                );
                             }
                         }
                     }
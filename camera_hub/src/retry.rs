@@ -0,0 +1,190 @@
+//! Retry policy for reconnecting to the server.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use rand::Rng;
+use std::io;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[cfg(feature = "ip")]
+use std::fs;
+
+/// Exponential backoff with jitter.
+/// The jitter prevents all cameras from reconnecting at the same time after a server restart.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomly added or subtracted (0.1 -> +/-10%).
+    pub jitter_fraction: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(5 * 60),
+            multiplier: 2.0,
+            jitter_fraction: 0.1,
+        }
+    }
+}
+
+/// The optional retry_policy section of cameras.yaml. Delays are in seconds.
+#[cfg(feature = "ip")]
+#[derive(Debug, Default, Deserialize)]
+struct RetryPolicyConfig {
+    initial_delay_secs: Option<f64>,
+    max_delay_secs: Option<f64>,
+    multiplier: Option<f64>,
+    jitter_fraction: Option<f64>,
+}
+
+#[cfg(feature = "ip")]
+#[derive(Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    retry_policy: RetryPolicyConfig,
+}
+
+impl RetryPolicy {
+    /// Loads the retry policy from cameras.yaml (IP cameras only).
+    /// Missing fields (or a missing file) fall back to the defaults.
+    pub fn load() -> Self {
+        #[cfg(feature = "ip")]
+        if let Ok(content) = fs::read_to_string("cameras.yaml") {
+            match serde_yaml2::from_str::<Config>(&content) {
+                Ok(cfg) => return Self::from_config(cfg.retry_policy),
+                Err(e) => {
+                    error!("Failed to parse retry_policy in cameras.yaml, using defaults ({e})");
+                }
+            }
+        }
+
+        Self::default()
+    }
+
+    #[cfg(feature = "ip")]
+    fn from_config(cfg: RetryPolicyConfig) -> Self {
+        let default = Self::default();
+
+        Self {
+            initial_delay: cfg
+                .initial_delay_secs
+                .map(Duration::from_secs_f64)
+                .unwrap_or(default.initial_delay),
+            max_delay: cfg
+                .max_delay_secs
+                .map(Duration::from_secs_f64)
+                .unwrap_or(default.max_delay),
+            multiplier: cfg.multiplier.unwrap_or(default.multiplier).max(1.0),
+            jitter_fraction: cfg
+                .jitter_fraction
+                .unwrap_or(default.jitter_fraction)
+                .clamp(0.0, 1.0),
+        }
+    }
+
+    /// Delay before retry number `attempt` (starting from 0), before jitter is applied.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.min(i32::MAX as u32) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+
+    /// Delay before retry number `attempt` with a random jitter applied.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay(attempt).as_secs_f64();
+        let jitter = if self.jitter_fraction > 0.0 {
+            rand::rng().random_range(-self.jitter_fraction..=self.jitter_fraction)
+        } else {
+            0.0
+        };
+
+        Duration::from_secs_f64((base * (1.0 + jitter)).max(0.0))
+    }
+}
+
+/// Calls f until it succeeds, sleeping according to the policy between attempts.
+/// Gives up (and returns the last error) only if the hub is shutting down.
+pub fn retry_with_policy<F, T>(policy: &RetryPolicy, mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut attempt: u32 = 0;
+
+    loop {
+        match f() {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                if crate::shutdown_requested() {
+                    return Err(e);
+                }
+
+                let delay = policy.delay(attempt);
+                debug!("Attempt {} failed ({e}), retrying in {:?}", attempt + 1, delay);
+                sleep(delay);
+                attempt = attempt.saturating_add(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_with_policy, RetryPolicy};
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    fn policy_ms(initial: u64, max: u64, jitter_fraction: f64) -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(initial),
+            max_delay: Duration::from_millis(max),
+            multiplier: 2.0,
+            jitter_fraction,
+        }
+    }
+
+    #[test]
+    fn five_failures_back_off_up_to_the_cap() {
+        let policy = policy_ms(10, 40, 0.0);
+        let delays: Vec<Duration> = (0..5).map(|attempt| policy.delay(attempt)).collect();
+        let expected: Vec<Duration> = [10, 20, 40, 40, 40]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+        assert_eq!(delays, expected);
+
+        let mut calls = 0;
+        let start = Instant::now();
+        let result = retry_with_policy(&policy, || {
+            calls += 1;
+            if calls <= 5 {
+                Err(io::Error::other("server unreachable"))
+            } else {
+                Ok(calls)
+            }
+        });
+
+        assert_eq!(result.unwrap(), 6);
+        assert!(start.elapsed() >= expected.iter().sum::<Duration>());
+    }
+
+    #[test]
+    fn jitter_stays_within_the_fraction() {
+        let policy = policy_ms(1000, 1000, 0.1);
+        for attempt in 0..100 {
+            let delay = policy.delay(attempt);
+            assert!(delay >= Duration::from_millis(900) && delay <= Duration::from_millis(1100));
+        }
+    }
+
+    #[test]
+    fn base_delay_does_not_overflow() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.base_delay(u32::MAX), policy.max_delay);
+    }
+}
@@ -0,0 +1,113 @@
+//! Talkback: plays the audio that the app sends to the camera during a livestream.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use secluso_client_lib::http_client::HttpClient;
+use secluso_client_lib::mls_client::MlsClient;
+use secluso_client_lib::talkback::decrypt_talkback_chunk;
+use std::io;
+
+#[cfg(feature = "raspberry")]
+use std::io::Write;
+#[cfg(feature = "raspberry")]
+use std::process::{Child, Command, Stdio};
+
+// Upper bound on the chunks played in one go, so that the livestream upload isn't held back.
+const MAX_TALKBACK_CHUNKS_PER_POLL: usize = 8;
+
+/// Where talkback audio goes. The audio is raw PCM, S16_LE, 48 kHz, mono.
+pub trait AudioSink {
+    fn play(&mut self, audio: &[u8]) -> io::Result<()>;
+}
+
+/// Drops the audio. Used by cameras without a speaker.
+pub struct NullAudioSink;
+
+impl AudioSink for NullAudioSink {
+    fn play(&mut self, audio: &[u8]) -> io::Result<()> {
+        debug!("Talkback: no speaker, dropping {} bytes of audio", audio.len());
+        Ok(())
+    }
+}
+
+/// Plays the audio on the default ALSA device (through aplay).
+/// aplay is started on the first chunk and stopped when the sink is dropped.
+#[cfg(feature = "raspberry")]
+#[derive(Default)]
+pub struct AlsaAudioSink {
+    child: Option<Child>,
+}
+
+#[cfg(feature = "raspberry")]
+impl AudioSink for AlsaAudioSink {
+    fn play(&mut self, audio: &[u8]) -> io::Result<()> {
+        if self.child.is_none() {
+            let child = Command::new("aplay")
+                .args(["-q", "-t", "raw", "-f", "S16_LE", "-r", "48000", "-c", "1", "-"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            self.child = Some(child);
+        }
+
+        let stdin = self
+            .child
+            .as_mut()
+            .and_then(|child| child.stdin.as_mut())
+            .ok_or_else(|| io::Error::other("aplay stdin not available"))?;
+
+        if let Err(e) = stdin.write_all(audio) {
+            // aplay exited. Start a new one for the next chunk.
+            self.child = None;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "raspberry")]
+impl Drop for AlsaAudioSink {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            // Closing stdin lets aplay finish what it has buffered.
+            drop(child.stdin.take());
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Plays the talkback chunks that the app has uploaded so far, starting at next_chunk_number.
+/// Talkback is best effort: errors are logged and never end the livestream.
+pub fn play_pending_talkback(
+    mls_client: &mut MlsClient,
+    http_client: &HttpClient,
+    group_name: &str,
+    next_chunk_number: &mut u64,
+    sink: &mut dyn AudioSink,
+) {
+    for _ in 0..MAX_TALKBACK_CHUNKS_PER_POLL {
+        let enc_data = match http_client.livestream_audio_retrieve(group_name, *next_chunk_number) {
+            Ok(Some(enc_data)) => enc_data,
+            Ok(None) => return,
+            Err(e) => {
+                debug!("Talkback: failed to retrieve chunk {}: {e}", *next_chunk_number);
+                return;
+            }
+        };
+
+        // The server has deleted the chunk, so move on even if it's bad.
+        let chunk_number = *next_chunk_number;
+        *next_chunk_number += 1;
+
+        match decrypt_talkback_chunk(mls_client, enc_data, chunk_number) {
+            Ok(audio) => {
+                if let Err(e) = sink.play(&audio) {
+                    error!("Talkback: failed to play chunk {chunk_number}: {e}");
+                }
+            }
+            Err(e) => error!("Talkback: dropping chunk {chunk_number}: {e}"),
+        }
+    }
+}
@@ -14,6 +14,7 @@ use tokio::time::{sleep, Duration};
 use std::thread;
 use tokio::io::AsyncWriteExt;
 use tokio::runtime::Runtime;
+use image::codecs::jpeg::JpegEncoder;
 use image::RgbImage;
 
 pub struct TestCamera {
@@ -25,12 +26,23 @@ pub struct TestCamera {
 }
 
 impl Camera for TestCamera {
-    fn record_motion_video(&self, info: &VideoInfo, _duration: u64) -> io::Result<()> {
+    fn record_motion_video(
+        &self,
+        info: &VideoInfo,
+        _duration: u64,
+        _preroll_secs: u64,
+    ) -> io::Result<()> {
         let mut file = File::create(self.video_dir.clone() + "/" + &info.filename)?;
 
         let mut rng = rand::rng();
         let data: Vec<u8> = (0..1024).map(|_| rng.random()).collect();
 
+        // Wrap the random data in ftyp and mdat boxes so that it passes validate_mp4_file().
+        file.write_all(&16u32.to_be_bytes())?;
+        file.write_all(b"ftypisom")?;
+        file.write_all(&[0, 0, 2, 0])?;
+        file.write_all(&((8 + data.len()) as u32).to_be_bytes())?;
+        file.write_all(b"mdat")?;
         file.write_all(&data)?;
 
         Ok(())
@@ -69,28 +81,23 @@ impl Camera for TestCamera {
                 thumbnail: None,
             })
         }
-        //create dummy thumbnail
-        let width = 256;
-        let height = 256;
-
-        // 3 bytes per pixel (RGB)
-        let mut data = vec![0u8; (width * height * 3) as usize];
-
-        // Fill with dummy pattern
-        for i in 0..data.len() {
-            data[i] = (i % 256) as u8;
-        }
-
-        let img = RgbImage::from_raw(width, height, data)
-            .expect("Buffer size mismatch");
 
         Ok(MotionResult {
             motion: true,
             detections: vec![],
-            thumbnail: Some(img),
+            thumbnail: Some(dummy_image()),
         })
     }
 
+    fn capture_still(&self) -> io::Result<Vec<u8>> {
+        let mut jpeg = Vec::new();
+        dummy_image()
+            .write_with_encoder(JpegEncoder::new(&mut jpeg))
+            .map_err(io::Error::other)?;
+
+        Ok(jpeg)
+    }
+
     fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -106,3 +113,19 @@ impl Camera for TestCamera {
         self.thumbnail_dir.clone()
     }
 }
+
+fn dummy_image() -> RgbImage {
+    let width = 256;
+    let height = 256;
+
+    // 3 bytes per pixel (RGB)
+    let mut data = vec![0u8; (width * height * 3) as usize];
+
+    // Fill with dummy pattern
+    for i in 0..data.len() {
+        data[i] = (i % 256) as u8;
+    }
+
+    RgbImage::from_raw(width, height, data)
+        .expect("Buffer size mismatch")
+}
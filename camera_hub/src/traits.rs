@@ -2,11 +2,17 @@
 //!
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::continuous_recording::SegmentStore;
 use crate::delivery_monitor::VideoInfo;
-use crate::livestream::LivestreamWriter;
+use crate::livestream::{LivestreamWriter, StreamQuality};
 use crate::motion::MotionResult;
+use crate::motion_settings::MotionSettings;
+use crate::talkback::{AudioSink, NullAudioSink};
+use crate::wakeup::Wakeup;
 use anyhow::Error;
 use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(any(feature = "raspberry", feature = "ip"))]
 use bytes::BytesMut;
@@ -31,12 +37,92 @@ pub trait Mp4 {
     async fn finish_fragment(&mut self) -> Result<(), Error>;
 }
 
+/// How often wait_for_motion() polls cameras that can't signal motion on their own.
+pub const MOTION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
 pub trait Camera {
     fn is_there_motion(&mut self) -> Result<MotionResult, Error>;
-    fn record_motion_video(&self, info: &VideoInfo, duration: u64) -> io::Result<()>;
+
+    /// Blocks until the camera detects motion, the wakeup is notified, or the timeout expires.
+    /// The default implementation polls is_there_motion() every MOTION_POLL_INTERVAL.
+    fn wait_for_motion(&mut self, wakeup: &Wakeup, timeout: Duration) -> Result<MotionResult, Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let result = self.is_there_motion()?;
+            let now = Instant::now();
+            if result.motion || now >= deadline {
+                return Ok(result);
+            }
+
+            // The core loop checks for requests after every call, so just return.
+            if wakeup.wait(MOTION_POLL_INTERVAL.min(deadline - now)).request {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Gives the camera a handle it can use to wake up the core loop when it detects motion.
+    fn set_wakeup(&mut self, _wakeup: Arc<Wakeup>) {}
+
+    /// Records a motion video that starts with up to preroll_secs of buffered frames
+    /// from before the trigger, followed by duration seconds of live frames.
+    fn record_motion_video(
+        &self,
+        info: &VideoInfo,
+        duration: u64,
+        preroll_secs: u64,
+    ) -> io::Result<()>;
     fn launch_livestream(&self, livestream_writer: LivestreamWriter) -> io::Result<()>;
+
+    /// The local continuous recording of this camera, if it's enabled.
+    fn recorded_segments(&self) -> Option<SegmentStore> {
+        None
+    }
+
+    /// How many attempts in a row to (re)connect to the camera's stream failed.
+    /// 0 while the camera is streaming.
+    fn stream_failures(&self) -> u32 {
+        0
+    }
+
+    /// Whether to generate a thumbnail on the hub (from the recorded video) for motion videos
+    /// that motion detection didn't provide one for.
+    fn hub_thumbnails(&self) -> bool {
+        false
+    }
+
+    /// Grabs a single frame from the camera as a JPEG (used for on-demand snapshots).
+    fn capture_still(&self) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Camera does not support snapshots",
+        ))
+    }
+
+    /// Changes the quality of the running livestream (used when the uplink can't keep up).
+    /// Returns the quality in effect. Cameras that can't change it stay at full quality.
+    fn set_stream_quality(&self, _quality: StreamQuality) -> StreamQuality {
+        StreamQuality::Full
+    }
+    /// Where the audio the app sends during a livestream (talkback) is played.
+    fn talkback_sink(&self) -> Box<dyn AudioSink> {
+        Box::new(NullAudioSink)
+    }
+
     fn get_name(&self) -> String;
     fn get_state_dir(&self) -> String;
     fn get_video_dir(&self) -> String;
     fn get_thumbnail_dir(&self) -> String;
+
+    /// Motion cooldown and recording duration for this camera.
+    fn get_motion_settings(&self) -> MotionSettings {
+        MotionSettings::default()
+    }
+
+    /// Port the app connects to for pairing, if set in the config.
+    /// Otherwise, it depends on the position of the camera in the hub.
+    fn get_pairing_port(&self) -> Option<u16> {
+        None
+    }
 }
@@ -0,0 +1,104 @@
+//! Wakes up the core loop when there's something to do.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// What woke up the core loop.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pending {
+    /// The camera detected new motion.
+    pub motion: bool,
+    /// A livestream request or a config command arrived from the server.
+    pub request: bool,
+}
+
+impl Pending {
+    pub fn any(&self) -> bool {
+        self.motion || self.request
+    }
+}
+
+/// Condvar-backed signal shared by the core loop, the server poller threads, and the camera.
+#[derive(Default)]
+pub struct Wakeup {
+    pending: Mutex<Pending>,
+    cvar: Condvar,
+}
+
+impl Wakeup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn notify_motion(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.motion = true;
+        self.cvar.notify_all();
+    }
+
+    pub fn notify_request(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.request = true;
+        self.cvar.notify_all();
+    }
+
+    /// Blocks until notified or until the timeout expires.
+    /// Returns (and clears) whatever was pending.
+    pub fn wait(&self, timeout: Duration) -> Pending {
+        let deadline = Instant::now() + timeout;
+        let mut pending = self.pending.lock().unwrap();
+
+        while !pending.any() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            pending = self.cvar.wait_timeout(pending, deadline - now).unwrap().0;
+        }
+
+        std::mem::take(&mut *pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Wakeup;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn notification_wakes_the_waiter_before_the_timeout() {
+        let wakeup = Arc::new(Wakeup::new());
+        let notifier = Arc::clone(&wakeup);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            notifier.notify_request();
+        });
+
+        let start = Instant::now();
+        let pending = wakeup.wait(Duration::from_secs(30));
+        handle.join().unwrap();
+
+        assert!(pending.request && !pending.motion);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn pending_notifications_are_returned_once() {
+        let wakeup = Wakeup::new();
+        wakeup.notify_motion();
+        wakeup.notify_request();
+
+        let pending = wakeup.wait(Duration::from_secs(30));
+        assert!(pending.motion && pending.request);
+
+        let start = Instant::now();
+        let pending = wakeup.wait(Duration::from_millis(50));
+        assert!(!pending.any());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,144 @@
+//! Runs `secluso-camera-hub --status` against a state directory prepared on disk.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use secluso_client_lib::mls_client::{ClientType, MlsClient};
+use secluso_client_lib::mls_clients::MLS_CLIENT_TAGS;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("secluso-status-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Same files as a finished pairing: the names of the camera clients and groups,
+// the group states, and first_time_done.
+fn write_paired_state(state_dir: &Path) {
+    fs::create_dir_all(state_dir).unwrap();
+    for tag in MLS_CLIENT_TAGS {
+        let camera_name = format!("camera-{tag}");
+        let group_name = format!("group-{tag}");
+        fs::write(state_dir.join(format!("camera_{tag}_name")), &camera_name).unwrap();
+        fs::write(state_dir.join(format!("group_{tag}_name")), &group_name).unwrap();
+
+        let mut client = MlsClient::new(
+            camera_name,
+            true,
+            state_dir.to_str().unwrap().to_string(),
+            tag.to_string(),
+            ClientType::Camera,
+        )
+        .unwrap();
+        client.create_group(&group_name).unwrap();
+        client.save_group_state().unwrap();
+    }
+    fs::write(state_dir.join("first_time_done"), b"").unwrap();
+}
+
+// (camera name, state dir) of the cameras the hub reports on, one paired and (for IP cameras)
+// one that isn't.
+#[cfg(feature = "ip")]
+fn prepare_cameras(dir: &Path) -> (String, Option<String>) {
+    fs::write(
+        dir.join("cameras.yaml"),
+        "cameras:\n\
+         \x20 - name: \"Front Door\"\n\
+         \x20   ip: \"192.168.1.2\"\n\
+         \x20   rtsp_port: 554\n\
+         \x20   motion_fps: 5\n\
+         \x20 - name: \"Garage\"\n\
+         \x20   ip: \"192.168.1.3\"\n\
+         \x20   rtsp_port: 554\n\
+         \x20   motion_fps: 5\n",
+    )
+    .unwrap();
+    write_paired_state(&dir.join("state/front_door"));
+    fs::create_dir_all(dir.join("state/garage")).unwrap();
+
+    ("Front Door".to_string(), Some("Garage".to_string()))
+}
+
+#[cfg(feature = "manual")]
+fn prepare_cameras(dir: &Path) -> (String, Option<String>) {
+    write_paired_state(&dir.join("state/manual"));
+    ("RPi".to_string(), None)
+}
+
+#[cfg(all(feature = "raspberry", not(feature = "manual")))]
+fn prepare_cameras(dir: &Path) -> (String, Option<String>) {
+    write_paired_state(&dir.join("state"));
+    ("RPi".to_string(), None)
+}
+
+#[cfg(all(feature = "test", not(any(feature = "ip", feature = "raspberry", feature = "manual"))))]
+fn prepare_cameras(dir: &Path) -> (String, Option<String>) {
+    write_paired_state(&dir.join("state"));
+    ("TestCamera".to_string(), None)
+}
+
+#[test]
+fn status_reports_pairing_and_group_epochs() {
+    let dir = test_dir("paired");
+    let (paired, unpaired) = prepare_cameras(&dir);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_secluso-camera-hub"))
+        .arg("--status")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{stdout}{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let paired_section = stdout
+        .split(&format!("[{paired}]\n"))
+        .nth(1)
+        .expect("paired camera missing from the status");
+    for tag in MLS_CLIENT_TAGS {
+        assert!(
+            paired_section.contains(&format!("Group {tag} (group-{tag}): epoch 0")),
+            "{stdout}"
+        );
+    }
+    assert!(paired_section.contains("No delivery state found."), "{stdout}");
+
+    if let Some(unpaired) = unpaired {
+        assert!(
+            stdout.contains(&format!("[{unpaired}]\nNot paired yet.")),
+            "{stdout}"
+        );
+    }
+
+    // --status only reads the state.
+    assert!(!dir.join("credentials_full").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn status_without_any_state() {
+    let dir = test_dir("empty");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_secluso-camera-hub"))
+        .arg("--status")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    // IP cameras are listed in cameras.yaml. The other cameras have nothing to read yet.
+    if cfg!(feature = "ip") {
+        assert!(!output.status.success());
+    } else {
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Not paired yet."));
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
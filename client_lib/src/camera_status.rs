@@ -0,0 +1,26 @@
+//! Camera status notifications, sent by the camera over the FCM channel.
+//! Unlike the motion timestamps, these are JSON, so the app passes them through as is.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use serde::{Deserialize, Serialize};
+use std::io;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CameraStatusNotification {
+    /// The camera hub lost the camera's stream and failed to reconnect this many times in a row.
+    CameraOffline { consecutive_failures: u32, timestamp: u64 },
+    /// The stream is back after a CameraOffline notification.
+    CameraOnline { timestamp: u64 },
+}
+
+impl CameraStatusNotification {
+    pub fn to_json_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("camera status serializes to JSON")
+    }
+
+    pub fn from_json_bytes(bytes: &[u8]) -> io::Result<Self> {
+        serde_json::from_slice(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
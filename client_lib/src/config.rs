@@ -14,6 +14,14 @@ pub const OPCODE_HEARTBEAT_REQUEST: u8 = 0;
 pub const OPCODE_HEARTBEAT_RESPONSE: u8 = 1;
 pub const OPCODE_ADD_APP_REQUEST: u8 = 2;
 pub const OPCODE_ADD_APP_RESPONSE: u8 = 3;
+pub const OPCODE_SNAPSHOT_REQUEST: u8 = 4;
+pub const OPCODE_SNAPSHOT_RESPONSE: u8 = 5;
+pub const OPCODE_LIST_SEGMENTS_REQUEST: u8 = 6;
+pub const OPCODE_LIST_SEGMENTS_RESPONSE: u8 = 7;
+pub const OPCODE_RETRIEVE_SEGMENTS_REQUEST: u8 = 8;
+pub const OPCODE_RETRIEVE_SEGMENTS_RESPONSE: u8 = 9;
+pub const OPCODE_SET_SCHEDULE_REQUEST: u8 = 10;
+pub const OPCODE_SET_SCHEDULE_RESPONSE: u8 = 11;
 
 pub enum HeartbeatResult {
     InvalidTimestamp,
@@ -43,7 +51,7 @@ impl HeartbeatRequest {
                 || MLS_CLIENT_TAGS[i] == "thumbnail"
             {
                 let update_proposal = clients[i].update_proposal()?;
-                clients[i].save_group_state().unwrap();
+                clients[i].save_group_state()?;
                 update_proposals.push(update_proposal);
             }
         }
@@ -68,12 +76,12 @@ impl HeartbeatRequest {
             {
                 let _ =
                     clients_com[i].decrypt(self.update_proposals[proposals_i].clone(), false)?;
-                clients_com[i].save_group_state().unwrap();
+                clients_com[i].save_group_state()?;
                 proposals_i += 1;
             } else if MLS_CLIENT_TAGS[i] == "livestream" {
                 let _ =
                     clients_ded[i - NUM_COMMON_MLS_CLIENTS].decrypt(self.update_proposals[proposals_i].clone(), false)?;
-                clients_ded[i - NUM_COMMON_MLS_CLIENTS].save_group_state().unwrap();
+                clients_ded[i - NUM_COMMON_MLS_CLIENTS].save_group_state()?;
                 proposals_i += 1;
             }
         }
@@ -88,6 +96,15 @@ pub struct CameraVersionInfo {
     pub os_version: String,
 }
 
+/// A problem on the camera side (e.g., RTSP auth failure, storage full) that
+/// the app should show to the user.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CameraEvent {
+    pub code: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Heartbeat {
     pub firmware_version: String,
@@ -95,6 +112,19 @@ pub struct Heartbeat {
     pub timestamp: u64,
     pub epochs: Vec<u64>,          //for motion and livestream MLS clients
     pub ciphertexts: Vec<Vec<u8>>, //for all MLS clients except for config
+    // Added after the fields above. Must stay last: old apps deserialize
+    // the fields above with bincode and ignore the trailing bytes.
+    pub events: Vec<CameraEvent>,
+}
+
+/// Heartbeat as sent by cameras from before events were added.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HeartbeatV1 {
+    pub firmware_version: String,
+    pub os_version: String,
+    pub timestamp: u64,
+    pub epochs: Vec<u64>,
+    pub ciphertexts: Vec<Vec<u8>>,
 }
 
 impl Heartbeat {
@@ -103,6 +133,7 @@ impl Heartbeat {
         clients_ded: &mut MlsClientsDedicated,
         timestamp: u64,
         version_info: CameraVersionInfo,
+        events: Vec<CameraEvent>,
     ) -> io::Result<Self> {
         let mut ciphertexts: Vec<Vec<u8>> = vec![];
         let mut epochs: Vec<u64> = vec![];
@@ -110,7 +141,7 @@ impl Heartbeat {
 
         for i in 0..NUM_COMMON_MLS_CLIENTS {
             let ciphertext = clients_com[i].encrypt(&timestamp_bytes)?;
-            clients_com[i].save_group_state().unwrap();
+            clients_com[i].save_group_state()?;
             ciphertexts.push(ciphertext);
 
             if MLS_CLIENT_TAGS[i] == "motion"
@@ -123,7 +154,7 @@ impl Heartbeat {
 
         // livestream
         let ciphertext = clients_ded[LIVESTREAM_DED].encrypt(&timestamp_bytes)?;
-        clients_ded[LIVESTREAM_DED].save_group_state().unwrap();
+        clients_ded[LIVESTREAM_DED].save_group_state()?;
         ciphertexts.push(ciphertext);
     
         let epoch = clients_ded[LIVESTREAM_DED].get_epoch()?;
@@ -135,6 +166,27 @@ impl Heartbeat {
             timestamp,
             epochs,
             ciphertexts,
+            events,
+        })
+    }
+
+    /// Deserializes a heartbeat from either a new or an old camera.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if let Ok(heartbeat) = bincode::deserialize::<Self>(bytes) {
+            return Ok(heartbeat);
+        }
+
+        let v1: HeartbeatV1 = bincode::deserialize(bytes).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Failed to deserialize heartbeat msg - {e}"))
+        })?;
+
+        Ok(Self {
+            firmware_version: v1.firmware_version,
+            os_version: v1.os_version,
+            timestamp: v1.timestamp,
+            epochs: v1.epochs,
+            ciphertexts: v1.ciphertexts,
+            events: vec![],
         })
     }
 
@@ -190,7 +242,7 @@ impl Heartbeat {
                             return Ok(HeartbeatResult::InvalidCiphertext);
                         }
                     };
-                clients[i].save_group_state().unwrap();
+                clients[i].save_group_state()?;
 
                 info!("Checking plaintext for {}", MLS_CLIENT_TAGS[i]);
                 let timestamp_bytes: [u8; 8] = match plaintext.try_into() {
@@ -237,3 +289,67 @@ pub struct AddAppResponseDedicated {
     pub welcome_msg_vec: Vec<u8>,
     pub group_name: String,
 }
+
+/// Sent by the camera after a snapshot request. The snapshot itself is sent
+/// as a regular thumbnail, which the app downloads and decrypts with decrypt_thumbnail().
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum SnapshotResponse {
+    Taken { timestamp: u64, filename: String },
+    Failed(String),
+}
+
+impl SnapshotResponse {
+    /// The config message for the response (opcode followed by the response).
+    pub fn to_config_msg(&self) -> Vec<u8> {
+        let mut config_msg = vec![OPCODE_SNAPSHOT_RESPONSE];
+        config_msg.extend(bincode::serialize(self).unwrap());
+        config_msg
+    }
+
+    pub fn from_config_msg(config_msg: &[u8]) -> io::Result<Self> {
+        match config_msg.split_first() {
+            Some((&OPCODE_SNAPSHOT_RESPONSE, response_bytes)) => bincode::deserialize(response_bytes)
+                .map_err(|e| io::Error::other(format!("Failed to deserialize snapshot response - {e}"))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a snapshot response",
+            )),
+        }
+    }
+}
+
+/// A segment of the continuous recording stored on the hub (timestamps in seconds).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedSegment {
+    pub start: u64,
+    pub end: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum ListSegmentsResponse {
+    Segments(Vec<RecordedSegment>),
+    Failed(String),
+}
+
+/// Asks the camera to send the recorded segments that overlap [from, to].
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetrieveSegmentsRequest {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// The segments are sent as motion videos. The response lists their video timestamps.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum RetrieveSegmentsResponse {
+    Queued(Vec<u64>),
+    Failed(String),
+}
+
+/// Sent by the camera after a request to set its notification schedule
+/// (a NotificationSchedule, see notification_schedule.rs).
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum SetScheduleResponse {
+    Saved,
+    Failed(String),
+}
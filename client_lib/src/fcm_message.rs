@@ -0,0 +1,83 @@
+//! Format of the messages that the camera sends to the app over the FCM channel.
+//! The first byte of the plaintext tells how the rest is encoded.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use log::warn;
+use std::io;
+
+/// JSON message (e.g., a CameraStatusNotification).
+pub const FORMAT_JSON: u8 = 0x01;
+/// bincode u64 timestamp of a motion event. 0 tells the app to start downloading.
+pub const FORMAT_TIMESTAMP: u8 = 0x02;
+
+const TIMESTAMP_LEN: usize = 8;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FcmMessage {
+    Json(String),
+    Timestamp(u64),
+}
+
+pub fn encode_json(json: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(1 + json.len());
+    msg.push(FORMAT_JSON);
+    msg.extend_from_slice(json);
+    msg
+}
+
+pub fn encode_timestamp(timestamp: u64) -> Vec<u8> {
+    let mut msg = vec![FORMAT_TIMESTAMP];
+    msg.extend(bincode::serialize(&timestamp).unwrap());
+    msg
+}
+
+pub fn decode(msg: &[u8]) -> io::Result<FcmMessage> {
+    match msg.split_first() {
+        Some((&FORMAT_JSON, json)) => {
+            if let Some(json) = as_json(json) {
+                return Ok(FcmMessage::Json(json));
+            }
+        }
+        Some((&FORMAT_TIMESTAMP, timestamp)) if timestamp.len() == TIMESTAMP_LEN => {
+            return deserialize_timestamp(timestamp).map(FcmMessage::Timestamp);
+        }
+        _ => {}
+    }
+
+    // The format byte can't be confused with the start of a legacy message:
+    // legacy JSON starts with a printable character, and legacy timestamps are 8 bytes long
+    // (a timestamp starting with 0x01 would need its other 7 bytes to be valid JSON).
+    let decoded = decode_legacy(msg)?;
+    warn!(
+        "Received a message without a format byte. This format is deprecated, update the camera."
+    );
+    Ok(decoded)
+}
+
+// Messages from cameras that predate the format byte.
+fn decode_legacy(msg: &[u8]) -> io::Result<FcmMessage> {
+    if let Some(json) = as_json(msg) {
+        return Ok(FcmMessage::Json(json));
+    }
+
+    if msg.len() == TIMESTAMP_LEN {
+        return deserialize_timestamp(msg).map(FcmMessage::Timestamp);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Error: invalid len in decrypted msg ({})", msg.len()),
+    ))
+}
+
+fn as_json(bytes: &[u8]) -> Option<String> {
+    let json = std::str::from_utf8(bytes).ok()?;
+    serde_json::from_str::<serde_json::Value>(json).ok()?;
+    Some(json.to_string())
+}
+
+fn deserialize_timestamp(bytes: &[u8]) -> io::Result<u64> {
+    bincode::deserialize(bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
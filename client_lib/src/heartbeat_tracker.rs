@@ -0,0 +1,77 @@
+//! Tracks the heartbeat requests sent to the cameras and the healthy responses to them,
+//! so that the app can tell when a camera hasn't answered for too long (e.g., it's offline).
+//! Timestamps are the ones used for the heartbeat requests; any unit works as long as
+//! now and threshold use the same one.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Default)]
+struct CameraHeartbeats {
+    last_request: Option<u64>,
+    // The requests sent after the last healthy response.
+    // Requests are usually sent periodically, so the wait is counted from the first one.
+    unanswered: BTreeSet<u64>,
+    last_healthy: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct HeartbeatTracker {
+    cameras: HashMap<String, CameraHeartbeats>,
+}
+
+impl HeartbeatTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a heartbeat request sent to the camera with the given timestamp.
+    pub fn record_request(&mut self, camera: &str, timestamp: u64) {
+        let heartbeats = self.cameras.entry(camera.to_string()).or_default();
+        heartbeats.last_request = Some(timestamp);
+        heartbeats.unanswered.insert(timestamp);
+    }
+
+    /// Records a healthy response to the request with the given timestamp
+    /// (the expected timestamp of process_heartbeat_config_response).
+    /// Responses to requests sent before the last healthy one are ignored.
+    pub fn record_healthy_response(&mut self, camera: &str, request_timestamp: u64) {
+        let heartbeats = self.cameras.entry(camera.to_string()).or_default();
+        if heartbeats
+            .last_healthy
+            .is_some_and(|last_healthy| request_timestamp < last_healthy)
+        {
+            return;
+        }
+
+        heartbeats.last_healthy = Some(request_timestamp);
+        // Requests sent after this one are still waiting for a response.
+        heartbeats.unanswered = heartbeats
+            .unanswered
+            .split_off(&request_timestamp.saturating_add(1));
+    }
+
+    pub fn last_request(&self, camera: &str) -> Option<u64> {
+        self.cameras.get(camera)?.last_request
+    }
+
+    /// Timestamp of the last request that got a healthy response.
+    pub fn last_healthy(&self, camera: &str) -> Option<u64> {
+        self.cameras.get(camera)?.last_healthy
+    }
+
+    /// Whether a request has been waiting for a healthy response for threshold or more.
+    /// False if no request was sent to the camera.
+    pub fn is_heartbeat_overdue(&self, camera: &str, now: u64, threshold: u64) -> bool {
+        self.cameras
+            .get(camera)
+            .and_then(|heartbeats| heartbeats.unanswered.first().copied())
+            .is_some_and(|sent| now.saturating_sub(sent) >= threshold)
+    }
+
+    /// Forgets the camera (e.g., once it's removed).
+    pub fn remove_camera(&mut self, camera: &str) {
+        self.cameras.remove(camera);
+    }
+}
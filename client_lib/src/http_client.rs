@@ -4,11 +4,15 @@
 
 use base64::engine::general_purpose::STANDARD as base64_engine;
 use base64::{engine::general_purpose, Engine as _};
+use crate::livestream_chunk::{
+    parse_chunk_format, LIVESTREAM_CHUNK_FORMAT, LIVESTREAM_CHUNK_FORMAT_HEADER,
+};
 use reqwest::blocking::{Body, Client, RequestBuilder};
 use reqwest::Url;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Write, Read};
 use std::path::Path;
@@ -18,12 +22,20 @@ use std::env;
 // Some of these constants are based on the ones in server/main.rs.
 const MAX_MOTION_FILE_SIZE: u64 = 50 * 1024 * 1024; // 50 mebibytes
 const MAX_LIVESTREAM_FILE_SIZE: u64 = 20 * 1024 * 1024; // 20 mebibytes
+const MAX_TALKBACK_FILE_SIZE: u64 = 1024 * 1024; // 1 mebibyte
 const MAX_COMMAND_FILE_SIZE: u64 = 100 * 1024; // 100 kibibytes
 const MAX_CHECK_RESP_SIZE: u64 = 20 * 1024; // 20 kibibytes
 const MAX_NOTIFICATION_TARGET_SIZE: u64 = 10 * 1024; // 10 kibibytes
 const IOS_NOTIFICATION_RESP_MAX_SIZE: u64 = 10 * 1024; // 10 kibibytes
 const MAX_ADD_APP_REQUEST_SIZE: u64 = 100 * 1024; // 100 kibibytes
 
+// Lets the server reject uploads that got corrupted (e.g., truncated) on the way.
+const CONTENT_SHA256_HEADER: &str = "X-Content-Sha256";
+
+// SSE event names of the server's /events.
+const UPLOAD_EVENT: &str = "upload";
+const LAGGED_EVENT: &str = "lagged";
+
 #[derive(Clone)]
 pub struct HttpClient {
     server_addr: String,
@@ -61,6 +73,16 @@ pub struct PairingStatus {
     pub notification_target: Option<NotificationTarget>,
 }
 
+/// A file uploaded to one of the cameras (group_name), as sent by the server's /events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadEvent {
+    pub camera: String,
+    pub filename: String,
+    /// Id of the event, to pass to the next wait_for_upload_event().
+    #[serde(skip)]
+    pub id: String,
+}
+
 const TRUSTED_IOS_RELAY_HOSTS: &[&str] = &["relay.secluso.com", "testing-relay.secluso.com"];
 
 //TODO: There's a lot of repitition between the functions here.
@@ -371,6 +393,7 @@ impl HttpClient {
 
         let server_url = format!("{}/{}/{}/{}", self.server_addr, group_name, enc_file_name, num_apps);
 
+        let digest = Self::file_sha256_hex(enc_file_path)?;
         let file = File::open(enc_file_path)?;
         let reader = BufReader::new(file);
 
@@ -382,6 +405,7 @@ impl HttpClient {
         let response = self.authorized_headers(client
             .post(server_url))
             .header("Content-Type", "application/octet-stream")
+            .header(CONTENT_SHA256_HEADER, digest)
             .body(Body::new(reader))
             .send()
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
@@ -400,6 +424,17 @@ impl HttpClient {
         Ok(())
     }
 
+    fn file_sha256_hex(path: &Path) -> io::Result<String> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect())
+    }
+
     /// Fetches an (encrypted) video file or thumbnail, persists it, and then deletes it from the server.
     pub fn fetch_enc_file(
         &self, group_name: &str,
@@ -481,6 +516,8 @@ impl HttpClient {
         Ok(())
     }
 
+    /// Deletes the camera's directory on the server.
+    /// Fails with NotFound if the server has no directory for group_name (e.g., already deleted).
     pub fn deregister(&self, group_name: &str) -> io::Result<()> {
         let server_url = format!("{}/{}", self.server_addr, group_name);
 
@@ -494,6 +531,13 @@ impl HttpClient {
             Self::give_hint_to_updater();
         }
 
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Server error: {}", response.status()),
+            ));
+        }
+
         if !response.status().is_success() {
             return Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -504,6 +548,96 @@ impl HttpClient {
         Ok(())
     }
 
+    /// Waits for a file to be uploaded to any of the cameras (for apps without push notifications).
+    /// last_event_id is the id of the last event returned, so that the uploads in between
+    /// two calls aren't missed. None the first time (after checking for new files with bulkCheck).
+    /// Returns None if nothing was uploaded within timeout.
+    /// Fails if the server missed some uploads, which then need to be checked for (bulkCheck).
+    pub fn wait_for_upload_event(
+        &self,
+        last_event_id: Option<&str>,
+        timeout: Duration,
+    ) -> io::Result<Option<UploadEvent>> {
+        let server_url = format!("{}/events", self.server_addr);
+
+        let client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        let mut request = self.authorized_headers(client.get(&server_url));
+        if let Some(last_event_id) = last_event_id {
+            request = request.header("Last-Event-ID", last_event_id);
+        }
+        let response = match request.send() {
+            Ok(response) => response,
+            Err(e) if e.is_timeout() => return Ok(None),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
+        };
+
+        if response.status() == StatusCode::CONFLICT {
+            Self::give_hint_to_updater();
+        }
+
+        if !response.status().is_success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Server error: {}", response.status()),
+            ));
+        }
+
+        let reader = BufReader::new(response.take(MAX_CHECK_RESP_SIZE));
+        let mut event = None;
+        let mut data = None;
+        let mut id = String::new();
+
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) if Self::is_timeout(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+
+            if let Some(name) = line.strip_prefix("event:") {
+                event = Some(name.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("id:") {
+                id = value.trim().to_string();
+            } else if line.is_empty() {
+                match (event.as_deref(), data.take()) {
+                    (Some(UPLOAD_EVENT), Some(data)) => {
+                        let mut upload: UploadEvent = serde_json::from_str(&data).map_err(|e| {
+                            io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+                        })?;
+                        upload.id = id;
+                        return Ok(Some(upload));
+                    }
+                    (Some(LAGGED_EVENT), _) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            "Server missed some upload events",
+                        ));
+                    }
+                    _ => {}
+                }
+                event = None;
+                id.clear();
+            }
+        }
+
+        // The server closed the stream (e.g., it's shutting down).
+        Ok(None)
+    }
+
+    // Reading the body past the client timeout fails with the reqwest timeout error.
+    fn is_timeout(e: &io::Error) -> bool {
+        e.kind() == io::ErrorKind::TimedOut
+            || e.get_ref()
+                .and_then(|inner| inner.downcast_ref::<reqwest::Error>())
+                .is_some_and(|inner| inner.is_timeout())
+    }
+
     pub fn send_fcm_notification(&self, notification: Vec<u8>) -> io::Result<()> {
         let server_url = format!("{}/fcm_notification", self.server_addr);
 
@@ -530,6 +664,7 @@ impl HttpClient {
     }
 
     /// Start a livestream session
+    /// Tells the camera (through the server) which livestream chunk format we decode.
     pub fn livestream_start(&self, group_name: &str) -> io::Result<()> {
         let server_url = format!("{}/livestream/{}", self.server_addr, group_name);
 
@@ -537,6 +672,7 @@ impl HttpClient {
         let response = self.authorized_headers(client
             .post(server_url))
             .header("Content-Type", "application/octet-stream")
+            .header(LIVESTREAM_CHUNK_FORMAT_HEADER, LIVESTREAM_CHUNK_FORMAT.to_string())
             .send()
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
 
@@ -555,7 +691,8 @@ impl HttpClient {
     }
 
     /// Checks to see if there's a livestream request.
-    pub fn livestream_check(&self, group_name: &str) -> io::Result<()> {
+    /// Returns the livestream chunk format requested by the app (see livestream_chunk).
+    pub fn livestream_check(&self, group_name: &str) -> io::Result<u8> {
         let max_size = MAX_CHECK_RESP_SIZE;
 
         let server_url = format!("{}/livestream/{}", self.server_addr, group_name);
@@ -595,8 +732,8 @@ impl HttpClient {
 
         for line in reader.lines() {
             let line = line?;
-            if line.starts_with("data:") {
-                return Ok(());
+            if let Some(data) = line.strip_prefix("data:") {
+                return Ok(parse_chunk_format(data));
             }
         }
 
@@ -715,6 +852,104 @@ impl HttpClient {
         Ok(response_vec)
     }
 
+    /// Uploads an (encrypted) talkback chunk for the camera.
+    /// Returns the number of talkback chunks the camera hasn't retrieved yet.
+    pub fn livestream_audio_upload(
+        &self,
+        group_name: &str,
+        data: Vec<u8>,
+        chunk_number: u64,
+    ) -> io::Result<usize> {
+        let server_url = format!(
+            "{}/livestream_audio/{}/{}",
+            self.server_addr, group_name, chunk_number
+        );
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        let response = self.authorized_headers(client
+            .post(server_url))
+            .header("Content-Type", "application/octet-stream")
+            .body(data)
+            .send()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        if response.status() == StatusCode::CONFLICT {
+            Self::give_hint_to_updater();
+        }
+
+        if !response.status().is_success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Server error: {}", response.status()),
+            ));
+        }
+
+        let num_files: usize = response
+            .text()
+            .map_err(|e: reqwest::Error| io::Error::new(io::ErrorKind::Other, e.to_string()))?
+            .parse()
+            .map_err(|e: std::num::ParseIntError| {
+                io::Error::new(io::ErrorKind::Other, e.to_string())
+            })?;
+
+        Ok(num_files)
+    }
+
+    /// Retrieves (and removes from the server) an (encrypted) talkback chunk.
+    /// Returns None if the chunk hasn't been uploaded (yet).
+    pub fn livestream_audio_retrieve(
+        &self,
+        group_name: &str,
+        chunk_number: u64,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let max_size = MAX_TALKBACK_FILE_SIZE;
+
+        let server_url = format!(
+            "{}/livestream_audio/{}/{}",
+            self.server_addr, group_name, chunk_number
+        );
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        let response = self.authorized_headers(client
+            .get(&server_url))
+            .send()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        if response.status() == StatusCode::CONFLICT {
+            Self::give_hint_to_updater();
+        }
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Server error: {}", response.status()),
+            ));
+        }
+
+        let mut response_vec = Vec::new();
+        let mut limited = response.take(max_size);
+
+        limited.read_to_end(&mut response_vec)?;
+
+        if response_vec.len() >= max_size.try_into().unwrap() {
+            return Err(io::Error::new(io::ErrorKind::Other, "Talkback chunk download exceeded maximum allowed size"));
+        }
+
+        Ok(Some(response_vec))
+    }
+
     /// End a livestream session
     // FIXME: shares a lot of code with livestream_start
     pub fn livestream_end(&self, group_name: &str) -> io::Result<()> {
@@ -779,8 +1014,14 @@ impl HttpClient {
 
     /// Checks to see if there's a config command.
     /// The server sends the command encoded in Base64.
-    /// This function converts the command to Vec<u8> to returns it.
-    pub fn config_check(&self, group_name: &str) -> io::Result<Vec<u8>> {
+    /// This function converts the command to Vec<u8> to returns it, with its id.
+    /// The server keeps sending the same command until it's acknowledged, by passing its id
+    /// as last_command_id in the next check.
+    pub fn config_check(
+        &self,
+        group_name: &str,
+        last_command_id: Option<&str>,
+    ) -> io::Result<(Vec<u8>, String)> {
         let max_size = MAX_CHECK_RESP_SIZE;
 
         let server_url = format!("{}/config/{}", self.server_addr, group_name);
@@ -790,8 +1031,11 @@ impl HttpClient {
             .build()
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
 
-        let response = self.authorized_headers(client
-            .get(&server_url))
+        let mut request = self.authorized_headers(client.get(&server_url));
+        if let Some(last_command_id) = last_command_id {
+            request = request.header("Last-Event-ID", last_command_id);
+        }
+        let response = request
             .send()
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
 
@@ -817,15 +1061,24 @@ impl HttpClient {
             ));
         }
         let reader = BufReader::new(&buf[..]);
+        let mut command = None;
+        let mut id = None;
 
         for line in reader.lines() {
             let line = line?;
             if line.starts_with("data:") {
                 let encoded_command = &line[5..];
-                let command = base64_engine
-                    .decode(encoded_command)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                return Ok(command);
+                command = Some(
+                    base64_engine
+                        .decode(encoded_command)
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?,
+                );
+            } else if let Some(value) = line.strip_prefix("id:") {
+                id = Some(value.trim().to_string());
+            } else if line.is_empty() {
+                if let (Some(command), Some(id)) = (command.take(), id.take()) {
+                    return Ok((command, id));
+                }
             }
         }
 
@@ -971,7 +1224,9 @@ impl HttpClient {
 
 #[cfg(test)]
 mod tests {
-    use super::{validate_ios_relay_base_url, validate_ios_relay_binding, IosRelayBinding};
+    use super::{
+        validate_ios_relay_base_url, validate_ios_relay_binding, HttpClient, IosRelayBinding,
+    };
 
     // Build an otherwise-valid relay binding and let each test vary only the relay base URL it wants to validate.
     fn ios_binding(relay_base_url: &str) -> IosRelayBinding {
@@ -1011,4 +1266,19 @@ mod tests {
 
         assert!(err.to_string().contains("iOS relay base URL is required"));
     }
+
+    #[test]
+    // Tests that uploads carry the hex SHA-256 digest of the whole file (checked by the server).
+    fn upload_digest_is_hex_sha256_of_file() {
+        let path = std::env::temp_dir().join(format!("secluso-digest-{}", std::process::id()));
+        std::fs::write(&path, b"test").unwrap();
+
+        let digest = HttpClient::file_sha256_hex(&path).unwrap();
+        assert_eq!(
+            digest,
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -8,10 +8,30 @@
 use openmls::prelude::*;
 use openmls_basic_credential::SignatureKeyPair;
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Write};
 
 use super::openmls_rust_persistent_crypto::OpenMlsRustPersistentCrypto;
 
+// DER prefix of an Ed25519 SubjectPublicKeyInfo (RFC 8410), followed by the 32-byte key.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
+/// DER encoding of a raw Ed25519 (signature) public key.
+pub fn ed25519_public_key_der(public_key: &[u8]) -> io::Result<Vec<u8>> {
+    if public_key.len() != ED25519_PUBLIC_KEY_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid Ed25519 public key length ({})", public_key.len()),
+        ));
+    }
+
+    let mut der = ED25519_SPKI_PREFIX.to_vec();
+    der.extend_from_slice(public_key);
+    Ok(der)
+}
+
 pub struct Identity {
     pub(crate) kp: KeyPackage,
     pub(crate) credential_with_key: CredentialWithKey,
@@ -105,6 +125,11 @@ impl Identity {
             .to_string()
     }
 
+    /// The signature public key of this identity, DER encoded.
+    pub fn public_key_der(&self) -> io::Result<Vec<u8>> {
+        ed25519_public_key_der(self.signer.public())
+    }
+
     pub fn delete_signature_key(&self, file_dir: String, tag: String) {
         let pathname = file_dir + "/signature_key_" + &tag;
         let _ = fs::remove_file(pathname);
@@ -1,11 +1,18 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod camera_status;
 pub mod config;
+pub mod fcm_message;
+pub mod heartbeat_tracker;
 pub mod identity;
+pub mod livestream_buffer;
+pub mod livestream_chunk;
 pub mod mls_client;
 pub mod mls_clients;
+pub mod notification_schedule;
 pub mod openmls_rust_persistent_crypto;
 pub mod pairing;
+pub mod talkback;
 pub mod tests;
 pub mod thumbnail_meta_info;
 pub mod video_net_info;
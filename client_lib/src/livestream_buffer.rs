@@ -0,0 +1,84 @@
+//! Reorder buffer for livestream chunks, so that a chunk delivered a bit late doesn't end the
+//! livestream. Chunks that arrive ahead of the one to be played next are held (up to capacity
+//! chunks ahead) and handed out in order.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+use std::io;
+
+/// Chunks held by default: about a second of video at the camera's chunk rate.
+pub const DEFAULT_LIVESTREAM_BUFFER_CAPACITY: usize = 8;
+
+pub struct LivestreamBuffer {
+    capacity: usize,
+    // Decrypted chunks ahead of the next one to be played, by chunk number.
+    pending: BTreeMap<u64, Vec<u8>>,
+}
+
+impl LivestreamBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Takes a decrypted chunk, when expected_chunk_number is the next chunk to be played.
+    /// Returns the data of the expected chunk if it's available (this chunk or a held one).
+    /// Fails if the chunk was already played, or if it's more than capacity chunks ahead.
+    pub fn push(
+        &mut self,
+        expected_chunk_number: u64,
+        chunk_number: u64,
+        data: Vec<u8>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        if chunk_number == expected_chunk_number {
+            return Ok(Some(data));
+        }
+
+        if chunk_number < expected_chunk_number {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Error: chunk {chunk_number} arrived after chunk {expected_chunk_number} was expected!"
+                ),
+            ));
+        }
+
+        // Chunks that far ahead mean that the expected one is most likely lost.
+        if chunk_number - expected_chunk_number > self.capacity as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Error: chunk {chunk_number} is too far ahead of chunk {expected_chunk_number}!"
+                ),
+            ));
+        }
+
+        self.pending.insert(chunk_number, data);
+        Ok(self.take(expected_chunk_number))
+    }
+
+    /// The expected chunk, if it was received ahead of time.
+    /// Chunks before it are dropped (the caller skipped them).
+    pub fn take(&mut self, expected_chunk_number: u64) -> Option<Vec<u8>> {
+        self.pending = self.pending.split_off(&expected_chunk_number);
+        self.pending.remove(&expected_chunk_number)
+    }
+
+    /// Number of chunks held.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Default for LivestreamBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIVESTREAM_BUFFER_CAPACITY)
+    }
+}
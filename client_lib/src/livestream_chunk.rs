@@ -0,0 +1,74 @@
+//! Format of the (decrypted) livestream chunks sent by the camera.
+//! Every chunk starts with its chunk number (8 bytes, big endian), so that a malicious server
+//! can't reorder the chunks without the app noticing.
+//!
+//! Format 0 (legacy): chunk number, then the video data.
+//! Format 1: chunk number with CHUNK_NUMBER_FLAG set, the quality level (1 byte), then the video data.
+//! Apps that can decode format 1 ask for it when they start the livestream
+//! (LIVESTREAM_CHUNK_FORMAT_HEADER), and the camera uses format 0 otherwise,
+//! so that older apps keep working. The flag tells the formats apart when decoding.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::io;
+
+pub const LIVESTREAM_CHUNK_FORMAT_LEGACY: u8 = 0;
+pub const LIVESTREAM_CHUNK_FORMAT_QUALITY: u8 = 1;
+/// Newest format that this version can encode and decode.
+pub const LIVESTREAM_CHUNK_FORMAT: u8 = LIVESTREAM_CHUNK_FORMAT_QUALITY;
+
+/// Sent by the app to the server when starting a livestream, with the newest format it decodes.
+/// The server passes it on to the camera.
+pub const LIVESTREAM_CHUNK_FORMAT_HEADER: &str = "X-Livestream-Chunk-Format";
+
+// Chunk numbers never get this high, so format 0 chunks never have it.
+const CHUNK_NUMBER_FLAG: u64 = 1 << 63;
+
+/// Parses the format sent by the app (through the server) when starting a livestream.
+/// Anything else (e.g., from an older app or server) means the legacy format.
+pub fn parse_chunk_format(value: &str) -> u8 {
+    value
+        .trim()
+        .parse::<u8>()
+        .map(|format| format.min(LIVESTREAM_CHUNK_FORMAT))
+        .unwrap_or(LIVESTREAM_CHUNK_FORMAT_LEGACY)
+}
+
+pub fn encode_chunk(format: u8, chunk_number: u64, quality_level: u8, data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(9 + data.len());
+    if format >= LIVESTREAM_CHUNK_FORMAT_QUALITY {
+        chunk.extend((chunk_number | CHUNK_NUMBER_FLAG).to_be_bytes());
+        chunk.push(quality_level);
+    } else {
+        chunk.extend(chunk_number.to_be_bytes());
+    }
+    chunk.extend_from_slice(data);
+    chunk
+}
+
+/// Returns the chunk number, the quality level (0, i.e., full quality, for format 0), and the data.
+pub fn decode_chunk(chunk: &[u8]) -> io::Result<(u64, u8, &[u8])> {
+    if chunk.len() < 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Error: too few bytes!".to_string(),
+        ));
+    }
+
+    let chunk_number = u64::from_be_bytes(chunk[..8].try_into().unwrap());
+    if chunk_number & CHUNK_NUMBER_FLAG == 0 {
+        return Ok((chunk_number, 0, &chunk[8..]));
+    }
+
+    match chunk.get(8) {
+        Some(&quality_level) => Ok((
+            chunk_number & !CHUNK_NUMBER_FLAG,
+            quality_level,
+            &chunk[9..],
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Error: too few bytes!".to_string(),
+        )),
+    }
+}
@@ -5,9 +5,12 @@
 //! Based on the OpenMLS client (openmls/cli).
 //! MIT License.
 
-use super::identity::Identity;
+use super::identity::{ed25519_public_key_der, Identity};
 use super::openmls_rust_persistent_crypto::OpenMlsRustPersistentCrypto;
 use openmls_traits::{storage::StorageProvider as StorageProviderTrait};
+use openmls_traits::crypto::OpenMlsCrypto;
+use openmls_traits::random::OpenMlsRand;
+use openmls_traits::types::AeadType;
 use crate::pairing;
 use openmls::prelude::*;
 use openmls::schedule::{ExternalPsk, PreSharedKeyId, Psk};
@@ -17,6 +20,7 @@ use std::io;
 use std::io::{BufRead, BufReader, Write, Read};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::cmp;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use tls_codec::{Deserialize as TlsDeserialize, Serialize as TlsSerialize};
 
@@ -30,6 +34,24 @@ const CURRENT_FILE: &str = "CURRENT";
 const GROUP_STATE_FILENAME: &str = "group_state";
 const KEY_STORE_FILENAME: &str = "key_store";
 
+// Exported state: STATE_EXPORT_MAGIC || salt || nonce || ciphertext.
+// The header (magic and salt) is also authenticated as associated data.
+const STATE_EXPORT_MAGIC: &[u8] = b"secluso-mls-state-v1";
+const STATE_EXPORT_SALT_LEN: usize = 16;
+const STATE_EXPORT_NONCE_LEN: usize = 12;
+const STATE_EXPORT_KEY_LEN: usize = 32;
+
+// Upper bound on how much larger than its plaintext an application message gets once encrypted
+// (MLS framing, sender data, signature and AEAD tags), so that encrypt_bounded() can reject
+// a message before encrypting it.
+pub const MAX_MESSAGE_OVERHEAD: u64 = 1024;
+
+/// Maximum number of apps in a group, in addition to the camera.
+#[cfg(not(feature = "multi_app_groups"))]
+pub const MAX_APPS_PER_GROUP: usize = 2;
+#[cfg(feature = "multi_app_groups")]
+pub const MAX_APPS_PER_GROUP: usize = 8;
+
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Contact {
     username: String,
@@ -103,12 +125,87 @@ impl Group {
     }
 }
 
+/// Everything needed to restore an MlsClient on another device.
+#[derive(Serialize, Deserialize)]
+struct ExportedState {
+    username: String,
+    // Contents of the signature_key_<tag> file (the public key, used to find the key pair in the key store).
+    signature_key: Vec<u8>,
+    group_state: Vec<u8>,
+    key_store: Vec<u8>,
+}
+
 #[derive(PartialEq)]
 pub enum ClientType {
     Camera,
     App,
 }
 
+/// Why decrypt() failed, for the failures that callers handle differently.
+/// It's carried inside the returned io::Error. Use DecryptError::of() to get it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecryptError {
+    /// The secret for this message was already used (and deleted), e.g., the message was
+    /// decrypted before but the updated group state wasn't kept.
+    SecretReuse(String),
+    /// The message is from a different epoch than the group's.
+    WrongEpoch(String),
+    /// The message is malformed or failed validation (e.g., bad ciphertext, signature or AAD).
+    Validation(String),
+}
+
+impl DecryptError {
+    /// Returns the DecryptError inside an error returned by decrypt(), if any.
+    pub fn of(e: &io::Error) -> Option<&DecryptError> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<DecryptError>())
+    }
+
+    /// Whether rolling back the group state and decrypting again could work.
+    /// A message that failed validation will fail again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DecryptError::SecretReuse(_) | DecryptError::WrongEpoch(_))
+    }
+
+    fn from_process_message_error(e: &ProcessMessageError) -> Self {
+        let message = format!("Error processing unverified message: {:?} -  Dropping message.", e);
+        match e {
+            ProcessMessageError::ValidationError(ValidationError::UnableToDecrypt(
+                MessageDecryptionError::SecretTreeError(SecretTreeError::SecretReuseError),
+            )) => DecryptError::SecretReuse(message),
+            ProcessMessageError::ValidationError(ValidationError::WrongEpoch) => {
+                DecryptError::WrongEpoch(message)
+            }
+            _ => DecryptError::Validation(message),
+        }
+    }
+
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            DecryptError::SecretReuse(_) => io::ErrorKind::AlreadyExists,
+            DecryptError::WrongEpoch(_) => io::ErrorKind::InvalidInput,
+            DecryptError::Validation(_) => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::SecretReuse(message)
+            | DecryptError::WrongEpoch(message)
+            | DecryptError::Validation(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+impl From<DecryptError> for io::Error {
+    fn from(e: DecryptError) -> Self {
+        io::Error::new(e.kind(), e)
+    }
+}
+
 pub struct MlsClient {
     pub(crate) group: Option<Group>,
     pub(crate) identity: Identity,
@@ -259,10 +356,11 @@ impl MlsClient {
         let group = self.group.as_mut().unwrap();
 
         #[cfg(not(test))] {
-            // For now, we allow two apps only.
             // We allow more apps for tests.
-            if group.contacts.len() >= 2 {
-                return Err(io::Error::other("Cannot invite more than two apps".to_string()));
+            if group.contacts.len() >= MAX_APPS_PER_GROUP {
+                return Err(io::Error::other(format!(
+                    "Cannot invite more than {MAX_APPS_PER_GROUP} apps"
+                )));
             }
         }
 
@@ -378,7 +476,13 @@ impl MlsClient {
                 .into_group(&self.provider)
                 .map_err(|e| io::Error::other(format!("Failed to create MlsGroup - {e}")))?;
 
-        let is_admin = mls_group.members().count() == 2;
+        // The camera invites the admin app first, so it has the lowest leaf index after the camera.
+        // members() iterates in leaf order.
+        let is_admin = mls_group
+            .members()
+            .map(|member| BasicCredential::try_from(member.credential).unwrap())
+            .find(|credential| credential.identity() != expected_inviter.id.as_slice())
+            .is_some_and(|credential| credential.identity() == self.identity.identity());
 
         // Check to ensure the welcome message is from the contact we expect.
         // Also check the other group member (which should be us).
@@ -553,9 +657,10 @@ impl MlsClient {
     ///   self.file_dir/self.tag/CURRENT  (contains "v<version>")
     ///
     /// Atomicity guarantee:
-    /// - The new version becomes visible only when CURRENT is switched.
-    /// - If crash occurs before CURRENT rename, restore sees the old version.
-    /// - If CURRENT is switched, both files are already written+fsynced in that version directory.
+    /// - Both files are written+fsynced under temporary names, then renamed in place.
+    /// - The new version becomes visible only when CURRENT is switched, after both renames.
+    /// - If crash occurs before CURRENT rename, restore sees the old version (both files).
+    /// - The old version is deleted only once CURRENT is switched.
     pub fn save_group_state(
         &mut self
     ) -> io::Result<()> {
@@ -571,6 +676,8 @@ impl MlsClient {
 
         let g_path = new_dir.join(GROUP_STATE_FILENAME);
         let ks_path = new_dir.join(KEY_STORE_FILENAME);
+        let g_tmp_path = new_dir.join(format!(".{GROUP_STATE_FILENAME}.tmp"));
+        let ks_tmp_path = new_dir.join(format!(".{KEY_STORE_FILENAME}.tmp"));
 
         let group_helper_option = self.group.as_ref().map(|group| GroupHelper {
             group_name: group.group_name.clone(),
@@ -581,10 +688,7 @@ impl MlsClient {
 
         let data = bincode::serialize(&group_helper_option)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        let mut g_file = File::create(g_path)?;
-        g_file.write_all(&data)?;
-        g_file.flush()?;
-        g_file.sync_all()?;
+        Self::write_and_fsync(&g_tmp_path, &data)?;
 
         #[cfg(test)]
         {
@@ -593,12 +697,22 @@ impl MlsClient {
             }
         }
 
-        let mut ks_file = File::create(ks_path)?;
+        let mut ks_file = File::create(&ks_tmp_path)?;
         self.provider.save_keystore(&ks_file)
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
         ks_file.flush()?;
         ks_file.sync_all()?;
 
+        fs::rename(&g_tmp_path, &g_path)?;
+
+        #[cfg(test)]
+        {
+            if std::env::var("SAVE_GROUP_STATE_RENAME_CRASH").is_ok() {
+                return Ok(());
+            }
+        }
+
+        fs::rename(&ks_tmp_path, &ks_path)?;
         Self::fsync_dir(&new_dir)?;
 
         Self::write_current_atomic(&state_dir_path, &version)?;
@@ -609,6 +723,46 @@ impl MlsClient {
         Ok(())
     }
 
+    /// Drops provider storage that is no longer needed and returns the number of bytes reclaimed.
+    /// The caller should call save_group_state() afterwards to persist the smaller key store.
+    ///
+    /// A new key package is generated (and stored) every time the client is restored
+    /// or hands out a key package, but each client only ever joins one group.
+    /// Once the group exists, all key packages other than the current one are stale.
+    /// Epoch secrets are not touched here: OpenMLS already prunes past epochs
+    /// according to the group's configuration, and removing them would break
+    /// decryption of files within the out-of-epoch tolerance window.
+    /// Pending proposals are not touched either since they are still needed for
+    /// the next commit.
+    pub fn compact_storage(&mut self) -> io::Result<usize> {
+        if self.group.is_none() {
+            // Key packages might still be needed to process a welcome message.
+            return Ok(0);
+        }
+
+        let size_before = self.provider.storage_size();
+
+        let keep = self
+            .identity
+            .kp
+            .hash_ref(self.provider.crypto())
+            .map_err(|e| io::Error::other(format!("Failed to compute key package ref - {e}")))?;
+        let own_leaf = self.group.as_ref().and_then(|g| g.mls_group.own_leaf_node());
+        let num_deleted = self
+            .provider
+            .delete_key_packages_except(&keep, own_leaf)
+            .map_err(io::Error::other)?;
+
+        let size_after = self.provider.storage_size();
+        log::debug!(
+            "Compacted storage: deleted {} key packages, reclaimed {} bytes",
+            num_deleted,
+            size_before.saturating_sub(size_after)
+        );
+
+        Ok(size_before.saturating_sub(size_after))
+    }
+
     fn restore_group_state(
         file_dir: String,
         tag: String,
@@ -661,6 +815,140 @@ impl MlsClient {
         }
     }
 
+    fn signature_key_path(file_dir: &str, tag: &str) -> String {
+        format!("{file_dir}/signature_key_{tag}")
+    }
+
+    /// Derives the key used to encrypt the exported state from the passphrase.
+    fn derive_export_key(passphrase: &str, salt: &[u8]) -> io::Result<Vec<u8>> {
+        let mut key = vec![0u8; STATE_EXPORT_KEY_LEN];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| io::Error::other(format!("Failed to derive key from passphrase - {e}")))?;
+        Ok(key)
+    }
+
+    /// Exports the group state, the key store, and the identity of this client in one blob
+    /// encrypted under the passphrase, to be restored with import_state() on a new device.
+    /// The state is saved first so that the blob matches what's on disk.
+    ///
+    /// Once the state is imported elsewhere, this client must no longer be used: the two copies
+    /// would diverge as soon as either of them processes a commit.
+    pub fn export_state(&mut self, passphrase: &str) -> io::Result<Vec<u8>> {
+        if passphrase.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Passphrase must not be empty",
+            ));
+        }
+
+        self.save_group_state()?;
+
+        let state_dir_path = Path::new(&self.file_dir).join(&self.tag);
+        let version_dir = state_dir_path.join(Self::read_current(&state_dir_path)?);
+        let state = ExportedState {
+            username: self.identity.identity_as_string(),
+            signature_key: fs::read(Self::signature_key_path(&self.file_dir, &self.tag))?,
+            group_state: fs::read(version_dir.join(GROUP_STATE_FILENAME))?,
+            key_store: fs::read(version_dir.join(KEY_STORE_FILENAME))?,
+        };
+        let plaintext = bincode::serialize(&state)
+            .map_err(|e| io::Error::other(format!("Failed to serialize state - {e}")))?;
+
+        let crypto = self.provider.crypto();
+        let salt = self
+            .provider
+            .rand()
+            .random_vec(STATE_EXPORT_SALT_LEN)
+            .map_err(|e| io::Error::other(format!("Failed to generate salt - {e:?}")))?;
+        let nonce = self
+            .provider
+            .rand()
+            .random_vec(STATE_EXPORT_NONCE_LEN)
+            .map_err(|e| io::Error::other(format!("Failed to generate nonce - {e:?}")))?;
+        let key = Self::derive_export_key(passphrase, &salt)?;
+
+        let mut blob = STATE_EXPORT_MAGIC.to_vec();
+        blob.extend_from_slice(&salt);
+        let ciphertext = crypto
+            .aead_encrypt(AeadType::ChaCha20Poly1305, &key, &plaintext, &nonce, &blob)
+            .map_err(|e| io::Error::other(format!("Failed to encrypt state - {e:?}")))?;
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&ciphertext);
+
+        Ok(blob)
+    }
+
+    /// Restores a blob generated by export_state() into file_dir for tag, which must not have
+    /// any state yet. Returns the username, which is needed to create the client afterwards
+    /// with MlsClient::new(username, false, file_dir, tag, client_type).
+    pub fn import_state(
+        blob: &[u8],
+        passphrase: &str,
+        file_dir: String,
+        tag: String,
+    ) -> io::Result<String> {
+        let header_len = STATE_EXPORT_MAGIC.len() + STATE_EXPORT_SALT_LEN;
+        if blob.len() < header_len + STATE_EXPORT_NONCE_LEN || !blob.starts_with(STATE_EXPORT_MAGIC)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not an exported state",
+            ));
+        }
+        let (header, rest) = blob.split_at(header_len);
+        let (nonce, ciphertext) = rest.split_at(STATE_EXPORT_NONCE_LEN);
+        let salt = &header[STATE_EXPORT_MAGIC.len()..];
+
+        let key = Self::derive_export_key(passphrase, salt)?;
+        let crypto = OpenMlsRustPersistentCrypto::default();
+        let plaintext = crypto
+            .crypto()
+            .aead_decrypt(AeadType::ChaCha20Poly1305, &key, ciphertext, nonce, header)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Failed to decrypt state (wrong passphrase or corrupted data)",
+                )
+            })?;
+        let state: ExportedState = bincode::deserialize(&plaintext)
+            .map_err(|e| io::Error::other(format!("Failed to deserialize state - {e}")))?;
+
+        let file_dir_path = Path::new(&file_dir);
+        let state_dir_path = file_dir_path.join(&tag);
+        if state_dir_path.join(CURRENT_FILE).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already has a state", state_dir_path.display()),
+            ));
+        }
+        if !state_dir_path.exists() {
+            fs::create_dir(&state_dir_path)?;
+            Self::fsync_dir(file_dir_path)?;
+        }
+
+        let version = Self::next_version(&state_dir_path)?;
+        let version_dir = state_dir_path.join(&version);
+        if !version_dir.exists() {
+            fs::create_dir(&version_dir)?;
+            Self::fsync_dir(&state_dir_path)?;
+        }
+        Self::write_and_fsync(&version_dir.join(GROUP_STATE_FILENAME), &state.group_state)?;
+        Self::write_and_fsync(&version_dir.join(KEY_STORE_FILENAME), &state.key_store)?;
+        Self::fsync_dir(&version_dir)?;
+
+        Self::write_and_fsync(
+            Path::new(&Self::signature_key_path(&file_dir, &tag)),
+            &state.signature_key,
+        )?;
+        Self::fsync_dir(file_dir_path)?;
+
+        // The state becomes visible only once CURRENT is written (see save_group_state()).
+        Self::write_current_atomic(&state_dir_path, &version)?;
+
+        Ok(state.username)
+    }
+
     pub fn create_contact(name: &str, key_package: KeyPackage) -> io::Result<Contact> {
         let id = key_package
             .leaf_node()
@@ -687,6 +975,69 @@ impl MlsClient {
         }
     }
 
+    /// Whether we're the admin app of the group, i.e., the first app the camera invited.
+    /// Always false for the camera.
+    pub fn is_admin(&self) -> io::Result<bool> {
+        match &self.group {
+            Some(g) => Ok(g.is_admin),
+
+            None => Err(io::Error::other("Group not created yet".to_string())),
+        }
+    }
+
+    /// Our signature public key (DER-encoded Ed25519), e.g., for the camera to show
+    /// so that the user can compare it with the one the app has for it.
+    pub fn get_own_public_key(&self) -> io::Result<Vec<u8>> {
+        self.identity.public_key_der()
+    }
+
+    /// The signature public key (DER-encoded Ed25519) of the camera that the app paired with,
+    /// as in the group's ratchet tree. Used to verify the camera out of band.
+    pub fn get_contact_public_key(&self) -> io::Result<Vec<u8>> {
+        if self.client_type != ClientType::App {
+            return Err(io::Error::other(
+                "Only the app has a camera contact".to_string(),
+            ));
+        }
+
+        let group = match &self.group {
+            Some(g) => g,
+            None => return Err(io::Error::other("Group not created yet".to_string())),
+        };
+
+        let contact = group
+            .contacts
+            .first()
+            .ok_or_else(|| io::Error::other("Group has no contact".to_string()))?;
+        let member = group
+            .mls_group
+            .members()
+            .find(|member| member.credential == contact.get_credential())
+            .ok_or_else(|| io::Error::other("Contact is not a member of the group".to_string()))?;
+
+        ed25519_public_key_der(&member.signature_key)
+    }
+
+    /// Name of the camera that the app paired with (the camera name given when pairing).
+    pub fn get_contact_name(&self) -> io::Result<String> {
+        if self.client_type != ClientType::App {
+            return Err(io::Error::other(
+                "Only the app has a camera contact".to_string(),
+            ));
+        }
+
+        let group = match &self.group {
+            Some(g) => g,
+            None => return Err(io::Error::other("Group not created yet".to_string())),
+        };
+
+        group
+            .contacts
+            .first()
+            .map(|contact| contact.username.clone())
+            .ok_or_else(|| io::Error::other("Group has no contact".to_string()))
+    }
+
     /// Generate a commit to update self leaf node in the ratchet tree, merge the commit, and return the message
     /// to be sent to other group members. It also returns the epoch number after the update.
     pub fn update(&mut self) -> io::Result<(Vec<u8>, u64)> {
@@ -866,6 +1217,44 @@ impl MlsClient {
         Ok(msg_vec)
     }
 
+    /// Encrypts a message like encrypt(), but returns an error if the
+    /// resulting ciphertext would be larger than max_size bytes.
+    /// See mls_clients::MAX_CIPHERTEXT_SIZES for the per-client limits.
+    ///
+    /// The plaintext is checked against max_size minus MAX_MESSAGE_OVERHEAD before
+    /// encrypting, so a rejected message doesn't change the group state. Only if the
+    /// ciphertext still turns out too large (i.e., the overhead was underestimated) is
+    /// the error returned after encrypting, in which case the ratchet has already
+    /// advanced past a message that will never be sent.
+    pub fn encrypt_bounded(&mut self, bytes: &[u8], max_size: u64) -> io::Result<Vec<u8>> {
+        if (bytes.len() as u64).saturating_add(MAX_MESSAGE_OVERHEAD) > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Plaintext ({} bytes) and encryption overhead ({} bytes) exceed the maximum message size ({} bytes)",
+                    bytes.len(),
+                    MAX_MESSAGE_OVERHEAD,
+                    max_size
+                ),
+            ));
+        }
+
+        let msg_vec = self.encrypt(bytes)?;
+
+        if msg_vec.len() as u64 > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Ciphertext ({} bytes) exceeds the maximum message size ({} bytes)",
+                    msg_vec.len(),
+                    max_size
+                ),
+            ));
+        }
+
+        Ok(msg_vec)
+    }
+
     fn find_matching_contact<'a>(
         processed_message: &ProcessedMessage,
         contacts: &'a mut Vec<Contact>
@@ -900,21 +1289,19 @@ impl MlsClient {
         // Instead, we return an error here and leave it to the caller to decide if the error
         // needs to be printed or not.
         if mls_group.epoch() != message.epoch() {
-            return Err(io::Error::other(format!(
+            return Err(DecryptError::WrongEpoch(format!(
                 "Error: message epoch ({}) must match the group epoch ({})",
                 message.epoch(),
                 mls_group.epoch()
-            )));
+            ))
+            .into());
         }
 
         let processed_message = match mls_group.process_message(&self.provider, message) {
             Ok(msg) => msg,
             Err(e) => {
                 log::debug!("process_message returned: {e}");
-                return Err(io::Error::other(format!(
-                    "Error processing unverified message: {:?} -  Dropping message.",
-                    e
-                )));
+                return Err(DecryptError::from_process_message_error(&e).into());
             }
         };
 
@@ -922,9 +1309,10 @@ impl MlsClient {
         let group_aad = group.group_name.clone() + " AAD";
 
         if processed_message.aad().to_vec() != group_aad.into_bytes() {
-            return Err(io::Error::other(
+            return Err(DecryptError::Validation(
                 "Error: received a message with an invalid AAD".to_string(),
-            ));
+            )
+            .into());
         }
 
         // Only accepts messages from one of our contacts.
@@ -1057,6 +1445,7 @@ impl MlsClient {
     /// application message (app_msg = true) or a commit message (app_msg = false).
     /// This function will return an error if the message type is different from
     /// what was provided as input.
+    /// Secret reuse, epoch mismatch and validation failures can be told apart with DecryptError::of().
     pub fn decrypt(
         &mut self,
         msg: Vec<u8>,
@@ -1065,7 +1454,7 @@ impl MlsClient {
         let mls_msg = match MlsMessageIn::tls_deserialize(&mut msg.as_slice()) {
             Ok(m) => m,
             Err(e) => {
-                return Err(io::Error::other(format!("Could not deserialize msg ({e})")));
+                return Err(DecryptError::Validation(format!("Could not deserialize msg ({e})")).into());
             }
         };
 
@@ -3,6 +3,9 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::mls_client::MlsClient;
+use secluso_client_server_lib::limits::{
+    MAX_COMMAND_FILE_SIZE, MAX_LIVESTREAM_FILE_SIZE, MAX_MOTION_FILE_SIZE, MAX_NOTIFICATION_SIZE,
+};
 
 pub const NUM_MLS_CLIENTS: usize = 5;
 pub static MLS_CLIENT_TAGS: [&str; NUM_MLS_CLIENTS] =
@@ -30,3 +33,14 @@ pub const CONFIG_DED: usize = 1;
 
 // Maximum time that we allow other group members to be offline (in seconds)
 pub const MAX_OFFLINE_WINDOW: u64 = 24 * 60 * 60;
+
+// Maximum size (in bytes) of a single encrypted message for each client.
+// These are the upload limits enforced by the delivery service so that
+// oversized payloads can be rejected before they are uploaded.
+pub static MAX_CIPHERTEXT_SIZES: [u64; NUM_MLS_CLIENTS] = [
+    MAX_MOTION_FILE_SIZE,     // motion
+    MAX_MOTION_FILE_SIZE,     // thumbnail
+    MAX_NOTIFICATION_SIZE,    // fcm
+    MAX_LIVESTREAM_FILE_SIZE, // livestream
+    MAX_COMMAND_FILE_SIZE,    // config
+];
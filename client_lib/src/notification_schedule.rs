@@ -0,0 +1,139 @@
+//! Schedule for disarming motion notifications (e.g., during work hours).
+//! Set by the app with a config command and evaluated by the camera.
+//! Recording and video upload are not affected.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use serde::{Deserialize, Serialize};
+use std::io;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+// UTC offsets range from UTC-12:00 to UTC+14:00.
+const MIN_UTC_OFFSET_MINUTES: i16 = -12 * 60;
+const MAX_UTC_OFFSET_MINUTES: i16 = 14 * 60;
+const MAX_WINDOWS: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Day of the week of the given day since the Unix epoch (a Thursday).
+    fn from_days_since_epoch(days: i64) -> Self {
+        Self::ALL[(days + 3).rem_euclid(7) as usize]
+    }
+
+    fn previous(self) -> Self {
+        Self::ALL[(self as usize + 6) % 7]
+    }
+}
+
+/// Notifications are disarmed from start_minute to end_minute (minutes since local midnight)
+/// on each of days. If end_minute is before start_minute, the window continues past midnight
+/// into the next day (e.g., 22:00-06:00). If they're equal, the window covers the whole day.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleWindow {
+    pub days: Vec<Weekday>,
+    pub start_minute: u16,
+    pub end_minute: u16,
+}
+
+impl ScheduleWindow {
+    fn contains(&self, day: Weekday, minute: u16) -> bool {
+        if self.start_minute < self.end_minute {
+            self.days.contains(&day) && (self.start_minute..self.end_minute).contains(&minute)
+        } else if self.start_minute > self.end_minute {
+            (self.days.contains(&day) && minute >= self.start_minute)
+                || (self.days.contains(&day.previous()) && minute < self.end_minute)
+        } else {
+            self.days.contains(&day)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NotificationSchedule {
+    /// When false, no motion notifications are sent at all.
+    pub armed: bool,
+    /// Offset of the user's local time from UTC, in minutes (e.g., -300 for UTC-5).
+    /// The camera's clock is UTC (set by the app during pairing), and there's no daylight
+    /// saving time here: the app sends an updated offset when it changes.
+    pub utc_offset_minutes: i16,
+    pub disarmed_windows: Vec<ScheduleWindow>,
+}
+
+impl Default for NotificationSchedule {
+    fn default() -> Self {
+        Self {
+            armed: true,
+            utc_offset_minutes: 0,
+            disarmed_windows: vec![],
+        }
+    }
+}
+
+impl NotificationSchedule {
+    pub fn validate(&self) -> io::Result<()> {
+        if !(MIN_UTC_OFFSET_MINUTES..=MAX_UTC_OFFSET_MINUTES).contains(&self.utc_offset_minutes) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid UTC offset ({} minutes)", self.utc_offset_minutes),
+            ));
+        }
+
+        if self.disarmed_windows.len() > MAX_WINDOWS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Too many schedule windows (at most {MAX_WINDOWS})"),
+            ));
+        }
+
+        for window in &self.disarmed_windows {
+            if window.start_minute >= MINUTES_PER_DAY || window.end_minute >= MINUTES_PER_DAY {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid schedule window ({}-{}): minutes must be less than {MINUTES_PER_DAY}",
+                        window.start_minute, window.end_minute
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether a motion notification may be sent at the given Unix time (in seconds).
+    pub fn notifications_allowed(&self, unix_secs: u64) -> bool {
+        if !self.armed {
+            return false;
+        }
+
+        let local_secs = unix_secs as i64 + i64::from(self.utc_offset_minutes) * 60;
+        let day = Weekday::from_days_since_epoch(local_secs.div_euclid(SECS_PER_DAY));
+        let minute = (local_secs.rem_euclid(SECS_PER_DAY) / 60) as u16;
+
+        !self
+            .disarmed_windows
+            .iter()
+            .any(|window| window.contains(day, minute))
+    }
+}
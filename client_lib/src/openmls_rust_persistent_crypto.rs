@@ -10,11 +10,16 @@
 //! This is an implementation of the [`OpenMlsCryptoProvider`] trait to use with
 //! OpenMLS.
 
+use openmls::prelude::{KeyPackageBundle, KeyPackageRef, LeafNode};
 use openmls_rust_crypto::{MemoryStorage, RustCrypto};
 use openmls_libcrux_crypto::CryptoProvider;
+use openmls_traits::storage::StorageProvider as StorageProviderTrait;
 use openmls_traits::OpenMlsProvider;
 use std::fs::File;
 
+// Storage label used by OpenMLS for key package bundles.
+const KEY_PACKAGE_LABEL: &[u8] = b"KeyPackage";
+
 pub struct OpenMlsRustPersistentCrypto {
     crypto: CryptoProvider,
     rand: RustCrypto,
@@ -57,4 +62,59 @@ impl OpenMlsRustPersistentCrypto {
     pub fn load_keystore(&mut self, file: &File) -> Result<(), String> {
         self.storage.load_from_file(file)
     }
+
+    /// Returns the total number of bytes (keys and values) held in the storage.
+    pub fn storage_size(&self) -> usize {
+        self.storage
+            .values
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| k.len() + v.len())
+            .sum()
+    }
+
+    /// Deletes all key package bundles (and their encryption keys) other than keep.
+    /// The encryption key of own_leaf is kept even if its key package is deleted: a client
+    /// that joined a group with a key package keeps using its encryption key until its next update.
+    /// Returns the number of key packages deleted.
+    pub fn delete_key_packages_except(
+        &self,
+        keep: &KeyPackageRef,
+        own_leaf: Option<&LeafNode>,
+    ) -> Result<usize, String> {
+        let stale: Vec<KeyPackageBundle> = self
+            .storage
+            .values
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(k, _)| k.starts_with(KEY_PACKAGE_LABEL))
+            // Skip anything we cannot parse rather than risk deleting it.
+            .filter_map(|(_, v)| serde_json::from_slice::<KeyPackageBundle>(v).ok())
+            .filter(|bundle| match bundle.key_package().hash_ref(&self.crypto) {
+                Ok(hash_ref) => hash_ref != *keep,
+                Err(_) => false,
+            })
+            .collect();
+
+        for bundle in &stale {
+            let hash_ref = bundle
+                .key_package()
+                .hash_ref(&self.crypto)
+                .map_err(|e| e.to_string())?;
+            self.storage
+                .delete_key_package(&hash_ref)
+                .map_err(|e| e.to_string())?;
+            let encryption_key = bundle.key_package().leaf_node().encryption_key();
+            if own_leaf.is_some_and(|leaf| leaf.encryption_key() == encryption_key) {
+                continue;
+            }
+            self.storage
+                .delete_encryption_key_pair(encryption_key)
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(stale.len())
+    }
 }
@@ -141,10 +141,11 @@ pub fn generate_random(num_chars: usize, special_characters: bool) -> String {
         .collect()
 }
 
+/// Returns the content of the QR code (also saved as camera_secret_qrcode.png).
 pub fn generate_raspberry_camera_secret(
     dir: &Path,
     error_on_folder_exist: bool,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<String> {
     // If it already exists and we don't want to try re-generating credentials..
     if dir.exists() && error_on_folder_exist {
         return Err(anyhow!("The directory exists!"));
@@ -182,7 +183,7 @@ pub fn generate_raspberry_camera_secret(
     // Save as QR code to be shown to the app (with secret + version + wifi password).
     save_camera_secret_qrcode(&dir.join("camera_secret_qrcode.png"), qr_content.as_bytes())?;
 
-    Ok(())
+    Ok(qr_content)
 }
 
 pub fn generate_add_app_secret() -> anyhow::Result<String> {
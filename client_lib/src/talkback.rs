@@ -0,0 +1,58 @@
+//! Talkback: audio sent from the app to the camera during a livestream.
+//! The chunks are encrypted in the livestream MLS group (the same group as the
+//! livestream chunks, in the other direction).
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::mls_client::MlsClient;
+use std::io;
+
+/// Maximum size of the audio in one talkback chunk.
+pub const MAX_TALKBACK_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Encrypts a talkback audio chunk.
+/// Like the livestream chunks, the chunk number is included in the ciphertext so that
+/// a malicious server can't reorder, drop, or replay chunks without the camera noticing.
+pub fn encrypt_talkback_chunk(
+    livestream_mls_client: &mut MlsClient,
+    audio: &[u8],
+    chunk_number: u64,
+) -> io::Result<Vec<u8>> {
+    if audio.len() > MAX_TALKBACK_CHUNK_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Error: talkback chunk too large!".to_string(),
+        ));
+    }
+
+    let mut data: Vec<u8> = chunk_number.to_be_bytes().to_vec();
+    data.extend_from_slice(audio);
+
+    livestream_mls_client.encrypt(&data)
+}
+
+/// Decrypts a talkback audio chunk and checks its chunk number.
+pub fn decrypt_talkback_chunk(
+    livestream_mls_client: &mut MlsClient,
+    enc_data: Vec<u8>,
+    expected_chunk_number: u64,
+) -> io::Result<Vec<u8>> {
+    let dec_data = livestream_mls_client.decrypt(enc_data, true)?;
+
+    if dec_data.len() < 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Error: too few bytes!".to_string(),
+        ));
+    }
+
+    let chunk_number = u64::from_be_bytes(dec_data[..8].try_into().unwrap());
+    if chunk_number != expected_chunk_number {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Error: invalid chunk number!".to_string(),
+        ));
+    }
+
+    Ok(dec_data[8..].to_vec())
+}
@@ -7,10 +7,31 @@
 #[cfg(test)]
 mod tests {
     use crate::pairing::NUM_SECRET_BYTES;
-    use crate::mls_client::{MlsClient, Contact, ClientType};
+    use crate::mls_client::{MlsClient, Contact, ClientType, DecryptError, MAX_MESSAGE_OVERHEAD};
+    use crate::mls_clients::{MAX_CIPHERTEXT_SIZES, MLS_CLIENT_TAGS};
     use crate::video::{encrypt_video_file, decrypt_video_file,
-        encrypt_thumbnail_file, decrypt_thumbnail_file};
-    use crate::thumbnail_meta_info::ThumbnailMetaInfo;
+        decrypt_video_file_and_retire_source, encrypt_thumbnail_file, decrypt_thumbnail_file,
+        validate_mp4_file};
+    use crate::thumbnail_meta_info::{
+        BoundingBox, Detection, GeneralDetectionType, ThumbnailMetaFile, ThumbnailMetaInfo,
+        THUMBNAIL_META_FILE_VERSION, THUMBNAIL_META_INFO_VERSION, THUMBNAIL_SANITY,
+    };
+    use crate::talkback::{encrypt_talkback_chunk, decrypt_talkback_chunk};
+    use crate::livestream_buffer::LivestreamBuffer;
+    use crate::livestream_chunk::{
+        decode_chunk, encode_chunk, parse_chunk_format, LIVESTREAM_CHUNK_FORMAT,
+        LIVESTREAM_CHUNK_FORMAT_LEGACY, LIVESTREAM_CHUNK_FORMAT_QUALITY,
+    };
+    use crate::heartbeat_tracker::HeartbeatTracker;
+    use crate::fcm_message::{self, FcmMessage, FORMAT_JSON, FORMAT_TIMESTAMP};
+    use crate::config::{SnapshotResponse, OPCODE_HEARTBEAT_RESPONSE, OPCODE_SNAPSHOT_REQUEST};
+    use crate::mls_clients::CONFIG;
+    use crate::camera_status::CameraStatusNotification;
+    use crate::config::{CameraEvent, Heartbeat, HeartbeatV1};
+    use crate::video_net_info::{
+        VideoNetInfo, MAX_VIDEO_CHUNKS, VIDEONETINFO_SANITY, VIDEONETINFO_VERSION,
+    };
+    use crate::notification_schedule::{NotificationSchedule, ScheduleWindow, Weekday};
     use std::fs::{self, File};
     use std::io;
     use std::io::{Read, Write};
@@ -144,6 +165,33 @@ mod tests {
         assert!(welcome_result.is_err());
     }
 
+    #[test]
+    /// The app has the signature public key of the camera it paired with,
+    /// and a new one after the camera is paired again.
+    fn contact_public_key_test() {
+        let (camera, app) = pair();
+
+        let camera_key = camera.get_own_public_key().unwrap();
+        assert_eq!(camera_key.len(), 44);
+        assert_eq!(&camera_key[..12], &[0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00]);
+        assert_eq!(app.get_contact_public_key().unwrap(), camera_key);
+        assert!(camera.get_contact_public_key().is_err());
+
+        let (new_camera, new_app) = pair();
+
+        let new_camera_key = new_camera.get_own_public_key().unwrap();
+        assert_ne!(new_camera_key, camera_key);
+        assert_eq!(new_app.get_contact_public_key().unwrap(), new_camera_key);
+    }
+
+    #[test]
+    fn contact_name_test() {
+        let (camera, app) = pair();
+
+        assert_eq!(app.get_contact_name().unwrap(), "camera");
+        assert!(camera.get_contact_name().is_err());
+    }
+
     #[test]
     /// Camera invites app and immediately sends a message to it.
     fn camera_to_app_message_test() {
@@ -164,6 +212,31 @@ mod tests {
         assert!(msg == msg_dec);
     }
 
+    #[test]
+    /// App decrypts the same message twice, as it would after losing the group state
+    /// saved after the first attempt. The second attempt is reported as a secret reuse,
+    /// which can be retried after rolling back the group state. Garbage is not retryable.
+    fn decrypt_error_kinds_test() {
+        let (mut camera, mut app) = pair();
+
+        let msg_enc = camera
+            .encrypt(b"Hello, app!")
+            .unwrap();
+        camera.save_group_state().unwrap();
+
+        app.decrypt(msg_enc.clone(), true).unwrap();
+        let err = app.decrypt(msg_enc, true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let decrypt_err = DecryptError::of(&err).unwrap();
+        assert!(matches!(decrypt_err, DecryptError::SecretReuse(_)));
+        assert!(decrypt_err.is_retryable());
+
+        let err = app.decrypt(vec![0u8; 16], true).unwrap_err();
+        let decrypt_err = DecryptError::of(&err).unwrap();
+        assert!(matches!(decrypt_err, DecryptError::Validation(_)));
+        assert!(!decrypt_err.is_retryable());
+    }
+
     #[test]
     /// Camera invites app and the app immediately sends a message to camera.
     fn app_to_camera_message_test() {
@@ -184,6 +257,230 @@ mod tests {
         assert!(msg == msg_dec);
     }
 
+    #[test]
+    /// The app sends a few talkback chunks to the camera during a livestream.
+    fn talkback_round_trip_test() {
+        let (mut camera, mut app) = pair();
+
+        for chunk_number in 1..4u64 {
+            let audio = vec![chunk_number as u8; 960];
+            let enc = encrypt_talkback_chunk(&mut app, &audio, chunk_number).unwrap();
+            app.save_group_state().unwrap();
+
+            let dec = decrypt_talkback_chunk(&mut camera, enc, chunk_number).unwrap();
+            camera.save_group_state().unwrap();
+
+            assert_eq!(dec, audio);
+        }
+    }
+
+    #[test]
+    /// The camera rejects talkback chunks that arrive out of order.
+    fn talkback_out_of_order_test() {
+        let (mut camera, mut app) = pair();
+
+        let enc_1 = encrypt_talkback_chunk(&mut app, b"first", 1).unwrap();
+        let enc_2 = encrypt_talkback_chunk(&mut app, b"second", 2).unwrap();
+        app.save_group_state().unwrap();
+
+        // The server delivers chunk 2 when the camera expects chunk 1.
+        assert!(decrypt_talkback_chunk(&mut camera, enc_2, 1).is_err());
+
+        assert_eq!(decrypt_talkback_chunk(&mut camera, enc_1, 1).unwrap(), b"first");
+        camera.save_group_state().unwrap();
+    }
+
+    /// Delivers chunks 1 to 1 + gap with chunk 1 last, and checks that they're played in order.
+    fn livestream_buffer_gap(gap: u64) {
+        let mut buffer = LivestreamBuffer::new(3);
+        let mut played = Vec::new();
+        let mut expected = 1;
+
+        let mut arrivals: Vec<u64> = (2..=1 + gap).collect();
+        arrivals.push(1);
+
+        for chunk_number in arrivals {
+            let data = vec![chunk_number as u8];
+            if let Some(data) = buffer.push(expected, chunk_number, data).unwrap() {
+                played.push(data[0] as u64);
+                expected += 1;
+                while let Some(data) = buffer.take(expected) {
+                    played.push(data[0] as u64);
+                    expected += 1;
+                }
+            }
+        }
+
+        assert_eq!(played, (1..=1 + gap).collect::<Vec<u64>>());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn livestream_buffer_gap_of_1_test() {
+        livestream_buffer_gap(1);
+    }
+
+    #[test]
+    fn livestream_buffer_gap_of_2_test() {
+        livestream_buffer_gap(2);
+    }
+
+    #[test]
+    fn livestream_buffer_gap_of_3_test() {
+        livestream_buffer_gap(3);
+    }
+
+    #[test]
+    /// A chunk more than capacity chunks ahead means that the expected one is lost.
+    fn livestream_buffer_gap_over_capacity_test() {
+        let mut buffer = LivestreamBuffer::new(3);
+
+        assert_eq!(buffer.push(1, 4, vec![4]).unwrap(), None);
+        let err = buffer.push(1, 5, vec![5]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(buffer.len(), 1);
+
+        // Chunks that were already played are rejected too.
+        assert!(buffer.push(3, 2, vec![2]).is_err());
+    }
+
+    #[test]
+    /// Chunks for older apps keep the legacy format, and newer apps decode both formats.
+    fn livestream_chunk_formats_test() {
+        let legacy = encode_chunk(LIVESTREAM_CHUNK_FORMAT_LEGACY, 7, 2, b"video");
+        let mut expected = 7u64.to_be_bytes().to_vec();
+        expected.extend_from_slice(b"video");
+        assert_eq!(legacy, expected);
+        assert_eq!(decode_chunk(&legacy).unwrap(), (7, 0, &b"video"[..]));
+
+        let chunk = encode_chunk(LIVESTREAM_CHUNK_FORMAT_QUALITY, 7, 2, b"video");
+        assert_eq!(chunk.len(), legacy.len() + 1);
+        assert_eq!(decode_chunk(&chunk).unwrap(), (7, 2, &b"video"[..]));
+        // An older app would reject it because of the chunk number, instead of playing the quality byte.
+        assert_ne!(u64::from_be_bytes(chunk[..8].try_into().unwrap()), 7);
+
+        assert!(decode_chunk(&chunk[..8]).is_err());
+        assert!(decode_chunk(&legacy[..7]).is_err());
+        assert_eq!(decode_chunk(&legacy[..8]).unwrap(), (7, 0, &b""[..]));
+    }
+
+    #[test]
+    fn livestream_chunk_format_negotiation_test() {
+        assert_eq!(parse_chunk_format("1"), LIVESTREAM_CHUNK_FORMAT_QUALITY);
+        assert_eq!(parse_chunk_format(" 0 "), LIVESTREAM_CHUNK_FORMAT_LEGACY);
+        // Older apps and servers
+        assert_eq!(
+            parse_chunk_format("placeholder"),
+            LIVESTREAM_CHUNK_FORMAT_LEGACY
+        );
+        // Newer apps get the newest format we have.
+        assert_eq!(parse_chunk_format("200"), LIVESTREAM_CHUNK_FORMAT);
+    }
+
+    #[test]
+    fn heartbeat_tracker_overdue_test() {
+        let mut tracker = HeartbeatTracker::new();
+        assert!(!tracker.is_heartbeat_overdue("camera", 1000, 60));
+
+        tracker.record_request("camera", 100);
+        tracker.record_request("camera", 130);
+        assert_eq!(tracker.last_request("camera"), Some(130));
+        assert!(!tracker.is_heartbeat_overdue("camera", 159, 60));
+        // Counted from the first request that wasn't answered.
+        assert!(tracker.is_heartbeat_overdue("camera", 160, 60));
+        assert!(!tracker.is_heartbeat_overdue("other_camera", 160, 60));
+
+        // The response to the first request leaves the other two outstanding,
+        // and the wait is counted from the second one, not the last one.
+        tracker.record_request("camera", 160);
+        tracker.record_healthy_response("camera", 100);
+        assert_eq!(tracker.last_healthy("camera"), Some(100));
+        assert!(!tracker.is_heartbeat_overdue("camera", 189, 60));
+        assert!(tracker.is_heartbeat_overdue("camera", 190, 60));
+
+        tracker.record_healthy_response("camera", 130);
+        assert!(!tracker.is_heartbeat_overdue("camera", 219, 60));
+        assert!(tracker.is_heartbeat_overdue("camera", 220, 60));
+
+        tracker.record_healthy_response("camera", 160);
+        assert!(!tracker.is_heartbeat_overdue("camera", 1000, 60));
+
+        // A late response to an older request doesn't clear a newer one.
+        tracker.record_request("camera", 200);
+        tracker.record_healthy_response("camera", 130);
+        assert_eq!(tracker.last_healthy("camera"), Some(160));
+        assert!(tracker.is_heartbeat_overdue("camera", 260, 60));
+
+        tracker.remove_camera("camera");
+        assert_eq!(tracker.last_request("camera"), None);
+        assert!(!tracker.is_heartbeat_overdue("camera", 1000, 60));
+    }
+
+    #[test]
+    /// JSON messages on the FCM channel are tagged, and decoded as JSON whatever their length.
+    fn fcm_message_json_test() {
+        let status = CameraStatusNotification::CameraOffline {
+            consecutive_failures: 3,
+            timestamp: 1_700_000_000,
+        };
+        let msg = fcm_message::encode_json(&status.to_json_bytes());
+        assert_eq!(msg[0], FORMAT_JSON);
+        let FcmMessage::Json(json) = fcm_message::decode(&msg).unwrap() else {
+            panic!("expected a JSON message");
+        };
+        assert_eq!(
+            CameraStatusNotification::from_json_bytes(json.as_bytes()).unwrap(),
+            status
+        );
+
+        // An 8-byte JSON string used to be ambiguous with a timestamp.
+        let msg = fcm_message::encode_json(b"\"abcdef\"");
+        assert_eq!(
+            fcm_message::decode(&msg).unwrap(),
+            FcmMessage::Json("\"abcdef\"".to_string())
+        );
+    }
+
+    #[test]
+    /// Timestamps on the FCM channel are tagged, including the 0 that starts the download.
+    fn fcm_message_timestamp_test() {
+        for timestamp in [0, 1, 1_700_000_000] {
+            let msg = fcm_message::encode_timestamp(timestamp);
+            assert_eq!(msg[0], FORMAT_TIMESTAMP);
+            assert_eq!(msg.len(), 9);
+            assert_eq!(
+                fcm_message::decode(&msg).unwrap(),
+                FcmMessage::Timestamp(timestamp)
+            );
+        }
+    }
+
+    #[test]
+    /// Messages from cameras without the format byte are still decoded.
+    fn fcm_message_legacy_test() {
+        let status = CameraStatusNotification::CameraOnline {
+            timestamp: 1_700_000_000,
+        };
+        let json = status.to_json_bytes();
+        assert_eq!(
+            fcm_message::decode(&json).unwrap(),
+            FcmMessage::Json(String::from_utf8(json).unwrap())
+        );
+
+        // Including timestamps whose first byte is a format byte.
+        for timestamp in [0u64, 1_700_000_000, 0x0101, 0x0202] {
+            let msg = bincode::serialize(&timestamp).unwrap();
+            assert_eq!(
+                fcm_message::decode(&msg).unwrap(),
+                FcmMessage::Timestamp(timestamp)
+            );
+        }
+
+        assert!(fcm_message::decode(&[]).is_err());
+        assert!(fcm_message::decode(&[FORMAT_TIMESTAMP, 1, 2]).is_err());
+        assert!(fcm_message::decode(&[FORMAT_JSON, b'{']).is_err());
+    }
+
     #[test]
     /// Camera invites app and then sends a couple of messages to the app.
     /// The camera and the app reinitialize multiple times in this process.
@@ -710,6 +1007,31 @@ mod tests {
         assert!(msg == msg_dec);
     }
 
+    #[test]
+    /// Camera invites more apps than MAX_APPS_PER_GROUP allows without multi_app_groups.
+    /// Every app decrypts the camera's messages and only the first one is the admin.
+    fn add_more_apps_admin() {
+        let (mut camera, mut app) = pair();
+        let (mut app2, mut app3) = pair_with_two_more_apps(&mut camera, &mut app);
+
+        assert!(!camera.is_admin().unwrap());
+        assert!(app.is_admin().unwrap());
+        assert!(!app2.is_admin().unwrap());
+        assert!(!app3.is_admin().unwrap());
+
+        for i in 0..3 {
+            let msg = format!("Hello, apps! -- {i}");
+            let msg_enc = camera.encrypt(msg.as_bytes()).unwrap();
+            camera.save_group_state().unwrap();
+
+            for app in [&mut app, &mut app2, &mut app3] {
+                let msg_dec_vec = app.decrypt(msg_enc.clone(), true).unwrap();
+                app.save_group_state().unwrap();
+                assert_eq!(msg.as_bytes(), msg_dec_vec.as_slice());
+            }
+        }
+    }
+
     #[test]
     /// Camera invites three apps and immediately sends a message to them.
     /// It then does a self update and sends another message.
@@ -977,6 +1299,184 @@ mod tests {
         assert!(ret.is_err());
     }
 
+    #[test]
+    /// A camera sends a video whose info claims u64::MAX chunks.
+    /// The app rejects it right away, instead of waiting for chunks until storage runs out.
+    fn camera_to_app_too_many_chunks_test() {
+        let (mut camera, mut app) = pair();
+
+        // Same layout as encrypt_video_file(): proposals, commit, then the video info.
+        let mut enc_data = Vec::new();
+        let mut append = |msg: Vec<u8>| {
+            enc_data.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+            enc_data.extend_from_slice(&msg);
+        };
+        let update_proposals = camera.get_update_proposals().unwrap();
+        append(bincode::serialize(&update_proposals).unwrap());
+        let (commit_msg, _) = camera.update().unwrap();
+        append(commit_msg);
+        let info = VideoNetInfo {
+            timestamp: 1700000000,
+            num_msg: u64::MAX,
+            sanity: VIDEONETINFO_SANITY.to_string(),
+        };
+        append(camera.encrypt(&info.serialize()).unwrap());
+
+        let enc_video_pathname = "test_data/enc_video_file";
+        fs::write(enc_video_pathname, enc_data).unwrap();
+        fs::create_dir("test_data/app/videos").unwrap();
+
+        let err = decrypt_video_file(&mut app, enc_video_pathname).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(&MAX_VIDEO_CHUNKS.to_string()));
+        assert!(!Path::new("test_data/app/videos/video_1700000000.mp4").exists());
+    }
+
+    /// Writes a minimal MP4 file: an ftyp box followed by an mdat box with mdat_len bytes of payload.
+    fn generate_dummy_mp4_file(pathname: &str, mdat_len: usize) {
+        let mut data = Vec::new();
+        data.extend_from_slice(&16u32.to_be_bytes());
+        data.extend_from_slice(b"ftypisom");
+        data.extend_from_slice(&[0, 0, 2, 0]);
+        data.extend_from_slice(&((8 + mdat_len) as u32).to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&vec![0u8; mdat_len]);
+
+        fs::create_dir_all("test_data").unwrap();
+        fs::write(pathname, data).unwrap();
+    }
+
+    #[test]
+    fn validate_mp4_file_accepts_complete_file_test() {
+        let pathname = "test_data/complete.mp4";
+        generate_dummy_mp4_file(pathname, 4096);
+
+        assert!(validate_mp4_file(pathname).is_ok());
+    }
+
+    #[test]
+    /// A recording cut short (e.g., by a power loss) must be rejected before it's encrypted.
+    fn validate_mp4_file_rejects_truncated_file_test() {
+        let pathname = "test_data/truncated.mp4";
+        generate_dummy_mp4_file(pathname, 4096);
+
+        let file = fs::OpenOptions::new().write(true).open(pathname).unwrap();
+        file.set_len(16 + 8 + 1000).unwrap();
+
+        assert!(validate_mp4_file(pathname).is_err());
+    }
+
+    #[test]
+    fn validate_mp4_file_rejects_missing_ftyp_test() {
+        let pathname = "test_data/not_an_mp4";
+        fs::create_dir_all("test_data").unwrap();
+        generate_dummy_file(pathname, 1024);
+
+        assert!(validate_mp4_file(pathname).is_err());
+    }
+
+    #[test]
+    /// A successful decrypt removes the encrypted source.
+    fn camera_to_app_video_retire_source_test() {
+        let (mut camera, mut app) = pair();
+
+        let video_pathname = "test_data/video_file";
+        let file_size: usize = 96 * 1024 + 135;
+
+        generate_dummy_file(video_pathname, file_size);
+
+        let enc_video_pathname = "test_data/enc_video_file";
+
+        encrypt_video_file(
+            &mut camera,
+            video_pathname,
+            enc_video_pathname,
+            0,
+        ).unwrap();
+
+        fs::create_dir("test_data/app/videos").unwrap();
+
+        let dec_video_filename = decrypt_video_file_and_retire_source(
+            &mut app,
+            enc_video_pathname,
+            0,
+        ).unwrap();
+
+        let dec_video_pathname = format!("test_data/app/videos/{}", dec_video_filename);
+        check_decrypted_dummy_file(&dec_video_pathname, file_size);
+        assert!(!Path::new(enc_video_pathname).exists());
+    }
+
+    #[test]
+    /// With keep_last > 0, the encrypted source is moved to the retained directory instead.
+    fn camera_to_app_video_retain_source_test() {
+        let (mut camera, mut app) = pair();
+
+        let video_pathname = "test_data/video_file";
+        let file_size: usize = 96 * 1024 + 135;
+
+        generate_dummy_file(video_pathname, file_size);
+
+        let enc_video_pathname = "test_data/enc_video_file";
+
+        encrypt_video_file(
+            &mut camera,
+            video_pathname,
+            enc_video_pathname,
+            0,
+        ).unwrap();
+
+        fs::create_dir("test_data/app/videos").unwrap();
+
+        decrypt_video_file_and_retire_source(
+            &mut app,
+            enc_video_pathname,
+            1,
+        ).unwrap();
+
+        assert!(!Path::new(enc_video_pathname).exists());
+        assert!(Path::new("test_data/retained/enc_video_file").exists());
+    }
+
+    #[test]
+    /// A failed decrypt keeps the encrypted source.
+    fn camera_to_app_missed_video_keeps_source_test() {
+        let (mut camera, mut app) = pair();
+
+        let video_pathname = "test_data/video_file";
+        let file_size: usize = 96 * 1024 + 135;
+
+        generate_dummy_file(video_pathname, file_size);
+
+        // The first video is "lost".
+        encrypt_video_file(
+            &mut camera,
+            video_pathname,
+            "test_data/enc_video_file_0",
+            0,
+        ).unwrap();
+
+        let enc_second_video_pathname = "test_data/enc_video_file_1";
+
+        encrypt_video_file(
+            &mut camera,
+            video_pathname,
+            enc_second_video_pathname,
+            1,
+        ).unwrap();
+
+        fs::create_dir("test_data/app/videos").unwrap();
+
+        let ret = decrypt_video_file_and_retire_source(
+            &mut app,
+            enc_second_video_pathname,
+            0,
+        );
+
+        assert!(ret.is_err());
+        assert!(Path::new(enc_second_video_pathname).exists());
+    }
+
     #[test]
     /// Camera invites app and immediately sends two thumbnails to it.
     /// The first thumbnail is however "lost".
@@ -1092,6 +1592,43 @@ mod tests {
         camera_to_app_video_decrypt_crash("SAVE_GROUP_STATE_CRASH", false);
     }
 
+    #[test]
+    fn camera_to_app_video_decrypt_crash_test_3() {
+        camera_to_app_video_decrypt_crash("SAVE_GROUP_STATE_RENAME_CRASH", false);
+    }
+
+    #[test]
+    /// The app crashes in save_group_state() after renaming the new group state in place,
+    /// but before renaming the key store. It restarts from the previous version
+    /// (both files), and the next save replaces it.
+    fn save_group_state_rename_crash_test() {
+        let (_camera, mut app) = pair();
+        let state_dir = "test_data/app/app";
+        let current = || fs::read_to_string(format!("{state_dir}/CURRENT")).unwrap();
+        let old_version = current();
+
+        std::env::set_var("SAVE_GROUP_STATE_RENAME_CRASH", "1");
+        app.save_group_state().unwrap();
+        std::env::remove_var("SAVE_GROUP_STATE_RENAME_CRASH");
+
+        assert_eq!(current(), old_version);
+        let old_dir = format!("{state_dir}/{}", old_version.trim());
+        assert!(Path::new(&old_dir).join("group_state").exists());
+        assert!(Path::new(&old_dir).join("key_store").exists());
+
+        let mut app = reinitialize_app();
+        assert_eq!(app.get_group_name().unwrap(), GROUP_NAME);
+
+        app.save_group_state().unwrap();
+        let new_version = current();
+        assert_ne!(new_version, old_version);
+        assert!(!Path::new(&old_dir).exists());
+        let new_dir = Path::new(state_dir).join(new_version.trim());
+        assert!(new_dir.join("group_state").exists());
+        assert!(new_dir.join("key_store").exists());
+        assert!(!new_dir.join(".key_store.tmp").exists());
+    }
+
     #[test]
     /// Camera invites app and immediately sends a thumbnail to it.
     /// The app tries to decrypt the thumbnail, but crashes halfway.
@@ -1456,4 +1993,624 @@ mod tests {
             check_decrypted_dummy_file(&dec_thumbnail_pathname, file_size);
         }
     }
+
+    #[test]
+    /// Camera encrypts messages within each client's size limit and the app
+    /// decrypts them.
+    fn camera_to_app_bounded_message_test() {
+        let (mut camera, mut app) = pair();
+
+        for (i, max_size) in MAX_CIPHERTEXT_SIZES.iter().enumerate() {
+            let msg = vec![i as u8; 1024];
+            let msg_enc = camera
+                .encrypt_bounded(&msg, *max_size)
+                .unwrap();
+            camera.save_group_state().unwrap();
+            assert!(msg_enc.len() as u64 <= *max_size);
+
+            let msg_dec = app.decrypt(msg_enc, true).unwrap();
+            app.save_group_state().unwrap();
+
+            assert!(msg == msg_dec, "Mismatch for {} client", MLS_CLIENT_TAGS[i]);
+        }
+    }
+
+    #[test]
+    /// Camera tries to encrypt messages that exceed a size limit. Encryption fails
+    /// without changing the camera's group state, so later messages still reach the app.
+    /// A small limit stands in for the per-client ones, which go up to 50 MiB.
+    fn camera_to_app_oversized_message_test() {
+        let (mut camera, mut app) = pair();
+        let max_size = 4 * 1024;
+
+        // Plaintext alone is already over the limit.
+        let msg = vec![0u8; max_size as usize + 1];
+        let err = camera.encrypt_bounded(&msg, max_size).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Plaintext fits, but not with the encryption overhead.
+        let msg = vec![0u8; (max_size - MAX_MESSAGE_OVERHEAD) as usize + 1];
+        let err = camera.encrypt_bounded(&msg, max_size).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        camera.save_group_state().unwrap();
+
+        // The largest plaintext that's accepted.
+        let msg = vec![1u8; (max_size - MAX_MESSAGE_OVERHEAD) as usize];
+        let msg_enc = camera.encrypt_bounded(&msg, max_size).unwrap();
+        assert!(msg_enc.len() as u64 <= max_size);
+        camera.save_group_state().unwrap();
+
+        let msg_dec = app.decrypt(msg_enc, true).unwrap();
+        app.save_group_state().unwrap();
+        assert!(msg == msg_dec);
+    }
+
+    fn persisted_key_store_size(state_dir: &str) -> u64 {
+        let mut version = String::new();
+        File::open(format!("{state_dir}/CURRENT"))
+            .unwrap()
+            .read_to_string(&mut version)
+            .unwrap();
+
+        fs::metadata(format!("{state_dir}/{}/key_store", version.trim()))
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    /// Camera and app go through hundreds of update cycles, reinitializing
+    /// every few cycles (which generates new key packages).
+    /// Both then compact their storage. The persisted key stores should shrink
+    /// and the camera and app should still be able to communicate.
+    fn compact_storage_test() {
+        let _ = pair();
+        let mut camera = reinitialize_camera();
+        let mut app = reinitialize_app();
+
+        for i in 0..200 {
+            if i % 10 == 0 {
+                camera = reinitialize_camera();
+                app = reinitialize_app();
+            }
+
+            let (commit_msg, _) = camera.update().unwrap();
+            camera.save_group_state().unwrap();
+            app.decrypt(commit_msg, false).unwrap();
+            app.save_group_state().unwrap();
+
+            let update_proposal = app.update_proposal().unwrap();
+            app.save_group_state().unwrap();
+            camera.decrypt(update_proposal, false).unwrap();
+            camera.save_group_state().unwrap();
+        }
+
+        let camera_size_before = persisted_key_store_size("test_data/camera/camera");
+        let app_size_before = persisted_key_store_size("test_data/app/app");
+
+        assert!(camera.compact_storage().unwrap() > 0);
+        camera.save_group_state().unwrap();
+        assert!(app.compact_storage().unwrap() > 0);
+        app.save_group_state().unwrap();
+
+        assert!(persisted_key_store_size("test_data/camera/camera") < camera_size_before);
+        assert!(persisted_key_store_size("test_data/app/app") < app_size_before);
+
+        // Compacting again should not find anything else to remove.
+        assert_eq!(camera.compact_storage().unwrap(), 0);
+
+        let mut camera = reinitialize_camera();
+        let mut app = reinitialize_app();
+
+        let (commit_msg, _) = camera.update().unwrap();
+        camera.save_group_state().unwrap();
+        app.decrypt(commit_msg, false).unwrap();
+        app.save_group_state().unwrap();
+
+        let msg = "Hello, app!";
+        let msg_enc = camera.encrypt(msg.as_bytes()).unwrap();
+        camera.save_group_state().unwrap();
+        let msg_dec_vec = app.decrypt(msg_enc, true).unwrap();
+        app.save_group_state().unwrap();
+        assert!(msg.as_bytes() == msg_dec_vec.as_slice());
+
+        let msg = "Hello, camera!";
+        let msg_enc = app.encrypt(msg.as_bytes()).unwrap();
+        app.save_group_state().unwrap();
+        let msg_dec_vec = camera.decrypt(msg_enc, true).unwrap();
+        camera.save_group_state().unwrap();
+        assert!(msg.as_bytes() == msg_dec_vec.as_slice());
+    }
+
+    #[test]
+    /// The app restarts right after joining, which leaves the key package it joined with
+    /// stale, and compacts its storage. The encryption key of that key package is still
+    /// the app's leaf key, so the app must still be able to process the camera's next commit.
+    fn compact_storage_keeps_own_leaf_key_test() {
+        let _ = pair();
+        let mut app = reinitialize_app();
+
+        app.compact_storage().unwrap();
+        app.save_group_state().unwrap();
+
+        let mut camera = reinitialize_camera();
+        let mut app = reinitialize_app();
+
+        let (commit_msg, _) = camera.update().unwrap();
+        camera.save_group_state().unwrap();
+        app.decrypt(commit_msg, false).unwrap();
+        app.save_group_state().unwrap();
+
+        let msg = "Hello, app!";
+        let msg_enc = camera.encrypt(msg.as_bytes()).unwrap();
+        camera.save_group_state().unwrap();
+        let msg_dec_vec = app.decrypt(msg_enc, true).unwrap();
+        app.save_group_state().unwrap();
+        assert!(msg.as_bytes() == msg_dec_vec.as_slice());
+    }
+
+    #[test]
+    /// App sends a snapshot request over the config channel and the camera
+    /// replies with the snapshot's filename and timestamp.
+    fn snapshot_request_response_test() {
+        let (mut camera, mut app) = pair();
+
+        let request_enc = app
+            .encrypt_bounded(&[OPCODE_SNAPSHOT_REQUEST], MAX_CIPHERTEXT_SIZES[CONFIG])
+            .unwrap();
+        app.save_group_state().unwrap();
+        let request = camera.decrypt(request_enc, true).unwrap();
+        camera.save_group_state().unwrap();
+        assert_eq!(request, vec![OPCODE_SNAPSHOT_REQUEST]);
+
+        let timestamp = 1_700_000_000;
+        let response = SnapshotResponse::Taken {
+            timestamp,
+            filename: ThumbnailMetaInfo::get_filename_from_timestamp(timestamp),
+        };
+        let response_enc = camera
+            .encrypt_bounded(&response.to_config_msg(), MAX_CIPHERTEXT_SIZES[CONFIG])
+            .unwrap();
+        camera.save_group_state().unwrap();
+        let response_msg = app.decrypt(response_enc, true).unwrap();
+        app.save_group_state().unwrap();
+
+        assert_eq!(SnapshotResponse::from_config_msg(&response_msg).unwrap(), response);
+    }
+
+    #[test]
+    /// Messages that aren't well-formed snapshot responses are rejected.
+    fn snapshot_response_parse_error_test() {
+        let failed = SnapshotResponse::Failed("no frame".to_string());
+        assert_eq!(
+            SnapshotResponse::from_config_msg(&failed.to_config_msg()).unwrap(),
+            failed
+        );
+
+        let mut wrong_opcode = failed.to_config_msg();
+        wrong_opcode[0] = OPCODE_HEARTBEAT_RESPONSE;
+        assert!(SnapshotResponse::from_config_msg(&wrong_opcode).is_err());
+
+        assert!(SnapshotResponse::from_config_msg(&[]).is_err());
+        assert!(SnapshotResponse::from_config_msg(&failed.to_config_msg()[..3]).is_err());
+    }
+
+    #[test]
+    fn camera_status_notification_test() {
+        let offline = CameraStatusNotification::CameraOffline {
+            consecutive_failures: 3,
+            timestamp: 1700000000,
+        };
+        let bytes = offline.to_json_bytes();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            r#"{"event":"camera_offline","consecutive_failures":3,"timestamp":1700000000}"#
+        );
+        assert_eq!(CameraStatusNotification::from_json_bytes(&bytes).unwrap(), offline);
+
+        // Must never be mistaken for a motion timestamp (8 bytes of bincode).
+        let online = CameraStatusNotification::CameraOnline { timestamp: 0 };
+        assert_ne!(online.to_json_bytes().len(), 8);
+
+        assert!(CameraStatusNotification::from_json_bytes(br#"{"event":"unknown"}"#).is_err());
+    }
+
+    fn heartbeat_v1() -> HeartbeatV1 {
+        HeartbeatV1 {
+            firmware_version: "1.0.0".to_string(),
+            os_version: "Raspbian 12".to_string(),
+            timestamp: 1700000000,
+            epochs: vec![2, 3, 4],
+            ciphertexts: vec![vec![1, 2, 3], vec![4, 5]],
+        }
+    }
+
+    #[test]
+    fn video_net_info_version_test() {
+        let info = VideoNetInfo::new(1700000000, 200 * 1024, 64 * 1024);
+        let bytes = info.serialize();
+        assert_eq!(bytes[0], VIDEONETINFO_VERSION);
+        let decoded = VideoNetInfo::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.timestamp, 1700000000);
+        assert_eq!(decoded.num_msg, 4);
+
+        // Old cameras send the info without a version byte.
+        let legacy = bincode::serialize(&info).unwrap();
+        let decoded = VideoNetInfo::deserialize(&legacy).unwrap();
+        assert_eq!(decoded.timestamp, 1700000000);
+        assert_eq!(decoded.num_msg, 4);
+    }
+
+    #[test]
+    /// Info from a newer camera must not be decoded as garbage.
+    fn video_net_info_unknown_version_test() {
+        let info = VideoNetInfo::new(1700000000, 200 * 1024, 64 * 1024);
+        let mut bytes = vec![0x02];
+        bytes.extend(bincode::serialize(&info).unwrap());
+        // Say the new version added a field.
+        bytes.extend([0xaa; 16]);
+
+        let err = VideoNetInfo::deserialize(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Unsupported VideoNetInfo version 2"));
+    }
+
+    fn person_detection() -> Detection {
+        Detection {
+            label: "human".to_string(),
+            confidence: Some(0.87),
+            bbox: Some(BoundingBox {
+                x: 0.25,
+                y: 0.1,
+                width: 0.2,
+                height: 0.6,
+            }),
+        }
+    }
+
+    #[test]
+    fn thumbnail_meta_info_round_trip_test() {
+        let info = ThumbnailMetaInfo::new(1700000000, 3, vec![person_detection()]);
+        let bytes = info.serialize();
+        assert_eq!(bytes[0], THUMBNAIL_META_INFO_VERSION);
+
+        let decoded = ThumbnailMetaInfo::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.timestamp, 1700000000);
+        assert_eq!(decoded.epoch, 3);
+        assert_eq!(decoded.detections, vec![person_detection()]);
+    }
+
+    #[derive(serde::Serialize)]
+    struct ThumbnailMetaInfoV0 {
+        timestamp: u64,
+        detections: Vec<GeneralDetectionType>,
+        sanity: String,
+        epoch: u64,
+    }
+
+    #[test]
+    /// A new app reads the info from old cameras, which send labels only and no version byte.
+    fn thumbnail_meta_info_from_old_camera_test() {
+        // Timestamps starting with the version byte must not be taken for versioned info.
+        for timestamp in [1700000000, 0x100 + THUMBNAIL_META_INFO_VERSION as u64] {
+            let bytes = bincode::serialize(&ThumbnailMetaInfoV0 {
+                timestamp,
+                detections: vec![GeneralDetectionType::Human, GeneralDetectionType::Car],
+                sanity: THUMBNAIL_SANITY.to_string(),
+                epoch: 3,
+            })
+            .unwrap();
+
+            let decoded = ThumbnailMetaInfo::deserialize(&bytes).unwrap();
+            assert_eq!(decoded.timestamp, timestamp);
+            assert_eq!(decoded.epoch, 3);
+            assert_eq!(
+                decoded.detections,
+                vec![
+                    Detection::from_label("human".to_string()),
+                    Detection::from_label("car".to_string()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn thumbnail_meta_info_from_newer_camera_test() {
+        let mut bytes = vec![THUMBNAIL_META_INFO_VERSION + 1];
+        bytes.extend([0xaa; 16]);
+
+        let err = ThumbnailMetaInfo::deserialize(&bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(&format!(
+            "Unsupported ThumbnailMetaInfo version {}",
+            THUMBNAIL_META_INFO_VERSION + 1
+        )));
+
+        assert!(ThumbnailMetaInfo::deserialize(&[]).is_err());
+    }
+
+    #[test]
+    fn thumbnail_meta_file_round_trip_test() {
+        let meta_file = ThumbnailMetaFile::new(vec![
+            person_detection(),
+            Detection::from_label("car".to_string()),
+        ]);
+        let contents = serde_json::to_string(&meta_file).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(value["version"], THUMBNAIL_META_FILE_VERSION);
+        assert_eq!(value["detections"][0]["label"], "human");
+        assert_eq!(value["detections"][0]["bbox"]["x"], 0.25);
+        assert!(value["detections"][1]["confidence"].is_null());
+        assert!(value["detections"][1]["bbox"].is_null());
+
+        assert_eq!(ThumbnailMetaFile::parse(&contents).unwrap(), meta_file);
+    }
+
+    #[test]
+    /// Meta files written before detections had a confidence and a box are converted.
+    fn thumbnail_meta_file_migration_test() {
+        let meta_file = ThumbnailMetaFile::parse(r#"["human","car"]"#).unwrap();
+        assert_eq!(meta_file.version, THUMBNAIL_META_FILE_VERSION);
+        let labels: Vec<&str> = meta_file.detections.iter().map(|d| d.label.as_str()).collect();
+        assert_eq!(labels, ["human", "car"]);
+        // Nothing is made up for them.
+        assert!(meta_file.detections.iter().all(|d| d.confidence.is_none()));
+        assert!(meta_file.detections.iter().all(|d| d.bbox.is_none()));
+
+        assert!(ThumbnailMetaFile::parse("[]").unwrap().detections.is_empty());
+        assert!(ThumbnailMetaFile::parse("[1, 2]").is_err());
+        assert!(ThumbnailMetaFile::parse(r#"{"version": 99, "detections": []}"#).is_err());
+    }
+
+    #[test]
+    /// Meta files left on disk by an older app are loaded and rewritten in the current format.
+    fn thumbnail_meta_file_load_test() {
+        let dir = std::env::temp_dir().join(format!("thumbnail_meta_load_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("meta_1700000000.txt");
+        fs::write(&path, r#"["human"]"#).unwrap();
+
+        let meta_file = ThumbnailMetaFile::load(&path).unwrap();
+        assert_eq!(
+            meta_file.detections,
+            vec![Detection::from_label("human".to_string())]
+        );
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(ThumbnailMetaFile::parse(&contents).unwrap(), meta_file);
+        assert!(contents.starts_with('{'));
+        assert_eq!(ThumbnailMetaFile::load(&path).unwrap(), meta_file);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    /// A new app reads heartbeats from old cameras, which have no events.
+    fn heartbeat_from_old_camera_test() {
+        let bytes = bincode::serialize(&heartbeat_v1()).unwrap();
+        let heartbeat = Heartbeat::from_bytes(&bytes).unwrap();
+        assert_eq!(heartbeat.firmware_version, "1.0.0");
+        assert_eq!(heartbeat.os_version, "Raspbian 12");
+        assert_eq!(heartbeat.timestamp, 1700000000);
+        assert_eq!(heartbeat.epochs, vec![2, 3, 4]);
+        assert_eq!(heartbeat.ciphertexts, vec![vec![1, 2, 3], vec![4, 5]]);
+        assert!(heartbeat.events.is_empty());
+    }
+
+    #[test]
+    /// Old apps read heartbeats from new cameras and ignore the events.
+    fn heartbeat_to_old_app_test() {
+        let v1 = heartbeat_v1();
+        let events = vec![CameraEvent {
+            code: "rtsp_auth_failed".to_string(),
+            message: "401 Unauthorized".to_string(),
+            timestamp: 1699999990,
+        }];
+        let heartbeat = Heartbeat {
+            firmware_version: v1.firmware_version,
+            os_version: v1.os_version,
+            timestamp: v1.timestamp,
+            epochs: v1.epochs,
+            ciphertexts: v1.ciphertexts,
+            events: events.clone(),
+        };
+        let bytes = bincode::serialize(&heartbeat).unwrap();
+
+        let old: HeartbeatV1 = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(old.firmware_version, "1.0.0");
+        assert_eq!(old.timestamp, 1700000000);
+        assert_eq!(old.ciphertexts, vec![vec![1, 2, 3], vec![4, 5]]);
+
+        let new = Heartbeat::from_bytes(&bytes).unwrap();
+        assert_eq!(new.events, events);
+
+        assert!(Heartbeat::from_bytes(&bytes[..10]).is_err());
+    }
+
+    // Unix time of the given UTC time, days after Monday 1970-01-05.
+    fn monday_utc(days: u64, hour: u64, minute: u64) -> u64 {
+        4 * 86400 + days * 86400 + hour * 3600 + minute * 60
+    }
+
+    fn schedule_with(utc_offset_minutes: i16, windows: Vec<ScheduleWindow>) -> NotificationSchedule {
+        NotificationSchedule {
+            armed: true,
+            utc_offset_minutes,
+            disarmed_windows: windows,
+        }
+    }
+
+    #[test]
+    fn schedule_weekday_window_test() {
+        let schedule = schedule_with(
+            0,
+            vec![ScheduleWindow {
+                days: vec![
+                    Weekday::Monday,
+                    Weekday::Tuesday,
+                    Weekday::Wednesday,
+                    Weekday::Thursday,
+                    Weekday::Friday,
+                ],
+                start_minute: 8 * 60,
+                end_minute: 18 * 60,
+            }],
+        );
+        schedule.validate().unwrap();
+
+        assert!(schedule.notifications_allowed(monday_utc(0, 7, 59)));
+        assert!(!schedule.notifications_allowed(monday_utc(0, 8, 0)));
+        assert!(!schedule.notifications_allowed(monday_utc(4, 17, 59)));
+        assert!(schedule.notifications_allowed(monday_utc(4, 18, 0)));
+        // Saturday
+        assert!(schedule.notifications_allowed(monday_utc(5, 12, 0)));
+
+        assert!(NotificationSchedule::default().notifications_allowed(monday_utc(0, 12, 0)));
+        let disarmed = NotificationSchedule {
+            armed: false,
+            ..NotificationSchedule::default()
+        };
+        assert!(!disarmed.notifications_allowed(monday_utc(0, 12, 0)));
+    }
+
+    #[test]
+    fn schedule_window_across_midnight_test() {
+        let schedule = schedule_with(
+            0,
+            vec![ScheduleWindow {
+                days: vec![Weekday::Friday, Weekday::Sunday],
+                start_minute: 22 * 60,
+                end_minute: 6 * 60,
+            }],
+        );
+
+        // Friday night into Saturday morning.
+        assert!(schedule.notifications_allowed(monday_utc(4, 21, 59)));
+        assert!(!schedule.notifications_allowed(monday_utc(4, 23, 0)));
+        assert!(!schedule.notifications_allowed(monday_utc(5, 5, 59)));
+        assert!(schedule.notifications_allowed(monday_utc(5, 6, 0)));
+        // The early morning of Friday follows Thursday night, which isn't in the schedule.
+        assert!(schedule.notifications_allowed(monday_utc(4, 5, 0)));
+        // Sunday night into Monday morning (across the end of the week).
+        assert!(!schedule.notifications_allowed(monday_utc(7, 2, 0)));
+        assert!(!schedule.notifications_allowed(monday_utc(0, 2, 0)));
+        assert!(schedule.notifications_allowed(monday_utc(0, 22, 0)));
+
+        // Equal start and end cover the whole day.
+        let all_day = schedule_with(
+            0,
+            vec![ScheduleWindow {
+                days: vec![Weekday::Wednesday],
+                start_minute: 0,
+                end_minute: 0,
+            }],
+        );
+        assert!(!all_day.notifications_allowed(monday_utc(2, 0, 0)));
+        assert!(!all_day.notifications_allowed(monday_utc(2, 23, 59)));
+        assert!(all_day.notifications_allowed(monday_utc(3, 0, 0)));
+    }
+
+    #[test]
+    fn schedule_utc_offset_test() {
+        let window = ScheduleWindow {
+            days: vec![Weekday::Monday],
+            start_minute: 8 * 60,
+            end_minute: 18 * 60,
+        };
+
+        // UTC-5: 13:00 UTC is 08:00 local.
+        let schedule = schedule_with(-300, vec![window.clone()]);
+        assert!(schedule.notifications_allowed(monday_utc(0, 12, 59)));
+        assert!(!schedule.notifications_allowed(monday_utc(0, 13, 0)));
+        assert!(!schedule.notifications_allowed(monday_utc(0, 22, 59)));
+        assert!(schedule.notifications_allowed(monday_utc(0, 23, 0)));
+
+        // UTC+14: 18:00 UTC on Sunday is 08:00 local on Monday.
+        let schedule = schedule_with(14 * 60, vec![window]);
+        assert!(!schedule.notifications_allowed(monday_utc(6, 18, 0)));
+        assert!(schedule.notifications_allowed(monday_utc(0, 18, 0)));
+
+        // The local day is computed before the epoch too (Wednesday 1969-12-31).
+        let schedule = schedule_with(
+            -60,
+            vec![ScheduleWindow {
+                days: vec![Weekday::Wednesday],
+                start_minute: 23 * 60,
+                end_minute: 0,
+            }],
+        );
+        assert!(!schedule.notifications_allowed(0));
+    }
+
+    #[test]
+    fn schedule_validation_test() {
+        let window = |start_minute, end_minute| ScheduleWindow {
+            days: vec![Weekday::Monday],
+            start_minute,
+            end_minute,
+        };
+
+        assert!(schedule_with(0, vec![window(0, 1439)]).validate().is_ok());
+        assert!(schedule_with(0, vec![window(0, 1440)]).validate().is_err());
+        assert!(schedule_with(0, vec![window(1440, 0)]).validate().is_err());
+        assert!(schedule_with(-12 * 60, vec![]).validate().is_ok());
+        assert!(schedule_with(-12 * 60 - 1, vec![]).validate().is_err());
+        assert!(schedule_with(14 * 60 + 1, vec![]).validate().is_err());
+    }
+
+    #[test]
+    /// The app's state is exported and imported on a new device, which can then decrypt
+    /// messages from the camera.
+    fn export_import_state_test() {
+        let (mut camera, mut app) = pair();
+
+        let blob = app.export_state("correct horse").unwrap();
+
+        let new_app_path = Path::new("test_data/new_app");
+        fs::create_dir(new_app_path).unwrap();
+
+        assert!(MlsClient::import_state(
+            &blob,
+            "wrong passphrase",
+            "test_data/new_app".to_string(),
+            "app".to_string(),
+        )
+        .is_err());
+        assert!(!new_app_path.join("app").exists());
+
+        let username = MlsClient::import_state(
+            &blob,
+            "correct horse",
+            "test_data/new_app".to_string(),
+            "app".to_string(),
+        )
+        .unwrap();
+        assert_eq!(username, "app");
+
+        // Importing twice must not overwrite the state.
+        assert!(MlsClient::import_state(
+            &blob,
+            "correct horse",
+            "test_data/new_app".to_string(),
+            "app".to_string(),
+        )
+        .is_err());
+
+        let mut new_app = MlsClient::new(
+            username,
+            false,
+            "test_data/new_app".to_string(),
+            "app".to_string(),
+            ClientType::App,
+        )
+        .unwrap();
+        assert_eq!(new_app.get_epoch().unwrap(), app.get_epoch().unwrap());
+
+        let msg = "Hello, new app!";
+        let msg_enc = camera.encrypt(msg.as_bytes()).unwrap();
+        camera.save_group_state().unwrap();
+
+        let msg_dec_vec = new_app.decrypt(msg_enc, true).unwrap();
+        new_app.save_group_state().unwrap();
+        assert_eq!(msg.as_bytes(), &msg_dec_vec[..]);
+    }
 }
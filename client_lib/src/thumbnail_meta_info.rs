@@ -1,6 +1,9 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "lowercase")]
@@ -10,21 +13,146 @@ pub enum GeneralDetectionType {
     Car,
 }
 
+impl GeneralDetectionType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GeneralDetectionType::Human => "human",
+            GeneralDetectionType::Pet => "pet",
+            GeneralDetectionType::Car => "car",
+        }
+    }
+}
+
+/// Part of the thumbnail, in normalized coordinates (0 to 1, from the top-left corner).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Detection {
+    /// e.g., "human", "pet", or "car"
+    pub label: String,
+    /// Between 0 and 1. None if the detector didn't report one.
+    #[serde(default)]
+    pub confidence: Option<f32>,
+    /// None if the detector didn't report where the object is.
+    #[serde(default)]
+    pub bbox: Option<BoundingBox>,
+}
+
+impl Detection {
+    /// A detection with a label only.
+    pub fn from_label(label: String) -> Self {
+        Self {
+            label,
+            confidence: None,
+            bbox: None,
+        }
+    }
+}
+
+impl From<&GeneralDetectionType> for Detection {
+    fn from(detection_type: &GeneralDetectionType) -> Self {
+        Self::from_label(detection_type.label().to_string())
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ThumbnailMetaInfo {
     pub timestamp: u64,
-    pub detections: Vec<GeneralDetectionType>,
+    pub detections: Vec<Detection>,
     pub sanity: String,
     pub epoch: u64,
 }
 
+// ThumbnailMetaInfo as sent by cameras from before the version byte.
+#[derive(Deserialize)]
+struct ThumbnailMetaInfoV0 {
+    timestamp: u64,
+    detections: Vec<GeneralDetectionType>,
+    sanity: String,
+    epoch: u64,
+}
+
 pub const THUMBNAIL_SANITY: &str = "thumbbeef";
 
+/// Version of the serialized ThumbnailMetaInfo, sent as its first byte.
+/// Bump it (and handle the old versions in deserialize()) when the fields change.
+pub const THUMBNAIL_META_INFO_VERSION: u8 = 1;
+
+/// Version of the meta_{timestamp}.txt files written for the app's pending processor.
+/// Files without a version are from before detections had a confidence and a bounding box.
+pub const THUMBNAIL_META_FILE_VERSION: u32 = 1;
+
+/// Content of a meta_{timestamp}.txt file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThumbnailMetaFile {
+    pub version: u32,
+    pub detections: Vec<Detection>,
+}
+
+impl ThumbnailMetaFile {
+    pub fn new(detections: Vec<Detection>) -> Self {
+        Self {
+            version: THUMBNAIL_META_FILE_VERSION,
+            detections,
+        }
+    }
+
+    /// Parses a meta file, in the current format or in the unversioned one
+    /// (a JSON array of labels, e.g., ["human","car"]), which is converted to the current one.
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        if value.is_array() {
+            let labels: Vec<String> = serde_json::from_value(value)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            let detections = labels.into_iter().map(Detection::from_label).collect();
+            return Ok(Self::new(detections));
+        }
+
+        let meta_file: Self = serde_json::from_value(value)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if meta_file.version > THUMBNAIL_META_FILE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported thumbnail meta file version {} (this app supports up to {})",
+                    meta_file.version, THUMBNAIL_META_FILE_VERSION
+                ),
+            ));
+        }
+
+        Ok(meta_file)
+    }
+
+    /// Reads a meta file, rewriting it in the current format if it's in an older one.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let meta_file = Self::parse(&contents)?;
+
+        let current = serde_json::to_string(&meta_file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if current != contents {
+            let tmp_path = path.with_extension("txt.tmp");
+            fs::write(&tmp_path, &current)?;
+            fs::rename(&tmp_path, path)?;
+        }
+
+        Ok(meta_file)
+    }
+}
+
 impl ThumbnailMetaInfo {
     pub fn new(
         timestamp: u64,
         thumbnail_epoch: u64,
-        detections: Vec<GeneralDetectionType>,
+        detections: Vec<Detection>,
     ) -> Self {
         Self {
             timestamp, // Matches video ts
@@ -34,6 +162,52 @@ impl ThumbnailMetaInfo {
         }
     }
 
+    /// Serializes the info, preceded by the version byte.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = vec![THUMBNAIL_META_INFO_VERSION];
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    /// Deserializes the info from a camera of this version or an older one.
+    /// Cameras from before the version byte send the bincode struct alone,
+    /// with the detections as labels only.
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.first() == Some(&THUMBNAIL_META_INFO_VERSION) {
+            if let Some(info) = bincode::deserialize::<Self>(&bytes[1..])
+                .ok()
+                .filter(|info| info.sanity == THUMBNAIL_SANITY)
+            {
+                return Ok(info);
+            }
+        }
+
+        if let Some(info) = bincode::deserialize::<ThumbnailMetaInfoV0>(bytes)
+            .ok()
+            .filter(|info| info.sanity == THUMBNAIL_SANITY)
+        {
+            return Ok(Self {
+                timestamp: info.timestamp,
+                detections: info.detections.iter().map(Detection::from).collect(),
+                sanity: info.sanity,
+                epoch: info.epoch,
+            });
+        }
+
+        match bytes.first() {
+            Some(&version) if version > THUMBNAIL_META_INFO_VERSION => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported ThumbnailMetaInfo version {version} (at most {THUMBNAIL_META_INFO_VERSION} is supported). The app may need to be updated."
+                ),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Error: Corrupt ThumbnailMetaInfo message.",
+            )),
+        }
+    }
+
     pub fn get_filename_from_timestamp(timestamp: u64) -> String {
         "thumbnail_".to_owned() + &timestamp.to_string() + ".png"
     }
@@ -1,17 +1,85 @@
 use std::fs::{self, File};
-use std::io::{self, Read, Write, BufRead, BufReader, BufWriter};
-use std::time::Instant;
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write, BufRead, BufReader, BufWriter};
+use std::time::{Instant, SystemTime};
+use std::path::{Path, PathBuf};
 use log::{debug, info, error};
 use openmls::prelude::QueuedProposal;
 use crate::mls_client::MlsClient;
-use crate::video_net_info::{VideoNetInfo, VIDEONETINFO_SANITY};
-use crate::thumbnail_meta_info::{ThumbnailMetaInfo, THUMBNAIL_SANITY};
+use crate::mls_clients::{MAX_CIPHERTEXT_SIZES, THUMBNAIL};
+use crate::video_net_info::{VideoNetInfo, MAX_VIDEO_CHUNKS, VIDEONETINFO_SANITY};
+use crate::thumbnail_meta_info::{ThumbnailMetaFile, ThumbnailMetaInfo};
+
+// Subdirectory (next to the encrypted files) where retained encrypted sources are kept.
+const RETAINED_DIR: &str = "retained";
 
 pub fn decrypt_video_file(
     motion_mls_client: &mut MlsClient,
     enc_pathname: &str,
 ) -> io::Result<String> {
+    decrypt_video_file_internal(motion_mls_client, enc_pathname).map(|(dec_filename, _)| dec_filename)
+}
+
+/// Same as decrypt_video_file(), but also gets rid of the encrypted source once the
+/// video is decrypted and the group state is saved.
+/// The most recent keep_last sources are moved to a retained directory (for debugging)
+/// and the older ones are deleted.
+/// The source is left in place if decryption fails or if the video was already decrypted.
+pub fn decrypt_video_file_and_retire_source(
+    motion_mls_client: &mut MlsClient,
+    enc_pathname: &str,
+    keep_last: usize,
+) -> io::Result<String> {
+    let (dec_filename, newly_decrypted) =
+        decrypt_video_file_internal(motion_mls_client, enc_pathname)?;
+
+    if newly_decrypted {
+        if let Err(e) = retire_encrypted_file(enc_pathname, keep_last) {
+            error!("Failed to clean up encrypted video {} ({})", enc_pathname, e);
+        }
+    }
+
+    Ok(dec_filename)
+}
+
+fn retire_encrypted_file(enc_pathname: &str, keep_last: usize) -> io::Result<()> {
+    let enc_path = Path::new(enc_pathname);
+    if keep_last == 0 {
+        return fs::remove_file(enc_path);
+    }
+
+    let file_name = enc_path
+        .file_name()
+        .ok_or_else(|| io::Error::other("Error: invalid encrypted pathname"))?;
+    let retained_dir = enc_path.parent().unwrap_or(Path::new(".")).join(RETAINED_DIR);
+    fs::create_dir_all(&retained_dir)?;
+    fs::rename(enc_path, retained_dir.join(file_name))?;
+
+    // Only keep the newest keep_last files.
+    let mut retained: Vec<(SystemTime, PathBuf)> = fs::read_dir(&retained_dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            Some((metadata.modified().ok()?, entry.path()))
+        })
+        .collect();
+    retained.sort_by(|a, b| b.0.cmp(&a.0));
+
+    for (_, path) in retained.into_iter().skip(keep_last) {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the decrypted filename and whether the video was decrypted now
+/// (as opposed to having been decrypted before).
+fn decrypt_video_file_internal(
+    motion_mls_client: &mut MlsClient,
+    enc_pathname: &str,
+) -> io::Result<(String, bool)> {
     let total_start = Instant::now();
     let file_dir = motion_mls_client.get_file_dir();
     info!("File dir: {}", file_dir);
@@ -37,13 +105,22 @@ pub fn decrypt_video_file(
     let dec_msg = motion_mls_client.decrypt(enc_msg, true)?;
     let info_ms = info_start.elapsed().as_millis();
 
-    let info: VideoNetInfo = bincode::deserialize(&dec_msg)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let info = VideoNetInfo::deserialize(&dec_msg)?;
 
     if info.sanity != *VIDEONETINFO_SANITY || info.num_msg == 0 {
         return Err(io::Error::other("Error: Corrupt VideoNetInfo message."));
     }
 
+    if info.num_msg > MAX_VIDEO_CHUNKS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Error: Video has too many chunks ({}, at most {}).",
+                info.num_msg, MAX_VIDEO_CHUNKS
+            ),
+        ));
+    }
+
     #[cfg(test)]
     {
         if std::env::var("DECRYPT_VIDEO_FILE_CRASH").is_ok() {
@@ -68,7 +145,7 @@ pub fn decrypt_video_file(
             info_ms,
             total_start.elapsed().as_millis()
         );
-        return Ok(dec_filename);
+        return Ok((dec_filename, false));
     }
 
     info!("Decrypted pathname: {}", dec_pathname);
@@ -106,7 +183,7 @@ pub fn decrypt_video_file(
     let flush_start = Instant::now();
     dec_file.flush().unwrap();
     dec_file.sync_all().unwrap();
-    motion_mls_client.save_group_state().unwrap();
+    motion_mls_client.save_group_state()?;
     let flush_ms = flush_start.elapsed().as_millis();
 
     debug!(
@@ -120,7 +197,7 @@ pub fn decrypt_video_file(
         info.num_msg
     );
 
-    Ok(dec_filename)
+    Ok((dec_filename, true))
 }
 
 pub fn decrypt_thumbnail_file(
@@ -154,12 +231,7 @@ pub fn decrypt_thumbnail_file(
     let dec_msg = thumbnail_mls_client.decrypt(enc_msg, true)?;
     let meta_ms = meta_start.elapsed().as_millis();
 
-    let thumbnail_meta_info: ThumbnailMetaInfo = bincode::deserialize(&dec_msg)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-
-    if thumbnail_meta_info.sanity != *THUMBNAIL_SANITY {
-        return Err(io::Error::other("Error: Corrupt ThumbalMetaInfo message."));
-    }
+    let thumbnail_meta_info = ThumbnailMetaInfo::deserialize(&dec_msg)?;
 
     #[cfg(test)]
     {
@@ -195,8 +267,11 @@ pub fn decrypt_thumbnail_file(
     let mut meta_file_writer = BufWriter::new(meta_file);
 
     // Write JSON data to file.
-    serde_json::to_writer(&mut meta_file_writer, &thumbnail_meta_info.detections)
-        .map_err(std::io::Error::other)?;
+    serde_json::to_writer(
+        &mut meta_file_writer,
+        &ThumbnailMetaFile::new(thumbnail_meta_info.detections),
+    )
+    .map_err(std::io::Error::other)?;
 
     let mut dec_file = File::create(&dec_pathname).expect("Could not create decrypted file");
 
@@ -212,7 +287,7 @@ pub fn decrypt_thumbnail_file(
     let flush_start = Instant::now();
     dec_file.flush().unwrap();
     dec_file.sync_all().unwrap();
-    thumbnail_mls_client.save_group_state().unwrap();
+    thumbnail_mls_client.save_group_state()?;
     let flush_ms = flush_start.elapsed().as_millis();
 
     debug!(
@@ -250,6 +325,71 @@ fn read_next_msg_from_file(file: &mut File) -> io::Result<Vec<u8>> {
     Ok(buffer)
 }
 
+/// Lightweight check that a recorded video is a complete MP4 file before we encrypt and send it.
+/// The file must start with an ftyp box and its top-level boxes must add up to exactly the file length,
+/// which catches recordings that were cut short (e.g., by a power loss).
+pub fn validate_mp4_file(video_pathname: &str) -> io::Result<()> {
+    let mut file = File::open(video_pathname)?;
+    let file_len = file.metadata()?.len();
+    if file_len == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Error: empty MP4 file"));
+    }
+
+    let mut offset: u64 = 0;
+    let mut header = [0u8; 16];
+    while offset < file_len {
+        if file_len - offset < 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Error: truncated MP4 box header at offset {}", offset),
+            ));
+        }
+
+        file.read_exact(&mut header[..8])?;
+        let box_type = &header[4..8];
+        if offset == 0 && box_type != b"ftyp" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Error: MP4 file does not start with an ftyp box",
+            ));
+        }
+
+        let box_size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            // The box extends to the end of the file.
+            0 => file_len - offset,
+            // 64-bit size follows the type.
+            1 => {
+                if file_len - offset < 16 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Error: truncated MP4 box header at offset {}", offset),
+                    ));
+                }
+                file.read_exact(&mut header[8..16])?;
+                u64::from_be_bytes(header[8..16].try_into().unwrap())
+            }
+            size => size as u64,
+        };
+
+        if box_size < 8 || box_size > file_len - offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Error: MP4 box at offset {} has size {} but only {} bytes are left",
+                    offset,
+                    box_size,
+                    file_len - offset
+                ),
+            ));
+        }
+
+        offset += box_size;
+        file.seek(SeekFrom::Start(offset))?;
+    }
+
+    Ok(())
+}
+
 pub fn encrypt_video_file(
     motion_mls_client: &mut MlsClient,
     video_pathname: &str,
@@ -257,6 +397,20 @@ pub fn encrypt_video_file(
     timestamp: u64,
 ) -> io::Result<u64> {
     debug!("Starting to encrypt video.");
+
+    // FIXME: why this chunk size? Test larger and smaller chunks.
+    const READ_SIZE: usize = 64 * 1024;
+
+    let file = File::open(video_pathname).expect("Could not open video file to send");
+    let file_len = file.metadata().unwrap().len();
+    // The app would reject the video. Checked before the group's state is updated.
+    if file_len / READ_SIZE as u64 + 1 > MAX_VIDEO_CHUNKS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Error: Video is too large ({} bytes).", file_len),
+        ));
+    }
+
     let mut enc_file =
         File::create(&enc_pathname).expect("Could not create encrypted video file");
 
@@ -268,17 +422,12 @@ pub fn encrypt_video_file(
 
     append_to_file(&enc_file, commit_msg);
 
-    let file = File::open(video_pathname).expect("Could not open video file to send");
-    let file_len = file.metadata().unwrap().len();
-
-    // FIXME: why this chunk size? Test larger and smaller chunks.
-    const READ_SIZE: usize = 64 * 1024;
     let mut reader = BufReader::with_capacity(READ_SIZE, file);
 
     let net_info = VideoNetInfo::new(timestamp, file_len, READ_SIZE as u64);
 
     let msg = motion_mls_client
-        .encrypt(&bincode::serialize(&net_info).unwrap())
+        .encrypt(&net_info.serialize())
         .inspect_err(|_| {
             error!("encrypt() returned error:");
         })?;
@@ -312,7 +461,7 @@ pub fn encrypt_video_file(
     // When we return from this function, we enqueue to be uploaded to the server.
     enc_file.flush().unwrap();
     enc_file.sync_all().unwrap();
-    motion_mls_client.save_group_state().unwrap();
+    motion_mls_client.save_group_state()?;
 
     Ok(epoch)
 }
@@ -338,7 +487,7 @@ pub fn encrypt_thumbnail_file(
 
     // We need to store the timestamp to match against the video's, as otherwise we only have epoch-level info (which can vary between videos and timestamps easily)
     let msg = thumbnail_mls_client
-        .encrypt(&bincode::serialize(&thumbnail_info).unwrap())
+        .encrypt(&thumbnail_info.serialize())
         .inspect_err(|_| {
             error!("encrypt() returned error:");
         })?;
@@ -348,9 +497,11 @@ pub fn encrypt_thumbnail_file(
     let mut thumbnail_data: Vec<u8> = Vec::new();
     file.read_to_end(&mut thumbnail_data)?;
 
-    let msg = thumbnail_mls_client.encrypt(&thumbnail_data).inspect_err(|_| {
-        error!("encrypt() returned error:");
-    })?;
+    let msg = thumbnail_mls_client
+        .encrypt_bounded(&thumbnail_data, MAX_CIPHERTEXT_SIZES[THUMBNAIL])
+        .inspect_err(|_| {
+            error!("encrypt_bounded() returned error:");
+        })?;
     append_to_file(&enc_file, msg);
 
     // Here, we first make sure the enc_file is flushed.
@@ -358,7 +509,7 @@ pub fn encrypt_thumbnail_file(
     // Then, we enqueue to be uploaded to the server.
     enc_file.flush().unwrap();
     enc_file.sync_all().unwrap();
-    thumbnail_mls_client.save_group_state().unwrap();
+    thumbnail_mls_client.save_group_state()?;
 
     Ok(thumbnail_epoch)
 }
@@ -1,6 +1,7 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use serde::{Deserialize, Serialize};
+use std::io;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct VideoNetInfo {
@@ -12,6 +13,14 @@ pub struct VideoNetInfo {
 
 pub const VIDEONETINFO_SANITY: &str = "deadbeef";
 
+/// Most chunks a video can have: 10,000 chunks of 64 KiB, i.e., about 625 MiB.
+/// num_msg comes from the camera, so the app doesn't trust it beyond that.
+pub const MAX_VIDEO_CHUNKS: u64 = 10_000;
+
+/// Version of the serialized VideoNetInfo, sent as its first byte.
+/// Bump it (and handle the old versions in deserialize()) when the fields change.
+pub const VIDEONETINFO_VERSION: u8 = 1;
+
 impl VideoNetInfo {
     pub fn new(timestamp: u64, video_size: u64, read_size: u64) -> Self {
         Self {
@@ -28,4 +37,45 @@ impl VideoNetInfo {
             sanity: VIDEONETINFO_SANITY.to_string(),
         }
     }
+
+    /// Serializes the info, preceded by the version byte.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = vec![VIDEONETINFO_VERSION];
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    /// Deserializes the info from a camera of this version or an older one.
+    /// Cameras from before the version byte send the bincode struct alone.
+    pub fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.first() == Some(&VIDEONETINFO_VERSION) {
+            if let Some(info) = Self::deserialize_v1(&bytes[1..]) {
+                return Ok(info);
+            }
+        }
+
+        if let Some(info) = Self::deserialize_v1(bytes) {
+            return Ok(info);
+        }
+
+        match bytes.first() {
+            Some(&version) if version > VIDEONETINFO_VERSION => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported VideoNetInfo version {version} (at most {VIDEONETINFO_VERSION} is supported). The app may need to be updated."
+                ),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Failed to deserialize VideoNetInfo",
+            )),
+        }
+    }
+
+    // Only accepted with the right sanity string, so that we don't take another version for this one.
+    fn deserialize_v1(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize::<Self>(bytes)
+            .ok()
+            .filter(|info| info.sanity == VIDEONETINFO_SANITY)
+    }
 }
@@ -90,6 +90,27 @@ pub fn create_user_credentials(server_addr: String) -> anyhow::Result<(Vec<u8>,
     let username = generate_random(NUM_USERNAME_CHARS, true);
     let password = generate_random(NUM_PASSWORD_CHARS, true);
 
+    user_credentials_from_parts(username, password, server_addr)
+}
+
+/// Same as create_user_credentials(), but keeps the username of existing credentials and only
+/// generates a new key. The server keeps the user's data under the username, so the cameras
+/// stay paired once they're given the new credentials.
+pub fn rotate_user_credentials(
+    username: String,
+    server_addr: String,
+) -> anyhow::Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let password = generate_random(NUM_PASSWORD_CHARS, true);
+
+    user_credentials_from_parts(username, password, server_addr)
+}
+
+/// Same as create_user_credentials(), but for an existing username and password.
+pub fn user_credentials_from_parts(
+    username: String,
+    password: String,
+    server_addr: String,
+) -> anyhow::Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
     let credentials_string = format!("{}{}", username, password);
     let credentials = credentials_string.into_bytes();
 
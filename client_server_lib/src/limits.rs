@@ -0,0 +1,14 @@
+//! Secluso upload size limits, shared by the server (which enforces them) and the clients
+//! (which reject oversized messages before uploading them).
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+// All sizes are in bytes.
+
+/// Motion videos and thumbnails.
+pub const MAX_MOTION_FILE_SIZE: u64 = 50 * 1024 * 1024;
+pub const MAX_LIVESTREAM_FILE_SIZE: u64 = 20 * 1024 * 1024;
+/// Notifications relayed through FCM (or another notification target).
+pub const MAX_NOTIFICATION_SIZE: u64 = 8 * 1024;
+/// Config commands and their responses.
+pub const MAX_COMMAND_FILE_SIZE: u64 = 100 * 1024;
@@ -6,6 +6,7 @@
 extern crate serde_derive;
 
 use docopt::Docopt;
+use qrcode::render::unicode;
 use qrcode::QrCode;
 use image::Luma;
 use std::fs;
@@ -14,7 +15,12 @@ use std::io::Write;
 use std::fs::create_dir;
 use std::path::Path;
 use url::Url;
-use secluso_client_server_lib::auth::{create_user_credentials};
+use secluso_client_server_lib::auth::{
+    create_user_credentials, parse_user_credentials, parse_user_credentials_full,
+    rotate_user_credentials, user_credentials_from_parts, UserCredentials, NUM_PASSWORD_CHARS, NUM_USERNAME_CHARS,
+    USER_CREDENTIALS_VERSION,
+};
+use secluso_client_lib::pairing::NUM_SECRET_BYTES;
 use anyhow::Context;
 use anyhow::anyhow;
 
@@ -22,16 +28,31 @@ const USAGE: &str = "
 Helps configure the Secluso server, camera, and app.
 
 Usage:
-  secluso-config-tool --generate-user-credentials --server-addr ADDR --dir DIR
-  secluso-config-tool --generate-camera-secret --dir DIR
+  secluso-config-tool --generate-user-credentials --server-addr ADDR --dir DIR [--credentials-file PATH] [--ascii-qr]
+  secluso-config-tool --rotate-user-credentials --credentials-file PATH --server-addr ADDR --dir DIR [--ascii-qr]
+  secluso-config-tool --generate-camera-secret --dir DIR [--ascii-qr]
+  secluso-config-tool --verify --dir DIR
+  secluso-config-tool --verify-credentials FILE
   secluso-config-tool (--version | -v)
   secluso-config-tool (--help | -h)
 
 Options:
     --generate-user-credentials     Generate a random username and a random key to be used to authenticate with the server.
+    --rotate-user-credentials       Generate a new key for the username in an existing user_credentials file, e.g., if
+                                    the key was leaked. The paired cameras and their data are kept. To switch over:
+                                    give the new user_credentials to the server (PUT /admin/users/<username>, or
+                                    replace the old file in its user_credentials directory and restart it), give
+                                    user_credentials_for_testing to the hub as its credentials_full file and restart
+                                    it, and scan the new QR code in the app.
     --generate-camera-secret        Generate a random secret to be used for camera pairing (used for Raspberry Pi cameras).
+    --verify                        Check the camera_secret and user_credentials files in a directory before deployment.
+    --verify-credentials FILE       Check that a credentials file with the server address (user_credentials_for_testing
+                                    or the JSON in the QR code) is well-formed, without connecting to the server.
     --server-addr ADDR              Address (URL) of the server, e.g., https://example.com:8080/ or http://192.168.0.1/.
     --dir DIR                       Directory for storing the camera's secret files.
+    --credentials-file PATH         Reuse the username and key in an existing user_credentials file instead of
+                                    generating new ones (e.g., to re-render the QR code for a new server address).
+    --ascii-qr                      Also print the QR code in the terminal (the PNG is still saved).
     --version, -v                   Show tool version.
     --help, -h                      Show this screen.
 ";
@@ -39,9 +60,14 @@ Options:
 #[derive(Debug, Deserialize)]
 struct Args {
     flag_generate_user_credentials: bool,
+    flag_rotate_user_credentials: bool,
     flag_generate_camera_secret: bool,
+    flag_verify: bool,
+    flag_verify_credentials: Option<String>,
     flag_server_addr: String,
     flag_dir: String,
+    flag_credentials_file: Option<String>,
+    flag_ascii_qr: bool,
 }
 
 fn main() -> io::Result<()> {
@@ -54,16 +80,56 @@ fn main() -> io::Result<()> {
         .unwrap_or_else(|e| e.exit());
 
     if args.flag_generate_user_credentials {
-        if let Err(e) = generate_user_credentials(Path::new(&args.flag_dir), &args.flag_server_addr) {
-            println!("Failed to generate!");
-            println!("Error: {}", e);
+        if let Err(e) = generate_user_credentials(
+            Path::new(&args.flag_dir),
+            &args.flag_server_addr,
+            args.flag_credentials_file.as_deref().map(Path::new),
+            false,
+            args.flag_ascii_qr,
+        ) {
+            eprintln!("Failed to generate!");
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
         } else {
             println!("Successfully generated!");
         }
+    } else if args.flag_rotate_user_credentials {
+        if let Err(e) = generate_user_credentials(
+            Path::new(&args.flag_dir),
+            &args.flag_server_addr,
+            args.flag_credentials_file.as_deref().map(Path::new),
+            true,
+            args.flag_ascii_qr,
+        ) {
+            eprintln!("Failed to rotate!");
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        } else {
+            println!("Successfully rotated!");
+            println!("Give the new credentials to the server, the hub, and the app (see --help).");
+        }
     } else if args.flag_generate_camera_secret {
-        if let Err(e) = secluso_client_lib::pairing::generate_raspberry_camera_secret(Path::new(&args.flag_dir), true) {
-            println!("Failed to generate camera secret!");
-            println!("Error: {}", e);
+        if let Err(e) = generate_camera_secret(Path::new(&args.flag_dir), args.flag_ascii_qr) {
+            eprintln!("Failed to generate camera secret!");
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(file) = args.flag_verify_credentials {
+        match verify_credentials_file(Path::new(&file)) {
+            Ok((server_addr, username)) => {
+                println!("OK: credentials for {server_addr} (user: {username})");
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.flag_verify {
+        if verify_dir(Path::new(&args.flag_dir)) {
+            println!("Verification passed!");
+        } else {
+            println!("Verification failed!");
+            std::process::exit(1);
         }
     } else {
         println!("Unsupported command!");
@@ -73,7 +139,13 @@ fn main() -> io::Result<()> {
 }
 
 
-fn generate_user_credentials(dir: &Path, mut server_addr: &str) -> anyhow::Result<()> {
+fn generate_user_credentials(
+    dir: &Path,
+    mut server_addr: &str,
+    existing_credentials: Option<&Path>,
+    rotate: bool,
+    ascii_qr: bool,
+) -> anyhow::Result<()> {
     if let Ok(parsed_url) = Url::parse(server_addr) {
         if parsed_url.scheme() != "http" && parsed_url.scheme() != "https" {
             return Err(anyhow!("Invalid server URL scheme: {}", parsed_url.scheme()));
@@ -86,29 +158,419 @@ fn generate_user_credentials(dir: &Path, mut server_addr: &str) -> anyhow::Resul
     server_addr = server_addr.trim_end_matches('/');
 
 
-    let (credentials, credentials_full, credentials_full_testing) =
-        create_user_credentials(server_addr.to_string())?;
+    let (credentials, credentials_full, credentials_full_testing) = match existing_credentials {
+        Some(path) => {
+            let existing = fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let (username, password) = parse_user_credentials(existing).with_context(|| {
+                format!("{} is not a valid user_credentials file", path.display())
+            })?;
+            if rotate {
+                rotate_user_credentials(username, server_addr.to_string())?
+            } else {
+                user_credentials_from_parts(username, password, server_addr.to_string())?
+            }
+        }
+        None => create_user_credentials(server_addr.to_string())?,
+    };
 
-    // Create the directory if it doesn't exist
-    create_dir(dir).context("Failed to create directory (it may already exist)")?;
+    if existing_credentials.is_some() {
+        // We're not creating a new identity, so it's fine to write next to the existing files.
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    } else {
+        // Create the directory if it doesn't exist
+        create_dir(dir).with_context(|| {
+            format!("Failed to create directory {} (it may already exist)", dir.display())
+        })?;
+    }
 
     // Save the credentials in a file to be given to the server (delivery service)
-    let mut file =
-        fs::File::create(dir.join("user_credentials")).context("Could not create user_credentials file")?;
-    file.write_all(&credentials).context("Failed to write to file")?;
+    write_file(&dir.join("user_credentials"), &credentials)?;
 
     // Save the credentials_full (which includes the server addr) as QR code to be shown to the app
     let code = QrCode::new(&credentials_full).context("Failed to generate QR code")?;
     let image = code.render::<Luma<u8>>().build();
+    let qrcode_path = dir.join("user_credentials_qrcode.png");
     image
-        .save(dir.join("user_credentials_qrcode.png"))
-        .context("Failed to save image")?;
+        .save(&qrcode_path)
+        .with_context(|| format!("Failed to save {}", qrcode_path.display()))?;
+    if ascii_qr {
+        print_qr_code(&credentials_full)?;
+    }
 
     // Save the credentials_full in a file to be used for testing with the example app
-    let mut file =
-        fs::File::create(dir.join("user_credentials_for_testing")).expect("Could not create file");
-    let _ = file.write_all(&credentials_full_testing);
+    write_file(&dir.join("user_credentials_for_testing"), &credentials_full_testing)?;
+
+    Ok(())
+}
+
+fn generate_camera_secret(dir: &Path, ascii_qr: bool) -> anyhow::Result<()> {
+    let qr_content = secluso_client_lib::pairing::generate_raspberry_camera_secret(dir, true)
+        .with_context(|| format!("Failed to generate the camera secret in {}", dir.display()))?;
+    if ascii_qr {
+        print_qr_code(qr_content.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    file.write_all(contents)
+        .with_context(|| format!("Failed to write to {}", path.display()))
+}
+
+/// Prints the QR code with Unicode half blocks so that it can be scanned from a terminal.
+fn print_qr_code(content: &[u8]) -> anyhow::Result<()> {
+    let code = QrCode::new(content).context("Failed to generate QR code")?;
+    let rendered = code
+        .render::<unicode::Dense1x2>()
+        // Most terminals use light text on a dark background.
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .quiet_zone(true)
+        .build();
+    println!("{rendered}");
 
     Ok(())
 }
 
+/// Checks a credentials file that includes the server address and returns the server
+/// address and the username.
+fn verify_credentials_file(path: &Path) -> anyhow::Result<(String, String)> {
+    check_user_credentials_full(path, None).map_err(|outcome| match outcome {
+        CheckOutcome::Skip(reason) | CheckOutcome::Fail(reason) => {
+            anyhow!("{}: {reason}", path.display())
+        }
+        CheckOutcome::Pass => anyhow!("{}: check failed", path.display()),
+    })
+}
+
+enum CheckOutcome {
+    Pass,
+    Skip(String),
+    Fail(String),
+}
+
+/// Checks the secret files generated in dir and prints a report.
+/// Returns true if all the checks passed (and at least one of the files was found).
+fn verify_dir(dir: &Path) -> bool {
+    let checks = [
+        ("camera_secret", check_camera_secret(dir)),
+        ("user_credentials", check_user_credentials(dir)),
+        (
+            "user_credentials_for_testing",
+            check_user_credentials_full(
+                &dir.join("user_credentials_for_testing"),
+                Some(&dir.join("user_credentials")),
+            )
+            .map_or_else(|outcome| outcome, |_| CheckOutcome::Pass),
+        ),
+    ];
+
+    for (name, outcome) in &checks {
+        match outcome {
+            CheckOutcome::Pass => println!("[PASS] {name}"),
+            CheckOutcome::Skip(reason) => println!("[SKIP] {name}: {reason}"),
+            CheckOutcome::Fail(reason) => println!("[FAIL] {name}: {reason}"),
+        }
+    }
+
+    let any_failed = checks
+        .iter()
+        .any(|(_, outcome)| matches!(outcome, CheckOutcome::Fail(_)));
+    let all_skipped = checks
+        .iter()
+        .all(|(_, outcome)| matches!(outcome, CheckOutcome::Skip(_)));
+    if all_skipped {
+        println!("No secret files found in {}", dir.display());
+    }
+
+    !any_failed && !all_skipped
+}
+
+// The camera secret and the user credentials are usually generated in different directories.
+fn read_if_exists(path: &Path) -> Result<Vec<u8>, CheckOutcome> {
+    if !path.exists() {
+        return Err(CheckOutcome::Skip("not found".to_string()));
+    }
+
+    fs::read(path).map_err(|e| CheckOutcome::Fail(format!("failed to read: {e}")))
+}
+
+fn check_camera_secret(dir: &Path) -> CheckOutcome {
+    let secret = match read_if_exists(&dir.join("camera_secret")) {
+        Ok(secret) => secret,
+        Err(outcome) => return outcome,
+    };
+
+    if secret.len() != NUM_SECRET_BYTES {
+        return CheckOutcome::Fail(format!(
+            "expected {NUM_SECRET_BYTES} bytes, found {}",
+            secret.len()
+        ));
+    }
+
+    CheckOutcome::Pass
+}
+
+fn check_user_credentials(dir: &Path) -> CheckOutcome {
+    let credentials = match read_if_exists(&dir.join("user_credentials")) {
+        Ok(credentials) => credentials,
+        Err(outcome) => return outcome,
+    };
+
+    match parse_user_credentials(credentials) {
+        Ok(_) => CheckOutcome::Pass,
+        Err(e) => CheckOutcome::Fail(format!("invalid username/key ({e})")),
+    }
+}
+
+// The full credentials also embed the server address. They can be JSON (as in the QR code) or
+// the username, key, and server address back to back. If given, they must match user_credentials.
+// Returns the server address and the username.
+fn check_user_credentials_full(
+    path: &Path,
+    user_credentials: Option<&Path>,
+) -> Result<(String, String), CheckOutcome> {
+    let contents = read_if_exists(path)?;
+
+    let (username, password, server_addr) = if contents.first() == Some(&b'{') {
+        let credentials: UserCredentials = serde_json::from_slice(&contents)
+            .map_err(|e| CheckOutcome::Fail(format!("invalid credentials JSON ({e})")))?;
+        if credentials.version != USER_CREDENTIALS_VERSION {
+            return Err(CheckOutcome::Fail(format!(
+                "unsupported credentials version {:?} (expected {:?})",
+                credentials.version, USER_CREDENTIALS_VERSION
+            )));
+        }
+        (
+            credentials.username,
+            credentials.password,
+            credentials.server_addr,
+        )
+    } else {
+        // Usernames and keys are ASCII (see generate_random()).
+        if !contents.is_ascii() {
+            return Err(CheckOutcome::Fail("non-ASCII characters".to_string()));
+        }
+        if contents.len() == NUM_USERNAME_CHARS + NUM_PASSWORD_CHARS {
+            return Err(CheckOutcome::Fail(
+                "only a username and key (it's the file for the server); \
+                 there's no server address to check"
+                    .to_string(),
+            ));
+        }
+        let len = contents.len();
+        parse_user_credentials_full(contents).map_err(|_| {
+            CheckOutcome::Fail(format!(
+                "truncated ({len} bytes, expected a {NUM_USERNAME_CHARS}-character username, \
+                 a {NUM_PASSWORD_CHARS}-character key, and the server address)"
+            ))
+        })?
+    };
+
+    if username.chars().count() != NUM_USERNAME_CHARS {
+        return Err(CheckOutcome::Fail(format!(
+            "invalid username length: {} (expected {NUM_USERNAME_CHARS})",
+            username.chars().count()
+        )));
+    }
+    if password.chars().count() != NUM_PASSWORD_CHARS {
+        return Err(CheckOutcome::Fail(format!(
+            "invalid key length: {} (expected {NUM_PASSWORD_CHARS})",
+            password.chars().count()
+        )));
+    }
+
+    match Url::parse(&server_addr) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        Ok(url) => {
+            return Err(CheckOutcome::Fail(format!(
+                "invalid server URL scheme: {}",
+                url.scheme()
+            )))
+        }
+        Err(e) => {
+            return Err(CheckOutcome::Fail(format!(
+                "invalid server URL {server_addr:?} ({e})"
+            )))
+        }
+    }
+
+    if let Some(Ok(credentials)) = user_credentials.map(fs::read) {
+        if credentials != format!("{username}{password}").into_bytes() {
+            return Err(CheckOutcome::Fail(
+                "username/key do not match user_credentials".to_string(),
+            ));
+        }
+    }
+
+    Ok((server_addr, username))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("secluso-config-tool-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Permissions don't apply to root (e.g., in a container).
+    fn running_as_root() -> bool {
+        fs::read_to_string("/proc/self/status")
+            .map(|status| {
+                status
+                    .lines()
+                    .any(|l| l.starts_with("Uid:") && l.split_whitespace().nth(2) == Some("0"))
+            })
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn write_file_writes_the_contents() {
+        let dir = test_dir("write");
+        let path = dir.join("camera_secret");
+
+        write_file(&path, b"secret").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn write_file_reports_a_missing_dir() {
+        let dir = test_dir("missing");
+        let path = dir.join("does_not_exist").join("camera_secret");
+
+        let err = write_file(&path, b"secret").unwrap_err();
+        assert!(
+            err.to_string().contains(&path.display().to_string()),
+            "{err}"
+        );
+        assert_eq!(
+            err.downcast_ref::<io::Error>().map(|e| e.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn write_file_reports_a_read_only_dir() {
+        if running_as_root() {
+            return;
+        }
+        let dir = test_dir("read-only");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+        let path = dir.join("camera_secret");
+
+        let result = write_file(&path, b"secret");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains(&path.display().to_string()),
+            "{err}"
+        );
+        assert_eq!(
+            err.downcast_ref::<io::Error>().map(|e| e.kind()),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    const USERNAME: &str = "abcdefghijklmn";
+    const KEY: &str = "ABCDEFGHIJKLMN";
+
+    #[test]
+    fn verify_credentials_accepts_valid_files() {
+        let dir = test_dir("valid");
+        let legacy = dir.join("user_credentials_for_testing");
+        fs::write(&legacy, format!("{USERNAME}{KEY}https://example.com:8080")).unwrap();
+        let json = dir.join("credentials.json");
+        fs::write(
+            &json,
+            serde_json::to_vec(&UserCredentials {
+                version: USER_CREDENTIALS_VERSION.to_string(),
+                username: USERNAME.to_string(),
+                password: KEY.to_string(),
+                server_addr: "http://192.168.0.1/".to_string(),
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            verify_credentials_file(&legacy).unwrap(),
+            ("https://example.com:8080".to_string(), USERNAME.to_string())
+        );
+        assert_eq!(
+            verify_credentials_file(&json).unwrap(),
+            ("http://192.168.0.1/".to_string(), USERNAME.to_string())
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn verify_credentials_rejects_a_truncated_file() {
+        let dir = test_dir("truncated");
+        let path = dir.join("user_credentials_for_testing");
+        fs::write(&path, &USERNAME[..10]).unwrap();
+
+        let err = verify_credentials_file(&path).unwrap_err();
+        assert!(err.to_string().contains("truncated (10 bytes"), "{err}");
+
+        // The server's file has no address.
+        fs::write(&path, format!("{USERNAME}{KEY}")).unwrap();
+        let err = verify_credentials_file(&path).unwrap_err();
+        assert!(err.to_string().contains("no server address"), "{err}");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn verify_credentials_rejects_an_invalid_url() {
+        let dir = test_dir("invalid-url");
+        let path = dir.join("user_credentials_for_testing");
+
+        fs::write(&path, format!("{USERNAME}{KEY}example.com:8080")).unwrap();
+        let err = verify_credentials_file(&path).unwrap_err();
+        assert!(err.to_string().contains("invalid server URL"), "{err}");
+
+        fs::write(&path, format!("{USERNAME}{KEY}not a url")).unwrap();
+        let err = verify_credentials_file(&path).unwrap_err();
+        assert!(err.to_string().contains("invalid server URL"), "{err}");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn verify_dir_checks_the_full_credentials_against_user_credentials() {
+        let dir = test_dir("verify-dir");
+        fs::write(
+            dir.join("user_credentials_for_testing"),
+            format!("{USERNAME}{KEY}https://example.com"),
+        )
+        .unwrap();
+        fs::write(dir.join("user_credentials"), format!("{USERNAME}{KEY}")).unwrap();
+        assert!(verify_dir(&dir));
+
+        fs::write(dir.join("user_credentials"), format!("{KEY}{USERNAME}")).unwrap();
+        assert!(!verify_dir(&dir));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
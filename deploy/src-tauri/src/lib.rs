@@ -1,6 +1,7 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 // more tauri command info at https://tauri.app/develop/calling-rust/
 
+mod migration;
 mod pi_hub_provision;
 mod provision_server;
 mod release_config;
@@ -26,6 +27,8 @@ pub fn run() {
             provision_server::default_ssh_key_path,
             provision_server::generate_ssh_keypair,
             provision_server::install_ssh_public_key,
+            migration::plan_migration,
+            migration::apply_migration,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
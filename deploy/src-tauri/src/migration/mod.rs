@@ -0,0 +1,436 @@
+//! SPDX-License-Identifier: GPL-3.0-or-later
+// Moving an existing deployment to a new server.
+// plan_migration only reads the old files and describes what will change.
+// apply_migration generates the new credentials and optionally pushes them to the hubs.
+
+use crate::pi_hub_provision::credentials::generate_user_credentials_only;
+use crate::provision_server::events::{emit, log_line, step_error, step_ok, step_start, ProvisionEvent};
+use crate::provision_server::ssh::{connect_ssh, exec_remote_script_streaming, scp_upload_bytes, sudo_prefix};
+use crate::provision_server::types::SshTarget;
+use crate::provision_server::ProvisionStart;
+use anyhow::{anyhow, bail, Context, Result};
+use secluso_client_server_lib::auth::{parse_user_credentials_full, UserCredentials};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tauri::AppHandle;
+use uuid::Uuid;
+
+// These mirror the layout used by camera_hub.
+const STATE_DIR_GENERAL: &str = "state";
+const FIRST_TIME_DONE: &str = "first_time_done";
+const RASPBERRY_CAMERA_NAME: &str = "Raspberry Pi camera";
+
+// Rough numbers used for the downtime estimate shown to the user.
+const HUB_RESTART_SECS: u64 = 30;
+const CAMERA_REPAIR_SECS: u64 = 180;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OldConfigPaths {
+  /// cameras.yaml of an IP camera hub. None for a Raspberry Pi hub.
+  pub cameras_yaml: Option<String>,
+  /// credentials_full (the file the hubs and the app read the server credentials from)
+  pub credentials_full: String,
+  /// Working directory of the hub copied to this machine, used to check the pairing state of each camera.
+  pub hub_dir: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RepairDecision {
+  NotNeeded,
+  Required { reason: String },
+  Unknown { reason: String },
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraMigration {
+  pub name: String,
+  pub state_dir: String,
+  pub repair: RepairDecision,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationPlan {
+  pub old_server_url: String,
+  pub new_server_url: String,
+  pub cameras: Vec<CameraMigration>,
+  pub files_to_replace: Vec<String>,
+  pub steps: Vec<String>,
+  pub estimated_downtime_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HubTarget {
+  pub ssh: SshTarget,
+  /// Working directory of camera_hub on the hub (where credentials_full lives).
+  pub working_dir: String,
+  /// systemd unit to restart once the credentials are replaced. Left alone if not set.
+  pub service: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CamerasYaml {
+  cameras: Vec<CameraYamlEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CameraYamlEntry {
+  name: String,
+}
+
+// tauri commands
+
+#[tauri::command]
+pub async fn plan_migration(old_config_paths: OldConfigPaths, new_server_url: String) -> Result<MigrationPlan, String> {
+  tokio::task::spawn_blocking(move || build_plan(&old_config_paths, &new_server_url))
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r.map_err(|e| format!("{e:#}")))
+}
+
+#[tauri::command]
+pub async fn apply_migration(
+  app: AppHandle,
+  old_config_paths: OldConfigPaths,
+  new_server_url: String,
+  output_dir: String,
+  hub: Option<HubTarget>,
+) -> Result<ProvisionStart, String> {
+  let run_id = Uuid::new_v4();
+
+  // return so ui can start listening while work runs in the background
+  let app2 = app.clone();
+  tokio::task::spawn_blocking(move || {
+    let res = run_migration(&app2, run_id, &old_config_paths, &new_server_url, Path::new(&output_dir), hub.as_ref());
+    if let Err(e) = res {
+      log_line(&app2, run_id, "error", Some("fatal"), format!("{e:#}"));
+      emit(&app2, ProvisionEvent::Done { run_id, ok: false });
+    } else {
+      emit(&app2, ProvisionEvent::Done { run_id, ok: true });
+    }
+  });
+
+  Ok(ProvisionStart { run_id })
+}
+
+// planning
+
+fn build_plan(paths: &OldConfigPaths, new_server_url: &str) -> Result<MigrationPlan> {
+  let old_server_url = read_old_server_url(Path::new(&paths.credentials_full))?;
+  let new_server_url = new_server_url.trim().trim_end_matches('/').to_string();
+  if new_server_url.is_empty() {
+    bail!("New server URL is empty.");
+  }
+  if new_server_url == old_server_url {
+    bail!("The new server URL is the same as the current one ({old_server_url}).");
+  }
+
+  let hub_dir = paths.hub_dir.as_deref().map(Path::new);
+  let cameras = match &paths.cameras_yaml {
+    Some(p) => read_camera_names(Path::new(p))?
+      .into_iter()
+      .map(|name| {
+        let state_dir = format!("{}/{}", STATE_DIR_GENERAL, name.replace(" ", "_").to_lowercase());
+        camera_migration(name, state_dir, hub_dir)
+      })
+      .collect(),
+    None => vec![camera_migration(
+      RASPBERRY_CAMERA_NAME.to_string(),
+      STATE_DIR_GENERAL.to_string(),
+      hub_dir,
+    )],
+  };
+
+  let files_to_replace = vec![
+    "credentials_full (hub working directory)".to_string(),
+    "user_credentials (server)".to_string(),
+  ];
+
+  let repairs = cameras
+    .iter()
+    .filter(|c| !matches!(c.repair, RepairDecision::NotNeeded))
+    .count() as u64;
+
+  let mut steps = vec![
+    format!("Provision the new server at {new_server_url} with the newly generated user_credentials."),
+    "Stop the camera hub.".to_string(),
+    "Replace credentials_full in the hub working directory.".to_string(),
+    "Start the camera hub and check that it reaches the new server.".to_string(),
+    "Scan the new user credentials QR code in the app.".to_string(),
+  ];
+  for c in &cameras {
+    match &c.repair {
+      RepairDecision::NotNeeded => {}
+      RepairDecision::Required { reason } | RepairDecision::Unknown { reason } => {
+        steps.push(format!("Pair \"{}\" again from the app ({reason}).", c.name));
+      }
+    }
+  }
+  steps.push(format!("Decommission the old server at {old_server_url}."));
+
+  Ok(MigrationPlan {
+    old_server_url,
+    new_server_url,
+    cameras,
+    files_to_replace,
+    steps,
+    estimated_downtime_secs: HUB_RESTART_SECS + repairs * CAMERA_REPAIR_SECS,
+  })
+}
+
+// The MLS groups between a camera and the app are end-to-end and do not depend on the server,
+// so an already paired camera keeps working once the hub and the app use the new credentials.
+fn camera_migration(name: String, state_dir: String, hub_dir: Option<&Path>) -> CameraMigration {
+  let repair = match hub_dir {
+    None => RepairDecision::Unknown {
+      reason: "hub working directory not provided".to_string(),
+    },
+    Some(dir) => {
+      let state_path = dir.join(&state_dir);
+      if state_path.join(FIRST_TIME_DONE).exists() {
+        RepairDecision::NotNeeded
+      } else if state_path.exists() {
+        RepairDecision::Required {
+          reason: "pairing was never completed".to_string(),
+        }
+      } else {
+        RepairDecision::Required {
+          reason: "no pairing state found".to_string(),
+        }
+      }
+    }
+  };
+
+  CameraMigration { name, state_dir, repair }
+}
+
+fn read_camera_names(path: &Path) -> Result<Vec<String>> {
+  let content = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+  let parsed: CamerasYaml =
+    serde_yaml2::from_str(&content).map_err(|e| anyhow!("Failed to parse {}: {e}", path.display()))?;
+
+  Ok(parsed.cameras.into_iter().map(|c| c.name).collect())
+}
+
+fn read_old_server_url(path: &Path) -> Result<String> {
+  let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+  // Newer credentials are JSON; older ones are the raw username + password + address.
+  let server_addr = match serde_json::from_slice::<UserCredentials>(&bytes) {
+    Ok(creds) => creds.server_addr,
+    Err(_) => parse_user_credentials_full(bytes)
+      .with_context(|| format!("parsing {}", path.display()))?
+      .2,
+  };
+
+  Ok(server_addr.trim_end_matches('/').to_string())
+}
+
+// applying
+
+fn run_migration(
+  app: &AppHandle,
+  run_id: Uuid,
+  paths: &OldConfigPaths,
+  new_server_url: &str,
+  output_dir: &Path,
+  hub: Option<&HubTarget>,
+) -> Result<()> {
+  step_start(app, run_id, "plan", "Checking the current configuration");
+  let plan = match build_plan(paths, new_server_url) {
+    Ok(plan) => plan,
+    Err(e) => {
+      step_error(app, run_id, "plan", format!("{e:#}"));
+      return Err(e);
+    }
+  };
+  for step in &plan.steps {
+    log_line(app, run_id, "info", Some("plan"), step.clone());
+  }
+  step_ok(app, run_id, "plan");
+
+  step_start(app, run_id, "credentials", "Generating credentials for the new server");
+  if let Err(e) =
+    generate_user_credentials_only(app, run_id, output_dir, &plan.new_server_url, "", None, None)
+  {
+    step_error(app, run_id, "credentials", format!("{e:#}"));
+    return Err(e);
+  }
+  log_line(
+    app,
+    run_id,
+    "info",
+    Some("credentials"),
+    format!("New credentials written to {}", output_dir.display()),
+  );
+  step_ok(app, run_id, "credentials");
+
+  if let Some(hub) = hub {
+    step_start(app, run_id, "push_hub", "Replacing the credentials on the hub");
+    if let Err(e) = push_to_hub(app, run_id, hub, &output_dir.join("credentials_full")) {
+      step_error(app, run_id, "push_hub", format!("{e:#}"));
+      return Err(e);
+    }
+    step_ok(app, run_id, "push_hub");
+  }
+
+  Ok(())
+}
+
+fn push_to_hub(app: &AppHandle, run_id: Uuid, hub: &HubTarget, credentials_full: &Path) -> Result<()> {
+  let bytes = fs::read(credentials_full).with_context(|| format!("reading {}", credentials_full.display()))?;
+  let (sess, _temps) = connect_ssh(&hub.ssh)?;
+
+  // Upload to a temp path as the ssh user, then move it into place with sudo.
+  let staged = format!("/tmp/secluso_credentials_full_{run_id}");
+  scp_upload_bytes(&sess, &staged, 0o600, &bytes)?;
+
+  let (sudo, sudo_pw) = sudo_prefix(&hub.ssh);
+  let script = r#"
+set -eu
+SUDO="${SUDO_CMD:-}"
+if [ -n "$HUB_SERVICE" ]; then $SUDO systemctl stop "$HUB_SERVICE"; fi
+if [ -f "$HUB_DIR/credentials_full" ]; then $SUDO cp "$HUB_DIR/credentials_full" "$HUB_DIR/credentials_full.bak"; fi
+$SUDO install -m 600 "$STAGED" "$HUB_DIR/credentials_full"
+rm -f "$STAGED"
+if [ -n "$HUB_SERVICE" ]; then $SUDO systemctl start "$HUB_SERVICE"; fi
+"#;
+
+  exec_remote_script_streaming(
+    app,
+    run_id,
+    "push_hub",
+    &sess,
+    &[
+      ("SUDO_CMD", sudo),
+      ("STAGED", staged),
+      ("HUB_DIR", hub.working_dir.clone()),
+      ("HUB_SERVICE", hub.service.clone().unwrap_or_default()),
+    ],
+    sudo_pw,
+    script,
+  )?;
+
+  if hub.service.is_none() {
+    log_line(
+      app,
+      run_id,
+      "warn",
+      Some("push_hub"),
+      "No hub service given; restart the camera hub manually to use the new server.",
+    );
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  const OLD_URL: &str = "https://old.example.com";
+  const NEW_URL: &str = "https://new.example.com";
+
+  fn write_credentials(dir: &Path) -> String {
+    let creds = UserCredentials {
+      version: "uc-v1.0".to_string(),
+      username: "u".repeat(14),
+      password: "p".repeat(14),
+      server_addr: format!("{OLD_URL}/"),
+    };
+    let path = dir.join("credentials_full");
+    fs::write(&path, serde_json::to_vec(&creds).unwrap()).unwrap();
+    path.to_string_lossy().into_owned()
+  }
+
+  fn ip_hub(dir: &Path) -> OldConfigPaths {
+    let cameras_yaml = dir.join("cameras.yaml");
+    fs::write(
+      &cameras_yaml,
+      "cameras:\n  - name: Front Door\n  - name: Garage\n  - name: Backyard\n",
+    )
+    .unwrap();
+
+    // Front Door is paired, Garage started pairing but never finished, Backyard has no state.
+    let hub_dir = dir.join("hub");
+    fs::create_dir_all(hub_dir.join("state/front_door")).unwrap();
+    fs::write(hub_dir.join("state/front_door").join(FIRST_TIME_DONE), b"").unwrap();
+    fs::create_dir_all(hub_dir.join("state/garage")).unwrap();
+
+    OldConfigPaths {
+      cameras_yaml: Some(cameras_yaml.to_string_lossy().into_owned()),
+      credentials_full: write_credentials(dir),
+      hub_dir: Some(hub_dir.to_string_lossy().into_owned()),
+    }
+  }
+
+  #[test]
+  fn plan_only_repairs_cameras_that_were_never_paired() {
+    let dir = TempDir::new().unwrap();
+    let plan = build_plan(&ip_hub(dir.path()), &format!("{NEW_URL}/")).unwrap();
+
+    assert_eq!(plan.old_server_url, OLD_URL);
+    assert_eq!(plan.new_server_url, NEW_URL);
+
+    let repairs: Vec<(&str, &str, bool)> = plan
+      .cameras
+      .iter()
+      .map(|c| (c.name.as_str(), c.state_dir.as_str(), matches!(c.repair, RepairDecision::NotNeeded)))
+      .collect();
+    assert_eq!(
+      repairs,
+      vec![
+        ("Front Door", "state/front_door", true),
+        ("Garage", "state/garage", false),
+        ("Backyard", "state/backyard", false),
+      ]
+    );
+
+    assert_eq!(plan.estimated_downtime_secs, HUB_RESTART_SECS + 2 * CAMERA_REPAIR_SECS);
+    assert!(plan.steps.iter().any(|s| s.contains("\"Garage\"") && s.contains("never completed")));
+    assert!(plan.steps.iter().any(|s| s.contains("\"Backyard\"") && s.contains("no pairing state")));
+    assert!(!plan.steps.iter().any(|s| s.contains("\"Front Door\"")));
+    assert!(plan.steps.last().unwrap().contains(OLD_URL));
+  }
+
+  #[test]
+  fn repair_is_unknown_without_the_hub_dir() {
+    let dir = TempDir::new().unwrap();
+    let paths = OldConfigPaths {
+      cameras_yaml: None,
+      credentials_full: write_credentials(dir.path()),
+      hub_dir: None,
+    };
+    let plan = build_plan(&paths, NEW_URL).unwrap();
+
+    assert_eq!(plan.cameras.len(), 1);
+    assert_eq!(plan.cameras[0].name, RASPBERRY_CAMERA_NAME);
+    assert_eq!(plan.cameras[0].state_dir, STATE_DIR_GENERAL);
+    assert!(matches!(plan.cameras[0].repair, RepairDecision::Unknown { .. }));
+    assert_eq!(plan.estimated_downtime_secs, HUB_RESTART_SECS + CAMERA_REPAIR_SECS);
+  }
+
+  #[test]
+  fn plan_reads_legacy_credentials() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("credentials_full");
+    fs::write(&path, format!("{}{}{OLD_URL}", "u".repeat(14), "p".repeat(14))).unwrap();
+
+    assert_eq!(read_old_server_url(&path).unwrap(), OLD_URL);
+  }
+
+  #[test]
+  fn plan_rejects_the_same_or_an_empty_server() {
+    let dir = TempDir::new().unwrap();
+    let paths = ip_hub(dir.path());
+
+    assert!(build_plan(&paths, OLD_URL).is_err());
+    assert!(build_plan(&paths, "  ").is_err());
+  }
+}
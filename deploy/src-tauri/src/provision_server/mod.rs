@@ -1,11 +1,11 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
-mod events;
+pub(crate) mod events;
 mod harden;
 mod key_gen;
 mod preflight;
 mod provision;
 mod script;
-mod ssh;
+pub(crate) mod ssh;
 pub(crate) mod types;
 
 use crate::provision_server::events::{emit, log_line, step_error, step_ok, step_start, ProvisionEvent};
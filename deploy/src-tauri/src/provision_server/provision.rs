@@ -71,6 +71,7 @@ fn download_verified_artifacts(
         remote_arch,
         None,
         &signers,
+        None,
     )
     .with_context(|| format!("Downloading and verifying secluso-server for {remote_arch}"))?;
 
@@ -91,6 +92,7 @@ fn download_verified_artifacts(
                 .context("bundle path is not valid UTF-8")?,
         ),
         &signers,
+        None,
     )
     .with_context(|| format!("Downloading and verifying secluso-update for {remote_arch}"))?;
 
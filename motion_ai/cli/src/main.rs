@@ -2,13 +2,12 @@
 
 use std::io::*;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use secluso_motion_ai::backend::spawn_replay_server;
 use secluso_motion_ai::frame::RawFrame;
-use secluso_motion_ai::logic::pipeline::PipelineController;
+use secluso_motion_ai::logic::pipeline::{HealthThresholds, PipelineController};
 use secluso_motion_ai::pipeline;
 
 /// Matches label for MacOS laptop CPU sensor (allows to test on Mac computer when Raspberry Pi is inaccessible)
@@ -47,8 +46,8 @@ fn main() -> anyhow::Result<()> {
                 PathBuf::from(runs_path_trimmed)
             };
 
-            let (_join_handle, success) = spawn_replay_server(runs_root);
-            if !success {
+            let server = spawn_replay_server(runs_root);
+            if !server.started_ok {
                 println!("Replay server failed to start.");
                 return Ok(());
             }
@@ -94,27 +93,15 @@ fn use_from_video(video_path: &str) -> std::result::Result<(), anyhow::Error> {
     ];
 
     // Create and start controller
-    let mut new_controller = PipelineController::new(pipeline, true, false)?;
-    new_controller.start_working();
-    let controller = Arc::new(Mutex::new(new_controller));
-    let controller_clone = Arc::clone(&controller);
-
-    // Background thread: runs the pipeline's main event loop
-    thread::spawn(move || {
-        //todo: only loop until exit
-        loop {
-            // when false (health issue), we should exit + we should also have some way for user to safely exit
-            let result = controller_clone.lock().unwrap().tick(TEMP_LABEL);
-            if let Err(e) = result {
-                println!("Encountered error: {e}");
-                break;
-            } else if let Ok(accepted) = result
-                && !accepted
-            {
-                println!("Not accepted");
-                break;
-            }
-            thread::sleep(Duration::from_millis(100));
+    let mut controller = PipelineController::new(pipeline, true, false)?
+        .with_health_thresholds(HealthThresholds::from_env()?);
+    controller.start_working();
+    let frame_sender = controller.frame_sender();
+
+    // Background thread: runs the pipeline's main event loop until we stop sending frames
+    let worker = thread::spawn(move || {
+        if let Err(e) = controller.run(TEMP_LABEL, |_| {}) {
+            println!("Encountered error: {e}");
         }
 
         println!("Exited loop");
@@ -134,13 +121,18 @@ fn use_from_video(video_path: &str) -> std::result::Result<(), anyhow::Error> {
                 last_frame_time = time.as_secs();
 
                 let raw_frame = RawFrame::create_from_rgb(frame);
-                controller.lock().unwrap().push_frame(raw_frame?);
+                if frame_sender.send(raw_frame?).is_err() {
+                    break; // The pipeline stopped (the worker prints why).
+                }
             }
         } else {
             break; // Stop decoding on failure
         }
     }
 
+    drop(frame_sender);
+    let _ = worker.join();
+
     Ok(())
 }
 #[cfg(feature = "file_mode")]
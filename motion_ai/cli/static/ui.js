@@ -68,6 +68,7 @@
     const AUTO_REFRESH_MS = 5000;
     let reloadInFlight = false;
     let autoReloadId = null;
+    let liveSource = null;
 
     /* init */
     document.addEventListener("DOMContentLoaded", init);
@@ -327,12 +328,15 @@
     async function selectSession(id, opts = {}) {
         if (!id) {
             sid = null;
+            followLive(null);
             clearUI();
             return;
         }
+        const changed = sid !== id;
         sid = id;
         [...listEl.children].forEach(li => li.classList.toggle("selected", li.textContent === id));
         await ensureSessionDetail(id);
+        if (changed || !liveSource) followLive(id);
         rebuildRunState({runId: opts.runId, frame: opts.frame});
         await fetchSeries(id, true);
         drawChart();
@@ -342,6 +346,35 @@
         updateDataMeta();
     }
 
+    /* live events, pushed by the server as the session's telemetry is written */
+    function followLive(id) {
+        if (liveSource) {
+            liveSource.close();
+            liveSource = null;
+        }
+        if (!id || !window.EventSource) return;
+
+        const source = new EventSource(`/sessions/${encodeURIComponent(id)}/live`);
+        source.addEventListener("telemetry", msg => {
+            const s = sessionDetails.get(id);
+            if (!s) return;
+            s.events = s.events || [];
+            s.events.push(JSON.parse(msg.data));
+            s.event_total = (Number.isFinite(+s.event_total) ? +s.event_total : 0) + 1;
+            if (id !== sid) return;
+            renderUnifiedLog();
+            updateDataMeta();
+        });
+        source.addEventListener("reset", async () => {
+            await ensureSessionDetail(id, {force: true});
+            if (id !== sid) return;
+            rebuildRunState();
+            renderUnifiedLog();
+            updateDataMeta();
+        });
+        liveSource = source;
+    }
+
     function current() {
         return sessionDetails.get(sid) || {id: "empty", frames: [], events: [], frame_total: 0, event_total: 0};
     }
@@ -1005,8 +1038,11 @@
         const n = health.length;
         const xAt = i => padL + (i / (n - 1)) * plotW;
         const yPct = v => padT + (1 - clamp01(v / 100)) * plotH;
-        const tMin = Math.min(...health.map(h => safeNum(h.temp, 0)));
-        const tMax = Math.max(...health.map(h => safeNum(h.temp, 0)));
+        // Keep the temperature thresholds in view, so that the readings can be compared to them.
+        const isNum = v => typeof v === "number" && Number.isFinite(v);
+        const temps = health.flatMap(h => [h.temp, h.temp_high, h.temp_critical]).filter(isNum);
+        const tMin = temps.length ? Math.min(...temps) : 0;
+        const tMax = temps.length ? Math.max(...temps) : 0;
         const tRange = (tMax > tMin) ? (tMax - tMin) : 1;
         const yTemp = t => padT + (1 - ((t - tMin) / tRange)) * plotH;
 
@@ -1031,6 +1067,22 @@
         if (visible.ram) drawSeries(health, h => yPct(safeNum(h.ram, 0)), "#00a87e", [6, 4]);
         if (visible.temp) drawSeries(health, h => yTemp(safeNum(h.temp, 0)), "#ff6a00", [3, 3]);
 
+        // Thresholds at which the pipeline switches to the fast model (or stops inference).
+        function drawThreshold(key, yFn, color) {
+            // Sessions recorded before the thresholds were logged don't have them.
+            if (!health.every(h => isNum(h[key]))) return;
+            ctx.save();
+            ctx.globalAlpha = 0.55;
+            drawSeries(health, h => yFn(h[key]), color, [2, 6]);
+            ctx.restore();
+        }
+
+        if (visible.cpu) drawThreshold("cpu_cap", yPct, "#005ff9");
+        if (visible.temp) {
+            drawThreshold("temp_high", yTemp, "#ff6a00");
+            drawThreshold("temp_critical", yTemp, "#d7263d");
+        }
+
         let iActive;
         if (hoverIndex != null) {
             iActive = clamp(hoverIndex, 0, n - 1);
@@ -2,21 +2,31 @@
 
 use anyhow::{Context, Result, bail};
 use rocket::{
-    State, fairing::AdHoc, form::FromForm, fs::FileServer, get, http::ContentType, post,
-    response::content::RawHtml, routes, serde::json::Json,
+    Build, Rocket, Shutdown, State,
+    fairing::AdHoc,
+    form::FromForm,
+    fs::FileServer,
+    get,
+    http::ContentType,
+    post,
+    response::content::RawHtml,
+    response::stream::{Event, EventStream},
+    routes,
+    serde::json::Json,
+    tokio::{select, time},
 };
 use serde::Serialize;
 use serde_json::Value;
 use std::{
     cmp::Ordering,
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     fs,
-    io::{BufRead, BufReader},
-    path::{Path, PathBuf},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
     sync::{Arc, RwLock, mpsc},
     thread,
     thread::JoinHandle,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::runtime::Builder as RtBuilder;
 use walkdir::WalkDir;
@@ -223,7 +233,7 @@ struct SessionDetail {
     id: String,
     /// Browser URLs for frames (/runs/<id>/<subdir>/<file>).
     frames: Vec<String>,
-    /// Per-frame events (mapped via stage.replay_frame_idx heuristic).
+    /// Per-frame events (mapped to the first frame of their run).
     events: Vec<FrontEvent>,
     /// Total frames available on disk (before tailing).
     frame_total: usize,
@@ -245,6 +255,13 @@ struct SeriesHealth {
     cpu: f32,
     ram: f32,
     temp: f32,
+    /// Thresholds in effect (not in telemetry from before they were configurable).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temp_high: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temp_critical: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_cap: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     run: Option<String>,
 }
@@ -280,6 +297,35 @@ struct SessionQuery {
     offset: Option<usize>,
     frames_tail: Option<usize>,
     events_tail: Option<usize>,
+    /// Number of the most recent events to skip, to page backward.
+    events_offset: Option<usize>,
+    /// Only events with a ts (ms since the epoch) in this range, inclusive.
+    ts_from: Option<u64>,
+    ts_to: Option<u64>,
+}
+
+/// Which events of a session to return.
+#[derive(Debug, Clone, Default)]
+struct EventWindow {
+    ts_from: Option<u128>,
+    ts_to: Option<u128>,
+    /// Number of the most recent matching events to skip.
+    offset: usize,
+    /// Number of matching events to return (the most recent ones before the offset), None for all.
+    tail: Option<usize>,
+}
+
+impl EventWindow {
+    // Events without a ts are only in the window when there's no time range.
+    fn in_range(&self, ev: &FrontEvent) -> bool {
+        if self.ts_from.is_none() && self.ts_to.is_none() {
+            return true;
+        }
+        let Some(ts) = ev.ts else {
+            return false;
+        };
+        self.ts_from.is_none_or(|from| ts >= from) && self.ts_to.is_none_or(|to| ts <= to)
+    }
 }
 
 #[derive(Debug, Default, FromForm)]
@@ -295,11 +341,69 @@ const DEFAULT_EVENTS_TAIL: usize = 1000;
 const MAX_EVENTS_TAIL: usize = 20000;
 const DEFAULT_SERIES_TAIL: usize = 1500;
 const MAX_SERIES_TAIL: usize = 20000;
+const LIVE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// How long we wait for Rocket's liftoff, unless overridden with REPLAY_STARTUP_TIMEOUT_SECS.
+// Slow containers can take more than the original 15 seconds to get there.
+const DEFAULT_STARTUP_TIMEOUT_SECS: u64 = 30;
+const LATE_STARTUP_SECS: u64 = 15;
+
+/// The replay server started by spawn_replay_server.
+pub struct ReplayServer {
+    pub join_handle: JoinHandle<Result<()>>,
+    /// Whether the server confirmed that it's running before the startup timeout.
+    /// If not, it may still be starting (or have failed).
+    pub started_ok: bool,
+    /// Whether that confirmation took more than 15 seconds.
+    pub started_late: bool,
+}
+
+/// Where the replay server listens and what it serves, from the environment.
+struct ReplayConfig {
+    addr: String,
+    port: u16,
+    static_dir: PathBuf,
+    startup_timeout: Duration,
+}
+
+impl ReplayConfig {
+    fn from_env() -> Self {
+        let startup_timeout_secs = std::env::var("REPLAY_STARTUP_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_STARTUP_TIMEOUT_SECS);
+        Self {
+            addr: std::env::var("REPLAY_ADDR").unwrap_or_else(|_| "0.0.0.0".into()),
+            port: std::env::var("REPLAY_PORT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(8080),
+            static_dir: PathBuf::from(
+                std::env::var("STATIC_DIR").unwrap_or_else(|_| "static".into()),
+            ),
+            startup_timeout: Duration::from_secs(startup_timeout_secs),
+        }
+    }
+}
 
 /** Public API functions below **/
 /// Spawn the Rocket server on a background thread.
-pub fn spawn_replay_server(runs_root: impl Into<PathBuf>) -> (JoinHandle<Result<()>>, bool) {
-    let runs_root: PathBuf = runs_root.into();
+pub fn spawn_replay_server(runs_root: impl Into<PathBuf>) -> ReplayServer {
+    spawn_replay_server_with(runs_root.into(), ReplayConfig::from_env(), |rocket| rocket)
+}
+
+// customize is applied to the Rocket right before its launch (e.g., to attach fairings).
+fn spawn_replay_server_with(
+    runs_root: PathBuf,
+    config: ReplayConfig,
+    customize: impl FnOnce(Rocket<Build>) -> Rocket<Build> + Send + 'static,
+) -> ReplayServer {
+    let ReplayConfig {
+        addr,
+        port,
+        static_dir,
+        startup_timeout,
+    } = config;
 
     // Used to notify the caller whether the server started successfully.
     let (ready_tx, ready_rx) = mpsc::channel::<std::result::Result<(), String>>();
@@ -313,16 +417,6 @@ pub fn spawn_replay_server(runs_root: impl Into<PathBuf>) -> (JoinHandle<Result<
                 .context("building tokio runtime for replay server")?;
 
             rt.block_on(async move {
-                // ---- Config (address/port via figment custom) ----
-                let addr = std::env::var("REPLAY_ADDR").unwrap_or_else(|_| "0.0.0.0".into());
-                let port: u16 = std::env::var("REPLAY_PORT")
-                    .ok()
-                    .and_then(|s| s.parse().ok())
-                    .unwrap_or(8080);
-
-                let static_dir =
-                    PathBuf::from(std::env::var("STATIC_DIR").unwrap_or_else(|_| "static".into()));
-
                 // Ensure required static assets exist before continuing.
                 for f in ["index.html", "styles.css", "ui.js"] {
                     let p = static_dir.join(f);
@@ -371,6 +465,7 @@ pub fn spawn_replay_server(runs_root: impl Into<PathBuf>) -> (JoinHandle<Result<
                             get_sessions,
                             get_session_one,
                             get_session_series,
+                            get_session_live,
                             reload_sessions
                         ],
                     )
@@ -387,7 +482,7 @@ pub fn spawn_replay_server(runs_root: impl Into<PathBuf>) -> (JoinHandle<Result<
                     }));
 
                 // On failure (e.g., port in use), notify the caller.
-                if let Err(e) = rocket.launch().await {
+                if let Err(e) = customize(rocket).launch().await {
                     let _ = ready_tx.send(Err(format!("Rocket launch error: {e}")));
                     return Err(anyhow::anyhow!(e));
                 }
@@ -398,19 +493,35 @@ pub fn spawn_replay_server(runs_root: impl Into<PathBuf>) -> (JoinHandle<Result<
         .expect("failed to spawn replay-rocket thread");
 
     // Wait for confirmation that the server is running or failed.
-    let started_ok = match ready_rx.recv_timeout(Duration::from_secs(15)) {
+    let started_at = Instant::now();
+    let timeout = startup_timeout;
+    let started_ok = match ready_rx.recv_timeout(timeout) {
         Ok(Ok(())) => true,
         Ok(Err(msg)) => {
             eprintln!("replay server failed: {msg}");
             false
         }
         Err(_timeout) => {
-            eprintln!("replay server startup not confirmed (timeout)");
+            eprintln!(
+                "replay server startup not confirmed after {}s (timeout, it may still be starting)",
+                timeout.as_secs()
+            );
             false
         }
     };
+    let started_late = started_ok && started_at.elapsed() > Duration::from_secs(LATE_STARTUP_SECS);
+    if started_late {
+        eprintln!(
+            "replay server took {:.1}s to start",
+            started_at.elapsed().as_secs_f32()
+        );
+    }
 
-    (handle, started_ok)
+    ReplayServer {
+        join_handle: handle,
+        started_ok,
+        started_late,
+    }
 }
 
 /**  Routes start here **/
@@ -473,6 +584,7 @@ async fn get_sessions(state: &State<AppState>, q: Option<SessionQuery>) -> Json<
 }
 
 /// GET /sessions/<id> to single session (frames+events)
+/// Events can be limited to a time range (ts_from/ts_to) and paged backward with events_offset.
 #[get("/sessions/<id>?<q..>")]
 async fn get_session_one(
     id: String,
@@ -488,8 +600,14 @@ async fn get_session_one(
         .events_tail
         .unwrap_or(DEFAULT_EVENTS_TAIL)
         .clamp(0, MAX_EVENTS_TAIL);
+    let events = EventWindow {
+        ts_from: q.ts_from.map(u128::from),
+        ts_to: q.ts_to.map(u128::from),
+        offset: q.events_offset.unwrap_or(0),
+        tail: Some(events_tail),
+    };
 
-    load_session_detail(&state.runs_root, &id, frames_tail, events_tail)
+    load_session_detail(&state.runs_root, &id, frames_tail, &events)
         .ok()
         .map(Json)
 }
@@ -516,6 +634,54 @@ async fn get_session_series(
     Json(series)
 }
 
+/// GET /sessions/<id>/live to stream new events (SSE) as telemetry.log is written.
+/// Only events written after the request are sent, as "telemetry" events. If the log is truncated
+/// or replaced, a "reset" event is sent and the stream continues from the start of the new log.
+#[get("/sessions/<id>/live")]
+async fn get_session_live(
+    id: String,
+    state: &State<AppState>,
+    mut shutdown: Shutdown,
+) -> Option<EventStream![]> {
+    let run_dir = state.runs_root.join(&id);
+    if !is_plain_name(&id) || !run_dir.is_dir() {
+        return None;
+    }
+
+    // The existing rows are skipped (the UI gets them from /sessions/<id>),
+    // but they still set the frame anchor.
+    let mut tail = TelemetryTail::new(run_dir.join("telemetry.log"));
+    let mut parser = TelemetryEventParser::new(&session_frames(&run_dir).1);
+    for line in tail.read_new_lines().lines {
+        parser.parse_line(&line);
+    }
+
+    Some(EventStream! {
+        let mut interval = time::interval(LIVE_POLL_INTERVAL);
+        loop {
+            select! {
+                _ = interval.tick() => {},
+                _ = &mut shutdown => break,
+            };
+
+            let update = tail.read_new_lines();
+            if update.restarted {
+                parser = TelemetryEventParser::default();
+                yield Event::data("").event("reset");
+            }
+            // New rows usually come with new frames.
+            if !update.lines.is_empty() {
+                parser.set_frames(&session_frames(&run_dir).1);
+            }
+            for line in update.lines {
+                for ev in parser.parse_line(&line) {
+                    yield Event::json(&ev).event("telemetry");
+                }
+            }
+        }
+    })
+}
+
 /// POST /reload to rescan RUNS_ROOT
 #[post("/reload")]
 async fn reload_sessions(
@@ -538,6 +704,15 @@ fn must_exist(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Whether s is a single file name (no separators, no "..").
+fn is_plain_name(s: &str) -> bool {
+    let mut components = Path::new(s).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
 /// Discover sessions as folders under root. A session is valid if it has frames
 /// under <run>/frames (or fallback <run>/images).
 fn load_session_ids(root: &Path) -> Result<Vec<String>> {
@@ -569,32 +744,28 @@ fn load_session_detail(
     root: &Path,
     run_id: &str,
     frames_tail: usize,
-    events_tail: usize,
+    events: &EventWindow,
 ) -> Result<SessionDetail> {
     let run_dir = root.join(run_id);
     if !run_dir.exists() {
         bail!("session not found: {}", run_id);
     }
 
-    let frames_dir = run_dir.join("frames");
-    let images_dir = run_dir.join("images");
     let telemetry_path = run_dir.join("telemetry.log");
 
-    let (mut frames, mut frame_total) =
-        collect_frames(run_id, &frames_dir, "frames", Some(frames_tail));
-    if frames.is_empty() {
-        let (alt_frames, alt_total) =
-            collect_frames(run_id, &images_dir, "images", Some(frames_tail));
-        frames = alt_frames;
-        frame_total = alt_total;
-    }
-
-    if frames.is_empty() {
+    let (web_subdir, all_frames) = session_frames(&run_dir);
+    if all_frames.is_empty() {
         bail!("no frames found for session: {}", run_id);
     }
 
+    let frame_total = all_frames.len();
+    let frames = all_frames[frame_total.saturating_sub(frames_tail)..]
+        .iter()
+        .map(|file| format!("/runs/{}/{}/{}", run_id, web_subdir, file))
+        .collect();
+
     let (events, event_total) = if telemetry_path.exists() {
-        build_events_from_telemetry(&telemetry_path, Some(events_tail))
+        build_events_from_telemetry(&telemetry_path, events, &all_frames)
     } else {
         (vec![], 0)
     };
@@ -621,15 +792,19 @@ fn file_time(p: &Path) -> SystemTime {
     }
 }
 
-// Collect image files and their timestamps from the given directory.
-fn collect_frames(
-    run_id: &str,
-    dir: &Path,
-    web_subdir: &str,
-    tail: Option<usize>,
-) -> (Vec<String>, usize) {
+/// The frames of a session (under frames/, or images/ for older sessions) and their subdirectory.
+fn session_frames(run_dir: &Path) -> (&'static str, Vec<String>) {
+    let frames = collect_frames(&run_dir.join("frames"));
+    if !frames.is_empty() {
+        return ("frames", frames);
+    }
+    ("images", collect_frames(&run_dir.join("images")))
+}
+
+// Collect the names of the image files in the given directory, in frame order.
+fn collect_frames(dir: &Path) -> Vec<String> {
     if !dir.exists() {
-        return (vec![], 0);
+        return vec![];
     }
 
     // Gather (path, timestamp)
@@ -652,21 +827,26 @@ fn collect_frames(
         })
         .collect();
 
-    // Sort files in chronological order by timestamp, then name.
-    files.sort_by(|(pa, ta), (pb, tb)| ta.cmp(tb).then_with(|| file_name_cmp(pa, pb)));
-
-    let total = files.len();
-    let tail = tail.unwrap_or(total).min(total);
-    let start = total.saturating_sub(tail);
+    // Sort files in frame order. Timestamps are unreliable (e.g., on ext4, there's no creation time
+    // and replays rewrite the frames), so they only order the frames that aren't numbered.
+    files.sort_by(|(pa, ta), (pb, tb)| match (frame_number(pa), frame_number(pb)) {
+        (Some(na), Some(nb)) => na.cmp(&nb).then_with(|| file_name_cmp(pa, pb)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => ta.cmp(tb).then_with(|| file_name_cmp(pa, pb)),
+    });
 
-    let frames = files
+    files
         .into_iter()
-        .skip(start)
         .filter_map(|(p, _ts)| p.file_name().map(|os| os.to_string_lossy().to_string()))
-        .map(|file| format!("/runs/{}/{}/{}", run_id, web_subdir, file))
-        .collect();
+        .collect()
+}
 
-    (frames, total)
+/// Trailing number of the file stem, e.g., 1 for frame_00001.png.
+fn frame_number(p: &Path) -> Option<u64> {
+    let stem = p.file_stem()?.to_str()?;
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[stem.len() - digits..].parse().ok()
 }
 
 fn file_name_cmp(a: &Path, b: &Path) -> Ordering {
@@ -724,6 +904,9 @@ fn build_series_from_telemetry(path: &Path, tail: Option<usize>) -> Result<Serie
                         cpu,
                         ram,
                         temp,
+                        temp_high: as_f32_opt(&v, "temp_high_c"),
+                        temp_critical: as_f32_opt(&v, "temp_critical_c"),
+                        cpu_cap: as_f32_opt(&v, "cpu_cap_pct"),
                         run: run_key_from_json(&v),
                     });
                 }
@@ -764,32 +947,123 @@ fn build_series_from_telemetry(path: &Path, tail: Option<usize>) -> Result<Serie
     Ok(SeriesData { health, ticks })
 }
 
-/// Build per-frame events from telemetry.log.
-/// Heuristic: remember the last replay_frame_idx from "stage" rows and attach subsequent events to that frame.
-fn build_events_from_telemetry(path: &Path, tail: Option<usize>) -> (Vec<FrontEvent>, usize) {
-    use std::collections::HashMap;
+/// Build per-frame events from telemetry.log, given the session's frames (see collect_frames).
+/// Events are attached to the first frame of their run, or of the last run before them with frames.
+/// Returns the events of the window and the total number of events in its time range.
+fn build_events_from_telemetry(
+    path: &Path,
+    window: &EventWindow,
+    frames: &[String],
+) -> (Vec<FrontEvent>, usize) {
+    let max = window.tail.unwrap_or(usize::MAX);
+
+    if window.offset == 0 {
+        let mut events: VecDeque<FrontEvent> = VecDeque::new();
+        let total_events = for_each_telemetry_event(path, window, frames, |ev| {
+            if max == 0 {
+                return;
+            }
+            if events.len() == max {
+                events.pop_front();
+            }
+            events.push_back(ev);
+        });
+        return (events.into_iter().collect(), total_events);
+    }
+
+    // Paging backward: the events have to be counted first to know where the page starts.
+    let total_events = for_each_telemetry_event(path, window, frames, |_| {});
+    let end = total_events.saturating_sub(window.offset);
+    let start = end.saturating_sub(max);
+
+    let mut events = vec![];
+    let mut index = 0usize;
+    for_each_telemetry_event(path, window, frames, |ev| {
+        if (start..end).contains(&index) {
+            events.push(ev);
+        }
+        index += 1;
+    });
+    (events, total_events)
+}
 
+/// Calls f with every event of telemetry.log in the time range of the window, in order.
+/// Returns the number of these events.
+fn for_each_telemetry_event(
+    path: &Path,
+    window: &EventWindow,
+    frames: &[String],
+    mut f: impl FnMut(FrontEvent),
+) -> usize {
     let file = match fs::File::open(path) {
         Ok(f) => f,
         Err(e) => {
             eprintln!("error; cannot open telemetry.log {}: {e}", path.display());
-            return (vec![], 0);
+            return 0;
         }
     };
     let reader = BufReader::new(file);
 
-    let mut events: VecDeque<FrontEvent> = VecDeque::new();
-    let max = tail.unwrap_or(usize::MAX);
     let mut total_events = 0usize;
-    let mut skipped_no_run = 0usize;
-
-    // Anchor frame index per run (not used — always defaults to 0).
-    let last_f_by_run: HashMap<String, usize> = HashMap::new();
-    let default_f = 0usize;
+    let mut parser = TelemetryEventParser::new(frames);
 
     for line in reader.lines().map_while(Result::ok) {
-        let Ok(v) = serde_json::from_str::<serde_json::Value>(&line) else {
-            continue;
+        // Every row is parsed, even out of the time range, for the frame anchor.
+        for ev in parser.parse_line(&line) {
+            if window.in_range(&ev) {
+                total_events += 1;
+                f(ev);
+            }
+        }
+    }
+
+    // Notify if we dropped events due to missing run_id
+    if parser.skipped_no_run > 0 {
+        eprintln!(
+            "for_each_telemetry_event: skipped {} rows with no usable run_id",
+            parser.skipped_no_run
+        );
+    }
+
+    total_events
+}
+
+/// Turns telemetry.log rows into UI events, one row at a time, so that the same parsing works for
+/// a whole log and for the rows appended to a live one.
+#[derive(Default)]
+struct TelemetryEventParser {
+    // Index of the first frame of each run. The pipeline names the frames it saves after their
+    // run (<run_id>_<name>.png).
+    first_frame_by_run: HashMap<String, usize>,
+    // Frame of the last run with frames. Rows of other runs (e.g., health) are attached to it.
+    last_f: usize,
+    skipped_no_run: usize,
+}
+
+impl TelemetryEventParser {
+    fn new(frames: &[String]) -> Self {
+        let mut parser = Self::default();
+        parser.set_frames(frames);
+        parser
+    }
+
+    /// Replaces the session's frames (see collect_frames), e.g., when new ones were saved.
+    fn set_frames(&mut self, frames: &[String]) {
+        self.first_frame_by_run.clear();
+        for (index, file) in frames.iter().enumerate() {
+            if let Some((run, _)) = file.split_once('_') {
+                self.first_frame_by_run
+                    .entry(canon_uuid_like(run))
+                    .or_insert(index);
+            }
+        }
+    }
+
+    /// The events of one row (most rows have none).
+    fn parse_line(&mut self, line: &str) -> Vec<FrontEvent> {
+        let mut events = vec![];
+        let Ok(v) = serde_json::from_str::<serde_json::Value>(line) else {
+            return events;
         };
         let kind = v.get("kind").and_then(|k| k.as_str()).unwrap_or("");
 
@@ -797,8 +1071,8 @@ fn build_events_from_telemetry(path: &Path, tail: Option<usize>) -> (Vec<FrontEv
         let run_key = match run_key_from_json(&v) {
             Some(k) if !k.is_empty() => k,
             _ => {
-                skipped_no_run += 1;
-                continue;
+                self.skipped_no_run += 1;
+                return events;
             }
         };
 
@@ -814,19 +1088,15 @@ fn build_events_from_telemetry(path: &Path, tail: Option<usize>) -> (Vec<FrontEv
                     .map(|s| s.to_string())
             });
 
-        // Anchor frame index — required for frame mapping but defaulted to 0.
-        let f_for_ev = *last_f_by_run.get(&run_key).unwrap_or(&default_f);
+        // Anchor frame index (0 until a run with frames).
+        if let Some(&f) = self.first_frame_by_run.get(&run_key) {
+            self.last_f = f;
+        }
+        let f_for_ev = self.last_f;
 
         let mut push_ev = |txt: String, stage_override: Option<String>| {
-            total_events += 1;
-            if max == 0 {
-                return;
-            }
-            if events.len() == max {
-                events.pop_front();
-            }
             let stage = stage_override.or_else(|| stage_label.clone());
-            events.push_back(FrontEvent {
+            events.push(FrontEvent {
                 f: f_for_ev,
                 txt,
                 run: Some(run_key.clone()),
@@ -906,7 +1176,11 @@ fn build_events_from_telemetry(path: &Path, tail: Option<usize>) -> (Vec<FrontEv
                     v.get("threshold").and_then(|x| x.as_u64()),
                     v.get("w_b").and_then(|x| x.as_f64()),
                 ) {
-                    push_ev(format!("Motion pts {}/{} thr {} w_b {}", cp, tp, th, w_b), None);
+                    let mut s = format!("Motion pts {}/{} thr {} w_b {}", cp, tp, th, w_b);
+                    if let Some(region) = v.get("region").filter(|r| !r.is_null()) {
+                        s.push_str(&format!(" region {region}"));
+                    }
+                    push_ev(s, None);
                 }
             }
             "detections_summary" => {
@@ -947,24 +1221,96 @@ fn build_events_from_telemetry(path: &Path, tail: Option<usize>) -> (Vec<FrontEv
                 }
             }
             "intent_triggered" | "intent" => {
-                if is_noop_intent(&v) {
-                    continue;
+                if !is_noop_intent(&v) {
+                    let intent_str = v.get("intent").unwrap();
+                    push_ev(format!("Intent: {}", intent_str), None);
                 }
-                let intent_str = v.get("intent").unwrap();
-                push_ev(format!("Intent: {}", intent_str), None);
             }
             _ => { /* ignore */ }
         }
+
+        events
     }
+}
 
-    // Notify if we dropped events due to missing run_id
-    if skipped_no_run > 0 {
-        eprintln!(
-            "build_events_from_telemetry: skipped {skipped_no_run} rows with no usable run_id"
-        );
+/// Follows telemetry.log as it's written. If the log is truncated or replaced (e.g., rotated),
+/// it starts over from the beginning of the new log.
+struct TelemetryTail {
+    path: PathBuf,
+    offset: u64,
+    file_id: Option<u64>,
+    // Start of a row that hasn't been fully written yet.
+    partial: Vec<u8>,
+}
+
+#[derive(Default)]
+struct TailUpdate {
+    /// The log started over since the last read.
+    restarted: bool,
+    /// New complete rows.
+    lines: Vec<String>,
+}
+
+impl TelemetryTail {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            offset: 0,
+            file_id: None,
+            partial: vec![],
+        }
+    }
+
+    fn read_new_lines(&mut self) -> TailUpdate {
+        let mut update = TailUpdate::default();
+
+        // A missing log may not have been created yet, or is being rotated.
+        let Ok(mut file) = fs::File::open(&self.path) else {
+            return update;
+        };
+        let Ok(md) = file.metadata() else {
+            return update;
+        };
+
+        let id = file_id(&md);
+        if md.len() < self.offset || (self.file_id.is_some() && id != self.file_id) {
+            self.offset = 0;
+            self.partial.clear();
+            update.restarted = true;
+        }
+        self.file_id = id;
+        if md.len() == self.offset {
+            return update;
+        }
+
+        let mut buf = vec![];
+        if file.seek(SeekFrom::Start(self.offset)).is_err() || file.read_to_end(&mut buf).is_err() {
+            return update;
+        }
+        self.offset += buf.len() as u64;
+        self.partial.extend_from_slice(&buf);
+
+        if let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') {
+            let rest = self.partial.split_off(end + 1);
+            let complete = std::mem::replace(&mut self.partial, rest);
+            update.lines = String::from_utf8_lossy(&complete)
+                .lines()
+                .map(str::to_string)
+                .collect();
+        }
+        update
     }
+}
+
+#[cfg(unix)]
+fn file_id(md: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(md.ino())
+}
 
-    (events.into_iter().collect(), total_events)
+#[cfg(not(unix))]
+fn file_id(_md: &fs::Metadata) -> Option<u64> {
+    None
 }
 
 /** JSON helpers below **/
@@ -1039,3 +1385,173 @@ fn as_bool_any(v: &Value, keys: &[&str]) -> Option<bool> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const RUN_A: &str = "0b7c3a52-9d0e-4c9f-8f5e-6a1d2b3c4d5e";
+    const RUN_B: &str = "1f2e3d4c-5b6a-4978-8695-a4b3c2d1e0f9";
+    // A rejected run, whose frames were purged.
+    const RUN_C: &str = "2a3b4c5d-6e7f-4a8b-9c0d-e1f2a3b4c5d6";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("secluso-motion-ai-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frames() -> Vec<String> {
+        [
+            format!("{RUN_A}_acceptance.png"),
+            format!("{RUN_A}_det_box.png"),
+            format!("{RUN_B}_acceptance.png"),
+            format!("{RUN_B}_det_box.png"),
+        ]
+        .into()
+    }
+
+    fn detection(run_id: &str, ts: u64) -> Value {
+        json!({
+            "kind": "detection",
+            "run_id": run_id,
+            "frame_rel": format!("output/runs/session/frames/{run_id}_det_box.png"),
+            "detections": 1,
+            "latency_ms": 40,
+            "ts": ts,
+        })
+    }
+
+    fn dropped(run_id: &str, reason: &str, ts: u64) -> Value {
+        json!({"kind": "dropped_frame", "run_id": run_id, "reason": reason, "ts": ts})
+    }
+
+    fn stage(run_id: &str, ts: u64) -> Value {
+        json!({"kind": "stage", "run_id": run_id, "stage": "motion", "calls": 1, "ts": ts})
+    }
+
+    fn anchors(events: &[FrontEvent]) -> Vec<(usize, &str)> {
+        events.iter().map(|ev| (ev.f, ev.txt.as_str())).collect()
+    }
+
+    #[test]
+    fn events_are_attached_to_the_first_frame_of_their_run() {
+        let dir = test_dir("events");
+        let log = dir.join("telemetry.log");
+        let rows = [
+            json!({"kind": "fsm_transition", "run_id": "session", "from": "Idle", "to": "Primed", "ts": 1}),
+            stage(RUN_A, 2),
+            stage(RUN_B, 3),
+            detection(RUN_A, 4),
+            dropped(RUN_B, "no_motion", 5),
+            stage(RUN_A, 6),
+            detection(RUN_B, 7),
+            dropped(RUN_C, "no_allowed_detection", 8),
+            detection(RUN_A, 9),
+        ];
+        let lines: Vec<String> = rows.iter().map(Value::to_string).collect();
+        fs::write(&log, lines.join("\n")).unwrap();
+
+        let (events, total) = build_events_from_telemetry(&log, &EventWindow::default(), &frames());
+        assert_eq!(total, 6);
+        assert_eq!(
+            anchors(&events),
+            [
+                (0, "Idle ➜ Primed"),
+                (0, "InferenceCompleted: 1 detections (40 ms)"),
+                (2, "Dropped: no_motion"),
+                (2, "InferenceCompleted: 1 detections (40 ms)"),
+                (2, "Dropped: no_allowed_detection"),
+                (0, "InferenceCompleted: 1 detections (40 ms)"),
+            ]
+        );
+
+        // Paging backward keeps the anchors of the rows before the page.
+        let window = EventWindow {
+            offset: 1,
+            tail: Some(2),
+            ..Default::default()
+        };
+        let (events, _) = build_events_from_telemetry(&log, &window, &frames());
+        assert_eq!(
+            anchors(&events),
+            [
+                (2, "InferenceCompleted: 1 detections (40 ms)"),
+                (2, "Dropped: no_allowed_detection"),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn frames_are_sorted_by_number_not_by_time() {
+        let dir = test_dir("frames");
+        // Saved newest first, with reverse modification times, and not zero-padded,
+        // so neither the timestamps nor the names are in frame order.
+        for i in (0..20u64).rev() {
+            let file = fs::File::create(dir.join(format!("frame_{i}.png"))).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1000 - i))
+                .unwrap();
+        }
+        fs::write(dir.join("background.png"), b"").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let mut expected: Vec<String> = (0..20).map(|i| format!("frame_{i}.png")).collect();
+        // Unnumbered frames come last.
+        expected.push("background.png".into());
+        assert_eq!(collect_frames(&dir), expected);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn late_liftoff_is_reported_as_started() {
+        let dir = test_dir("replay");
+        let static_dir = dir.join("static");
+        fs::create_dir_all(&static_dir).unwrap();
+        for file in ["index.html", "styles.css", "ui.js"] {
+            fs::write(static_dir.join(file), b"").unwrap();
+        }
+        let config = ReplayConfig {
+            addr: "127.0.0.1".into(),
+            port: 0,
+            static_dir,
+            startup_timeout: Duration::from_secs(DEFAULT_STARTUP_TIMEOUT_SECS),
+        };
+
+        // Ignite fairings run one after the other before liftoff, so this defers it by 20 seconds.
+        let server = spawn_replay_server_with(dir.join("runs"), config, |rocket| {
+            rocket.attach(AdHoc::on_ignite("slow-start", |rocket| async move {
+                time::sleep(Duration::from_secs(20)).await;
+                rocket
+            }))
+        });
+
+        assert!(server.started_ok);
+        assert!(server.started_late);
+        assert!(!server.join_handle.is_finished());
+    }
+
+    #[test]
+    fn live_events_use_frames_saved_after_the_stream_started() {
+        let mut parser = TelemetryEventParser::new(&frames()[..2]);
+        let parse = |parser: &mut TelemetryEventParser, row: Value| {
+            parser
+                .parse_line(&row.to_string())
+                .into_iter()
+                .map(|ev| ev.f)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(parse(&mut parser, detection(RUN_A, 1)), [0]);
+        // RUN_B's frames aren't known yet.
+        assert_eq!(parse(&mut parser, dropped(RUN_B, "no_motion", 2)), [0]);
+
+        parser.set_frames(&frames());
+        assert_eq!(parse(&mut parser, detection(RUN_B, 3)), [2]);
+    }
+}
@@ -3,8 +3,8 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::logic::activity_states::ActivityState;
-use crate::logic::health_states::HealthState;
-use crate::logic::pipeline::{PipelineResult, RunId};
+use crate::logic::health_states::{HealthState, HealthThresholds};
+use crate::logic::pipeline::{MotionAiConfig, PipelineResult, RunId};
 use crate::ml::models::ModelKind;
 use crate::motion::detector::MotionDetection;
 use std::collections::HashMap;
@@ -15,6 +15,8 @@ pub struct StateContext {
     pub(crate) activity: ActivityState,
     /// Coarse health classification (Normal / High Temp / etc)
     pub(crate) health: HealthState,
+    /// Readings at which the health changes.
+    pub(crate) health_thresholds: HealthThresholds,
     /// Currently selected ML model for inference
     pub(crate) active_model: ModelKind,
     /// Motion detection accumulator and thresholds.
@@ -27,6 +29,8 @@ pub struct StateContext {
     // backoff_until: Option<Instant>,
     /// Whether to execute model inference for this run/frame.
     pub use_inference: bool,
+    /// Which detections count as a motion event.
+    pub(crate) motion_ai_config: MotionAiConfig,
     // pub metadata: HashMap<String, String>,
     /// Per stage counters and last-latency samples.
     pub stats: HashMap<String, StageStats>,
@@ -39,6 +43,7 @@ impl StateContext {
         Self {
             activity: ActivityState::Idle,
             health: HealthState::Normal,
+            health_thresholds: HealthThresholds::default(),
             active_model: ModelKind::Accurate,
             motion_detection: MotionDetection::new(),
             run_id: RunId::new(), // Will be replaced with the first frame, so it can be this instead of an Option for ease-of-use
@@ -47,6 +52,7 @@ impl StateContext {
             // temp_history: Default::default(),
             // backoff_until: None,
             use_inference: true,
+            motion_ai_config: MotionAiConfig::default(),
             // metadata: Default::default(),
             stats: Default::default(),
             last_detection: None,
@@ -9,6 +9,7 @@ use crate::logic::intent::Intent;
 use crate::logic::pipeline::PipelineEvent::TemperatureDrop;
 use crate::logic::pipeline::{Pipeline, PipelineEvent};
 use crate::ml::models::ModelKind;
+use std::cmp::Ordering;
 use std::fmt;
 
 use crate::logic::telemetry::{TelemetryPacket, TelemetryRun};
@@ -54,6 +55,85 @@ impl HealthState {
     }
 }
 
+/// Environment variables that override the default HealthThresholds (e.g., a Pi Zero 2W can be
+/// throttled earlier than a Pi 4).
+pub const TEMP_HIGH_ENV: &str = "SECLUSO_HEALTH_TEMP_HIGH_C";
+pub const TEMP_CRITICAL_ENV: &str = "SECLUSO_HEALTH_TEMP_CRITICAL_C";
+pub const CPU_CAP_ENV: &str = "SECLUSO_HEALTH_CPU_PCT";
+
+/// Readings at which the health FSM degrades the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HealthThresholds {
+    /// At or above this temperature (°C), the pipeline switches to the fast model.
+    pub temp_high_c: f32,
+    /// At or above this temperature (°C), inference stops.
+    pub temp_critical_c: f32,
+    /// At or above this CPU usage (percent), the pipeline switches to the fast model.
+    pub cpu_pct: f32,
+}
+
+/// The thresholds in config.rs, used before they were configurable.
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            temp_high_c: THRESH_TEMP_HIGH,
+            temp_critical_c: THRESH_TEMP_CRITICAL,
+            cpu_pct: CPU_RESOURCE_CAP,
+        }
+    }
+}
+
+impl HealthThresholds {
+    /// The defaults, with the ones set in the environment (TEMP_HIGH_ENV, ...) replaced.
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        fn read(name: &str, default: f32) -> Result<f32, anyhow::Error> {
+            match std::env::var(name) {
+                Ok(value) => value
+                    .trim()
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid {name} ({value}): {e}")),
+                Err(_) => Ok(default),
+            }
+        }
+
+        let defaults = Self::default();
+        let thresholds = Self {
+            temp_high_c: read(TEMP_HIGH_ENV, defaults.temp_high_c)?,
+            temp_critical_c: read(TEMP_CRITICAL_ENV, defaults.temp_critical_c)?,
+            cpu_pct: read(CPU_CAP_ENV, defaults.cpu_pct)?,
+        };
+        thresholds.validate()?;
+        Ok(thresholds)
+    }
+
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.temp_high_c.partial_cmp(&self.temp_critical_c) != Some(Ordering::Less) {
+            anyhow::bail!(
+                "The high temperature threshold ({}) must be below the critical one ({})",
+                self.temp_high_c,
+                self.temp_critical_c
+            );
+        }
+        if !(f32::MIN_POSITIVE..=100.0).contains(&self.cpu_pct) {
+            anyhow::bail!("The CPU threshold ({}) must be a percentage", self.cpu_pct);
+        }
+        Ok(())
+    }
+
+    /// The health state for the readings (temperature in °C, CPU and memory in percent).
+    pub fn classify(&self, temp: f32, cpu: f32, mem: f32) -> HealthState {
+        if temp >= self.temp_critical_c {
+            HealthState::CriticalTemp
+        } else if temp >= self.temp_high_c {
+            HealthState::HighTemp
+        } else if cpu >= self.cpu_pct || mem >= MEMORY_RESOURCE_CAP {
+            HealthState::ResourceLow
+        } else {
+            HealthState::Normal
+        }
+    }
+}
+
 /// Shared System instance for efficient hardware usage tracking.
 static SYS: Lazy<Mutex<System>> = Lazy::new(|| {
     let kind = RefreshKind::nothing()
@@ -73,27 +153,23 @@ pub fn update(
 
     let cpu = cpu_and_mem.cpu_pct;
     let mem = ((cpu_and_mem.used_kib as f32) / (cpu_and_mem.total_kib as f32)) * 100.0;
+    let thresholds = ctx.health_thresholds;
 
     telemetry.write(&TelemetryPacket::Health {
         run_id: ctx.run_id.clone(),
         cpu_pct: cpu,
         ram_pct: mem,
         temp_c: temp,
+        temp_high_c: thresholds.temp_high_c,
+        temp_critical_c: thresholds.temp_critical_c,
+        cpu_cap_pct: thresholds.cpu_pct,
         ts: SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Time should go forward.")
             .as_millis(),
     })?;
 
-    let next = if temp >= THRESH_TEMP_CRITICAL {
-        HealthState::CriticalTemp
-    } else if temp >= THRESH_TEMP_HIGH {
-        HealthState::HighTemp
-    } else if cpu >= CPU_RESOURCE_CAP || mem >= MEMORY_RESOURCE_CAP {
-        HealthState::ResourceLow
-    } else {
-        HealthState::Normal
-    };
+    let next = thresholds.classify(temp, cpu, mem);
 
     if next == ctx.health {
         return Ok(None);
@@ -212,18 +288,20 @@ impl StateHandler<HealthState> for CriticalTempState {
     fn on_event(
         &mut self,
         _pipeline: &mut Pipeline,
-        _ctx: &mut StateContext,
+        ctx: &mut StateContext,
         e: &PipelineEvent,
     ) -> TransitionDecision<HealthState> {
         match e {
-            TemperatureDrop(t) if *t < THRESH_TEMP_HIGH => TransitionDecision::Transition {
-                to: HealthState::HighTemp,
-                reason: "cooled below critical".into(),
-                intents: vec![
-                    Intent::AllowInference(false), // still throttled
-                                                   // keep Fast model
-                ],
-            },
+            TemperatureDrop(t) if *t < ctx.health_thresholds.temp_high_c => {
+                TransitionDecision::Transition {
+                    to: HealthState::HighTemp,
+                    reason: "cooled below critical".into(),
+                    intents: vec![
+                        Intent::AllowInference(false), // still throttled
+                                                       // keep Fast model
+                    ],
+                }
+            }
             _ => TransitionDecision::Stay(vec![Intent::NoOp]),
         }
     }
@@ -14,15 +14,36 @@ use crate::logic::intent::{Intent, execute_intent};
 use crate::logic::stages::{PipelineStage, StageResult, StageType};
 use crate::logic::telemetry::{TelemetryPacket, TelemetryRun};
 use crate::logic::timer::{Timer, TimerManager};
-use crate::ml::models::{DetectionType, init_model_paths};
+use crate::ml::models::{BoxInfo, DetectionType, init_model_paths};
+use crate::motion::region::RegionOfInterest;
 use anyhow::{Context, Error};
+use crossbeam_channel::{Receiver, RecvTimeoutError, SendError, Sender, TrySendError, bounded};
 use log::debug;
 use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
 use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
 use std::default::Default;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+pub use crate::logic::health_states::HealthThresholds;
+
+/// Only the most recent frames matter, so the channel between the camera and the pipeline is kept short.
+const FRAME_CHANNEL_CAPACITY: usize = 2;
+
+/// How long run() waits for a frame before ticking anyway (for timers and health checks).
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a detection is reported by motion_recently(). TODO: Adjust 30 accordingly
+const MOTION_WINDOW: Duration = Duration::from_secs(30);
+
+/// COCO 2017 class ID of a person.
+pub const PERSON_LABEL: i32 = 0;
+
+/// The models don't report detections below this confidence anyway.
+const DEFAULT_MIN_CONFIDENCE: f32 = 0.4;
+
 /// The main sequential container for executing image processing stages.
 /// Each stage handles a specific task (e.g., motion, detection, inference).
 pub struct Pipeline {
@@ -186,6 +207,79 @@ pub struct PipelineController {
     last_health_change: Option<(HealthState, Instant)>,
     last_activity_change: Option<(ActivityState, Instant)>,
     max_event_queue_len: usize,
+    frames: Receiver<RawFrame>,
+    // Template for frame_sender(). Dropped by run() so that it returns once all senders are gone.
+    frame_sender: Option<FrameSender>,
+    // Set when the controller is dropped, i.e., when run() returns.
+    stopped: Arc<AtomicBool>,
+    detections: DetectionHandle,
+}
+
+impl Drop for PipelineController {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+}
+
+/// Submits frames to a running PipelineController from another thread.
+/// When the pipeline falls behind, the oldest queued frame is dropped in favor of the new one.
+#[derive(Clone)]
+pub struct FrameSender {
+    tx: Sender<RawFrame>,
+    // Used to drop the oldest frame. Since it keeps the channel connected, the controller
+    // reports that it's gone through stopped instead.
+    rx: Receiver<RawFrame>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl FrameSender {
+    /// Fails, returning the frame, once the PipelineController has stopped.
+    pub fn send(&self, mut frame: RawFrame) -> Result<(), SendError<RawFrame>> {
+        loop {
+            if self.stopped.load(Ordering::Acquire) {
+                return Err(SendError(frame));
+            }
+            match self.tx.try_send(frame) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(f)) => {
+                    let _ = self.rx.try_recv();
+                    frame = f;
+                }
+                Err(TrySendError::Disconnected(f)) => return Err(SendError(f)),
+            }
+        }
+    }
+}
+
+/// Gives other threads access to the latest detection of a running PipelineController.
+/// The lock is only held to store or clone the detection, never while the pipeline works.
+#[derive(Clone, Default)]
+pub struct DetectionHandle {
+    latest: Arc<Mutex<Option<PipelineResult>>>,
+}
+
+impl DetectionHandle {
+    /// Was there a motion event in the last MOTION_WINDOW?
+    pub fn motion_recently(&self) -> Option<PipelineResult> {
+        let latest = self.latest.lock().unwrap();
+        latest.as_ref().and_then(within_motion_window)
+    }
+
+    fn publish(&self, detection: &PipelineResult) {
+        *self.latest.lock().unwrap() = Some(detection.clone());
+    }
+}
+
+fn within_motion_window(detection: &PipelineResult) -> Option<PipelineResult> {
+    let elapsed = detection.time.elapsed();
+    let secs = elapsed.as_secs();
+    if elapsed <= MOTION_WINDOW {
+        debug!("Motion detected {} seconds ago (within 30s window).", secs);
+        Some(detection.clone())
+    } else {
+        debug!("Motion detected {} seconds ago (outside 30s window).", secs);
+        None
+    }
 }
 
 /// Holds the current active and standby frame references used by the pipeline.
@@ -214,6 +308,37 @@ pub struct PipelineResult {
     pub thumbnail: RawFrame,
 }
 
+/// Decides which detections of the InferenceStage count as a motion event (and so trigger a notification).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotionAiConfig {
+    /// Detections with a lower confidence (between 0 and 1) are ignored.
+    pub min_confidence: f32,
+    /// COCO 2017 class IDs that count, e.g., PERSON_LABEL. An empty list allows all of them.
+    pub allowed_labels: Vec<i32>,
+}
+
+/// Only people count, as before the config existed.
+impl Default for MotionAiConfig {
+    fn default() -> Self {
+        Self {
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+            allowed_labels: vec![PERSON_LABEL],
+        }
+    }
+}
+
+impl MotionAiConfig {
+    pub(crate) fn is_confident(&self, detection: &BoxInfo) -> bool {
+        detection.confidence >= self.min_confidence
+    }
+
+    /// Whether the detection counts as a motion event.
+    pub(crate) fn counts(&self, detection: &BoxInfo) -> bool {
+        self.is_confident(detection)
+            && (self.allowed_labels.is_empty() || self.allowed_labels.contains(&detection.label))
+    }
+}
+
 /// Implements pipeline orchestration logic including ticking, pushing frames,
 /// and reacting to state transitions.
 impl PipelineController {
@@ -250,6 +375,9 @@ impl PipelineController {
 
         init_model_paths()?; // We should occasionally query this to hot-reload. But for this purpose, initializing and checking everything is OK is good enough
 
+        let (tx, rx) = bounded(FRAME_CHANNEL_CAPACITY);
+        let stopped = Arc::new(AtomicBool::new(false));
+
         Ok(Self {
             activity_registry,
             health_registry,
@@ -268,25 +396,59 @@ impl PipelineController {
             },
             last_activity_change: None,
             max_event_queue_len: 0,
+            frames: rx.clone(),
+            frame_sender: Some(FrameSender {
+                tx,
+                rx,
+                stopped: Arc::clone(&stopped),
+            }),
+            stopped,
+            detections: DetectionHandle::default(),
         })
     }
 
-    // Was there a positive motion event in the last 30 seconds? TODO: Adjust 30 accordingly
+    /// Replaces the default MotionAiConfig (people only) used by the InferenceStage.
+    pub fn with_motion_ai_config(mut self, config: MotionAiConfig) -> Self {
+        self.host_data.ctx.motion_ai_config = config;
+        self
+    }
+
+    /// Replaces the default temperature and CPU thresholds at which the pipeline switches to the
+    /// fast model (or stops inference).
+    pub fn with_health_thresholds(mut self, thresholds: HealthThresholds) -> Self {
+        self.host_data.ctx.health_thresholds = thresholds;
+        self
+    }
+
+    /// Makes the MotionStage ignore motion outside of region. By default, the whole frame is used.
+    pub fn with_region_of_interest(mut self, region: RegionOfInterest) -> Self {
+        self.host_data
+            .ctx
+            .motion_detection
+            .set_region_of_interest(Some(region));
+        self
+    }
+
+    // Was there a positive motion event in the last 30 seconds?
     pub fn motion_recently(&mut self) -> Result<Option<PipelineResult>, Error> {
-        match &self.host_data.ctx.last_detection {
-            None => Ok(None),
-            Some(last_detection) => {
-                let elapsed = last_detection.time.elapsed();
-                let secs = elapsed.as_secs();
-                if elapsed <= Duration::from_secs(30) {
-                    debug!("Motion detected {} seconds ago (within 30s window).", secs);
-                    Ok(Some(last_detection.clone()))
-                } else {
-                    debug!("Motion detected {} seconds ago (outside 30s window).", secs);
-                    Ok(None)
-                }
-            }
-        }
+        Ok(self
+            .host_data
+            .ctx
+            .last_detection
+            .as_ref()
+            .and_then(within_motion_window))
+    }
+
+    /// Returns a sender for submitting frames to run() from another thread.
+    pub fn frame_sender(&self) -> FrameSender {
+        self.frame_sender
+            .clone()
+            .expect("frame_sender is only taken by run()")
+    }
+
+    /// Returns a handle to the latest detection, which run() keeps up to date.
+    pub fn detection_handle(&self) -> DetectionHandle {
+        self.detections.clone()
     }
 
     /// Loads a new frame into the standby buffer and queues a NewFrame event.
@@ -314,9 +476,52 @@ impl PipelineController {
         format!("{event:?}")
     }
 
+    /// Runs the pipeline on the frames submitted through frame_sender() until all senders are dropped.
+    /// Ticks on every new frame, and at least every TICK_INTERVAL for timers and health checks.
+    /// on_detection is called from the pipeline thread for every new motion detection.
+    /// Fails if a tick fails or isn't accepted. Either way, frame senders fail from then on.
+    pub fn run(
+        mut self,
+        temp_label: &'static str,
+        mut on_detection: impl FnMut(&PipelineResult),
+    ) -> Result<(), anyhow::Error> {
+        self.frame_sender = None;
+        let mut last_detection_time: Option<Instant> = None;
+
+        loop {
+            match self.frames.recv_timeout(TICK_INTERVAL) {
+                Ok(frame) => {
+                    // Skip to the most recent frame if more have queued up.
+                    let frame = self.frames.try_iter().last().unwrap_or(frame);
+                    self.push_frame(frame);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            // when false (health issue), we should exit + we should also have some way for user to safely exit
+            if !self.tick(temp_label)? {
+                anyhow::bail!(
+                    "Tick not accepted (health: {}, activity: {})",
+                    self.host_data.ctx.health.as_str(),
+                    self.host_data.ctx.activity.as_str()
+                );
+            }
+
+            if let Some(detection) = &self.host_data.ctx.last_detection
+                && detection.motion
+                && last_detection_time != Some(detection.time)
+            {
+                last_detection_time = Some(detection.time);
+                self.detections.publish(detection);
+                on_detection(detection);
+            }
+        }
+    }
+
     /// Main loop to process events, update health/activity FSMs,
     /// emit telemetry, and dispatch intents.
-    pub fn tick(&mut self, temp_label: &'static str) -> Result<bool, anyhow::Error> {
+    fn tick(&mut self, temp_label: &'static str) -> Result<bool, anyhow::Error> {
         let time = Instant::now();
 
         // Is there a timer event?
@@ -657,3 +862,185 @@ macro_rules! pipeline {
         builder.build()
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Sustained load, as sent by the Raspberry Pi camera.
+    const LOAD_FPS: u64 = 10;
+    const LOAD_DURATION: Duration = Duration::from_secs(20);
+    // Roughly the fast model's inference time on a Raspberry Pi Zero 2W.
+    const INFERENCE_TIME: Duration = Duration::from_millis(30);
+    const TEMP_LABEL: &str = "cpu_thermal temp1";
+
+    // Stands in for the MotionStage: every frame has motion.
+    struct AlwaysMotion;
+
+    impl PipelineStage for AlwaysMotion {
+        fn name(&self) -> &'static str {
+            "always_motion"
+        }
+
+        fn kind(&self) -> StageType {
+            StageType::Motion
+        }
+
+        fn handle(
+            &self,
+            _frame: &mut RawFrame,
+            _ctx: &mut StateContext,
+            _telemetry: &mut TelemetryRun,
+        ) -> Result<StageResult, anyhow::Error> {
+            Ok(StageResult::Continue)
+        }
+    }
+
+    // Stands in for the InferenceStage: finds a person in every frame after INFERENCE_TIME.
+    struct AlwaysPerson;
+
+    impl PipelineStage for AlwaysPerson {
+        fn name(&self) -> &'static str {
+            "always_person"
+        }
+
+        fn kind(&self) -> StageType {
+            StageType::Inference
+        }
+
+        fn handle(
+            &self,
+            frame: &mut RawFrame,
+            ctx: &mut StateContext,
+            _telemetry: &mut TelemetryRun,
+        ) -> Result<StageResult, anyhow::Error> {
+            thread::sleep(INFERENCE_TIME);
+            ctx.last_detection = Some(PipelineResult {
+                time: Instant::now(),
+                motion: true,
+                detections: vec![DetectionType::Human],
+                thumbnail: frame.clone(),
+            });
+            Ok(StageResult::Continue)
+        }
+    }
+
+    fn controller() -> PipelineController {
+        let pipeline = PipelineBuilder::new()
+            .then(AlwaysMotion)
+            .then(AlwaysPerson)
+            .build();
+        let mut controller = PipelineController::new(pipeline, false, false).unwrap();
+        controller.start_working();
+        controller
+    }
+
+    // The thumbnail is the detected frame, which is timestamped when it's created.
+    fn latency(detection: &PipelineResult) -> Duration {
+        detection.thumbnail.timestamp.elapsed().unwrap_or_default()
+    }
+
+    fn p95(mut samples: Vec<Duration>) -> Duration {
+        assert!(!samples.is_empty(), "no detections");
+        samples.sort();
+        samples[(samples.len() * 95).div_ceil(100) - 1]
+    }
+
+    // Sends a small frame every 1/LOAD_FPS seconds for LOAD_DURATION.
+    fn sustain_load(mut send: impl FnMut(RawFrame)) {
+        let start = Instant::now();
+        let mut next = start;
+        while start.elapsed() < LOAD_DURATION {
+            let (width, height) = (64, 48);
+            let yuv420 = vec![0; width * height * 3 / 2];
+            send(RawFrame::create_from_buffer(yuv420, width, height));
+            next += Duration::from_millis(1000 / LOAD_FPS);
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    // How the camera hub drove the pipeline before run(): frames were pushed into a shared
+    // Mutex, which another thread locked to tick every 100 ms.
+    fn mutex_latencies() -> Vec<Duration> {
+        let controller = Arc::new(Mutex::new(controller()));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let ticker = {
+            let controller = Arc::clone(&controller);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut latencies = Vec::new();
+                let mut last_detection_time = None;
+                while !done.load(Ordering::Relaxed) {
+                    let mut controller = controller.lock().unwrap();
+                    assert!(controller.tick(TEMP_LABEL).unwrap());
+                    if let Some(detection) = &controller.host_data.ctx.last_detection
+                        && last_detection_time != Some(detection.time)
+                    {
+                        last_detection_time = Some(detection.time);
+                        latencies.push(latency(detection));
+                    }
+                    drop(controller);
+                    thread::sleep(Duration::from_millis(100));
+                }
+                latencies
+            })
+        };
+
+        sustain_load(|frame| controller.lock().unwrap().push_frame(frame));
+        done.store(true, Ordering::Relaxed);
+        ticker.join().unwrap()
+    }
+
+    fn channel_latencies() -> Vec<Duration> {
+        let controller = controller();
+        let frame_sender = controller.frame_sender();
+
+        let worker = thread::spawn(move || {
+            let mut latencies = Vec::new();
+            controller
+                .run(TEMP_LABEL, |detection| latencies.push(latency(detection)))
+                .unwrap();
+            latencies
+        });
+
+        sustain_load(|frame| frame_sender.send(frame).unwrap());
+        drop(frame_sender);
+        worker.join().unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs the ONNX runtime and a temperature sensor: run on the camera with --release --ignored"]
+    fn frame_to_detection_latency_p95() {
+        let before = p95(mutex_latencies());
+        let after = p95(channel_latencies());
+        println!(
+            "Frame-to-detection latency at p95 under {LOAD_FPS} fps: {before:?} (Mutex), {after:?} (channel)"
+        );
+        assert!(
+            after <= before,
+            "the channel ({after:?}) is slower than the Mutex ({before:?})"
+        );
+    }
+
+    #[test]
+    fn frame_sender_fails_once_the_controller_stops() {
+        let (tx, rx) = bounded(FRAME_CHANNEL_CAPACITY);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let sender = FrameSender {
+            tx,
+            rx,
+            stopped: Arc::clone(&stopped),
+        };
+        let frame = || RawFrame::create_from_buffer(vec![0; 6], 2, 2);
+
+        // A full channel drops the oldest frame instead of failing.
+        for _ in 0..FRAME_CHANNEL_CAPACITY + 2 {
+            assert!(sender.send(frame()).is_ok());
+        }
+
+        stopped.store(true, Ordering::Release);
+        assert!(sender.send(frame()).is_err());
+    }
+}
@@ -4,11 +4,21 @@ use crate::frame::RawFrame;
 use crate::logic::context::StateContext;
 use crate::logic::pipeline::PipelineResult;
 use crate::logic::telemetry::{TelemetryPacket, TelemetryRun};
-use crate::ml::models::DetectionType;
-use log::debug;
+use crate::ml::models::{BoxInfo, DetectionType, ModelKind, reload_model};
+use crate::ml::nanodet::INPUT_SIZE;
+use image::RgbImage;
+use image::imageops;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
 use std::collections::HashSet;
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 /// Describes the type of stage within the pipeline (e.g., motion, inference).
@@ -16,6 +26,7 @@ use std::time::{Instant, SystemTime, UNIX_EPOCH};
 pub enum StageType {
     Motion,
     Inference,
+    PrivacyMask,
     Custom(String),
 }
 
@@ -25,6 +36,7 @@ impl fmt::Display for StageType {
         match self {
             StageType::Motion => write!(f, "motion"),
             StageType::Inference => write!(f, "inference"),
+            StageType::PrivacyMask => write!(f, "privacy_mask"),
             StageType::Custom(s) => write!(f, "{s}"),
         }
     }
@@ -112,7 +124,54 @@ impl PipelineStage for MotionStage {
 /// Performs object detection using the currently active ML model.
 pub struct InferenceStage;
 
-/// Pipeline stage that runs inference and filters based on required labels (e.g., human detection).
+/// Whether a SIGHUP handler was installed by InferenceStage::with_hot_reload().
+static HOT_RELOAD_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+impl InferenceStage {
+    /// Returns the stage after making SIGHUP reload the models from model_dir, without a restart.
+    /// The models are read from <model_dir>/fast.onnx and <model_dir>/accurate.onnx, whichever exist.
+    /// Frames keep going through the current models while the new ones load.
+    pub fn with_hot_reload(model_dir: PathBuf) -> io::Result<Self> {
+        if HOT_RELOAD_INSTALLED.swap(true, Ordering::SeqCst) {
+            warn!("Model hot reload is already enabled");
+            return Ok(InferenceStage);
+        }
+
+        let mut signals = match Signals::new([SIGHUP]) {
+            Ok(signals) => signals,
+            Err(e) => {
+                HOT_RELOAD_INSTALLED.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                reload_models(&model_dir);
+            }
+        });
+
+        Ok(InferenceStage)
+    }
+}
+
+fn reload_models(model_dir: &Path) {
+    for kind in [ModelKind::Fast, ModelKind::Accurate] {
+        let path = model_dir.join(format!("{kind}.onnx"));
+        if !path.exists() {
+            continue;
+        }
+
+        match reload_model(&kind, &path) {
+            Ok(()) => info!("Reloaded the {kind} model from {}", path.display()),
+            Err(e) => error!(
+                "Failed to reload the {kind} model from {} ({e}). Keeping the current model.",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Pipeline stage that runs inference and filters based on the MotionAiConfig (by default, human detection).
 impl PipelineStage for InferenceStage {
     fn name(&self) -> &'static str {
         "inference"
@@ -207,10 +266,13 @@ impl PipelineStage for InferenceStage {
             return Ok(StageResult::Fault("Failed to write telemetry".into()));
         }
 
-        const REQUIRED_LABEL: DetectionType = DetectionType::Human;
-        if result.results.iter().any(|b| b.det_type == REQUIRED_LABEL) {
+        let config = &ctx.motion_ai_config;
+        if result.results.iter().any(|b| config.counts(b)) {
             let mut detection_results = HashSet::new();
             for box_data in result.results {
+                if !config.is_confident(&box_data) {
+                    continue;
+                }
                 match box_data.det_type {
                     DetectionType::Human | DetectionType::Car | DetectionType::Animal => {
                         detection_results.insert(box_data.det_type);
@@ -235,10 +297,303 @@ impl PipelineStage for InferenceStage {
             telemetry.write(&TelemetryPacket::DroppedFrame {
                 run_id: ctx.run_id.clone(),
                 ts,
-                reason: "no_human",
+                reason: "no_allowed_detection",
             })?;
             telemetry.reject_run(&ctx.run_id);
-            Ok(StageResult::Drop("no human detected".into()))
+            Ok(StageResult::Drop("no allowed detection".into()))
+        }
+    }
+}
+
+/// Blurs the people found by the InferenceStage, so that they can't be recognized in the
+/// detection thumbnail. Goes right after the InferenceStage.
+/// Only the decoded frames (and so the thumbnail) are masked, not the camera's encoded video.
+pub struct PrivacyMaskStage {
+    /// Size (in pixels) of the Gaussian kernel. Larger kernels blur more.
+    pub blur_kernel_size: u32,
+}
+
+impl PrivacyMaskStage {
+    /// Same kernel size to sigma conversion as OpenCV's getGaussianKernel.
+    fn sigma(&self) -> f32 {
+        let half = (self.blur_kernel_size.max(3) - 1) as f32 * 0.5;
+        0.3 * (half - 1.0) + 0.8
+    }
+
+    /// Maps a bounding box from the model's input space to the frame as (x, y, width, height).
+    fn region(bbox: &BoxInfo, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let scale_x = width as f32 / INPUT_SIZE as f32;
+        let scale_y = height as f32 / INPUT_SIZE as f32;
+        let x1 = ((bbox.x1 * scale_x).floor().max(0.0) as u32).min(width);
+        let y1 = ((bbox.y1 * scale_y).floor().max(0.0) as u32).min(height);
+        let x2 = ((bbox.x2 * scale_x).ceil().max(0.0) as u32).min(width);
+        let y2 = ((bbox.y2 * scale_y).ceil().max(0.0) as u32).min(height);
+
+        if x2 > x1 && y2 > y1 {
+            Some((x1, y1, x2 - x1, y2 - y1))
+        } else {
+            None
         }
     }
+
+    /// Blurs the given boxes (in the model's input space) of img.
+    pub(crate) fn mask(&self, img: &mut RgbImage, boxes: &[&BoxInfo]) {
+        let sigma = self.sigma();
+        let (width, height) = img.dimensions();
+
+        for bbox in boxes {
+            if let Some((x, y, w, h)) = Self::region(bbox, width, height) {
+                let region = imageops::crop_imm(img, x, y, w, h).to_image();
+                let blurred = imageproc::filter::gaussian_blur_f32(&region, sigma);
+                imageops::replace(img, &blurred, x as i64, y as i64);
+            }
+        }
+    }
+}
+
+impl PipelineStage for PrivacyMaskStage {
+    fn name(&self) -> &'static str {
+        "privacy_mask"
+    }
+
+    fn kind(&self) -> StageType {
+        StageType::PrivacyMask
+    }
+
+    fn handle(
+        &self,
+        frame: &mut RawFrame,
+        ctx: &mut StateContext,
+        _telemetry: &mut TelemetryRun,
+    ) -> Result<StageResult, anyhow::Error> {
+        let Some(detection_result) = frame.detection_result.as_ref() else {
+            return Ok(StageResult::Continue);
+        };
+        let people: Vec<&BoxInfo> = detection_result
+            .results
+            .iter()
+            .filter(|b| b.det_type == DetectionType::Human)
+            .collect();
+        if people.is_empty() {
+            return Ok(StageResult::Continue);
+        }
+
+        debug!("Privacy mask stage blurring {} people", people.len());
+        if frame.rgb_data.is_none() {
+            frame.yuv_to_rgb();
+        }
+        let rgb = frame
+            .rgb_data
+            .as_ref()
+            .map(|v| v.to_vec())
+            .unwrap_or_default();
+        let Some(mut img) = RgbImage::from_raw(frame.width as u32, frame.height as u32, rgb) else {
+            return Ok(StageResult::Fault(
+                "RGB buffer doesn't match the frame size".into(),
+            ));
+        };
+
+        self.mask(&mut img, &people);
+        frame.rgb_data = Some(Arc::new(img.into_raw()));
+
+        // The InferenceStage took the thumbnail before we masked the frame.
+        if let Some(last_detection) = ctx.last_detection.as_mut()
+            && last_detection.thumbnail.timestamp == frame.timestamp
+        {
+            last_detection.thumbnail = frame.clone();
+        }
+
+        Ok(StageResult::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ml::models::{init_model_paths, reloaded_models};
+    use image::Rgb;
+    use signal_hook::low_level::raise;
+    use std::fs;
+    use std::time::Duration;
+
+    const SKIN: Rgb<u8> = Rgb([224, 172, 105]);
+    const FEATURE: Rgb<u8> = Rgb([30, 20, 20]);
+
+    // A simple face (eyes and mouth on skin) at (x, y) with the given size, on a gray background.
+    // The eyes and the mouth are a tenth of the size high.
+    fn face_image(x: u32, y: u32, size: u32) -> RgbImage {
+        let mut img =
+            RgbImage::from_pixel(INPUT_SIZE as u32, INPUT_SIZE as u32, Rgb([128, 128, 128]));
+        for (px, py, pixel) in img.enumerate_pixels_mut() {
+            if px < x || py < y || px >= x + size || py >= y + size {
+                continue;
+            }
+            let (fx, fy) = ((px - x) * 10 / size, (py - y) * 10 / size);
+            let eye = fy == 3 && (fx == 3 || fx == 6);
+            let mouth = fy == 7 && (3..=6).contains(&fx);
+            *pixel = if eye || mouth { FEATURE } else { SKIN };
+        }
+        img
+    }
+
+    fn person(x1: f32, y1: f32, x2: f32, y2: f32) -> BoxInfo {
+        BoxInfo {
+            x1,
+            y1,
+            x2,
+            y2,
+            score: 0.9,
+            label: 0,
+            det_type: DetectionType::Human,
+            confidence: 0.9,
+        }
+    }
+
+    fn max_diff(a: &Rgb<u8>, b: &Rgb<u8>) -> u8 {
+        a.0.iter()
+            .zip(b.0.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn privacy_mask_blurs_inside_the_box() {
+        let (x, y, size) = (100, 80, 80);
+        let original = face_image(x, y, size);
+        let mut masked = original.clone();
+        let stage = PrivacyMaskStage {
+            blur_kernel_size: 25,
+        };
+        let bbox = person(x as f32, y as f32, (x + size) as f32, (y + size) as f32);
+        stage.mask(&mut masked, &[&bbox]);
+
+        // The eyes and the mouth are no longer recognizable.
+        let features = [
+            (x + size * 35 / 100, y + size * 35 / 100), // left eye
+            (x + size * 65 / 100, y + size * 35 / 100), // right eye
+            (x + size * 45 / 100, y + size * 75 / 100), // mouth
+        ];
+        for (px, py) in features {
+            assert_eq!(*original.get_pixel(px, py), FEATURE);
+            let diff = max_diff(original.get_pixel(px, py), masked.get_pixel(px, py));
+            assert!(diff > 20, "pixel ({px}, {py}) only changed by {diff}");
+        }
+
+        // Nothing changes outside of the box.
+        for (px, py, pixel) in original.enumerate_pixels() {
+            if px < x || py < y || px >= x + size || py >= y + size {
+                assert_eq!(pixel, masked.get_pixel(px, py));
+            }
+        }
+    }
+
+    #[test]
+    fn privacy_mask_scales_the_box_to_the_frame() {
+        // The box is in the model's input space, the frame is twice as large.
+        let frame_size = INPUT_SIZE as u32 * 2;
+        assert_eq!(
+            PrivacyMaskStage::region(&person(10.0, 20.0, 110.0, 220.0), frame_size, frame_size),
+            Some((20, 40, 200, 400))
+        );
+        // Clamped to the frame.
+        assert_eq!(
+            PrivacyMaskStage::region(&person(-10.0, 0.0, 500.0, 100.0), frame_size, frame_size),
+            Some((0, 0, frame_size, 200))
+        );
+        // Empty boxes are skipped.
+        assert_eq!(
+            PrivacyMaskStage::region(&person(50.0, 50.0, 50.0, 90.0), frame_size, frame_size),
+            None
+        );
+    }
+
+    // How long reload_model() may take to load the new model.
+    const RELOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn gray_frame() -> RawFrame {
+        RawFrame {
+            yuv_data: Arc::new(vec![]),
+            rgb_data: Some(Arc::new(vec![128; 640 * 480 * 3])),
+            timestamp: SystemTime::now(),
+            width: 640,
+            height: 480,
+            detection_result: None,
+            dma_aligned: false,
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the ONNX runtime: run with --ignored"]
+    fn sighup_reloads_the_models_mid_stream() {
+        init_model_paths().unwrap();
+
+        // A new fast model, and a corrupt accurate model that has to be rejected.
+        let model_dir =
+            std::env::temp_dir().join(format!("secluso-motion-ai-models-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&model_dir);
+        fs::create_dir_all(&model_dir).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/onnx_models/nanodet-plus-m_416.onnx"
+            ),
+            model_dir.join("fast.onnx"),
+        )
+        .unwrap();
+        fs::write(model_dir.join("accurate.onnx"), b"not an onnx model").unwrap();
+
+        let stage = InferenceStage::with_hot_reload(model_dir.clone()).unwrap();
+        let mut ctx = StateContext::new();
+        ctx.active_model = ModelKind::Fast;
+        let mut telemetry = TelemetryRun::new(false, false).unwrap();
+        let mut frame = gray_frame();
+
+        // Runs one frame through the stage and returns how long it took.
+        let mut infer = || {
+            let start = Instant::now();
+            if let StageResult::Fault(reason) =
+                stage.handle(&mut frame, &mut ctx, &mut telemetry).unwrap()
+            {
+                panic!("frame dropped: {reason}");
+            }
+            start.elapsed()
+        };
+
+        infer(); // Builds the session.
+        let baseline = infer();
+        let slow = baseline * 5 + Duration::from_millis(100);
+
+        let reloads = reloaded_models();
+        raise(SIGHUP).unwrap();
+
+        // Frames keep going through the current model while the new one loads.
+        let start = Instant::now();
+        while reloaded_models() == reloads {
+            assert!(
+                start.elapsed() < RELOAD_TIMEOUT,
+                "the model wasn't reloaded"
+            );
+            let elapsed = infer();
+            assert!(elapsed < slow, "a frame took {elapsed:?} during the reload");
+        }
+
+        // The new model is used right away, without rebuilding a session.
+        let elapsed = infer();
+        assert!(
+            elapsed < slow,
+            "the first frame took {elapsed:?} after the reload"
+        );
+
+        // The corrupt model was rejected, the bundled one still runs.
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(reloaded_models(), reloads + 1);
+        assert!(
+            ModelKind::Accurate
+                .run(&gray_frame(), &mut telemetry, &ctx.run_id)
+                .is_ok()
+        );
+
+        let _ = fs::remove_dir_all(&model_dir);
+    }
 }
@@ -3,6 +3,7 @@
 use crate::frame::{SAVE_IMAGES, mark_run_rejected, purge_run_frames};
 use crate::logic::intent::Intent;
 use crate::logic::pipeline::RunId;
+use crate::motion::region::RegionOfInterest;
 use crossbeam_channel::{Sender, TrySendError, bounded, select, tick};
 use serde::Serialize;
 use std::io::BufWriter;
@@ -52,6 +53,8 @@ pub enum TelemetryPacket<'a> {
         total_points: u32,
         clustered_points: u32,
         threshold: u32,
+        // Region of interest in use (None for the whole frame)
+        region: Option<&'a RegionOfInterest>,
         ts: u128,
     },
     // Frame dropped due to failure or resource constraints
@@ -66,12 +69,15 @@ pub enum TelemetryPacket<'a> {
         ts: u128,
         reason: &'a str,
     },
-    // CPU/RAM/temp snapshot
+    // CPU/RAM/temp snapshot, with the thresholds in effect
     Health {
         run_id: RunId,
         cpu_pct: f32,
         ram_pct: f32,
         temp_c: f32,
+        temp_high_c: f32,
+        temp_critical_c: f32,
+        cpu_cap_pct: f32,
         ts: u128,
     },
     // ML model change triggered by health events
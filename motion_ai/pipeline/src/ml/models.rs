@@ -10,6 +10,8 @@ use ort::session::Session;
 use ort::session::builder::SessionBuilder;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 use thiserror::Error;
@@ -21,6 +23,9 @@ static MODEL_PATHS: OnceLock<HashMap<ModelKind, String>> = OnceLock::new();
 static SESSION_CACHE: Lazy<Mutex<HashMap<ModelKind, SessionEntry>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Number of models replaced by reload_model() since the start.
+static RELOADED_MODELS: AtomicUsize = AtomicUsize::new(0);
+
 /// Loads the models.toml configuration into the binary when compiling
 static MODEL_CONFIG: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/models.toml"));
 
@@ -98,10 +103,50 @@ where
 
 /// Constructs a new ONNX session from the specified model path using default threading config.
 fn build_session(path: &str) -> Result<Session, ort::Error> {
+    build_session_from_memory(MODEL_DATA_DIR.get_file(path).unwrap().contents())
+}
+
+fn build_session_from_memory(model: &[u8]) -> Result<Session, ort::Error> {
     SessionBuilder::new()?
         .with_inter_threads(1)?
         .with_intra_threads(1)?
-        .commit_from_memory(MODEL_DATA_DIR.get_file(path).unwrap().contents())
+        .commit_from_memory(model)
+}
+
+/// Replaces the model of the given kind with the ONNX file at path, without a restart.
+/// The new model is loaded and test-run before the cache is locked, so inference keeps using
+/// the current model meanwhile. If the new model can't be loaded or doesn't run, the current one is kept.
+pub fn reload_model(kind: &ModelKind, path: &Path) -> Result<(), ModelError> {
+    let model = std::fs::read(path)?;
+    let mut session = build_session_from_memory(&model)?;
+    NanodetRunner::validate(&mut session)?;
+
+    let paths = MODEL_PATHS
+        .get()
+        .ok_or_else(|| ModelError::Inference("init_model_paths not called".into()))?;
+    let wanted_path = paths
+        .get(kind)
+        .ok_or_else(|| ModelError::Inference(format!("No path for model kind: {:?}", kind)))?;
+    let mut cache = SESSION_CACHE
+        .lock()
+        .map_err(|_| ModelError::Inference("Mutex poisoned".into()))?;
+
+    // Keep the configured path so that with_session() doesn't rebuild the bundled model.
+    cache.insert(
+        *kind,
+        SessionEntry {
+            path: wanted_path.clone(),
+            session,
+        },
+    );
+    RELOADED_MODELS.fetch_add(1, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Number of models replaced by reload_model() since the start.
+pub fn reloaded_models() -> usize {
+    RELOADED_MODELS.load(Ordering::SeqCst)
 }
 
 /// Loads model file paths from `models.toml` and registers them for later lookup.
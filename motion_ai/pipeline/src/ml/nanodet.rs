@@ -12,6 +12,7 @@ use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use image::RgbImage;
 use image::imageops::FilterType;
 use ndarray::{Array, Array4, Ix3, s};
+use ort::session::Session;
 use ort::value::TensorRef;
 
 use crate::frame::RawFrame;
@@ -30,9 +31,37 @@ const STRIDES: [i32; 4] = [8, 16, 32, 64];
 /// Maximum bin index for NanoDet’s distance regression (DISTR head).
 const REG_MAX: usize = 7;
 
+/// Input resolution of the model (square).
+pub(crate) const INPUT_SIZE: usize = 416;
+
 /// Runs NanoDet object detection using a cached ONNX session and post-processing pipeline.
 pub struct NanodetRunner;
 
+impl NanodetRunner {
+    /// Runs the session on a blank image and checks that its output has the shape decode() expects.
+    /// Used to reject a broken model before it replaces a working one.
+    pub(crate) fn validate(sess: &mut Session) -> Result<(), ModelError> {
+        let input_tensor = Array4::<f32>::zeros((1, 3, INPUT_SIZE, INPUT_SIZE));
+        let input_value = TensorRef::from_array_view((
+            input_tensor.shape().to_vec(),
+            input_tensor
+                .as_slice()
+                .ok_or_else(|| ModelError::Inference("Failed to get tensor slice".into()))?,
+        ))?;
+
+        let outs = sess.run(ort::inputs![input_value])?;
+        let shape = outs[0].try_extract_array::<f32>()?.shape().to_vec();
+        let expected = NUM_CLASSES + 4 * (REG_MAX + 1);
+        if shape.len() != 3 || shape[2] != expected {
+            return Err(ModelError::Inference(format!(
+                "Unexpected model output shape {shape:?} (expected [1, N, {expected}])"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 /// Implements the NanoDet inference flow, including image preprocessing, session execution,
 /// decoding, non-max suppression, and telemetry emission.
 impl ModelRunner for NanodetRunner {
@@ -44,8 +73,8 @@ impl ModelRunner for NanodetRunner {
         telemetry: &mut TelemetryRun,
         run_id: &RunId,
     ) -> Result<DetectionResult, ModelError> {
-        const W: usize = 416;
-        const H: usize = 416;
+        const W: usize = INPUT_SIZE;
+        const H: usize = INPUT_SIZE;
 
         let old_w: u32 = frame.width as u32;
         let old_h: u32 = frame.height as u32;
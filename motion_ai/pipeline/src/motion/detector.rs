@@ -19,6 +19,7 @@ use crate::logic::pipeline::RunId;
 use crate::logic::telemetry::{TelemetryPacket, TelemetryRun};
 use crate::motion::background::BackgroundSubtractor;
 use crate::motion::preprocessing;
+use crate::motion::region::{RegionMask, RegionOfInterest};
 
 const ALPHA: f32 = 0.05; // Background update assuming updates every second. Adjusts based on motion FPS.
 pub(crate) const WEIGHT_BLUE_THRESHOLD: f32 = 70.0; // The threshold we consider an image to be night vision based on the emphasis on blue in R, G, B.
@@ -32,11 +33,36 @@ const MINIMUM_INDIVIDUAL_CLUSTER_POINTS: usize = 210; // The minimum amount of p
 /// MotionDetection reads raw YUV420 frames from the shared camera stream and checks for motion.
 pub struct MotionDetection {
     motion: Option<BackgroundSubtractor>,
+    /// Motion outside of this region is ignored (None for the whole frame).
+    region: Option<RegionOfInterest>,
+    region_mask: Option<RegionMask>,
 }
 
 impl MotionDetection {
     pub fn new() -> Self {
-        MotionDetection { motion: None }
+        MotionDetection {
+            motion: None,
+            region: None,
+            region_mask: None,
+        }
+    }
+
+    pub fn set_region_of_interest(&mut self, region: Option<RegionOfInterest>) {
+        self.region = region;
+        self.region_mask = None;
+    }
+
+    // Clears the motion outside of the region of interest (if any).
+    fn mask_region(&mut self, diff_result: &mut GrayImage) {
+        let Some(region) = self.region.as_ref() else {
+            return;
+        };
+        let mask = match self.region_mask.take() {
+            Some(mask) if mask.matches(diff_result) => mask,
+            _ => RegionMask::new(region, diff_result.width(), diff_result.height()),
+        };
+        mask.apply(diff_result);
+        self.region_mask = Some(mask);
     }
 
     // We run this method every time we want to check for motion.
@@ -78,11 +104,12 @@ impl MotionDetection {
         };
 
         bgs.save_backed_image(telemetry.run_id.as_str(), run_id)?; // Save the backing of the bg first
-        let diff_result = bgs.apply(
+        let mut diff_result = bgs.apply(
             &blurred_image,
             ALPHA / alpha_ratio,
             w_b >= WEIGHT_BLUE_THRESHOLD,
         );
+        self.mask_region(&mut diff_result);
         RawFrame::save_gray_image(&diff_result, telemetry.run_id.as_str(), run_id, "bg_result")?; // Now save the mask comparison
 
         self.motion = Some(bgs);
@@ -171,6 +198,7 @@ impl MotionDetection {
                     total_points: total_points as u32,
                     clustered_points: total_clustered_points as u32,
                     threshold: threshold_used,
+                    region: self.region.as_ref(),
                     ts,
                 })?;
                 return Ok(true);
@@ -189,6 +217,7 @@ impl MotionDetection {
             total_points: total_points as u32,
             clustered_points: 0,
             threshold: threshold_used,
+            region: self.region.as_ref(),
             ts,
         })?;
 
@@ -4,3 +4,4 @@ mod background;
 mod clahe;
 pub(crate) mod detector;
 mod preprocessing;
+pub mod region;
@@ -0,0 +1,88 @@
+//! Region of interest for motion detection. Motion outside of it is ignored.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+/// Part of the frame where motion counts, in normalized coordinates
+/// (0 to 1, from the top-left corner of the frame).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RegionOfInterest {
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    /// Vertices (x, y) of a polygon, in order. The polygon is closed automatically.
+    Polygon { points: Vec<(f32, f32)> },
+}
+
+impl RegionOfInterest {
+    /// Whether the point (normalized coordinates) is inside the region.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        match self {
+            RegionOfInterest::Rect {
+                x: rx,
+                y: ry,
+                width,
+                height,
+            } => x >= *rx && x <= rx + width && y >= *ry && y <= ry + height,
+            RegionOfInterest::Polygon { points } => {
+                // Even-odd rule: count how many edges a ray going right from the point crosses.
+                let mut inside = false;
+                let mut j = points.len().wrapping_sub(1);
+                for (i, &(xi, yi)) in points.iter().enumerate() {
+                    let (xj, yj) = points[j];
+                    if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Per-pixel version of a region for a given frame size, so that it's only computed once.
+pub(crate) struct RegionMask {
+    width: u32,
+    height: u32,
+    active: Vec<bool>,
+}
+
+impl RegionMask {
+    pub(crate) fn new(region: &RegionOfInterest, width: u32, height: u32) -> Self {
+        let mut active = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                // Pixel centers
+                let nx = (x as f32 + 0.5) / width as f32;
+                let ny = (y as f32 + 0.5) / height as f32;
+                active.push(region.contains(nx, ny));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            active,
+        }
+    }
+
+    pub(crate) fn matches(&self, img: &GrayImage) -> bool {
+        self.width == img.width() && self.height == img.height()
+    }
+
+    /// Clears the pixels of img that are outside of the region.
+    pub(crate) fn apply(&self, img: &mut GrayImage) {
+        for (pixel, &active) in img.iter_mut().zip(&self.active) {
+            if !active {
+                *pixel = 0;
+            }
+        }
+    }
+}
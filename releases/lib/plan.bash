@@ -21,7 +21,8 @@ resolve_build_plan() {
 
   case "$TARGET" in
     raspberry)
-      TRIPLES=( "aarch64-unknown-linux-gnu" )
+      # armv7 is for the 32-bit Raspberry Pi OS on the Pi 2/3, which can't run aarch64 binaries.
+      TRIPLES=( "aarch64-unknown-linux-gnu" "armv7-unknown-linux-gnueabihf" )
       case "$PROFILE" in
         all) PKGS=( "update" "reset" "raspberry_camera_hub" "config_tool" ) ;;
         core) PKGS=( "raspberry_camera_hub" "reset" "update" ) ;;
@@ -38,12 +39,13 @@ resolve_build_plan() {
       esac
       ;;
     all)
-      TRIPLES=( "aarch64-unknown-linux-gnu" "x86_64-unknown-linux-gnu" )
+      TRIPLES=( "aarch64-unknown-linux-gnu" "armv7-unknown-linux-gnueabihf" "x86_64-unknown-linux-gnu" )
       case "$PROFILE" in
         all) PKGS=( "update" "reset" "raspberry_camera_hub" "config_tool" "server" ) ;;
         release) PKGS=( "update" "raspberry_camera_hub" "server" ) ;;
         release-x64) PKGS=( "update" "raspberry_camera_hub" "server" ) TRIPLES=( "x86_64-unknown-linux-gnu" ) ;;
         release-arm64 ) PKGS=( "update" "raspberry_camera_hub" "server" ) TRIPLES=( "aarch64-unknown-linux-gnu" ) ;;
+        release-armv7) PKGS=( "update" "raspberry_camera_hub" ) TRIPLES=( "armv7-unknown-linux-gnueabihf" ) ;;
         *) die "Invalid profile for all: $PROFILE" ;;
       esac
       ;;
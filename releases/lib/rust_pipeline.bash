@@ -50,11 +50,16 @@ build_and_manifest() {
       # We skip those invalid combinations explicitly so profile semantics stay pretty
       # simple for release managers while still also enforcing architecture rules.
       if [[ "$pkg" == "raspberry_camera_hub" || "$pkg" == "reset" ]]; then
-        if [[ "$triple" != "aarch64-unknown-linux-gnu" ]]; then
+        if [[ "$triple" != "aarch64-unknown-linux-gnu" && "$triple" != "armv7-unknown-linux-gnueabihf" ]]; then
           echo "==> [run $run_id] SKIP $pkg for $triple (raspberry-only)"
           continue
         fi
       fi
+      # The server only ships for 64-bit hosts (see Component::zip_path in the updater).
+      if [[ "$pkg" == "server" && "$triple" == "armv7-unknown-linux-gnueabihf" ]]; then
+        echo "==> [run $run_id] SKIP $pkg for $triple (no 32-bit server)"
+        continue
+      fi
       if [[ "$TARGET" == "all" && "$PROFILE" == "test" && "$pkg" == "update" && "$triple" != "aarch64-unknown-linux-gnu" ]]; then
         echo "==> [run $run_id] SKIP $pkg for $triple (test profile keeps update on raspberry only)"
         continue
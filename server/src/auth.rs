@@ -3,6 +3,7 @@
 //! SPDX-License-Identifier: GPL-3.0-or-later
 
 use base64::{engine::general_purpose, Engine as _};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
@@ -10,16 +11,14 @@ use rocket::State;
 use secluso_client_server_lib::auth::{
     parse_user_credentials, NUM_PASSWORD_CHARS, NUM_USERNAME_CHARS,
 };
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
 use std::collections::VecDeque;
-use std::ffi::OsString;
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::str;
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::time::Duration;
 use std::time::Instant;
 use std::env;
@@ -51,7 +50,151 @@ pub struct FailEntry {
 // DashMap helps avoid normal user issues in cases of brute-forcing from lots of different IPs at once.
 pub type FailStore = Arc<DashMap<String, FailEntry>>;
 
-type UserStore = Mutex<HashMap<String, String>>;
+/// The users of the server and their passwords, consulted by the BasicAuth guard at request time.
+/// Users added or removed through the admin API are persisted to the user credentials directory,
+/// so there's no need to restart the server.
+#[derive(Clone)]
+pub struct UserStore {
+    users: Arc<DashMap<String, String>>,
+    // None when the credentials aren't persisted (SECLUSO_SKIP_USER_CREDENTIALS).
+    dir: Option<PathBuf>,
+}
+
+impl UserStore {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            users: Arc::new(DashMap::new()),
+            dir,
+        }
+    }
+
+    /// Adds a user in memory only, e.g., a temporary one. Returns the old password, if any.
+    pub fn insert(&self, username: String, password: String) -> Option<String> {
+        self.users.insert(username, password)
+    }
+
+    pub fn password(&self, username: &str) -> Option<String> {
+        self.users
+            .get(username)
+            .map(|password| password.value().clone())
+    }
+
+    /// The usernames, sorted.
+    pub fn usernames(&self) -> Vec<String> {
+        let mut usernames: Vec<String> = self.users.iter().map(|user| user.key().clone()).collect();
+        usernames.sort();
+        usernames
+    }
+
+    pub fn num_users(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Adds a new user and persists its credentials (in the same format as the ones
+    /// loaded at startup). Fails with AlreadyExists if the username is taken.
+    pub fn add(&self, username: &str, password: &str) -> io::Result<()> {
+        check_credentials(username, password)?;
+
+        match self.users.entry(username.to_string()) {
+            Entry::Occupied(_) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "User already exists",
+            )),
+            Entry::Vacant(entry) => {
+                if let Some(dir) = &self.dir {
+                    let mut file = fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(dir.join(username))?;
+                    file.write_all(format!("{username}{password}").as_bytes())?;
+                    file.sync_all()?;
+                }
+                entry.insert(password.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes a user, which stops authenticating right away, and deletes its persisted
+    /// credentials. Returns false if there's no such user.
+    pub fn remove(&self, username: &str) -> io::Result<bool> {
+        if self.users.remove(username).is_none() {
+            return Ok(false);
+        }
+
+        if let Some(dir) = &self.dir {
+            for path in credential_files(dir, username)? {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Replaces the password of a user with a rotated one (see rotate_user_credentials() in
+    /// client_server_lib), keeping the username and hence the user's data and cameras.
+    /// The old password stops authenticating right away. Returns false if there's no such user.
+    pub fn set_password(&self, username: &str, password: &str) -> io::Result<bool> {
+        check_credentials(username, password)?;
+
+        let Some(mut stored_password) = self.users.get_mut(username) else {
+            return Ok(false);
+        };
+
+        if let Some(dir) = &self.dir {
+            let credentials = format!("{username}{password}");
+            let paths = credential_files(dir, username)?;
+            if paths.is_empty() {
+                fs::write(dir.join(username), &credentials)?;
+            }
+            for path in paths {
+                fs::write(path, &credentials)?;
+            }
+        }
+
+        *stored_password = password.to_string();
+        Ok(true)
+    }
+}
+
+fn check_credentials(username: &str, password: &str) -> io::Result<()> {
+    if username.len() != NUM_USERNAME_CHARS
+        || password.len() != NUM_PASSWORD_CHARS
+        || username.contains([':', '/'])
+        || password.contains(':')
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid credentials",
+        ));
+    }
+
+    Ok(())
+}
+
+// The files in dir with the credentials of username.
+// The ones loaded at startup can have any name.
+fn credential_files(dir: &Path, username: &str) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        if !path.is_file() {
+            continue;
+        }
+        match parse_user_credentials(fs::read(&path)?) {
+            Ok((file_username, _)) if file_username == username => paths.push(path),
+            _ => {}
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Guard for the admin API. The token is the admin_token of the Rocket config
+/// (e.g., ROCKET_ADMIN_TOKEN), sent as "Authorization: Bearer <token>".
+/// The admin API is disabled (404) unless a token is configured.
+pub struct AdminAuth;
 
 // Check and see if the given IP (key) is in lock-mode.
 fn is_locked(store: &FailStore, key: &str) -> bool {
@@ -161,10 +304,9 @@ impl<'r> FromRequest<'r> for &'r BasicAuth {
             if let Some((username, password)) = decode_basic_auth(auth_value) {
                 let password_bytes: [u8; NUM_PASSWORD_CHARS] = to_fixed_bytes(&password);
 
-                let users = user_store.lock().unwrap();
                 let (stored_password_bytes, user_exists): ([u8; NUM_PASSWORD_CHARS], bool) =
-                    match users.get(&username) {
-                        Some(stored_password) => (to_fixed_bytes(stored_password), true),
+                    match user_store.password(&username) {
+                        Some(stored_password) => (to_fixed_bytes(&stored_password), true),
                         None => (DUMMY_PASSWORD, false),
                     };
 
@@ -194,6 +336,51 @@ impl<'r> FromRequest<'r> for &'r BasicAuth {
     }
 }
 
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let admin_token = match req
+            .rocket()
+            .figment()
+            .extract_inner::<String>("admin_token")
+        {
+            Ok(token) if !token.is_empty() => token,
+            _ => return Outcome::Error((Status::NotFound, ())),
+        };
+
+        let fail_store = req.guard::<&State<FailStore>>().await.unwrap();
+        let ip = req
+            .client_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "unknown".into());
+
+        if is_locked(fail_store, &ip) {
+            return Outcome::Error((Status::TooManyRequests, ()));
+        }
+
+        // Compare the digests so that the comparison doesn't depend on the length of the token.
+        let token = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        let eq: Choice = Sha256::digest(token.as_bytes())
+            .as_slice()
+            .ct_eq(Sha256::digest(admin_token.as_bytes()).as_slice());
+        if bool::from(eq) {
+            return Outcome::Success(AdminAuth);
+        }
+
+        if record_failure(fail_store, &ip) {
+            return Outcome::Error((Status::TooManyRequests, ()));
+        }
+
+        Outcome::Error((Status::Unauthorized, ()))
+    }
+}
+
 fn decode_basic_auth(auth_value: &str) -> Option<(String, String)> {
     if let Some(encoded) = auth_value.strip_prefix("Basic ") {
         // Remove "Basic " prefix
@@ -213,14 +400,19 @@ fn decode_basic_auth(auth_value: &str) -> Option<(String, String)> {
 }
 
 pub fn initialize_users() -> UserStore {
-    let mut users = HashMap::new();
     if std::env::var("SECLUSO_SKIP_USER_CREDENTIALS").is_ok() {
-        return Mutex::new(users);
+        return UserStore::new(None);
     }
 
     let dir = std::env::var("SECLUSO_USER_CREDENTIALS_DIR")
         .unwrap_or_else(|_| "./user_credentials".to_string());
-    match fs::read_dir(dir.clone()) {
+    load_users(Path::new(&dir))
+}
+
+/// Loads the users from the credential files in dir, and persists later changes there.
+pub fn load_users(dir: &Path) -> UserStore {
+    let users = UserStore::new(Some(dir.to_path_buf()));
+    match fs::read_dir(dir) {
         Ok(files) => {
             for file in files {
                 match file {
@@ -229,10 +421,8 @@ pub fn initialize_users() -> UserStore {
                             Ok(file_type) => {
                                 //Ignore dir, symlink, etc.
                                 if file_type.is_file() {
-                                    let mut pathname = OsString::from(dir.clone() + "/");
-                                    pathname.push(f.file_name());
                                     let fil =
-                                        fs::File::open(pathname).expect("Could not open file");
+                                        fs::File::open(f.path()).expect("Could not open file");
                                     let mut reader = BufReader::with_capacity(
                                         fil.metadata().unwrap().len().try_into().unwrap(),
                                         fil,
@@ -269,5 +459,61 @@ pub fn initialize_users() -> UserStore {
             panic!("Could not read directory: {:?}", e);
         }
     }
-    Mutex::new(users)
+    users
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load_users;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn user_changes_are_persisted() {
+        let dir = std::env::temp_dir().join("secluso_auth_tests_users");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("user_credentials"), "authtestuser01authtestpass01").unwrap();
+
+        let users = load_users(&dir);
+        assert_eq!(users.password("authtestuser01").unwrap(), "authtestpass01");
+
+        users.add("authtestuser02", "authtestpass02").unwrap();
+        let e = users.add("authtestuser02", "authtestpass03").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        let e = users.add("short", "authtestpass03").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+        assert!(users
+            .set_password("authtestuser01", "authtestpass04")
+            .unwrap());
+        assert_eq!(
+            fs::read(dir.join("user_credentials")).unwrap(),
+            b"authtestuser01authtestpass04"
+        );
+        assert_eq!(
+            load_users(&dir).password("authtestuser01").unwrap(),
+            "authtestpass04"
+        );
+        assert!(!users
+            .set_password("authtestuser03", "authtestpass04")
+            .unwrap());
+
+        assert!(users.remove("authtestuser01").unwrap());
+        assert!(!users.remove("authtestuser01").unwrap());
+        assert!(!dir.join("user_credentials").exists());
+
+        // What a restart would load.
+        let reloaded = load_users(&dir);
+        assert_eq!(reloaded.usernames(), vec!["authtestuser02".to_string()]);
+        assert_eq!(
+            reloaded.password("authtestuser02").unwrap(),
+            "authtestpass02"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(Path::new("data").join("authtestuser01"));
+        let _ = fs::remove_dir_all(Path::new("data").join("authtestuser02"));
+    }
 }
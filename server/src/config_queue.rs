@@ -0,0 +1,204 @@
+//! Per-camera queues for config commands and config responses.
+//! Every entry is its own numbered file (e.g., command_000001) in the camera directory, so that
+//! back-to-back commands (or responses) don't overwrite each other and are delivered in the
+//! order they were received.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::integrity::parse_hex_digest;
+use rocket::tokio::fs;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const COMMAND_PREFIX: &str = "command";
+pub const RESPONSE_PREFIX: &str = "config_response";
+
+/// Entries that haven't been retrieved yet, per camera and per queue.
+pub const MAX_QUEUED_ENTRIES: usize = 32;
+
+fn entry_name(prefix: &str, number: u64) -> String {
+    format!("{prefix}_{number:06}")
+}
+
+// Number of a queue entry, e.g., 12 for "command_000012".
+// Other files (temp files, the other queue, ...) return None.
+fn entry_number(prefix: &str, filename: &str) -> Option<u64> {
+    let digits = filename.strip_prefix(prefix)?.strip_prefix('_')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The entries of a queue in dir, oldest first.
+pub async fn queued_entries(dir: &Path, prefix: &str) -> io::Result<Vec<PathBuf>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut numbered = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(number) = entry_number(prefix, &entry.file_name().to_string_lossy()) {
+            numbered.push((number, entry.path()));
+        }
+    }
+    numbered.sort();
+
+    Ok(numbered.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Path of a new entry at the end of a queue in dir.
+/// Fails with ErrorKind::QuotaExceeded if the queue is full.
+/// Callers must serialize the calls for the same queue until the entry is created.
+pub async fn next_entry_path(dir: &Path, prefix: &str) -> io::Result<PathBuf> {
+    let entries = queued_entries(dir, prefix).await?;
+    if entries.len() >= MAX_QUEUED_ENTRIES {
+        return Err(io::Error::new(
+            io::ErrorKind::QuotaExceeded,
+            format!("Too many pending {prefix} entries (at most {MAX_QUEUED_ENTRIES})"),
+        ));
+    }
+
+    let last = entries
+        .last()
+        .and_then(|path| entry_number(prefix, &path.file_name()?.to_string_lossy()))
+        .unwrap_or(0);
+
+    Ok(dir.join(entry_name(prefix, last + 1)))
+}
+
+/// Id of the entry at path for the client to acknowledge it with, e.g., "command_000012.<digest>".
+/// Numbers are reused once a queue is empty, so the digest of the content makes sure that a
+/// repeated acknowledgment doesn't remove a newer entry with the same number.
+pub fn entry_id(path: &Path, content: &[u8]) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let digest: String = Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{name}.{digest}")
+}
+
+/// Removes the entry of a queue in dir that id (from entry_id) names, if it's still there
+/// with the same content, and returns its size.
+pub async fn remove_acknowledged_entry(
+    dir: &Path,
+    prefix: &str,
+    id: &str,
+) -> io::Result<Option<u64>> {
+    let Some((name, digest)) = id.split_once('.') else {
+        return Ok(None);
+    };
+    let (Some(_), Some(digest)) = (entry_number(prefix, name), parse_hex_digest(digest)) else {
+        return Ok(None);
+    };
+
+    let path = dir.join(name);
+    let content = match fs::read(&path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if Sha256::digest(&content)[..] != digest[..] {
+        return Ok(None);
+    }
+
+    fs::remove_file(&path).await?;
+    Ok(Some(content.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        entry_id, entry_name, entry_number, remove_acknowledged_entry, COMMAND_PREFIX,
+        RESPONSE_PREFIX,
+    };
+    use std::fs;
+
+    #[test]
+    fn parses_entry_numbers() {
+        assert_eq!(entry_name(COMMAND_PREFIX, 12), "command_000012");
+        assert_eq!(entry_number(COMMAND_PREFIX, "command_000012"), Some(12));
+        assert_eq!(
+            entry_number(COMMAND_PREFIX, "command_1234567"),
+            Some(1234567)
+        );
+        assert_eq!(
+            entry_number(RESPONSE_PREFIX, "config_response_000001"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        assert_eq!(entry_number(COMMAND_PREFIX, "command"), None);
+        assert_eq!(entry_number(COMMAND_PREFIX, "command_"), None);
+        assert_eq!(entry_number(COMMAND_PREFIX, "command.partial"), None);
+        assert_eq!(entry_number(COMMAND_PREFIX, "command_+12"), None);
+        assert_eq!(entry_number(RESPONSE_PREFIX, "config_response_tmp"), None);
+        assert_eq!(entry_number(COMMAND_PREFIX, "config_response_000001"), None);
+    }
+
+    #[rocket::async_test]
+    async fn acknowledged_entries_are_removed_only_with_the_same_content() {
+        let dir = std::env::temp_dir().join(format!("secluso-ack-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("command_000001");
+        fs::write(&path, b"first").unwrap();
+        let id = entry_id(&path, b"first");
+        assert!(id.starts_with("command_000001."));
+
+        // The same number, reused for a newer command.
+        fs::write(&path, b"second").unwrap();
+        assert_eq!(
+            remove_acknowledged_entry(&dir, COMMAND_PREFIX, &id)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(path.exists());
+
+        let id = entry_id(&path, b"second");
+        for bad_id in [
+            "command_000001",
+            "../command_000001.00",
+            "command.partial.00",
+        ] {
+            assert_eq!(
+                remove_acknowledged_entry(&dir, COMMAND_PREFIX, bad_id)
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        assert_eq!(
+            remove_acknowledged_entry(&dir, RESPONSE_PREFIX, &id)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            remove_acknowledged_entry(&dir, COMMAND_PREFIX, &id)
+                .await
+                .unwrap(),
+            Some(6)
+        );
+        assert!(!path.exists());
+        // Acknowledging it again is a no-op.
+        assert_eq!(
+            remove_acknowledged_entry(&dir, COMMAND_PREFIX, &id)
+                .await
+                .unwrap(),
+            None
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,97 @@
+//! Download-and-delete in one request, so that a lost DELETE can't make the app download
+//! (and decrypt) the same file twice.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use rocket::http::ContentType;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use rocket::Request;
+use std::future::Future;
+use std::io;
+use std::io::{ErrorKind, SeekFrom};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+pub type ReleaseFuture = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
+/// A file that is released (e.g., deleted) once its whole content has been read into the response.
+/// If the download is aborted before that, the body is dropped before the end and the file is kept.
+pub struct ConsumedFile {
+    body: ConsumingBody,
+    size: u64,
+}
+
+impl ConsumedFile {
+    /// release only runs once the end of the file is reached.
+    pub async fn new(file: File, release: ReleaseFuture) -> io::Result<Self> {
+        let size = file.metadata().await?.len();
+        Ok(Self {
+            body: ConsumingBody {
+                file,
+                release: Some(release),
+            },
+            size,
+        })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ConsumedFile {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::Plain)
+            .sized_body(Some(self.size as usize), self.body)
+            .ok()
+    }
+}
+
+pub struct ConsumingBody {
+    file: File,
+    release: Option<ReleaseFuture>,
+}
+
+impl AsyncRead for ConsumingBody {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.file).poll_read(cx, buf))?;
+        if buf.filled().len() > filled || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // End of the file: everything has been handed to the response.
+        // We only report the end once the file is released.
+        if let Some(release) = this.release.as_mut() {
+            let result = ready!(release.as_mut().poll(cx));
+            this.release = None;
+            if let Err(e) = result {
+                error!("Failed to delete a consumed file: {e}");
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Rocket requires sized bodies to be seekable in order to find their size.
+// We always give it the size, so seeking is never needed.
+impl AsyncSeek for ConsumingBody {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Seeking within a consumed file is not supported",
+        ))
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Seeking within a consumed file is not supported",
+        )))
+    }
+}
@@ -0,0 +1,385 @@
+//! Expiry of the data that nobody is going to retrieve: motion files of cameras whose app is
+//! gone, livestream directories of sessions that crashed, and empty camera directories.
+//!
+//! The TTLs come from the expiry table of the Rocket config, e.g., in Rocket.toml:
+//!
+//! [default.expiry]
+//! motion_file_ttl_secs = 2592000
+//! livestream_idle_secs = 3600
+//! sweep_interval_secs = 3600
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::notification_target::NOTIFICATION_TARGETS_DIR;
+use crate::push_token::is_push_token_dir;
+use crate::quota::StorageQuota;
+use crate::AllEventState;
+use rocket::fairing::AdHoc;
+use rocket::serde::Deserialize;
+use rocket::tokio::{self, select, task};
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// Marks a camera directory as used for livestreaming: data/<user>/.<camera>.livestream
+// Kept next to the camera directory, so that it doesn't count as a pending chunk.
+const LIVESTREAM_MARKER_SUFFIX: &str = ".livestream";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct ExpiryConfig {
+    /// Files not retrieved within this long are deleted.
+    pub motion_file_ttl_secs: u64,
+    /// Livestream directories without activity for this long are deleted.
+    pub livestream_idle_secs: u64,
+    /// How often data/ is scanned. 0 disables expiry.
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self {
+            motion_file_ttl_secs: 30 * 24 * 60 * 60,
+            livestream_idle_secs: 60 * 60,
+            sweep_interval_secs: 60 * 60,
+        }
+    }
+}
+
+pub fn livestream_marker(root: &Path, camera: &str) -> PathBuf {
+    root.join(format!(".{camera}{LIVESTREAM_MARKER_SUFFIX}"))
+}
+
+fn is_expired(modified: SystemTime, ttl: Duration, now: SystemTime) -> bool {
+    now.duration_since(modified)
+        .map(|age| age > ttl)
+        .unwrap_or(false)
+}
+
+fn remove_logged(path: &Path, removed: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    info!("Expired {}", path.display());
+    removed.push(path.to_path_buf());
+    Ok(())
+}
+
+// Most recent modification of the directory or anything in it (None if it doesn't exist).
+fn last_activity(path: &Path) -> io::Result<Option<SystemTime>> {
+    let mut latest = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let modified = if entry.file_type()?.is_dir() {
+            last_activity(&entry.path())?
+        } else {
+            Some(entry.metadata()?.modified()?)
+        };
+        latest = latest.max(modified.unwrap_or(latest));
+    }
+
+    Ok(Some(latest))
+}
+
+// Deletes the files of camera_path that are older than the TTL,
+// and camera_path itself if it ends up (or was already) empty.
+fn sweep_camera(
+    camera_path: &Path,
+    config: &ExpiryConfig,
+    now: SystemTime,
+    removed: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let motion_file_ttl = Duration::from_secs(config.motion_file_ttl_secs);
+    let mut num_removed = 0;
+    let mut num_left = 0;
+
+    for entry in fs::read_dir(camera_path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        // This includes the refcount files, which are as old as their motion file (or newer).
+        if metadata.is_file() && is_expired(metadata.modified()?, motion_file_ttl, now) {
+            remove_logged(&entry.path(), removed)?;
+            num_removed += 1;
+        } else {
+            num_left += 1;
+        }
+    }
+
+    // Fresh empty directories are left alone, since an upload might be about to write into them.
+    // remove_dir (not remove_dir_all) fails if a file was uploaded since we listed the directory.
+    if num_left == 0 {
+        let idle = Duration::from_secs(config.livestream_idle_secs);
+        if (num_removed > 0 || is_expired(fs::metadata(camera_path)?.modified()?, idle, now))
+            && fs::remove_dir(camera_path).is_ok()
+        {
+            info!("Expired {}", camera_path.display());
+            removed.push(camera_path.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the expired data of all users under data_root and returns the deleted paths.
+pub fn sweep(data_root: &Path, config: &ExpiryConfig, now: SystemTime) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let livestream_idle = Duration::from_secs(config.livestream_idle_secs);
+
+    let users = match fs::read_dir(data_root) {
+        Ok(users) => users,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(removed),
+        Err(e) => return Err(e),
+    };
+
+    for user in users {
+        let user = user?;
+        if !user.file_type()?.is_dir() {
+            continue;
+        }
+        let root = user.path();
+
+        let mut cameras = Vec::new();
+        let mut livestream_cameras = Vec::new();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() {
+                if !name.starts_with('.')
+                    && name != NOTIFICATION_TARGETS_DIR
+                    && !is_push_token_dir(&name)
+                {
+                    cameras.push(name);
+                }
+            } else if let Some(camera) = name
+                .strip_prefix('.')
+                .and_then(|name| name.strip_suffix(LIVESTREAM_MARKER_SUFFIX))
+            {
+                livestream_cameras.push(camera.to_string());
+            }
+        }
+
+        for camera in &livestream_cameras {
+            let camera_path = root.join(camera);
+            let expired = match last_activity(&camera_path)? {
+                Some(modified) => is_expired(modified, livestream_idle, now),
+                None => true,
+            };
+            if expired {
+                if camera_path.exists() {
+                    remove_logged(&camera_path, &mut removed)?;
+                }
+                fs::remove_file(livestream_marker(&root, camera))?;
+            }
+        }
+
+        for camera in cameras {
+            if !livestream_cameras.contains(&camera) {
+                sweep_camera(&root.join(camera), config, now, &mut removed)?;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Periodically sweeps data/ in the background once the server is up.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Expiry of stale files", |rocket| {
+        Box::pin(async move {
+            let config = match rocket.figment().extract_inner::<ExpiryConfig>("expiry") {
+                Ok(config) => config,
+                Err(e) if e.missing() => ExpiryConfig::default(),
+                Err(e) => {
+                    error!("Invalid expiry config ({e}). Falling back to the defaults.");
+                    ExpiryConfig::default()
+                }
+            };
+            if config.sweep_interval_secs == 0 {
+                return;
+            }
+
+            let quota = rocket.state::<StorageQuota>().cloned();
+            let all_state = rocket.state::<AllEventState>().cloned();
+            let mut shutdown = rocket.shutdown();
+
+            tokio::spawn(async move {
+                let data_root = Path::new("data");
+                let interval = Duration::from_secs(config.sweep_interval_secs);
+
+                loop {
+                    select! {
+                        _ = tokio::time::sleep(interval) => {},
+                        _ = &mut shutdown => break,
+                    };
+
+                    let sweep_config = config.clone();
+                    let removed = match task::spawn_blocking(move || {
+                        sweep(data_root, &sweep_config, SystemTime::now())
+                    })
+                    .await
+                    {
+                        Ok(Ok(removed)) => removed,
+                        Ok(Err(e)) => {
+                            error!("Failed to sweep expired files: {e}");
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Expiry task failed: {e}");
+                            continue;
+                        }
+                    };
+
+                    // The deleted files no longer count against the users' quotas,
+                    // and deleted livestream directories no longer have a livestream going on.
+                    for path in removed {
+                        let mut components = path
+                            .strip_prefix(data_root)
+                            .unwrap_or(&path)
+                            .iter()
+                            .map(|c| c.to_string_lossy().into_owned());
+                        let (Some(username), Some(camera)) = (components.next(), components.next())
+                        else {
+                            continue;
+                        };
+
+                        if let Some(quota) = &quota {
+                            quota.invalidate(&username);
+                        }
+                        if components.next().is_none() {
+                            if let Some(user_state) =
+                                all_state.as_ref().and_then(|state| state.get(&username))
+                            {
+                                user_state.livestreams.remove(&camera);
+                            }
+                        }
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{livestream_marker, sweep, ExpiryConfig};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn test_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("secluso-expiry-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn write_aged(path: &Path, age: Duration) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"data").unwrap();
+        backdate(path, age);
+    }
+
+    // Works for directories too (on Linux).
+    fn backdate(path: &Path, age: Duration) {
+        let file = fs::File::open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn sweep_removes_exactly_the_expired_data() {
+        let data_root = test_root("sweep");
+        let root = data_root.join("user");
+
+        // Motion files: only the ones past the TTL (and their refcount files) go.
+        write_aged(&root.join("front").join("100"), 31 * DAY);
+        write_aged(&root.join("front").join(".100.refcount"), 31 * DAY);
+        write_aged(&root.join("front").join("200"), 29 * DAY);
+        write_aged(&root.join("front").join(".200.refcount"), 29 * DAY);
+
+        // A camera whose files all expired goes too.
+        write_aged(&root.join("back").join("100"), 40 * DAY);
+
+        // Empty camera directories go once they're not fresh anymore.
+        fs::create_dir_all(root.join("empty")).unwrap();
+        backdate(&root.join("empty"), 2 * HOUR);
+        fs::create_dir_all(root.join("new")).unwrap();
+
+        // Livestream directories go after an hour without activity, talkback included.
+        write_aged(&root.join("live").join("3"), 2 * HOUR);
+        write_aged(&root.join("live").join("talkback").join("1"), 2 * HOUR);
+        backdate(&root.join("live").join("talkback"), 2 * HOUR);
+        backdate(&root.join("live"), 2 * HOUR);
+        write_aged(&livestream_marker(&root, "live"), 2 * HOUR);
+
+        write_aged(&root.join("ongoing").join("3"), 2 * HOUR);
+        write_aged(&root.join("ongoing").join("4"), Duration::ZERO);
+        write_aged(&livestream_marker(&root, "ongoing"), 2 * HOUR);
+
+        // Not camera data.
+        write_aged(&root.join("fcm_token"), 40 * DAY);
+        write_aged(&root.join("fcm_tokens").join("fcm_token_1"), 40 * DAY);
+        write_aged(&root.join("apns_tokens").join("apns_token_1"), 40 * DAY);
+        write_aged(
+            &root
+                .join("notification_targets")
+                .join("notification_target_a.json"),
+            40 * DAY,
+        );
+
+        let mut removed = sweep(&data_root, &ExpiryConfig::default(), SystemTime::now()).unwrap();
+        removed.sort();
+
+        let mut expected = vec![
+            root.join("front").join("100"),
+            root.join("front").join(".100.refcount"),
+            root.join("back").join("100"),
+            root.join("back"),
+            root.join("empty"),
+            root.join("live"),
+        ];
+        expected.sort();
+        assert_eq!(removed, expected);
+
+        assert!(!livestream_marker(&root, "live").exists());
+        assert!(livestream_marker(&root, "ongoing").exists());
+        assert!(root.join("front").join("200").exists());
+        assert!(root.join("new").is_dir());
+        assert!(root.join("ongoing").join("3").exists());
+        assert!(root.join("fcm_token").exists());
+        assert!(root.join("fcm_tokens").join("fcm_token_1").exists());
+        assert!(root.join("apns_tokens").join("apns_token_1").exists());
+        assert!(root.join("notification_targets").is_dir());
+
+        // Nothing left to expire.
+        assert!(
+            sweep(&data_root, &ExpiryConfig::default(), SystemTime::now())
+                .unwrap()
+                .is_empty()
+        );
+
+        let _ = fs::remove_dir_all(&data_root);
+    }
+
+    #[test]
+    fn sweep_without_data() {
+        let data_root = test_root("missing").join("data");
+        assert!(
+            sweep(&data_root, &ExpiryConfig::default(), SystemTime::now())
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -8,22 +8,17 @@ use chrono::{Duration, Utc};
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use plist::Value;
 use reqwest::blocking::Client;
-use reqwest::Url;
-use secluso_server_backbone::types::ConfigResponse;
+use reqwest::{StatusCode, Url};
+use secluso_server_backbone::types::{ConfigResponse, PushProvider};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashSet;
 use std::error::Error;
-use std::io::{self, ErrorKind};
-use std::path::Path;
 use std::{fs, thread, time};
-use std::sync::OnceLock;
-use rocket::tokio::sync::Mutex;
 
 use rocket::tokio::fs as tokio_fs;
-use rocket::tokio::io::AsyncWriteExt;
+use rocket::tokio::time::sleep;
 
-use crate::security::check_path_sandboxed;
+use crate::push_token::{PushError, PushSender};
 
 // Fixed bundle id used to locate the Firebase app
 const BUNDLE_ID: &str = "com.secluso.mobile";
@@ -39,6 +34,11 @@ const LEGACY_FCM_TOKEN_FILE: &str = "fcm_token";
 const FCM_TOKENS_DIR: &str = "fcm_tokens";
 const FCM_TOKEN_FILE_PREFIX: &str = "fcm_token_";
 
+// Attempts per notification when FCM has a transient failure (5xx or 429) or can't be reached.
+// The delay between attempts doubles every time.
+const FCM_SEND_ATTEMPTS: u32 = 3;
+const FCM_RETRY_DELAY: time::Duration = time::Duration::from_millis(500);
+
 // In this file we send very sensitive stuff over HTTP requests: a JWT assertion signed with the
 // Firebase service-account private key, bearer access tokens, and push payloads tied to user
 // devices. If any endpoint URL is quietly changed to plain http:// or to a lookalike host,
@@ -149,11 +149,11 @@ struct Claims {
     iat: usize,
 }
 
-fn fetch_token(
+// The token endpoint of the service account, and the signed JWT assertion to send to it.
+fn token_assertion(
     service_account_key: &ServiceAccountKey,
-    client: &Client,
     scope: String,
-) -> Result<String, Box<dyn Error>> {
+) -> Result<(Url, String)> {
     let token_uri = validate_https_url(
         &service_account_key.token_uri,
         OAUTH_TOKEN_ALLOWED_HOSTS,
@@ -178,6 +178,16 @@ fn fetch_token(
     let encoding_key = EncodingKey::from_rsa_pem(private_key.as_bytes())?;
     let jwt = encode(&header, &claims, &encoding_key)?;
 
+    Ok((token_uri, jwt))
+}
+
+fn fetch_token(
+    service_account_key: &ServiceAccountKey,
+    client: &Client,
+    scope: String,
+) -> Result<String, Box<dyn Error>> {
+    let (token_uri, jwt) = token_assertion(service_account_key, scope)?;
+
     // Obtain the OAuth 2.0 token
     let token_response: serde_json::Value = client
         .post(token_uri)
@@ -195,6 +205,32 @@ fn fetch_token(
         .to_string())
 }
 
+async fn fetch_token_async(
+    service_account_key: &ServiceAccountKey,
+    client: &reqwest::Client,
+    scope: String,
+) -> Result<String> {
+    let (token_uri, jwt) = token_assertion(service_account_key, scope)?;
+
+    // Obtain the OAuth 2.0 token
+    let token_response: serde_json::Value = client
+        .post(token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", &jwt),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(token_response["access_token"]
+        .as_str()
+        .context("Failed to get access_token")?
+        .to_string())
+}
+
 fn fetch_app_identifier(
     client: &Client,
     service_account_key: &ServiceAccountKey,
@@ -508,189 +544,303 @@ pub fn fetch_config() -> Result<ConfigResponse, Box<dyn Error>> {
         project_id: project_id,
         storage_bucket: storage_bucket.to_string(),
         bundle_id: BUNDLE_ID.to_string(),
+        push_providers: vec![PushProvider::Fcm],
     };
 
     Ok(response)
 }
 
-pub fn send_notification(device_token: String, msg: Vec<u8>) -> Result<(), Box<dyn Error>> {
-    let client = Client::builder().https_only(true).build()?;
+/// Sends notifications through the FCM HTTP v1 API.
+pub struct FcmSender {
+    client: reqwest::Client,
+    send_url: Url,
+    access_token: String,
+    retry_delay: time::Duration,
+}
 
-    // Read the service account key file
-    let service_account_key: ServiceAccountKey =
-        serde_json::from_str(&fs::read_to_string("service_account_key.json")?)?;
+impl FcmSender {
+    /// Uses the credentials in service_account_key.json.
+    pub async fn from_service_account() -> Result<Self> {
+        let client = reqwest::Client::builder().https_only(true).build()?;
 
-    let access_token = fetch_token(
-        &service_account_key,
-        &client,
-        "https://www.googleapis.com/auth/firebase.messaging".to_string(),
-    )?;
+        // Read the service account key file
+        let service_account_key: ServiceAccountKey =
+            serde_json::from_str(&tokio_fs::read_to_string("service_account_key.json").await?)?;
 
-    // The FCM endpoint for sending messages
-    let fcm_url = format!(
-        "https://fcm.googleapis.com/v1/projects/{}/messages:send",
-        service_account_key.project_id
-    );
-    let fcm_url = validate_https_url(&fcm_url, &[FCM_API_HOST], "FCM endpoint")?;
+        let access_token = fetch_token_async(
+            &service_account_key,
+            &client,
+            "https://www.googleapis.com/auth/firebase.messaging".to_string(),
+        )
+        .await?;
+
+        // The FCM endpoint for sending messages
+        let send_url = format!(
+            "https://fcm.googleapis.com/v1/projects/{}/messages:send",
+            service_account_key.project_id
+        );
+        let send_url = validate_https_url(&send_url, &[FCM_API_HOST], "FCM endpoint")?;
 
-    // Create the FCM message payload
-    let message = json!({
-        "message": {
-            "token": device_token,
-            "data": {
-                "title": "",
-                "body": general_purpose::STANDARD.encode(msg),
-            },
-            "android": {
-                "priority": "high"
-            },
-            "apns": {
-                "headers": {
-                    "apns-push-type": "background",
-                    "apns-priority": "5"
+        Ok(Self {
+            client,
+            send_url,
+            access_token,
+            retry_delay: FCM_RETRY_DELAY,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl PushSender for FcmSender {
+    const PROVIDER: PushProvider = PushProvider::Fcm;
+
+    async fn send(&self, device_token: &str, msg: &[u8]) -> Result<(), PushError> {
+        // Create the FCM message payload
+        let message = json!({
+            "message": {
+                "token": device_token,
+                "data": {
+                    "title": "",
+                    "body": general_purpose::STANDARD.encode(msg),
+                },
+                "android": {
+                    "priority": "high"
                 },
-                "payload": {
-                    "aps": {
-                        "content-available": 1
+                "apns": {
+                    "headers": {
+                        "apns-push-type": "background",
+                        "apns-priority": "5"
+                    },
+                    "payload": {
+                        "aps": {
+                            "content-available": 1
+                        }
                     }
                 }
             }
-        }
-    });
+        });
+
+        let mut attempt = 1;
+        let mut delay = self.retry_delay;
+        loop {
+            let response = self
+                .client
+                .post(self.send_url.clone())
+                .bearer_auth(&self.access_token)
+                .json(&message)
+                .send()
+                .await;
+
+            let error = match response {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    if is_unregistered(&body) {
+                        return Err(PushError::Unregistered);
+                    }
 
-    // Send the POST request
-    let response = client
-        .post(fcm_url)
-        .bearer_auth(access_token)
-        .header("Content-Type", "application/json")
-        .json(&message)
-        .send()?;
-
-    // Check the response status
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().unwrap_or_default();
-        return Err(
-            anyhow::anyhow!("Error: Failed to send notification. ({status}). {body}").into(),
-        );
-    }
+                    let error = format!("Failed to send notification. ({status}). {body}");
+                    if !status.is_server_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                        return Err(PushError::Failed(error));
+                    }
+                    error
+                }
+                Err(e) => format!("Failed to reach FCM: {e}"),
+            };
 
-    Ok(())
+            if attempt >= FCM_SEND_ATTEMPTS {
+                return Err(PushError::Failed(error));
+            }
+            debug!("Transient FCM failure (attempt {attempt}): {error}");
+            sleep(delay).await;
+            attempt += 1;
+            delay *= 2;
+        }
+    }
 }
 
-static FCM_TOKEN_STORE_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
-
-// FIXME: we only add fcm_tokens, but never remove stale ones.
-pub(crate) async fn store_fcm_token(root: &Path, token: &str) -> io::Result<()> {
-    // Two overlapping calls to store_fcm_token from one app for the same token can
-    // result in duplicates. This lock is used to prevent that.
-    let lock = FCM_TOKEN_STORE_LOCK.get_or_init(|| Mutex::new(()));
-    let _guard = lock.lock().await;
+// FCM reports tokens that are no longer valid with an UNREGISTERED error code, e.g.:
+// {"error": {"code": 404, "status": "NOT_FOUND", "details": [{"errorCode": "UNREGISTERED", ...}]}}
+fn is_unregistered(body: &str) -> bool {
+    let Ok(body) = serde_json::from_str::<serde_json::Value>(body) else {
+        return false;
+    };
 
-    tokio_fs::create_dir_all(root).await?;
+    body.pointer("/error/details")
+        .and_then(|details| details.as_array())
+        .is_some_and(|details| {
+            details.iter().any(|detail| {
+                detail.get("errorCode").and_then(|c| c.as_str()) == Some("UNREGISTERED")
+            })
+        })
+}
 
-    let tokens_dir = root.join(FCM_TOKENS_DIR);
-    check_path_sandboxed(root, &tokens_dir)?;
-    tokio_fs::create_dir_all(&tokens_dir).await?;
+#[cfg(test)]
+mod tests {
+    use super::FcmSender;
+    use crate::push_token::{
+        load_push_tokens, send_to_all, store_push_token, PushError, PushSender,
+    };
+    use reqwest::Url;
+    use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use rocket::tokio::net::{TcpListener, TcpStream};
+    use secluso_server_backbone::types::PushProvider;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const UNREGISTERED: &str = r#"{"error": {"code": 404, "status": "NOT_FOUND", "details": [{"@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError", "errorCode": "UNREGISTERED"}]}}"#;
+    const INVALID_ARGUMENT: &str = r#"{"error": {"code": 400, "status": "INVALID_ARGUMENT", "details": [{"@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError", "errorCode": "INVALID_ARGUMENT"}]}}"#;
+
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("secluso-fcm-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
 
-    let mut entries = tokio_fs::read_dir(&tokens_dir).await?;
-    while let Some(entry) = entries.next_entry().await? {
-        if !entry.file_type().await?.is_file() {
-            continue;
-        }
+    // Local stand-in for the FCM send endpoint. respond gets the number of the request (from 0)
+    // and its body, and returns the status and body of the response.
+    async fn mock_fcm(
+        respond: fn(usize, &str) -> (u16, &'static str),
+    ) -> (FcmSender, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let send_url = Url::parse(&format!(
+            "http://{}/v1/projects/test/messages:send",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        rocket::tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let body = read_request(&mut stream).await;
+                let (status, reply) = respond(counter.fetch_add(1, Ordering::SeqCst), &body);
+                let response = format!(
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
 
-        let file_name = entry.file_name();
-        let Some(file_name) = file_name.to_str() else {
-            continue;
+        let sender = FcmSender {
+            client: reqwest::Client::new(),
+            send_url,
+            access_token: "test-access-token".to_string(),
+            retry_delay: Duration::from_millis(10),
         };
+        (sender, requests)
+    }
 
-        if !file_name.starts_with(FCM_TOKEN_FILE_PREFIX) {
-            continue;
-        }
-
-        let path = entry.path();
-        check_path_sandboxed(root, &path)?;
-
-        let existing = tokio_fs::read_to_string(&path).await?;
-        if existing.trim() == token {
-            return Ok(());
+    async fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut chunk).await.unwrap_or(0);
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&chunk[..n]);
+
+            let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+            let length = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if request.len() >= end + 4 + length {
+                return String::from_utf8_lossy(&request[end + 4..]).to_string();
+            }
         }
+        String::from_utf8_lossy(&request).to_string()
     }
 
-    for index in 1u64.. {
-        let token_path = tokens_dir.join(format!("{}{}", FCM_TOKEN_FILE_PREFIX, index));
-        check_path_sandboxed(root, &token_path)?;
-
-        let mut file = match tokio_fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(&token_path)
-            .await
-        {
-            Ok(file) => file,
-            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
-            Err(e) => return Err(e),
-        };
+    #[rocket::async_test]
+    async fn sends_a_notification() {
+        let (sender, requests) = mock_fcm(|_, body| {
+            assert!(body.contains("\"token\":\"device-token\""));
+            (200, r#"{"name": "projects/test/messages/1"}"#)
+        })
+        .await;
 
-        file.write_all(token.as_bytes()).await?;
-        file.sync_all().await?;
-        return Ok(());
+        sender.send("device-token", b"hello").await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
     }
 
-    unreachable!()
-}
-
-pub(crate) async fn load_fcm_tokens(root: &Path) -> io::Result<Vec<String>> {
-    let mut tokens = Vec::new();
-    let mut seen = HashSet::new();
-
-    if !root.exists() {
-        return Ok(tokens);
+    #[rocket::async_test]
+    async fn retries_transient_failures() {
+        let (sender, requests) = mock_fcm(|n, _| match n {
+            0 => (503, "{}"),
+            1 => (429, "{}"),
+            _ => (200, "{}"),
+        })
+        .await;
+
+        sender.send("device-token", b"hello").await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
     }
 
-    let legacy_token_path = root.join(LEGACY_FCM_TOKEN_FILE);
-    check_path_sandboxed(root, &legacy_token_path)?;
-    if legacy_token_path.exists() {
-        let token = tokio_fs::read_to_string(&legacy_token_path).await?;
-        let token = token.trim().to_string();
+    #[rocket::async_test]
+    async fn gives_up_after_the_last_attempt() {
+        let (sender, requests) = mock_fcm(|_, _| (500, "{}")).await;
 
-        if !token.is_empty() && seen.insert(token.clone()) {
-            tokens.push(token);
-        }
+        let result = sender.send("device-token", b"hello").await;
+        assert!(matches!(result, Err(PushError::Failed(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
     }
 
-    let tokens_dir = root.join(FCM_TOKENS_DIR);
-    check_path_sandboxed(root, &tokens_dir)?;
+    #[rocket::async_test]
+    async fn does_not_retry_rejected_notifications() {
+        let (sender, requests) = mock_fcm(|_, _| (400, INVALID_ARGUMENT)).await;
 
-    if !tokens_dir.exists() {
-        return Ok(tokens);
+        let result = sender.send("device-token", b"hello").await;
+        assert!(matches!(result, Err(PushError::Failed(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
     }
 
-    let mut entries = tokio_fs::read_dir(&tokens_dir).await?;
-    while let Some(entry) = entries.next_entry().await? {
-        if !entry.file_type().await?.is_file() {
-            continue;
-        }
-
-        let file_name = entry.file_name();
-        let Some(file_name) = file_name.to_str() else {
-            continue;
-        };
-
-        if !file_name.starts_with(FCM_TOKEN_FILE_PREFIX) {
-            continue;
-        }
-
-        let token_path = entry.path();
-        check_path_sandboxed(root, &token_path)?;
-
-        let token = tokio_fs::read_to_string(token_path).await?;
-        let token = token.trim().to_string();
+    #[rocket::async_test]
+    async fn removes_unregistered_tokens() {
+        let root = test_root("unregistered");
+        store_push_token(&root, PushProvider::Fcm, "good-token")
+            .await
+            .unwrap();
+        store_push_token(&root, PushProvider::Fcm, "stale-token")
+            .await
+            .unwrap();
+        std::fs::write(root.join("fcm_token"), b"stale-token").unwrap();
+
+        let (sender, requests) = mock_fcm(|_, body| {
+            if body.contains("stale-token") {
+                (404, UNREGISTERED)
+            } else {
+                (200, "{}")
+            }
+        })
+        .await;
+
+        let tokens = load_push_tokens(&root, PushProvider::Fcm).await.unwrap();
+        assert_eq!(tokens.len(), 2);
+        let fan_out = send_to_all(&sender, &root, &tokens, b"hello").await;
+        assert_eq!(fan_out.sent, 1);
+        assert_eq!(fan_out.unregistered, 1);
+        assert!(fan_out.last_error.is_none());
+        // Unregistered tokens aren't retried.
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        assert_eq!(
+            load_push_tokens(&root, PushProvider::Fcm).await.unwrap(),
+            ["good-token"]
+        );
+        assert!(!root.join("fcm_token").exists());
 
-        if !token.is_empty() && seen.insert(token.clone()) {
-            tokens.push(token);
-        }
+        let _ = std::fs::remove_dir_all(&root);
     }
-
-    Ok(tokens)
-}
\ No newline at end of file
+}
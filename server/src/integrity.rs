@@ -0,0 +1,137 @@
+//! Integrity check of uploads against the SHA-256 digest computed by the client
+//! (X-Content-Sha256), so that an upload truncated on the way is rejected instead of
+//! being stored and failing to decrypt in the app.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub const CONTENT_SHA256_HEADER: &str = "X-Content-Sha256";
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The digest of the body sent by the client, if any (hex encoded).
+/// The header is optional, but a malformed one is rejected with a 400.
+pub struct ContentSha256(pub Option<[u8; 32]>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentSha256 {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one(CONTENT_SHA256_HEADER) {
+            None => Outcome::Success(ContentSha256(None)),
+            Some(value) => match parse_hex_digest(value) {
+                Some(digest) => Outcome::Success(ContentSha256(Some(digest))),
+                None => Outcome::Error((Status::BadRequest, ())),
+            },
+        }
+    }
+}
+
+pub fn parse_hex_digest(value: &str) -> Option<[u8; 32]> {
+    let value = value.trim().as_bytes();
+    if value.len() != 64 {
+        return None;
+    }
+
+    let mut digest = [0u8; 32];
+    for (byte, pair) in digest.iter_mut().zip(value.chunks_exact(2)) {
+        let pair = std::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+
+    Some(digest)
+}
+
+#[derive(Debug)]
+pub struct DigestMismatch;
+
+impl fmt::Display for DigestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Content doesn't match {CONTENT_SHA256_HEADER}")
+    }
+}
+
+impl Error for DigestMismatch {}
+
+/// Whether the upload failed because of a digest mismatch (reported as 422).
+pub fn is_digest_mismatch(e: &io::Error) -> bool {
+    e.get_ref()
+        .is_some_and(|inner| inner.is::<DigestMismatch>())
+}
+
+/// Copies reader to writer like tokio::io::copy, computing the SHA-256 digest of the data.
+pub async fn copy_with_digest<R, W>(reader: &mut R, writer: &mut W) -> io::Result<[u8; 32]>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        writer.write_all(&buf[..n]).await?;
+    }
+    writer.flush().await?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Checks the digest of the received data against the one sent by the client, if any.
+pub fn check_digest(expected: Option<[u8; 32]>, actual: [u8; 32]) -> io::Result<()> {
+    match expected {
+        Some(expected) if expected != actual => {
+            Err(io::Error::new(io::ErrorKind::InvalidData, DigestMismatch))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_digest, copy_with_digest, is_digest_mismatch, parse_hex_digest};
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn hex_digests_are_parsed() {
+        let hex = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let digest = parse_hex_digest(hex).unwrap();
+        assert_eq!(digest[..], Sha256::digest(b"test")[..]);
+        assert_eq!(parse_hex_digest(&hex.to_uppercase()), Some(digest));
+
+        assert_eq!(parse_hex_digest(&hex[..62]), None);
+        assert_eq!(parse_hex_digest(&format!("{hex}00")), None);
+        assert_eq!(parse_hex_digest(&hex.replace('f', "g")), None);
+        assert_eq!(parse_hex_digest(&"é".repeat(32)), None);
+    }
+
+    #[rocket::async_test]
+    async fn copy_computes_the_digest() {
+        let data = vec![7u8; 200 * 1024];
+        let mut written = Vec::new();
+        let digest = copy_with_digest(&mut &data[..], &mut written)
+            .await
+            .unwrap();
+
+        assert_eq!(written, data);
+        assert_eq!(digest[..], Sha256::digest(&data)[..]);
+        assert!(check_digest(Some(digest), digest).is_ok());
+        assert!(check_digest(None, digest).is_ok());
+
+        let e = check_digest(Some([0u8; 32]), digest).unwrap_err();
+        assert!(is_digest_mismatch(&e));
+        assert!(!is_digest_mismatch(&std::io::Error::other("other")));
+    }
+}
@@ -8,48 +8,83 @@
 extern crate rocket;
 
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::io;
 use std::io::ErrorKind;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, UNIX_EPOCH};
 
 use base64::engine::general_purpose::STANDARD as base64_engine;
 use base64::Engine;
 use dashmap::mapref::entry::Entry;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use rocket::config::TlsConfig;
 use rocket::data::{Data, Limits, ToByteUnit};
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::{Header, Status};
 use rocket::request::{FromRequest, Outcome};
 use rocket::response::content::RawText;
 use rocket::response::stream::{Event, EventStream};
-use rocket::response::status::Custom;
 use rocket::serde::json::Json;
 use rocket::tokio;
 use rocket::tokio::fs::{self, File};
 use rocket::tokio::select;
-use rocket::tokio::sync::broadcast::{channel, Sender};
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::sync::broadcast::{channel, Receiver, Sender};
 use rocket::tokio::sync::Mutex as AsyncMutex;
 use rocket::tokio::sync::Notify;
 use rocket::tokio::task;
 use rocket::tokio::time::timeout;
 use rocket::tokio::io::AsyncWriteExt;
 use rocket::{Response, Request, Shutdown};
+use secluso_server_backbone::routes::normalize_base_path;
+use secluso_client_server_lib::auth::{
+    generate_random, parse_user_credentials, NUM_PASSWORD_CHARS, NUM_USERNAME_CHARS,
+};
+use secluso_client_server_lib::limits::{
+    MAX_COMMAND_FILE_SIZE, MAX_LIVESTREAM_FILE_SIZE, MAX_MOTION_FILE_SIZE, MAX_NOTIFICATION_SIZE,
+};
 use secluso_server_backbone::types::{
-    ConfigResponse, GroupTimestamp, MotionPairs, NotificationTarget, PairingRequest,
-    PairingResponse, ServerStatus,
+    AdminUser, ConfigResponse, ErrorResponse, GroupTimestamp, MotionPairs, NotificationTarget,
+    PairingRequest, CameraStatus, PairingResponse, PendingFile, PushProvider, PushToken,
+    ServerDiagnostics, ServerStatus, StatusDetail, UploadEvent,
 };
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
+pub mod apns;
 pub mod auth;
+pub mod config_queue;
+pub mod consume;
+pub mod expiry;
 pub mod fcm;
+pub mod integrity;
 pub mod notification_target;
+pub mod push_token;
+pub mod quota;
+pub mod range;
+pub mod rate_limit;
 pub mod security;
+pub mod self_test;
 
-use self::auth::{initialize_users, BasicAuth, FailStore};
-use self::fcm::{send_notification, store_fcm_token, load_fcm_tokens};
+use self::apns::ApnsSender;
+use self::auth::{initialize_users, AdminAuth, BasicAuth, FailStore, UserStore};
+use self::config_queue::{
+    entry_id, next_entry_path, queued_entries, remove_acknowledged_entry, COMMAND_PREFIX,
+    RESPONSE_PREFIX,
+};
+use self::consume::ConsumedFile;
+use self::expiry::livestream_marker;
+use self::fcm::FcmSender;
+use self::integrity::{check_digest, copy_with_digest, is_digest_mismatch, ContentSha256};
+use self::push_token::{
+    load_push_tokens, providers_to_notify, send_to_all, store_push_token, FanOut,
+};
+use self::quota::{dir_size, StorageQuota};
+use self::range::{RangeHeader, RangedFile};
+use self::rate_limit::{Checks, Notifications, RateLimit, RateLimiting, Uploads};
 use self::security::{check_path_sandboxed, join_validated_child};
 
 // Store the version of the current crate, which we'll use in all responses.
@@ -83,11 +118,194 @@ impl Fairing for ServerVersionHeader {
     }
 }
 
+// Short id of a request, to tie the log lines of a handler (e.g., its debug! lines) to the line
+// that RequestLogger logs for the request.
+#[derive(Clone, Debug)]
+struct RequestId(String);
+
+const REQUEST_ID_LEN: usize = 8;
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+struct RequestStart {
+    id: RequestId,
+    at: Instant,
+}
+
+// Set by RequestLogger when the request comes in.
+fn request_start<'r>(request: &'r Request<'_>) -> &'r RequestStart {
+    request.local_cache(|| RequestStart {
+        id: RequestId(generate_random(REQUEST_ID_LEN, false)),
+        at: Instant::now(),
+    })
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request_start(request).id.clone())
+    }
+}
+
+// Logs every request at info level: its id, method, path, user, camera, status, and duration.
+// Headers (e.g., Authorization) and bodies (e.g., the pairing token) are never logged.
+struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Log requests with correlation ids",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request_start(request);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let start = request_start(request);
+        // Cached by the &BasicAuth request guard.
+        let auth: &BasicAuth = request.local_cache(|| BasicAuth {
+            username: "N/A".to_string(),
+            authenticated: false,
+        });
+        let user = if auth.authenticated {
+            auth.username.as_str()
+        } else {
+            "-"
+        };
+
+        info!(
+            "[{}] {} {} user={} camera={} -> {} in {}ms",
+            start.id,
+            request.method(),
+            request.uri().path(),
+            user,
+            routed_camera(request).unwrap_or("-"),
+            response.status().code,
+            start.at.elapsed().as_millis()
+        );
+    }
+}
+
+// The <camera> parameter of the route that handled the request, if any.
+fn routed_camera<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    let route = request.route()?;
+    let index = route
+        .uri
+        .unmounted()
+        .path()
+        .segments()
+        .position(|segment| segment == "<camera>")?;
+    request.routed_segment(index)
+}
+
+// Sent to the user's subscribers.
+#[derive(Clone, Debug)]
+enum UserEvent {
+    // Something changed in events or on disk (e.g., a livestream chunk): checks look again.
+    Wake,
+    // A motion file was uploaded, with its event id (see UploadLog and events_check).
+    Upload(String, UploadEvent),
+}
+
+// Number of recent uploads kept per user, so that the apps that reconnect to events_check
+// get the ones they missed in between.
+const MAX_RECENT_UPLOADS: usize = 256;
+
+// The recent uploads of a user, numbered from 1 in the order they were sent to the subscribers.
+// The event ids are "{generation}-{number}", so that the ids from before a restart
+// of the server aren't mistaken for new ones.
+struct UploadLog {
+    generation: u128,
+    last_id: u64,
+    recent: VecDeque<(u64, UploadEvent)>,
+}
+
+impl UploadLog {
+    fn new() -> Self {
+        Self {
+            generation: UNIX_EPOCH.elapsed().unwrap_or_default().as_nanos(),
+            last_id: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn event_id(&self, id: u64) -> String {
+        format!("{}-{}", self.generation, id)
+    }
+
+    // The number of an event id of this generation.
+    fn parse_event_id(&self, event_id: &str) -> Option<u64> {
+        let (generation, id) = event_id.trim().split_once('-')?;
+        if generation.parse::<u128>().ok()? != self.generation {
+            return None;
+        }
+        id.parse().ok()
+    }
+}
+
 // Per-user livestream start state
 #[derive(Clone)]
 struct EventState {
-    sender: Sender<()>,
+    sender: Sender<UserEvent>,
     events: Arc<DashMap<String, String>>, // <Camera, Event Msg>
+    livestreams: Arc<DashSet<String>>,    // Cameras with a livestream started and not yet ended
+    uploads: Arc<Mutex<UploadLog>>,
+}
+
+impl EventState {
+    // Numbers the upload and sends it to the subscribers.
+    // The log is locked while sending, so that subscribe_uploads() sees every upload
+    // either in the log or in its receiver.
+    fn record_upload(&self, upload: UploadEvent) {
+        let mut log = self.uploads.lock().unwrap();
+        log.last_id += 1;
+        let id = log.last_id;
+        log.recent.push_back((id, upload.clone()));
+        if log.recent.len() > MAX_RECENT_UPLOADS {
+            log.recent.pop_front();
+        }
+        let _ = self
+            .sender
+            .send(UserEvent::Upload(log.event_id(id), upload));
+    }
+
+    // Subscribes to the events, and returns the uploads sent after last_event_id.
+    // None if some of them aren't in the log anymore (or the server restarted since).
+    fn subscribe_uploads(
+        &self,
+        last_event_id: Option<&str>,
+    ) -> (Receiver<UserEvent>, Option<Vec<(String, UploadEvent)>>) {
+        let log = self.uploads.lock().unwrap();
+        let rx = self.sender.subscribe();
+        let Some(last_event_id) = last_event_id else {
+            return (rx, Some(vec![]));
+        };
+        let Some(last_id) = log.parse_event_id(last_event_id) else {
+            return (rx, None);
+        };
+
+        let oldest_id = log.recent.front().map_or(log.last_id + 1, |(id, _)| *id);
+        if last_id > log.last_id || last_id + 1 < oldest_id {
+            return (rx, None);
+        }
+        let missed = log
+            .recent
+            .iter()
+            .filter(|(id, _)| *id > last_id)
+            .map(|(id, upload)| (log.event_id(*id), upload.clone()))
+            .collect();
+        (rx, Some(missed))
+    }
 }
 
 // Pairing structures
@@ -110,23 +328,32 @@ struct AddAppEntry {
 }
 
 type PairingSessionKey = (String, String);
-type SharedPairingState = Arc<Mutex<HashMap<PairingSessionKey, Arc<Mutex<PairingEntry>>>>>;
+type SharedPairingState = Arc<DashMap<PairingSessionKey, Arc<Mutex<PairingEntry>>>>;
 type AllEventState = Arc<DashMap<String, EventState>>;
 type AddAppKey = (String, String);
 type SharedAddAppState = Arc<DashMap<AddAppKey, Arc<AddAppEntry>>>;
 
 // Simple rate limiters for the server
-const MAX_MOTION_FILE_SIZE: usize = 50; // in mebibytes
+// (the size limits that the clients check too are in secluso_client_server_lib::limits)
 const MAX_NUM_PENDING_MOTION_FILES: usize = 100;
-const MAX_LIVESTREAM_FILE_SIZE: usize = 20; // in mebibytes
 const MAX_NUM_PENDING_LIVESTREAM_FILES: usize = 50;
-const MAX_COMMAND_FILE_SIZE: usize = 100; // in kibibytes
+const MAX_TALKBACK_FILE_SIZE: usize = 1; // in mebibytes
+const MAX_NUM_PENDING_TALKBACK_FILES: usize = 50;
+// Talkback (app-to-camera audio) chunks are kept apart from the livestream chunks
+// so that they don't count towards the camera's pending chunks.
+const TALKBACK_DIR: &str = "talkback";
 const MAX_ADD_APP_REQUEST_SIZE: usize = 100; // in kibibytes
 const MAX_JSON_SIZE: usize = 10; // in kibibytes
+const MAX_LISTED_FILES: usize = 500;
+// Same as MAX_APPS_PER_GROUP in the client library with multi_app_groups.
+const MAX_APPS_PER_CAMERA: u32 = 8;
 #[cfg(not(test))]
 const PAIRING_SESSION_TIMEOUT: Duration = Duration::from_secs(45);
 #[cfg(test)]
 const PAIRING_SESSION_TIMEOUT: Duration = Duration::from_millis(250);
+// Sessions that a user can have waiting for the other side at once.
+const MAX_PAIRING_SESSIONS_PER_USER: usize = 8;
+const MAX_PAIRING_TOKEN_LEN: usize = 128;
 const ADD_APP_REQUEST_TIMEOUT: Duration = Duration::from_secs(45);
 
 async fn get_num_files(path: &Path) -> io::Result<usize> {
@@ -142,6 +369,24 @@ async fn get_num_files(path: &Path) -> io::Result<usize> {
     Ok(num_files)
 }
 
+// Number and total size of the files waiting in path for the app.
+// Unlike get_num_files, this skips our own bookkeeping (hidden) files.
+async fn get_pending_files_usage(path: &Path) -> io::Result<(usize, u64)> {
+    let mut entries = fs::read_dir(path).await?;
+    let mut num_files = 0;
+    let mut num_bytes = 0;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+            num_files += 1;
+            num_bytes += metadata.len();
+        }
+    }
+
+    Ok((num_files, num_bytes))
+}
+
 async fn persist_pair_notification_target(
     auth: &BasicAuth,
     target: &NotificationTarget,
@@ -159,21 +404,40 @@ async fn load_notification_target(
     Ok(targets.into_iter().next())
 }
 
+// Tokens are printable ASCII (without quotes) and short.
+fn is_valid_pairing_token(token: &str) -> bool {
+    !token.is_empty()
+        && token.len() <= MAX_PAIRING_TOKEN_LEN
+        && token.bytes().all(|b| b.is_ascii_graphic() && b != b'"')
+}
+
+// Removes the sessions past the pairing window. A request with the same token then starts
+// a new session (the requests still waiting on a removed session keep their own reference).
+fn remove_expired_pairing_sessions(sessions: &SharedPairingState) {
+    sessions.retain(|_, entry| {
+        entry
+            .lock()
+            .map(|entry| entry.created_at.elapsed() <= PAIRING_SESSION_TIMEOUT)
+            .unwrap_or(false)
+    });
+}
+
 #[post("/pair", data = "<data>")]
 async fn pair(
     data: Json<PairingRequest>,
     state: &rocket::State<SharedPairingState>,
     notification_target_policy: &rocket::State<notification_target::UnifiedPushPolicy>,
     auth: &BasicAuth,
+    req_id: RequestId,
 ) -> Json<PairingResponse> {
     debug!(
-        "[PAIR] Entered pair method with role: {}, token: {}",
-        data.role, data.pairing_token
+        "[PAIR {req_id}] Entered pair method with role: {}",
+        data.role
     );
 
     let role = data.role.to_lowercase();
     if role != "phone" && role != "camera" {
-        debug!("[PAIR] Invalid role: {}", role);
+        debug!("[PAIR {req_id}] Invalid role: {}", role);
         return Json(PairingResponse {
             status: "invalid_role".into(),
             notification_target: None,
@@ -182,38 +446,55 @@ async fn pair(
 
     let token = &data.pairing_token;
 
-    // Check for disallowed quote characters in the token
-    if token.is_empty() || token.contains('"') {
-        debug!("[PAIR] Invalid token (empty or contains quote character: {})", token);
+    if !is_valid_pairing_token(token) {
+        debug!("[PAIR {req_id}] Invalid token (empty, too long, or with disallowed characters)");
         return Json(PairingResponse {
             status: "invalid_token".into(),
             notification_target: None,
         });
     }
 
+    // Reclaim the sessions of tokens that were never used by the other side.
+    remove_expired_pairing_sessions(state);
+
     let session_key = (auth.username.clone(), token.clone());
-    let entry_arc = {
-        let mut sessions = state.lock().unwrap();
-        debug!(
-            "[PAIR] Looking up or creating session for user: {}, token: {}",
-            auth.username, token
-        );
-        sessions
-            .entry(session_key)
-            .or_insert_with(|| {
-                debug!("[PAIR] No existing session found. Creating new entry.");
-                Arc::new(Mutex::new(PairingEntry {
-                    phone_connected: false,
-                    camera_connected: false,
-                    phone_notified: false,
-                    camera_notified: false,
+    debug!(
+        "[PAIR {req_id}] Looking up or creating session for user: {}",
+        auth.username
+    );
+    let existing = state.get(&session_key).map(|entry| entry.value().clone());
+    let entry_arc = match existing {
+        Some(entry) => entry,
+        None => {
+            let num_sessions = state
+                .iter()
+                .filter(|entry| entry.key().0 == auth.username)
+                .count();
+            if num_sessions >= MAX_PAIRING_SESSIONS_PER_USER {
+                debug!("[PAIR {req_id}] Too many pairing sessions ({num_sessions})");
+                return Json(PairingResponse {
+                    status: "busy".into(),
                     notification_target: None,
-                    created_at: Instant::now(),
-                    notify: Arc::new(Notify::new()),
-                    expired: false,
-                }))
-            })
-            .clone()
+                });
+            }
+
+            state
+                .entry(session_key)
+                .or_insert_with(|| {
+                    debug!("[PAIR {req_id}] No existing session found. Creating new entry.");
+                    Arc::new(Mutex::new(PairingEntry {
+                        phone_connected: false,
+                        camera_connected: false,
+                        phone_notified: false,
+                        camera_notified: false,
+                        notification_target: None,
+                        created_at: Instant::now(),
+                        notify: Arc::new(Notify::new()),
+                        expired: false,
+                    }))
+                })
+                .clone()
+        }
     };
 
     let notify;
@@ -223,7 +504,7 @@ async fn pair(
         let mut entry = entry_arc.lock().unwrap();
 
         if entry.expired {
-            debug!("[PAIR] Session already expired for token: {}", token);
+            debug!("[PAIR {req_id}] Session already expired");
             return Json(PairingResponse {
                 status: "expired".into(),
                 notification_target: entry.notification_target.clone(),
@@ -232,12 +513,12 @@ async fn pair(
 
         let elapsed = entry.created_at.elapsed();
         debug!(
-            "[PAIR] Elapsed: {:?}, phone_notified: {}, camera_notified: {}",
+            "[PAIR {req_id}] Elapsed: {:?}, phone_notified: {}, camera_notified: {}",
             elapsed, entry.phone_notified, entry.camera_notified
         );
 
         if elapsed > PAIRING_SESSION_TIMEOUT || entry.phone_notified || entry.camera_notified {
-            debug!("[PAIR] Expiring session due to timeout or notification flag");
+            debug!("[PAIR {req_id}] Expiring session due to timeout or notification flag");
             entry.expired = true;
             return Json(PairingResponse {
                 status: "expired".into(),
@@ -247,7 +528,7 @@ async fn pair(
 
         match role.as_str() {
             "phone" => {
-                debug!("[PAIR] Phone connected");
+                debug!("[PAIR {req_id}] Phone connected");
                 entry.phone_connected = true;
                 entry.notification_target = data.notification_target.clone().and_then(|target| {
                     if let Err(err) = notification_target::validate_notification_target(
@@ -262,14 +543,14 @@ async fn pair(
                 });
             }
             "camera" => {
-                debug!("[PAIR] Camera connected");
+                debug!("[PAIR {req_id}] Camera connected");
                 entry.camera_connected = true;
             }
             _ => unreachable!(),
         }
 
         debug!(
-            "[PAIR] phone_connected: {}, camera_connected: {}",
+            "[PAIR {req_id}] phone_connected: {}, camera_connected: {}",
             entry.phone_connected, entry.camera_connected
         );
 
@@ -280,7 +561,7 @@ async fn pair(
         };
 
         if entry.phone_connected && entry.camera_connected {
-            debug!("[PAIR] Both parties connected, returning 'paired'");
+            debug!("[PAIR {req_id}] Both parties connected, returning 'paired'");
             entry.notify.notify_waiters();
             match role.as_str() {
                 "phone" => entry.phone_notified = true,
@@ -297,17 +578,17 @@ async fn pair(
         notify = entry.notify.clone();
         expired_at = entry.created_at + PAIRING_SESSION_TIMEOUT;
         debug!(
-            "[PAIR] Only one side connected, waiting until {:?}",
+            "[PAIR {req_id}] Only one side connected, waiting until {:?}",
             expired_at
         );
     }
 
     if let Some(target) = target_to_persist.as_ref() {
         if let Err(e) = persist_pair_notification_target(&auth, target).await {
-            error!("[PAIR] Failed to persist notification target from pair payload: {e}");
+            error!("[PAIR {req_id}] Failed to persist notification target from pair payload: {e}");
         } else {
             debug!(
-                "[PAIR] Persisted notification target from pair payload (platform={})",
+                "[PAIR {req_id}] Persisted notification target from pair payload (platform={})",
                 target.platform
             );
         }
@@ -315,7 +596,7 @@ async fn pair(
 
     let wait_duration = expired_at.saturating_duration_since(Instant::now());
     debug!(
-        "[PAIR] Awaiting notify or timeout for up to {:?}",
+        "[PAIR {req_id}] Awaiting notify or timeout for up to {:?}",
         wait_duration
     );
     let _ = timeout(wait_duration, notify.notified()).await;
@@ -324,7 +605,7 @@ async fn pair(
     let still_valid = entry.phone_connected && entry.camera_connected;
 
     if still_valid {
-        debug!("[PAIR] Notify wait completed: still valid. Returning paired response");
+        debug!("[PAIR {req_id}] Notify wait completed: still valid. Returning paired response");
         match role.as_str() {
             "phone" => entry.phone_notified = true,
             "camera" => entry.camera_notified = true,
@@ -336,7 +617,7 @@ async fn pair(
             notification_target: entry.notification_target.clone(),
         })
     } else {
-        debug!("[PAIR] Notify wait completed: pairing expired.");
+        debug!("[PAIR {req_id}] Notify wait completed: pairing expired.");
         entry.expired = true;
         match role.as_str() {
             "phone" => entry.phone_notified = true,
@@ -357,10 +638,46 @@ async fn upload(
     counter: u32,
     data: Data<'_>,
     auth: &BasicAuth,
+    content_sha256: ContentSha256,
+    _rate_limit: RateLimit<Uploads>,
+    quota: &rocket::State<StorageQuota>,
+    all_state: &rocket::State<AllEventState>,
+) -> Result<String, ErrorResponse> {
+    let response = store_motion_file(
+        camera,
+        filename,
+        counter,
+        data,
+        auth,
+        content_sha256.0,
+        quota,
+    )
+    .await
+    .map_err(storage_error)?;
+
+    let user_state = get_user_state(all_state.inner().clone(), &auth.username);
+    user_state.record_upload(UploadEvent {
+        camera: camera.to_string(),
+        filename: filename.to_string(),
+    });
+
+    Ok(response)
+}
+
+async fn store_motion_file(
+    camera: &str,
+    filename: &str,
+    counter: u32,
+    data: Data<'_>,
+    auth: &BasicAuth,
+    expected_digest: Option<[u8; 32]>,
+    quota: &StorageQuota,
 ) -> io::Result<String> {
-    // Validate counter (must be 1 or 2)
-    if counter == 0 || counter > 2 {
-        return Err(io::Error::other("counter must be 1 or 2"));
+    // Validate counter (number of apps that will retrieve the file)
+    if counter == 0 || counter > MAX_APPS_PER_CAMERA {
+        return Err(io::Error::other(format!(
+            "counter must be between 1 and {MAX_APPS_PER_CAMERA}"
+        )));
     }
 
     let root = Path::new("data").join(&auth.username);
@@ -375,6 +692,7 @@ async fn upload(
     if num_pending_files > MAX_NUM_PENDING_MOTION_FILES {
         return Err(io::Error::other("Error: Reached max motion pending limit."));
     }
+    quota.check(&auth.username, &root).await?;
 
     let filepath = camera_path.join(filename);
     check_path_sandboxed(&root, &filepath)?;
@@ -389,9 +707,11 @@ async fn upload(
     check_path_sandboxed(&root, &refcount_tmp_path)?;
 
     let mut file = fs::File::create(&filepath_tmp).await?;
-    let mut stream = data.open(MAX_MOTION_FILE_SIZE.mebibytes());
-    tokio::io::copy(&mut stream, &mut file).await?;
+    let mut stream = data.open(MAX_MOTION_FILE_SIZE.bytes());
+    let digest = copy_with_digest(&mut stream, &mut file).await?;
+    discard_if_mismatch(&filepath_tmp, expected_digest, digest).await?;
     file.sync_all().await?;
+    reserve_or_remove(quota, &auth.username, &root, &filepath, &filepath_tmp).await?;
 
     // We write to a temp file first and then rename to avoid a race with the retrieve operation.
     fs::rename(&filepath_tmp, &filepath).await?;
@@ -407,8 +727,69 @@ async fn upload(
     Ok("ok".to_string())
 }
 
+// Removes the uploaded filepath_tmp if its digest doesn't match the one sent by the client.
+async fn discard_if_mismatch(
+    filepath_tmp: &Path,
+    expected_digest: Option<[u8; 32]>,
+    digest: [u8; 32],
+) -> io::Result<()> {
+    if let Err(e) = check_digest(expected_digest, digest) {
+        let _ = fs::remove_file(filepath_tmp).await;
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+// Errors are sent as JSON (ErrorResponse), with the status in code and the specifics in detail.
+fn error_response(status: Status, detail: impl Into<String>) -> ErrorResponse {
+    ErrorResponse::from(status).with_detail(detail)
+}
+
+// Also covers the requests that no route handles, the failed guards, and the routes returning None.
+#[catch(default)]
+fn default_catcher(status: Status, _req: &Request) -> ErrorResponse {
+    ErrorResponse::from(status)
+}
+
+// Quota errors are reported as 413 so that clients can tell them apart from server failures,
+// and corrupted uploads as 422 so that they're sent again.
+fn storage_error(e: io::Error) -> ErrorResponse {
+    if e.kind() == ErrorKind::StorageFull {
+        error_response(Status::PayloadTooLarge, e.to_string())
+    } else if is_digest_mismatch(&e) {
+        error_response(Status::UnprocessableEntity, e.to_string())
+    } else {
+        internal_error(e)
+    }
+}
+
+// Accounts for filepath_tmp replacing filepath in the user's quota.
+// If that puts the user over the quota, filepath_tmp is removed.
+async fn reserve_or_remove(
+    quota: &StorageQuota,
+    username: &str,
+    root: &Path,
+    filepath: &Path,
+    filepath_tmp: &Path,
+) -> io::Result<()> {
+    let new_size = fs::metadata(filepath_tmp).await?.len();
+    let old_size = fs::metadata(filepath).await.map(|m| m.len()).unwrap_or(0);
+
+    if let Err(e) = quota.reserve(username, root, old_size, new_size).await {
+        let _ = fs::remove_file(filepath_tmp).await;
+        return Err(e);
+    }
+
+    Ok(())
+}
+
 #[post("/bulkCheck", format = "application/json", data = "<data>")]
-async fn bulk_group_check(data: Json<MotionPairs>, auth: &BasicAuth) -> Json<Vec<GroupTimestamp>> {
+async fn bulk_group_check(
+    data: Json<MotionPairs>,
+    auth: &BasicAuth,
+    _rate_limit: RateLimit<Checks>,
+) -> Json<Vec<GroupTimestamp>> {
     let root = Path::new("data").join(&auth.username);
     let pairs_wrapper: MotionPairs = data.into_inner();
     let pair_list = pairs_wrapper.group_names;
@@ -427,25 +808,38 @@ async fn bulk_group_check(data: Json<MotionPairs>, auth: &BasicAuth) -> Json<Vec
             continue;
         }
 
-        let filepath = camera_path.join(epoch_to_check.to_string());
-        if check_path_sandboxed(&root, &filepath).is_err() {
-            continue;
-        }
-
-        if let Ok(true) = Path::try_exists(&filepath) {
-            if let Ok(meta) = fs::metadata(&filepath).await {
-                let ts = meta
-                    .created()
-                    .or_else(|_| meta.modified())
-                    .and_then(|t| t.duration_since(UNIX_EPOCH).map_err(std::io::Error::other))
-                    .map(|d| d.as_secs() as i64)
-                    .unwrap_or(0);
+        // The prefixed name first, then the epoch alone (as uploaded by the cameras).
+        let filenames = [
+            format!("{}{}", pair.kind.prefix(), epoch_to_check),
+            epoch_to_check.to_string(),
+        ];
+        for filename in filenames {
+            let filepath = camera_path.join(&filename);
+            if check_path_sandboxed(&root, &filepath).is_err() {
+                continue;
+            }
 
-                results.push(GroupTimestamp {
-                    group_name,
-                    timestamp: ts,
-                });
+            let Ok(meta) = fs::metadata(&filepath).await else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
             }
+
+            let ts = meta
+                .created()
+                .or_else(|_| meta.modified())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).map_err(std::io::Error::other))
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+
+            results.push(GroupTimestamp {
+                group_name,
+                timestamp: ts,
+                size_bytes: meta.len(),
+                filename,
+            });
+            break;
         }
     }
 
@@ -453,7 +847,12 @@ async fn bulk_group_check(data: Json<MotionPairs>, auth: &BasicAuth) -> Json<Vec
 }
 
 #[get("/<camera>/<filename>")]
-async fn retrieve(camera: &str, filename: &str, auth: &BasicAuth) -> Option<RawText<File>> {
+async fn retrieve(
+    camera: &str,
+    filename: &str,
+    range: RangeHeader,
+    auth: &BasicAuth,
+) -> Option<RangedFile> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera").ok()?;
     if check_path_sandboxed(&root, &camera_path).is_err() {
@@ -465,7 +864,103 @@ async fn retrieve(camera: &str, filename: &str, auth: &BasicAuth) -> Option<RawT
         return None;
     }
 
-    File::open(filepath).await.map(RawText).ok()
+    RangedFile::open(&filepath, &range).await.ok()
+}
+
+// ?offset=N&limit=N of /list. At most MAX_LISTED_FILES files are listed per request.
+struct ListPage {
+    offset: usize,
+    limit: usize,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ListPage {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let offset = match req.query_value::<usize>("offset") {
+            None => 0,
+            Some(Ok(offset)) => offset,
+            Some(Err(_)) => {
+                return Outcome::Error((Status::BadRequest, "Invalid offset".to_string()))
+            }
+        };
+        let limit = match req.query_value::<usize>("limit") {
+            None => MAX_LISTED_FILES,
+            Some(Ok(limit)) => limit.min(MAX_LISTED_FILES),
+            Some(Err(_)) => {
+                return Outcome::Error((Status::BadRequest, "Invalid limit".to_string()))
+            }
+        };
+
+        Outcome::Success(ListPage { offset, limit })
+    }
+}
+
+// Trailing number of a filename (its epoch), e.g., 12 for "12" or "encVideo12".
+fn epoch_suffix(filename: &str) -> Option<u64> {
+    let digits = filename.len()
+        - filename
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .len();
+    filename[filename.len() - digits..].parse().ok()
+}
+
+/// Lists the files waiting for the app in a camera directory, so that the app doesn't need to guess their names.
+/// Files with a numeric suffix come first, in order of that number, followed by the rest by name.
+/// Our own bookkeeping files and uploads in progress are not listed.
+#[get("/list/<camera>")]
+async fn list_files(
+    camera: &str,
+    page: ListPage,
+    auth: &BasicAuth,
+    _rate_limit: RateLimit<Checks>,
+) -> Result<Json<Vec<PendingFile>>, ErrorResponse> {
+    let root = Path::new("data").join(&auth.username);
+    let camera_path = join_validated_child(&root, camera, "camera")?;
+    if !camera_path.exists() {
+        return Ok(Json(Vec::new()));
+    }
+    check_path_sandboxed(&root, &camera_path)?;
+
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(&camera_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        if filename.starts_with('.') || filename.ends_with("_tmp") {
+            continue;
+        }
+
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata
+            .modified()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).map_err(std::io::Error::other))
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        files.push(PendingFile {
+            filename,
+            size: metadata.len(),
+            modified,
+        });
+    }
+
+    files.sort_by(|a, b| {
+        let a_epoch = epoch_suffix(&a.filename);
+        let b_epoch = epoch_suffix(&b.filename);
+        (a_epoch.is_none(), a_epoch, &a.filename).cmp(&(b_epoch.is_none(), b_epoch, &b.filename))
+    });
+
+    Ok(Json(
+        files
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit)
+            .collect(),
+    ))
 }
 
 static FILE_LOCKS: Lazy<AsyncMutex<HashMap<String, Arc<AsyncMutex<()>>>>> =
@@ -485,7 +980,12 @@ async fn remove_file_lock(camera: &str) {
 }
 
 #[delete("/<camera>/<filename>")]
-async fn delete_file(camera: &str, filename: &str, auth: &BasicAuth) -> Option<()> {
+async fn delete_file(
+    camera: &str,
+    filename: &str,
+    auth: &BasicAuth,
+    quota: &rocket::State<StorageQuota>,
+) -> Option<()> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera").ok()?;
 
@@ -503,9 +1003,29 @@ async fn delete_file(camera: &str, filename: &str, auth: &BasicAuth) -> Option<(
         return None;
     }
 
+    release_file(
+        auth.username.clone(),
+        camera.to_string(),
+        filepath,
+        refcount_path,
+        quota.inner().clone(),
+    )
+    .await
+    .ok()
+}
+
+// Drops one reference to filepath, and deletes it once no reference is left.
+// The paths must have been sandbox-checked by the caller.
+async fn release_file(
+    username: String,
+    camera: String,
+    filepath: PathBuf,
+    refcount_path: PathBuf,
+    quota: StorageQuota,
+) -> io::Result<()> {
     // Two concurrent delete calls could race and we'll end
     // up not deleting the file. That's why we need this lock.
-    let file_lock = get_file_lock(camera.to_string()).await;
+    let file_lock = get_file_lock(camera).await;
     let _guard = file_lock.lock().await;
 
     // Read refcount (default = 1 if missing)
@@ -517,47 +1037,93 @@ async fn delete_file(camera: &str, filename: &str, auth: &BasicAuth) -> Option<(
             .filter(|v| *v >= 1)
             .unwrap_or(1),
         Err(e) if e.kind() == ErrorKind::NotFound => 1,
-        Err(_) => return None,
+        Err(e) => return Err(e),
     };
 
     if refcount > 1 {
         let new_refcount = refcount - 1;
-        if fs::write(&refcount_path, new_refcount.to_string())
-            .await
-            .is_err()
-        {
-            return None;
-        }
+        fs::write(&refcount_path, new_refcount.to_string()).await?;
     } else {
         // Delete actual file
-        if fs::remove_file(&filepath).await.is_err() {
-            return None;
-        }
+        let size = fs::metadata(&filepath).await.map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&filepath).await?;
+        quota.release(&username, size);
 
         // Best-effort remove refcount file
         match fs::remove_file(&refcount_path).await {
             Ok(_) => {}
             Err(e) if e.kind() == ErrorKind::NotFound => {}
-            Err(_) => return None,
+            Err(e) => return Err(e),
         }
     }
 
-    Some(())
+    Ok(())
+}
+
+/// Retrieves a file and deletes it (like delete_file) once it has been sent in full.
+/// If the download is interrupted, the file is kept for another attempt.
+#[post("/consume/<camera>/<filename>")]
+async fn consume_file(
+    camera: &str,
+    filename: &str,
+    auth: &BasicAuth,
+    quota: &rocket::State<StorageQuota>,
+) -> Option<ConsumedFile> {
+    let root = Path::new("data").join(&auth.username);
+    let camera_path = join_validated_child(&root, camera, "camera").ok()?;
+    if check_path_sandboxed(&root, &camera_path).is_err() {
+        return None;
+    }
+
+    let filepath = camera_path.join(filename);
+    if check_path_sandboxed(&root, &filepath).is_err() {
+        return None;
+    }
+
+    let refcount_path = camera_path.join(format!(".{}.refcount", filename));
+    if check_path_sandboxed(&root, &refcount_path).is_err() {
+        return None;
+    }
+
+    let file = File::open(&filepath).await.ok()?;
+    let release = release_file(
+        auth.username.clone(),
+        camera.to_string(),
+        filepath,
+        refcount_path,
+        quota.inner().clone(),
+    );
+    ConsumedFile::new(file, Box::pin(release)).await.ok()
 }
 
 #[delete("/<camera>")]
-async fn delete_camera(camera: &str, auth: &BasicAuth) -> io::Result<()> {
+async fn delete_camera(
+    camera: &str,
+    auth: &BasicAuth,
+    quota: &rocket::State<StorageQuota>,
+) -> Result<(), ErrorResponse> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera")?;
     check_path_sandboxed(&root, &camera_path)?;
 
     remove_file_lock(camera).await;
 
-    fs::remove_dir_all(camera_path).await
+    let camera_path_clone = camera_path.clone();
+    let size = task::spawn_blocking(move || dir_size(&camera_path_clone))
+        .await
+        .map_err(io::Error::other)??;
+    fs::remove_dir_all(camera_path).await?;
+    quota.release(&auth.username, size);
+
+    let marker_path = livestream_marker(&root, camera);
+    check_path_sandboxed(&root, &marker_path)?;
+    fs::remove_file(marker_path).await.ok();
+
+    Ok(())
 }
 
 #[post("/fcm_token", data = "<data>")]
-async fn upload_fcm_token(data: Data<'_>, auth: &BasicAuth) -> io::Result<String> {
+async fn upload_fcm_token(data: Data<'_>, auth: &BasicAuth) -> Result<String, ErrorResponse> {
     let root = Path::new("data").join(&auth.username);
     
     let token_bytes = data.open(5.kibibytes()).into_bytes().await?;
@@ -566,13 +1132,40 @@ async fn upload_fcm_token(data: Data<'_>, auth: &BasicAuth) -> io::Result<String
     let token = token.trim();
 
     if token.is_empty() {
+        return Err(
+            io::Error::new(io::ErrorKind::InvalidInput, "Error: FCM token is empty.").into(),
+        );
+    }
+
+    store_push_token(&root, PushProvider::Fcm, token).await?;
+
+    Ok("ok".to_string())
+}
+
+/// Like /fcm_token, for the push provider of the app's choosing.
+#[post("/push_token", format = "json", data = "<data>")]
+async fn upload_push_token(
+    data: Json<PushToken>,
+    auth: &BasicAuth,
+) -> Result<String, ErrorResponse> {
+    let root = Path::new("data").join(&auth.username);
+    let PushToken { provider, token } = data.into_inner();
+    let token = token.trim();
+
+    if token.is_empty() || token.len() > 4096 {
+        return Err(
+            io::Error::new(io::ErrorKind::InvalidInput, "Error: Invalid push token.").into(),
+        );
+    }
+    if provider == PushProvider::Apns && !apns::is_valid_device_token(token) {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
-            "Error: FCM token is empty.",
-        ));
+            "Error: Invalid APNs device token.",
+        )
+        .into());
     }
 
-    store_fcm_token(&root, token).await?;
+    store_push_token(&root, provider, token).await?;
 
     Ok("ok".to_string())
 }
@@ -582,7 +1175,7 @@ async fn upload_notification_target(
     data: Json<NotificationTarget>,
     notification_target_policy: &rocket::State<notification_target::UnifiedPushPolicy>,
     auth: &BasicAuth,
-) -> io::Result<String> {
+) -> Result<String, ErrorResponse> {
     let target = data.into_inner();
     notification_target::validate_notification_target(notification_target_policy.inner(), &target)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
@@ -605,18 +1198,45 @@ async fn retrieve_notification_target(
     Some(Json(parsed))
 }
 
+// The push providers that the server can send notifications through.
+fn supported_push_providers(
+    fcm_config: &Option<ConfigResponse>,
+    apns: &Option<ApnsSender>,
+) -> Vec<PushProvider> {
+    let mut providers = Vec::new();
+    if fcm_config.is_some() {
+        providers.push(PushProvider::Fcm);
+    }
+    if apns.is_some() {
+        providers.push(PushProvider::Apns);
+    }
+    providers
+}
+
+/// Sends the notification to the user's devices, through FCM and/or APNs depending on the tokens
+/// that they registered.
+/// Fails with 410 if the providers say that all the user's push tokens are unregistered (they're
+/// then removed, so that the app registers a new one), or with 502 if the notification couldn't be
+/// sent otherwise.
 #[post("/fcm_notification", data = "<data>")]
 async fn send_fcm_notification(
     data: Data<'_>,
     notification_target_policy: &rocket::State<notification_target::UnifiedPushPolicy>,
     fcm_config: &rocket::State<Option<ConfigResponse>>,
+    apns: &rocket::State<Option<ApnsSender>>,
     auth: &BasicAuth,
-) -> io::Result<String> {
+    _rate_limit: RateLimit<Notifications>,
+) -> Result<String, ErrorResponse> {
     let root = Path::new("data").join(&auth.username);
     let notification_targets =
         notification_target::load_notification_targets(&root, notification_target_policy.inner())
-            .await?;
-    let notification_msg = data.open(8.kibibytes()).into_bytes().await?;
+            .await
+            .map_err(internal_error)?;
+    let notification_msg = data
+        .open(MAX_NOTIFICATION_SIZE.bytes())
+        .into_bytes()
+        .await
+        .map_err(internal_error)?;
 
     let mut attempted_notification_target = false;
     // FIXME: caller won't know if the notification failed to send
@@ -676,39 +1296,70 @@ async fn send_fcm_notification(
         );
     }
 
-    if fcm_config.inner().is_none() {
-        debug!("Skipping FCM notification; server has no FCM configuration");
+    let supported = supported_push_providers(fcm_config.inner(), apns.inner());
+    if supported.is_empty() {
+        debug!("Skipping push notification; server has no FCM or APNs configuration");
         return Ok("ok".to_string());
     }
 
-    let tokens = load_fcm_tokens(&root).await?;
-    if tokens.is_empty() {
-        return Err(io::Error::other("Error: FCM token not available."));
+    let mut tokens = HashMap::new();
+    for provider in [PushProvider::Fcm, PushProvider::Apns] {
+        let provider_tokens = load_push_tokens(&root, provider)
+            .await
+            .map_err(internal_error)?;
+        if !provider_tokens.is_empty() {
+            tokens.insert(provider, provider_tokens);
+        }
+    }
+    let with_tokens: Vec<PushProvider> = tokens.keys().copied().collect();
+    let providers = providers_to_notify(&supported, &with_tokens);
+    if providers.is_empty() {
+        return Err(internal_error("Error: Push token not available."));
     }
 
-    let notification_payload = notification_msg.to_vec();
-
-    task::block_in_place(|| {
-        // FIXME: caller won't know if the notification failed to send
-        let mut success_count: usize = 0;
-
-        for token in tokens {
-            match send_notification(token, notification_payload.clone()) {
-                Ok(_) => {
-                    success_count += 1;
-                    debug!("Notification sent successfully.");
-                }
+    let mut fan_out = FanOut::default();
+    for provider in providers {
+        let provider_tokens = &tokens[&provider];
+        let provider_fan_out = match provider {
+            PushProvider::Fcm => match FcmSender::from_service_account().await {
+                Ok(sender) => send_to_all(&sender, &root, provider_tokens, &notification_msg).await,
                 Err(e) => {
-                    debug!("Failed to send notification: {}", e);
+                    error!("Failed to set up FCM: {e:#}");
+                    FanOut {
+                        attempted: provider_tokens.len(),
+                        last_error: Some(format!("Failed to set up FCM: {e}")),
+                        ..FanOut::default()
+                    }
                 }
+            },
+            PushProvider::Apns => {
+                let Some(sender) = apns.inner() else {
+                    continue;
+                };
+                send_to_all(sender, &root, provider_tokens, &notification_msg).await
             }
-        }
-
-        debug!("FCM notification fan-out completed: {success_count} successful sends.");
-    });
-
+        };
+        debug!(
+            "{provider:?} notification fan-out completed: {} successful sends.",
+            provider_fan_out.sent
+        );
+        fan_out.merge(provider_fan_out);
+    }
 
-    Ok("ok".to_string())
+    if fan_out.sent > 0 {
+        Ok("ok".to_string())
+    } else if fan_out.unregistered == fan_out.attempted {
+        Err(error_response(
+            Status::Gone,
+            "Error: Push token unregistered. The app needs to register a new one.",
+        ))
+    } else {
+        let reason = fan_out.last_error.unwrap_or_default();
+        Err(error_response(
+            Status::BadGateway,
+            format!("Error: Failed to send push notification. {reason}"),
+        ))
+    }
 }
 
 fn get_user_state(all_state: AllEventState, username: &str) -> EventState {
@@ -719,7 +1370,9 @@ fn get_user_state(all_state: AllEventState, username: &str) -> EventState {
             let (tx, _) = channel(1024);
             let user_state = EventState {
                 events: Arc::new(DashMap::new()),
+                livestreams: Arc::new(DashSet::new()),
                 sender: tx,
+                uploads: Arc::new(Mutex::new(UploadLog::new())),
             };
             entry.insert(user_state.clone());
             user_state
@@ -727,12 +1380,36 @@ fn get_user_state(all_state: AllEventState, username: &str) -> EventState {
     }
 }
 
+// Newest livestream chunk format that the app decodes (see livestream_chunk in the client library).
+// Passed on to the camera as is: the chunks are end-to-end encrypted, so we don't look at them.
+const LIVESTREAM_CHUNK_FORMAT_HEADER: &str = "X-Livestream-Chunk-Format";
+
+// Missing for older apps, which decode the legacy format only.
+struct LivestreamChunkFormat(Option<u8>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LivestreamChunkFormat {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one(LIVESTREAM_CHUNK_FORMAT_HEADER) {
+            None => Outcome::Success(LivestreamChunkFormat(None)),
+            Some(value) => match value.trim().parse::<u8>() {
+                Ok(format) => Outcome::Success(LivestreamChunkFormat(Some(format))),
+                Err(_) => Outcome::Error((Status::BadRequest, ())),
+            },
+        }
+    }
+}
+
 #[post("/livestream/<camera>")]
 async fn livestream_start(
     camera: &str,
     auth: &BasicAuth,
+    chunk_format: LivestreamChunkFormat,
     all_state: &rocket::State<AllEventState>,
-) -> io::Result<()> {
+    quota: &rocket::State<StorageQuota>,
+) -> Result<(), ErrorResponse> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera")?;
     check_path_sandboxed(&root, &camera_path)?;
@@ -745,9 +1422,7 @@ async fn livestream_start(
     check_path_sandboxed(&root, &update_path)?;
 
     if update_path.exists() {
-        return Err(io::Error::other(
-            "Error: Previous update has not been retrieved yet.",
-        ));
+        return Err(io::Error::other("Error: Previous update has not been retrieved yet.").into());
     }
 
     let livestream_end_path = Path::new(&camera_path).join("livestream_end");
@@ -757,11 +1432,29 @@ async fn livestream_start(
         fs::remove_file(livestream_end_path).await.ok();
     }
 
+    // Lets the expiry task tell this camera's livestream directory apart from motion directories.
+    let marker_path = livestream_marker(&root, camera);
+    check_path_sandboxed(&root, &marker_path)?;
+    File::create(marker_path).await?;
+
+    // Talkback chunks from a previous session are encrypted for an older epoch.
+    let talkback_path = camera_path.join(TALKBACK_DIR);
+    check_path_sandboxed(&root, &talkback_path)?;
+    if talkback_path.exists() {
+        fs::remove_dir_all(&talkback_path).await.ok();
+        quota.invalidate(&auth.username);
+    }
+
     let user_state = get_user_state(all_state.inner().clone(), &auth.username);
 
-    let epoch = "placeholder".to_string();
+    // Older cameras only check that there's an event.
+    let epoch = match chunk_format.0 {
+        Some(format) => format.to_string(),
+        None => "placeholder".to_string(),
+    };
     user_state.events.insert(camera.to_string(), epoch);
-    let _ = user_state.sender.send(());
+    user_state.livestreams.insert(camera.to_string());
+    let _ = user_state.sender.send(UserEvent::Wake);
 
     Ok(())
 }
@@ -770,10 +1463,14 @@ async fn livestream_start(
 async fn livestream_check(
     camera: &str,
     auth: &BasicAuth,
+    _rate_limit: RateLimit<Checks>,
     all_state: &rocket::State<AllEventState>,
+    quota: &rocket::State<StorageQuota>,
     mut end: Shutdown,
 ) -> EventStream![] {
     let camera = camera.to_string();
+    let username = auth.username.clone();
+    let quota = quota.inner().clone();
 
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, &camera, "camera");
@@ -798,16 +1495,17 @@ async fn livestream_check(
         loop {
             if let Some((_key, epoch)) = user_state.events.remove(&camera) {
                 // wipe all the data from the previous stream (if any)
-                // FIXME: error is ignored here and other uses of ok()
-                fs::remove_dir_all(&camera_path).await.ok();
-                fs::create_dir_all(&camera_path).await.ok();
+                if let Err(e) = wipe_livestream_dir(camera_path).await {
+                    error!("Failed to wipe previous livestream data of {camera}: {e}");
+                }
+                quota.invalidate(&username);
                 yield Event::data(epoch.to_string());
                 return;
             }
 
             select! {
                 msg = rx.recv() => match msg {
-                    Ok(()) => {},
+                    Ok(_) => {},
                     Err(_) => break,
                 },
                 _ = &mut end => break,
@@ -816,13 +1514,172 @@ async fn livestream_check(
     }
 }
 
+// SSE event names of events_check.
+const UPLOAD_EVENT: &str = "upload";
+// Some uploads were missed because the subscriber fell behind: the app should use bulkCheck.
+const LAGGED_EVENT: &str = "lagged";
+
+// Id of the last event received by the client, sent when it reconnects to events_check (or
+// to config_check, to acknowledge the last command).
+struct LastEventId(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(LastEventId(
+            req.headers().get_one("Last-Event-ID").map(str::to_string),
+        ))
+    }
+}
+
+/// Streams an event for every file uploaded to any camera of the user (see UploadEvent),
+/// so that the apps that don't use push notifications don't need to poll bulkCheck.
+/// Push notifications are sent as before.
+/// An app that reconnects with the id of the last event it got (Last-Event-ID) first gets
+/// the uploads it missed in between, or a lagged event if the server doesn't have them anymore.
+#[get("/events")]
+async fn events_check(
+    auth: &BasicAuth,
+    last_event_id: LastEventId,
+    _rate_limit: RateLimit<Checks>,
+    all_state: &rocket::State<AllEventState>,
+    mut end: Shutdown,
+) -> EventStream![] {
+    let user_state = get_user_state(all_state.inner().clone(), &auth.username);
+    let (mut rx, missed) = user_state.subscribe_uploads(last_event_id.0.as_deref());
+
+    EventStream! {
+        match missed {
+            Some(missed) => {
+                for (id, upload) in missed {
+                    yield Event::json(&upload).event(UPLOAD_EVENT).id(id);
+                }
+            }
+            None => {
+                yield Event::empty().event(LAGGED_EVENT);
+            }
+        }
+
+        loop {
+            let msg = select! {
+                msg = rx.recv() => msg,
+                _ = &mut end => break,
+            };
+
+            match msg {
+                Ok(UserEvent::Upload(id, upload)) => {
+                    yield Event::json(&upload).event(UPLOAD_EVENT).id(id);
+                }
+                Ok(UserEvent::Wake) => {}
+                Err(RecvError::Lagged(_)) => {
+                    yield Event::empty().event(LAGGED_EVENT);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+// Suffix of a camera directory that is being wiped for a new livestream.
+const STALE_DIR_SUFFIX: &str = ".stale";
+
+// Empties camera_path for a new livestream.
+// The old directory is renamed away first, so that the camera directory is only ever
+// missing between the rename and the create_dir_all, and never left half-deleted.
+// Stale directories that we fail to remove here are swept at startup (see sweep_stale_dirs).
+async fn wipe_livestream_dir(camera_path: &Path) -> io::Result<()> {
+    if fs::metadata(camera_path).await.is_ok() {
+        let name = camera_path
+            .file_name()
+            .ok_or_else(|| io::Error::other("Invalid camera path"))?
+            .to_string_lossy();
+        let nanos = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        // Hidden, so that it's never taken for a camera.
+        let stale_path = camera_path.with_file_name(format!(".{name}.{nanos}{STALE_DIR_SUFFIX}"));
+
+        fs::rename(camera_path, &stale_path).await?;
+        fs::create_dir_all(camera_path).await?;
+        if let Err(e) = fs::remove_dir_all(&stale_path).await {
+            error!("Failed to remove {}: {e}", stale_path.display());
+        }
+    } else {
+        fs::create_dir_all(camera_path).await?;
+    }
+
+    Ok(())
+}
+
+// Removes the stale livestream directories (data/<user>/.<camera>.<nanos>.stale)
+// left behind by a wipe_livestream_dir that was interrupted.
+fn sweep_stale_dirs(data_root: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+
+    let users = match std::fs::read_dir(data_root) {
+        Ok(users) => users,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    for user in users {
+        let user = user?;
+        if !user.file_type()?.is_dir() {
+            continue;
+        }
+
+        for entry in std::fs::read_dir(user.path())? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if entry.file_type()?.is_dir()
+                && name.starts_with('.')
+                && name.ends_with(STALE_DIR_SUFFIX)
+            {
+                std::fs::remove_dir_all(entry.path())?;
+                removed += 1;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
 #[post("/livestream/<camera>/<filename>", data = "<data>")]
 async fn livestream_upload(
     camera: &str,
     filename: &str,
     data: Data<'_>,
     auth: &BasicAuth,
+    content_sha256: ContentSha256,
+    _rate_limit: RateLimit<Uploads>,
     all_state: &rocket::State<AllEventState>,
+    quota: &rocket::State<StorageQuota>,
+) -> Result<String, ErrorResponse> {
+    store_livestream_file(
+        camera,
+        filename,
+        data,
+        auth,
+        content_sha256.0,
+        all_state,
+        quota,
+    )
+    .await
+    .map_err(storage_error)
+}
+
+async fn store_livestream_file(
+    camera: &str,
+    filename: &str,
+    data: Data<'_>,
+    auth: &BasicAuth,
+    expected_digest: Option<[u8; 32]>,
+    all_state: &AllEventState,
+    quota: &StorageQuota,
 ) -> io::Result<String> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera")?;
@@ -851,6 +1708,7 @@ async fn livestream_upload(
             "Error: Reached max livestream pending limit.",
         ));
     }
+    quota.check(&auth.username, &root).await?;
 
     let filepath = Path::new(&camera_path).join(filename);
     check_path_sandboxed(&root, &filepath)?;
@@ -859,10 +1717,12 @@ async fn livestream_upload(
     check_path_sandboxed(&root, &filepath_tmp)?;
 
     let mut file = fs::File::create(&filepath_tmp).await?;
-    let mut stream = data.open(MAX_LIVESTREAM_FILE_SIZE.mebibytes());
-    tokio::io::copy(&mut stream, &mut file).await?;
+    let mut stream = data.open(MAX_LIVESTREAM_FILE_SIZE.bytes());
+    let digest = copy_with_digest(&mut stream, &mut file).await?;
+    discard_if_mismatch(&filepath_tmp, expected_digest, digest).await?;
     // Flush the file to disk
     file.sync_all().await?;
+    reserve_or_remove(quota, &auth.username, &root, &filepath, &filepath_tmp).await?;
 
     // We write to a temp file first and then rename to avoid a race with the retrieve operation.
     fs::rename(filepath_tmp, filepath).await?;
@@ -871,8 +1731,8 @@ async fn livestream_upload(
     let camera_dir = File::open(camera_path).await?;
     camera_dir.sync_all().await?;
 
-    let user_state = get_user_state(all_state.inner().clone(), &auth.username);
-    let _ = user_state.sender.send(());
+    let user_state = get_user_state(all_state.clone(), &auth.username);
+    let _ = user_state.sender.send(UserEvent::Wake);
 
     // Returns the number of pending files
     Ok((num_pending_files + 1).to_string())
@@ -882,9 +1742,10 @@ async fn livestream_upload(
 async fn livestream_retrieve(
     camera: &str,
     filename: &str,
+    range: RangeHeader,
     auth: &BasicAuth,
     all_state: &rocket::State<AllEventState>,
-) -> Option<RawText<File>> {
+) -> Option<RangedFile> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera").ok()?;
     if check_path_sandboxed(&root, &camera_path).is_err() {
@@ -910,7 +1771,7 @@ async fn livestream_retrieve(
         // So we subscribe up front, then keep re-checking the file.
         for _ in 0..3 {
             if filepath.exists() {
-                let response = File::open(&filepath).await.map(RawText).ok();
+                let response = RangedFile::open(&filepath, &range).await.ok();
                 return response;
             }
 
@@ -919,7 +1780,7 @@ async fn livestream_retrieve(
         }
 
         if filepath.exists() {
-            let response = File::open(&filepath).await.map(RawText).ok();
+            let response = RangedFile::open(&filepath, &range).await.ok();
             return response;
         }
     }
@@ -927,8 +1788,91 @@ async fn livestream_retrieve(
     None
 }
 
+/// Uploads a talkback (app-to-camera audio) chunk during a livestream.
+/// Returns the number of talkback chunks the camera hasn't retrieved yet.
+#[post("/livestream_audio/<camera>/<filename>", data = "<data>")]
+async fn livestream_audio_upload(
+    camera: &str,
+    filename: &str,
+    data: Data<'_>,
+    auth: &BasicAuth,
+    _rate_limit: RateLimit<Uploads>,
+    quota: &rocket::State<StorageQuota>,
+) -> Result<String, ErrorResponse> {
+    let root = Path::new("data").join(&auth.username);
+    let camera_path = join_validated_child(&root, camera, "camera")?;
+    check_path_sandboxed(&root, &camera_path)?;
+
+    if !camera_path.exists() {
+        return Err(io::Error::other("Error: Livestream session not started properly.").into());
+    }
+
+    let talkback_path = camera_path.join(TALKBACK_DIR);
+    check_path_sandboxed(&root, &talkback_path)?;
+    if !talkback_path.exists() {
+        fs::create_dir_all(&talkback_path).await?;
+    }
+
+    let num_pending_files = get_num_files(&talkback_path).await?;
+    if num_pending_files >= MAX_NUM_PENDING_TALKBACK_FILES {
+        return Err(io::Error::other("Error: Reached max talkback pending limit.").into());
+    }
+    quota
+        .check(&auth.username, &root)
+        .await
+        .map_err(storage_error)?;
+
+    let filepath = join_validated_child(&talkback_path, filename, "chunk")?;
+    check_path_sandboxed(&root, &filepath)?;
+
+    let filepath_tmp = talkback_path.join(format!("{}_tmp", filename));
+    check_path_sandboxed(&root, &filepath_tmp)?;
+
+    let mut file = fs::File::create(&filepath_tmp).await?;
+    let mut stream = data.open(MAX_TALKBACK_FILE_SIZE.mebibytes());
+    tokio::io::copy(&mut stream, &mut file).await?;
+    file.sync_all().await?;
+    reserve_or_remove(quota, &auth.username, &root, &filepath, &filepath_tmp)
+        .await
+        .map_err(storage_error)?;
+
+    // Same as livestream chunks: rename so that the camera never sees a partial chunk.
+    fs::rename(filepath_tmp, filepath).await?;
+
+    Ok((num_pending_files + 1).to_string())
+}
+
+/// Retrieves and deletes a talkback chunk. Doesn't wait for the chunk to arrive,
+/// since the camera checks for talkback in between uploading livestream chunks.
+#[get("/livestream_audio/<camera>/<filename>")]
+async fn livestream_audio_retrieve(
+    camera: &str,
+    filename: &str,
+    auth: &BasicAuth,
+    quota: &rocket::State<StorageQuota>,
+) -> Option<Vec<u8>> {
+    let root = Path::new("data").join(&auth.username);
+    let camera_path = join_validated_child(&root, camera, "camera").ok()?;
+    let talkback_path = camera_path.join(TALKBACK_DIR);
+    let filepath = join_validated_child(&talkback_path, filename, "chunk").ok()?;
+    if check_path_sandboxed(&root, &filepath).is_err() {
+        return None;
+    }
+
+    let data = fs::read(&filepath).await.ok()?;
+    if fs::remove_file(&filepath).await.is_ok() {
+        quota.release(&auth.username, data.len() as u64);
+    }
+
+    Some(data)
+}
+
 #[post("/livestream_end/<camera>")]
-async fn livestream_end(camera: &str, auth: &BasicAuth) -> io::Result<()> {
+async fn livestream_end(
+    camera: &str,
+    auth: &BasicAuth,
+    all_state: &rocket::State<AllEventState>,
+) -> Result<(), ErrorResponse> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera")?;
     check_path_sandboxed(&root, &camera_path)?;
@@ -942,6 +1886,9 @@ async fn livestream_end(camera: &str, auth: &BasicAuth) -> io::Result<()> {
 
     let _ = File::create(livestream_end_path).await?;
 
+    let user_state = get_user_state(all_state.inner().clone(), &auth.username);
+    user_state.livestreams.remove(camera);
+
     Ok(())
 }
 
@@ -974,22 +1921,24 @@ async fn config_command(
     data: Data<'_>,
     expected_size: ExpectedCommandSize,
     auth: &BasicAuth,
+    _rate_limit: RateLimit<Uploads>,
     all_state: &rocket::State<AllEventState>,
-) -> Result<(), Custom<String>> {
+    quota: &rocket::State<StorageQuota>,
+) -> Result<(), ErrorResponse> {
     let expected_size = expected_size.0;
-    let max_size = MAX_COMMAND_FILE_SIZE.kibibytes().as_u64();
+    let max_size = MAX_COMMAND_FILE_SIZE;
 
     if expected_size > max_size {
-        return Err(Custom(
+        return Err(error_response(
             Status::PayloadTooLarge,
-            "Command is too large".to_string(),
+            "Command is too large",
         ));
     }
 
     if expected_size == 0 {
-        return Err(Custom(
+        return Err(error_response(
             Status::BadRequest,
-            "Empty command upload is not allowed".to_string(),
+            "Empty command upload is not allowed",
         ));
     }
 
@@ -1004,9 +1953,17 @@ async fn config_command(
             .map_err(internal_error)?;
     }
 
-    //FIXME: if we receive two commands back to back, one could overwrite the other.
-    let command_file_name = "command".to_string();
-    let command_path = camera_path.join(&command_file_name);
+    quota.check(&auth.username, &root).await
+        .map_err(storage_error)?;
+
+    // Each command is queued in its own file. The lock makes sure that two commands
+    // received back to back don't get the same one.
+    let queue_key = format!("{}/{}/{}", auth.username, camera, COMMAND_PREFIX);
+    let queue_lock = get_file_lock(queue_key).await;
+    let _guard = queue_lock.lock().await;
+
+    let command_path = next_entry_path(&camera_path, COMMAND_PREFIX).await
+        .map_err(queue_error)?;
     let temp_command_path = camera_path.join("command.partial");
 
     check_path_sandboxed(&root, &command_path)
@@ -1031,7 +1988,7 @@ async fn config_command(
         Ok(n) => n,
         Err(e) => {
             let _ = fs::remove_file(&temp_command_path).await;
-            return Err(Custom(
+            return Err(error_response(
                 Status::BadRequest,
                 format!("Failed to receive complete command: {e}"),
             ));
@@ -1040,7 +1997,7 @@ async fn config_command(
 
     if bytes_written != expected_size {
         let _ = fs::remove_file(&temp_command_path).await;
-        return Err(Custom(
+        return Err(error_response(
             Status::BadRequest,
             format!(
                 "Incomplete command upload: expected {expected_size} bytes, received {bytes_written} bytes"
@@ -1048,32 +2005,58 @@ async fn config_command(
         ));
     }
 
+    reserve_or_remove(quota, &auth.username, &root, &command_path, &temp_command_path).await
+        .map_err(storage_error)?;
+
     fs::rename(&temp_command_path, &command_path).await
         .map_err(internal_error)?;
 
+    let command_file_name = command_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
     let user_state = get_user_state(all_state.inner().clone(), &auth.username);
 
     user_state
         .events
         .insert(camera.to_string(), command_file_name);
 
-    let _ = user_state.sender.send(());
+    let _ = user_state.sender.send(UserEvent::Wake);
 
     Ok(())
 }
 
-fn internal_error(e: impl std::fmt::Display) -> Custom<String> {
-    Custom(Status::InternalServerError, e.to_string())
+fn internal_error(e: impl std::fmt::Display) -> ErrorResponse {
+    error_response(Status::InternalServerError, e.to_string())
+}
+
+fn queue_error(e: io::Error) -> ErrorResponse {
+    if e.kind() == ErrorKind::QuotaExceeded {
+        error_response(Status::TooManyRequests, e.to_string())
+    } else {
+        internal_error(e)
+    }
 }
 
+/// Sends the oldest queued config command of the camera, waiting for one if there's none.
+/// The event id acknowledges the command: the camera sends it back as Last-Event-ID in the
+/// next check, which removes the command from the queue. Until then, the command is sent
+/// again, so that one lost with an interrupted check isn't lost for the camera.
+/// Commands are delivered one per check, in order.
 #[get("/config/<camera>")]
 async fn config_check(
     camera: &str,
     auth: &BasicAuth,
+    last_event_id: LastEventId,
+    _rate_limit: RateLimit<Checks>,
     all_state: &rocket::State<AllEventState>,
+    quota: &rocket::State<StorageQuota>,
     mut end: Shutdown,
 ) -> EventStream![] {
     let camera = camera.to_string();
+    let username = auth.username.clone();
+    let quota = quota.inner().clone();
 
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, &camera, "camera");
@@ -1095,9 +2078,30 @@ async fn config_check(
             return;
         }
 
+        if let Some(id) = last_event_id.0.as_deref() {
+            match remove_acknowledged_entry(camera_path, COMMAND_PREFIX, id).await {
+                Ok(Some(size)) => quota.release(&username, size),
+                Ok(None) => {}
+                Err(_) => {
+                    yield Event::data("error reading file");
+                    return;
+                }
+            }
+        }
+
         loop {
-            if let Some((_key, command_file_name)) = user_state.events.remove(&camera) {
-                let command_path = Path::new(&camera_path).join(&command_file_name);
+            // The queue is the source of truth. The event only wakes us up.
+            user_state.events.remove(&camera);
+
+            let command_path = match queued_entries(camera_path, COMMAND_PREFIX).await {
+                Ok(entries) => entries.into_iter().next(),
+                Err(_) => {
+                    yield Event::data("error reading file");
+                    return;
+                }
+            };
+
+            if let Some(command_path) = command_path {
                 if check_path_sandboxed(&root, &command_path).is_err() {
                     yield Event::data("invalid");
                     return;
@@ -1111,17 +2115,15 @@ async fn config_check(
                     }
                 };
 
-                fs::remove_file(&camera_path).await.ok();
-
                 // Encode binary data as base64 and return
                 let encoded = base64_engine.encode(&content);
-                yield Event::data(encoded);
+                yield Event::data(encoded).id(entry_id(&command_path, &content));
                 return;
             }
 
             select! {
                 msg = rx.recv() => match msg {
-                    Ok(()) => {},
+                    Ok(_) => {},
                     Err(_) => break,
                 },
                 _ = &mut end => break,
@@ -1131,26 +2133,43 @@ async fn config_check(
 }
 
 #[post("/config_response/<camera>", data = "<data>")]
-async fn config_response(camera: &str, data: Data<'_>, auth: &BasicAuth) -> io::Result<()> {
+async fn config_response(
+    camera: &str,
+    data: Data<'_>,
+    auth: &BasicAuth,
+    _rate_limit: RateLimit<Uploads>,
+    quota: &rocket::State<StorageQuota>,
+) -> Result<(), ErrorResponse> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera")?;
     check_path_sandboxed(&root, &camera_path)?;
 
     if !camera_path.exists() {
-        return Err(io::Error::other("Error: config camera doesn't exist."));
+        return Err(io::Error::other("Error: config camera doesn't exist.").into());
     }
 
-    let filepath = camera_path.join("config_response");
+    quota.check(&auth.username, &root).await
+        .map_err(storage_error)?;
+
+    // Same as for the commands: one file per response, so that none of them is overwritten.
+    let queue_key = format!("{}/{}/{}", auth.username, camera, RESPONSE_PREFIX);
+    let queue_lock = get_file_lock(queue_key).await;
+    let _guard = queue_lock.lock().await;
+
+    let filepath = next_entry_path(&camera_path, RESPONSE_PREFIX).await?;
     check_path_sandboxed(&root, &filepath)?;
 
     let filepath_tmp = camera_path.join("config_response_tmp");
     check_path_sandboxed(&root, &filepath_tmp)?;
 
     let mut file = fs::File::create(&filepath_tmp).await?;
-    let mut stream = data.open(MAX_COMMAND_FILE_SIZE.kibibytes());
+    let mut stream = data.open(MAX_COMMAND_FILE_SIZE.bytes());
     tokio::io::copy(&mut stream, &mut file).await?;
     // Flush the file to disk
     file.sync_all().await?;
+    reserve_or_remove(quota, &auth.username, &root, &filepath, &filepath_tmp)
+        .await
+        .map_err(storage_error)?;
 
     // We write to a temp file first and then rename to avoid a race with the retrieve operation.
     fs::rename(filepath_tmp, filepath).await?;
@@ -1163,25 +2182,34 @@ async fn config_response(camera: &str, data: Data<'_>, auth: &BasicAuth) -> io::
 }
 
 #[get("/config_response/<camera>")]
-async fn retrieve_config_response(camera: &str, auth: &BasicAuth) -> Option<RawText<File>> {
+async fn retrieve_config_response(
+    camera: &str,
+    auth: &BasicAuth,
+    quota: &rocket::State<StorageQuota>,
+) -> Option<RawText<File>> {
     let root = Path::new("data").join(&auth.username);
     let camera_path = join_validated_child(&root, camera, "camera").ok()?;
     if check_path_sandboxed(&root, &camera_path).is_err() {
         return None;
     }
 
-    let filepath = camera_path.join("config_response");
+    // Responses are retrieved one at a time, oldest first.
+    let filepath = queued_entries(&camera_path, RESPONSE_PREFIX)
+        .await
+        .ok()?
+        .into_iter()
+        .next()?;
     if check_path_sandboxed(&root, &filepath).is_err() {
         return None;
     }
 
-    if camera_path.exists() {
-        let response = File::open(&filepath).await.map(RawText).ok();
-        fs::remove_file(filepath).await.ok();
-        return response;
+    // The open file can still be read after it's removed.
+    let response = File::open(&filepath).await.ok()?;
+    let size = response.metadata().await.map(|m| m.len()).unwrap_or(0);
+    if fs::remove_file(filepath).await.is_ok() {
+        quota.release(&auth.username, size);
     }
-
-    None
+    Some(RawText(response))
 }
 
 // state.inner() utilizes a borrowed value
@@ -1195,25 +2223,214 @@ async fn retrieve_fcm_data<'a>(
     state.inner().as_ref().map(Json)
 }
 
+// Whether /status was asked for the detailed report (?detail=true).
+struct StatusDetailRequested(bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for StatusDetailRequested {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let detail = req
+            .query_value::<bool>("detail")
+            .and_then(Result::ok)
+            .unwrap_or(false);
+        Outcome::Success(StatusDetailRequested(detail))
+    }
+}
+
+// Without credentials, this is a plain health check.
 #[get("/status")]
-async fn retrieve_server_status(_auth: &BasicAuth) -> Json<ServerStatus> {
-    let server_status = ServerStatus { ok: true };
+async fn retrieve_server_status(
+    auth: Option<&BasicAuth>,
+    detail: StatusDetailRequested,
+    all_state: &rocket::State<AllEventState>,
+) -> Json<ServerStatus> {
+    let detail = match auth {
+        Some(auth) if detail.0 => {
+            let root = Path::new("data").join(&auth.username);
+            let user_state = get_user_state(all_state.inner().clone(), &auth.username);
+            match collect_status_detail(&root, &user_state).await {
+                Ok(detail) => Some(detail),
+                Err(e) => {
+                    error!("Failed to collect status for {}: {e}", auth.username);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    Json(ServerStatus { ok: true, detail })
+}
+
+// The cameras of the user whose directory is root, with their directories.
+// Skips the entries that aren't camera data (push tokens, notification targets, hidden files).
+async fn camera_dirs(root: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut cameras = Vec::new();
+
+    if !root.exists() {
+        return Ok(cameras);
+    }
+
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let camera = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type().await?.is_dir()
+            || camera.starts_with('.')
+            || camera == notification_target::NOTIFICATION_TARGETS_DIR
+            || push_token::is_push_token_dir(&camera)
+        {
+            continue;
+        }
 
-    Json(server_status)
+        let Ok(camera_path) = join_validated_child(root, &camera, "camera") else {
+            continue;
+        };
+        check_path_sandboxed(root, &camera_path)?;
+        cameras.push((camera, camera_path));
+    }
+
+    Ok(cameras)
+}
+
+async fn collect_status_detail(root: &Path, user_state: &EventState) -> io::Result<StatusDetail> {
+    let mut cameras = Vec::new();
+
+    for (camera, camera_path) in camera_dirs(root).await? {
+        let (pending_files, pending_bytes) = get_pending_files_usage(&camera_path).await?;
+        cameras.push(CameraStatus {
+            livestream_active: user_state.livestreams.contains(&camera),
+            camera,
+            pending_files,
+            pending_bytes,
+        });
+    }
+    cameras.sort_by(|a, b| a.camera.cmp(&b.camera));
+
+    Ok(StatusDetail {
+        num_cameras: cameras.len(),
+        pending_bytes: cameras.iter().map(|c| c.pending_bytes).sum(),
+        cameras,
+    })
+}
+
+// Server-wide, unlike /status, so it's part of the admin API: the users of this server
+// shouldn't learn about each other. See AdminAuth.
+#[get("/status/full")]
+async fn retrieve_server_diagnostics(
+    _admin: AdminAuth,
+    users: &rocket::State<UserStore>,
+    fcm_config: &rocket::State<Option<ConfigResponse>>,
+) -> Json<ServerDiagnostics> {
+    let data_root = Path::new("data");
+    let num_users = users.num_users();
+
+    let (ok, pending_files) = match count_all_pending_files(data_root).await {
+        Ok(pending_files) => (true, pending_files),
+        Err(e) => {
+            error!("Failed to count the pending files: {e}");
+            (false, 0)
+        }
+    };
+
+    Json(ServerDiagnostics {
+        ok,
+        data_free_bytes: free_disk_space(data_root).await,
+        num_users,
+        pending_files,
+        fcm_config_loaded: fcm_config.inner().is_some(),
+    })
+}
+
+// Files waiting for the apps in all the cameras of all the users under data_root.
+async fn count_all_pending_files(data_root: &Path) -> io::Result<usize> {
+    let mut total = 0;
+
+    if !data_root.exists() {
+        return Ok(total);
+    }
+
+    let mut entries = fs::read_dir(data_root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.file_type().await?.is_dir()
+            || entry.file_name().to_string_lossy().starts_with('.')
+        {
+            continue;
+        }
+
+        for (_, camera_path) in camera_dirs(&entry.path()).await? {
+            total += get_pending_files_usage(&camera_path).await?.0;
+        }
+    }
+
+    Ok(total)
+}
+
+// Free space on the file system of path, as reported by df.
+// None when df isn't available or path doesn't exist.
+async fn free_disk_space(path: &Path) -> Option<u64> {
+    let path = path.to_path_buf();
+    let output = task::spawn_blocking(move || {
+        std::process::Command::new("df")
+            .arg("-Pk")
+            .arg(path)
+            .output()
+    })
+    .await
+    .ok()?
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_df_available(&String::from_utf8_lossy(&output.stdout))
+}
+
+// The available space (in bytes) in the output of df -Pk: a header line, then
+// "Filesystem 1024-blocks Used Available Capacity Mounted-on".
+fn parse_df_available(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let available_kb: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    available_kb.checked_mul(1024)
 }
 
 #[post("/debug_logs", data = "<data>")]
-async fn upload_debug_logs(data: Data<'_>, auth: &BasicAuth) -> io::Result<String> {
+async fn upload_debug_logs(
+    data: Data<'_>,
+    auth: &BasicAuth,
+    _rate_limit: RateLimit<Uploads>,
+    quota: &rocket::State<StorageQuota>,
+) -> Result<String, ErrorResponse> {
+    store_debug_logs(data, auth, quota)
+        .await
+        .map_err(storage_error)
+}
+
+async fn store_debug_logs(
+    data: Data<'_>,
+    auth: &BasicAuth,
+    quota: &StorageQuota,
+) -> io::Result<String> {
     let root = Path::new("data").join(&auth.username);
     let logs_path = root.join("debug_logs");
     check_path_sandboxed(&root, &logs_path)?;
 
-    let mut file = fs::File::create(&logs_path).await?;
+    let logs_path_tmp = root.join("debug_logs_tmp");
+    check_path_sandboxed(&root, &logs_path_tmp)?;
+
+    quota.check(&auth.username, &root).await?;
+
+    let mut file = fs::File::create(&logs_path_tmp).await?;
     // FIXME: hardcoded max size
     let mut stream = data.open(5.mebibytes());
     tokio::io::copy(&mut stream, &mut file).await?;
     // Flush the file to disk
     file.sync_all().await?;
+    reserve_or_remove(quota, &auth.username, &root, &logs_path, &logs_path_tmp).await?;
+
+    fs::rename(&logs_path_tmp, &logs_path).await?;
 
     Ok("ok".to_string())
 }
@@ -1223,10 +2440,10 @@ async fn add_app_check(
     op: &str,
     auth: &BasicAuth,
     state: &rocket::State<SharedAddAppState>,
-) -> Result<Vec<u8>, Custom<String>> {
+) -> Result<Vec<u8>, ErrorResponse> {
     if op.is_empty() || op.contains('"') {
         debug!("[ADD_APP_CHECK] Invalid op (empty or contains quote character: {})", op);
-        return Err(Custom(Status::BadRequest, "invalid op".to_string()));
+        return Err(error_response(Status::BadRequest, "invalid op"));
     }
 
     let key = (auth.username.clone(), op.to_string());
@@ -1252,7 +2469,7 @@ async fn add_app_check(
     .await;
 
     state.remove(&key);
-    result.map_err(|_| Custom(Status::RequestTimeout, "request timed out".to_string()))
+    result.map_err(|_| error_response(Status::RequestTimeout, "request timed out"))
 }
 
 #[post("/add_app_request/<op>", data = "<data>")]
@@ -1261,35 +2478,35 @@ async fn add_app_request(
     data: Data<'_>,
     auth: &BasicAuth,
     state: &rocket::State<SharedAddAppState>,
-) -> Result<String, Custom<String>> {
+) -> Result<String, ErrorResponse> {
     if op.is_empty() || op.contains('"') {
         debug!("[ADD_APP_REQUEST] Invalid op (empty or contains quote character: {})", op);
-        return Err(Custom(Status::BadRequest, "invalid op".to_string()));
+        return Err(error_response(Status::BadRequest, "invalid op"));
     }
 
     let key = (auth.username.clone(), op.to_string());
     let entry = state
         .get(&key)
         .map(|entry| entry.value().clone())
-        .ok_or_else(|| Custom(Status::NotFound, "no app is waiting for this op".to_string()))?;
+        .ok_or_else(|| error_response(Status::NotFound, "no app is waiting for this op"))?;
 
     let payload = data
         .open(MAX_ADD_APP_REQUEST_SIZE.kibibytes())
         .into_bytes()
         .await
-        .map_err(|error| Custom(Status::BadRequest, error.to_string()))?;
+        .map_err(|error| error_response(Status::BadRequest, error.to_string()))?;
     if !payload.is_complete() {
-        return Err(Custom(
+        return Err(error_response(
             Status::PayloadTooLarge,
-            "request payload is too large".to_string(),
+            "request payload is too large",
         ));
     }
 
     let mut pending_payload = entry.payload.lock().await;
     if pending_payload.is_some() {
-        return Err(Custom(
+        return Err(error_response(
             Status::Conflict,
-            "a request is already pending for this op".to_string(),
+            "a request is already pending for this op",
         ));
     }
     *pending_payload = Some(payload.into_inner());
@@ -1299,21 +2516,170 @@ async fn add_app_request(
     Ok("ok".to_string())
 }
 
-#[launch]
-fn rocket() -> rocket::Rocket<rocket::Build> {
-    build_rocket()
+// Admin API, to manage the users of the server without editing the credential files and
+// restarting it. See AdminAuth.
+#[post("/admin/users")]
+async fn admin_add_user(
+    _admin: AdminAuth,
+    users: &rocket::State<UserStore>,
+) -> Result<Json<AdminUser>, ErrorResponse> {
+    // Alphanumeric, so that the username can be used in ROUTE_ADMIN_USER as is.
+    let user = AdminUser {
+        username: generate_random(NUM_USERNAME_CHARS, false),
+        password: generate_random(NUM_PASSWORD_CHARS, false),
+    };
+
+    users
+        .add(&user.username, &user.password)
+        .map_err(|e| error_response(Status::InternalServerError, e.to_string()))?;
+    fs::create_dir_all(Path::new("data").join(&user.username))
+        .await
+        .map_err(|e| error_response(Status::InternalServerError, e.to_string()))?;
+
+    Ok(Json(user))
 }
 
-pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
-    let all_event_state: AllEventState = Arc::new(DashMap::new());
-    let pairing_state: SharedPairingState = Arc::new(Mutex::new(HashMap::new()));
-    let add_app_state: SharedAddAppState = Arc::new(DashMap::new());
-    let failure_store: FailStore = Arc::new(DashMap::new());
+#[get("/admin/users")]
+async fn admin_list_users(
+    _admin: AdminAuth,
+    users: &rocket::State<UserStore>,
+) -> Json<Vec<String>> {
+    Json(users.usernames())
+}
 
-    let mut network_type: Option<String> = None;
-    let mut bind_address: Option<String> = None;
+// Takes the rotated user_credentials of the user (secluso-config-tool --rotate-user-credentials).
+#[put("/admin/users/<username>", data = "<data>")]
+async fn admin_rotate_user(
+    username: &str,
+    data: Data<'_>,
+    _admin: AdminAuth,
+    users: &rocket::State<UserStore>,
+) -> Result<String, ErrorResponse> {
+    let credentials = data
+        .open(1.kibibytes())
+        .into_bytes()
+        .await
+        .map_err(|e| error_response(Status::BadRequest, e.to_string()))?;
+    let (new_username, password) = parse_user_credentials(credentials.into_inner())
+        .map_err(|e| error_response(Status::BadRequest, e.to_string()))?;
+    if new_username != username {
+        return Err(error_response(
+            Status::BadRequest,
+            "the rotated credentials must keep the username",
+        ));
+    }
+
+    match users.set_password(username, &password) {
+        Ok(true) => Ok("ok".to_string()),
+        Ok(false) => Err(error_response(Status::NotFound, "no such user")),
+        Err(e) if e.kind() == ErrorKind::InvalidInput => {
+            Err(error_response(Status::BadRequest, e.to_string()))
+        }
+        Err(e) => Err(error_response(Status::InternalServerError, e.to_string())),
+    }
+}
+
+#[delete("/admin/users/<username>")]
+async fn admin_delete_user(
+    username: &str,
+    _admin: AdminAuth,
+    users: &rocket::State<UserStore>,
+    all_event_state: &rocket::State<AllEventState>,
+    quota: &rocket::State<StorageQuota>,
+) -> Result<String, ErrorResponse> {
+    let user_path = join_validated_child(Path::new("data"), username, "username")
+        .map_err(|e| error_response(Status::BadRequest, e.to_string()))?;
+
+    // The user can't authenticate anymore once removed from the store.
+    match users.remove(username) {
+        Ok(true) => {}
+        Ok(false) => return Err(error_response(Status::NotFound, "no such user")),
+        Err(e) => return Err(error_response(Status::InternalServerError, e.to_string())),
+    }
+    all_event_state.remove(username);
+    quota.invalidate(username);
+
+    match fs::remove_dir_all(&user_path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(error_response(Status::InternalServerError, e.to_string())),
+    }
+
+    Ok("ok".to_string())
+}
+
+#[rocket::main]
+async fn main() -> Result<(), rocket::Error> {
+    if std::env::args().skip(1).any(|arg| arg == "--self-test") {
+        std::process::exit(self_test::run());
+    }
+
+    loop {
+        let rocket = build_rocket().ignite().await?;
+        let restart = restart_on_sighup(&rocket);
+        rocket.launch().await?;
+
+        if !restart.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        info!("Restarting to reload the TLS certificate");
+    }
+}
+
+/// With TLS, SIGHUP restarts the server so that it picks up a renewed certificate
+/// (e.g., from Let's Encrypt), since Rocket can't replace the certificate of a running server.
+/// The in-memory state (pairing sessions, open event streams, rate limits) doesn't survive it.
+fn restart_on_sighup(rocket: &rocket::Rocket<rocket::Ignite>) -> Arc<AtomicBool> {
+    let restart = Arc::new(AtomicBool::new(false));
+
+    #[cfg(unix)]
+    if rocket.config().tls_enabled() {
+        use rocket::tokio::signal::unix::{signal, SignalKind};
+
+        let restart = Arc::clone(&restart);
+        let shutdown = rocket.shutdown();
+        tokio::spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    error!("Failed to listen for SIGHUP: {e}");
+                    return;
+                }
+            };
+            if hangup.recv().await.is_some() {
+                restart.store(true, Ordering::SeqCst);
+                shutdown.notify();
+            }
+        });
+    }
+
+    restart
+}
+
+pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
+    let (config, base_path) = match server_config(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    build_rocket_with_config(config, base_path.as_deref().unwrap_or("/"))
+}
+
+/// Assembles the config from the command line arguments.
+/// Also returns the base path, if any (--base-path or SECLUSO_BASE_PATH).
+pub fn server_config(
+    args: impl IntoIterator<Item = String>,
+) -> Result<(rocket::Config, Option<String>), String> {
+    let mut network_type: Option<String> = None;
+    let mut bind_address: Option<String> = None;
     let mut listen_port: Option<u16> = None;
-    let mut args = std::env::args().skip(1);
+    let mut tls_cert: Option<String> = None;
+    let mut tls_key: Option<String> = None;
+    let mut base_path: Option<String> = std::env::var("SECLUSO_BASE_PATH").ok();
+    let mut args = args.into_iter();
     while let Some(arg) = args.next() {
         if arg == "--network-type" {
             if let Some(value) = args.next() {
@@ -1331,6 +2697,18 @@ pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
                     eprintln!("Invalid --port={value}. Falling back to default 8000.");
                 }
             }
+        } else if arg == "--base-path" {
+            if let Some(value) = args.next() {
+                base_path = Some(value);
+            }
+        } else if arg == "--tls-cert" {
+            if let Some(value) = args.next() {
+                tls_cert = Some(value);
+            }
+        } else if arg == "--tls-key" {
+            if let Some(value) = args.next() {
+                tls_key = Some(value);
+            }
         } else if let Some(value) = arg.strip_prefix("--network-type=") {
             network_type = Some(value.to_string());
         } else if let Some(value) = arg.strip_prefix("--bind-address=") {
@@ -1341,25 +2719,72 @@ pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
             } else {
                 eprintln!("Invalid --port={value}. Falling back to default 8000.");
             }
+        } else if let Some(value) = arg.strip_prefix("--base-path=") {
+            base_path = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--tls-cert=") {
+            tls_cert = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--tls-key=") {
+            tls_key = Some(value.to_string());
         }
     }
 
-    let address = bind_address.unwrap_or_else(|| match network_type.as_deref() {
-        Some("http") => "0.0.0.0".to_string(),
-        Some("https") | None => "127.0.0.1".to_string(),
-        Some(other) => {
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => Some(TlsConfig::from_paths(cert, key)),
+        (None, None) => None,
+        _ => return Err("--tls-cert and --tls-key must be given together.".to_string()),
+    };
+    // The tls table of Rocket.toml (or ROCKET_TLS) works too.
+    let tls_configured = tls.is_some() || rocket::Config::figment().find_value("tls").is_ok();
+
+    if network_type.as_deref() == Some("https") && !tls_configured {
+        return Err(
+            "--network-type=https serves HTTPS directly and needs --tls-cert and \
+            --tls-key (or the tls table of Rocket.toml). Leave --network-type out to listen on \
+            127.0.0.1 behind a reverse proxy."
+                .to_string(),
+        );
+    }
+
+    let address = match (bind_address, network_type.as_deref()) {
+        (Some(address), _) => address,
+        (None, Some("http" | "https")) => "0.0.0.0".to_string(),
+        (None, None) => "127.0.0.1".to_string(),
+        (None, Some(other)) => {
             eprintln!("Unknown --network-type={other}. Use http or https.");
             "127.0.0.1".to_string()
         }
-    });
+    };
+    let address = address
+        .parse()
+        .map_err(|e| format!("Invalid --bind-address={address}: {e}"))?;
 
     let config = rocket::Config {
         port: listen_port.unwrap_or(8000),
-        address: address.parse().unwrap(),
+        address,
         limits: Limits::default().limit("json", MAX_JSON_SIZE.kibibytes()),
+        tls,
         ..rocket::Config::default()
     };
 
+    Ok((config, base_path))
+}
+
+/// Builds the server with the given config and mounts all the routes under base_path
+/// (e.g., "/secluso" when hosted next to other apps on the same domain).
+pub fn build_rocket_with_config(
+    config: rocket::Config,
+    base_path: &str,
+) -> rocket::Rocket<rocket::Build> {
+    build_rocket_with_quota(config, base_path, StorageQuota::from_env())
+}
+
+/// Same as build_rocket_with_config, with the given per-user storage quota instead of
+/// the one from SECLUSO_USER_QUOTA_MIB.
+pub fn build_rocket_with_quota(
+    config: rocket::Config,
+    base_path: &str,
+    quota: StorageQuota,
+) -> rocket::Rocket<rocket::Build> {
     // Fetch the relevant app FCM data and store globally for future requests asking for it.
     // Tests and local tooling can skip this with SECLUSO_SKIP_FCM_CONFIG=1.
     // When service_account_key.json is not present, run without FCM support (UnifiedPush / iOS relay continue to work).
@@ -1371,30 +2796,77 @@ pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
         info!("service_account_key.json not found; running without FCM support");
         None
     };
+
+    build_rocket_with_state(config, base_path, quota, fcm_config, initialize_users())
+}
+
+/// Same as build_rocket_with_quota, with the given FCM config and users instead of the ones
+/// from service_account_key.json and the user credential files.
+fn build_rocket_with_state(
+    config: rocket::Config,
+    base_path: &str,
+    quota: StorageQuota,
+    fcm_config: Option<ConfigResponse>,
+    users: UserStore,
+) -> rocket::Rocket<rocket::Build> {
+    let all_event_state: AllEventState = Arc::new(DashMap::new());
+    let pairing_state: SharedPairingState = Arc::new(DashMap::new());
+    let add_app_state: SharedAddAppState = Arc::new(DashMap::new());
+    let failure_store: FailStore = Arc::new(DashMap::new());
+    let base_path = normalize_base_path(base_path);
+
+    // Rocket.toml and ROCKET_* variables still apply to the settings that config doesn't cover
+    // (e.g., the expiry table).
+    let figment = rocket::Config::figment().merge(config);
+
+    // iOS devices can also be notified directly through APNs, with the key in the apns table.
+    let apns = ApnsSender::from_figment(&figment).expect("Failed to set up APNs");
+    // Advertised to the apps in /fcm_config.
+    let push_providers = supported_push_providers(&fcm_config, &apns);
+    let fcm_config = fcm_config.map(|config| ConfigResponse {
+        push_providers,
+        ..config
+    });
+
     let notification_target_policy = notification_target::UnifiedPushPolicy::from_env()
         .expect("Failed to parse UnifiedPush allowlist");
 
-    rocket::custom(config)
+    // Livestreams must stay restartable after an unclean shutdown.
+    match sweep_stale_dirs(Path::new("data")) {
+        Ok(0) => {}
+        Ok(removed) => info!("Removed {removed} stale livestream directories"),
+        Err(e) => error!("Failed to sweep stale livestream directories: {e}"),
+    }
+
+    rocket::custom(figment)
         .attach(ServerVersionHeader {
             version: env!("CARGO_PKG_VERSION").to_string(), // Fetch the version of this crate
         })
         .manage(all_event_state)
-        .manage(initialize_users())
+        .manage(users)
         .manage(failure_store)
         .manage(pairing_state)
         .manage(fcm_config)
+        .manage(apns)
         .manage(notification_target_policy)
         .manage(add_app_state)
+        .manage(quota)
+        .attach(expiry::fairing())
+        .attach(RateLimiting)
+        .attach(RequestLogger)
         .mount(
-            "/",
+            base_path,
             routes![
                 pair,
                 upload,
                 bulk_group_check,
                 retrieve,
+                list_files,
+                consume_file,
                 delete_file,
                 delete_camera,
                 upload_fcm_token,
+                upload_push_token,
                 upload_notification_target,
                 retrieve_notification_target,
                 send_fcm_notification,
@@ -1402,6 +2874,8 @@ pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
                 livestream_check,
                 livestream_upload,
                 livestream_retrieve,
+                livestream_audio_upload,
+                livestream_audio_retrieve,
                 livestream_end,
                 config_command,
                 config_check,
@@ -1410,19 +2884,92 @@ pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
                 upload_debug_logs,
                 retrieve_fcm_data,
                 retrieve_server_status,
+                retrieve_server_diagnostics,
+                events_check,
                 add_app_check,
                 add_app_request,
+                admin_add_user,
+                admin_list_users,
+                admin_rotate_user,
+                admin_delete_user,
             ],
         )
+        .register("/", catchers![default_catcher])
+}
+
+#[cfg(test)]
+mod test_support {
+    use super::build_rocket_with_state;
+    use crate::auth::UserStore;
+    use crate::quota::StorageQuota;
+    use base64::engine::general_purpose::STANDARD as base64_engine;
+    use base64::Engine;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+    use std::fs;
+    use std::path::Path;
+
+    /// A server mounted at base_path, without FCM and without any user.
+    pub fn test_rocket_at(base_path: &str, quota: StorageQuota) -> rocket::Rocket<rocket::Build> {
+        build_rocket_with_state(
+            rocket::Config::debug_default(),
+            base_path,
+            quota,
+            None,
+            UserStore::new(None),
+        )
+    }
+
+    /// A server without FCM, with the given per-user quota and username as its only user.
+    /// The data of username left over from earlier runs is removed.
+    pub fn test_rocket(
+        username: &str,
+        password: &str,
+        quota: StorageQuota,
+    ) -> rocket::Rocket<rocket::Build> {
+        let rocket = test_rocket_at("/", quota);
+        rocket
+            .state::<UserStore>()
+            .unwrap()
+            .insert(username.to_string(), password.to_string());
+        let _ = fs::remove_dir_all(Path::new("data").join(username));
+        rocket
+    }
+
+    /// The Authorization and Client-Version headers for requests as username.
+    pub fn test_headers(username: &str, password: &str) -> (Header<'static>, Header<'static>) {
+        let encoded = base64_engine.encode(format!("{username}:{password}"));
+        let auth = Header::new("Authorization", format!("Basic {encoded}"));
+        let version = Header::new("Client-Version", env!("CARGO_PKG_VERSION"));
+        (auth, version)
+    }
+
+    /// test_rocket() behind a blocking client, with test_headers().
+    pub fn test_client(
+        username: &str,
+        password: &str,
+        quota: StorageQuota,
+    ) -> (Client, Header<'static>, Header<'static>) {
+        let client =
+            Client::tracked(test_rocket(username, password, quota)).expect("valid rocket instance");
+        let (auth, version) = test_headers(username, password);
+        (client, auth, version)
+    }
 }
 
 #[cfg(test)]
 mod pairing_tests {
-    use super::{auth::BasicAuth, notification_target, pair, PairingRequest, SharedPairingState};
+    use super::{
+        auth::BasicAuth, notification_target, pair, PairingEntry, PairingRequest, RequestId,
+        SharedPairingState, MAX_PAIRING_SESSIONS_PER_USER, MAX_PAIRING_TOKEN_LEN,
+        PAIRING_SESSION_TIMEOUT,
+    };
+    use dashmap::DashMap;
     use rocket::serde::json::Json;
+    use rocket::tokio::sync::Notify;
     use rocket::State;
-    use std::collections::HashMap;
     use std::sync::{Arc, Mutex};
+    use std::time::Instant;
 
     fn test_auth(username: &str) -> BasicAuth {
         BasicAuth {
@@ -1433,7 +2980,7 @@ mod pairing_tests {
 
     #[rocket::async_test]
     async fn pairing_succeeds_with_same_authenticated_account() {
-        let state: SharedPairingState = Arc::new(Mutex::new(HashMap::new()));
+        let state: SharedPairingState = Arc::new(DashMap::new());
         let policy = notification_target::UnifiedPushPolicy::from_env().unwrap();
         let auth = test_auth("sameacctuser01");
 
@@ -1447,6 +2994,7 @@ mod pairing_tests {
                 State::from(&state),
                 State::from(&policy),
                 &auth,
+                RequestId("pairtest".to_string()),
             ),
             pair(
                 Json(PairingRequest {
@@ -1457,6 +3005,7 @@ mod pairing_tests {
                 State::from(&state),
                 State::from(&policy),
                 &auth,
+                RequestId("pairtest".to_string()),
             )
         );
 
@@ -1466,7 +3015,7 @@ mod pairing_tests {
 
     #[rocket::async_test]
     async fn pairing_does_not_cross_authenticated_accounts() {
-        let state: SharedPairingState = Arc::new(Mutex::new(HashMap::new()));
+        let state: SharedPairingState = Arc::new(DashMap::new());
         let policy = notification_target::UnifiedPushPolicy::from_env().unwrap();
         let phone_auth = test_auth("phoneaccount01");
         let camera_auth = test_auth("cameraaccount1");
@@ -1481,6 +3030,7 @@ mod pairing_tests {
                 State::from(&state),
                 State::from(&policy),
                 &phone_auth,
+                RequestId("pairtest".to_string()),
             ),
             pair(
                 Json(PairingRequest {
@@ -1491,20 +3041,337 @@ mod pairing_tests {
                 State::from(&state),
                 State::from(&policy),
                 &camera_auth,
+                RequestId("pairtest".to_string()),
             )
         );
 
         assert_eq!(phone_response.into_inner().status, "expired");
         assert_eq!(camera_response.into_inner().status, "expired");
     }
+
+    fn session(created_at: Instant) -> Arc<Mutex<PairingEntry>> {
+        Arc::new(Mutex::new(PairingEntry {
+            phone_connected: true,
+            camera_connected: false,
+            phone_notified: false,
+            camera_notified: false,
+            notification_target: None,
+            created_at,
+            notify: Arc::new(Notify::new()),
+            expired: false,
+        }))
+    }
+
+    async fn pair_with_token(
+        state: &SharedPairingState,
+        policy: &notification_target::UnifiedPushPolicy,
+        auth: &BasicAuth,
+        token: &str,
+        role: &str,
+    ) -> String {
+        pair(
+            Json(PairingRequest {
+                pairing_token: token.to_string(),
+                role: role.to_string(),
+                notification_target: None,
+            }),
+            State::from(state),
+            State::from(policy),
+            auth,
+            RequestId("pairtest".to_string()),
+        )
+        .await
+        .into_inner()
+        .status
+    }
+
+    #[rocket::async_test]
+    async fn expired_sessions_are_reclaimed() {
+        let state: SharedPairingState = Arc::new(DashMap::new());
+        let policy = notification_target::UnifiedPushPolicy::from_env().unwrap();
+        let auth = test_auth("floodaccount01");
+
+        let created_at = Instant::now() - 2 * PAIRING_SESSION_TIMEOUT;
+        for i in 0..5000 {
+            let user = format!("flooduser{:05}", i % 10);
+            state.insert((user, format!("token-{i}")), session(created_at));
+        }
+
+        let (phone_status, camera_status) = rocket::tokio::join!(
+            pair_with_token(&state, &policy, &auth, "fresh-token", "phone"),
+            pair_with_token(&state, &policy, &auth, "fresh-token", "camera")
+        );
+        assert_eq!(phone_status, "paired");
+        assert_eq!(camera_status, "paired");
+        // Only the new session is left.
+        assert_eq!(state.len(), 1);
+    }
+
+    #[rocket::async_test]
+    async fn live_sessions_are_capped_per_user() {
+        let state: SharedPairingState = Arc::new(DashMap::new());
+        let policy = notification_target::UnifiedPushPolicy::from_env().unwrap();
+        let auth = test_auth("busyaccount001");
+        let other_auth = test_auth("otheraccount01");
+
+        for i in 0..MAX_PAIRING_SESSIONS_PER_USER {
+            state.insert(
+                (auth.username.clone(), format!("token-{i}")),
+                session(Instant::now()),
+            );
+        }
+
+        let status = pair_with_token(&state, &policy, &auth, "one-more", "phone").await;
+        assert_eq!(status, "busy");
+
+        let (phone_status, camera_status) = rocket::tokio::join!(
+            pair_with_token(&state, &policy, &other_auth, "other-token", "phone"),
+            pair_with_token(&state, &policy, &other_auth, "other-token", "camera")
+        );
+        assert_eq!(phone_status, "paired");
+        assert_eq!(camera_status, "paired");
+    }
+
+    #[rocket::async_test]
+    async fn invalid_tokens_are_rejected() {
+        let state: SharedPairingState = Arc::new(DashMap::new());
+        let policy = notification_target::UnifiedPushPolicy::from_env().unwrap();
+        let auth = test_auth("tokenaccount01");
+
+        let too_long = "t".repeat(MAX_PAIRING_TOKEN_LEN + 1);
+        for token in [
+            "",
+            "with space",
+            "with\"quote",
+            "non-ascii-é",
+            too_long.as_str(),
+        ] {
+            let status = pair_with_token(&state, &policy, &auth, token, "phone").await;
+            assert_eq!(status, "invalid_token", "token: {token:?}");
+        }
+        assert!(state.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod status_tests {
+    use super::test_support::{test_headers, test_rocket};
+    use super::{
+        collect_status_detail, count_all_pending_files, get_user_state, parse_df_available,
+        AllEventState,
+    };
+    use crate::quota::StorageQuota;
+    use dashmap::DashMap;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("secluso-status-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[rocket::async_test]
+    async fn status_detail_reports_pending_files_per_camera() {
+        let root = test_root("detail");
+        std::fs::create_dir_all(root.join("front")).unwrap();
+        std::fs::write(root.join("front").join("100"), vec![0u8; 10]).unwrap();
+        std::fs::write(root.join("front").join("200"), vec![0u8; 5]).unwrap();
+        // Bookkeeping files don't count.
+        std::fs::write(root.join("front").join(".100.refcount"), b"1").unwrap();
+        std::fs::create_dir_all(root.join("back")).unwrap();
+        // Neither do non-camera entries.
+        std::fs::create_dir_all(root.join("notification_targets")).unwrap();
+        std::fs::write(root.join("fcm_token"), b"token").unwrap();
+
+        let all_state: AllEventState = Arc::new(DashMap::new());
+        let user_state = get_user_state(all_state, "user");
+        user_state.livestreams.insert("back".to_string());
+
+        let detail = collect_status_detail(&root, &user_state).await.unwrap();
+        assert_eq!(detail.num_cameras, 2);
+        assert_eq!(detail.pending_bytes, 15);
+
+        assert_eq!(detail.cameras[0].camera, "back");
+        assert_eq!(detail.cameras[0].pending_files, 0);
+        assert!(detail.cameras[0].livestream_active);
+
+        assert_eq!(detail.cameras[1].camera, "front");
+        assert_eq!(detail.cameras[1].pending_files, 2);
+        assert_eq!(detail.cameras[1].pending_bytes, 15);
+        assert!(!detail.cameras[1].livestream_active);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[rocket::async_test]
+    async fn status_detail_for_user_without_data() {
+        let root = test_root("empty").join("missing");
+        let all_state: AllEventState = Arc::new(DashMap::new());
+        let user_state = get_user_state(all_state, "user");
+
+        let detail = collect_status_detail(&root, &user_state).await.unwrap();
+        assert_eq!(detail.num_cameras, 0);
+        assert_eq!(detail.pending_bytes, 0);
+    }
+
+    #[rocket::async_test]
+    async fn pending_files_are_counted_across_users() {
+        let root = test_root("all");
+        std::fs::create_dir_all(root.join("alice").join("front")).unwrap();
+        std::fs::write(root.join("alice").join("front").join("100"), b"video").unwrap();
+        std::fs::write(root.join("alice").join("front").join(".100.refcount"), b"1").unwrap();
+        std::fs::create_dir_all(root.join("alice").join("fcm_tokens")).unwrap();
+        std::fs::write(
+            root.join("alice").join("fcm_tokens").join("fcm_token_1"),
+            b"t",
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("bob").join("back")).unwrap();
+        std::fs::write(root.join("bob").join("back").join("1"), b"video").unwrap();
+        std::fs::write(root.join("bob").join("back").join("2"), b"video").unwrap();
+
+        assert_eq!(count_all_pending_files(&root).await.unwrap(), 3);
+        assert_eq!(
+            count_all_pending_files(&root.join("missing"))
+                .await
+                .unwrap(),
+            0
+        );
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn df_output_is_parsed() {
+        let output = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n\
+                      /dev/sda1         41152736 12345678  26703488      32% /\n";
+        assert_eq!(parse_df_available(output), Some(26703488 * 1024));
+        assert_eq!(
+            parse_df_available("Filesystem 1024-blocks Used Available\n"),
+            None
+        );
+        assert_eq!(parse_df_available(""), None);
+    }
+
+    #[test]
+    fn full_status_requires_the_admin_token() {
+        let username = "statustestuser";
+        let password = "statustestpass";
+        let rocket = test_rocket(username, password, StorageQuota::from_env());
+        let figment = rocket
+            .figment()
+            .clone()
+            .merge(("admin_token", "statustesttoken"));
+        let client = Client::tracked(rocket.configure(figment)).expect("valid rocket instance");
+        let (auth, version) = test_headers(username, password);
+        let admin_auth = Header::new("Authorization", "Bearer statustesttoken");
+
+        // The health check doesn't need anything.
+        let response = client.get("/status").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body, serde_json::json!({ "ok": true }));
+
+        let response = client
+            .get("/status/full")
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // The credentials of a user aren't enough to see the other users' data.
+        let response = client
+            .get("/status/full")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get("/status/full")
+            .header(admin_auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["ok"], true);
+        assert_eq!(body["num_users"], 1);
+        assert_eq!(body["fcm_config_loaded"], false);
+        assert!(body["pending_files"].is_u64());
+        assert!(body["data_free_bytes"].is_u64() || body["data_free_bytes"].is_null());
+    }
+}
+
+#[cfg(test)]
+mod livestream_wipe_tests {
+    use super::{sweep_stale_dirs, wipe_livestream_dir};
+    use std::path::PathBuf;
+
+    fn test_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("secluso-livestream-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[rocket::async_test]
+    async fn wipe_leaves_an_empty_camera_dir() {
+        let root = test_root("wipe");
+        let camera_path = root.join("user").join("front");
+        std::fs::create_dir_all(&camera_path).unwrap();
+        std::fs::write(camera_path.join("3"), b"old chunk").unwrap();
+
+        wipe_livestream_dir(&camera_path).await.unwrap();
+        assert!(camera_path.is_dir());
+        assert_eq!(std::fs::read_dir(&camera_path).unwrap().count(), 0);
+        // Only the camera dir is left, no stale dir.
+        assert_eq!(std::fs::read_dir(root.join("user")).unwrap().count(), 1);
+
+        // A camera that never streamed before.
+        let new_camera_path = root.join("user").join("back");
+        wipe_livestream_dir(&new_camera_path).await.unwrap();
+        assert!(new_camera_path.is_dir());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn sweep_removes_only_stale_dirs() {
+        let root = test_root("sweep");
+        let user_path = root.join("user");
+        std::fs::create_dir_all(user_path.join(".front.123.stale")).unwrap();
+        std::fs::write(user_path.join(".front.123.stale").join("3"), b"old chunk").unwrap();
+        std::fs::create_dir_all(user_path.join("front")).unwrap();
+        std::fs::create_dir_all(user_path.join("camera.stale")).unwrap();
+        std::fs::write(user_path.join("fcm_token"), b"token").unwrap();
+
+        assert_eq!(sweep_stale_dirs(&root).unwrap(), 1);
+        assert!(!user_path.join(".front.123.stale").exists());
+        assert!(user_path.join("front").is_dir());
+        assert!(user_path.join("camera.stale").is_dir());
+        assert!(user_path.join("fcm_token").exists());
+
+        assert_eq!(sweep_stale_dirs(&root.join("missing")).unwrap(), 0);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
 }
 
 #[cfg(test)]
 mod contract_tests {
-    use super::build_rocket;
-    use rocket::http::Method;
+    use super::test_support::test_rocket_at;
+    use crate::quota::StorageQuota;
+    use base64::engine::general_purpose::STANDARD as base64_engine;
+    use base64::Engine;
+    use rocket::http::{ContentType, Header, Method, Status};
+    use rocket::local::blocking::Client;
     use secluso_server_backbone::routes::BASE_ROUTES;
     use secluso_server_backbone::HttpMethod;
+    use std::collections::HashSet;
 
     fn to_rocket_method(method: HttpMethod) -> Method {
         match method {
@@ -1517,9 +3384,7 @@ mod contract_tests {
 
     #[test]
     fn base_routes_are_mounted() {
-        std::env::set_var("SECLUSO_SKIP_FCM_CONFIG", "1");
-        std::env::set_var("SECLUSO_SKIP_USER_CREDENTIALS", "1");
-        let rocket = build_rocket();
+        let rocket = test_rocket_at("/", StorageQuota::from_env());
         let routes: Vec<_> = rocket.routes().collect();
 
         for spec in BASE_ROUTES {
@@ -1541,6 +3406,104 @@ mod contract_tests {
         }
     }
 
+    #[test]
+    fn mounted_routes_are_base_routes() {
+        let rocket = test_rocket_at("/", StorageQuota::from_env());
+
+        for route in rocket.routes() {
+            assert!(
+                BASE_ROUTES.iter().any(|spec| {
+                    to_rocket_method(spec.method) == route.method && route.uri == spec.path
+                }),
+                "Route missing from BASE_ROUTES: {} {}",
+                route.method,
+                route.uri
+            );
+        }
+    }
+
+    #[test]
+    fn base_routes_are_unique() {
+        let mut seen = HashSet::new();
+        for spec in BASE_ROUTES {
+            assert!(
+                seen.insert((spec.path, spec.method)),
+                "Duplicate route in BASE_ROUTES: {:?} {}",
+                spec.method,
+                spec.path
+            );
+        }
+    }
+
+    /// Every route answers a request (here, rejecting the dummy credentials) instead of a 404.
+    #[test]
+    fn base_routes_respond() {
+        let rocket = test_rocket_at("/", StorageQuota::from_env());
+        // The admin routes are disabled (404) without a token.
+        let figment = rocket
+            .figment()
+            .clone()
+            .merge(("admin_token", "routetesttoken"));
+        let client = Client::tracked(rocket.configure(figment)).expect("valid rocket instance");
+
+        let encoded = base64_engine.encode("routetestuser1:routetestpass1");
+        let basic_auth = Header::new("Authorization", format!("Basic {encoded}"));
+        let admin_auth = Header::new("Authorization", "Bearer dummytoken");
+        let version = Header::new("Client-Version", env!("CARGO_PKG_VERSION"));
+
+        for spec in BASE_ROUTES {
+            let mut uri = spec.path.to_string();
+            for param in spec.params {
+                uri = uri.replace(&format!("<{param}>"), "1");
+            }
+            let auth = if uri.starts_with("/admin/") {
+                admin_auth.clone()
+            } else {
+                basic_auth.clone()
+            };
+
+            let response = client
+                .req(to_rocket_method(spec.method), uri)
+                .header(auth)
+                .header(version.clone())
+                .header(ContentType::JSON)
+                .dispatch();
+            assert_ne!(
+                response.status(),
+                Status::NotFound,
+                "No route for {:?} {}",
+                spec.method,
+                spec.path
+            );
+        }
+    }
+
+    #[test]
+    fn http_methods_round_trip() {
+        for method in [
+            HttpMethod::Get,
+            HttpMethod::Post,
+            HttpMethod::Delete,
+            HttpMethod::Put,
+        ] {
+            let name = method.to_string();
+            assert_eq!(name, to_rocket_method(method).as_str());
+            assert_eq!(name.parse::<HttpMethod>(), Ok(method));
+            assert_eq!(name.to_lowercase().parse::<HttpMethod>(), Ok(method));
+        }
+
+        assert_eq!("pOsT".parse::<HttpMethod>(), Ok(HttpMethod::Post));
+        let e = "PATCH".parse::<HttpMethod>().unwrap_err();
+        assert!(e.to_string().contains("PATCH"));
+        assert!(" GET".parse::<HttpMethod>().is_err());
+
+        assert_eq!(format!("{:?}", HttpMethod::Delete), "Delete");
+        assert_eq!(
+            format!("{:?}", [HttpMethod::Get, HttpMethod::Put]),
+            "[Get, Put]"
+        );
+    }
+
     fn extract_params(path: &str) -> Vec<&str> {
         let path = path.split('?').next().unwrap_or(path);
         let mut params = Vec::new();
@@ -1564,3 +3527,1452 @@ mod contract_tests {
         params
     }
 }
+
+#[cfg(test)]
+mod base_path_tests {
+    use super::test_support::test_rocket_at;
+    use crate::quota::StorageQuota;
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn routes_are_mounted_under_base_path() {
+        let rocket = test_rocket_at("secluso/", StorageQuota::from_env());
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+
+        // The route exists under the prefix (it rejects us since we're not authenticated).
+        let response = client.get("/secluso/status").dispatch();
+        assert_ne!(response.status(), Status::NotFound);
+
+        let response = client.get("/status").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
+
+#[cfg(test)]
+mod server_config_tests {
+    use super::server_config;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn config(args: &[&str]) -> Result<rocket::Config, String> {
+        server_config(args.iter().map(|arg| arg.to_string())).map(|(config, _)| config)
+    }
+
+    #[test]
+    fn tls_flags_are_applied() {
+        let config = config(&[
+            "--network-type",
+            "https",
+            "--tls-cert",
+            "/etc/secluso/cert.pem",
+            "--tls-key=/etc/secluso/key.pem",
+        ])
+        .unwrap();
+
+        let tls = config.tls.as_ref().expect("tls is configured");
+        assert_eq!(
+            tls.certs().left().as_deref(),
+            Some(std::path::Path::new("/etc/secluso/cert.pem"))
+        );
+        assert_eq!(
+            tls.key().left().as_deref(),
+            Some(std::path::Path::new("/etc/secluso/key.pem"))
+        );
+        assert_eq!(config.address, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+
+    #[test]
+    fn https_needs_a_certificate() {
+        assert!(config(&["--network-type=https"]).is_err());
+        assert!(config(&["--network-type=https", "--bind-address=127.0.0.1"]).is_err());
+        assert!(config(&["--tls-cert", "/etc/secluso/cert.pem"]).is_err());
+
+        let config = config(&["--port=8080"]).unwrap();
+        assert!(config.tls.is_none());
+        assert_eq!(config.address, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.port, 8080);
+    }
+}
+
+#[cfg(test)]
+mod quota_tests {
+    use super::test_support::test_client;
+    use crate::quota::StorageQuota;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use std::fs;
+    use std::path::Path;
+
+    // A server with a 1 MiB quota, and the headers to use it as username.
+    fn quota_client(username: &str, password: &str) -> (Client, Header<'static>, Header<'static>) {
+        test_client(username, password, StorageQuota::new(1024 * 1024))
+    }
+
+    #[test]
+    fn uploads_over_quota_are_rejected_until_files_are_deleted() {
+        let username = "quotatestuser1";
+        let (client, auth, version) = quota_client(username, "quotatestpass1");
+        let upload = |filename: &str| {
+            client
+                .post(format!("/quotacam/{filename}/1"))
+                .header(auth.clone())
+                .header(version.clone())
+                .body(vec![0u8; 600 * 1024])
+                .dispatch()
+                .status()
+        };
+
+        assert_eq!(upload("1"), Status::Ok);
+        assert_eq!(upload("2"), Status::PayloadTooLarge);
+        let camera_path = Path::new("data").join(username).join("quotacam");
+        assert!(!camera_path.join("2_tmp").exists());
+
+        let response = client
+            .delete("/quotacam/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(upload("2"), Status::Ok);
+
+        let _ = fs::remove_dir_all(Path::new("data").join(username));
+    }
+
+    #[test]
+    fn talkback_counts_against_the_quota_until_retrieved() {
+        let username = "quotatestuser2";
+        let (client, auth, version) = quota_client(username, "quotatestpass2");
+        // Stands in for a started livestream.
+        let camera_path = Path::new("data").join(username).join("quotatalkcam");
+        fs::create_dir_all(&camera_path).unwrap();
+
+        let upload = |filename: &str| {
+            client
+                .post(format!("/livestream_audio/quotatalkcam/{filename}"))
+                .header(auth.clone())
+                .header(version.clone())
+                .body(vec![0u8; 600 * 1024])
+                .dispatch()
+                .status()
+        };
+
+        assert_eq!(upload("1"), Status::Ok);
+        assert_eq!(upload("2"), Status::PayloadTooLarge);
+        assert!(!camera_path.join("talkback").join("2_tmp").exists());
+
+        // The camera retrieving the chunk frees its space right away.
+        let response = client
+            .get("/livestream_audio/quotatalkcam/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(upload("2"), Status::Ok);
+
+        let _ = fs::remove_dir_all(Path::new("data").join(username));
+    }
+
+    #[test]
+    fn config_responses_count_against_the_quota_until_retrieved() {
+        let username = "quotatestuser3";
+        let (client, auth, version) = quota_client(username, "quotatestpass3");
+        // Stands in for a paired camera whose other files leave room for one response.
+        let camera_path = Path::new("data").join(username).join("quotaconfigcam");
+        fs::create_dir_all(&camera_path).unwrap();
+        fs::write(camera_path.join("filler"), vec![0u8; 1024 * 1024 - 150]).unwrap();
+
+        let upload = || {
+            client
+                .post("/config_response/quotaconfigcam")
+                .header(auth.clone())
+                .header(version.clone())
+                .body(vec![0u8; 100])
+                .dispatch()
+                .status()
+        };
+
+        assert_eq!(upload(), Status::Ok);
+        assert_eq!(upload(), Status::PayloadTooLarge);
+        assert!(!camera_path.join("config_response_tmp").exists());
+
+        // The app retrieving the response frees its space right away.
+        let response = client
+            .get("/config_response/quotaconfigcam")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_bytes().unwrap().len(), 100);
+        assert_eq!(upload(), Status::Ok);
+
+        let _ = fs::remove_dir_all(Path::new("data").join(username));
+    }
+}
+
+#[cfg(test)]
+mod upload_integrity_tests {
+    use super::test_support::test_client;
+    use crate::integrity::CONTENT_SHA256_HEADER;
+    use crate::quota::StorageQuota;
+    use rocket::http::{Header, Status};
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::path::Path;
+
+    fn hex_digest(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    #[test]
+    fn uploads_are_checked_against_the_client_digest() {
+        let username = "digesttestuser";
+        let password = "digesttestpass";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+        let upload = |uri: &str, body: &[u8], digest: Option<String>| {
+            let mut request = client
+                .post(uri.to_string())
+                .header(auth.clone())
+                .header(version.clone())
+                .body(body);
+            if let Some(digest) = digest {
+                request = request.header(Header::new(CONTENT_SHA256_HEADER, digest));
+            }
+            request.dispatch().status()
+        };
+
+        let video = b"encrypted video".to_vec();
+        let camera_path = user_path.join("digestcam");
+
+        // Matching digest
+        assert_eq!(
+            upload("/digestcam/1/1", &video, Some(hex_digest(&video))),
+            Status::Ok
+        );
+        assert_eq!(fs::read(camera_path.join("1")).unwrap(), video);
+
+        // Truncated on the way
+        assert_eq!(
+            upload("/digestcam/2/1", &video[..5], Some(hex_digest(&video))),
+            Status::UnprocessableEntity
+        );
+        assert!(!camera_path.join("2").exists());
+        assert!(!camera_path.join("2_tmp").exists());
+
+        // No digest (older clients)
+        assert_eq!(upload("/digestcam/3/1", &video, None), Status::Ok);
+        assert!(camera_path.join("3").exists());
+
+        // Malformed digest
+        assert_eq!(
+            upload("/digestcam/4/1", &video, Some("not hex".to_string())),
+            Status::BadRequest
+        );
+        assert!(!camera_path.join("4").exists());
+
+        // Livestream chunks are checked too.
+        let response = client
+            .post("/livestream/digestlive")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let live_path = user_path.join("digestlive");
+        assert_eq!(
+            upload(
+                "/livestream/digestlive/1",
+                &video[..5],
+                Some(hex_digest(&video))
+            ),
+            Status::UnprocessableEntity
+        );
+        assert!(!live_path.join("1").exists());
+        assert!(!live_path.join("1_tmp").exists());
+        assert_eq!(
+            upload("/livestream/digestlive/1", &video, Some(hex_digest(&video))),
+            Status::Ok
+        );
+        assert!(live_path.join("1").exists());
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod livestream_chunk_format_tests {
+    use super::test_support::test_client;
+    use super::LIVESTREAM_CHUNK_FORMAT_HEADER;
+    use crate::quota::StorageQuota;
+    use rocket::http::{Header, Status};
+    use std::fs;
+    use std::path::Path;
+
+    /// The camera gets the chunk format that the app asked for, and a placeholder from older apps.
+    #[test]
+    fn chunk_format_is_passed_on_to_the_camera() {
+        let username = "chunkfmttstusr";
+        let password = "chunkfmttstpwd";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let start_and_check = |chunk_format: Option<&str>| {
+            let mut request = client
+                .post("/livestream/chunkfmtcam")
+                .header(auth.clone())
+                .header(version.clone());
+            if let Some(chunk_format) = chunk_format {
+                request = request.header(Header::new(
+                    LIVESTREAM_CHUNK_FORMAT_HEADER,
+                    chunk_format.to_string(),
+                ));
+            }
+            let response = request.dispatch();
+            if response.status() != Status::Ok {
+                return Err(response.status());
+            }
+
+            let response = client
+                .get("/livestream/chunkfmtcam")
+                .header(auth.clone())
+                .header(version.clone())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            Ok(response.into_string().unwrap())
+        };
+
+        assert!(start_and_check(Some("1")).unwrap().contains("data:1\n"));
+        assert!(start_and_check(None)
+            .unwrap()
+            .contains("data:placeholder\n"));
+        assert_eq!(start_and_check(Some("new")), Err(Status::BadRequest));
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod upload_events_tests {
+    use super::test_support::{test_headers, test_rocket};
+    use crate::quota::StorageQuota;
+    use rocket::http::{Header, Status};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use rocket::tokio::io::AsyncReadExt;
+    use rocket::tokio::time::timeout;
+    use secluso_server_backbone::types::UploadEvent;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    // Reads the stream up to the next named event, and returns its name, data and id.
+    async fn next_event(
+        events: &mut LocalResponse<'_>,
+        pending: &mut String,
+    ) -> (String, String, String) {
+        loop {
+            if let Some(end) = pending.find("\n\n") {
+                let block: String = pending.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    block
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(|value| value.trim().to_string())
+                };
+                // Skips the heartbeats.
+                if let Some(event) = field("event:") {
+                    return (
+                        event,
+                        field("data:").unwrap_or_default(),
+                        field("id:").unwrap_or_default(),
+                    );
+                }
+                continue;
+            }
+
+            let mut buf = [0u8; 1024];
+            let n = timeout(Duration::from_secs(5), events.read(&mut buf))
+                .await
+                .expect("no event received")
+                .unwrap();
+            assert!(n > 0, "event stream ended");
+            pending.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+        }
+    }
+
+    #[rocket::async_test]
+    async fn uploads_are_pushed_to_the_event_stream() {
+        let username = "eventstestuser";
+        let password = "eventstestpass";
+        let rocket = test_rocket(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let client = Client::tracked(rocket)
+            .await
+            .expect("valid rocket instance");
+        let (auth, version) = test_headers(username, password);
+
+        let mut events = client
+            .get("/events")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch()
+            .await;
+        assert_eq!(events.status(), Status::Ok);
+
+        // Livestreams don't generate events.
+        let response = client
+            .post("/livestream/eventslive")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .post("/eventscam/1700000000/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .body(b"encrypted video".to_vec())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let mut pending = String::new();
+        let (name, data, _) = next_event(&mut events, &mut pending).await;
+        assert_eq!(name, "upload");
+        let event: UploadEvent = serde_json::from_str(&data).unwrap();
+        assert_eq!(
+            event,
+            UploadEvent {
+                camera: "eventscam".to_string(),
+                filename: "1700000000".to_string(),
+            }
+        );
+
+        // Rejected uploads don't generate events either.
+        let response = client
+            .post("/eventscam/1700000001/0")
+            .header(auth.clone())
+            .header(version.clone())
+            .body(b"encrypted video".to_vec())
+            .dispatch()
+            .await;
+        assert_ne!(response.status(), Status::Ok);
+
+        let response = client
+            .post("/othercam/1700000002/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .body(b"encrypted video".to_vec())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let (name, data, _) = next_event(&mut events, &mut pending).await;
+        assert_eq!(name, "upload");
+        let event: UploadEvent = serde_json::from_str(&data).unwrap();
+        assert_eq!(event.camera, "othercam");
+        assert_eq!(event.filename, "1700000002");
+
+        drop(events);
+        let _ = fs::remove_dir_all(&user_path);
+    }
+
+    #[rocket::async_test]
+    async fn reconnecting_apps_get_the_uploads_they_missed() {
+        let username = "eventstestuser2";
+        let password = "eventstestpass2";
+        let rocket = test_rocket(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let client = Client::tracked(rocket)
+            .await
+            .expect("valid rocket instance");
+        let (auth, version) = test_headers(username, password);
+        let upload = |filename: &'static str| {
+            let request = client
+                .post(format!("/eventscam/{filename}/1"))
+                .header(auth.clone())
+                .header(version.clone())
+                .body(b"encrypted video".to_vec());
+            async move {
+                assert_eq!(request.dispatch().await.status(), Status::Ok);
+            }
+        };
+
+        let mut events = client
+            .get("/events")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch()
+            .await;
+        upload("1700000000").await;
+        let mut pending = String::new();
+        let (name, _, last_event_id) = next_event(&mut events, &mut pending).await;
+        assert_eq!(name, "upload");
+        assert!(!last_event_id.is_empty());
+        drop(events);
+
+        // Uploaded while the app was reconnecting.
+        upload("1700000001").await;
+        upload("1700000002").await;
+
+        let mut events = client
+            .get("/events")
+            .header(auth.clone())
+            .header(version.clone())
+            .header(Header::new("Last-Event-ID", last_event_id))
+            .dispatch()
+            .await;
+        upload("1700000003").await;
+        let mut pending = String::new();
+        for filename in ["1700000001", "1700000002", "1700000003"] {
+            let (name, data, _) = next_event(&mut events, &mut pending).await;
+            assert_eq!(name, "upload");
+            let event: UploadEvent = serde_json::from_str(&data).unwrap();
+            assert_eq!(event.filename, filename);
+        }
+        drop(events);
+
+        // The server can't tell what an app with an unknown id (e.g., from before a restart) missed.
+        let mut events = client
+            .get("/events")
+            .header(auth.clone())
+            .header(version.clone())
+            .header(Header::new("Last-Event-ID", "1-1"))
+            .dispatch()
+            .await;
+        let mut pending = String::new();
+        let (name, _, _) = next_event(&mut events, &mut pending).await;
+        assert_eq!(name, "lagged");
+
+        drop(events);
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod range_tests {
+    use super::test_support::test_client;
+    use crate::quota::StorageQuota;
+    use rocket::http::{Header, Status};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn retrieve_honors_range_requests() {
+        let username = "rangetestuser1";
+        let password = "rangetestpass1";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+
+        let response = client
+            .post("/rangecam/1/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .body(content.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let get = |uri: &str, range: Option<&str>| {
+            let mut request = client
+                .get(uri.to_string())
+                .header(auth.clone())
+                .header(version.clone());
+            if let Some(range) = range {
+                request = request.header(Header::new("Range", range.to_string()));
+            }
+            let response = request.dispatch();
+            let status = response.status();
+            let content_range = response
+                .headers()
+                .get_one("Content-Range")
+                .map(str::to_string);
+            (
+                status,
+                content_range,
+                response.into_bytes().unwrap_or_default(),
+            )
+        };
+
+        // Full download
+        let (status, content_range, body) = get("/rangecam/1", None);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(content_range, None);
+        assert_eq!(body, content);
+
+        // Resuming from the middle of the file
+        let (status, content_range, body) = get("/rangecam/1", Some("bytes=400-"));
+        assert_eq!(status, Status::PartialContent);
+        assert_eq!(content_range.as_deref(), Some("bytes 400-999/1000"));
+        assert_eq!(body, content[400..]);
+
+        let (status, content_range, body) = get("/rangecam/1", Some("bytes=100-199"));
+        assert_eq!(status, Status::PartialContent);
+        assert_eq!(content_range.as_deref(), Some("bytes 100-199/1000"));
+        assert_eq!(body, content[100..200]);
+
+        // Suffix range
+        let (status, content_range, body) = get("/rangecam/1", Some("bytes=-10"));
+        assert_eq!(status, Status::PartialContent);
+        assert_eq!(content_range.as_deref(), Some("bytes 990-999/1000"));
+        assert_eq!(body, content[990..]);
+
+        // Invalid ranges
+        let (status, content_range, _) = get("/rangecam/1", Some("bytes=1000-"));
+        assert_eq!(status, Status::RangeNotSatisfiable);
+        assert_eq!(content_range.as_deref(), Some("bytes */1000"));
+        let (status, _, _) = get("/rangecam/1", Some("bytes=500-100"));
+        assert_eq!(status, Status::RangeNotSatisfiable);
+
+        // Livestream chunks
+        let camera_path = Path::new("data").join(username).join("rangecam");
+        fs::write(camera_path.join("5"), &content).unwrap();
+        let (status, content_range, body) = get("/livestream/rangecam/5", Some("bytes=250-"));
+        assert_eq!(status, Status::PartialContent);
+        assert_eq!(content_range.as_deref(), Some("bytes 250-999/1000"));
+        assert_eq!(body, content[250..]);
+
+        let _ = fs::remove_dir_all(Path::new("data").join(username));
+    }
+}
+
+#[cfg(test)]
+mod list_files_tests {
+    use super::epoch_suffix;
+    use super::test_support::test_client;
+    use crate::quota::StorageQuota;
+    use rocket::http::Status;
+    use secluso_server_backbone::types::PendingFile;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn epoch_suffix_parses_trailing_digits() {
+        assert_eq!(epoch_suffix("12"), Some(12));
+        assert_eq!(epoch_suffix("encVideo7"), Some(7));
+        assert_eq!(epoch_suffix("notes"), None);
+        assert_eq!(epoch_suffix(""), None);
+    }
+
+    #[test]
+    fn lists_pending_files_in_epoch_order() {
+        let username = "listtestuser12";
+        let password = "listtestpass12";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+        let list = |uri: &str| {
+            let response = client
+                .get(uri.to_string())
+                .header(auth.clone())
+                .header(version.clone())
+                .dispatch();
+            let status = response.status();
+            let files = response.into_json::<Vec<PendingFile>>();
+            (status, files)
+        };
+        let names = |files: Option<Vec<PendingFile>>| -> Vec<String> {
+            files.unwrap().into_iter().map(|f| f.filename).collect()
+        };
+
+        // Unknown camera and empty camera directory
+        let (status, files) = list("/list/listcam");
+        assert_eq!(status, Status::Ok);
+        assert!(names(files).is_empty());
+
+        let camera_path = user_path.join("listcam");
+        fs::create_dir_all(&camera_path).unwrap();
+        let (status, files) = list("/list/listcam");
+        assert_eq!(status, Status::Ok);
+        assert!(names(files).is_empty());
+
+        // Mixed filenames, plus bookkeeping files and uploads in progress that aren't listed
+        for name in ["10", "2", "encVideo3", "notes", "1"] {
+            fs::write(camera_path.join(name), name).unwrap();
+        }
+        fs::write(camera_path.join(".2.refcount"), b"1").unwrap();
+        fs::write(camera_path.join("11_tmp"), b"partial").unwrap();
+        fs::create_dir_all(camera_path.join("talkback")).unwrap();
+
+        let (status, files) = list("/list/listcam");
+        assert_eq!(status, Status::Ok);
+        let files = files.unwrap();
+        assert_eq!(
+            files
+                .iter()
+                .map(|f| f.filename.as_str())
+                .collect::<Vec<_>>(),
+            ["1", "2", "encVideo3", "10", "notes"]
+        );
+        assert_eq!(files[2].size, "encVideo3".len() as u64);
+        assert!(files.iter().all(|f| f.modified > 0));
+
+        // Pagination
+        let (_, files) = list("/list/listcam?offset=1&limit=2");
+        assert_eq!(names(files), ["2", "encVideo3"]);
+        let (_, files) = list("/list/listcam?offset=4&limit=2");
+        assert_eq!(names(files), ["notes"]);
+        let (_, files) = list("/list/listcam?offset=10");
+        assert!(names(files).is_empty());
+        let (status, _) = list("/list/listcam?offset=-1");
+        assert_eq!(status, Status::BadRequest);
+
+        // The sandbox checks still apply.
+        let (status, _) = list("/list/..");
+        assert_ne!(status, Status::Ok);
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod bulk_check_tests {
+    use super::test_support::test_client;
+    use crate::quota::StorageQuota;
+    use rocket::http::{ContentType, Status};
+    use secluso_server_backbone::types::GroupTimestamp;
+    use serde_json::json;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn checks_motion_and_thumbnail_files_in_one_batch() {
+        let username = "bulktestuser12";
+        let password = "bulktestpass12";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let motion_path = user_path.join("bulkmotion");
+        let thumbnail_path = user_path.join("bulkthumbnail");
+        fs::create_dir_all(&motion_path).unwrap();
+        fs::create_dir_all(&thumbnail_path).unwrap();
+        fs::write(motion_path.join("12"), vec![0u8; 1200]).unwrap();
+        fs::write(motion_path.join("encVideo13"), vec![0u8; 1300]).unwrap();
+        fs::write(thumbnail_path.join("encThumbnail12"), vec![0u8; 120]).unwrap();
+        fs::write(thumbnail_path.join("13"), vec![0u8; 130]).unwrap();
+
+        let check = |body: serde_json::Value| {
+            let response = client
+                .post("/bulkCheck")
+                .header(auth.clone())
+                .header(version.clone())
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<Vec<GroupTimestamp>>().unwrap()
+        };
+
+        let results = check(json!({"group_names": [
+            {"group_name": "bulkmotion", "epoch_to_check": 12},
+            {"group_name": "bulkmotion", "epoch_to_check": 13, "kind": "motion"},
+            {"group_name": "bulkmotion", "epoch_to_check": 14},
+            {"group_name": "bulkthumbnail", "epoch_to_check": 12, "kind": "thumbnail"},
+            {"group_name": "bulkthumbnail", "epoch_to_check": 13, "kind": "thumbnail"},
+            {"group_name": "bulkthumbnail", "epoch_to_check": 13},
+            {"group_name": "bulkmissing", "epoch_to_check": 12, "kind": "thumbnail"},
+        ]}));
+        let found: Vec<_> = results
+            .iter()
+            .map(|r| (r.group_name.as_str(), r.filename.as_str(), r.size_bytes))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("bulkmotion", "12", 1200),
+                ("bulkmotion", "encVideo13", 1300),
+                ("bulkthumbnail", "encThumbnail12", 120),
+                ("bulkthumbnail", "13", 130),
+                ("bulkthumbnail", "13", 130),
+            ]
+        );
+        assert!(results.iter().all(|r| r.timestamp > 0));
+
+        // The fields added for the new apps come after the ones that old apps read.
+        let response = client
+            .post("/bulkCheck")
+            .header(auth.clone())
+            .header(version.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"group_names": [{"group_name": "bulkmotion", "epoch_to_check": 12}]}"#)
+            .dispatch();
+        let body = response.into_string().unwrap();
+        assert!(body.starts_with(r#"[{"group_name":"bulkmotion","timestamp":"#));
+        assert!(body.ends_with(r#","size_bytes":1200,"filename":"12"}]"#));
+
+        // Unknown kinds are rejected.
+        let response = client
+            .post("/bulkCheck")
+            .header(auth.clone())
+            .header(version.clone())
+            .header(ContentType::JSON)
+            .body(
+                json!({"group_names": [
+                    {"group_name": "bulkmotion", "epoch_to_check": 12, "kind": "audio"},
+                ]})
+                .to_string(),
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod consume_tests {
+    use super::test_support::test_client;
+    use crate::quota::StorageQuota;
+    use rocket::http::Status;
+    use std::fs;
+    use std::io::Read;
+    use std::path::Path;
+
+    #[test]
+    fn consumed_files_are_deleted_only_after_a_complete_download() {
+        let username = "consumetestusr";
+        let password = "consumetestpwd";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+
+        for filename in ["1", "2"] {
+            let response = client
+                .post(format!("/consumecam/{filename}/1"))
+                .header(auth.clone())
+                .header(version.clone())
+                .body(content.clone())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let camera_path = user_path.join("consumecam");
+
+        // Completed download
+        let response = client
+            .post("/consume/consumecam/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_bytes().unwrap(), content);
+        assert!(!camera_path.join("1").exists());
+
+        // It's gone.
+        let response = client
+            .post("/consume/consumecam/1")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // Aborted download
+        let mut response = client
+            .post("/consume/consumecam/2")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut partial = [0u8; 1000];
+        response.read_exact(&mut partial).unwrap();
+        assert_eq!(&partial[..], &content[..1000]);
+        drop(response);
+        assert_eq!(fs::read(camera_path.join("2")).unwrap(), content);
+
+        // Aborted before reading anything
+        let response = client
+            .post("/consume/consumecam/2")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        drop(response);
+        assert!(camera_path.join("2").exists());
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod config_queue_tests {
+    use super::test_support::test_client;
+    use crate::config_queue::MAX_QUEUED_ENTRIES;
+    use crate::quota::StorageQuota;
+    use base64::engine::general_purpose::STANDARD as base64_engine;
+    use base64::Engine;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use std::fs;
+    use std::path::Path;
+
+    // Checks for a command, acknowledging the one with id ack, and returns the command and its id.
+    fn check(
+        client: &Client,
+        auth: &Header<'static>,
+        version: &Header<'static>,
+        camera: &str,
+        ack: Option<&str>,
+    ) -> (Vec<u8>, String) {
+        let mut request = client
+            .get(format!("/config/{camera}"))
+            .header(auth.clone())
+            .header(version.clone());
+        if let Some(ack) = ack {
+            request = request.header(Header::new("Last-Event-ID", ack.to_string()));
+        }
+        let response = request.dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        let field = |name: &str| -> Vec<String> {
+            body.lines()
+                .filter_map(|line| line.strip_prefix(name))
+                .map(|value| value.trim().to_string())
+                .collect()
+        };
+        let (data, id) = (field("data:"), field("id:"));
+        assert_eq!(data.len(), 1);
+        assert_eq!(id.len(), 1);
+        (base64_engine.decode(&data[0]).unwrap(), id[0].clone())
+    }
+
+    #[test]
+    fn back_to_back_commands_and_responses_are_delivered_in_order() {
+        let username = "cfgqueuetstusr";
+        let password = "cfgqueuetstpwd";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let commands: Vec<Vec<u8>> = vec![
+            b"heartbeat".to_vec(),
+            b"settings".to_vec(),
+            b"snapshot".to_vec(),
+        ];
+        for command in &commands {
+            let response = client
+                .post("/config/cfgqueuecam")
+                .header(auth.clone())
+                .header(version.clone())
+                .header(Header::new("X-Command-Size", command.len().to_string()))
+                .body(command.clone())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        let mut ack = None;
+        for command in &commands {
+            let (received, id) = check(&client, &auth, &version, "cfgqueuecam", ack.as_deref());
+            assert_eq!(received, *command);
+            ack = Some(id);
+        }
+
+        // The responses are queued the same way.
+        for command in &commands {
+            let response = client
+                .post("/config_response/cfgqueuecam")
+                .header(auth.clone())
+                .header(version.clone())
+                .body(command.clone())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        for command in &commands {
+            let response = client
+                .get("/config_response/cfgqueuecam")
+                .header(auth.clone())
+                .header(version.clone())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.into_bytes().unwrap(), *command);
+        }
+        let response = client
+            .get("/config_response/cfgqueuecam")
+            .header(auth.clone())
+            .header(version.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+
+    #[test]
+    fn acknowledged_command_is_removed_but_not_the_camera_directory() {
+        let username = "cfgdeletetstus";
+        let password = "cfgdeletetstpw";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let response = client
+            .post("/config/cfgdeletecam")
+            .header(auth.clone())
+            .header(version.clone())
+            .header(Header::new("X-Command-Size", "9"))
+            .body("heartbeat")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let camera_path = user_path.join("cfgdeletecam");
+        let command_path = camera_path.join("command_000001");
+        assert!(command_path.exists());
+
+        let (received, id) = check(&client, &auth, &version, "cfgdeletecam", None);
+        assert_eq!(received, b"heartbeat");
+        // Not acknowledged yet (e.g., the check was interrupted), so it's sent again.
+        assert!(command_path.exists());
+        let (received, _) = check(&client, &auth, &version, "cfgdeletecam", None);
+        assert_eq!(received, b"heartbeat");
+
+        let response = client
+            .post("/config/cfgdeletecam")
+            .header(auth.clone())
+            .header(version.clone())
+            .header(Header::new("X-Command-Size", "8"))
+            .body("settings")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let (received, _) = check(&client, &auth, &version, "cfgdeletecam", Some(&id));
+        assert_eq!(received, b"settings");
+
+        assert!(!command_path.exists());
+        assert!(camera_path.join("command_000002").exists());
+        assert!(camera_path.is_dir());
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+
+    #[test]
+    fn full_command_queue_is_rejected() {
+        let username = "cfgqueuefulusr";
+        let password = "cfgqueuefulpwd";
+        let (client, auth, version) = test_client(username, password, StorageQuota::from_env());
+        let user_path = Path::new("data").join(username);
+
+        let post_command = || {
+            client
+                .post("/config/cfgfullcam")
+                .header(auth.clone())
+                .header(version.clone())
+                .header(Header::new("X-Command-Size", "7"))
+                .body("command")
+                .dispatch()
+                .status()
+        };
+
+        for _ in 0..MAX_QUEUED_ENTRIES {
+            assert_eq!(post_command(), Status::Ok);
+        }
+        assert_eq!(post_command(), Status::TooManyRequests);
+
+        // Acknowledging a command makes room for a new one.
+        let (_, id) = check(&client, &auth, &version, "cfgfullcam", None);
+        assert_eq!(post_command(), Status::TooManyRequests);
+        check(&client, &auth, &version, "cfgfullcam", Some(&id));
+        assert_eq!(post_command(), Status::Ok);
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::test_support::{test_headers, test_rocket};
+    use crate::quota::StorageQuota;
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn checks_over_the_limit_get_429_until_the_bucket_refills() {
+        let username = "ratetestuser12";
+        let password = "ratetestpass12";
+        let rocket = test_rocket(username, password, StorageQuota::from_env());
+        let figment = rocket
+            .figment()
+            .clone()
+            .merge(("rate_limit.checks.per_sec", 4.0))
+            .merge(("rate_limit.checks.burst", 3));
+        let client = Client::tracked(rocket.configure(figment)).expect("valid rocket instance");
+        let (auth, version) = test_headers(username, password);
+        let user_path = Path::new("data").join(username);
+        let list = || {
+            client
+                .get("/list/ratecam")
+                .header(auth.clone())
+                .header(version.clone())
+                .dispatch()
+        };
+
+        for _ in 0..3 {
+            assert_eq!(list().status(), Status::Ok);
+        }
+        let response = list();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("1"));
+
+        // One token every 250ms
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(list().status(), Status::Ok);
+        assert_eq!(list().status(), Status::TooManyRequests);
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+}
+
+#[cfg(test)]
+mod admin_tests {
+    use super::test_support::test_rocket_at;
+    use crate::quota::StorageQuota;
+    use base64::engine::general_purpose::STANDARD as base64_engine;
+    use base64::Engine;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use secluso_server_backbone::types::AdminUser;
+    use std::fs;
+    use std::path::Path;
+
+    fn client(admin_token: Option<&str>) -> Client {
+        let rocket = test_rocket_at("/", StorageQuota::from_env());
+        let rocket = match admin_token {
+            Some(token) => {
+                let figment = rocket.figment().clone().merge(("admin_token", token));
+                rocket.configure(figment)
+            }
+            None => rocket,
+        };
+        Client::tracked(rocket).expect("valid rocket instance")
+    }
+
+    fn admin_header(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {token}"))
+    }
+
+    #[test]
+    fn admin_api_is_disabled_without_a_token() {
+        let client = client(None);
+        let response = client
+            .get("/admin/users")
+            .header(admin_header(""))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn admin_api_requires_the_token() {
+        let client = client(Some("testadmintoken"));
+        assert_eq!(
+            client.get("/admin/users").dispatch().status(),
+            Status::Unauthorized
+        );
+
+        let response = client
+            .post("/admin/users")
+            .header(admin_header("wrongadmintoken"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn rotated_credentials_replace_the_old_ones() {
+        let client = client(Some("testadmintoken"));
+        let admin = admin_header("testadmintoken");
+        let version = Header::new("Client-Version", env!("CARGO_PKG_VERSION"));
+
+        let response = client.post("/admin/users").header(admin.clone()).dispatch();
+        let user: AdminUser = response.into_json().unwrap();
+        let user_path = Path::new("data").join(&user.username);
+        fs::create_dir_all(user_path.join("rotatecam")).unwrap();
+
+        let list_files = |password: &str| {
+            let encoded = base64_engine.encode(format!("{}:{password}", user.username));
+            client
+                .get("/list/rotatecam")
+                .header(Header::new("Authorization", format!("Basic {encoded}")))
+                .header(version.clone())
+                .dispatch()
+                .status()
+        };
+        let rotate = |credentials: String| {
+            client
+                .put(format!("/admin/users/{}", user.username))
+                .header(admin.clone())
+                .body(credentials)
+                .dispatch()
+                .status()
+        };
+
+        // Another username is rejected.
+        assert_eq!(
+            rotate(format!("{}rotatedpass001", "otheruser00001")),
+            Status::BadRequest
+        );
+
+        assert_eq!(
+            rotate(format!("{}rotatedpass001", user.username)),
+            Status::Ok
+        );
+        assert_eq!(list_files(&user.password), Status::Unauthorized);
+        assert_eq!(list_files("rotatedpass001"), Status::Ok);
+        // The user's data is still there.
+        assert!(user_path.join("rotatecam").exists());
+
+        let _ = fs::remove_dir_all(&user_path);
+    }
+
+    #[test]
+    fn users_are_added_listed_and_deleted() {
+        let client = client(Some("testadmintoken"));
+        let admin = admin_header("testadmintoken");
+        let version = Header::new("Client-Version", env!("CARGO_PKG_VERSION"));
+
+        let response = client.post("/admin/users").header(admin.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let user: AdminUser = response.into_json().unwrap();
+        let user_path = Path::new("data").join(&user.username);
+        assert!(user_path.exists());
+
+        let encoded = base64_engine.encode(format!("{}:{}", user.username, user.password));
+        let auth = Header::new("Authorization", format!("Basic {encoded}"));
+        let list_files = || {
+            client
+                .get("/list/admincam")
+                .header(auth.clone())
+                .header(version.clone())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(list_files(), Status::Ok);
+
+        let response = client.get("/admin/users").header(admin.clone()).dispatch();
+        let usernames: Vec<String> = response.into_json().unwrap();
+        assert_eq!(usernames, vec![user.username.clone()]);
+
+        let delete = || {
+            client
+                .delete(format!("/admin/users/{}", user.username))
+                .header(admin.clone())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(delete(), Status::Ok);
+        assert!(!user_path.exists());
+
+        // The credentials stop working right away.
+        assert_eq!(list_files(), Status::Unauthorized);
+
+        let response = client.get("/admin/users").header(admin.clone()).dispatch();
+        let usernames: Vec<String> = response.into_json().unwrap();
+        assert!(usernames.is_empty());
+
+        assert_eq!(delete(), Status::NotFound);
+    }
+}
+
+#[cfg(test)]
+mod request_logging_tests {
+    use super::{routed_camera, RequestId, RequestLogger, REQUEST_ID_LEN};
+    use rocket::local::blocking::Client;
+    use rocket::request::{FromRequest, Outcome};
+    use rocket::Request;
+
+    #[get("/<camera>/probe")]
+    fn camera_probe(camera: &str, req_id: RequestId) -> String {
+        format!("{req_id} {camera}")
+    }
+
+    struct RoutedCamera(Option<String>);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for RoutedCamera {
+        type Error = ();
+
+        async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            Outcome::Success(RoutedCamera(routed_camera(request).map(str::to_string)))
+        }
+    }
+
+    #[get("/other/<camera>")]
+    fn other_probe(routed: RoutedCamera) -> String {
+        routed.0.unwrap_or_default()
+    }
+
+    #[test]
+    fn requests_get_an_id_and_a_camera() {
+        let rocket = rocket::build()
+            .attach(RequestLogger)
+            .mount("/base", routes![camera_probe, other_probe]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+        let get = |uri: &'static str| client.get(uri).dispatch().into_string().unwrap();
+
+        let body = get("/base/front/probe");
+        let (id, camera) = body.split_once(' ').unwrap();
+        assert_eq!(id.len(), REQUEST_ID_LEN);
+        assert_eq!(camera, "front");
+
+        let other_body = get("/base/front/probe");
+        assert_ne!(other_body.split_once(' ').unwrap().0, id);
+
+        // The camera is found wherever it is in the route (after the mount point).
+        assert_eq!(get("/base/other/back"), "back");
+    }
+}
+
+#[cfg(test)]
+mod error_response_tests {
+    use super::test_support::test_rocket;
+    use crate::quota::StorageQuota;
+    use base64::engine::general_purpose::STANDARD as base64_engine;
+    use base64::Engine;
+    use rocket::http::{ContentType, Header, Method, Status};
+    use rocket::local::blocking::{Client, LocalResponse};
+    use secluso_server_backbone::routes::BASE_ROUTES;
+    use secluso_server_backbone::types::ErrorResponse;
+    use secluso_server_backbone::HttpMethod;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::Path;
+
+    fn client(username: &str, password: &str) -> Client {
+        let rocket = test_rocket(username, password, StorageQuota::from_env());
+        let figment = rocket
+            .figment()
+            .clone()
+            .merge(("admin_token", "errortesttoken"));
+        Client::tracked(rocket.configure(figment)).expect("valid rocket instance")
+    }
+
+    fn basic_auth(username: &str, password: &str) -> Header<'static> {
+        let encoded = base64_engine.encode(format!("{username}:{password}"));
+        Header::new("Authorization", format!("Basic {encoded}"))
+    }
+
+    fn version() -> Header<'static> {
+        Header::new("Client-Version", env!("CARGO_PKG_VERSION"))
+    }
+
+    /// Checks that the body has exactly the fields of ErrorResponse, and returns it.
+    fn error_body(response: LocalResponse<'_>) -> ErrorResponse {
+        let status = response.status();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let body: Value = response.into_json().expect("JSON error body");
+
+        let fields = body.as_object().expect("error body is an object");
+        let mut keys: Vec<_> = fields.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["code", "detail", "message"]);
+        assert!(fields["message"].is_string());
+        assert!(fields["detail"].is_null() || fields["detail"].is_string());
+
+        let error: ErrorResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(error.code, status.code);
+        error
+    }
+
+    #[test]
+    fn error_bodies_keep_their_format() {
+        let error = ErrorResponse::new(404, "Not Found");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"code": 404, "message": "Not Found", "detail": null})
+        );
+
+        let error = error.with_detail("no such user");
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#"{"code":404,"message":"Not Found","detail":"no such user"}"#
+        );
+
+        // detail can be left out.
+        let error: ErrorResponse =
+            serde_json::from_str(r#"{"code":500,"message":"Internal Server Error"}"#).unwrap();
+        assert_eq!(error, ErrorResponse::new(500, "Internal Server Error"));
+    }
+
+    /// The errors that no handler produces (here, the rejected credentials) are JSON as well.
+    #[test]
+    fn every_route_returns_json_errors() {
+        let client = client("errortestuser1", "errortestpass1");
+        let basic_auth = basic_auth("errortestuser1", "wrongpassword1");
+        let admin_auth = Header::new("Authorization", "Bearer wrongtoken");
+
+        for spec in BASE_ROUTES {
+            let mut uri = spec.path.to_string();
+            for param in spec.params {
+                uri = uri.replace(&format!("<{param}>"), "1");
+            }
+            let auth = if uri.starts_with("/admin/") {
+                admin_auth.clone()
+            } else {
+                basic_auth.clone()
+            };
+            let method = match spec.method {
+                HttpMethod::Get => Method::Get,
+                HttpMethod::Post => Method::Post,
+                HttpMethod::Delete => Method::Delete,
+                HttpMethod::Put => Method::Put,
+            };
+
+            let response = client
+                .req(method, uri)
+                .header(auth)
+                .header(version())
+                .header(ContentType::JSON)
+                .dispatch();
+            if response.status().class().is_success() {
+                continue;
+            }
+            error_body(response);
+        }
+
+        let error = error_body(client.get("/no/such/route/here").dispatch());
+        assert_eq!(error, ErrorResponse::new(404, "Not Found"));
+    }
+
+    #[test]
+    fn handler_errors_have_a_detail() {
+        let (username, password) = ("errortestuser2", "errortestpass2");
+        let client = client(username, password);
+        let _ = fs::remove_dir_all(Path::new("data").join(username));
+        let auth = basic_auth(username, password);
+
+        let response = client
+            .post("/add_app_request/errortestop")
+            .header(auth.clone())
+            .header(version())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            error_body(response).detail.as_deref(),
+            Some("no app is waiting for this op")
+        );
+
+        let response = client
+            .post("/livestream_audio/errortestcam/1")
+            .header(auth.clone())
+            .header(version())
+            .body("audio")
+            .dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        assert_eq!(
+            error_body(response).detail.as_deref(),
+            Some("Error: Livestream session not started properly.")
+        );
+
+        // None from the route.
+        let response = client
+            .get("/errortestcam/missingfile")
+            .header(auth)
+            .header(version())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(error_body(response), ErrorResponse::new(404, "Not Found"));
+
+        let response = client
+            .delete("/admin/users/nosuchuser")
+            .header(Header::new("Authorization", "Bearer errortesttoken"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(error_body(response).detail.as_deref(), Some("no such user"));
+    }
+}
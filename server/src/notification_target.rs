@@ -20,7 +20,7 @@ use crate::security::check_path_sandboxed;
 
 pub const UNIFIEDPUSH_ALLOWED_HOSTS_ENV: &str = "SECLUSO_UNIFIEDPUSH_ALLOWED_HOSTS";
 const LEGACY_NOTIFICATION_TARGET_FILE: &str = "notification_target.json";
-const NOTIFICATION_TARGETS_DIR: &str = "notification_targets";
+pub(crate) const NOTIFICATION_TARGETS_DIR: &str = "notification_targets";
 const NOTIFICATION_TARGET_FILE_PREFIX: &str = "notification_target_";
 const NOTIFICATION_TARGET_FILE_SUFFIX: &str = ".json";
 
@@ -0,0 +1,345 @@
+//! Push tokens of the user's devices, per push provider (FCM or APNs), and the fan-out of a
+//! notification to all of them.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use rocket::tokio::fs as tokio_fs;
+use rocket::tokio::io::AsyncWriteExt;
+use rocket::tokio::sync::Mutex;
+use secluso_server_backbone::types::PushProvider;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::security::check_path_sandboxed;
+
+const FCM_TOKENS_DIR: &str = "fcm_tokens";
+const APNS_TOKENS_DIR: &str = "apns_tokens";
+
+/// Whether a directory in the user's directory holds push tokens (and not camera data).
+pub(crate) fn is_push_token_dir(name: &str) -> bool {
+    name == FCM_TOKENS_DIR || name == APNS_TOKENS_DIR
+}
+
+// Where the tokens of a provider are stored in the user's directory: one token per file.
+struct TokenFiles {
+    // Single token file from before several devices were supported.
+    legacy_file: Option<&'static str>,
+    dir: &'static str,
+    file_prefix: &'static str,
+}
+
+fn token_files(provider: PushProvider) -> TokenFiles {
+    match provider {
+        PushProvider::Fcm => TokenFiles {
+            legacy_file: Some("fcm_token"),
+            dir: FCM_TOKENS_DIR,
+            file_prefix: "fcm_token_",
+        },
+        PushProvider::Apns => TokenFiles {
+            legacy_file: None,
+            dir: APNS_TOKENS_DIR,
+            file_prefix: "apns_token_",
+        },
+    }
+}
+
+#[derive(Debug)]
+pub enum PushError {
+    /// The device token is no longer valid (e.g., the app was uninstalled or got a new token).
+    Unregistered,
+    /// The provider rejected the notification, or couldn't be reached (after retrying).
+    Failed(String),
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushError::Unregistered => write!(f, "Push token is unregistered"),
+            PushError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for PushError {}
+
+/// A push provider that the server is configured to send notifications through.
+#[rocket::async_trait]
+pub trait PushSender: Send + Sync {
+    const PROVIDER: PushProvider;
+
+    async fn send(&self, device_token: &str, msg: &[u8]) -> Result<(), PushError>;
+}
+
+/// Result of sending a notification to all the devices of a user.
+#[derive(Debug, Default)]
+pub struct FanOut {
+    pub attempted: usize,
+    pub sent: usize,
+    pub unregistered: usize,
+    pub last_error: Option<String>,
+}
+
+impl FanOut {
+    pub fn merge(&mut self, other: FanOut) {
+        self.attempted += other.attempted;
+        self.sent += other.sent;
+        self.unregistered += other.unregistered;
+        if other.last_error.is_some() {
+            self.last_error = other.last_error;
+        }
+    }
+}
+
+/// The providers to send a notification through: the ones that the user has tokens for,
+/// among the ones that the server supports.
+pub fn providers_to_notify(
+    supported: &[PushProvider],
+    with_tokens: &[PushProvider],
+) -> Vec<PushProvider> {
+    supported
+        .iter()
+        .copied()
+        .filter(|provider| with_tokens.contains(provider))
+        .collect()
+}
+
+/// Sends the notification to every token of the sender's provider.
+/// Tokens that the provider reports as unregistered are removed from root,
+/// so that the app registers a new one.
+pub(crate) async fn send_to_all<S: PushSender>(
+    sender: &S,
+    root: &Path,
+    tokens: &[String],
+    msg: &[u8],
+) -> FanOut {
+    let mut fan_out = FanOut::default();
+
+    for token in tokens {
+        fan_out.attempted += 1;
+        match sender.send(token, msg).await {
+            Ok(()) => {
+                fan_out.sent += 1;
+                debug!("{:?} notification sent successfully.", S::PROVIDER);
+            }
+            Err(PushError::Unregistered) => {
+                fan_out.unregistered += 1;
+                debug!("{:?} token is unregistered. Removing it.", S::PROVIDER);
+                if let Err(e) = remove_push_token(root, S::PROVIDER, token).await {
+                    error!("Failed to remove an unregistered push token: {e}");
+                }
+            }
+            Err(e) => {
+                debug!("Failed to send {:?} notification: {}", S::PROVIDER, e);
+                fan_out.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    fan_out
+}
+
+static PUSH_TOKEN_STORE_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+
+// FIXME: we only remove the tokens that the provider reports as unregistered.
+pub(crate) async fn store_push_token(
+    root: &Path,
+    provider: PushProvider,
+    token: &str,
+) -> io::Result<()> {
+    // Two overlapping calls to store_push_token from one app for the same token can
+    // result in duplicates. This lock is used to prevent that.
+    let lock = PUSH_TOKEN_STORE_LOCK.get_or_init(|| Mutex::new(()));
+    let _guard = lock.lock().await;
+
+    tokio_fs::create_dir_all(root).await?;
+
+    let files = token_files(provider);
+    let tokens_dir = root.join(files.dir);
+    check_path_sandboxed(root, &tokens_dir)?;
+    tokio_fs::create_dir_all(&tokens_dir).await?;
+
+    for path in push_token_paths(root, provider).await? {
+        let existing = tokio_fs::read_to_string(&path).await?;
+        if existing.trim() == token {
+            return Ok(());
+        }
+    }
+
+    for index in 1u64.. {
+        let token_path = tokens_dir.join(format!("{}{}", files.file_prefix, index));
+        check_path_sandboxed(root, &token_path)?;
+
+        let mut file = match tokio_fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&token_path)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+
+        file.write_all(token.as_bytes()).await?;
+        file.sync_all().await?;
+        return Ok(());
+    }
+
+    unreachable!()
+}
+
+// The files with the tokens of the user for the provider: the legacy one, if any, then the others.
+async fn push_token_paths(root: &Path, provider: PushProvider) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    if !root.exists() {
+        return Ok(paths);
+    }
+
+    let files = token_files(provider);
+    if let Some(legacy_file) = files.legacy_file {
+        let legacy_token_path = root.join(legacy_file);
+        check_path_sandboxed(root, &legacy_token_path)?;
+        if legacy_token_path.exists() {
+            paths.push(legacy_token_path);
+        }
+    }
+
+    let tokens_dir = root.join(files.dir);
+    check_path_sandboxed(root, &tokens_dir)?;
+
+    if !tokens_dir.exists() {
+        return Ok(paths);
+    }
+
+    let mut entries = tokio_fs::read_dir(&tokens_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+
+        if !file_name.starts_with(files.file_prefix) {
+            continue;
+        }
+
+        let token_path = entry.path();
+        check_path_sandboxed(root, &token_path)?;
+        paths.push(token_path);
+    }
+
+    Ok(paths)
+}
+
+pub(crate) async fn load_push_tokens(
+    root: &Path,
+    provider: PushProvider,
+) -> io::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut seen = HashSet::new();
+
+    for token_path in push_token_paths(root, provider).await? {
+        let token = tokio_fs::read_to_string(token_path).await?;
+        let token = token.trim().to_string();
+
+        if !token.is_empty() && seen.insert(token.clone()) {
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Removes every stored copy of the token.
+pub(crate) async fn remove_push_token(
+    root: &Path,
+    provider: PushProvider,
+    token: &str,
+) -> io::Result<()> {
+    let lock = PUSH_TOKEN_STORE_LOCK.get_or_init(|| Mutex::new(()));
+    let _guard = lock.lock().await;
+
+    for token_path in push_token_paths(root, provider).await? {
+        let existing = tokio_fs::read_to_string(&token_path).await?;
+        if existing.trim() == token {
+            tokio_fs::remove_file(&token_path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_push_tokens, providers_to_notify, store_push_token};
+    use secluso_server_backbone::types::PushProvider;
+    use std::path::PathBuf;
+
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("secluso-push-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn notifies_the_supported_providers_with_tokens() {
+        use PushProvider::{Apns, Fcm};
+
+        assert_eq!(providers_to_notify(&[Fcm, Apns], &[Fcm, Apns]), [Fcm, Apns]);
+        assert_eq!(providers_to_notify(&[Fcm, Apns], &[Apns]), [Apns]);
+        // APNs tokens are ignored when the server has no APNs key.
+        assert_eq!(providers_to_notify(&[Fcm], &[Apns]), []);
+        assert_eq!(providers_to_notify(&[], &[Fcm, Apns]), []);
+    }
+
+    #[rocket::async_test]
+    async fn tokens_are_stored_per_provider() {
+        let root = test_root("providers");
+        store_push_token(&root, PushProvider::Fcm, "fcm-token")
+            .await
+            .unwrap();
+        store_push_token(&root, PushProvider::Apns, "apns-token")
+            .await
+            .unwrap();
+        store_push_token(&root, PushProvider::Apns, "apns-token")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            load_push_tokens(&root, PushProvider::Fcm).await.unwrap(),
+            ["fcm-token"]
+        );
+        assert_eq!(
+            load_push_tokens(&root, PushProvider::Apns).await.unwrap(),
+            ["apns-token"]
+        );
+
+        // The legacy file only holds an FCM token.
+        std::fs::write(root.join("fcm_token"), b"legacy-token").unwrap();
+        assert_eq!(
+            load_push_tokens(&root, PushProvider::Fcm)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            load_push_tokens(&root, PushProvider::Apns)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,244 @@
+//! Per-user storage quotas, across all the cameras of the user.
+//!
+//! The usage of each user is cached and updated on the writes and deletes that go through
+//! the quota, so that we don't walk data/<username> on every request. Files removed
+//! elsewhere (e.g., livestream chunks wiped for a new livestream) are only accounted for when
+//! the usage is recomputed from disk, which happens every QUOTA_RESYNC_INTERVAL or after
+//! invalidate().
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use dashmap::DashMap;
+use rocket::tokio::task;
+use std::env;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const USER_QUOTA_ENV: &str = "SECLUSO_USER_QUOTA_MIB";
+pub const DEFAULT_USER_QUOTA_MIB: u64 = 5 * 1024;
+const QUOTA_RESYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+struct CachedUsage {
+    bytes: u64,
+    synced_at: Instant,
+}
+
+// Cheap to clone: the clones share the cached usage.
+#[derive(Clone)]
+pub struct StorageQuota {
+    limit_bytes: u64,
+    usage: Arc<DashMap<String, CachedUsage>>,
+}
+
+impl StorageQuota {
+    pub fn new(limit_bytes: u64) -> Self {
+        Self {
+            limit_bytes,
+            usage: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Reads the quota (in mebibytes) from SECLUSO_USER_QUOTA_MIB.
+    pub fn from_env() -> Self {
+        let limit_mib = match env::var(USER_QUOTA_ENV) {
+            Ok(value) => value.trim().parse::<u64>().unwrap_or_else(|_| {
+                eprintln!("Invalid {USER_QUOTA_ENV}={value}. Falling back to default {DEFAULT_USER_QUOTA_MIB}.");
+                DEFAULT_USER_QUOTA_MIB
+            }),
+            Err(_) => DEFAULT_USER_QUOTA_MIB,
+        };
+
+        Self::new(limit_mib.saturating_mul(1024 * 1024))
+    }
+
+    /// Makes sure the cached usage of the user is there and recent enough.
+    async fn sync(&self, username: &str, root: &Path) -> io::Result<()> {
+        if let Some(cached) = self.usage.get(username) {
+            if cached.synced_at.elapsed() < QUOTA_RESYNC_INTERVAL {
+                return Ok(());
+            }
+        }
+
+        let root = root.to_path_buf();
+        let bytes = task::spawn_blocking(move || dir_size(&root))
+            .await
+            .map_err(io::Error::other)??;
+        self.usage.insert(
+            username.to_string(),
+            CachedUsage {
+                bytes,
+                synced_at: Instant::now(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Fails with ErrorKind::StorageFull if the user is already at the quota.
+    /// Used before receiving an upload, which is then accounted for with reserve().
+    pub async fn check(&self, username: &str, root: &Path) -> io::Result<()> {
+        self.sync(username, root).await?;
+
+        match self.usage.get(username) {
+            Some(cached) if cached.bytes >= self.limit_bytes => Err(io::Error::new(
+                ErrorKind::StorageFull,
+                "Error: Storage quota exceeded.",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Accounts for a file of old_size bytes (0 for a new file) being replaced by one of new_size bytes.
+    /// Fails with ErrorKind::StorageFull if the user would go over the quota.
+    /// Shrinking a file is always allowed, even when the user is over the quota.
+    pub async fn reserve(
+        &self,
+        username: &str,
+        root: &Path,
+        old_size: u64,
+        new_size: u64,
+    ) -> io::Result<()> {
+        self.sync(username, root).await?;
+
+        let mut cached = self
+            .usage
+            .get_mut(username)
+            .ok_or_else(|| io::Error::other("Storage usage not found"))?;
+        let bytes = cached
+            .bytes
+            .saturating_sub(old_size)
+            .saturating_add(new_size);
+        if new_size > old_size && bytes > self.limit_bytes {
+            return Err(io::Error::new(
+                ErrorKind::StorageFull,
+                "Error: Storage quota exceeded.",
+            ));
+        }
+        cached.bytes = bytes;
+
+        Ok(())
+    }
+
+    /// Accounts for deleted files.
+    pub fn release(&self, username: &str, bytes: u64) {
+        if let Some(mut cached) = self.usage.get_mut(username) {
+            cached.bytes = cached.bytes.saturating_sub(bytes);
+        }
+    }
+
+    /// Recomputes the usage of the user from disk on the next write.
+    pub fn invalidate(&self, username: &str) {
+        self.usage.remove(username);
+    }
+}
+
+/// Total size of the files under path (0 if it doesn't exist).
+pub fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    let mut dirs: Vec<PathBuf> = vec![path.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                total += entry.metadata()?.len();
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dir_size, StorageQuota};
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    fn test_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("secluso-quota-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[rocket::async_test]
+    async fn quota_rejects_uploads_until_files_are_deleted() {
+        let root = test_root("reject");
+        std::fs::create_dir_all(root.join("front")).unwrap();
+        std::fs::write(root.join("front").join("1"), vec![0u8; 60]).unwrap();
+
+        let quota = StorageQuota::new(100);
+
+        // Existing files count against the quota.
+        quota.reserve("user", &root, 0, 40).await.unwrap();
+        let err = quota.reserve("user", &root, 0, 1).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::StorageFull);
+
+        // Other users have their own quota.
+        let other_root = test_root("reject-other");
+        quota.reserve("other", &other_root, 0, 100).await.unwrap();
+
+        // Replacing a file only counts the difference.
+        quota.reserve("user", &root, 40, 40).await.unwrap();
+        assert!(quota.reserve("user", &root, 40, 41).await.is_err());
+
+        // At the quota, uploads are rejected before they're received.
+        assert!(quota.check("user", &root).await.is_err());
+
+        quota.release("user", 60);
+        quota.check("user", &root).await.unwrap();
+        quota.reserve("user", &root, 0, 60).await.unwrap();
+        assert!(quota.reserve("user", &root, 0, 1).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&root);
+        let _ = std::fs::remove_dir_all(&other_root);
+    }
+
+    #[rocket::async_test]
+    async fn invalidate_recomputes_usage_from_disk() {
+        let root = test_root("invalidate");
+        std::fs::create_dir_all(root.join("front")).unwrap();
+        std::fs::write(root.join("front").join("1"), vec![0u8; 90]).unwrap();
+
+        let quota = StorageQuota::new(100);
+        assert!(quota.reserve("user", &root, 0, 20).await.is_err());
+
+        // Deleted without going through the quota (e.g., a livestream wipe).
+        std::fs::remove_dir_all(root.join("front")).unwrap();
+        assert!(quota.reserve("user", &root, 0, 20).await.is_err());
+
+        quota.invalidate("user");
+        quota.reserve("user", &root, 0, 20).await.unwrap();
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn dir_size_counts_nested_files() {
+        let root = test_root("size");
+        std::fs::create_dir_all(root.join("front").join("talkback")).unwrap();
+        std::fs::write(root.join("front").join("1"), vec![0u8; 10]).unwrap();
+        std::fs::write(root.join("front").join(".1.refcount"), b"1").unwrap();
+        std::fs::write(root.join("front").join("talkback").join("2"), vec![0u8; 5]).unwrap();
+        std::fs::write(root.join("fcm_token"), b"token").unwrap();
+
+        assert_eq!(dir_size(&root).unwrap(), 21);
+        assert_eq!(dir_size(&root.join("missing")).unwrap(), 0);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,198 @@
+//! HTTP Range support for file downloads, so that the app can resume an interrupted download
+//! instead of starting over. Only single byte ranges are supported (bytes=start-, bytes=start-end
+//! and bytes=-suffix). Requests with several ranges get the whole file.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, content::RawText, Responder, Response};
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf, Take};
+use rocket::Request;
+use std::io;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The Range header of the request, if any.
+pub struct RangeHeader(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RangeHeader {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RangeHeader(
+            req.headers().get_one("Range").map(str::to_string),
+        ))
+    }
+}
+
+/// Parses a Range header for a file of total bytes.
+/// Returns the first and last byte (inclusive) to send, None to send the whole file,
+/// or an error if the range can't be satisfied.
+pub fn parse_range(header: &str, total: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        // Other units are ignored.
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+
+    let (start, end) = spec.trim().split_once('-').ok_or(())?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // bytes=-suffix: the last suffix bytes.
+        let suffix = end.parse::<u64>().map_err(|_| ())?;
+        if suffix == 0 || total == 0 {
+            return Err(());
+        }
+        return Ok(Some((total.saturating_sub(suffix), total - 1)));
+    }
+
+    let start = start.parse::<u64>().map_err(|_| ())?;
+    if start >= total {
+        return Err(());
+    }
+    let end = if end.is_empty() {
+        total - 1
+    } else {
+        let end = end.parse::<u64>().map_err(|_| ())?;
+        if end < start {
+            return Err(());
+        }
+        end.min(total - 1)
+    };
+
+    Ok(Some((start, end)))
+}
+
+pub enum RangedFile {
+    Full(File),
+    Partial {
+        body: FileRange,
+        start: u64,
+        end: u64,
+        total: u64,
+    },
+    Unsatisfiable {
+        total: u64,
+    },
+}
+
+impl RangedFile {
+    /// Opens the file at path and seeks to the requested range, if any.
+    pub async fn open(path: &Path, range: &RangeHeader) -> io::Result<Self> {
+        let mut file = File::open(path).await?;
+        let Some(header) = range.0.as_deref() else {
+            return Ok(RangedFile::Full(file));
+        };
+
+        let total = file.metadata().await?.len();
+        match parse_range(header, total) {
+            Ok(None) => Ok(RangedFile::Full(file)),
+            Ok(Some((start, end))) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Ok(RangedFile::Partial {
+                    body: FileRange(file.take(end - start + 1)),
+                    start,
+                    end,
+                    total,
+                })
+            }
+            Err(()) => Ok(RangedFile::Unsatisfiable { total }),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for RangedFile {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            RangedFile::Full(file) => Response::build_from(RawText(file).respond_to(req)?)
+                .raw_header("Accept-Ranges", "bytes")
+                .ok(),
+            RangedFile::Partial {
+                body,
+                start,
+                end,
+                total,
+            } => Response::build()
+                .status(Status::PartialContent)
+                .header(ContentType::Plain)
+                .raw_header("Accept-Ranges", "bytes")
+                .raw_header("Content-Range", format!("bytes {start}-{end}/{total}"))
+                .sized_body(Some((end - start + 1) as usize), body)
+                .ok(),
+            RangedFile::Unsatisfiable { total } => Response::build()
+                .status(Status::RangeNotSatisfiable)
+                .raw_header("Content-Range", format!("bytes */{total}"))
+                .ok(),
+        }
+    }
+}
+
+/// Part of a file, already seeked to the start of the range.
+/// Rocket requires sized bodies to be seekable in order to find their size.
+/// We always give it the size, so seeking is never needed.
+pub struct FileRange(Take<File>);
+
+impl AsyncRead for FileRange {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for FileRange {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Seeking within a file range is not supported",
+        ))
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Seeking within a file range is not supported",
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_range;
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        assert_eq!(parse_range("bytes=0-", 10), Ok(Some((0, 9))));
+        assert_eq!(parse_range("bytes=4-", 10), Ok(Some((4, 9))));
+        assert_eq!(parse_range("bytes=2-5", 10), Ok(Some((2, 5))));
+        assert_eq!(parse_range("bytes=2-100", 10), Ok(Some((2, 9))));
+        assert_eq!(parse_range("bytes=-3", 10), Ok(Some((7, 9))));
+        assert_eq!(parse_range("bytes=-30", 10), Ok(Some((0, 9))));
+    }
+
+    #[test]
+    fn ignores_unsupported_ranges() {
+        assert_eq!(parse_range("items=0-5", 10), Ok(None));
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), Ok(None));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert!(parse_range("bytes=10-", 10).is_err());
+        assert!(parse_range("bytes=5-2", 10).is_err());
+        assert!(parse_range("bytes=-0", 10).is_err());
+        assert!(parse_range("bytes=-5", 0).is_err());
+        assert!(parse_range("bytes=abc-", 10).is_err());
+        assert!(parse_range("bytes=", 10).is_err());
+    }
+}
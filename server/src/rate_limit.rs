@@ -0,0 +1,306 @@
+//! Per-user rate limiting, so that an authenticated client stuck in a retry loop can't keep the
+//! server busy. Every user has a token bucket per group of routes (uploads, notifications, and
+//! checks). Requests over the limit get a 429 with a Retry-After header.
+//!
+//! The limits come from the rate_limit table of the Rocket config, e.g., in Rocket.toml:
+//!
+//! [default.rate_limit.checks]
+//! per_sec = 10.0
+//! burst = 50
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::auth::BasicAuth;
+use dashmap::DashMap;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::Deserialize;
+use rocket::{Build, Request, Response, Rocket};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BucketConfig {
+    /// Requests allowed per second in the long run. 0 disables the limit.
+    pub per_sec: f64,
+    /// Requests allowed at once, on top of per_sec.
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RateLimitConfig {
+    pub uploads: BucketConfig,
+    pub notifications: BucketConfig,
+    pub checks: BucketConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            // Livestream chunks come in quickly.
+            uploads: BucketConfig {
+                per_sec: 50.0,
+                burst: 200,
+            },
+            notifications: BucketConfig {
+                per_sec: 1.0,
+                burst: 20,
+            },
+            checks: BucketConfig {
+                per_sec: 10.0,
+                burst: 50,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Uploads,
+    Notifications,
+    Checks,
+}
+
+impl RateLimitConfig {
+    fn bucket(&self, group: RouteGroup) -> BucketConfig {
+        match group {
+            RouteGroup::Uploads => self.uploads,
+            RouteGroup::Notifications => self.notifications,
+            RouteGroup::Checks => self.checks,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: DashMap<(String, RouteGroup), Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Takes a token from the user's bucket for the group.
+    /// Fails with how long to wait for the next token if the bucket is empty.
+    pub fn try_acquire(
+        &self,
+        username: &str,
+        group: RouteGroup,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let limit = self.config.bucket(group);
+        if limit.per_sec <= 0.0 {
+            return Ok(());
+        }
+        let burst = f64::from(limit.burst.max(1));
+
+        let mut bucket = self
+            .buckets
+            .entry((username.to_string(), group))
+            .or_insert_with(|| Bucket {
+                tokens: burst,
+                updated: now,
+            });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_sec).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.per_sec,
+            ))
+        }
+    }
+}
+
+/// Seconds to put in the Retry-After header of a rate-limited request.
+struct RetryAfter(Option<u64>);
+
+pub trait LimitedRoutes: Send + Sync + 'static {
+    const GROUP: RouteGroup;
+}
+
+pub struct Uploads;
+pub struct Notifications;
+pub struct Checks;
+
+impl LimitedRoutes for Uploads {
+    const GROUP: RouteGroup = RouteGroup::Uploads;
+}
+
+impl LimitedRoutes for Notifications {
+    const GROUP: RouteGroup = RouteGroup::Notifications;
+}
+
+impl LimitedRoutes for Checks {
+    const GROUP: RouteGroup = RouteGroup::Checks;
+}
+
+/// Request guard that counts the request against the user's limit for the group G.
+/// It goes after the &BasicAuth guard of the route.
+pub struct RateLimit<G: LimitedRoutes>(PhantomData<G>);
+
+#[rocket::async_trait]
+impl<'r, G: LimitedRoutes> FromRequest<'r> for RateLimit<G> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = match req.guard::<&BasicAuth>().await {
+            Outcome::Success(auth) => auth,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        let Some(limiter) = req.rocket().state::<RateLimiter>() else {
+            return Outcome::Success(RateLimit(PhantomData));
+        };
+
+        match limiter.try_acquire(&auth.username, G::GROUP, Instant::now()) {
+            Ok(()) => Outcome::Success(RateLimit(PhantomData)),
+            Err(wait) => {
+                let secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+                req.local_cache(|| RetryAfter(Some(secs)));
+                Outcome::Error((Status::TooManyRequests, ()))
+            }
+        }
+    }
+}
+
+/// Sets up the RateLimiter from the config and adds Retry-After to the rate-limited responses.
+pub struct RateLimiting;
+
+#[rocket::async_trait]
+impl Fairing for RateLimiting {
+    fn info(&self) -> Info {
+        Info {
+            name: "Per-user rate limiting",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = match rocket
+            .figment()
+            .extract_inner::<RateLimitConfig>("rate_limit")
+        {
+            Ok(config) => config,
+            Err(e) if e.missing() => RateLimitConfig::default(),
+            Err(e) => {
+                error!("Invalid rate_limit config ({e}). Falling back to the defaults.");
+                RateLimitConfig::default()
+            }
+        };
+
+        Ok(rocket.manage(RateLimiter::new(config)))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let RetryAfter(Some(secs)) = request.local_cache(|| RetryAfter(None)) {
+            response.set_raw_header("Retry-After", secs.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BucketConfig, RateLimitConfig, RateLimiter, RouteGroup};
+    use std::time::{Duration, Instant};
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            checks: BucketConfig {
+                per_sec: 2.0,
+                burst: 3,
+            },
+            notifications: BucketConfig {
+                per_sec: 0.0,
+                burst: 0,
+            },
+            ..RateLimitConfig::default()
+        })
+    }
+
+    #[test]
+    fn allows_the_burst_then_refills() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter
+                .try_acquire("user", RouteGroup::Checks, start)
+                .is_ok());
+        }
+        let wait = limiter
+            .try_acquire("user", RouteGroup::Checks, start)
+            .unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter
+            .try_acquire("user", RouteGroup::Checks, later)
+            .is_ok());
+        assert!(limiter
+            .try_acquire("user", RouteGroup::Checks, later)
+            .is_err());
+
+        // The bucket doesn't fill beyond the burst.
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter
+                .try_acquire("user", RouteGroup::Checks, much_later)
+                .is_ok());
+        }
+        assert!(limiter
+            .try_acquire("user", RouteGroup::Checks, much_later)
+            .is_err());
+    }
+
+    #[test]
+    fn buckets_are_per_user_and_group() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter
+                .try_acquire("user1", RouteGroup::Checks, now)
+                .is_ok());
+        }
+        assert!(limiter
+            .try_acquire("user1", RouteGroup::Checks, now)
+            .is_err());
+        assert!(limiter
+            .try_acquire("user2", RouteGroup::Checks, now)
+            .is_ok());
+        assert!(limiter
+            .try_acquire("user1", RouteGroup::Uploads, now)
+            .is_ok());
+    }
+
+    #[test]
+    fn zero_rate_disables_the_limit() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..1000 {
+            assert!(limiter
+                .try_acquire("user", RouteGroup::Notifications, now)
+                .is_ok());
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! One-shot diagnostic for new deployments (--self-test).
+//! Runs the storage and event paths against a temporary user and checks the FCM config,
+//! so that permission/config problems show up before going live.
+//!
+//! SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::auth::UserStore;
+use crate::build_rocket_with_config;
+use crate::fcm;
+use base64::engine::general_purpose::STANDARD as base64_engine;
+use base64::Engine;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::{Build, Rocket};
+use secluso_client_server_lib::auth::{generate_random, NUM_PASSWORD_CHARS, NUM_USERNAME_CHARS};
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+const SELF_TEST_CAMERA: &str = "selftest";
+const SELF_TEST_FILENAME: &str = "1";
+const SELF_TEST_PAYLOAD: &[u8] = b"secluso self-test payload";
+
+pub enum CheckOutcome {
+    Pass,
+    Skip(String),
+    Fail(String),
+}
+
+pub struct SelfTestReport {
+    pub checks: Vec<(&'static str, CheckOutcome)>,
+}
+
+impl SelfTestReport {
+    pub fn all_passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, CheckOutcome::Fail(_)))
+    }
+
+    pub fn print(&self) {
+        for (name, outcome) in &self.checks {
+            match outcome {
+                CheckOutcome::Pass => println!("[PASS] {name}"),
+                CheckOutcome::Skip(reason) => println!("[SKIP] {name}: {reason}"),
+                CheckOutcome::Fail(reason) => println!("[FAIL] {name}: {reason}"),
+            }
+        }
+    }
+}
+
+/// Temporary credentials used for the self-test.
+pub struct SelfTestUser {
+    pub username: String,
+    pub password: String,
+}
+
+impl SelfTestUser {
+    fn new() -> Self {
+        Self {
+            username: generate_random(NUM_USERNAME_CHARS, false),
+            password: generate_random(NUM_PASSWORD_CHARS, false),
+        }
+    }
+
+    fn auth_header(&self) -> Header<'static> {
+        let encoded = base64_engine.encode(format!("{}:{}", self.username, self.password));
+        Header::new("Authorization", format!("Basic {encoded}"))
+    }
+}
+
+/// Runs the self-test, prints the result, and returns the process exit code.
+pub fn run() -> i32 {
+    // The local client runs its own runtime, so it can't run on the launch runtime's thread.
+    let report = thread::spawn(run_self_test)
+        .join()
+        .expect("Self-test thread panicked");
+
+    report.print();
+    if report.all_passed() {
+        println!("Self-test passed.");
+        0
+    } else {
+        println!("Self-test failed.");
+        1
+    }
+}
+
+fn run_self_test() -> SelfTestReport {
+    let fcm_outcome = check_fcm_config();
+
+    // We already checked the FCM config above; don't let the server panic on it.
+    std::env::set_var("SECLUSO_SKIP_FCM_CONFIG", "1");
+    let rocket = build_rocket_with_config(rocket::Config::debug_default(), "/");
+
+    let user = add_temp_user(&rocket);
+
+    let mut report = match Client::untracked(rocket) {
+        Ok(client) => run_checks(&client, &user),
+        Err(e) => SelfTestReport {
+            checks: vec![("server", CheckOutcome::Fail(e.to_string()))],
+        },
+    };
+    report.checks.push(("fcm config", fcm_outcome));
+
+    // Don't leave anything behind for the temporary user.
+    let _ = fs::remove_dir_all(Path::new("data").join(&user.username));
+
+    report
+}
+
+/// Registers a temporary user that only exists in memory for this server instance.
+fn add_temp_user(rocket: &Rocket<Build>) -> SelfTestUser {
+    let user = SelfTestUser::new();
+    rocket
+        .state::<UserStore>()
+        .expect("user store is managed")
+        .insert(user.username.clone(), user.password.clone());
+
+    user
+}
+
+fn check_fcm_config() -> CheckOutcome {
+    if !Path::new("service_account_key.json").exists() {
+        return CheckOutcome::Skip("service_account_key.json not found".to_string());
+    }
+
+    match fcm::fetch_config() {
+        Ok(_) => CheckOutcome::Pass,
+        Err(e) => CheckOutcome::Fail(e.to_string()),
+    }
+}
+
+/// Runs the storage and event checks as the given user.
+pub fn run_checks(client: &Client, user: &SelfTestUser) -> SelfTestReport {
+    SelfTestReport {
+        checks: vec![
+            ("storage", outcome(check_storage(client, user))),
+            ("events", outcome(check_events(client, user))),
+        ],
+    }
+}
+
+fn outcome(result: Result<(), String>) -> CheckOutcome {
+    match result {
+        Ok(()) => CheckOutcome::Pass,
+        Err(e) => CheckOutcome::Fail(e),
+    }
+}
+
+fn expect_ok(step: &str, status: Status) -> Result<(), String> {
+    if status == Status::Ok {
+        Ok(())
+    } else {
+        Err(format!("{step} returned {status}"))
+    }
+}
+
+fn check_storage(client: &Client, user: &SelfTestUser) -> Result<(), String> {
+    let version = Header::new("Client-Version", env!("CARGO_PKG_VERSION"));
+    let file_uri = format!("/{SELF_TEST_CAMERA}/{SELF_TEST_FILENAME}");
+
+    let response = client
+        .post(format!("{file_uri}/1"))
+        .header(version.clone())
+        .header(user.auth_header())
+        .header(ContentType::Binary)
+        .body(SELF_TEST_PAYLOAD)
+        .dispatch();
+    expect_ok("upload", response.status())?;
+
+    let response = client
+        .get(file_uri.clone())
+        .header(version.clone())
+        .header(user.auth_header())
+        .dispatch();
+    expect_ok("retrieve", response.status())?;
+    if response.into_bytes().as_deref() != Some(SELF_TEST_PAYLOAD) {
+        return Err("retrieved data does not match the uploaded data".to_string());
+    }
+
+    let response = client
+        .delete(file_uri.clone())
+        .header(version.clone())
+        .header(user.auth_header())
+        .dispatch();
+    expect_ok("delete", response.status())?;
+
+    let response = client
+        .get(file_uri)
+        .header(version)
+        .header(user.auth_header())
+        .dispatch();
+    if response.status() != Status::NotFound {
+        return Err(format!(
+            "file still retrievable after delete ({})",
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
+
+// A livestream start followed by a livestream check goes through the same broadcast
+// channel and SSE stream the camera uses.
+fn check_events(client: &Client, user: &SelfTestUser) -> Result<(), String> {
+    let version = Header::new("Client-Version", env!("CARGO_PKG_VERSION"));
+    let uri = format!("/livestream/{SELF_TEST_CAMERA}");
+
+    let response = client
+        .post(uri.clone())
+        .header(version.clone())
+        .header(user.auth_header())
+        .dispatch();
+    expect_ok("livestream start", response.status())?;
+
+    let response = client
+        .get(uri)
+        .header(version.clone())
+        .header(user.auth_header())
+        .dispatch();
+    expect_ok("livestream check", response.status())?;
+    let body = response.into_string().unwrap_or_default();
+    if !body.contains("placeholder") {
+        return Err(format!("unexpected event stream: {body:?}"));
+    }
+
+    let response = client
+        .post(format!("/livestream_end/{SELF_TEST_CAMERA}"))
+        .header(version)
+        .header(user.auth_header())
+        .dispatch();
+    expect_ok("livestream end", response.status())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_client() -> (Client, SelfTestUser) {
+        std::env::set_var("SECLUSO_SKIP_FCM_CONFIG", "1");
+        std::env::set_var("SECLUSO_SKIP_USER_CREDENTIALS", "1");
+        let rocket = build_rocket_with_config(rocket::Config::debug_default(), "/");
+        let user = add_temp_user(&rocket);
+        let client = Client::untracked(rocket).expect("valid rocket instance");
+        (client, user)
+    }
+
+    fn outcome_of<'a>(report: &'a SelfTestReport, name: &str) -> &'a CheckOutcome {
+        &report
+            .checks
+            .iter()
+            .find(|(check, _)| *check == name)
+            .unwrap_or_else(|| panic!("Missing check: {name}"))
+            .1
+    }
+
+    #[test]
+    fn all_checks_pass_in_healthy_environment() {
+        let (client, user) = test_client();
+        let report = run_checks(&client, &user);
+        let _ = fs::remove_dir_all(Path::new("data").join(&user.username));
+
+        for (name, outcome) in &report.checks {
+            if let CheckOutcome::Fail(reason) = outcome {
+                panic!("{name} failed: {reason}");
+            }
+        }
+        assert!(report.all_passed());
+    }
+
+    #[test]
+    fn injected_failure_is_reported() {
+        let (client, user) = test_client();
+        // Credentials the server doesn't know about.
+        let unknown_user = SelfTestUser {
+            username: user.username.clone(),
+            password: generate_random(NUM_PASSWORD_CHARS, false),
+        };
+
+        let report = run_checks(&client, &unknown_user);
+
+        assert!(matches!(outcome_of(&report, "storage"), CheckOutcome::Fail(_)));
+        assert!(matches!(outcome_of(&report, "events"), CheckOutcome::Fail(_)));
+        assert!(!report.all_passed());
+    }
+}
@@ -13,20 +13,26 @@ pub mod routes {
     const PARAM_CAMERA_FILENAME: &[&str] = &["camera", "filename"];
     const PARAM_CAMERA_FILENAME_COUNTER: &[&str] = &["camera", "filename", "counter"];
     const PARAM_OP: &[&str] = &["op"];
+    const PARAM_USERNAME: &[&str] = &["username"];
 
     pub const ROUTE_PAIR: &str = "/pair";
     pub const ROUTE_UPLOAD: &str = "/<camera>/<filename>/<counter>";
     pub const ROUTE_BULK_CHECK: &str = "/bulkCheck";
     pub const ROUTE_RETRIEVE: &str = "/<camera>/<filename>";
+    pub const ROUTE_LIST_FILES: &str = "/list/<camera>";
+    pub const ROUTE_CONSUME_FILE: &str = "/consume/<camera>/<filename>";
     pub const ROUTE_DELETE_FILE: &str = "/<camera>/<filename>";
     pub const ROUTE_DELETE_CAMERA: &str = "/<camera>";
     pub const ROUTE_FCM_TOKEN: &str = "/fcm_token";
+    pub const ROUTE_PUSH_TOKEN: &str = "/push_token";
     pub const ROUTE_FCM_NOTIFICATION: &str = "/fcm_notification";
     pub const ROUTE_NOTIFICATION_TARGET: &str = "/notification_target";
     pub const ROUTE_LIVESTREAM_START: &str = "/livestream/<camera>";
     pub const ROUTE_LIVESTREAM_CHECK: &str = "/livestream/<camera>";
     pub const ROUTE_LIVESTREAM_UPLOAD: &str = "/livestream/<camera>/<filename>";
     pub const ROUTE_LIVESTREAM_RETRIEVE: &str = "/livestream/<camera>/<filename>";
+    pub const ROUTE_LIVESTREAM_AUDIO_UPLOAD: &str = "/livestream_audio/<camera>/<filename>";
+    pub const ROUTE_LIVESTREAM_AUDIO_RETRIEVE: &str = "/livestream_audio/<camera>/<filename>";
     pub const ROUTE_LIVESTREAM_END: &str = "/livestream_end/<camera>";
     pub const ROUTE_CONFIG_COMMAND: &str = "/config/<camera>";
     pub const ROUTE_CONFIG_CHECK: &str = "/config/<camera>";
@@ -34,9 +40,34 @@ pub mod routes {
     pub const ROUTE_CONFIG_RESPONSE_RETRIEVE: &str = "/config_response/<camera>";
     pub const ROUTE_FCM_CONFIG: &str = "/fcm_config";
     pub const ROUTE_STATUS: &str = "/status";
+    pub const ROUTE_STATUS_FULL: &str = "/status/full";
+    pub const ROUTE_EVENTS: &str = "/events";
     pub const ROUTE_DEBUG_LOGS: &str = "/debug_logs";
     pub const ROUTE_ADD_APP_CHECK: &str = "/add_app_check/<op>";
     pub const ROUTE_ADD_APP_REQUEST: &str = "/add_app_request/<op>";
+    pub const ROUTE_ADMIN_USERS: &str = "/admin/users";
+    pub const ROUTE_ADMIN_USER: &str = "/admin/users/<username>";
+
+    /// Normalizes a configurable mount prefix for the routes, e.g., "secluso/" -> "/secluso".
+    /// Returns "/" when no prefix is used.
+    pub fn normalize_base_path(raw: &str) -> String {
+        let trimmed = raw.trim().trim_matches('/');
+        if trimmed.is_empty() {
+            "/".to_string()
+        } else {
+            format!("/{}", trimmed)
+        }
+    }
+
+    /// Joins a (possibly empty) base path with one of the route paths above.
+    pub fn route_with_base(base_path: &str, path: &str) -> String {
+        let base = normalize_base_path(base_path);
+        if base == "/" {
+            path.to_string()
+        } else {
+            format!("{}{}", base, path)
+        }
+    }
 
     pub const BASE_ROUTES: &[RouteSpec] = &[
         RouteSpec {
@@ -59,6 +90,16 @@ pub mod routes {
             path: ROUTE_RETRIEVE,
             params: PARAM_CAMERA_FILENAME,
         },
+        RouteSpec {
+            method: HttpMethod::Get,
+            path: ROUTE_LIST_FILES,
+            params: PARAM_CAMERA,
+        },
+        RouteSpec {
+            method: HttpMethod::Post,
+            path: ROUTE_CONSUME_FILE,
+            params: PARAM_CAMERA_FILENAME,
+        },
         RouteSpec {
             method: HttpMethod::Delete,
             path: ROUTE_DELETE_FILE,
@@ -74,6 +115,11 @@ pub mod routes {
             path: ROUTE_FCM_TOKEN,
             params: PARAM_NONE,
         },
+        RouteSpec {
+            method: HttpMethod::Post,
+            path: ROUTE_PUSH_TOKEN,
+            params: PARAM_NONE,
+        },
         RouteSpec {
             method: HttpMethod::Post,
             path: ROUTE_FCM_NOTIFICATION,
@@ -109,6 +155,16 @@ pub mod routes {
             path: ROUTE_LIVESTREAM_RETRIEVE,
             params: PARAM_CAMERA_FILENAME,
         },
+        RouteSpec {
+            method: HttpMethod::Post,
+            path: ROUTE_LIVESTREAM_AUDIO_UPLOAD,
+            params: PARAM_CAMERA_FILENAME,
+        },
+        RouteSpec {
+            method: HttpMethod::Get,
+            path: ROUTE_LIVESTREAM_AUDIO_RETRIEVE,
+            params: PARAM_CAMERA_FILENAME,
+        },
         RouteSpec {
             method: HttpMethod::Post,
             path: ROUTE_LIVESTREAM_END,
@@ -144,6 +200,16 @@ pub mod routes {
             path: ROUTE_STATUS,
             params: PARAM_NONE,
         },
+        RouteSpec {
+            method: HttpMethod::Get,
+            path: ROUTE_STATUS_FULL,
+            params: PARAM_NONE,
+        },
+        RouteSpec {
+            method: HttpMethod::Get,
+            path: ROUTE_EVENTS,
+            params: PARAM_NONE,
+        },
         RouteSpec {
             method: HttpMethod::Post,
             path: ROUTE_DEBUG_LOGS,
@@ -159,6 +225,26 @@ pub mod routes {
             path: ROUTE_ADD_APP_REQUEST,
             params: PARAM_OP,
         },
+        RouteSpec {
+            method: HttpMethod::Post,
+            path: ROUTE_ADMIN_USERS,
+            params: PARAM_NONE,
+        },
+        RouteSpec {
+            method: HttpMethod::Get,
+            path: ROUTE_ADMIN_USERS,
+            params: PARAM_NONE,
+        },
+        RouteSpec {
+            method: HttpMethod::Put,
+            path: ROUTE_ADMIN_USER,
+            params: PARAM_USERNAME,
+        },
+        RouteSpec {
+            method: HttpMethod::Delete,
+            path: ROUTE_ADMIN_USER,
+            params: PARAM_USERNAME,
+        },
     ];
 }
 
@@ -166,10 +252,33 @@ pub mod types {
     use serde::{Deserialize, Serialize};
     use serde_json::Number;
 
+    /// The kind of file checked by ROUTE_BULK_CHECK.
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+    #[serde(rename_all = "lowercase")]
+    pub enum FileKind {
+        #[default]
+        Motion,
+        Thumbnail,
+    }
+
+    impl FileKind {
+        /// Prefix of the filename before the epoch.
+        /// Files named after the epoch alone (as uploaded by the cameras) are found as well.
+        pub fn prefix(&self) -> &'static str {
+            match self {
+                FileKind::Motion => "encVideo",
+                FileKind::Thumbnail => "encThumbnail",
+            }
+        }
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct MotionPair {
         pub group_name: String,
         pub epoch_to_check: Number,
+        /// Missing from old apps, which only check motion videos.
+        #[serde(default)]
+        pub kind: FileKind,
     }
 
     #[derive(Debug, Deserialize)]
@@ -177,10 +286,31 @@ pub mod types {
         pub group_names: Vec<MotionPair>,
     }
 
-    #[derive(Debug, Serialize)]
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
     pub struct GroupTimestamp {
         pub group_name: String,
         pub timestamp: i64,
+        /// Lets the app tell how much it's about to download (e.g., over cellular).
+        pub size_bytes: u64,
+        /// Name of the file found, to be fetched with ROUTE_RETRIEVE.
+        pub filename: String,
+    }
+
+    /// Sent by ROUTE_EVENTS when a file is uploaded with ROUTE_UPLOAD,
+    /// so that the app doesn't need to poll ROUTE_BULK_CHECK without push notifications.
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct UploadEvent {
+        pub camera: String,
+        pub filename: String,
+    }
+
+    /// A file waiting on the server for the app, as listed by ROUTE_LIST_FILES.
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    pub struct PendingFile {
+        pub filename: String,
+        pub size: u64,
+        /// Last modification time (seconds since the Unix epoch).
+        pub modified: i64,
     }
 
     #[derive(Debug, Deserialize)]
@@ -201,6 +331,20 @@ pub mod types {
         pub expires_at_epoch_ms: u64,
     }
 
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+    #[serde(rename_all = "lowercase")]
+    pub enum PushProvider {
+        Fcm,
+        Apns,
+    }
+
+    /// Body of ROUTE_PUSH_TOKEN.
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct PushToken {
+        pub provider: PushProvider,
+        pub token: String,
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct NotificationTarget {
         pub platform: String,
@@ -224,6 +368,101 @@ pub mod types {
     #[derive(Debug, Serialize)]
     pub struct ServerStatus {
         pub ok: bool,
+        /// Only with ?detail=true and valid credentials.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub detail: Option<StatusDetail>,
+    }
+
+    /// Storage and queue state of the authenticated user.
+    #[derive(Debug, Serialize)]
+    pub struct StatusDetail {
+        pub num_cameras: usize,
+        pub pending_bytes: u64,
+        pub cameras: Vec<CameraStatus>,
+    }
+
+    /// Server-wide diagnostics (/status/full), for monitoring and for checking a new deployment.
+    /// Only available with the admin token, like the admin API.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ServerDiagnostics {
+        pub ok: bool,
+        /// Free space on the file system of the data directory, if it could be determined.
+        pub data_free_bytes: Option<u64>,
+        pub num_users: usize,
+        /// Files waiting for the apps, across all users and cameras.
+        pub pending_files: usize,
+        pub fcm_config_loaded: bool,
+    }
+
+    /// Credentials of a user created through the admin API (ROUTE_ADMIN_USERS).
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct AdminUser {
+        pub username: String,
+        pub password: String,
+    }
+
+    /// Body of every error response of the server.
+    /// code is the HTTP status, and detail (null if absent) is meant for logs, not for users.
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct ErrorResponse {
+        pub code: u16,
+        pub message: String,
+        #[serde(default)]
+        pub detail: Option<String>,
+    }
+
+    impl ErrorResponse {
+        pub fn new(code: u16, message: impl Into<String>) -> Self {
+            Self {
+                code,
+                message: message.into(),
+                detail: None,
+            }
+        }
+
+        pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+            self.detail = Some(detail.into());
+            self
+        }
+    }
+
+    // Same statuses as Rocket's responder for io::Error.
+    impl From<std::io::Error> for ErrorResponse {
+        fn from(e: std::io::Error) -> Self {
+            let (code, message) = match e.kind() {
+                std::io::ErrorKind::NotFound => (404, "Not Found"),
+                _ => (500, "Internal Server Error"),
+            };
+            Self::new(code, message).with_detail(e.to_string())
+        }
+    }
+
+    #[cfg(feature = "rocket")]
+    impl From<rocket::http::Status> for ErrorResponse {
+        fn from(status: rocket::http::Status) -> Self {
+            Self::new(status.code, status.reason_lossy())
+        }
+    }
+
+    #[cfg(feature = "rocket")]
+    impl<'r> rocket::response::Responder<'r, 'static> for ErrorResponse {
+        fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+            use rocket::http::Status;
+            use rocket::response::status::Custom;
+            use rocket::response::Responder;
+            use rocket::serde::json::Json;
+
+            let status = Status::from_code(self.code).unwrap_or(Status::InternalServerError);
+            Custom(status, Json(self)).respond_to(req)
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct CameraStatus {
+        pub camera: String,
+        pub pending_files: usize,
+        pub pending_bytes: u64,
+        pub livestream_active: bool,
     }
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -236,6 +475,14 @@ pub mod types {
         pub project_id: String,
         pub storage_bucket: String,
         pub bundle_id: String,
+        /// The push providers that the server can send notifications through.
+        /// Missing from old servers, which only support FCM.
+        #[serde(default = "default_push_providers")]
+        pub push_providers: Vec<PushProvider>,
+    }
+
+    fn default_push_providers() -> Vec<PushProvider> {
+        vec![PushProvider::Fcm]
     }
 
     impl Default for ConfigResponse {
@@ -249,15 +496,66 @@ pub mod types {
                 project_id: String::new(),
                 storage_bucket: String::new(),
                 bundle_id: String::new(),
+                push_providers: default_push_providers(),
             }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum HttpMethod {
     Get,
     Post,
     Delete,
     Put,
 }
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Put => "PUT",
+        }
+    }
+}
+
+impl std::fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error of HttpMethod::from_str for anything but GET, POST, DELETE and PUT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseHttpMethodError(pub String);
+
+impl std::fmt::Display for ParseHttpMethodError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown HTTP method {:?} (expected GET, POST, DELETE or PUT)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseHttpMethodError {}
+
+impl std::str::FromStr for HttpMethod {
+    type Err = ParseHttpMethodError;
+
+    /// Case-insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            HttpMethod::Get,
+            HttpMethod::Post,
+            HttpMethod::Delete,
+            HttpMethod::Put,
+        ]
+        .into_iter()
+        .find(|method| method.as_str().eq_ignore_ascii_case(s))
+        .ok_or_else(|| ParseHttpMethodError(s.to_string()))
+    }
+}
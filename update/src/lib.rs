@@ -5,7 +5,7 @@ use bytes::Bytes;
 use reqwest::blocking::Client;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::fs;
@@ -28,9 +28,19 @@ pub const INSTALL_BIN_DIR: &str = "/usr/bin";
 
 pub const VERSION_ROOT: &str = "/var/lib/secluso/current_version";
 
+// Components that were verified but not installed yet (see save_verified_component).
+pub const VERIFIED_CACHE_ROOT: &str = "/var/lib/secluso/verified";
+
 // Where we store camera secrets, *user credentials*, etc.
 pub const WORKING_DIRECTORY: &str = "/var/lib/secluso";
 
+// Filesystem prefix for INSTALL_BIN_DIR and VERSION_ROOT.
+// Anything other than "/" is meant for rootless, containerized, or test installs.
+pub const DEFAULT_INSTALL_ROOT: &str = "/";
+
+// Environment override for the install root (the --install-root flag takes precedence).
+pub const INSTALL_ROOT_ENV: &str = "SECLUSO_INSTALL_ROOT";
+
 // Where we fetch releases from (unless changed by the program dev settings)
 pub const DEFAULT_OWNER_REPO: &str = "secluso/secluso";
 
@@ -48,6 +58,13 @@ pub struct Signer {
     pub fingerprint: Option<String>,
 }
 
+/// A signer whose signature verified, with the fingerprint of the key that made it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifiedSigner {
+    pub label: String,
+    pub fingerprint: String,
+}
+
 /// Primary use point here two signatures, two github contributors, two different keys.
 const DEFAULT_SIGNERS: [(&str, &str, &str); 2] = [
     (
@@ -71,8 +88,9 @@ pub struct GhRelease {
     #[serde(default)]
     pub draft: bool,
 
+    // Not every GitHub API response includes this field (None when it's absent).
     #[serde(default)]
-    pub immutable: bool,
+    pub immutable: Option<bool>,
 }
 
 impl GhRelease {
@@ -137,8 +155,21 @@ pub struct VerifiedComponent {
     pub latest_version: Version,
     pub manifest_version: String,
     pub component_path: String,
+    pub component_sha256: String,
     pub component_bytes: Vec<u8>,
     pub bundle_bytes: Vec<u8>,
+    /// The signers whose checksum signature verified.
+    pub verified_signers: Vec<VerifiedSigner>,
+}
+
+// Metadata stored next to a cached verified binary.
+#[derive(Debug, Serialize, Deserialize)]
+struct VerifiedCacheEntry {
+    release_tag: String,
+    latest_version: String,
+    manifest_version: String,
+    component_path: String,
+    sha256: String,
 }
 
 #[derive(Debug, Clone)]
@@ -172,11 +203,16 @@ impl Component {
 
             (Self::Updater, "x86_64") => Ok("x86_64-unknown-linux-gnu/secluso-update"),
             (Self::Updater, "aarch64") => Ok("aarch64-unknown-linux-gnu/secluso-update"),
+            (Self::Updater, "arm" | "armv7") => Ok("armv7-unknown-linux-gnueabihf/secluso-update"),
             (Self::Updater, _) => bail!("component=updater not supported on arch={}", arch),
 
             (Self::RaspberryCameraHub, "aarch64") => {
                 Ok("aarch64-unknown-linux-gnu/secluso-camera-hub")
             }
+            // 32-bit Raspberry Pi OS reports "arm" as std::env::consts::ARCH.
+            (Self::RaspberryCameraHub, "arm" | "armv7") => {
+                Ok("armv7-unknown-linux-gnueabihf/secluso-camera-hub")
+            }
             (Self::RaspberryCameraHub, _) => {
                 bail!(
                     "component=raspberry_camera_hub not supported on arch={}",
@@ -186,12 +222,20 @@ impl Component {
 
             (Self::ConfigTool, "x86_64") => Ok("x86_64-unknown-linux-gnu/secluso-config-tool"),
             (Self::ConfigTool, "aarch64") => Ok("aarch64-unknown-linux-gnu/secluso-config-tool"),
+            (Self::ConfigTool, "arm" | "armv7") => {
+                Ok("armv7-unknown-linux-gnueabihf/secluso-config-tool")
+            }
             (Self::ConfigTool, _) => bail!("component=config_tool not supported on arch={}", arch),
         }
     }
 
     /// Where to install on disk
     pub fn install_path(self) -> String {
+        self.install_path_under(DEFAULT_INSTALL_ROOT)
+    }
+
+    /// Where to install on disk, relative to install_root.
+    pub fn install_path_under(self, install_root: &str) -> String {
         let bin = match self {
             Self::Server => "secluso-server",
             Self::Updater => "secluso-update",
@@ -199,11 +243,34 @@ impl Component {
             Self::ConfigTool => "secluso-config-tool",
         };
 
-        format!("{}/{}", INSTALL_BIN_DIR.trim_end_matches('/'), bin)
+        format!("{}/{}", under_install_root(install_root, INSTALL_BIN_DIR), bin)
     }
 
     /// The version file location maintained per-component.
     pub fn version_file(self) -> String {
+        self.version_file_under(DEFAULT_INSTALL_ROOT)
+    }
+
+    /// The version file location maintained per-component, relative to install_root.
+    pub fn version_file_under(self, install_root: &str) -> String {
+        let name = match self {
+            Self::Server => "server",
+            Self::Updater => "updater",
+            Self::RaspberryCameraHub => "raspberry_camera_hub",
+            Self::ConfigTool => "config_tool",
+        };
+
+        format!("{}/{}", under_install_root(install_root, VERSION_ROOT), name)
+    }
+
+    /// Where the tag of a release that was installed and then rolled back is kept, relative to install_root.
+    pub fn failed_release_file_under(self, install_root: &str) -> String {
+        format!("{}.failed", self.version_file_under(install_root))
+    }
+
+    /// Where a verified but not yet installed binary is cached, relative to install_root.
+    /// The metadata lives next to it with a .json extension.
+    pub fn verified_cache_file_under(self, install_root: &str) -> String {
         let name = match self {
             Self::Server => "server",
             Self::Updater => "updater",
@@ -211,10 +278,32 @@ impl Component {
             Self::ConfigTool => "config_tool",
         };
 
-        format!("{}/{}", VERSION_ROOT.trim_end_matches('/'), name)
+        format!(
+            "{}/{}",
+            under_install_root(install_root, VERIFIED_CACHE_ROOT),
+            name
+        )
     }
 }
 
+// Prefixes an absolute system path with the install root (without a trailing slash).
+fn under_install_root(install_root: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        install_root.trim_end_matches('/'),
+        path.trim_start_matches('/').trim_end_matches('/')
+    )
+}
+
+/// The install root from the command line, falling back to INSTALL_ROOT_ENV and then DEFAULT_INSTALL_ROOT.
+pub fn resolve_install_root(flag: Option<&str>) -> String {
+    flag.map(str::to_string)
+        .or_else(|| std::env::var(INSTALL_ROOT_ENV).ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_INSTALL_ROOT.to_string())
+}
+
 pub fn default_signers() -> Vec<Signer> {
     DEFAULT_SIGNERS
         .iter()
@@ -254,6 +343,31 @@ pub fn parse_sig_keys(values: &[String]) -> Result<Vec<Signer>> {
     Ok(signers)
 }
 
+// Parses GITHUB_USER:PATH values into the local keyring file to use for each GitHub user.
+// Used in place of https://github.com/<user>.gpg when verifying without network access.
+pub fn parse_sig_key_files(values: &[String]) -> Result<HashMap<String, PathBuf>> {
+    let mut key_files = HashMap::with_capacity(values.len());
+    for raw in values {
+        let (github_user, path) = raw
+            .split_once(':')
+            .map(|(user, path)| (user.trim(), path.trim()))
+            .filter(|(user, path)| !user.is_empty() && !path.is_empty())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid --sig-key-file value {}. Expected GITHUB_USER:PATH with both non-empty.",
+                    raw
+                )
+            })?;
+        if key_files
+            .insert(github_user.to_string(), PathBuf::from(path))
+            .is_some()
+        {
+            bail!("More than one --sig-key-file given for {}", github_user);
+        }
+    }
+    Ok(key_files)
+}
+
 fn normalize_signer_fingerprint(raw: &str) -> Result<String> {
     Ok(Fingerprint::from_hex(raw)
         .with_context(|| format!("invalid OpenPGP fingerprint {}", raw))?
@@ -303,14 +417,61 @@ pub fn build_github_client(
 // Callers are expected to apply additional policy checks (draft/published/immutable) before trusting
 // the returned release for installation decisions.
 pub fn fetch_latest_release(client: &Client, owner_repo: &str) -> Result<GhRelease> {
-    let url = format!(
-        "https://api.github.com/repos/{}/releases/latest",
-        owner_repo
-    );
+    fetch_latest_release_from(client, "https://api.github.com", owner_repo)
+}
+
+pub fn fetch_latest_release_from(
+    client: &Client,
+    api_base_url: &str,
+    owner_repo: &str,
+) -> Result<GhRelease> {
+    let url = format!("{}/repos/{}/releases/latest", api_base_url, owner_repo);
     let resp = client.get(&url).send()?.error_for_status()?;
     Ok(resp.json::<GhRelease>()?)
 }
 
+// GitHub's default (and our) page size for the releases list API.
+pub const GITHUB_RELEASES_PER_PAGE: usize = 30;
+
+// Lists up to `count` releases, newest first, following the API's pagination.
+// Unlike fetch_latest_release, this includes drafts (if the token can see them) and prereleases.
+pub fn list_releases(client: &Client, owner_repo: &str, count: usize) -> Result<Vec<GhRelease>> {
+    list_releases_from(client, "https://api.github.com", owner_repo, count)
+}
+
+fn list_releases_from(
+    client: &Client,
+    api_base_url: &str,
+    owner_repo: &str,
+    count: usize,
+) -> Result<Vec<GhRelease>> {
+    let mut releases = Vec::new();
+    let mut page = 1;
+
+    while releases.len() < count {
+        let url = format!(
+            "{}/repos/{}/releases?per_page={}&page={}",
+            api_base_url, owner_repo, GITHUB_RELEASES_PER_PAGE, page
+        );
+        let resp = client
+            .get(&url)
+            .send()
+            .with_context(|| format!("Fetching releases page {}", page))?
+            .error_for_status()?;
+        let page_releases = resp.json::<Vec<GhRelease>>()?;
+        let last_page = page_releases.len() < GITHUB_RELEASES_PER_PAGE;
+
+        releases.extend(page_releases);
+        if last_page {
+            break;
+        }
+        page += 1;
+    }
+
+    releases.truncate(count);
+    Ok(releases)
+}
+
 // Enforces the full trust chain before returning any installable bytes.
 // 1) release policy checks (published, non-draft, immutable)
 // 2) detached signature verification over the top-level checksum file
@@ -325,6 +486,7 @@ pub fn download_and_verify_component(
     arch: &str,
     bundle_path: Option<&str>,
     signers: &[Signer],
+    sig_threshold: Option<usize>,
 ) -> Result<VerifiedComponent> {
     download_and_verify_component_with_key_base(
         client,
@@ -333,6 +495,7 @@ pub fn download_and_verify_component(
         arch,
         bundle_path,
         signers,
+        sig_threshold,
         "https://github.com",
     )
 }
@@ -381,11 +544,12 @@ fn download_and_verify_release_asset_to_path_with_key_base(
         .cloned()
         .ok_or_else(|| anyhow!("could not find runtime bundle zip asset in latest release"))?;
 
-    let checksums = verified_release_checksums_for_bundle(
+    let (checksums, _verified_signers) = verified_release_checksums_for_bundle(
         client,
         release,
         &bundle_asset,
         signers,
+        None,
         key_base_url,
     )?;
     let expected = checksums
@@ -418,21 +582,21 @@ fn download_and_verify_release_asset_to_path_with_key_base(
     })
 }
 
-fn download_and_verify_component_with_key_base(
+// Same as download_and_verify_component, with signer keys fetched from key_base_url/<user>.gpg.
+pub fn download_and_verify_component_with_key_base(
     client: &Client,
     release: &GhRelease,
     component: Component,
     arch: &str,
     bundle_path: Option<&str>,
     signers: &[Signer],
+    sig_threshold: Option<usize>,
     key_base_url: &str,
 ) -> Result<VerifiedComponent> {
     // Refuse mutable or unpublished releases up front. This prevents installing from states that can
     // still change after metadata is fetched.
     require_release_is_immutable(release)?;
 
-    let latest_version = release.parsed_version()?;
-
     // Source selection policy:
     // - The checksum file is a top-level release asset, and its detached signatures are top-level release assets too.
     //    The zip itself contains no .asc files.
@@ -465,22 +629,47 @@ fn download_and_verify_component_with_key_base(
         downloaded
     };
 
-    let checksums = verified_release_checksums_for_bundle(
+    let (checksums, verified_signers) = verified_release_checksums_for_bundle(
         client,
         release,
         &bundle_asset,
         signers,
+        sig_threshold,
         key_base_url,
     )?;
 
+    verify_component_bundle(
+        release,
+        component,
+        arch,
+        &bundle_asset.name,
+        zip_bytes,
+        &checksums,
+        verified_signers,
+    )
+}
+
+// Steps 3 and 4 of download_and_verify_component, given the checksums from the signed checksum file.
+// Only works on the bytes passed in, so it can run against a local bundle without any network access.
+pub fn verify_component_bundle(
+    release: &GhRelease,
+    component: Component,
+    arch: &str,
+    bundle_name: &str,
+    zip_bytes: Bytes,
+    checksums: &HashMap<String, String>,
+    verified_signers: Vec<VerifiedSigner>,
+) -> Result<VerifiedComponent> {
+    let latest_version = release.parsed_version()?;
+
     let expected_zip_sha = checksums
-        .get(bundle_asset.name.as_str())
-        .ok_or_else(|| anyhow!("checksum file missing entry for {}", bundle_asset.name))?;
+        .get(bundle_name)
+        .ok_or_else(|| anyhow!("checksum file missing entry for {}", bundle_name))?;
     let got_zip_sha = sha256_hex(&zip_bytes);
     if expected_zip_sha != &got_zip_sha {
         bail!(
             "sha256 mismatch for {}: expected={}, got={}",
-            bundle_asset.name,
+            bundle_name,
             expected_zip_sha,
             got_zip_sha
         );
@@ -537,11 +726,136 @@ fn download_and_verify_component_with_key_base(
         latest_version,
         manifest_version: art.version.trim().to_string(),
         component_path: target_path,
+        component_sha256: got,
         component_bytes: target_bytes,
         bundle_bytes: zip_bytes.to_vec(),
+        verified_signers,
     })
 }
 
+// Same checks as download_and_verify_component, without any network access. The release is identified by the
+// bundle's file name (secluso-runtime-vX.Y.Z.zip), the checksum file and its .asc signatures are read from next to
+// the bundle (they can't be inside it, since they cover its sha256), and each signer's keys come from key_files
+// (GitHub user -> armored keyring file).
+// Any bundle with valid signatures is accepted, whether or not its release is still the latest one.
+pub fn verify_local_bundle(
+    bundle_path: &Path,
+    component: Component,
+    arch: &str,
+    signers: &[Signer],
+    sig_threshold: Option<usize>,
+    key_files: &HashMap<String, PathBuf>,
+) -> Result<VerifiedComponent> {
+    let bundle_name = bundle_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| is_bundle_zip_asset(n))
+        .ok_or_else(|| {
+            anyhow!(
+                "{} is not named like a release bundle (secluso-runtime-vX.Y.Z.zip)",
+                bundle_path.display()
+            )
+        })?;
+    let tag_name = bundle_name
+        .trim_start_matches("secluso-runtime-")
+        .trim_end_matches(".zip");
+    let release = GhRelease {
+        tag_name: tag_name.to_string(),
+        assets: Vec::new(),
+        published_at: None,
+        draft: false,
+        immutable: None,
+    };
+    release
+        .parsed_version()
+        .with_context(|| format!("{} does not name a release version", bundle_name))?;
+
+    let zip_bytes = Bytes::from(
+        fs::read(bundle_path)
+            .with_context(|| format!("Failed reading bundle at {}", bundle_path.display()))?,
+    );
+
+    let checksum_name = checksum_asset_name_for_bundle(bundle_name)?;
+    let checksum_bytes = read_local_release_file(bundle_path, &checksum_name)?;
+
+    let (checksums, verified_signers) = verify_checksum_signatures(
+        &checksum_bytes,
+        &checksum_name,
+        signers,
+        sig_threshold,
+        |sig_name| read_local_release_file(bundle_path, sig_name),
+        |github_user| {
+            let path = key_files
+                .get(github_user)
+                .ok_or_else(|| anyhow!("no --sig-key-file given for {}", github_user))?;
+            let body = fs::read(path)
+                .with_context(|| format!("Failed reading keyring at {}", path.display()))?;
+            parse_keyring(&body, &path.display().to_string())
+        },
+    )?;
+
+    verify_component_bundle(
+        &release,
+        component,
+        arch,
+        bundle_name,
+        zip_bytes,
+        &checksums,
+        verified_signers,
+    )
+}
+
+// Reads a checksum or signature file that sits next to a local bundle.
+fn read_local_release_file(bundle_path: &Path, name: &str) -> Result<Vec<u8>> {
+    let path = bundle_path.with_file_name(name);
+    fs::read(&path).with_context(|| format!("Failed reading {} next to the bundle", path.display()))
+}
+
+// The target binary is the only artifact download_and_verify_component checks. This checks the rest
+// of the bundle too, so that a bundle with a swapped non-target binary is caught even if the target matches.
+// The manifest comes from the bundle that download_and_verify_component already authenticated.
+// Returns the paths of the artifacts checked, or an error listing every mismatch.
+pub fn verify_all_bundle_artifacts(verified: &VerifiedComponent) -> Result<Vec<String>> {
+    if verified.bundle_bytes.is_empty() {
+        bail!("no bundle available to verify (cached component?)");
+    }
+
+    let mut zip = ZipArchive::new(Cursor::new(Bytes::from(verified.bundle_bytes.clone())))
+        .context("Failed to parse zip archive")?;
+
+    let manifest_bytes =
+        read_zip_file(&mut zip, MANIFEST_PATH).context("Missing manifest.json in bundle")?;
+    let manifest: Manifest =
+        serde_json::from_slice(&manifest_bytes).context("manifest.json is not valid JSON")?;
+
+    let mut problems = Vec::new();
+    for art in &manifest.artifacts {
+        match read_zip_file(&mut zip, &art.bin_path) {
+            Ok(bytes) => {
+                let got = sha256_hex(&bytes);
+                if normalize_hex(&art.sha256) != got {
+                    problems.push(format!(
+                        "sha256 mismatch for {}: expected={}, got={}",
+                        art.bin_path, art.sha256, got
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("{}: {}", art.bin_path, e)),
+        }
+    }
+
+    if !problems.is_empty() {
+        bail!(
+            "{} of {} bundle artifacts failed verification:\n{}",
+            problems.len(),
+            manifest.artifacts.len(),
+            problems.join("\n")
+        );
+    }
+
+    Ok(manifest.artifacts.into_iter().map(|a| a.bin_path).collect())
+}
+
 fn is_bundle_zip_asset(name: &str) -> bool {
     name.starts_with("secluso-runtime-v") && name.ends_with(".zip")
 }
@@ -594,33 +908,84 @@ fn verified_release_checksums_for_bundle(
     release: &GhRelease,
     bundle_asset: &GhAsset,
     signers: &[Signer],
+    sig_threshold: Option<usize>,
     key_base_url: &str,
-) -> Result<HashMap<String, String>> {
+) -> Result<(HashMap<String, String>, Vec<VerifiedSigner>)> {
     // The checksum file is a top-level release asset, each required signer has a detached .asc signature beside it, and the payload is verified against the signers GitHub-published keys before any checksum entry is trusted.
     // GitHub's user GPG key API used for signer key discovery is documented here: https://docs.github.com/en/rest/users/gpg-keys?apiVersion=2026-03-10
     let checksum_asset_name = checksum_asset_name_for_bundle(&bundle_asset.name)?;
     let checksum_asset = find_release_asset(release, &checksum_asset_name)?;
     let checksum_bytes = fetch_release_asset_bytes(client, &checksum_asset)?;
+
+    verify_checksum_signatures(
+        &checksum_bytes,
+        &checksum_asset.name,
+        signers,
+        sig_threshold,
+        |sig_name| {
+            let sig_asset = find_release_asset(release, sig_name)?;
+            fetch_release_asset_bytes(client, &sig_asset)
+                .map(|bytes| bytes.to_vec())
+                .with_context(|| format!("Downloading checksum signature {}", sig_name))
+        },
+        |github_user| fetch_github_user_keyring(client, github_user, key_base_url),
+    )
+}
+
+// Verifies each signer's detached signature on the checksum file and returns the parsed checksums.
+// read_sig returns the bytes of a signature file by name and keyring returns a GitHub user's keys,
+// so the same checks run on release assets and on local files.
+fn verify_checksum_signatures(
+    checksum_bytes: &[u8],
+    checksum_name: &str,
+    signers: &[Signer],
+    sig_threshold: Option<usize>,
+    mut read_sig: impl FnMut(&str) -> Result<Vec<u8>>,
+    mut keyring: impl FnMut(&str) -> Result<(Vec<Cert>, HashSet<Fingerprint>)>,
+) -> Result<(HashMap<String, String>, Vec<VerifiedSigner>)> {
     let required_signers = effective_signers(signers);
+    let required = required_signatures(required_signers.len(), sig_threshold)?;
 
+    // With a threshold below the number of signers, a missing signature file only counts as a failed signer.
     let mut sigs: Vec<(Signer, Vec<u8>)> = Vec::with_capacity(required_signers.len());
+    let mut outcomes: Vec<(Signer, Result<String>)> = Vec::new();
     for signer in &required_signers {
-        let sig_name = checksum_sig_asset_name_for(&checksum_asset.name, &signer.label);
-        let sig_asset = find_release_asset(release, &sig_name)?;
-        let sig_bytes = fetch_release_asset_bytes(client, &sig_asset)
-            .with_context(|| format!("Downloading checksum signature {}", sig_name))?;
-        sigs.push((signer.clone(), sig_bytes.to_vec()));
+        let sig_name = checksum_sig_asset_name_for(checksum_name, &signer.label);
+        match read_sig(&sig_name) {
+            Ok(sig_bytes) => sigs.push((signer.clone(), sig_bytes)),
+            Err(e) if required < required_signers.len() => {
+                outcomes.push((signer.clone(), Err(e)))
+            }
+            Err(e) => return Err(e),
+        }
     }
 
-    verify_signed_payload_with_github_keys(
-        client,
-        &checksum_bytes,
+    // Without a threshold, any failure above has already been returned as an error.
+    let tolerate_failures = required < required_signers.len();
+    outcomes.extend(verify_signed_payload(
+        checksum_bytes,
         &sigs,
-        key_base_url,
-        &checksum_asset.name,
-    )?;
+        &mut keyring,
+        checksum_name,
+        tolerate_failures,
+    )?);
+    if tolerate_failures {
+        check_signature_threshold(&outcomes, required, checksum_name)?;
+    }
+
+    let verified_signers = outcomes
+        .into_iter()
+        .filter_map(|(signer, result)| {
+            result.ok().map(|fingerprint| VerifiedSigner {
+                label: signer.label,
+                fingerprint,
+            })
+        })
+        .collect();
+    let checksums =
+        parse_sha256sums(checksum_bytes).with_context(|| format!("Parsing {}", checksum_name))?;
 
-    parse_sha256sums(&checksum_bytes).with_context(|| format!("Parsing {}", checksum_asset.name))
+    Ok((checksums, verified_signers))
 }
 
 fn download_asset_to_path_and_hash(
@@ -770,7 +1135,16 @@ fn normalize_checksum_path(path: &str) -> String {
 // immutable=true plus non-draft/non-null published_at prevents update/install decisions from using
 // mutable pre-release states. Essentially a defense against race conditions where release assets or
 // metadata could change between discovery and installation.
+// If the API response doesn't include the immutable field at all, a published non-draft release is
+// accepted with a warning. Use require_release_is_immutable_with_policy() to refuse it instead.
 pub fn require_release_is_immutable(release: &GhRelease) -> Result<()> {
+    require_release_is_immutable_with_policy(release, false)
+}
+
+pub fn require_release_is_immutable_with_policy(
+    release: &GhRelease,
+    require_immutable_field: bool,
+) -> Result<()> {
     if release.draft {
         bail!(
             "Refusing update: latest release {} is a draft.",
@@ -783,11 +1157,20 @@ pub fn require_release_is_immutable(release: &GhRelease) -> Result<()> {
             release.tag_name
         );
     }
-    if !release.immutable {
-        bail!(
+    match release.immutable {
+        Some(true) => {}
+        Some(false) => bail!(
             "Refusing update: latest release {} is not marked immutable by GitHub (immutable=false).",
             release.tag_name
-        );
+        ),
+        None if require_immutable_field => bail!(
+            "Refusing update: GitHub did not report whether release {} is immutable (missing immutable field).",
+            release.tag_name
+        ),
+        None => eprintln!(
+            "Warning: GitHub did not report whether release {} is immutable; accepting it since it is published and not a draft.",
+            release.tag_name
+        ),
     }
     Ok(())
 }
@@ -889,11 +1272,15 @@ fn fetch_github_user_keyring(
 ) -> Result<(Vec<Cert>, HashSet<Fingerprint>)> {
     let url = format!("{}/{}.gpg", key_base_url.trim_end_matches('/'), user);
     let body = client.get(&url).send()?.error_for_status()?.bytes()?;
+    parse_keyring(&body, &url)
+}
 
+// Parses an armored keyring (as published at https://github.com/<user>.gpg) into its certs and all of their key fingerprints.
+fn parse_keyring(body: &[u8], source: &str) -> Result<(Vec<Cert>, HashSet<Fingerprint>)> {
     let mut certs = Vec::new();
     let mut fps = HashSet::new();
 
-    let mut parser = openpgp::cert::CertParser::from_bytes(&body)?;
+    let mut parser = openpgp::cert::CertParser::from_bytes(body)?;
     while let Some(cert) = parser.next().transpose()? {
         for ka in cert.keys() {
             fps.insert(ka.key().fingerprint());
@@ -902,7 +1289,7 @@ fn fetch_github_user_keyring(
     }
 
     if certs.is_empty() {
-        bail!("No OpenPGP certs found at {}", url);
+        bail!("No OpenPGP certs found at {}", source);
     }
 
     Ok((certs, fps))
@@ -938,72 +1325,146 @@ impl VerificationHelper for Helper {
     }
 }
 
-fn verify_signed_payload_with_github_keys(
-    client: &Client,
+fn verify_signed_payload(
     payload: &[u8],
     sigs: &[(Signer, Vec<u8>)],
-    key_base_url: &str,
+    keyring: &mut dyn FnMut(&str) -> Result<(Vec<Cert>, HashSet<Fingerprint>)>,
     payload_name: &str,
-) -> Result<()> {
+    tolerate_failures: bool,
+) -> Result<Vec<(Signer, Result<String>)>> {
     let mut key_cache: HashMap<String, (Vec<Cert>, HashSet<Fingerprint>)> = HashMap::new();
+    let mut outcomes = Vec::with_capacity(sigs.len());
 
     for (signer, sig_bytes) in sigs {
-        let (certs, fetched_fprs) = match key_cache.get(&signer.github_user) {
-            Some(v) => v.clone(),
-            None => {
-                let v = fetch_github_user_keyring(client, &signer.github_user, key_base_url)?;
-                key_cache.insert(signer.github_user.clone(), v.clone());
-                v
-            }
-        };
-        let allowed_fprs = if let Some(required_fpr_hex) = signer.fingerprint.as_deref() {
-            let required_fpr = Fingerprint::from_hex(required_fpr_hex).with_context(|| {
-                format!(
-                    "configured signer fingerprint is invalid (label={}, github_user={})",
-                    signer.label, signer.github_user
-                )
-            })?;
-
-            if !fetched_fprs.contains(&required_fpr) {
-                bail!(
-                    "Configured fingerprint {} was not found in {}'s GitHub keyring (label={})",
-                    required_fpr.to_hex(),
-                    signer.github_user,
-                    signer.label
-                );
-            }
-
-            HashSet::from([required_fpr])
-        } else {
-            fetched_fprs
-        };
-
-        verify_detached_sig_requires_user(
+        let result = verify_signer(
             payload,
+            signer,
             sig_bytes,
-            &certs,
-            &allowed_fprs,
-            &signer.github_user,
-            &signer.label,
-        )
-        .with_context(|| {
+            keyring,
+            payload_name,
+            &mut key_cache,
+        );
+        if !tolerate_failures {
+            outcomes.push((signer.clone(), Ok(result?)));
+        } else {
+            outcomes.push((signer.clone(), result));
+        }
+    }
+
+    Ok(outcomes)
+}
+
+fn verify_signer(
+    payload: &[u8],
+    signer: &Signer,
+    sig_bytes: &[u8],
+    keyring: &mut dyn FnMut(&str) -> Result<(Vec<Cert>, HashSet<Fingerprint>)>,
+    payload_name: &str,
+    key_cache: &mut HashMap<String, (Vec<Cert>, HashSet<Fingerprint>)>,
+) -> Result<String> {
+    let (certs, fetched_fprs) = match key_cache.get(&signer.github_user) {
+        Some(v) => v.clone(),
+        None => {
+            let v = keyring(&signer.github_user)?;
+            key_cache.insert(signer.github_user.clone(), v.clone());
+            v
+        }
+    };
+    let allowed_fprs = if let Some(required_fpr_hex) = signer.fingerprint.as_deref() {
+        let required_fpr = Fingerprint::from_hex(required_fpr_hex).with_context(|| {
             format!(
-                "Signature verification failed for {} (label={}, github_user={}, fingerprint={})",
-                payload_name,
-                signer.label,
-                signer.github_user,
-                signer.fingerprint.as_deref().unwrap_or("<any>")
+                "configured signer fingerprint is invalid (label={}, github_user={})",
+                signer.label, signer.github_user
             )
         })?;
+
+        if !fetched_fprs.contains(&required_fpr) {
+            bail!(
+                "Configured fingerprint {} was not found in {}'s GitHub keyring (label={})",
+                required_fpr.to_hex(),
+                signer.github_user,
+                signer.label
+            );
+        }
+
+        HashSet::from([required_fpr])
+    } else {
+        fetched_fprs
+    };
+
+    verify_detached_sig_requires_user(
+        payload,
+        sig_bytes,
+        &certs,
+        &allowed_fprs,
+        &signer.github_user,
+        &signer.label,
+    )
+    .with_context(|| {
+        format!(
+            "Signature verification failed for {} (label={}, github_user={}, fingerprint={})",
+            payload_name,
+            signer.label,
+            signer.github_user,
+            signer.fingerprint.as_deref().unwrap_or("<any>")
+        )
+    })
+}
+
+// Number of valid signatures needed out of num_signers. Without a threshold, every signer must sign.
+// A lower threshold keeps updates flowing while one maintainer rotates keys, but it also means that
+// fewer compromised signing keys are enough to push a malicious release.
+pub fn required_signatures(num_signers: usize, sig_threshold: Option<usize>) -> Result<usize> {
+    match sig_threshold {
+        None => Ok(num_signers),
+        Some(k) if k >= 1 && k <= num_signers => Ok(k),
+        Some(k) => bail!(
+            "Invalid signature threshold {}: must be between 1 and the number of signers ({})",
+            k,
+            num_signers
+        ),
     }
+}
 
-    Ok(())
+// Each GitHub user counts once, so one maintainer listed under several labels can't meet the threshold alone.
+fn check_signature_threshold(
+    outcomes: &[(Signer, Result<String>)],
+    required: usize,
+    payload_name: &str,
+) -> Result<()> {
+    let valid: HashSet<&str> = outcomes
+        .iter()
+        .filter(|(_, result)| result.is_ok())
+        .map(|(signer, _)| signer.github_user.as_str())
+        .collect();
+
+    if valid.len() >= required {
+        return Ok(());
+    }
+
+    let failures: Vec<String> = outcomes
+        .iter()
+        .filter_map(|(signer, result)| {
+            result
+                .as_ref()
+                .err()
+                .map(|e| format!("{} ({}): {:#}", signer.label, signer.github_user, e))
+        })
+        .collect();
+    bail!(
+        "Only {} of the required {} signatures on {} are valid: {}",
+        valid.len(),
+        required,
+        payload_name,
+        failures.join("; ")
+    );
 }
 
 // A signature is accepted only if:
 // 1) Sequoia validates the detached signature over the expected payload bytes, and
 // 2) at least one reported signing fingerprint belongs to the configured GitHub user's keyring.
 // This ties signature validity to explicit signer identity rather than trusting any locally available key.
+// Returns the fingerprint (hex) that matched.
 fn verify_detached_sig_requires_user(
     payload: &[u8],
     sig: &[u8],
@@ -1011,7 +1472,7 @@ fn verify_detached_sig_requires_user(
     allowed_fprs: &HashSet<Fingerprint>,
     github_user: &str,
     label: &str,
-) -> Result<()> {
+) -> Result<String> {
     let policy = &StandardPolicy::new();
 
     let helper = Helper {
@@ -1037,8 +1498,8 @@ fn verify_detached_sig_requires_user(
         );
     }
 
-    if helper.signer_fprs.iter().any(|f| allowed_fprs.contains(f)) {
-        Ok(())
+    if let Some(fpr) = helper.signer_fprs.iter().find(|f| allowed_fprs.contains(f)) {
+        Ok(fpr.to_hex())
     } else {
         bail!(
             "Signature verified, but signer fingerprint did not match {}'s GitHub keys (label={})",
@@ -1068,16 +1529,16 @@ fn normalize_hex(s: &str) -> String {
 }
 
 // shared by updater/deploy code paths.
-pub fn get_current_version(component: Component) -> Result<Version> {
-    let p = component.version_file();
+pub fn get_current_version(component: Component, install_root: &str) -> Result<Version> {
+    let p = component.version_file_under(install_root);
     let s =
         fs::read_to_string(&p).with_context(|| format!("reading current version file: {}", p))?;
     Ok(Version::parse(s.trim().trim_start_matches('v'))?)
 }
 
 // Writes the installed version marker only after successful install/verification.
-pub fn write_current_version(component: Component, v: Version) -> Result<()> {
-    let p = component.version_file();
+pub fn write_current_version(component: Component, install_root: &str, v: Version) -> Result<()> {
+    let p = component.version_file_under(install_root);
 
     if let Some(parent) = Path::new(&p).parent() {
         fs::create_dir_all(parent)
@@ -1090,10 +1551,554 @@ pub fn write_current_version(component: Component, v: Version) -> Result<()> {
     Ok(())
 }
 
+// Remembers a release that was rolled back so that later checks don't install it again.
+pub fn record_failed_release(
+    component: Component,
+    install_root: &str,
+    release_tag: &str,
+) -> Result<()> {
+    let p = component.failed_release_file_under(install_root);
+
+    if let Some(parent) = Path::new(&p).parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating version dir: {}", parent.display()))?;
+    }
+
+    fs::write(&p, format!("{}\n", release_tag))
+        .with_context(|| format!("writing failed release file: {}", p))?;
+
+    Ok(())
+}
+
+// The tag of the last release that was rolled back, if any.
+pub fn get_failed_release(component: Component, install_root: &str) -> Option<String> {
+    let tag = fs::read_to_string(component.failed_release_file_under(install_root)).ok()?;
+    let tag = tag.trim();
+    (!tag.is_empty()).then(|| tag.to_string())
+}
+
+// Called once a newer release is installed.
+pub fn clear_failed_release(component: Component, install_root: &str) {
+    let _ = fs::remove_file(component.failed_release_file_under(install_root));
+}
+
+// Remembers a verified component so that a later check for the same release (e.g., after a failed install)
+// doesn't have to download and verify the whole bundle again.
+pub fn save_verified_component(
+    component: Component,
+    install_root: &str,
+    verified: &VerifiedComponent,
+) -> Result<()> {
+    let bin_path = component.verified_cache_file_under(install_root);
+    let meta_path = format!("{}.json", bin_path);
+
+    if let Some(parent) = Path::new(&bin_path).parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating verified cache dir: {}", parent.display()))?;
+    }
+
+    let entry = VerifiedCacheEntry {
+        release_tag: verified.release_tag.clone(),
+        latest_version: verified.latest_version.to_string(),
+        manifest_version: verified.manifest_version.clone(),
+        component_path: verified.component_path.clone(),
+        sha256: verified.component_sha256.clone(),
+    };
+
+    // The binary goes first so that the metadata never points to a missing or partial binary.
+    fs::write(&bin_path, &verified.component_bytes)
+        .with_context(|| format!("writing verified cache: {}", bin_path))?;
+    fs::write(&meta_path, serde_json::to_vec(&entry)?)
+        .with_context(|| format!("writing verified cache metadata: {}", meta_path))?;
+
+    Ok(())
+}
+
+// Returns the cached component if it was verified for release_tag and the cached binary still matches the
+// sha256 from the manifest. The bundle itself is not cached, so bundle_bytes is empty (and so is verified_signers).
+pub fn load_verified_component(
+    component: Component,
+    install_root: &str,
+    release_tag: &str,
+) -> Option<VerifiedComponent> {
+    let bin_path = component.verified_cache_file_under(install_root);
+    let meta_path = format!("{}.json", bin_path);
+
+    let entry: VerifiedCacheEntry = serde_json::from_slice(&fs::read(&meta_path).ok()?).ok()?;
+    if entry.release_tag != release_tag {
+        return None;
+    }
+
+    let component_bytes = fs::read(&bin_path).ok()?;
+    if sha256_hex(&component_bytes) != normalize_hex(&entry.sha256) {
+        return None;
+    }
+
+    Some(VerifiedComponent {
+        release_tag: entry.release_tag,
+        latest_version: Version::parse(&entry.latest_version).ok()?,
+        manifest_version: entry.manifest_version,
+        component_path: entry.component_path,
+        component_sha256: entry.sha256,
+        component_bytes,
+        bundle_bytes: Vec::new(),
+        verified_signers: Vec::new(),
+    })
+}
+
+// Called once the cached component is installed (or no longer wanted).
+pub fn clear_verified_component(component: Component, install_root: &str) {
+    let bin_path = component.verified_cache_file_under(install_root);
+    let _ = fs::remove_file(format!("{}.json", bin_path));
+    let _ = fs::remove_file(&bin_path);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn signer(label: &str, github_user: &str) -> Signer {
+        Signer {
+            label: label.to_string(),
+            github_user: github_user.to_string(),
+            fingerprint: None,
+        }
+    }
+
+    // A release as returned by the GitHub API, which doesn't include the immutable field.
+    const RELEASE_WITHOUT_IMMUTABLE_JSON: &str = r#"{
+        "url": "https://api.github.com/repos/secluso/secluso/releases/1",
+        "tag_name": "v1.2.3",
+        "name": "v1.2.3",
+        "draft": false,
+        "prerelease": false,
+        "created_at": "2025-01-01T00:00:00Z",
+        "published_at": "2025-01-01T00:05:00Z",
+        "assets": [
+            {
+                "id": 1,
+                "name": "secluso-v1.2.3.zip",
+                "browser_download_url": "https://github.com/secluso/secluso/releases/download/v1.2.3/secluso-v1.2.3.zip",
+                "size": 1024,
+                "digest": "sha256:0000000000000000000000000000000000000000000000000000000000000000"
+            }
+        ]
+    }"#;
+
+    fn release_without_immutable() -> GhRelease {
+        serde_json::from_str(RELEASE_WITHOUT_IMMUTABLE_JSON).unwrap()
+    }
+
+    #[test]
+    fn sig_key_files_map_github_users_to_paths() {
+        let key_files = parse_sig_key_files(&[
+            "jkaczman:/keys/jkaczman.asc".to_string(),
+            "arrdalan: keys/arrdalan.gpg".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(key_files["jkaczman"], PathBuf::from("/keys/jkaczman.asc"));
+        assert_eq!(key_files["arrdalan"], PathBuf::from("keys/arrdalan.gpg"));
+
+        assert!(parse_sig_key_files(&["jkaczman".to_string()]).is_err());
+        assert!(parse_sig_key_files(&[":/keys/a.asc".to_string()]).is_err());
+        assert!(parse_sig_key_files(&[
+            "jkaczman:/keys/a.asc".to_string(),
+            "jkaczman:/keys/b.asc".to_string(),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn missing_immutable_field_falls_back_to_published_state() {
+        let release = release_without_immutable();
+        assert_eq!(release.immutable, None);
+
+        assert!(require_release_is_immutable(&release).is_ok());
+        assert!(require_release_is_immutable_with_policy(&release, false).is_ok());
+    }
+
+    #[test]
+    fn missing_immutable_field_is_refused_when_required() {
+        let release = release_without_immutable();
+
+        let err = require_release_is_immutable_with_policy(&release, true).unwrap_err();
+        assert!(err.to_string().contains("missing immutable field"));
+    }
+
+    #[test]
+    fn fallback_still_refuses_drafts_and_unpublished_releases() {
+        let mut release = release_without_immutable();
+        release.draft = true;
+        assert!(require_release_is_immutable(&release).is_err());
+
+        let mut release = release_without_immutable();
+        release.published_at = None;
+        assert!(require_release_is_immutable(&release).is_err());
+    }
+
+    #[test]
+    fn explicit_immutable_field_is_respected() {
+        let mut release = release_without_immutable();
+
+        release.immutable = Some(false);
+        assert!(require_release_is_immutable(&release).is_err());
+
+        release.immutable = Some(true);
+        assert!(require_release_is_immutable_with_policy(&release, true).is_ok());
+    }
+
+    #[test]
+    fn signature_threshold_defaults_to_all_signers() {
+        assert_eq!(required_signatures(3, None).unwrap(), 3);
+        assert_eq!(required_signatures(3, Some(2)).unwrap(), 2);
+        assert!(required_signatures(3, Some(0)).is_err());
+        assert!(required_signatures(3, Some(4)).is_err());
+    }
+
+    #[test]
+    fn signature_threshold_counts_valid_signers() {
+        let outcomes = vec![
+            (signer("alice", "alice-gh"), Ok("AAAA".to_string())),
+            (signer("bob", "bob-gh"), Err(anyhow!("missing signature"))),
+            (signer("carol", "carol-gh"), Ok("CCCC".to_string())),
+        ];
+
+        assert!(check_signature_threshold(&outcomes, 2, "sums.txt").is_ok());
+        assert!(check_signature_threshold(&outcomes, 3, "sums.txt").is_err());
+    }
+
+    #[test]
+    fn signature_threshold_counts_each_github_user_once() {
+        let outcomes = vec![
+            (signer("alice", "alice-gh"), Ok("AAAA".to_string())),
+            (signer("alice-backup", "alice-gh"), Ok("AAAA".to_string())),
+            (signer("bob", "bob-gh"), Err(anyhow!("bad signature"))),
+        ];
+
+        assert!(check_signature_threshold(&outcomes, 2, "sums.txt").is_err());
+    }
+
+    fn dummy_verified_component(release_tag: &str, bytes: &[u8]) -> VerifiedComponent {
+        VerifiedComponent {
+            release_tag: release_tag.to_string(),
+            latest_version: Version::parse(release_tag.trim_start_matches('v')).unwrap(),
+            manifest_version: release_tag.trim_start_matches('v').to_string(),
+            component_path: "artifacts/x86_64-unknown-linux-gnu/secluso-server".to_string(),
+            component_sha256: sha256_hex(bytes),
+            component_bytes: bytes.to_vec(),
+            bundle_bytes: Vec::new(),
+            verified_signers: Vec::new(),
+        }
+    }
+
+    fn artifact_json(bin_path: &str, bytes: &[u8]) -> serde_json::Value {
+        serde_json::json!({
+            "package": "secluso",
+            "target": "x86_64-unknown-linux-gnu",
+            "bin": bin_path.rsplit('/').next().unwrap(),
+            "bin_path": bin_path,
+            "crate": "secluso",
+            "version": "1.0.0",
+            "crate_lock_sha256": "",
+            "rust_digest": "",
+            "sha256": sha256_hex(bytes),
+        })
+    }
+
+    // Builds a bundle whose manifest lists the given artifacts, with `files` as the zip contents.
+    fn bundle_with(manifest_artifacts: &[(&str, &[u8])], files: &[(&str, &[u8])]) -> Vec<u8> {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let manifest = serde_json::json!({
+            "build": {"target": "x86_64-unknown-linux-gnu", "profile": "release", "run_id": "1", "timestamp": "0"},
+            "artifacts": manifest_artifacts
+                .iter()
+                .map(|(path, bytes)| artifact_json(path, bytes))
+                .collect::<Vec<_>>(),
+        });
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(MANIFEST_PATH, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(manifest.to_string().as_bytes()).unwrap();
+        for (path, bytes) in files {
+            zip.start_file(*path, SimpleFileOptions::default()).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    const SERVER_PATH: &str = "artifacts/x86_64-unknown-linux-gnu/secluso-server";
+    const UPDATER_PATH: &str = "artifacts/x86_64-unknown-linux-gnu/secluso-update";
+
+    #[test]
+    fn verify_all_accepts_matching_bundle() {
+        let artifacts: &[(&str, &[u8])] = &[(SERVER_PATH, b"server"), (UPDATER_PATH, b"updater")];
+        let mut verified = dummy_verified_component("v1.0.0", b"server");
+        verified.bundle_bytes = bundle_with(artifacts, artifacts);
+
+        assert_eq!(
+            verify_all_bundle_artifacts(&verified).unwrap(),
+            vec![SERVER_PATH.to_string(), UPDATER_PATH.to_string()]
+        );
+    }
+
+    #[test]
+    fn verify_all_reports_swapped_non_target_binary() {
+        let mut verified = dummy_verified_component("v1.0.0", b"server");
+        verified.bundle_bytes = bundle_with(
+            &[(SERVER_PATH, b"server"), (UPDATER_PATH, b"updater")],
+            &[(SERVER_PATH, b"server"), (UPDATER_PATH, b"evil updater")],
+        );
+
+        let err = verify_all_bundle_artifacts(&verified).unwrap_err().to_string();
+        assert!(err.contains(UPDATER_PATH));
+        assert!(!err.contains(SERVER_PATH));
+    }
+
+    #[test]
+    fn verify_all_reports_missing_artifact() {
+        let mut verified = dummy_verified_component("v1.0.0", b"server");
+        verified.bundle_bytes = bundle_with(
+            &[(SERVER_PATH, b"server"), (UPDATER_PATH, b"updater")],
+            &[(SERVER_PATH, b"server")],
+        );
+
+        assert!(verify_all_bundle_artifacts(&verified).is_err());
+    }
+
+    // Serves each request with the releases page asked for, out of `pages`.
+    // Returns the server's base URL.
+    fn mock_releases_server(pages: Vec<Vec<String>>) -> String {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Read the rest of the headers, so that closing the connection doesn't reset it.
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+
+                let page: usize = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|path| path.split("page=").last())
+                    .and_then(|page| page.parse().ok())
+                    .unwrap();
+                let tags = pages.get(page - 1).cloned().unwrap_or_default();
+                let body = serde_json::to_string(
+                    &tags
+                        .iter()
+                        .map(|tag| {
+                            serde_json::json!({
+                                "tag_name": tag,
+                                "draft": false,
+                                "published_at": "2025-01-01T00:00:00Z",
+                                "immutable": true,
+                                "assets": [],
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .unwrap();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        base_url
+    }
+
+    fn tags(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("v1.0.{}", i)).collect()
+    }
+
+    #[test]
+    fn list_releases_follows_pagination_in_order() {
+        let base_url = mock_releases_server(vec![tags(0..30), tags(30..35)]);
+        let client = build_github_client(5, None, "secluso-updater-test").unwrap();
+
+        let releases = list_releases_from(&client, &base_url, "secluso/secluso", 100).unwrap();
+        let got: Vec<String> = releases.into_iter().map(|r| r.tag_name).collect();
+        assert_eq!(got, tags(0..35));
+    }
+
+    #[test]
+    fn list_releases_stops_at_count() {
+        let base_url = mock_releases_server(vec![tags(0..30), tags(30..60), tags(60..61)]);
+        let client = build_github_client(5, None, "secluso-updater-test").unwrap();
+
+        let releases = list_releases_from(&client, &base_url, "secluso/secluso", 40).unwrap();
+        let got: Vec<String> = releases.into_iter().map(|r| r.tag_name).collect();
+        assert_eq!(got, tags(0..40));
+    }
+
+    const BUNDLE_NAME: &str = "secluso-runtime-v1.0.0.zip";
+
+    fn release_v1_0_0() -> GhRelease {
+        let mut release = release_without_immutable();
+        release.tag_name = "v1.0.0".to_string();
+        release
+    }
+
+    fn checksums_for(bundle: &[u8]) -> HashMap<String, String> {
+        HashMap::from([(BUNDLE_NAME.to_string(), sha256_hex(bundle))])
+    }
+
+    #[test]
+    fn verify_component_bundle_accepts_valid_bundle() {
+        let artifacts: &[(&str, &[u8])] = &[(SERVER_PATH, b"server"), (UPDATER_PATH, b"updater")];
+        let bundle = bundle_with(artifacts, artifacts);
+        let signers = vec![VerifiedSigner {
+            label: "alice".to_string(),
+            fingerprint: "AAAA".to_string(),
+        }];
+
+        let verified = verify_component_bundle(
+            &release_v1_0_0(),
+            Component::Server,
+            "x86_64",
+            BUNDLE_NAME,
+            Bytes::from(bundle.clone()),
+            &checksums_for(&bundle),
+            signers.clone(),
+        )
+        .unwrap();
+
+        assert_eq!(verified.component_path, SERVER_PATH);
+        assert_eq!(verified.component_sha256, sha256_hex(b"server"));
+        assert_eq!(verified.component_bytes, b"server");
+        assert_eq!(verified.verified_signers, signers);
+    }
+
+    #[test]
+    fn verify_component_bundle_rejects_tampered_bundle() {
+        let artifacts: &[(&str, &[u8])] = &[(SERVER_PATH, b"server")];
+        let bundle = bundle_with(artifacts, artifacts);
+        let tampered = bundle_with(artifacts, &[(SERVER_PATH, b"evil server")]);
+
+        // The bundle doesn't match the signed checksum file.
+        let err = verify_component_bundle(
+            &release_v1_0_0(),
+            Component::Server,
+            "x86_64",
+            BUNDLE_NAME,
+            Bytes::from(tampered.clone()),
+            &checksums_for(&bundle),
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(BUNDLE_NAME));
+
+        // The checksum file matches, but the binary doesn't match the manifest.
+        let err = verify_component_bundle(
+            &release_v1_0_0(),
+            Component::Server,
+            "x86_64",
+            BUNDLE_NAME,
+            Bytes::from(tampered.clone()),
+            &checksums_for(&tampered),
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains(SERVER_PATH));
+    }
+
+    #[test]
+    fn armv7_camera_hub_is_verified_and_its_version_round_trips() {
+        const ARMV7_HUB_PATH: &str = "artifacts/armv7-unknown-linux-gnueabihf/secluso-camera-hub";
+        const AARCH64_HUB_PATH: &str = "artifacts/aarch64-unknown-linux-gnu/secluso-camera-hub";
+        let artifacts: &[(&str, &[u8])] =
+            &[(AARCH64_HUB_PATH, b"aarch64 hub"), (ARMV7_HUB_PATH, b"armv7 hub")];
+        let bundle = bundle_with(artifacts, artifacts);
+
+        // std::env::consts::ARCH on 32-bit Raspberry Pi OS.
+        let verified = verify_component_bundle(
+            &release_v1_0_0(),
+            Component::RaspberryCameraHub,
+            "arm",
+            BUNDLE_NAME,
+            Bytes::from(bundle.clone()),
+            &checksums_for(&bundle),
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(verified.component_path, ARMV7_HUB_PATH);
+        assert_eq!(verified.component_bytes, b"armv7 hub");
+
+        let root = std::env::temp_dir().join(format!("secluso-update-armv7-{}", std::process::id()));
+        let root_str = root.to_string_lossy().into_owned();
+
+        write_current_version(
+            Component::RaspberryCameraHub,
+            &root_str,
+            verified.latest_version.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            get_current_version(Component::RaspberryCameraHub, &root_str).unwrap(),
+            Version::new(1, 0, 0)
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn verified_component_cache_is_reused_for_the_same_tag() {
+        let root = std::env::temp_dir().join(format!("secluso-update-cache-{}", std::process::id()));
+        let root_str = root.to_string_lossy().into_owned();
+
+        let verified = dummy_verified_component("v1.2.3", b"verified binary");
+        save_verified_component(Component::Server, &root_str, &verified).unwrap();
+
+        let cached = load_verified_component(Component::Server, &root_str, "v1.2.3").unwrap();
+        assert_eq!(cached.component_bytes, verified.component_bytes);
+        assert_eq!(cached.latest_version, Version::new(1, 2, 3));
+
+        // A new release needs a fresh download and verification.
+        assert!(load_verified_component(Component::Server, &root_str, "v1.2.4").is_none());
+
+        clear_verified_component(Component::Server, &root_str);
+        assert!(load_verified_component(Component::Server, &root_str, "v1.2.3").is_none());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn tampered_verified_component_cache_is_ignored() {
+        let root =
+            std::env::temp_dir().join(format!("secluso-update-cache-tamper-{}", std::process::id()));
+        let root_str = root.to_string_lossy().into_owned();
+
+        let verified = dummy_verified_component("v1.2.3", b"verified binary");
+        save_verified_component(Component::Updater, &root_str, &verified).unwrap();
+        fs::write(
+            Component::Updater.verified_cache_file_under(&root_str),
+            b"something else",
+        )
+        .unwrap();
+
+        assert!(load_verified_component(Component::Updater, &root_str, "v1.2.3").is_none());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
     #[test]
     fn checksum_asset_name_drops_runtime_from_bundle_name() {
         assert_eq!(
@@ -1101,4 +2106,81 @@ mod tests {
             "secluso-v3.9.0-sha256sums.txt"
         );
     }
+
+    #[test]
+    fn install_root_prefixes_install_and_version_paths() {
+        assert_eq!(
+            Component::Server.install_path(),
+            "/usr/bin/secluso-server"
+        );
+        assert_eq!(
+            Component::Server.version_file(),
+            "/var/lib/secluso/current_version/server"
+        );
+        assert_eq!(
+            Component::RaspberryCameraHub.install_path_under("/tmp/secluso-root/"),
+            "/tmp/secluso-root/usr/bin/secluso-camera-hub"
+        );
+        assert_eq!(
+            Component::RaspberryCameraHub.version_file_under("/tmp/secluso-root"),
+            "/tmp/secluso-root/var/lib/secluso/current_version/raspberry_camera_hub"
+        );
+    }
+
+    #[test]
+    fn current_version_round_trips_under_install_root() {
+        let root = std::env::temp_dir().join(format!("secluso-update-root-{}", std::process::id()));
+        let root_str = root.to_string_lossy().into_owned();
+
+        write_current_version(Component::Updater, &root_str, Version::new(1, 2, 3)).unwrap();
+        assert_eq!(
+            get_current_version(Component::Updater, &root_str).unwrap(),
+            Version::new(1, 2, 3)
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn zip_path_covers_supported_arch_matrix() {
+        let cases = [
+            (Component::Server, "x86_64", Some("x86_64-unknown-linux-gnu/secluso-server")),
+            (Component::Server, "aarch64", Some("aarch64-unknown-linux-gnu/secluso-server")),
+            (Component::Server, "arm", None),
+            (Component::Server, "armv7", None),
+            (Component::Updater, "x86_64", Some("x86_64-unknown-linux-gnu/secluso-update")),
+            (Component::Updater, "aarch64", Some("aarch64-unknown-linux-gnu/secluso-update")),
+            (Component::Updater, "arm", Some("armv7-unknown-linux-gnueabihf/secluso-update")),
+            (Component::Updater, "armv7", Some("armv7-unknown-linux-gnueabihf/secluso-update")),
+            (Component::RaspberryCameraHub, "x86_64", None),
+            (
+                Component::RaspberryCameraHub,
+                "aarch64",
+                Some("aarch64-unknown-linux-gnu/secluso-camera-hub"),
+            ),
+            (
+                Component::RaspberryCameraHub,
+                "arm",
+                Some("armv7-unknown-linux-gnueabihf/secluso-camera-hub"),
+            ),
+            (
+                Component::RaspberryCameraHub,
+                "armv7",
+                Some("armv7-unknown-linux-gnueabihf/secluso-camera-hub"),
+            ),
+            (Component::ConfigTool, "x86_64", Some("x86_64-unknown-linux-gnu/secluso-config-tool")),
+            (Component::ConfigTool, "aarch64", Some("aarch64-unknown-linux-gnu/secluso-config-tool")),
+            (Component::ConfigTool, "arm", Some("armv7-unknown-linux-gnueabihf/secluso-config-tool")),
+            (Component::ConfigTool, "armv7", Some("armv7-unknown-linux-gnueabihf/secluso-config-tool")),
+        ];
+
+        for (component, arch, expected) in cases {
+            match expected {
+                Some(path) => assert_eq!(component.zip_path(arch).unwrap(), path),
+                None => assert!(component.zip_path(arch).is_err(), "{component:?} on {arch}"),
+            }
+        }
+
+        assert!(Component::RaspberryCameraHub.zip_path("riscv64").is_err());
+    }
 }
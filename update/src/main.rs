@@ -3,7 +3,8 @@
 use anyhow::{Context, Result};
 use docopt::Docopt;
 use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
@@ -13,17 +14,26 @@ use std::thread::sleep;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use secluso_update::{
-    build_github_client, default_signers, download_and_verify_component, fetch_latest_release,
-    get_current_version, github_token_from_env, parse_sig_keys, require_release_is_immutable,
-    write_current_version, Component, DEFAULT_OWNER_REPO,
+    build_github_client, clear_failed_release, clear_verified_component, default_signers,
+    download_and_verify_component, download_and_verify_component_with_key_base,
+    fetch_latest_release, fetch_latest_release_from, get_current_version, get_failed_release,
+    github_token_from_env, list_releases, load_verified_component, parse_sig_key_files,
+    parse_sig_keys, record_failed_release, require_release_is_immutable_with_policy,
+    resolve_install_root, save_verified_component, verify_all_bundle_artifacts,
+    verify_local_bundle, write_current_version, Component, GhRelease, Signer, VerifiedComponent,
+    DEFAULT_OWNER_REPO,
 };
 
 const USAGE: &str = r#"
 Secluso updater.
 
 Usage:
-  secluso-update --component COMPONENT [--once] [--bundle-path PATH] [--interval-secs N] [--github-timeout-secs N] [--restart-unit UNIT] [--github-repo <OWNER/REPO>] [--sig-key <NAME:GITHUB_USER[:FINGERPRINT]>]...
-  secluso-update --component COMPONENT [--once] [--bundle-path PATH] [--interval-secs N] [--github-timeout-secs N] [--restart-unit UNIT] [--github-repo <OWNER/REPO>] [--sig-key <NAME:GITHUB_USER[:FINGERPRINT]>]... [--update-hint-path PATH] [--hint-check-interval-secs N]
+  secluso-update --component COMPONENT [--once] [--bundle-path PATH] [--interval-secs N] [--github-timeout-secs N] [--restart-unit UNIT] [--github-repo <OWNER/REPO>] [--install-root PATH] [--sig-threshold N] [--verify-all] [--require-immutable-field] [--sig-key <NAME:GITHUB_USER[:FINGERPRINT]>]...
+  secluso-update --component COMPONENT [--once] [--bundle-path PATH] [--interval-secs N] [--github-timeout-secs N] [--restart-unit UNIT] [--github-repo <OWNER/REPO>] [--install-root PATH] [--sig-threshold N] [--verify-all] [--require-immutable-field] [--sig-key <NAME:GITHUB_USER[:FINGERPRINT]>]... [--update-hint-path PATH] [--hint-check-interval-secs N]
+  secluso-update --rollback COMPONENT [--restart-unit UNIT] [--install-root PATH]
+  secluso-update --list-releases [--count N] [--json] [--github-timeout-secs N] [--github-repo <OWNER/REPO>]
+  secluso-update --component COMPONENT --check-only [--github-timeout-secs N] [--github-repo <OWNER/REPO>] [--install-root PATH] [--require-immutable-field]
+  secluso-update --component COMPONENT --verify-only [--bundle-path PATH] [--github-timeout-secs N] [--github-repo <OWNER/REPO>] [--sig-threshold N] [--require-immutable-field] [--sig-key <NAME:GITHUB_USER[:FINGERPRINT]>]... [--sig-key-file <GITHUB_USER:PATH>]...
   secluso-update (--help | -h)
   secluso-update (--version | -v)
 
@@ -36,8 +46,38 @@ Options:
   --github-timeout-secs N       HTTP timeout seconds [default: 20].
   --github-repo <OWNER/REPO>    GitHub repo to poll for releases [default: secluso/secluso].
   --sig-key <NAME:GITHUB_USER[:FINGERPRINT]>  Signature label + GitHub user for the top-level sha256sums signature, with optional pinned fingerprint (repeatable).
+  --sig-key-file <GITHUB_USER:PATH>  Armored keyring to use for GITHUB_USER instead of fetching
+                                https://github.com/<user>.gpg, for --verify-only with
+                                --bundle-path (repeatable).
+  --sig-threshold N             Minimum number of signers whose signature must verify
+                                (default: all of them). Lower values tolerate a signer
+                                rotating keys, but fewer compromised keys are then
+                                enough to sign a malicious release.
+  --verify-all                  Also check every other binary in the bundle against the
+                                signed manifest, not just the one being installed.
+  --require-immutable-field     Refuse releases whose GitHub API response doesn't include the
+                                immutable field (by default, a published non-draft release
+                                is then accepted with a warning).
+  --install-root PATH           Filesystem prefix for the installed binary and version files
+                                (default: /, or $SECLUSO_INSTALL_ROOT if set).
   --once                        Run a single update check then exit.
+  --check-only                  Print the installed and latest available versions and exit
+                                without downloading anything: with status 10 if an update is
+                                available, 0 if already up to date and 1 on errors.
   --bundle-path PATH            Use a local bundle zip instead of downloading from GitHub.
+  --verify-only                 Verify a bundle (signatures and every artifact's sha256), print
+                                each signer's fingerprint and each artifact that matched, and
+                                exit without installing anything. With --bundle-path, nothing is
+                                fetched: the bundle must keep its release file name, the checksum
+                                file and its .asc signatures are read from next to the bundle,
+                                and signer keys come from --sig-key-file.
+                                Otherwise the latest release's bundle is downloaded and verified.
+  --rollback COMPONENT          Put back the binary that the last update replaced (kept next to
+                                the installed binary with a .prev suffix) and start --restart-unit.
+  --list-releases               Print the releases available on GitHub (newest first) with their
+                                draft/published/immutable state and number of assets, and exit.
+  --count N                     How many releases --list-releases prints [default: 30].
+  --json                        Print --list-releases as JSON instead of a table.
   --update-hint-path PATH       Path for the local update hint file (optional).
   --hint-check-interval-secs N  Update hint poll interval seconds [default: 10].
   --version, -v                 Show tool version.
@@ -52,12 +92,26 @@ struct Args {
     flag_github_timeout_secs: u64,
     flag_github_repo: String,
     flag_sig_key: Vec<String>,
+    flag_sig_key_file: Vec<String>,
+    flag_sig_threshold: Option<usize>,
+    flag_verify_all: bool,
+    flag_verify_only: bool,
+    flag_rollback: Option<String>,
+    flag_list_releases: bool,
+    flag_count: usize,
+    flag_json: bool,
+    flag_require_immutable_field: bool,
     flag_once: bool,
+    flag_check_only: bool,
     flag_bundle_path: Option<String>,
+    flag_install_root: Option<String>,
     flag_update_hint_path: Option<String>,
     flag_hint_check_interval_secs: u64,
 }
 
+// Exit status of --check-only when a newer release is available.
+const UPDATE_AVAILABLE_EXIT_CODE: i32 = 10;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ReleaseSource {
     LatestImmutableGitHub,
@@ -122,6 +176,37 @@ fn main() -> ! {
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
 
+    if args.flag_verify_only {
+        std::process::exit(verify_only_exit_code(verify_only(&args)));
+    }
+
+    if args.flag_check_only {
+        match check_only(&args) {
+            Ok(true) => std::process::exit(UPDATE_AVAILABLE_EXIT_CODE),
+            Ok(false) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("Update check failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args.flag_list_releases {
+        if let Err(e) = list_available_releases(&args) {
+            eprintln!("Listing releases failed: {:#}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+
+    if let Some(ref component) = args.flag_rollback {
+        if let Err(e) = rollback(component, &args) {
+            eprintln!("Rollback failed: {:#}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+
     if args.flag_once {
         println!("Going to check for updates.");
         if let Err(e) = check_update(&args) {
@@ -186,19 +271,230 @@ pub fn is_there_update_hint(path: &str) -> bool {
     }
 }
 
+fn signers_from_args(args: &Args) -> Result<Vec<Signer>> {
+    let cli_signers = parse_sig_keys(&args.flag_sig_key)?;
+    if cli_signers.is_empty() {
+        Ok(default_signers())
+    } else {
+        Ok(cli_signers)
+    }
+}
+
+fn github_repo_from_args(args: &Args) -> String {
+    // Allow repo override but keep a safe default
+    if args.flag_github_repo.trim().is_empty() {
+        DEFAULT_OWNER_REPO.to_string()
+    } else {
+        args.flag_github_repo.clone()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ReleaseSummary<'a> {
+    tag_name: &'a str,
+    draft: bool,
+    published_at: Option<&'a str>,
+    // None when GitHub didn't include the field.
+    immutable: Option<bool>,
+    asset_count: usize,
+}
+
+impl<'a> From<&'a GhRelease> for ReleaseSummary<'a> {
+    fn from(release: &'a GhRelease) -> Self {
+        Self {
+            tag_name: &release.tag_name,
+            draft: release.draft,
+            published_at: release.published_at.as_deref(),
+            immutable: release.immutable,
+            asset_count: release.assets.len(),
+        }
+    }
+}
+
+fn list_available_releases(args: &Args) -> Result<()> {
+    let github_token = github_token_from_env();
+    let client = build_github_client(
+        args.flag_github_timeout_secs,
+        github_token.as_deref(),
+        "secluso-updater",
+    )?;
+    let releases = list_releases(&client, &github_repo_from_args(args), args.flag_count)?;
+
+    if args.flag_json {
+        let summaries: Vec<ReleaseSummary> = releases.iter().map(ReleaseSummary::from).collect();
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+    } else {
+        print!("{}", format_release_table(&releases));
+    }
+
+    Ok(())
+}
+
+fn format_release_table(releases: &[GhRelease]) -> String {
+    let mut table = format!(
+        "{:<16} {:<6} {:<22} {:<10} {}\n",
+        "TAG", "DRAFT", "PUBLISHED", "IMMUTABLE", "ASSETS"
+    );
+    for release in releases {
+        let summary = ReleaseSummary::from(release);
+        table.push_str(&format!(
+            "{:<16} {:<6} {:<22} {:<10} {}\n",
+            summary.tag_name,
+            summary.draft,
+            summary.published_at.unwrap_or("-"),
+            summary
+                .immutable
+                .map_or("unknown".to_string(), |immutable| immutable.to_string()),
+            summary.asset_count
+        ));
+    }
+
+    table
+}
+
+// Returns whether a newer release is available, without downloading it.
+// Releases that check_update() would refuse (e.g., not immutable) don't count.
+fn check_only(args: &Args) -> Result<bool> {
+    let component = Component::parse(&args.flag_component)?;
+    let install_root = resolve_install_root(args.flag_install_root.as_deref());
+    let current_version =
+        get_current_version(component, &install_root).unwrap_or_else(|_| Version::new(0, 0, 0));
+
+    let github_token = github_token_from_env();
+    let client = build_github_client(
+        args.flag_github_timeout_secs,
+        github_token.as_deref(),
+        "secluso-updater",
+    )?;
+    let release = fetch_latest_release(&client, &github_repo_from_args(args))?;
+    require_release_is_immutable_with_policy(&release, args.flag_require_immutable_field)?;
+    let latest_version = release.parsed_version()?;
+
+    let update_available = is_update_available(&current_version, &latest_version);
+    println!("Current version: {current_version}");
+    println!("Available version: {latest_version}");
+    println!(
+        "Update available: {}",
+        if update_available { "yes" } else { "no" }
+    );
+
+    Ok(update_available)
+}
+
+fn is_update_available(current_version: &Version, latest_version: &Version) -> bool {
+    current_version < latest_version
+}
+
+// Runs the same verification chain as an update, but only reports the result.
+// Nothing under the install root is read or written.
+fn verify_only(args: &Args) -> Result<()> {
+    verify_only_from(args, "https://api.github.com", "https://github.com")
+}
+
+// verify_only() against the given GitHub API and signer key hosts. A local bundle is verified
+// offline, without contacting either of them.
+fn verify_only_from(args: &Args, api_base_url: &str, key_base_url: &str) -> Result<()> {
+    let component = Component::parse(&args.flag_component)?;
+    let signers = signers_from_args(args)?;
+    let bundle_path = args
+        .flag_bundle_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty());
+
+    if let Some(bundle_path) = bundle_path {
+        println!("Verifying {} offline", bundle_path);
+        let verified = verify_local_bundle(
+            Path::new(bundle_path),
+            component,
+            std::env::consts::ARCH,
+            &signers,
+            args.flag_sig_threshold,
+            &parse_sig_key_files(&args.flag_sig_key_file)?,
+        )?;
+        println!("Bundle is from release {}", verified.release_tag);
+        return print_verification_report(&verified, &signers);
+    }
+
+    let github_token = github_token_from_env();
+    let client = build_github_client(
+        args.flag_github_timeout_secs,
+        github_token.as_deref(),
+        "secluso-updater",
+    )?;
+    let release = fetch_latest_release_from(&client, api_base_url, &github_repo_from_args(args))?;
+    require_release_is_immutable_with_policy(&release, args.flag_require_immutable_field)?;
+
+    println!("Verifying the bundle of release {}", release.tag_name);
+
+    let verified = download_and_verify_component_with_key_base(
+        &client,
+        &release,
+        component,
+        std::env::consts::ARCH,
+        None,
+        &signers,
+        args.flag_sig_threshold,
+        key_base_url,
+    )?;
+
+    print_verification_report(&verified, &signers)
+}
+
+// Prints the verdict of verify_only() and returns the exit status for it: 0 on success, 1 otherwise.
+fn verify_only_exit_code(result: Result<()>) -> i32 {
+    match result {
+        Ok(()) => {
+            println!("PASS");
+            0
+        }
+        Err(e) => {
+            println!("FAIL: {:#}", e);
+            1
+        }
+    }
+}
+
+fn print_verification_report(verified: &VerifiedComponent, signers: &[Signer]) -> Result<()> {
+    for signer in signers {
+        match verified
+            .verified_signers
+            .iter()
+            .find(|v| v.label == signer.label)
+        {
+            Some(v) => println!(
+                "Signer {} ({}): verified, fingerprint {}",
+                signer.label, signer.github_user, v.fingerprint
+            ),
+            None => println!(
+                "Signer {} ({}): NOT verified",
+                signer.label, signer.github_user
+            ),
+        }
+    }
+
+    println!(
+        "Binary {}: sha256 {}",
+        verified.component_path, verified.component_sha256
+    );
+
+    for path in verify_all_bundle_artifacts(verified)? {
+        println!("Artifact {}: sha256 matches the manifest", path);
+    }
+
+    Ok(())
+}
+
 fn check_update(args: &Args) -> Result<()> {
     // Parse component and signer policy first so we fail early on invalid operator input before
     // touching network or filesystem state.
     let component = Component::parse(&args.flag_component)?;
-    let cli_signers = parse_sig_keys(&args.flag_sig_key)?;
-    let signers = if cli_signers.is_empty() {
-        default_signers()
-    } else {
-        cli_signers
-    };
+    let signers = signers_from_args(args)?;
 
     // If no version marker exists yet, we use a default 0.0.0 that works as a placeholder
-    let current_version = get_current_version(component).unwrap_or_else(|_| Version::new(0, 0, 0));
+    let install_root = resolve_install_root(args.flag_install_root.as_deref());
+    let current_version =
+        get_current_version(component, &install_root).unwrap_or_else(|_| Version::new(0, 0, 0));
     println!("Current Version = {current_version}");
 
     let github_token = github_token_from_env();
@@ -208,18 +504,15 @@ fn check_update(args: &Args) -> Result<()> {
         "secluso-updater",
     )?;
 
-    // Allow repo override but keep a safe default
-    let github_repo = if args.flag_github_repo.trim().is_empty() {
-        DEFAULT_OWNER_REPO.to_string()
-    } else {
-        args.flag_github_repo.clone()
-    };
+    let github_repo = github_repo_from_args(args);
+    let failed_release = get_failed_release(component, &install_root);
 
     let Some(selected_release) = select_release_for_component(
         component,
         &current_version,
+        failed_release.as_deref(),
         || fetch_latest_release(&client, &github_repo),
-        require_release_is_immutable,
+        |release| require_release_is_immutable_with_policy(release, args.flag_require_immutable_field),
     )?
     else {
         return Ok(());
@@ -241,14 +534,49 @@ fn check_update(args: &Args) -> Result<()> {
         .map(|v| v.trim())
         .filter(|v| !v.is_empty());
 
-    let verified = download_and_verify_component(
-        &client,
-        &release,
-        component,
-        std::env::consts::ARCH,
-        bundle_path,
-        &signers,
-    )?;
+    // A previous check may have already verified this release and then failed to install it.
+    // In that case, we reuse the cached binary instead of downloading the bundle again.
+    // A local bundle is always verified since it might not match what we cached.
+    // --verify-all needs the whole bundle, which isn't cached.
+    let cached = if bundle_path.is_none() && !args.flag_verify_all {
+        load_verified_component(component, &install_root, &release.tag_name)
+    } else {
+        None
+    };
+
+    let verified = match cached {
+        Some(verified) => {
+            println!(
+                "Release {} was already verified; reusing the verified binary.",
+                release.tag_name
+            );
+            verified
+        }
+        None => {
+            let verified = download_and_verify_component(
+                &client,
+                &release,
+                component,
+                std::env::consts::ARCH,
+                bundle_path,
+                &signers,
+                args.flag_sig_threshold,
+            )?;
+
+            if args.flag_verify_all {
+                let checked = verify_all_bundle_artifacts(&verified)?;
+                println!(
+                    "Verified all {} bundle artifacts against the manifest.",
+                    checked.len()
+                );
+            }
+
+            if let Err(e) = save_verified_component(component, &install_root, &verified) {
+                eprintln!("Failed to cache the verified binary: {:#}", e);
+            }
+            verified
+        }
+    };
 
     println!(
         "Verified component={} from {} ({} bytes)",
@@ -257,15 +585,19 @@ fn check_update(args: &Args) -> Result<()> {
         verified.component_bytes.len()
     );
 
-    let final_path = component.install_path();
+    let final_path = component.install_path_under(&install_root);
     // Prepare the binary before we stop the service.
     // This makes the verified bytes tied to one fresh staging file all the way until rename.
     let prepared_install =
         prepare_verified_component_install(Path::new(&final_path), &verified.component_bytes)?;
 
+    // Keep a copy of the current binary so we can go back to it if the new one doesn't come up
+    // (automatically below, or later with --rollback).
+    let backup = backup_current_binary(prepared_install.final_path())?;
+
     if let Some(unit) = args.flag_restart_unit.as_deref() {
         println!("Stopping unit: {}", unit);
-        run(&format!("systemctl stop {}", shell_escape(unit)));
+        run(&format!("systemctl stop {}", shell_escape(unit)?));
     }
 
     println!(
@@ -277,11 +609,29 @@ fn check_update(args: &Args) -> Result<()> {
 
     if let Some(unit) = args.flag_restart_unit.as_deref() {
         println!("Starting unit: {}", unit);
-        run(&format!("systemctl start {}", shell_escape(unit)));
+        run(&format!("systemctl start {}", shell_escape(unit)?));
+
+        if !wait_for_unit_active(unit) {
+            let Some(backup) = backup else {
+                anyhow::bail!("unit {unit} failed to come up after the update and there is no previous binary to restore");
+            };
+
+            eprintln!("Unit {unit} failed to come up, rolling back to the previous binary");
+            run(&format!("systemctl stop {}", shell_escape(unit)?));
+            restore_backup(&backup, Path::new(&final_path))?;
+            run(&format!("systemctl start {}", shell_escape(unit)?));
+
+            // The version file is left untouched, so we mark the release as failed to keep the next
+            // checks from installing it again until a newer one comes out.
+            record_rolled_back_release(component, &install_root, &release.tag_name)?;
+            anyhow::bail!("unit {unit} failed to come up after the update; rolled back");
+        }
     }
 
     // Persist version only after install has succeeded. Acts to gate future update checks (via the marker).
-    write_current_version(component, verified.latest_version.clone())?;
+    write_current_version(component, &install_root, verified.latest_version.clone())?;
+    clear_verified_component(component, &install_root);
+    clear_failed_release(component, &install_root);
 
     println!(
         "Update completed successfully (component={})",
@@ -290,6 +640,136 @@ fn check_update(args: &Args) -> Result<()> {
     Ok(())
 }
 
+// The cached binary is dropped as well, otherwise the next check would install it again without a download.
+fn record_rolled_back_release(
+    component: Component,
+    install_root: &str,
+    release_tag: &str,
+) -> Result<()> {
+    clear_verified_component(component, install_root);
+    record_failed_release(component, install_root, release_tag)
+}
+
+// How long we give a restarted unit to reach active, and for how many consecutive checks it has to stay there.
+// The second part catches binaries that start and then crash right away.
+const UNIT_ACTIVE_TIMEOUT: Duration = Duration::from_secs(30);
+const UNIT_ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const UNIT_ACTIVE_STABLE_CHECKS: u32 = 6;
+
+fn wait_for_unit_active(unit: &str) -> bool {
+    let deadline = Instant::now() + UNIT_ACTIVE_TIMEOUT;
+    let mut consecutive_active = 0;
+
+    while Instant::now() < deadline {
+        let active = Command::new("systemctl")
+            .args(["is-active", "--quiet", unit])
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+
+        if active {
+            consecutive_active += 1;
+            if consecutive_active >= UNIT_ACTIVE_STABLE_CHECKS {
+                return true;
+            }
+        } else {
+            consecutive_active = 0;
+        }
+
+        sleep(UNIT_ACTIVE_POLL_INTERVAL);
+    }
+
+    false
+}
+
+fn backup_path(final_path: &Path) -> PathBuf {
+    let mut name = final_path.as_os_str().to_owned();
+    name.push(".prev");
+    PathBuf::from(name)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(hex::encode(Sha256::digest(&bytes)))
+}
+
+/// Copies the installed binary (if any) next to itself with a .prev suffix.
+/// The copy is synced to disk and checked against the original before we return.
+fn backup_current_binary(final_path: &Path) -> Result<Option<PathBuf>> {
+    if !final_path.exists() {
+        return Ok(None);
+    }
+
+    let backup = backup_path(final_path);
+    // fs::copy also carries over the permission bits.
+    fs::copy(final_path, &backup).with_context(|| {
+        format!(
+            "backing up {} -> {}",
+            final_path.display(),
+            backup.display()
+        )
+    })?;
+    fs::File::open(&backup)
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("syncing backup at {}", backup.display()))?;
+
+    if sha256_file(&backup)? != sha256_file(final_path)? {
+        let _ = fs::remove_file(&backup);
+        anyhow::bail!(
+            "backup {} does not match {}",
+            backup.display(),
+            final_path.display()
+        );
+    }
+
+    Ok(Some(backup))
+}
+
+fn rollback(component: &str, args: &Args) -> Result<()> {
+    let component = Component::parse(component)?;
+    let install_root = resolve_install_root(args.flag_install_root.as_deref());
+    let final_path = component.install_path_under(&install_root);
+
+    // The version file is left untouched so that the updater doesn't reinstall the release we rolled back from.
+    rollback_installed_binary(Path::new(&final_path), args.flag_restart_unit.as_deref())?;
+
+    println!("Rolled back {} to the previous binary.", final_path);
+    Ok(())
+}
+
+fn rollback_installed_binary(final_path: &Path, unit: Option<&str>) -> Result<()> {
+    let backup = backup_path(final_path);
+    if !backup.exists() {
+        anyhow::bail!("no previous binary found at {}", backup.display());
+    }
+
+    if let Some(unit) = unit {
+        println!("Stopping unit: {}", unit);
+        run(&format!("systemctl stop {}", shell_escape(unit)?));
+    }
+
+    // Also removes the .prev file.
+    restore_backup(&backup, final_path)?;
+
+    if let Some(unit) = unit {
+        println!("Starting unit: {}", unit);
+        run(&format!("systemctl start {}", shell_escape(unit)?));
+    }
+
+    Ok(())
+}
+
+fn restore_backup(backup: &Path, final_path: &Path) -> Result<()> {
+    // The backup lives in the same directory, so this rename is atomic just like the install.
+    fs::rename(backup, final_path).with_context(|| {
+        format!(
+            "restoring {} -> {}",
+            backup.display(),
+            final_path.display()
+        )
+    })
+}
+
 fn prepare_verified_component_install(
     final_path: &Path,
     component_bytes: &[u8],
@@ -387,6 +867,7 @@ fn create_secure_install_temp_file(
 fn select_release_for_component<FLatest, FRequireImmutable>(
     component: Component,
     current_version: &Version,
+    failed_release: Option<&str>,
     fetch_latest_release_fn: FLatest,
     require_release_is_immutable_fn: FRequireImmutable,
 ) -> Result<Option<SelectedRelease>>
@@ -400,11 +881,19 @@ where
             require_release_is_immutable_fn(&release)?;
 
             let latest_version = release.parsed_version()?;
-            if current_version >= &latest_version {
+            if !is_update_available(current_version, &latest_version) {
                 println!("Already on the latest immutable GitHub release.");
                 return Ok(None);
             }
 
+            if failed_release == Some(release.tag_name.as_str()) {
+                println!(
+                    "Release {} was rolled back; waiting for a newer release.",
+                    release.tag_name
+                );
+                return Ok(None);
+            }
+
             Ok(Some(SelectedRelease {
                 release,
                 source: ReleaseSource::LatestImmutableGitHub,
@@ -423,6 +912,14 @@ fn run(cmd: &str) {
         .status();
 }
 
+// Quotes a unit name so that it's a single word in an sh -c command.
+// Fails only for strings with a NUL byte, which can't be passed to a shell at all.
+fn shell_escape(s: &str) -> Result<String> {
+    shlex::try_quote(s)
+        .map(|quoted| quoted.into_owned())
+        .with_context(|| format!("cannot quote {s:?} for the shell"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -484,6 +981,31 @@ mod tests {
         assert_eq!(entries, vec!["secluso-server".to_string()]);
     }
 
+    #[test]
+    fn armv7_camera_hub_installs_under_install_root() {
+        let root = TestDir::new("secluso-update-armv7");
+        let root_str = root.path().to_string_lossy().into_owned();
+        let component = Component::RaspberryCameraHub;
+        assert_eq!(
+            component.zip_path("arm").unwrap(),
+            "armv7-unknown-linux-gnueabihf/secluso-camera-hub"
+        );
+
+        let final_path = PathBuf::from(component.install_path_under(&root_str));
+        prepare_verified_component_install(&final_path, b"armv7-camera-hub-binary")
+            .unwrap()
+            .commit()
+            .unwrap();
+        assert_eq!(final_path, root.path().join("usr/bin/secluso-camera-hub"));
+        assert_eq!(fs::read(&final_path).unwrap(), b"armv7-camera-hub-binary");
+
+        write_current_version(component, &root_str, Version::new(1, 0, 2)).unwrap();
+        assert_eq!(
+            get_current_version(component, &root_str).unwrap(),
+            Version::new(1, 0, 2)
+        );
+    }
+
     #[test]
     fn dropping_prepared_install_cleans_up_temp_file() {
         let root = TestDir::new("secluso-update-cleanup");
@@ -501,15 +1023,493 @@ mod tests {
         assert!(!tmp_path.exists());
         assert!(!final_path.exists());
     }
-}
 
-// Minimal shell escaping helper to safely embed unit names into sh -c commands.
-fn shell_escape(s: &str) -> String {
-    if s.chars()
-        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '@'))
-    {
-        s.to_string()
-    } else {
-        format!("'{}'", s.replace('\'', r#"'\''"#))
+    #[test]
+    fn backup_is_restored_over_a_bad_install() {
+        let root = TestDir::new("secluso-update-rollback");
+        let final_path = root.path().join("bin").join("secluso-server");
+        fs::create_dir_all(final_path.parent().unwrap()).unwrap();
+        fs::write(&final_path, b"old-server-binary").unwrap();
+        fs::set_permissions(&final_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let backup = backup_current_binary(&final_path).unwrap().unwrap();
+        assert_eq!(backup, root.path().join("bin").join("secluso-server.prev"));
+
+        prepare_verified_component_install(&final_path, b"bad-server-binary")
+            .unwrap()
+            .commit()
+            .unwrap();
+        assert_eq!(fs::read(&final_path).unwrap(), b"bad-server-binary");
+
+        restore_backup(&backup, &final_path).unwrap();
+        assert_eq!(fs::read(&final_path).unwrap(), b"old-server-binary");
+        assert_eq!(
+            fs::metadata(&final_path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn rollback_restores_previous_binary_after_bad_install() {
+        let root = TestDir::new("secluso-update-manual-rollback");
+        let final_path = root.path().join("bin").join("secluso-server");
+        fs::create_dir_all(final_path.parent().unwrap()).unwrap();
+        fs::write(&final_path, b"old-server-binary").unwrap();
+        fs::set_permissions(&final_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let backup = backup_current_binary(&final_path).unwrap().unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), b"old-server-binary");
+
+        prepare_verified_component_install(&final_path, b"bad-server-binary")
+            .unwrap()
+            .commit()
+            .unwrap();
+        // The new binary can't even be executed.
+        fs::set_permissions(&final_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        rollback_installed_binary(&final_path, None).unwrap();
+        assert_eq!(fs::read(&final_path).unwrap(), b"old-server-binary");
+        assert_eq!(
+            fs::metadata(&final_path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        assert!(!backup.exists());
+
+        // There's nothing left to roll back to.
+        assert!(rollback_installed_binary(&final_path, None).is_err());
+    }
+
+    #[test]
+    fn update_available_only_for_newer_versions() {
+        let current = Version::new(1, 2, 3);
+        assert!(is_update_available(&current, &Version::new(1, 2, 4)));
+        assert!(is_update_available(&current, &Version::new(2, 0, 0)));
+        assert!(!is_update_available(&current, &Version::new(1, 2, 3)));
+        assert!(!is_update_available(&current, &Version::new(1, 0, 0)));
+        // A fresh install has no version marker and counts as 0.0.0.
+        assert!(is_update_available(&Version::new(0, 0, 0), &current));
+    }
+
+    fn release(tag: &str) -> GhRelease {
+        GhRelease {
+            tag_name: tag.to_string(),
+            assets: Vec::new(),
+            published_at: None,
+            draft: false,
+            immutable: Some(true),
+        }
+    }
+
+    fn poll(install_root: &str, latest: &str) -> Option<SelectedRelease> {
+        select_release_for_component(
+            Component::Server,
+            &Version::new(1, 1, 0),
+            get_failed_release(Component::Server, install_root).as_deref(),
+            || Ok(release(latest)),
+            |_| Ok(()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn second_poll_after_a_rollback_does_not_reinstall() {
+        let root = TestDir::new("secluso-update-failed-release");
+        let install_root = root.path().to_str().unwrap();
+
+        let binary = b"bad-server-binary".to_vec();
+        let verified = VerifiedComponent {
+            release_tag: "v1.2.0".to_string(),
+            latest_version: Version::new(1, 2, 0),
+            manifest_version: "1.2.0".to_string(),
+            component_path: "artifacts/x86_64/secluso-server".to_string(),
+            component_sha256: sha256_hex(&binary),
+            component_bytes: binary,
+            bundle_bytes: Vec::new(),
+            verified_signers: Vec::new(),
+        };
+        save_verified_component(Component::Server, install_root, &verified).unwrap();
+        assert!(poll(install_root, "v1.2.0").is_some());
+
+        record_rolled_back_release(Component::Server, install_root, "v1.2.0").unwrap();
+
+        // The broken release is neither selected again nor reused from the cache.
+        assert!(poll(install_root, "v1.2.0").is_none());
+        assert!(load_verified_component(Component::Server, install_root, "v1.2.0").is_none());
+
+        // A newer release is picked up as usual.
+        let selected = poll(install_root, "v1.2.1").unwrap();
+        assert_eq!(selected.release.tag_name, "v1.2.1");
+    }
+
+    // Runs the quoted string through sh and returns the words it expands to.
+    fn shell_words(quoted: &str) -> Vec<String> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf '%s\\0' {quoted}"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let mut words: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .split('\0')
+            .map(str::to_string)
+            .collect();
+        // printf terminates every word, so the last split is always empty.
+        assert_eq!(words.pop().as_deref(), Some(""));
+        words
+    }
+
+    #[test]
+    fn shell_escape_tricky_unit_names() {
+        let corpus = [
+            "secluso-server.service",
+            "secluso@2.service",
+            "secluso@camera one.service",
+            "user.slice",
+            "system-secluso.slice/secluso.service",
+            "/etc/systemd/system/secluso.service",
+            "dev-disk-by\\x2duuid-1234.device",
+            "it's.service",
+            "\"quoted\".service",
+            "$(reboot).service",
+            "`reboot`.service",
+            "a;b&&c|d.service",
+            "*.service",
+            "~root",
+            "tab\there.service",
+            "new\nline.service",
+            "caméra.service",
+            "カメラ.service",
+            "",
+            "-",
+            "--help",
+        ];
+
+        for name in corpus {
+            assert_eq!(shell_words(&shell_escape(name).unwrap()), vec![name], "{name:?}");
+        }
+    }
+
+    #[test]
+    fn release_table_keeps_release_order() {
+        let releases: Vec<GhRelease> = serde_json::from_str(
+            r#"[
+                {"tag_name": "v1.0.2", "draft": false, "published_at": "2025-02-01T00:00:00Z", "immutable": true, "assets": []},
+                {"tag_name": "v1.0.1", "draft": true, "published_at": null, "assets": []}
+            ]"#,
+        )
+        .unwrap();
+
+        let table = format_release_table(&releases);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("TAG"));
+        assert!(lines[1].starts_with("v1.0.2"));
+        assert!(lines[1].contains("true"));
+        assert!(lines[2].starts_with("v1.0.1"));
+        assert!(lines[2].contains("unknown"));
+    }
+
+    #[test]
+    fn shell_escape_rejects_nul() {
+        assert!(shell_escape("secluso\0.service").is_err());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn shell_escape_is_a_single_word(name in "[^\\x00]*") {
+            proptest::prop_assert_eq!(shell_words(&shell_escape(&name).unwrap()), vec![name]);
+        }
+    }
+
+    #[test]
+    fn no_backup_without_an_installed_binary() {
+        let root = TestDir::new("secluso-update-no-backup");
+        let final_path = root.path().join("bin").join("secluso-server");
+
+        assert!(backup_current_binary(&final_path).unwrap().is_none());
+    }
+
+    const VERIFY_TAG: &str = "v1.0.0";
+    const VERIFY_SIGNER: &str = "release-test";
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    // A bundle holding the updater binary for this arch, listed in its manifest.
+    fn updater_bundle(binary: &[u8]) -> Vec<u8> {
+        use std::io::Cursor;
+        use zip::write::SimpleFileOptions;
+
+        let target = Component::Updater.zip_path(std::env::consts::ARCH).unwrap();
+        let bin_path = format!("artifacts/{}", target);
+        let manifest = serde_json::json!({
+            "build": {"target": target, "profile": "release", "run_id": "1", "timestamp": "0"},
+            "artifacts": [{
+                "package": "secluso",
+                "target": target,
+                "bin": "secluso-update",
+                "bin_path": &bin_path,
+                "crate": "secluso-update",
+                "version": VERIFY_TAG.trim_start_matches('v'),
+                "crate_lock_sha256": "",
+                "rust_digest": "",
+                "sha256": sha256_hex(binary),
+            }],
+        });
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("manifest.json", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(manifest.to_string().as_bytes()).unwrap();
+        zip.start_file(bin_path, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(binary).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    // Returns a fresh signer's armored public key and its detached signature over `payload`.
+    fn sign_detached(payload: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        use openpgp::cert::CertBuilder;
+        use openpgp::packet::signature::SignatureBuilder;
+        use openpgp::policy::StandardPolicy;
+        use openpgp::serialize::SerializeInto;
+        use openpgp::types::SignatureType;
+        use sequoia_openpgp as openpgp;
+
+        let (cert, _) = CertBuilder::new()
+            .add_userid("Secluso release test <release-test@secluso.invalid>")
+            .add_signing_subkey()
+            .generate()
+            .unwrap();
+        let mut keypair = cert
+            .keys()
+            .with_policy(&StandardPolicy::new(), None)
+            .for_signing()
+            .secret()
+            .next()
+            .unwrap()
+            .key()
+            .clone()
+            .into_keypair()
+            .unwrap();
+        let sig = SignatureBuilder::new(SignatureType::Binary)
+            .sign_message(&mut keypair, payload)
+            .unwrap();
+
+        (
+            cert.armored().to_vec().unwrap(),
+            openpgp::Packet::from(sig).to_vec().unwrap(),
+        )
+    }
+
+    // Serves a latest release whose checksum file (signed by VERIFY_SIGNER) is for `signed` but whose
+    // bundle asset is `served`, its assets, and the signer's key, from a single local host standing in
+    // for both api.github.com and github.com. Returns the host's base URL.
+    fn mock_github_release(signed: &[u8], served: &[u8]) -> String {
+        use std::collections::HashMap;
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let bundle_name = format!("secluso-runtime-{}.zip", VERIFY_TAG);
+        let sums_name = format!("secluso-{}-sha256sums.txt", VERIFY_TAG);
+        let sums = format!("{}  {}\n", sha256_hex(signed), bundle_name).into_bytes();
+        let (key, sig) = sign_detached(&sums);
+        let assets = [
+            (bundle_name, served.to_vec()),
+            (format!("{}.{}.asc", sums_name, VERIFY_SIGNER), sig),
+            (sums_name, sums),
+        ];
+
+        let release = serde_json::json!({
+            "tag_name": VERIFY_TAG,
+            "draft": false,
+            "published_at": "2025-01-01T00:00:00Z",
+            "immutable": true,
+            "assets": assets
+                .iter()
+                .enumerate()
+                .map(|(id, (name, bytes))| {
+                    serde_json::json!({
+                        "id": id,
+                        "name": name,
+                        "browser_download_url": format!("{}/download/{}", base_url, name),
+                        "size": bytes.len(),
+                        "digest": format!("sha256:{}", sha256_hex(bytes)),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        let mut files: HashMap<String, Vec<u8>> = assets
+            .into_iter()
+            .map(|(name, bytes)| (format!("/download/{}", name), bytes))
+            .collect();
+        files.insert(
+            format!("/repos/{}/releases/latest", DEFAULT_OWNER_REPO),
+            release.to_string().into_bytes(),
+        );
+        files.insert(format!("/{}.gpg", VERIFY_SIGNER), key);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Read the rest of the headers, so that closing the connection doesn't reset it.
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match files.get(path) {
+                    Some(body) => ("200 OK", body.as_slice()),
+                    None => ("404 Not Found", &b""[..]),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+
+        base_url
+    }
+
+    fn verify_only_args(extra: &[&str]) -> Args {
+        let sig_key = format!("{}:{}", VERIFY_SIGNER, VERIFY_SIGNER);
+        let mut argv = vec![
+            "secluso-update",
+            "--component",
+            "updater",
+            "--verify-only",
+            "--sig-key",
+            sig_key.as_str(),
+        ];
+        argv.extend_from_slice(extra);
+        Docopt::new(USAGE)
+            .unwrap()
+            .argv(argv)
+            .deserialize()
+            .unwrap()
+    }
+
+    // Every file under `dir`, with its contents and modification time.
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Vec<u8>, SystemTime)> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(snapshot(&path));
+            } else {
+                let modified = fs::metadata(&path).unwrap().modified().unwrap();
+                files.push((path.clone(), fs::read(&path).unwrap(), modified));
+            }
+        }
+        files.sort();
+        files
+    }
+
+    // An install root with an updater already installed.
+    fn install_root_with_updater(name: &str) -> TestDir {
+        let root = TestDir::new(name);
+        let root_str = root.path().to_string_lossy().into_owned();
+        let component = Component::Updater;
+        prepare_verified_component_install(
+            Path::new(&component.install_path_under(&root_str)),
+            b"installed-updater",
+        )
+        .unwrap()
+        .commit()
+        .unwrap();
+        write_current_version(component, &root_str, Version::new(0, 9, 0)).unwrap();
+        root
+    }
+
+    // Writes a release bundle for `served` into `dir` with, next to it, a checksum file for `signed`, VERIFY_SIGNER's
+    // signature over it and the signer's keyring, i.e. what an operator copies onto an air-gapped host.
+    // Returns the --bundle-path and --sig-key-file arguments for verify_only.
+    fn local_release(dir: &Path, signed: &[u8], served: &[u8]) -> Vec<String> {
+        let bundle_name = format!("secluso-runtime-{}.zip", VERIFY_TAG);
+        let sums_name = format!("secluso-{}-sha256sums.txt", VERIFY_TAG);
+        let sums = format!("{}  {}\n", sha256_hex(signed), bundle_name).into_bytes();
+        let (key, sig) = sign_detached(&sums);
+
+        let bundle_path = dir.join(&bundle_name);
+        let key_path = dir.join(format!("{}.asc", VERIFY_SIGNER));
+        fs::write(&bundle_path, served).unwrap();
+        let sig_path = dir.join(format!("{}.{}.asc", sums_name, VERIFY_SIGNER));
+        fs::write(sig_path, sig).unwrap();
+        fs::write(dir.join(sums_name), sums).unwrap();
+        fs::write(&key_path, key).unwrap();
+
+        vec![
+            "--bundle-path".to_string(),
+            bundle_path.to_string_lossy().into_owned(),
+            "--sig-key-file".to_string(),
+            format!("{}:{}", VERIFY_SIGNER, key_path.display()),
+        ]
+    }
+
+    // No mock host is started: with --bundle-path, verify_only must not need the network at all.
+    #[test]
+    fn verify_only_checks_a_local_bundle_without_network() {
+        let bundle = updater_bundle(b"updater-v1.0.0");
+        let tampered = updater_bundle(b"evil-updater-v1.0.0");
+        let root = install_root_with_updater("secluso-update-verify-offline");
+        let before = snapshot(root.path());
+
+        let valid_dir = TestDir::new("secluso-update-verify-offline-valid");
+        let extra = local_release(valid_dir.path(), &bundle, &bundle);
+        let extra: Vec<&str> = extra.iter().map(String::as_str).collect();
+        let result = verify_only(&verify_only_args(&extra));
+        assert_eq!(verify_only_exit_code(result), 0);
+
+        let tampered_dir = TestDir::new("secluso-update-verify-offline-tampered");
+        let extra = local_release(tampered_dir.path(), &bundle, &tampered);
+        let extra: Vec<&str> = extra.iter().map(String::as_str).collect();
+        let result = verify_only(&verify_only_args(&extra));
+        assert_eq!(verify_only_exit_code(result), 1);
+
+        assert_eq!(snapshot(root.path()), before);
+    }
+
+    #[test]
+    fn verify_only_passes_a_valid_bundle_without_touching_install_root() {
+        let bundle = updater_bundle(b"updater-v1.0.0");
+        let base_url = mock_github_release(&bundle, &bundle);
+        let root = install_root_with_updater("secluso-update-verify-valid");
+        let before = snapshot(root.path());
+
+        let result = verify_only_from(&verify_only_args(&[]), &base_url, &base_url);
+
+        assert_eq!(verify_only_exit_code(result), 0);
+        assert_eq!(snapshot(root.path()), before);
+    }
+
+    #[test]
+    fn verify_only_fails_a_tampered_bundle_without_touching_install_root() {
+        let bundle = updater_bundle(b"updater-v1.0.0");
+        let tampered = updater_bundle(b"evil-updater-v1.0.0");
+        let base_url = mock_github_release(&bundle, &tampered);
+        let root = install_root_with_updater("secluso-update-verify-tampered");
+        let before = snapshot(root.path());
+
+        let result = verify_only_from(&verify_only_args(&[]), &base_url, &base_url);
+
+        assert_eq!(verify_only_exit_code(result), 1);
+        assert_eq!(snapshot(root.path()), before);
     }
 }